@@ -0,0 +1,191 @@
+#![warn(clippy::pedantic, clippy::nursery)]
+#![warn(clippy::all, clippy::correctness)]
+//! A single compiler driver for high-level Caiman (`.cm`), Caiman assembly
+//! (`.cair`), and serialized programs (`.ron`).
+//!
+//! Every input format is run through the same stages, and `--emit` picks the
+//! stage whose result is printed, so `caiman --emit=asm foo.cm` and
+//! `caiman --emit=ir foo.cair` work the same way regardless of where the
+//! program started. Stages that don't exist for an input, like normalizing
+//! assembly, are reported as errors.
+
+mod pipeline;
+
+use caiman::backend::BackendKind;
+use caiman::diagnostics::{codes, Diagnostic, MessageFormat};
+use caiman::frontend::Optimization;
+use clap::Parser;
+use pipeline::{Emit, InputFormat, Options, Report, Source};
+use std::io::Read;
+use std::path::Path;
+use std::process::{Command, ExitCode};
+
+// each flag is a switch on the command line, so it's a bool here
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Parser)]
+#[clap(name = "caiman", version)]
+struct Arguments {
+    /// The file to compile, or `-` to read from stdin.
+    input: String,
+
+    /// The stage to stop after and print the result of, one of `ast`,
+    /// `normalized`, `asm`, `expir`, `ir`, or `rust`.
+    #[clap(long, default_value = "rust", takes_value = true)]
+    emit: Emit,
+
+    /// Where to write the result of the last stage, or `-` for stdout.
+    #[clap(long, short, takes_value = true)]
+    output: Option<String>,
+
+    /// The language of the input, one of `cm`, `cair`, or `ron`. Defaults to
+    /// the extension of the input, and must be given when reading from stdin.
+    #[clap(long, takes_value = true)]
+    input_format: Option<InputFormat>,
+
+    /// Where the generated code runs work placed on the GPU, either `wgpu` or
+    /// `cpu`.
+    #[clap(long, default_value = "wgpu", takes_value = true)]
+    backend: BackendKind,
+
+    /// How errors are printed, either `human` or `json` (one diagnostic per
+    /// line).
+    #[clap(long, default_value = "human", takes_value = true)]
+    message_format: MessageFormat,
+
+    /// When this flag is enabled, the generated code will print debug
+    /// information about code generation.
+    #[clap(long)]
+    print_codegen_debug_info: bool,
+
+    /// When this flag is enabled, the frontend will not infer the quotients of
+    /// high-level variables.
+    #[clap(long)]
+    no_inference: bool,
+
+    /// A pass to run over the program once it type checks, which can be given
+    /// more than once to run several in order. Only `fusion` exists so far.
+    #[clap(long = "opt", takes_value = true, multiple_occurrences = true)]
+    optimizations: Vec<Optimization>,
+
+    /// Rewrite a RON program written for an older version for this one, and
+    /// print it instead of compiling it.
+    #[clap(
+        long,
+        conflicts_with_all = &["emit", "optimizations", "print-join-stack", "explain-choices"]
+    )]
+    upgrade: bool,
+
+    /// Print how many joins each pipeline can have waiting at once instead of
+    /// the generated code.
+    #[clap(long, conflicts_with_all = &["emit", "explain-choices"])]
+    print_join_stack: bool,
+
+    /// Print which implementation explication chose for each function call
+    /// instead of the generated code.
+    #[clap(long, conflicts_with = "emit")]
+    explain_choices: bool,
+}
+
+impl Arguments {
+    const fn report(&self) -> Option<Report> {
+        if self.print_join_stack {
+            Some(Report::JoinStack)
+        } else if self.explain_choices {
+            Some(Report::Choices)
+        } else {
+            None
+        }
+    }
+}
+
+/// Tries to run `rustfmt` on the given path.
+fn format(path: &Path) {
+    let _ = Command::new("rustfmt")
+        .arg("-q")
+        .arg("--")
+        .arg(path)
+        .status();
+}
+
+fn read_source(args: &Arguments) -> Result<Source, Box<Diagnostic>> {
+    let io_error = |e: std::io::Error| {
+        Box::new(Diagnostic::error(
+            codes::IO,
+            format!("Failed to read {}: {e}", args.input),
+        ))
+    };
+    let (filename, text) = if args.input == "-" {
+        let mut text = String::new();
+        std::io::stdin()
+            .read_to_string(&mut text)
+            .map_err(io_error)?;
+        ("stdin".to_string(), text)
+    } else {
+        let text = std::fs::read_to_string(&args.input).map_err(io_error)?;
+        (args.input.clone(), text)
+    };
+    let format = args
+        .input_format
+        .or_else(|| InputFormat::from_path(&filename))
+        .ok_or_else(|| {
+            Box::new(Diagnostic::error(
+                codes::USAGE,
+                format!("Can't tell the format of {filename}, pass --input-format=cm|cair|ron"),
+            ))
+        })?;
+    Ok(Source {
+        filename,
+        text,
+        format,
+    })
+}
+
+fn write_output(args: &Arguments, output: &str) -> Result<(), Box<Diagnostic>> {
+    match args.output.as_deref() {
+        None | Some("-") => print!("{output}"),
+        Some(path) => {
+            let path = Path::new(path);
+            let io_error = |e: std::io::Error| {
+                Box::new(Diagnostic::error(
+                    codes::IO,
+                    format!("Failed to write {}: {e}", path.display()),
+                ))
+            };
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent).map_err(io_error)?;
+            }
+            std::fs::write(path, output).map_err(io_error)?;
+            // reports and upgraded programs aren't Rust
+            if args.emit == Emit::Rust && args.report().is_none() && !args.upgrade {
+                format(path);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn compile(args: &Arguments) -> Result<(), Box<Diagnostic>> {
+    let source = read_source(args)?;
+    let options = Options {
+        emit: args.emit,
+        backend: args.backend,
+        print_codegen_debug_info: args.print_codegen_debug_info,
+        no_inference: args.no_inference,
+        optimizations: args.optimizations.clone(),
+        report: args.report(),
+        upgrade: args.upgrade,
+    };
+    let output = pipeline::run(&source, &options).map_err(|e| Box::new(e.to_diagnostic()))?;
+    write_output(args, &output)
+}
+
+fn main() -> ExitCode {
+    let args = Arguments::parse();
+    match compile(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(diagnostic) => {
+            eprintln!("{}", diagnostic.render(args.message_format));
+            ExitCode::FAILURE
+        }
+    }
+}
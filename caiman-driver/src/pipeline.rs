@@ -0,0 +1,451 @@
+//! The stages every input format goes through, and where each one can stop.
+
+use caiman::assembly::printer::print_program;
+use caiman::backend::BackendKind;
+use caiman::diagnostics::{codes, Diagnostic};
+use caiman::explication::cost::TransferCostModel;
+use caiman::frontend::{
+    self, CompileData, CompileError, CompileMode, CompileOptions, ExplicationDefinition,
+    Optimization,
+};
+use hlc::error::LocalError;
+use std::path::Path;
+use std::str::FromStr;
+
+/// The language a program is written in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputFormat {
+    /// High-level Caiman, `.cm`
+    HighLevel,
+    /// Caiman assembly, `.cair`
+    Assembly,
+    /// A serialized `caiman::frontend::Definition`, `.ron`
+    Ron,
+}
+
+impl InputFormat {
+    /// The format of `path`, going by its extension
+    #[must_use]
+    pub fn from_path(path: &str) -> Option<Self> {
+        Path::new(path)
+            .extension()
+            .and_then(std::ffi::OsStr::to_str)
+            .and_then(|extension| extension.parse().ok())
+    }
+}
+
+impl FromStr for InputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cm" => Ok(Self::HighLevel),
+            "cair" => Ok(Self::Assembly),
+            "ron" => Ok(Self::Ron),
+            other => Err(format!(
+                "Unknown input format {other}, expected `cm`, `cair`, or `ron`"
+            )),
+        }
+    }
+}
+
+/// The stage to stop after, and print the result of.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Emit {
+    /// The parsed program
+    Ast,
+    /// The high-level AST after normalization and type checking, as it is
+    /// given to lowering
+    Normalized,
+    /// Caiman assembly
+    Asm,
+    /// The lowered program that explication starts from
+    Expir,
+    /// The explicated program, before it is type checked
+    Ir,
+    /// Rust code generated for the type checked program
+    Rust,
+}
+
+impl Emit {
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Ast => "ast",
+            Self::Normalized => "normalized",
+            Self::Asm => "asm",
+            Self::Expir => "expir",
+            Self::Ir => "ir",
+            Self::Rust => "rust",
+        }
+    }
+}
+
+impl FromStr for Emit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ast" => Ok(Self::Ast),
+            "normalized" => Ok(Self::Normalized),
+            "asm" => Ok(Self::Asm),
+            "expir" => Ok(Self::Expir),
+            "ir" => Ok(Self::Ir),
+            "rust" => Ok(Self::Rust),
+            other => Err(format!(
+                "Unknown stage {other}, expected `ast`, `normalized`, `asm`, `expir`, `ir`, or `rust`"
+            )),
+        }
+    }
+}
+
+/// A description of the type checked program, printed instead of the code
+/// generated for it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Report {
+    /// How many joins each pipeline can have waiting at once
+    JoinStack,
+    /// Which implementation explication chose for each function call
+    Choices,
+}
+
+/// A program to compile.
+pub struct Source {
+    /// The file the program was read from, or `stdin`
+    pub filename: String,
+    pub text: String,
+    pub format: InputFormat,
+}
+
+impl Source {
+    fn is_stdin(&self) -> bool {
+        self.filename == "stdin"
+    }
+
+    fn compile_data(&self) -> CompileData {
+        let directory = if self.is_stdin() {
+            Path::new(".")
+        } else {
+            Path::new(&self.filename)
+                .parent()
+                .unwrap_or_else(|| Path::new("."))
+        };
+        CompileData {
+            path: directory.to_string_lossy().to_string(),
+            filename: self.filename.clone(),
+            input_string: self.text.clone(),
+        }
+    }
+}
+
+pub struct Options {
+    pub emit: Emit,
+    pub backend: BackendKind,
+    pub print_codegen_debug_info: bool,
+    /// Don't infer the quotients of high-level variables
+    pub no_inference: bool,
+    /// Passes to run over the type checked program, in order
+    pub optimizations: Vec<Optimization>,
+    /// Print this instead of the generated code
+    pub report: Option<Report>,
+    /// Rewrite a RON program written for an older version for this one
+    /// instead of compiling it
+    pub upgrade: bool,
+}
+
+/// Why a program couldn't be taken to the requested stage.
+#[derive(Debug)]
+pub enum Error {
+    HighLevel(hlc::error::Error),
+    Caiman(Box<CompileError>),
+    /// The requested stage doesn't exist for the input format
+    Usage(String),
+}
+
+impl Error {
+    #[must_use]
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        match self {
+            Self::HighLevel(error) => error.to_diagnostic(),
+            Self::Caiman(error) => error.diagnostic.clone(),
+            Self::Usage(message) => Diagnostic::error(codes::USAGE, message.clone()),
+        }
+    }
+}
+
+impl From<hlc::error::Error> for Error {
+    fn from(error: hlc::error::Error) -> Self {
+        Self::HighLevel(error)
+    }
+}
+
+impl From<CompileError> for Error {
+    fn from(error: CompileError) -> Self {
+        Self::Caiman(Box::new(error))
+    }
+}
+
+fn unavailable(emit: Emit, format: &str) -> Error {
+    Error::Usage(format!(
+        "--emit={} is not available for {format} input",
+        emit.name()
+    ))
+}
+
+fn to_ron<T: serde::Serialize>(value: &T) -> String {
+    let pretty = ron::ser::PrettyConfig::new().enumerate_arrays(true);
+    ron::ser::to_string_pretty(value, pretty).unwrap()
+}
+
+/// Runs `source` through every stage up to `options.emit`, returning what that
+/// stage produced.
+///
+/// # Errors
+/// Returns an error if any stage rejects the program, or if the requested
+/// stage doesn't exist for the input format.
+pub fn run(source: &Source, options: &Options) -> Result<String, Error> {
+    if options.upgrade {
+        return upgrade(source);
+    }
+    match source.format {
+        InputFormat::HighLevel => run_high_level(source, options),
+        InputFormat::Assembly => run_assembly(source, options),
+        InputFormat::Ron => {
+            let emit = options.emit;
+            if matches!(emit, Emit::Ast | Emit::Normalized | Emit::Asm | Emit::Expir) {
+                return Err(unavailable(emit, "RON"));
+            }
+            finish(frontend::read_ron(&source.compile_data())?, options)
+        }
+    }
+}
+
+fn upgrade(source: &Source) -> Result<String, Error> {
+    if source.format != InputFormat::Ron {
+        return Err(Error::Usage(
+            "--upgrade is only available for RON input".to_string(),
+        ));
+    }
+    let options = CompileOptions {
+        compile_mode: CompileMode::RON,
+        ..CompileOptions::default()
+    };
+    Ok(frontend::upgrade_caiman(source.compile_data(), options)?)
+}
+
+fn run_high_level(source: &Source, options: &Options) -> Result<String, Error> {
+    let filename = &source.filename;
+    let located = |error: LocalError| hlc::error::Error {
+        error,
+        filename: filename.clone(),
+    };
+    let ast = if source.is_stdin() {
+        hlc::parse::parse_read(source.text.as_bytes(), filename)?
+    } else {
+        hlc::parse::parse_source(&source.text, filename)?.0
+    };
+    if options.emit == Emit::Ast {
+        return Ok(format!("{ast:#?}"));
+    }
+    let ast = hlc::normalize::normalize_ast(ast).map_err(located)?;
+    let ctx = hlc::typing::Context::new(&ast).map_err(located)?;
+    let final_ast = hlc::normalize::post_typecheck_norm(ast);
+    if options.emit == Emit::Normalized {
+        return Ok(format!("{final_ast:#?}"));
+    }
+    let lowered = hlc::lower::lower(final_ast, &ctx, options.no_inference).map_err(located)?;
+    if options.emit == Emit::Asm {
+        return Ok(print_program(&lowered).map_err(CompileError::from)?);
+    }
+    explicate(frontend::lower_assembly(lowered, filename)?, options)
+}
+
+fn run_assembly(source: &Source, options: &Options) -> Result<String, Error> {
+    let program = frontend::parse_assembly(&source.compile_data())?;
+    match options.emit {
+        Emit::Ast => Ok(format!("{program:#?}")),
+        Emit::Normalized => Err(unavailable(options.emit, "assembly")),
+        Emit::Asm => Ok(print_program(&program).map_err(CompileError::from)?),
+        _ => explicate(
+            frontend::lower_assembly(program, &source.filename)?,
+            options,
+        ),
+    }
+}
+
+fn explicate(definition: ExplicationDefinition, options: &Options) -> Result<String, Error> {
+    if options.emit == Emit::Expir {
+        return Ok(to_ron(&definition));
+    }
+    let definition = caiman::explication::explicate(definition).map_err(CompileError::from)?;
+    finish(definition, options)
+}
+
+fn finish(mut definition: frontend::Definition, options: &Options) -> Result<String, Error> {
+    if options.emit == Emit::Ir {
+        return Ok(to_ron(&definition));
+    }
+    frontend::check_and_optimize(&mut definition, &options.optimizations)?;
+    match options.report {
+        Some(Report::JoinStack) => return Ok(frontend::join_stack_report(&definition)),
+        Some(Report::Choices) => {
+            let cost_model = TransferCostModel::default();
+            return Ok(frontend::choice_report(&definition, &cost_model));
+        }
+        None => {}
+    }
+    let compile_options = CompileOptions {
+        print_codegen_debug_info: options.print_codegen_debug_info,
+        backend: options.backend,
+        ..CompileOptions::default()
+    };
+    Ok(frontend::generate(&definition, &compile_options)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(file: &str) -> Source {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../caiman-test")
+            .join(file);
+        let filename = path.to_string_lossy().to_string();
+        Source {
+            text: std::fs::read_to_string(&path).unwrap(),
+            format: InputFormat::from_path(&filename).unwrap(),
+            filename,
+        }
+    }
+
+    fn emit(source: &Source, emit: Emit) -> Result<String, Error> {
+        let options = Options {
+            emit,
+            backend: BackendKind::Wgpu,
+            print_codegen_debug_info: false,
+            no_inference: false,
+            optimizations: Vec::new(),
+            report: None,
+            upgrade: false,
+        };
+        run(source, &options)
+    }
+
+    #[test]
+    fn high_level_reaches_every_stage() {
+        let source = source("high-level-caiman/types/array_ret_test.cm");
+        for stage in [
+            Emit::Ast,
+            Emit::Normalized,
+            Emit::Asm,
+            Emit::Expir,
+            Emit::Ir,
+            Emit::Rust,
+        ] {
+            assert!(emit(&source, stage).is_ok(), "--emit={}", stage.name());
+        }
+        let asm = emit(&source, Emit::Asm).unwrap();
+        assert!(asm.starts_with("version"));
+    }
+
+    #[test]
+    fn assembly_matches_the_frontend() {
+        let source = source("basics/copy_add_test.cair");
+        let expected = frontend::compile_caiman(source.compile_data(), CompileOptions::default());
+        assert_eq!(emit(&source, Emit::Rust).ok(), expected.ok());
+        assert!(matches!(
+            emit(&source, Emit::Normalized),
+            Err(Error::Usage(_))
+        ));
+    }
+
+    #[test]
+    fn runs_optimizations() {
+        let source = source("optimizations/kernel_fusion_test.cair");
+        let options = Options {
+            emit: Emit::Rust,
+            backend: BackendKind::Wgpu,
+            print_codegen_debug_info: false,
+            no_inference: false,
+            optimizations: vec![Optimization::Fusion],
+            report: None,
+            upgrade: false,
+        };
+        assert!(run(&source, &options).unwrap().contains("fused_"));
+        assert!(!emit(&source, Emit::Rust).unwrap().contains("fused_"));
+    }
+
+    #[test]
+    fn reports_replace_the_generated_code() {
+        let source = source("basics/copy_add_test.cair");
+        let options = |report, upgrade| Options {
+            emit: Emit::Rust,
+            backend: BackendKind::Wgpu,
+            print_codegen_debug_info: false,
+            no_inference: false,
+            optimizations: Vec::new(),
+            report,
+            upgrade,
+        };
+        let expected =
+            frontend::print_join_stacks(source.compile_data(), CompileOptions::default());
+        let join_stacks = run(&source, &options(Some(Report::JoinStack), false));
+        assert_eq!(join_stacks.ok(), expected.ok());
+        let expected = frontend::explain_choices(source.compile_data(), CompileOptions::default());
+        let choices = run(&source, &options(Some(Report::Choices), false));
+        assert_eq!(choices.ok(), expected.ok());
+        assert!(matches!(
+            run(&source, &options(None, true)),
+            Err(Error::Usage(_))
+        ));
+        let ron = self::source("ron/pipeline_1_test.ron");
+        assert!(run(&ron, &options(None, true)).is_ok());
+    }
+
+    #[test]
+    fn ron_only_has_later_stages() {
+        let source = source("ron/pipeline_1_test.ron");
+        assert!(emit(&source, Emit::Rust).is_ok());
+        assert!(matches!(emit(&source, Emit::Asm), Err(Error::Usage(_))));
+        assert_eq!(
+            emit(&source, Emit::Asm).unwrap_err().to_diagnostic().code,
+            codes::USAGE
+        );
+    }
+
+    #[test]
+    fn high_level_nodes_have_source_spans() {
+        let source = source("high-level-caiman/simple-lower/hlc_cond_op_test.cm");
+        let ast = hlc::parse::parse_source(&source.text, &source.filename)
+            .unwrap()
+            .0;
+        let ast = hlc::normalize::normalize_ast(ast).unwrap();
+        let ctx = hlc::typing::Context::new(&ast).unwrap();
+        let ast = hlc::normalize::post_typecheck_norm(ast);
+        let lowered = hlc::lower::lower(ast, &ctx, false).unwrap();
+        let debug_info = frontend::lower_assembly(lowered, &source.filename)
+            .unwrap()
+            .debug_info;
+        let line_of = |funclet: &str, node: &str| {
+            let (funclet_id, funclet_map) = debug_info
+                .funclet_map
+                .iter()
+                .find(|(_, map)| map.name == funclet)
+                .unwrap();
+            let node_id = funclet_map
+                .node_map
+                .iter()
+                .find_map(|(quotient, name)| match quotient {
+                    caiman::ir::Quotient::Node { node_id } if name == node => Some(*node_id),
+                    _ => None,
+                })
+                .unwrap();
+            let span = debug_info.node_span(funclet_id, node_id).unwrap();
+            assert_eq!(span.file, source.filename);
+            span.start.line
+        };
+        // the spec assignment `plus :- a + a`
+        assert_eq!(line_of("main", "plus"), 8);
+        // the schedule declaration `let a: i64 @ node(val.a) = 5;`
+        assert_eq!(line_of("main_impl", "a_0"), 19);
+    }
+}
@@ -0,0 +1,226 @@
+//! Analysis of Caiman assembly (`.cair`) files.
+//!
+//! Funclets and named nodes are located with the spans recorded by the
+//! assembly parser. Function classes have no recorded span, so they are found
+//! by scanning for their `function @name` declarations.
+
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+
+use caiman::assembly::ast::{self, FuncletBinding, FuncletId, NodeId};
+use caiman::diagnostics::{self, Diagnostic};
+use caiman::frontend::{self, CompileData, CompileOptions};
+use lsp_types::{CompletionItem, CompletionItemKind, Location, Position, Range};
+
+use crate::document;
+
+/// The definitions of a `.cair` file
+pub struct Index {
+    file: String,
+    program: ast::Program,
+    /// Where each function class is declared
+    function_classes: HashMap<String, Range>,
+}
+
+fn lsp_range(range: diagnostics::Range) -> Range {
+    document::range(
+        (range.start.line, range.start.column),
+        (range.end.line, range.end.column),
+    )
+}
+
+/// If `before` ends with a reference to a spec funclet such as `$val.%`, gets
+/// the name of the meta variable
+fn meta_before_node(before: &str) -> Option<&str> {
+    let before = before.strip_suffix(".%")?;
+    let start = before.rfind('$')?;
+    let meta = &before[start + 1..];
+    meta.chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_')
+        .then_some(meta)
+}
+
+impl Index {
+    fn new(program: ast::Program, text: &str, file: &str) -> Self {
+        let mut function_classes = HashMap::new();
+        for (line, src) in text.lines().enumerate() {
+            let trimmed = src.trim_start();
+            if let Some(rest) = trimmed.strip_prefix("function @") {
+                let name: String = rest
+                    .chars()
+                    .take_while(|c| c.is_ascii_alphanumeric() || *c == '_')
+                    .collect();
+                let column = src.len() - trimmed.len() + "function @".len() + 1;
+                let range = document::range((line + 1, column), (line + 1, column + name.len()));
+                function_classes.entry(name).or_insert(range);
+            }
+        }
+        Self {
+            file: file.to_string(),
+            program,
+            function_classes,
+        }
+    }
+
+    /// Parses and type checks `text`, the contents of `file`. Returns the index
+    /// of the program if it parsed, along with any errors.
+    #[must_use]
+    #[allow(clippy::result_large_err)]
+    pub fn analyze(text: &str, file: &str) -> (Option<Self>, Vec<Diagnostic>) {
+        let dir = Path::new(file)
+            .parent()
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let index = caiman::assembly::parser::parse(&dir, text)
+            .ok()
+            .map(|program| Self::new(program, text, file));
+        let compile_data = CompileData {
+            path: dir,
+            filename: file.to_string(),
+            input_string: text.to_string(),
+        };
+        let result = document::catch_internal_error(file, || {
+            frontend::check_caiman(compile_data, CompileOptions::default())
+        });
+        let diagnostics = match result {
+            Ok(Ok(())) => vec![],
+            Ok(Err(e)) => vec![e.diagnostic],
+            Err(d) => vec![d],
+        };
+        (index, diagnostics)
+    }
+
+    /// Updates the definitions to those of `text` if it parses
+    pub fn reparse(&mut self, text: &str) {
+        if let Ok(program) = caiman::assembly::parser::parse(&self.program.path, text) {
+            *self = Self::new(program, text, &self.file);
+        }
+    }
+
+    /// Gets the funclet containing `pos`
+    fn funclet_at(&self, pos: Position) -> Option<(&FuncletId, &ast::FuncletSpans)> {
+        self.program.spans.iter().find(|(_, spans)| {
+            spans
+                .funclet
+                .is_some_and(|r| document::on_lines(r.start.line, r.end.line, pos))
+        })
+    }
+
+    /// Gets the funclet that a meta variable of the schedule funclet `funclet`
+    /// stands for
+    fn meta_funclet(&self, funclet: &FuncletId, meta: &str) -> Option<&FuncletId> {
+        self.program
+            .declarations
+            .iter()
+            .find_map(|decl| match decl {
+                ast::Declaration::Funclet(f) if &f.header.name == funclet => {
+                    match &f.header.binding {
+                        FuncletBinding::ScheduleBinding(binding) => {
+                            let map = &binding.meta_map;
+                            [&map.value, &map.timeline, &map.spatial]
+                                .into_iter()
+                                .find(|(m, _)| m.0 == meta)
+                                .map(|(_, f)| f)
+                        }
+                        _ => None,
+                    }
+                }
+                _ => None,
+            })
+    }
+
+    fn node_or_funclet(&self, funclet: &FuncletId, node: &str) -> Option<Range> {
+        let spans = self.program.spans.get(funclet)?;
+        spans
+            .nodes
+            .get(&NodeId(node.to_string()))
+            .copied()
+            .or(spans.funclet)
+            .map(lsp_range)
+    }
+
+    /// Finds the definition of the function class (`@name`), funclet, node
+    /// (`%name`), or spec node (`$val.%name`) at `pos`
+    #[must_use]
+    pub fn definition(&self, text: &str, pos: Position) -> Option<Location> {
+        let word = document::word_at(text, pos)?;
+        let enclosing = self.funclet_at(pos);
+        let range = if word.before.ends_with('@') {
+            self.function_classes.get(word.text).copied()
+        } else if let Some(meta) = meta_before_node(word.before) {
+            let funclet = self.meta_funclet(enclosing?.0, meta)?;
+            self.node_or_funclet(funclet, word.text)
+        } else if word.before.ends_with('$') {
+            let funclet = self.meta_funclet(enclosing?.0, word.text)?;
+            self.node_or_funclet(funclet, "")
+        } else if word.before.ends_with('%') {
+            enclosing
+                .and_then(|(_, spans)| spans.nodes.get(&NodeId(word.text.to_string())))
+                .copied()
+                .or_else(|| {
+                    self.program
+                        .spans
+                        .get(&FuncletId(word.text.to_string()))
+                        .and_then(|s| s.funclet)
+                })
+                .map(lsp_range)
+        } else {
+            None
+        }?;
+        Some(Location::new(document::file_url(&self.file)?, range))
+    }
+
+    /// Completes node names of spec funclets after `$val.%`
+    #[must_use]
+    pub fn completion(&self, text: &str, pos: Position) -> Vec<CompletionItem> {
+        let before = document::line_before(text, pos);
+        let before = before.trim_end_matches(|c: char| c.is_ascii_alphanumeric() || c == '_');
+        let funclet = meta_before_node(before)
+            .and_then(|meta| self.meta_funclet(self.funclet_at(pos)?.0, meta))
+            .and_then(|f| self.program.spans.get(f));
+        funclet.map_or_else(Vec::new, |spans| {
+            let names: BTreeSet<_> = spans.nodes.keys().map(|n| n.0.clone()).collect();
+            names
+                .into_iter()
+                .map(|label| CompletionItem {
+                    label,
+                    kind: Some(CompletionItemKind::VARIABLE),
+                    ..CompletionItem::default()
+                })
+                .collect()
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const PROGRAM: &str = include_str!("../../caiman-test/basics/call_func_test.cair");
+
+    #[test]
+    fn definitions() {
+        let (index, diagnostics) = Index::analyze(PROGRAM, "call_func_test.cair");
+        assert!(diagnostics.is_empty(), "{diagnostics:?}");
+        let index = index.unwrap();
+        let line_of = |line, column| {
+            index
+                .definition(PROGRAM, Position::new(line, column))
+                .map(|l| l.range.start.line)
+        };
+        // `@foo` in `value[impl default @foo]`
+        assert_eq!(line_of(50, 22), Some(10));
+        // `%i_ref` in `read-ref i64 %i_ref`
+        assert_eq!(line_of(61, 33), Some(59));
+        // `%y_t` in `$val.%y_t`
+        assert_eq!(line_of(36, 35), Some(16));
+        // `%foo_impl` in `schedule-call %foo_impl`
+        assert_eq!(line_of(36, 20), Some(55));
+        let nodes: Vec<_> = index
+            .completion(PROGRAM, Position::new(36, 34))
+            .into_iter()
+            .map(|i| i.label)
+            .collect();
+        assert_eq!(nodes, ["y", "y_t"]);
+    }
+}
@@ -0,0 +1,194 @@
+//! Helpers for working with source text and positions, shared by both
+//! languages.
+//!
+//! Both compilers report 1-indexed lines and columns while LSP positions are
+//! 0-indexed. Columns are counted in characters, which matches the UTF-16
+//! offsets of the LSP for the ASCII sources we deal with.
+
+use std::fmt::Write;
+use std::path::Path;
+
+use caiman::diagnostics::{self, Severity, SourceSpan};
+use lsp_types::{
+    DiagnosticRelatedInformation, DiagnosticSeverity, Location, NumberOrString, Position, Range,
+    Url,
+};
+
+/// Converts a 1-indexed line and column to an LSP position
+#[must_use]
+pub fn position(line: usize, column: usize) -> Position {
+    let to_u32 = |x: usize| u32::try_from(x.saturating_sub(1)).unwrap_or(u32::MAX);
+    Position::new(to_u32(line), to_u32(column))
+}
+
+/// Converts a 1-indexed start and end line and column to an LSP range
+#[must_use]
+pub fn range(start: (usize, usize), end: (usize, usize)) -> Range {
+    Range::new(position(start.0, start.1), position(end.0, end.1))
+}
+
+/// Returns true if `pos` lies on one of the 1-indexed lines from `start_line`
+/// to `end_line`, inclusive
+#[must_use]
+pub const fn on_lines(start_line: usize, end_line: usize, pos: Position) -> bool {
+    let line = pos.line as usize + 1;
+    start_line <= line && line <= end_line
+}
+
+/// Gets the text of the line containing `pos` up to `pos`
+#[must_use]
+pub fn line_before(text: &str, pos: Position) -> &str {
+    let line = text.lines().nth(pos.line as usize).unwrap_or("");
+    let end = line
+        .char_indices()
+        .nth(pos.character as usize)
+        .map_or(line.len(), |(i, _)| i);
+    &line[..end]
+}
+
+const fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// An identifier under the cursor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Word<'a> {
+    /// The identifier
+    pub text: &'a str,
+    /// The text of the line before the identifier
+    pub before: &'a str,
+}
+
+/// Gets the identifier under or immediately before `pos`
+#[must_use]
+pub fn word_at(text: &str, pos: Position) -> Option<Word<'_>> {
+    let line = text.lines().nth(pos.line as usize)?;
+    let cursor = line
+        .char_indices()
+        .nth(pos.character as usize)
+        .map_or(line.len(), |(i, _)| i);
+    let start = line[..cursor]
+        .rfind(|c: char| !is_word_char(c))
+        .map_or(0, |i| i + 1);
+    let end = line[cursor..]
+        .find(|c: char| !is_word_char(c))
+        .map_or(line.len(), |i| cursor + i);
+    if start == end {
+        return None;
+    }
+    Some(Word {
+        text: &line[start..end],
+        before: &line[..start],
+    })
+}
+
+/// Gets the URL of a file reported by one of the compilers
+#[must_use]
+pub fn file_url(file: &str) -> Option<Url> {
+    let path = Path::new(file);
+    let path = path.canonicalize().unwrap_or_else(|_| {
+        std::env::current_dir().map_or_else(|_| path.to_path_buf(), |dir| dir.join(path))
+    });
+    Url::from_file_path(path).ok()
+}
+
+fn span_range(span: &SourceSpan) -> Range {
+    range(
+        (span.start.line, span.start.column),
+        (span.end.line, span.end.column),
+    )
+}
+
+/// Converts a compiler diagnostic to an LSP diagnostic of the document read
+/// from `file`. Diagnostics without a location, or located in another file,
+/// are reported at the start of the document.
+#[must_use]
+pub fn to_lsp_diagnostic(
+    diagnostic: &diagnostics::Diagnostic,
+    file: &str,
+) -> lsp_types::Diagnostic {
+    let range = diagnostic
+        .primary
+        .as_ref()
+        .filter(|span| span.file == file)
+        .map(span_range)
+        .unwrap_or_default();
+    let mut message = diagnostic.message.clone();
+    if let Some(span) = diagnostic.primary.as_ref().filter(|span| span.file != file) {
+        write!(message, "\n  --> {span}").unwrap();
+    }
+    for note in &diagnostic.notes {
+        message.push('\n');
+        message.push_str(note);
+    }
+    let related: Vec<_> = diagnostic
+        .secondary
+        .iter()
+        .filter_map(|label| {
+            Some(DiagnosticRelatedInformation {
+                location: Location::new(file_url(&label.span.file)?, span_range(&label.span)),
+                message: label.message.clone(),
+            })
+        })
+        .collect();
+    lsp_types::Diagnostic {
+        range,
+        severity: Some(match diagnostic.severity {
+            Severity::Error => DiagnosticSeverity::ERROR,
+            Severity::Warning => DiagnosticSeverity::WARNING,
+            Severity::Note => DiagnosticSeverity::INFORMATION,
+        }),
+        code: Some(NumberOrString::String(diagnostic.code.clone())),
+        source: Some("caiman".to_string()),
+        message,
+        related_information: if related.is_empty() {
+            None
+        } else {
+            Some(related)
+        },
+        ..lsp_types::Diagnostic::default()
+    }
+}
+
+/// Runs `f`, turning a panic into an internal error diagnostic. The compilers
+/// still panic on some malformed inputs, which must not bring down the server.
+#[allow(clippy::result_large_err)]
+pub fn catch_internal_error<T>(
+    file: &str,
+    f: impl FnOnce() -> T,
+) -> Result<T, diagnostics::Diagnostic> {
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)).map_err(|payload| {
+        let message = payload
+            .downcast_ref::<String>()
+            .cloned()
+            .or_else(|| payload.downcast_ref::<&str>().map(ToString::to_string))
+            .unwrap_or_else(|| "unknown error".to_string());
+        diagnostics::Diagnostic::error(
+            diagnostics::codes::INTERNAL,
+            format!("Internal compiler error: {message}"),
+        )
+        .with_primary(Some(SourceSpan {
+            file: file.to_string(),
+            ..SourceSpan::default()
+        }))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn words() {
+        let text = "let x @ node(val.res2) = 1;\n  %y = read-ref i64 %x_ref;";
+        let w = word_at(text, Position::new(0, 19)).unwrap();
+        assert_eq!(w.text, "res2");
+        assert_eq!(w.before, "let x @ node(val.");
+        let w = word_at(text, Position::new(1, 23)).unwrap();
+        assert_eq!(w.text, "x_ref");
+        assert!(w.before.ends_with('%'));
+        assert_eq!(word_at(text, Position::new(0, 3)).unwrap().text, "let");
+        assert_eq!(word_at(text, Position::new(1, 0)), None);
+        assert_eq!(line_before(text, Position::new(0, 17)), "let x @ node(val.");
+    }
+}
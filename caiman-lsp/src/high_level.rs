@@ -0,0 +1,701 @@
+//! Analysis of high-level Caiman (`.cm`) files.
+//!
+//! The index records where every function class, funclet, and variable is
+//! defined, using the source locations of the parsed AST. Data types come
+//! from the typing context of the last version of the file that type checked,
+//! and tags from the last version that lowered, so hovering keeps working while
+//! the file is being edited.
+
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use caiman::diagnostics::Diagnostic;
+use hlc::error::{Error, Info};
+use hlc::lower::{lower_with_tags, DeducedTags};
+use hlc::normalize::{normalize_ast, post_typecheck_norm};
+use hlc::parse::ast::{
+    ClassMembers, DataType, Flow, FullType, LoopHeader, Quotient, SchedStmt, SpecFunclet, SpecStmt,
+    SpecType, Tag, TopLevel,
+};
+use hlc::typing::{Context, SchedOrExtern, Signature};
+use lsp_types::{CompletionItem, CompletionItemKind, Location, Position};
+
+use crate::document;
+
+/// What a name refers to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    FunctionClass,
+    Spec(SpecType),
+    Extern,
+    Schedule,
+    Type,
+    Const,
+    Pipeline,
+    /// A variable local to the funclet named by `Definition::scope`
+    Variable,
+}
+
+#[derive(Debug, Clone)]
+struct Definition {
+    name: String,
+    kind: Kind,
+    file: String,
+    info: Info,
+    /// The funclet a variable is local to
+    scope: Option<String>,
+    /// The type a variable is annotated with in the source, if any
+    annotation: Option<String>,
+}
+
+/// A funclet that has local variables
+#[derive(Debug, Clone)]
+struct Scope {
+    name: String,
+    file: String,
+    info: Info,
+    /// The kind of spec, or `None` for a scheduling function
+    spec: Option<SpecType>,
+    /// The specs implemented by a scheduling function
+    specs: Vec<String>,
+}
+
+/// The definitions of a `.cm` file and the files it imports
+pub struct Index {
+    file: String,
+    defs: Vec<Definition>,
+    scopes: Vec<Scope>,
+    /// The typing context of the last version of the program that type checked
+    context: Option<Context>,
+    /// The tags deduced for schedule variables by the last version of the
+    /// program that lowered
+    tags: DeducedTags,
+}
+
+const SPEC_KEYWORDS: [(&str, SpecType); 3] = [
+    ("val", SpecType::Value),
+    ("tmln", SpecType::Timeline),
+    ("sptl", SpecType::Spatial),
+];
+
+const QUOTIENT_KEYWORDS: [&str; 3] = ["node", "input", "none"];
+
+const fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// If the text before an identifier is a quotient up to the spec variable,
+/// such as `@ node(val.` or `@ node(tmln.(loc1, `, gets the spec being
+/// referred to
+fn quoted_spec(before: &str) -> Option<SpecType> {
+    let before = before.trim_end_matches(|c: char| is_word_char(c) || " ,(".contains(c));
+    let before = before.strip_suffix('.')?;
+    SPEC_KEYWORDS.iter().find_map(|(kw, spec)| {
+        let rest = before.strip_suffix(kw)?;
+        let rest = rest.strip_suffix('(')?.trim_end();
+        QUOTIENT_KEYWORDS
+            .iter()
+            .any(|q| rest.ends_with(q))
+            .then_some(*spec)
+    })
+}
+
+/// Returns true if `before` ends with the opening of a quotient, such as
+/// `@ node(`
+fn opens_quotient(before: &str) -> bool {
+    before.trim_end().strip_suffix('(').is_some_and(|rest| {
+        QUOTIENT_KEYWORDS
+            .iter()
+            .any(|q| rest.trim_end().ends_with(q))
+    })
+}
+
+fn spec_keyword(spec: SpecType) -> &'static str {
+    SPEC_KEYWORDS.iter().find(|(_, s)| *s == spec).unwrap().0
+}
+
+fn fmt_tag(tag: &Tag) -> String {
+    let quot = match tag.quot {
+        Some(Quotient::Node) => "node",
+        Some(Quotient::Input) => "input",
+        Some(Quotient::None) => "none",
+        None => "?",
+    };
+    let var = tag
+        .quot_var
+        .spec_var
+        .as_ref()
+        .map_or_else(String::new, |v| format!(".{v}"));
+    let flow = match tag.flow {
+        Some(Flow::Usable) => "-usable",
+        Some(Flow::Save) => "-saved",
+        Some(Flow::Need) => "-need",
+        Some(Flow::Dead) => "-dead",
+        None => "",
+    };
+    format!(
+        "{quot}({}{var}){flow}",
+        spec_keyword(tag.quot_var.spec_type)
+    )
+}
+
+fn fmt_tags(tags: &[Tag]) -> String {
+    match tags {
+        [tag] => fmt_tag(tag),
+        tags => format!(
+            "[{}]",
+            tags.iter().map(fmt_tag).collect::<Vec<_>>().join(", ")
+        ),
+    }
+}
+
+fn fmt_full_type(typ: &FullType) -> String {
+    let base = typ.base.as_ref().map(ToString::to_string);
+    let tags = (!typ.tags.is_empty()).then(|| format!("@ {}", fmt_tags(&typ.tags)));
+    base.into_iter().chain(tags).collect::<Vec<_>>().join(" ")
+}
+
+fn fmt_signature(sig: &Signature) -> String {
+    let list = |types: &[hlc::parse::ast::FlaggedType]| {
+        types
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    format!("({}) -> {}", list(&sig.input), list(&sig.output))
+}
+
+fn code_block(code: &str) -> String {
+    format!("```caiman\n{code}\n```")
+}
+
+impl Index {
+    fn new(program: &[TopLevel], files: &[String], file: &str) -> Self {
+        let mut index = Self {
+            file: file.to_string(),
+            defs: vec![],
+            scopes: vec![],
+            context: None,
+            tags: DeducedTags::new(),
+        };
+        for (decl, file) in program.iter().zip(files) {
+            index.add_decl(decl, file);
+        }
+        index
+    }
+
+    /// Parses and type checks `text`, the contents of `file`. Returns the
+    /// index of the program if it parsed, along with any errors.
+    #[must_use]
+    #[allow(clippy::result_large_err)]
+    pub fn analyze(text: &str, file: &str) -> (Option<Self>, Vec<Diagnostic>) {
+        let (program, files) = match hlc::parse::parse_source(text, file) {
+            Ok(parsed) => parsed,
+            Err(e) => return (None, vec![e.to_diagnostic()]),
+        };
+        let mut index = Self::new(&program, &files, file);
+        let in_file = |error| {
+            Error {
+                error,
+                filename: file.to_string(),
+            }
+            .to_diagnostic()
+        };
+        let result = document::catch_internal_error(file, || {
+            let ast = normalize_ast(program).map_err(in_file)?;
+            let ctx = Context::new(&ast).map_err(in_file)?;
+            let lowered = lower_with_tags(post_typecheck_norm(ast), &ctx, false).map_err(in_file);
+            index.context = Some(ctx);
+            index.tags = lowered?.1;
+            Ok(())
+        });
+        let diagnostics = match result {
+            Ok(Ok(())) => vec![],
+            Ok(Err(d)) | Err(d) => vec![d],
+        };
+        (Some(index), diagnostics)
+    }
+
+    /// Updates the definitions to those of `text` if it parses, keeping the
+    /// typing context and tags of the last version that type checked
+    pub fn reparse(&mut self, text: &str) {
+        if let Ok((program, files)) = hlc::parse::parse_source(text, &self.file) {
+            let mut index = Self::new(&program, &files, &self.file);
+            index.context = self.context.take();
+            index.tags = std::mem::take(&mut self.tags);
+            *self = index;
+        }
+    }
+
+    fn add(&mut self, name: &str, kind: Kind, file: &str, info: Info) {
+        self.defs.push(Definition {
+            name: name.to_string(),
+            kind,
+            file: file.to_string(),
+            info,
+            scope: None,
+            annotation: None,
+        });
+    }
+
+    fn add_var(
+        &mut self,
+        name: &str,
+        scope: &str,
+        file: &str,
+        info: Info,
+        annotation: Option<String>,
+    ) {
+        self.defs.push(Definition {
+            name: name.to_string(),
+            kind: Kind::Variable,
+            file: file.to_string(),
+            info,
+            scope: Some(scope.to_string()),
+            annotation,
+        });
+    }
+
+    fn add_decl(&mut self, decl: &TopLevel, file: &str) {
+        match decl {
+            TopLevel::FunctionClass {
+                info,
+                name,
+                members,
+            } => {
+                self.add(name, Kind::FunctionClass, file, *info);
+                for member in members {
+                    match member {
+                        ClassMembers::ValueFunclet(f) => self.add_spec(f, SpecType::Value, file),
+                        ClassMembers::TimelineFunclet(f) => {
+                            self.add_spec(f, SpecType::Timeline, file);
+                        }
+                        ClassMembers::SpatialFunclet(f) => {
+                            self.add_spec(f, SpecType::Spatial, file);
+                        }
+                        ClassMembers::Extern { info, name, .. } => {
+                            self.add(name, Kind::Extern, file, *info);
+                        }
+                    }
+                }
+            }
+            TopLevel::SchedulingFunc {
+                info,
+                name,
+                input,
+                specs,
+                statements,
+                ..
+            } => {
+                self.add(name, Kind::Schedule, file, *info);
+                self.scopes.push(Scope {
+                    name: name.clone(),
+                    file: file.to_string(),
+                    info: *info,
+                    spec: None,
+                    specs: specs.clone(),
+                });
+                for (arg, typ) in input {
+                    self.add_var(arg, name, file, *info, typ.as_ref().map(fmt_full_type));
+                }
+                self.add_sched_vars(statements, name, file);
+            }
+            TopLevel::Typedef { info, name, .. } => self.add(name, Kind::Type, file, *info),
+            TopLevel::Const { info, name, .. } => self.add(name, Kind::Const, file, *info),
+            TopLevel::Pipeline { info, name, .. } => self.add(name, Kind::Pipeline, file, *info),
+            TopLevel::Import { .. } => (),
+        }
+    }
+
+    fn add_spec(&mut self, f: &SpecFunclet, spec: SpecType, file: &str) {
+        self.add(&f.name, Kind::Spec(spec), file, f.info);
+        self.scopes.push(Scope {
+            name: f.name.clone(),
+            file: file.to_string(),
+            info: f.info,
+            spec: Some(spec),
+            specs: vec![],
+        });
+        for (arg, typ) in &f.input {
+            self.add_var(arg, &f.name, file, f.info, Some(typ.to_string()));
+        }
+        for (arg, typ) in &f.output {
+            if let Some(arg) = arg {
+                self.add_var(arg, &f.name, file, f.info, Some(typ.to_string()));
+            }
+        }
+        self.add_spec_vars(&f.statements, &f.name, file);
+    }
+
+    fn add_spec_vars(&mut self, stmts: &[SpecStmt], scope: &str, file: &str) {
+        for stmt in stmts {
+            match stmt {
+                SpecStmt::Assign { info, lhs, .. } => {
+                    for (name, typ) in lhs {
+                        self.add_var(
+                            name,
+                            scope,
+                            file,
+                            *info,
+                            typ.as_ref().map(ToString::to_string),
+                        );
+                    }
+                }
+                SpecStmt::Loop {
+                    info,
+                    lhs,
+                    state,
+                    header,
+                    body,
+                } => {
+                    for (name, typ) in lhs {
+                        self.add_var(
+                            name,
+                            scope,
+                            file,
+                            *info,
+                            typ.as_ref().map(ToString::to_string),
+                        );
+                    }
+                    for var in state {
+                        self.add_var(&var.name, scope, file, *info, Some(var.typ.to_string()));
+                    }
+                    if let LoopHeader::For { name, typ, .. } = header {
+                        self.add_var(name, scope, file, *info, Some(typ.to_string()));
+                    }
+                    self.add_spec_vars(body, scope, file);
+                }
+                SpecStmt::Returns(..) => (),
+            }
+        }
+    }
+
+    fn add_sched_vars(&mut self, stmts: &[SchedStmt], scope: &str, file: &str) {
+        for stmt in stmts {
+            match stmt {
+                SchedStmt::Decl { info, lhs, .. } => {
+                    for (name, typ) in lhs {
+                        self.add_var(name, scope, file, *info, typ.as_ref().map(fmt_full_type));
+                    }
+                }
+                SchedStmt::Seq {
+                    info, dests, block, ..
+                } => {
+                    for (name, typ) in dests {
+                        self.add_var(name, scope, file, *info, typ.as_ref().map(fmt_full_type));
+                    }
+                    self.add_sched_vars(std::slice::from_ref(block), scope, file);
+                }
+                SchedStmt::Loop {
+                    info,
+                    dests,
+                    state,
+                    header,
+                    body,
+                } => {
+                    for (name, typ) in dests {
+                        self.add_var(name, scope, file, *info, typ.as_ref().map(fmt_full_type));
+                    }
+                    for var in state {
+                        self.add_var(&var.name, scope, file, *info, Some(fmt_full_type(&var.typ)));
+                    }
+                    if let LoopHeader::For { name, typ, .. } = header {
+                        self.add_var(name, scope, file, *info, Some(typ.to_string()));
+                    }
+                    self.add_sched_vars(body, scope, file);
+                }
+                SchedStmt::Encode { info, stmt, .. } => {
+                    for (name, tags) in &stmt.lhs {
+                        let annotation = tags.as_ref().map(|t| format!("@ {}", fmt_tags(t)));
+                        self.add_var(name, scope, file, *info, annotation);
+                    }
+                }
+                SchedStmt::If {
+                    true_block,
+                    false_block,
+                    ..
+                } => {
+                    self.add_sched_vars(true_block, scope, file);
+                    self.add_sched_vars(false_block, scope, file);
+                }
+                SchedStmt::Block(_, stmts) => self.add_sched_vars(stmts, scope, file),
+                _ => (),
+            }
+        }
+    }
+
+    /// Gets the funclet of this file containing `pos`
+    fn scope_at(&self, pos: Position) -> Option<&Scope> {
+        self.scopes.iter().find(|s| {
+            s.file == self.file
+                && document::on_lines(s.info.start_ln_and_col.0, s.info.end_ln_and_col.0, pos)
+        })
+    }
+
+    /// Gets the name of the spec of type `spec` that `scope` refers to
+    fn spec_of<'a>(&'a self, scope: &'a Scope, spec: SpecType) -> Option<&'a str> {
+        if scope.spec == Some(spec) {
+            return Some(&scope.name);
+        }
+        scope
+            .specs
+            .iter()
+            .find(|s| {
+                self.defs
+                    .iter()
+                    .any(|d| &d.name == *s && d.kind == Kind::Spec(spec))
+            })
+            .map(String::as_str)
+    }
+
+    fn variable(&self, scope: &str, name: &str) -> Option<&Definition> {
+        self.defs.iter().find(|d| {
+            d.kind == Kind::Variable && d.scope.as_deref() == Some(scope) && d.name == name
+        })
+    }
+
+    fn global(&self, name: &str) -> Option<&Definition> {
+        self.defs
+            .iter()
+            .find(|d| d.kind != Kind::Variable && d.name == name)
+    }
+
+    fn variables(&self, scope: &str) -> BTreeSet<&str> {
+        self.defs
+            .iter()
+            .filter(|d| d.kind == Kind::Variable && d.scope.as_deref() == Some(scope))
+            .map(|d| d.name.as_str())
+            .collect()
+    }
+
+    /// Finds the definition of the name at `pos`, which is either a variable
+    /// of the enclosing funclet, a spec variable referred to by a quotient,
+    /// or a top-level name
+    fn resolve(&self, text: &str, pos: Position) -> Option<&Definition> {
+        let word = document::word_at(text, pos)?;
+        let scope = self.scope_at(pos);
+        if let Some(spec) = quoted_spec(word.before) {
+            let spec = self.spec_of(scope?, spec)?;
+            return self.variable(spec, word.text);
+        }
+        scope
+            .and_then(|s| self.variable(&s.name, word.text))
+            .or_else(|| self.global(word.text))
+    }
+
+    #[must_use]
+    pub fn definition(&self, text: &str, pos: Position) -> Option<Location> {
+        let def = self.resolve(text, pos)?;
+        Some(Location::new(
+            document::file_url(&def.file)?,
+            document::range(def.info.start_ln_and_col, def.info.end_ln_and_col),
+        ))
+    }
+
+    fn data_type(&self, scope: &str, name: &str) -> Option<&DataType> {
+        let ctx = self.context.as_ref()?;
+        ctx.specs
+            .get(scope)
+            .and_then(|s| s.types.get(name))
+            .or_else(|| match ctx.scheds.get(scope) {
+                // schedule variables are renamed to `name_id` before type
+                // checking, and renaming never changes their data type
+                Some(SchedOrExtern::Sched(s)) => s
+                    .types
+                    .get(name)
+                    .or_else(|| s.types.get(&format!("{name}_0"))),
+                _ => None,
+            })
+    }
+
+    /// Gets the tags deduced for a variable of a scheduling function
+    fn deduced_tags(&self, scope: &str, name: &str) -> Option<&[Tag]> {
+        let tags = self.tags.get(scope)?;
+        // see `data_type` for why variables may have the suffix `_0`
+        tags.get(name)
+            .or_else(|| tags.get(&format!("{name}_0")))
+            .map(Vec::as_slice)
+    }
+
+    /// Describes the name at `pos` in markdown
+    #[must_use]
+    pub fn hover(&self, text: &str, pos: Position) -> Option<String> {
+        let def = self.resolve(text, pos)?;
+        let ctx = self.context.as_ref();
+        Some(match def.kind {
+            Kind::Variable => {
+                let scope = def.scope.as_deref().unwrap_or_default();
+                let typ = self
+                    .data_type(scope, &def.name)
+                    .map_or_else(|| "?".to_string(), ToString::to_string);
+                let mut hover = code_block(&format!("{}: {typ}", def.name));
+                if let Some(annotation) = &def.annotation {
+                    write!(hover, "\n\nAnnotated as `{annotation}`").unwrap();
+                }
+                if let Some(tags) = self.deduced_tags(scope, &def.name) {
+                    write!(hover, "\n\nDeduced as `@ {}`", fmt_tags(tags)).unwrap();
+                }
+                write!(hover, "\n\nDefined in `{scope}`").unwrap();
+                hover
+            }
+            Kind::FunctionClass => {
+                let sig = ctx
+                    .and_then(|c| c.signatures.get(&def.name))
+                    .map(fmt_signature)
+                    .unwrap_or_default();
+                code_block(&format!("feq {}{sig}", def.name))
+            }
+            Kind::Spec(spec) => {
+                let sig = ctx
+                    .and_then(|c| c.specs.get(&def.name))
+                    .map(|s| fmt_signature(&(&s.sig).into()))
+                    .unwrap_or_default();
+                code_block(&format!("{} {}{sig}", spec_keyword(spec), def.name))
+            }
+            Kind::Schedule | Kind::Extern => {
+                let sig = ctx
+                    .and_then(|c| c.scheds.get(&def.name))
+                    .map(|s| fmt_signature(s.sig()))
+                    .unwrap_or_default();
+                let kw = if def.kind == Kind::Schedule {
+                    "fn"
+                } else {
+                    "extern"
+                };
+                code_block(&format!("{kw} {}{sig}", def.name))
+            }
+            Kind::Type => code_block(&format!("type {}", def.name)),
+            Kind::Const => code_block(&format!("const {}", def.name)),
+            Kind::Pipeline => code_block(&format!("pipeline {}", def.name)),
+        })
+    }
+
+    /// Completes spec variable names inside a quotient such as `@ node(val.`
+    #[must_use]
+    pub fn completion(&self, text: &str, pos: Position) -> Vec<CompletionItem> {
+        let before = document::line_before(text, pos);
+        let before = before.trim_end_matches(is_word_char);
+        let Some(scope) = self.scope_at(pos) else {
+            return vec![];
+        };
+        let item = |spec: &str, label: String, name: &str| CompletionItem {
+            detail: self.data_type(spec, name).map(ToString::to_string),
+            kind: Some(CompletionItemKind::VARIABLE),
+            label,
+            ..CompletionItem::default()
+        };
+        if let Some(spec) = quoted_spec(before) {
+            let Some(spec) = self.spec_of(scope, spec) else {
+                return vec![];
+            };
+            return self
+                .variables(spec)
+                .into_iter()
+                .map(|name| item(spec, name.to_string(), name))
+                .collect();
+        }
+        if !opens_quotient(before) {
+            return vec![];
+        }
+        let mut items = vec![];
+        for (kw, spec) in SPEC_KEYWORDS {
+            if let Some(spec) = self.spec_of(scope, spec) {
+                items.extend(
+                    self.variables(spec)
+                        .into_iter()
+                        .map(|name| item(spec, format!("{kw}.{name}"), name)),
+                );
+            }
+        }
+        items
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const PROGRAM: &str = "#version 0.1.0
+
+tmln time(e: Event) -> Event { returns e }
+sptl space(bs: BufferSpace) -> BufferSpace { returns bs }
+
+val main() -> i64 {
+    a :- 3
+    b :- 5
+    c :- a + b
+    returns c
+}
+
+fn main_impl() -> i64
+    impls main, time, space
+{
+    let a: i64 @ node(val.a)-usable = 3;
+    let b: i64 @ node(val.b) = 5;
+    let c @ node(val.c) = a + b;
+    c
+}
+
+pipeline main { main_impl }
+";
+
+    fn index() -> Index {
+        let (index, diagnostics) = Index::analyze(PROGRAM, "main.cm");
+        assert!(diagnostics.is_empty(), "{diagnostics:?}");
+        index.unwrap()
+    }
+
+    fn line_of(def: Option<Location>) -> u32 {
+        def.unwrap().range.start.line
+    }
+
+    #[test]
+    fn definitions() {
+        let index = index();
+        // `val.b` in `@ node(val.b)` goes to `b :- 5`
+        assert_eq!(line_of(index.definition(PROGRAM, Position::new(16, 26))), 7);
+        // `a` in `a + b` goes to the schedule's `let a`
+        assert_eq!(
+            line_of(index.definition(PROGRAM, Position::new(17, 26))),
+            15
+        );
+        // `main` in `impls main` goes to the value spec
+        assert_eq!(line_of(index.definition(PROGRAM, Position::new(13, 12))), 5);
+        assert!(index.definition(PROGRAM, Position::new(13, 6)).is_none());
+    }
+
+    #[test]
+    fn hover_and_completion() {
+        let index = index();
+        let hover = index.hover(PROGRAM, Position::new(15, 8)).unwrap();
+        assert!(hover.contains("a: i64"), "{hover}");
+        assert!(hover.contains("node(val.a)-usable"), "{hover}");
+        // `c` is annotated with neither a flow nor spatial and timeline tags
+        let hover = index.hover(PROGRAM, Position::new(17, 8)).unwrap();
+        assert!(
+            hover.contains("Deduced as `@ [node(val.c)-usable, none(sptl)-usable"),
+            "{hover}"
+        );
+        let names = |items: Vec<CompletionItem>| -> Vec<String> {
+            items.into_iter().map(|i| i.label).collect()
+        };
+        assert_eq!(
+            names(index.completion(PROGRAM, Position::new(16, 26))),
+            ["a", "b", "c"]
+        );
+        assert!(
+            names(index.completion(PROGRAM, Position::new(16, 22))).contains(&"val.c".to_string())
+        );
+        assert!(index.completion(PROGRAM, Position::new(17, 26)).is_empty());
+    }
+
+    #[test]
+    fn quotients() {
+        assert_eq!(quoted_spec("let x @ node(val."), Some(SpecType::Value));
+        assert_eq!(quoted_spec("@ node(tmln.(loc1, "), Some(SpecType::Timeline));
+        assert_eq!(quoted_spec("let y = f."), None);
+        assert!(opens_quotient("@ [node(val.x), input( "));
+        assert!(!opens_quotient("foo("));
+    }
+}
@@ -0,0 +1,25 @@
+#![warn(clippy::pedantic, clippy::nursery)]
+#![warn(clippy::all, clippy::correctness)]
+//! A language server for high-level Caiman (`.cm`) and Caiman assembly
+//! (`.cair`) files, communicating over stdio.
+//!
+//! It provides diagnostics when a file is opened or saved, go-to-definition
+//! for function classes, funclets, variables, and spec variables referenced
+//! by quotients such as `node(tmln.sub1)`, hover showing data types and
+//! annotated and deduced tags, and completion of spec node names inside
+//! `@ node(...)`.
+
+mod assembly;
+mod document;
+mod high_level;
+mod server;
+
+use lsp_server::Connection;
+
+fn main() -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
+    let (connection, io_threads) = Connection::stdio();
+    server::run(&connection)?;
+    drop(connection);
+    io_threads.join()?;
+    Ok(())
+}
@@ -0,0 +1,332 @@
+//! The main loop of the language server.
+//!
+//! Documents are analyzed in full when they are opened or saved, which
+//! publishes their diagnostics. Edits in between only refresh the definitions
+//! used by go-to-definition, hover, and completion, and only when the edited
+//! text parses.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+
+use lsp_server::{Connection, ExtractError, Message, Notification, Request, RequestId, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, DidSaveTextDocument,
+    Notification as _, PublishDiagnostics,
+};
+use lsp_types::request::{Completion, GotoDefinition, HoverRequest};
+use lsp_types::{
+    CompletionOptions, CompletionResponse, GotoDefinitionResponse, Hover, HoverContents,
+    HoverProviderCapability, MarkupContent, MarkupKind, OneOf, Position, PublishDiagnosticsParams,
+    ServerCapabilities, TextDocumentSyncCapability, TextDocumentSyncKind, TextDocumentSyncOptions,
+    TextDocumentSyncSaveOptions, Url,
+};
+
+use crate::{assembly, document, high_level};
+
+type Result<T> = std::result::Result<T, Box<dyn Error + Sync + Send>>;
+
+#[allow(clippy::large_enum_variant)]
+enum Analysis {
+    HighLevel(high_level::Index),
+    Assembly(assembly::Index),
+}
+
+struct Document {
+    /// The path of the document, which is how the compilers refer to it
+    file: String,
+    text: String,
+    /// The definitions of the last version of the document that parsed
+    analysis: Option<Analysis>,
+}
+
+impl Document {
+    fn is_assembly(&self) -> bool {
+        Path::new(&self.file)
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("cair"))
+    }
+
+    /// Analyzes the document, returning its diagnostics
+    fn analyze(&mut self) -> Vec<lsp_types::Diagnostic> {
+        let diagnostics = if self.is_assembly() {
+            let (index, diagnostics) = assembly::Index::analyze(&self.text, &self.file);
+            if let Some(index) = index {
+                self.analysis = Some(Analysis::Assembly(index));
+            }
+            diagnostics
+        } else {
+            let (index, diagnostics) = high_level::Index::analyze(&self.text, &self.file);
+            if let Some(index) = index {
+                self.analysis = Some(Analysis::HighLevel(index));
+            }
+            diagnostics
+        };
+        diagnostics
+            .iter()
+            .map(|d| document::to_lsp_diagnostic(d, &self.file))
+            .collect()
+    }
+
+    fn change(&mut self, text: String) {
+        self.text = text;
+        match &mut self.analysis {
+            Some(Analysis::HighLevel(index)) => index.reparse(&self.text),
+            Some(Analysis::Assembly(index)) => index.reparse(&self.text),
+            None => (),
+        }
+    }
+}
+
+/// The capabilities of the server
+#[must_use]
+pub fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Options(
+            TextDocumentSyncOptions {
+                open_close: Some(true),
+                change: Some(TextDocumentSyncKind::FULL),
+                save: Some(TextDocumentSyncSaveOptions::Supported(true)),
+                ..TextDocumentSyncOptions::default()
+            },
+        )),
+        definition_provider: Some(OneOf::Left(true)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec![".".to_string(), "(".to_string(), "%".to_string()]),
+            ..CompletionOptions::default()
+        }),
+        ..ServerCapabilities::default()
+    }
+}
+
+fn file_of(uri: &Url) -> String {
+    uri.to_file_path().map_or_else(
+        |()| uri.path().to_string(),
+        |p| p.to_string_lossy().to_string(),
+    )
+}
+
+/// Extracts the parameters of a request of type `R`, or gives the request
+/// back if it is of another type
+/// # Errors
+/// Returns a message describing the error if the request is of type `R` but
+/// its parameters are malformed
+fn cast<R: lsp_types::request::Request>(
+    req: Request,
+) -> std::result::Result<std::result::Result<(RequestId, R::Params), Request>, String> {
+    match req.extract(R::METHOD) {
+        Ok(params) => Ok(Ok(params)),
+        Err(ExtractError::MethodMismatch(req)) => Ok(Err(req)),
+        Err(ExtractError::JsonError { method, error }) => {
+            Err(format!("Malformed {method} request: {error}"))
+        }
+    }
+}
+
+/// Extracts the parameters of a notification of type `N`, or gives the
+/// notification back if it is of another type
+/// # Errors
+/// Returns a message describing the error if the notification is of type `N`
+/// but its parameters are malformed
+fn cast_notification<N: lsp_types::notification::Notification>(
+    not: Notification,
+) -> std::result::Result<std::result::Result<N::Params, Notification>, String> {
+    match not.extract(N::METHOD) {
+        Ok(params) => Ok(Ok(params)),
+        Err(ExtractError::MethodMismatch(not)) => Ok(Err(not)),
+        Err(ExtractError::JsonError { method, error }) => {
+            Err(format!("Malformed {method} notification: {error}"))
+        }
+    }
+}
+
+struct Server<'a> {
+    connection: &'a Connection,
+    documents: HashMap<Url, Document>,
+}
+
+impl Server<'_> {
+    fn publish(&self, uri: Url, diagnostics: Vec<lsp_types::Diagnostic>) -> Result<()> {
+        let params = PublishDiagnosticsParams::new(uri, diagnostics, None);
+        self.connection
+            .sender
+            .send(Message::Notification(Notification::new(
+                PublishDiagnostics::METHOD.to_string(),
+                params,
+            )))?;
+        Ok(())
+    }
+
+    fn analyze(&mut self, uri: &Url) -> Result<()> {
+        if let Some(doc) = self.documents.get_mut(uri) {
+            let diagnostics = doc.analyze();
+            self.publish(uri.clone(), diagnostics)?;
+        }
+        Ok(())
+    }
+
+    fn notification(&mut self, not: Notification) -> Result<()> {
+        // notifications have no response to report an error in, so malformed
+        // ones are only logged
+        self.handle_notification(not).unwrap_or_else(|error| {
+            eprintln!("{error}");
+            Ok(())
+        })
+    }
+
+    fn handle_notification(
+        &mut self,
+        not: Notification,
+    ) -> std::result::Result<Result<()>, String> {
+        let not = match cast_notification::<DidOpenTextDocument>(not)? {
+            Ok(params) => {
+                let uri = params.text_document.uri;
+                let doc = Document {
+                    file: file_of(&uri),
+                    text: params.text_document.text,
+                    analysis: None,
+                };
+                self.documents.insert(uri.clone(), doc);
+                return Ok(self.analyze(&uri));
+            }
+            Err(not) => not,
+        };
+        let not = match cast_notification::<DidChangeTextDocument>(not)? {
+            Ok(params) => {
+                let uri = params.text_document.uri;
+                if let (Some(doc), Some(change)) = (
+                    self.documents.get_mut(&uri),
+                    params.content_changes.into_iter().last(),
+                ) {
+                    doc.change(change.text);
+                }
+                return Ok(Ok(()));
+            }
+            Err(not) => not,
+        };
+        let not = match cast_notification::<DidSaveTextDocument>(not)? {
+            Ok(params) => {
+                let uri = params.text_document.uri;
+                if let (Some(doc), Some(text)) = (self.documents.get_mut(&uri), params.text) {
+                    doc.change(text);
+                }
+                return Ok(self.analyze(&uri));
+            }
+            Err(not) => not,
+        };
+        if let Ok(params) = cast_notification::<DidCloseTextDocument>(not)? {
+            let uri = params.text_document.uri;
+            self.documents.remove(&uri);
+            return Ok(self.publish(uri, vec![]));
+        }
+        Ok(Ok(()))
+    }
+
+    /// Runs `f` on the document at `uri` and the position of a request
+    fn with_document<T>(
+        &self,
+        uri: &Url,
+        f: impl FnOnce(&Analysis, &str) -> Option<T>,
+    ) -> Option<T> {
+        let doc = self.documents.get(uri)?;
+        f(doc.analysis.as_ref()?, &doc.text)
+    }
+
+    fn request(&self, req: Request) -> Result<()> {
+        let id = req.id.clone();
+        let response = self.respond(req).unwrap_or_else(|error| {
+            Response::new_err(id, lsp_server::ErrorCode::InvalidParams as i32, error)
+        });
+        self.connection.sender.send(Message::Response(response))?;
+        Ok(())
+    }
+
+    /// Gets the response to `req`
+    /// # Errors
+    /// Returns a message describing the error if the request is malformed
+    fn respond(&self, req: Request) -> std::result::Result<Response, String> {
+        Ok(match cast::<GotoDefinition>(req)? {
+            Ok((id, params)) => {
+                let params = params.text_document_position_params;
+                let pos = params.position;
+                let location =
+                    self.with_document(
+                        &params.text_document.uri,
+                        |analysis, text| match analysis {
+                            Analysis::HighLevel(index) => index.definition(text, pos),
+                            Analysis::Assembly(index) => index.definition(text, pos),
+                        },
+                    );
+                Response::new_ok(id, location.map(GotoDefinitionResponse::Scalar))
+            }
+            Err(req) => match cast::<HoverRequest>(req)? {
+                Ok((id, params)) => {
+                    let params = params.text_document_position_params;
+                    let pos: Position = params.position;
+                    let hover = self.with_document(&params.text_document.uri, |analysis, text| {
+                        match analysis {
+                            Analysis::HighLevel(index) => index.hover(text, pos),
+                            Analysis::Assembly(_) => None,
+                        }
+                    });
+                    Response::new_ok(
+                        id,
+                        hover.map(|value| Hover {
+                            contents: HoverContents::Markup(MarkupContent {
+                                kind: MarkupKind::Markdown,
+                                value,
+                            }),
+                            range: None,
+                        }),
+                    )
+                }
+                Err(req) => match cast::<Completion>(req)? {
+                    Ok((id, params)) => {
+                        let params = params.text_document_position;
+                        let pos = params.position;
+                        let items = self
+                            .with_document(&params.text_document.uri, |analysis, text| {
+                                Some(match analysis {
+                                    Analysis::HighLevel(index) => index.completion(text, pos),
+                                    Analysis::Assembly(index) => index.completion(text, pos),
+                                })
+                            })
+                            .unwrap_or_default();
+                        Response::new_ok(id, CompletionResponse::Array(items))
+                    }
+                    Err(req) => Response::new_err(
+                        req.id,
+                        lsp_server::ErrorCode::MethodNotFound as i32,
+                        format!("Unsupported request {}", req.method),
+                    ),
+                },
+            },
+        })
+    }
+}
+
+/// Serves requests on `connection` until the client shuts the server down
+/// # Errors
+/// Returns an error if the connection to the client fails
+pub fn run(connection: &Connection) -> Result<()> {
+    let capabilities = serde_json::to_value(capabilities())?;
+    connection.initialize(capabilities)?;
+    let mut server = Server {
+        connection,
+        documents: HashMap::new(),
+    };
+    for msg in &connection.receiver {
+        match msg {
+            Message::Request(req) => {
+                if connection.handle_shutdown(&req)? {
+                    return Ok(());
+                }
+                server.request(req)?;
+            }
+            Message::Notification(not) => server.notification(not)?,
+            Message::Response(_) => (),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,173 @@
+//! Drives the language server over stdio the way an editor would.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::PathBuf;
+use std::process::{ChildStdin, ChildStdout, Command, Stdio};
+
+use serde_json::{json, Value};
+
+const PROGRAM: &str = "#version 0.1.0
+
+tmln time(e: Event) -> Event { returns e }
+sptl space(bs: BufferSpace) -> BufferSpace { returns bs }
+
+val main() -> i64 {
+    a :- 3
+    b :- 5
+    c :- a + b
+    returns c
+}
+
+fn main_impl() -> i64
+    impls main, time, space
+{
+    let a: i64 @ node(val.a) = 3;
+    let b: i64 @ node(val.b) = 5;
+    let c @ node(val.c) = a + b;
+    c
+}
+
+pipeline main { main_impl }
+";
+
+struct Client {
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    next_id: i64,
+}
+
+impl Client {
+    fn send(&mut self, msg: &Value) {
+        let body = msg.to_string();
+        write!(self.stdin, "Content-Length: {}\r\n\r\n{body}", body.len()).unwrap();
+        self.stdin.flush().unwrap();
+    }
+
+    fn recv(&mut self) -> Value {
+        let mut len = 0;
+        loop {
+            let mut line = String::new();
+            self.stdout.read_line(&mut line).unwrap();
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some(n) = line.strip_prefix("Content-Length: ") {
+                len = n.parse().unwrap();
+            }
+        }
+        let mut body = vec![0; len];
+        self.stdout.read_exact(&mut body).unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    fn notify(&mut self, method: &str, params: Value) {
+        self.send(&json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+    }
+
+    /// Sends a request and gets the whole response, including any error
+    fn respond(&mut self, method: &str, params: Value) -> Value {
+        self.next_id += 1;
+        let id = self.next_id;
+        self.send(&json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }));
+        loop {
+            let msg = self.recv();
+            if msg["id"] == id {
+                return msg;
+            }
+        }
+    }
+
+    fn request(&mut self, method: &str, params: Value) -> Value {
+        self.respond(method, params)["result"].clone()
+    }
+
+    fn diagnostics(&mut self) -> Vec<Value> {
+        loop {
+            let msg = self.recv();
+            if msg["method"] == "textDocument/publishDiagnostics" {
+                return msg["params"]["diagnostics"].as_array().unwrap().clone();
+            }
+        }
+    }
+}
+
+fn temp_file(name: &str, text: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("caiman-lsp-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    std::fs::write(&path, text).unwrap();
+    path.canonicalize().unwrap()
+}
+
+#[test]
+fn session() {
+    let path = temp_file("main.cm", PROGRAM);
+    let uri = format!("file://{}", path.display());
+    let mut server = Command::new(env!("CARGO_BIN_EXE_caiman-lsp"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut client = Client {
+        stdin: server.stdin.take().unwrap(),
+        stdout: BufReader::new(server.stdout.take().unwrap()),
+        next_id: 0,
+    };
+
+    let caps = client.request("initialize", json!({ "capabilities": {} }));
+    assert_eq!(caps["capabilities"]["definitionProvider"], true);
+    client.notify("initialized", json!({}));
+
+    client.notify(
+        "textDocument/didOpen",
+        json!({ "textDocument": {
+            "uri": uri, "languageId": "caiman", "version": 1, "text": PROGRAM
+        }}),
+    );
+    assert_eq!(client.diagnostics(), Vec::<Value>::new());
+
+    let at = |line: u32, character: u32| {
+        json!({
+            "textDocument": { "uri": uri },
+            "position": { "line": line, "character": character }
+        })
+    };
+    let def = client.request("textDocument/definition", at(16, 26));
+    assert_eq!(def["uri"], uri.as_str());
+    assert_eq!(def["range"]["start"]["line"], 7);
+
+    let hover = client.request("textDocument/hover", at(15, 8));
+    let hover = hover["contents"]["value"].as_str().unwrap();
+    assert!(hover.contains("a: i64"), "{hover}");
+    assert!(hover.contains("Deduced as"), "{hover}");
+
+    // malformed requests get an error instead of stopping the server
+    let malformed = client.respond("textDocument/hover", json!({ "position": 3 }));
+    assert_eq!(malformed["error"]["code"], -32602, "{malformed}");
+    client.notify("textDocument/didChange", json!({ "textDocument": 3 }));
+
+    let completion = client.request("textDocument/completion", at(16, 26));
+    let labels: Vec<_> = completion
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|i| i["label"].as_str().unwrap().to_string())
+        .collect();
+    assert_eq!(labels, ["a", "b", "c"]);
+
+    // introduce a type error and save
+    let broken = PROGRAM.replace("let b: i64", "let b: bool");
+    client.notify(
+        "textDocument/didSave",
+        json!({ "textDocument": { "uri": uri }, "text": broken }),
+    );
+    let diagnostics = client.diagnostics();
+    assert_eq!(diagnostics.len(), 1, "{diagnostics:?}");
+    assert_eq!(diagnostics[0]["severity"], 1);
+
+    client.request("shutdown", Value::Null);
+    client.notify("exit", Value::Null);
+    assert!(server.wait().unwrap().success());
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
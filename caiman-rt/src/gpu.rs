@@ -0,0 +1,296 @@
+// Everything generated code needs to run on a GPU, which is only built with the `wgpu` feature
+
+use super::{AbstractAllocator, BumpAllocator, CpuAllocator, State, TypeLayout};
+use std::{any::Any, collections::HashMap};
+use wgpu::Buffer;
+
+// None = waits on whole queue
+pub type GpuFence = Option<wgpu::SubmissionIndex>;
+
+/// Allocates data on the GPU.
+struct GpuAllocator {
+    buffer: Buffer,
+    allocator: BumpAllocator,
+}
+
+impl GpuAllocator {
+    pub fn new(state: &mut dyn GpuState, usage: wgpu::BufferUsages) -> Self {
+        const BUFFER_SIZE: u64 = 4096;
+        Self {
+            buffer: state
+                .get_device_mut()
+                .create_buffer(&wgpu::BufferDescriptor {
+                    label: None,
+                    size: BUFFER_SIZE,
+                    usage,
+                    mapped_at_creation: false,
+                }),
+            allocator: BumpAllocator::new(BUFFER_SIZE as usize, 0),
+        }
+    }
+
+    pub fn alloc(&mut self, id: usize, size: usize, align: usize) {
+        self.allocator.alloc(id, size, align);
+    }
+
+    pub fn get_buffer_ref<T: Sized + Any>(&self, id: usize) -> GpuBufferRef<T> {
+        let addr = self.allocator.get_starting_addr(id);
+        GpuBufferRef::new(&self.buffer, addr.0 as wgpu::BufferAddress)
+    }
+
+    pub fn reset(&mut self) {
+        self.allocator.reset();
+    }
+}
+
+/// What code generated by the wgpu backend needs to run its kernels.
+pub trait GpuState: State {
+    fn get_device_mut(&mut self) -> &mut wgpu::Device;
+    fn get_queue_mut(&mut self) -> &mut wgpu::Queue;
+}
+
+pub struct RootState<'device, 'queue> {
+    device: &'device mut wgpu::Device,
+    queue: &'queue mut wgpu::Queue,
+    local_storage: CpuAllocator,
+}
+
+impl<'device, 'queue> RootState<'device, 'queue> {
+    pub fn new(device: &'device mut wgpu::Device, queue: &'queue mut wgpu::Queue) -> Self {
+        Self {
+            device,
+            queue,
+            local_storage: CpuAllocator::new(4096 * 4),
+        }
+    }
+}
+
+impl<'device, 'queue> State for RootState<'device, 'queue> {}
+
+impl<'device, 'queue> GpuState for RootState<'device, 'queue> {
+    fn get_device_mut(&mut self) -> &mut wgpu::Device {
+        self.device
+    }
+
+    fn get_queue_mut(&mut self) -> &mut wgpu::Queue {
+        self.queue
+    }
+}
+
+const GPU_BUFFERS: usize = 5;
+
+/// Manages the allocation of local variables on the GPU.
+/// Kept separate from `LocalVars` to make it possible to have a GPU and CPU
+/// reference live at the same time (ie. mutable borrow from two different
+/// objects instead of one).
+pub struct GpuLocals {
+    // one buffer for each local variable
+    gpu_allocators: Vec<GpuAllocator>,
+    // maps variable ids to the index of the gpu allocator that holds them
+    alloc_map: HashMap<usize, usize>,
+    // maps variable ids to their type ids. Used only for runtime checks
+    type_ids: HashMap<usize, std::any::TypeId>,
+}
+
+impl GpuLocals {
+    pub fn new(state: &mut dyn GpuState) -> Self {
+        Self {
+            gpu_allocators: vec![],
+            alloc_map: HashMap::new(),
+            type_ids: HashMap::new(),
+        }
+    }
+
+    /// Clears all allocations and resets the allocator to the empty state.
+    pub fn reset(&mut self) {
+        self.alloc_map.clear();
+        self.type_ids.clear();
+        for gpu_alloc in self.gpu_allocators.iter_mut() {
+            gpu_alloc.reset();
+        }
+    }
+
+    /// Allocates a GPU local variable with the given id, size, and alignment.
+    pub fn alloc_gpu<T: Sized + Any>(
+        &mut self,
+        state: &mut dyn GpuState,
+        id: usize,
+        usage: wgpu::BufferUsages,
+    ) {
+        let idx = self.gpu_allocators.len();
+        self.alloc_map.insert(id, idx);
+        self.type_ids.insert(id, std::any::TypeId::of::<T>());
+        // TODO: we can simplify this and avoid the need for a `GpuAllocator`
+        // since we can't actually allocate multiple things from a single
+        // buffer
+        self.gpu_allocators.push(GpuAllocator::new(state, usage));
+        self.gpu_allocators[idx].alloc(
+            id,
+            std::mem::size_of::<T>(),
+            std::mem::align_of::<T>()
+                .max(wgpu::Limits::default().min_storage_buffer_offset_alignment as usize),
+        );
+    }
+
+    /// Gets a GPU mutable pointer to the start of the allocation for the given id.
+    pub fn get_gpu_ref<T: Sized + Any>(&self, id: usize) -> GpuBufferRef<T> {
+        assert_eq!(
+            self.type_ids.get(&id).unwrap(),
+            &std::any::TypeId::of::<T>()
+        );
+        let idx = self.alloc_map.get(&id).unwrap();
+        self.gpu_allocators[*idx].get_buffer_ref(id)
+    }
+}
+
+#[derive(Debug)]
+pub struct GpuBufferAllocator<'buffer> {
+    buffer: &'buffer wgpu::Buffer,
+    abstract_allocator: AbstractAllocator,
+}
+
+impl<'buffer> GpuBufferAllocator<'buffer> {
+    pub fn new(buffer: &'buffer wgpu::Buffer, size: usize) -> Self {
+        Self {
+            buffer,
+            abstract_allocator: AbstractAllocator::new(size),
+        }
+    }
+
+    pub fn suballocate_ref<T: Sized>(&mut self) -> Option<GpuBufferRef<T>> {
+        if let Some(starting_address) = self.abstract_allocator.suballocate_ref::<T>() {
+            return Some(GpuBufferRef::new(
+                self.buffer,
+                starting_address.try_into().unwrap(),
+            ));
+        }
+
+        return None;
+    }
+
+    pub fn suballocate_slice<T: Sized>(
+        &mut self,
+        count: usize,
+    ) -> Option<GpuBufferSlice<'buffer, T>> {
+        if let Some((starting_address, byte_size)) =
+            self.abstract_allocator.suballocate_slice::<T>(count)
+        {
+            return Some(GpuBufferSlice::new(
+                self.buffer,
+                starting_address.try_into().unwrap(),
+                Some(std::num::NonZeroU64::new(byte_size.try_into().unwrap()).unwrap()),
+            ));
+        }
+
+        return None;
+    }
+
+    // Returns how many of the given allocations would succeed, in order
+    pub fn test_suballocate_many(
+        &self,
+        layouts: &[TypeLayout],
+        element_counts: &[Option<usize>],
+    ) -> usize {
+        self.abstract_allocator
+            .test_suballocate_many(layouts, element_counts)
+    }
+}
+
+#[derive(Debug)]
+// A slot holding a pointer to gpu-resident data of type T
+pub struct GpuBufferRef<T: Sized> {
+    phantom: std::marker::PhantomData<*const T>,
+    pub buffer: *const wgpu::Buffer,
+    pub base_address: wgpu::BufferAddress,
+    //offset : wgpu::DynamicOffset,
+}
+
+// A slot is only a view into a buffer, so copying it doesn't copy the data
+impl<T: Sized> Clone for GpuBufferRef<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: Sized> Copy for GpuBufferRef<T> {}
+
+impl<T: Sized> GpuBufferRef<T> {
+    pub fn new(buffer: &wgpu::Buffer, base_address: wgpu::BufferAddress) -> Self {
+        Self {
+            phantom: std::marker::PhantomData,
+            buffer,
+            base_address,
+        }
+    }
+
+    pub fn as_binding_resource(&self) -> wgpu::BindingResource<'static> {
+        let size_n: u64 = std::mem::size_of::<T>().try_into().unwrap();
+        wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+            // caiman should enforce that this is safe
+            buffer: unsafe { &*self.buffer },
+            offset: self.base_address,
+            size: std::num::NonZeroU64::new(size_n),
+        })
+    }
+
+    pub fn slice(&self) -> wgpu::BufferSlice<'static> {
+        // Technically, this could overflow?
+        let mut end_address = self.base_address;
+        // Rust needs the type hint...
+        let size_opt: Option<wgpu::BufferAddress> = std::mem::size_of::<T>().try_into().ok();
+        end_address += size_opt.unwrap();
+        // caiman should enforce that this is safe
+        unsafe { &*self.buffer }.slice(self.base_address..end_address)
+    }
+
+    // Mapping a slot maps its whole buffer, so this unmaps the buffer
+    pub fn unmap(&self) {
+        unsafe { &*self.buffer }.unmap()
+    }
+}
+
+#[derive(Debug)]
+// A slot holding a pointer to gpu-resident array of elements of type T
+pub struct GpuBufferSlice<'buffer, T: Sized> {
+    phantom: std::marker::PhantomData<*const T>,
+    pub buffer: &'buffer wgpu::Buffer,
+    pub base_address: wgpu::BufferAddress,
+    //offset : wgpu::DynamicOffset,
+    pub size_opt: Option<wgpu::BufferSize>,
+}
+
+impl<'buffer, T: Sized> GpuBufferSlice<'buffer, T> {
+    pub fn new(
+        buffer: &'buffer wgpu::Buffer,
+        base_address: wgpu::BufferAddress,
+        size_opt: Option<wgpu::BufferSize>,
+    ) -> Self {
+        Self {
+            phantom: std::marker::PhantomData,
+            buffer,
+            base_address,
+            size_opt,
+        }
+    }
+
+    pub fn as_binding_resource(&self) -> wgpu::BindingResource<'buffer> {
+        wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+            buffer: self.buffer,
+            offset: self.base_address,
+            size: self.size_opt,
+        })
+    }
+
+    pub fn slice(&self) -> wgpu::BufferSlice<'buffer> {
+        if let Some(size) = self.size_opt {
+            // Technically, this could overflow?
+            let mut end_address = self.base_address;
+            // Rust needs the type hint...
+            let size_opt: Option<wgpu::BufferAddress> = size.try_into().ok();
+            end_address += size_opt.unwrap();
+            self.buffer.slice(self.base_address..end_address)
+        } else {
+            self.buffer.slice(self.base_address..)
+        }
+    }
+}
@@ -6,12 +6,15 @@ use std::{
     mem::MaybeUninit, os::raw::c_void,
 };
 
-use wgpu::Buffer;
 pub extern crate bytemuck;
+#[cfg(feature = "wgpu")]
 pub extern crate wgpu;
 
-// None = waits on whole queue
-pub type GpuFence = Option<wgpu::SubmissionIndex>;
+#[cfg(feature = "wgpu")]
+mod gpu;
+#[cfg(feature = "wgpu")]
+pub use gpu::*;
+
 /// The dummy type for the encoder. This is just to allow
 /// passing an encoder via serialized join to typecheck
 pub type ErasedEncoder = ();
@@ -22,8 +25,8 @@ struct BumpAllocator {
     buffer_align: usize,
     // next available address in the buffer
     next_address: usize,
-    // map from variable ids to their starting addresses
-    address_map: HashMap<usize, BumpAddr>,
+    // map from variable ids to their starting addresses and sizes
+    address_map: HashMap<usize, (BumpAddr, usize)>,
     buffer_len: usize,
 }
 
@@ -47,21 +50,29 @@ impl BumpAllocator {
 
     /// Allocates a new variable with the given id, size, and alignment.
     /// The alignment must be less than or equal to `MAX_ALIGN`.
+    /// Allocating an id again reuses its old allocation when that one fits, since
+    /// the old allocation can only be reached through the id. This keeps loops,
+    /// which allocate the same ids every iteration, from running out of space.
     fn alloc(&mut self, id: usize, size: usize, align: usize) -> BumpAddr {
+        if let Some((addr, old_size)) = self.address_map.get(&id) {
+            if size <= *old_size && (addr.0 + self.buffer_align) % align == 0 {
+                return *addr;
+            }
+        }
         let next_aligned_addr = ((self.next_address + self.buffer_align + align - 1)
             & !(align - 1))
             - self.buffer_align;
         assert!(next_aligned_addr + size <= self.buffer_len);
         self.next_address = next_aligned_addr + size;
         let addr = BumpAddr(next_aligned_addr);
-        self.address_map.insert(id, addr);
+        self.address_map.insert(id, (addr, size));
         addr
     }
 
     /// Gets a pointer to the start of the allocation for the given id.
     /// Requires that the allocation exists and that usage of the pointer is safe.
     fn get_starting_addr(&self, id: usize) -> BumpAddr {
-        *self.address_map.get(&id).unwrap()
+        self.address_map.get(&id).unwrap().0
     }
 
     /// Resets the allocator to the empty state, essentially erase all allocations.
@@ -122,72 +133,23 @@ impl CpuAllocator {
     }
 }
 
-/// Allocates data on the GPU.
-struct GpuAllocator {
-    buffer: Buffer,
-    allocator: BumpAllocator,
-}
-
-impl GpuAllocator {
-    pub fn new(state: &mut dyn State, usage: wgpu::BufferUsages) -> Self {
-        const BUFFER_SIZE: u64 = 4096;
-        Self {
-            buffer: state
-                .get_device_mut()
-                .create_buffer(&wgpu::BufferDescriptor {
-                    label: None,
-                    size: BUFFER_SIZE,
-                    usage,
-                    mapped_at_creation: false,
-                }),
-            allocator: BumpAllocator::new(BUFFER_SIZE as usize, 0),
-        }
-    }
-
-    pub fn alloc(&mut self, id: usize, size: usize, align: usize) {
-        self.allocator.alloc(id, size, align);
-    }
-
-    pub fn get_buffer_ref<T: Sized + Any>(&self, id: usize) -> GpuBufferRef<T> {
-        let addr = self.allocator.get_starting_addr(id);
-        GpuBufferRef::new(&self.buffer, addr.0 as wgpu::BufferAddress)
-    }
-
-    pub fn reset(&mut self) {
-        self.allocator.reset();
-    }
-}
-
-pub trait State {
-    fn get_device_mut(&mut self) -> &mut wgpu::Device;
-    fn get_queue_mut(&mut self) -> &mut wgpu::Queue;
-}
+/// What generated code needs from the program running it.
+/// Code from the cpu backend needs nothing more, while code from the wgpu backend
+/// needs a `GpuState`. Either kind of state is passed on to `CpuFunctions` as a `State`.
+pub trait State {}
 
-pub struct RootState<'device, 'queue> {
-    device: &'device mut wgpu::Device,
-    queue: &'queue mut wgpu::Queue,
-    local_storage: CpuAllocator,
-}
+/// The state for code generated by the cpu backend, which never uses a GPU.
+/// Lets the same `CpuFunctions` implementation serve code from either backend.
+#[derive(Default)]
+pub struct HostState;
 
-impl<'device, 'queue> RootState<'device, 'queue> {
-    pub fn new(device: &'device mut wgpu::Device, queue: &'queue mut wgpu::Queue) -> Self {
-        Self {
-            device,
-            queue,
-            local_storage: CpuAllocator::new(4096 * 4),
-        }
+impl HostState {
+    pub fn new() -> Self {
+        Self
     }
 }
 
-impl<'device, 'queue> State for RootState<'device, 'queue> {
-    fn get_device_mut(&mut self) -> &mut wgpu::Device {
-        self.device
-    }
-
-    fn get_queue_mut(&mut self) -> &mut wgpu::Queue {
-        self.queue
-    }
-}
+impl State for HostState {}
 
 /// Manages the allocation of local variables on the CPU.
 /// The variables are allocated in a contiguous buffer, respecting their alignment requirements.
@@ -197,21 +159,6 @@ pub struct LocalVars {
     type_ids: HashMap<usize, std::any::TypeId>,
 }
 
-const GPU_BUFFERS: usize = 5;
-
-/// Manages the allocation of local variables on the GPU.
-/// Kept separate from `LocalVars` to make it possible to have a GPU and CPU
-/// reference live at the same time (ie. mutable borrow from two different
-/// objects instead of one).
-pub struct GpuLocals {
-    // one buffer for each local variable
-    gpu_allocators: Vec<GpuAllocator>,
-    // maps variable ids to the index of the gpu allocator that holds them
-    alloc_map: HashMap<usize, usize>,
-    // maps variable ids to their type ids. Used only for runtime checks
-    type_ids: HashMap<usize, std::any::TypeId>,
-}
-
 impl LocalVars {
     pub fn new() -> Self {
         const LOCAL_BUF_SIZE: usize = 4096 * 4;
@@ -288,57 +235,6 @@ impl LocalVars {
     }
 }
 
-impl GpuLocals {
-    pub fn new(state: &mut dyn State) -> Self {
-        Self {
-            gpu_allocators: vec![],
-            alloc_map: HashMap::new(),
-            type_ids: HashMap::new(),
-        }
-    }
-
-    /// Clears all allocations and resets the allocator to the empty state.
-    pub fn reset(&mut self) {
-        self.alloc_map.clear();
-        self.type_ids.clear();
-        for gpu_alloc in self.gpu_allocators.iter_mut() {
-            gpu_alloc.reset();
-        }
-    }
-
-    /// Allocates a GPU local variable with the given id, size, and alignment.
-    pub fn alloc_gpu<T: Sized + Any>(
-        &mut self,
-        state: &mut dyn State,
-        id: usize,
-        usage: wgpu::BufferUsages,
-    ) {
-        let idx = self.gpu_allocators.len();
-        self.alloc_map.insert(id, idx);
-        self.type_ids.insert(id, std::any::TypeId::of::<T>());
-        // TODO: we can simplify this and avoid the need for a `GpuAllocator`
-        // since we can't actually allocate multiple things from a single
-        // buffer
-        self.gpu_allocators.push(GpuAllocator::new(state, usage));
-        self.gpu_allocators[idx].alloc(
-            id,
-            std::mem::size_of::<T>(),
-            std::mem::align_of::<T>()
-                .max(wgpu::Limits::default().min_storage_buffer_offset_alignment as usize),
-        );
-    }
-
-    /// Gets a GPU mutable pointer to the start of the allocation for the given id.
-    pub fn get_gpu_ref<T: Sized + Any>(&self, id: usize) -> GpuBufferRef<T> {
-        assert_eq!(
-            self.type_ids.get(&id).unwrap(),
-            &std::any::TypeId::of::<T>()
-        );
-        let idx = self.alloc_map.get(&id).unwrap();
-        self.gpu_allocators[*idx].get_buffer_ref(id)
-    }
-}
-
 pub struct TypeLayout {
     pub byte_size: usize,
     pub alignment: usize,
@@ -433,6 +329,35 @@ impl AbstractAllocator {
     fn suballocate_slice<T: Sized>(&mut self, count: usize) -> Option<(usize, usize)> {
         return self.suballocate_erased_slice(&Self::type_layout_of::<T>(), count);
     }
+
+    // A very horribly implemented check
+    fn test_suballocate_many(
+        &self,
+        layouts: &[TypeLayout],
+        element_counts: &[Option<usize>],
+    ) -> usize {
+        let mut abstract_allocator = self.clone();
+
+        let mut success_count = 0usize;
+
+        for (i, layout) in layouts.iter().enumerate() {
+            let can_allocate = if let Some(element_count) = element_counts[i] {
+                abstract_allocator
+                    .suballocate_erased_slice(layout, element_count)
+                    .is_some()
+            } else {
+                abstract_allocator.suballocate_erased_ref(layout).is_some()
+            };
+
+            if !can_allocate {
+                return success_count;
+            }
+
+            success_count += 1usize;
+        }
+
+        success_count
+    }
 }
 
 pub struct CpuBufferAllocator<'buffer> {
@@ -441,6 +366,16 @@ pub struct CpuBufferAllocator<'buffer> {
 }
 
 impl<'buffer> CpuBufferAllocator<'buffer> {
+    // Returns how many of the given allocations would succeed, in order
+    pub fn test_suballocate_many(
+        &self,
+        layouts: &[TypeLayout],
+        element_counts: &[Option<usize>],
+    ) -> usize {
+        self.abstract_allocator
+            .test_suballocate_many(layouts, element_counts)
+    }
+
     pub fn suballocate_ref<T: Sized>(&mut self) -> Option<&'buffer mut std::mem::MaybeUninit<T>> {
         if let Some(starting_address) = self.abstract_allocator.suballocate_ref::<T>() {
             return unsafe {
@@ -590,163 +525,109 @@ impl<'buffer> JoinStack<'buffer> {
     }
 }
 
-#[derive(Debug)]
-pub struct GpuBufferAllocator<'buffer> {
-    buffer: &'buffer wgpu::Buffer,
-    abstract_allocator: AbstractAllocator,
-}
-
-impl<'buffer> GpuBufferAllocator<'buffer> {
-    pub fn new(buffer: &'buffer wgpu::Buffer, size: usize) -> Self {
-        Self {
-            buffer,
-            abstract_allocator: AbstractAllocator::new(size),
-        }
-    }
-
-    pub fn suballocate_ref<T: Sized>(&mut self) -> Option<GpuBufferRef<T>> {
-        if let Some(starting_address) = self.abstract_allocator.suballocate_ref::<T>() {
-            return Some(GpuBufferRef::new(
-                self.buffer,
-                starting_address.try_into().unwrap(),
-            ));
-        }
-
-        return None;
-    }
-
-    pub fn suballocate_slice<T: Sized>(
-        &mut self,
-        count: usize,
-    ) -> Option<GpuBufferSlice<'buffer, T>> {
-        if let Some((starting_address, byte_size)) =
-            self.abstract_allocator.suballocate_slice::<T>(count)
-        {
-            return Some(GpuBufferSlice::new(
-                self.buffer,
-                starting_address.try_into().unwrap(),
-                Some(std::num::NonZeroU64::new(byte_size.try_into().unwrap()).unwrap()),
-            ));
-        }
-
-        return None;
-    }
-
-    // A very horribly implemented check
-    pub fn test_suballocate_many(
-        &self,
-        layouts: &[TypeLayout],
-        element_counts: &[Option<usize>],
-    ) -> usize {
-        let mut abstract_allocator = self.abstract_allocator.clone();
-
-        let mut success_count = 0usize;
-
-        for (i, layout) in layouts.iter().enumerate() {
-            let can_allocate = if let Some(element_count) = element_counts[i] {
-                abstract_allocator
-                    .suballocate_erased_slice(layout, element_count)
-                    .is_some()
-            } else {
-                abstract_allocator.suballocate_erased_ref(layout).is_some()
-            };
-
-            if !can_allocate {
-                return success_count;
-            }
-
-            success_count += 1usize;
-        }
-
-        success_count
+/// The larger of two join stack sizes, which generated pipelines use to pick the largest of
+/// the sets of joins they can have pending at once.
+pub const fn max_join_stack_bytes(a: usize, b: usize) -> usize {
+    if a > b {
+        a
+    } else {
+        b
     }
 }
 
+/// A join stack that owns its bytes and remembers what was pushed onto it between calls into a
+/// pipeline, so a pipeline can be resumed without the caller keeping a `JoinStack` alive.
 #[derive(Debug)]
-// A slot holding a pointer to gpu-resident data of type T
-pub struct GpuBufferRef<T: Sized> {
-    phantom: std::marker::PhantomData<*const T>,
-    pub buffer: *const wgpu::Buffer,
-    pub base_address: wgpu::BufferAddress,
-    //offset : wgpu::DynamicOffset,
+pub struct OwnedJoinStack {
+    bytes: Box<[u8]>,
+    used: usize,
 }
 
-impl<T: Sized> GpuBufferRef<T> {
-    pub fn new(buffer: &wgpu::Buffer, base_address: wgpu::BufferAddress) -> Self {
+impl OwnedJoinStack {
+    pub fn new(capacity: usize) -> Self {
         Self {
-            phantom: std::marker::PhantomData,
-            buffer,
-            base_address,
+            bytes: vec![0u8; capacity].into_boxed_slice(),
+            used: 0,
         }
     }
 
-    pub fn as_binding_resource(&self) -> wgpu::BindingResource<'static> {
-        let size_n: u64 = std::mem::size_of::<T>().try_into().unwrap();
-        wgpu::BindingResource::Buffer(wgpu::BufferBinding {
-            // caiman should enforce that this is safe
-            buffer: unsafe { &*self.buffer },
-            offset: self.base_address,
-            size: std::num::NonZeroU64::new(size_n),
-        })
+    pub fn capacity(&self) -> usize {
+        self.bytes.len()
     }
 
-    pub fn slice(&self) -> wgpu::BufferSlice<'static> {
-        // Technically, this could overflow?
-        let mut end_address = self.base_address;
-        // Rust needs the type hint...
-        let size_opt: Option<wgpu::BufferAddress> = std::mem::size_of::<T>().try_into().ok();
-        end_address += size_opt.unwrap();
-        // caiman should enforce that this is safe
-        unsafe { &*self.buffer }.slice(self.base_address..end_address)
+    /// The number of bytes of joins waiting to be popped
+    pub fn used(&self) -> usize {
+        self.used
     }
-}
 
-#[derive(Debug)]
-// A slot holding a pointer to gpu-resident array of elements of type T
-pub struct GpuBufferSlice<'buffer, T: Sized> {
-    phantom: std::marker::PhantomData<*const T>,
-    pub buffer: &'buffer wgpu::Buffer,
-    pub base_address: wgpu::BufferAddress,
-    //offset : wgpu::DynamicOffset,
-    pub size_opt: Option<wgpu::BufferSize>,
-}
-
-impl<'buffer, T: Sized> GpuBufferSlice<'buffer, T> {
-    pub fn new(
-        buffer: &'buffer wgpu::Buffer,
-        base_address: wgpu::BufferAddress,
-        size_opt: Option<wgpu::BufferSize>,
-    ) -> Self {
-        Self {
-            phantom: std::marker::PhantomData,
-            buffer,
-            base_address,
-            size_opt,
-        }
+    /// Drops every pending join, as when a pipeline is started over
+    pub fn clear(&mut self) {
+        self.used = 0;
     }
 
-    pub fn as_binding_resource(&self) -> wgpu::BindingResource<'buffer> {
-        wgpu::BindingResource::Buffer(wgpu::BufferBinding {
-            buffer: self.buffer,
-            offset: self.base_address,
-            size: self.size_opt,
-        })
+    /// Runs `f` on a `JoinStack` over these bytes that picks up where the last call left off.
+    ///
+    /// # Safety
+    /// The `JoinStack` handed to `f`, and anything it returns borrowing from it, must not
+    /// outlive the call to `f`, even though `'buffer` is chosen by the caller.
+    pub unsafe fn with_join_stack<'buffer, R>(
+        &mut self,
+        f: impl FnOnce(&mut JoinStack<'buffer>) -> R,
+    ) -> R {
+        // The bytes are boxed, so they stay put for as long as `self` does
+        let bytes = std::slice::from_raw_parts_mut::<'buffer, u8>(
+            self.bytes.as_mut_ptr(),
+            self.bytes.len(),
+        );
+        let mut join_stack = JoinStack {
+            bytes,
+            abstract_allocator: AbstractAllocator {
+                base_address: self.used,
+                size: self.bytes.len() - self.used,
+            },
+        };
+        let result = f(&mut join_stack);
+        self.used = join_stack.abstract_allocator.base_address;
+        result
     }
+}
 
-    pub fn slice(&self) -> wgpu::BufferSlice<'buffer> {
-        if let Some(size) = self.size_opt {
-            // Technically, this could overflow?
-            let mut end_address = self.base_address;
-            // Rust needs the type hint...
-            let size_opt: Option<wgpu::BufferAddress> = size.try_into().ok();
-            end_address += size_opt.unwrap();
-            self.buffer.slice(self.base_address..end_address)
-        } else {
-            self.buffer.slice(self.base_address..)
+/// Why a generated `Pipeline` couldn't be resumed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResumeError {
+    /// The pipeline was resumed at `resumed_at`, but it is waiting at `waiting_at`, or isn't
+    /// waiting anywhere if that is `None`
+    NotWaitingAt {
+        resumed_at: &'static str,
+        waiting_at: Option<&'static str>,
+    },
+}
+
+impl std::fmt::Display for ResumeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotWaitingAt {
+                resumed_at,
+                waiting_at: Some(waiting_at),
+            } => write!(
+                f,
+                "Cannot resume at {} while waiting at {}",
+                resumed_at, waiting_at
+            ),
+            Self::NotWaitingAt {
+                resumed_at,
+                waiting_at: None,
+            } => write!(
+                f,
+                "Cannot resume at {} without having yielded there",
+                resumed_at
+            ),
         }
     }
 }
 
+impl std::error::Error for ResumeError {}
+
 /*pub struct SerializedGpuBufferSlot
 {
     pub offset : usize,
@@ -845,9 +726,44 @@ pub trait JoinPoint<'buffer>
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
     #[test]
     fn it_works() {
         let result = 2 + 2;
         assert_eq!(result, 4);
     }
+
+    #[test]
+    fn reallocating_a_local_reuses_its_space() {
+        let mut allocator = BumpAllocator::new(16, DEFAULT_ALIGN);
+        let first = allocator.alloc(0, 8, 8);
+        allocator.alloc(1, 4, 4);
+        for _ in 0..100 {
+            assert_eq!(allocator.alloc(0, 8, 8).0, first.0);
+        }
+        assert_eq!(allocator.next_address, 12);
+    }
+
+    #[test]
+    fn owned_join_stack_keeps_joins_between_calls() {
+        let mut join_stack = OwnedJoinStack::new(16);
+        unsafe {
+            join_stack.with_join_stack(|join_stack| {
+                join_stack.push_unsafe_unaligned(7u32).unwrap();
+                join_stack.push_unsafe_unaligned(9u64).unwrap();
+            });
+        }
+        assert_eq!(join_stack.used(), 12);
+        let popped = unsafe {
+            join_stack.with_join_stack(|join_stack| {
+                assert!(join_stack.push_unsafe_unaligned(0u64).is_err());
+                let second = join_stack.pop_unsafe_unaligned::<u64>().unwrap();
+                let first = join_stack.pop_unsafe_unaligned::<u32>().unwrap();
+                (first, second)
+            })
+        };
+        assert_eq!(popped, (7, 9));
+        assert_eq!(join_stack.used(), 0);
+    }
 }
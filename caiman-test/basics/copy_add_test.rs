@@ -17,3 +17,13 @@ fn main() -> Result<(), String> {
     let result = instance.start(&mut join_stack, 5);
     crate::expect_returned!(13, result.returned().map(|x| x.0))
 }
+
+#[test]
+fn pipeline_wrapper() -> Result<(), String> {
+    let callbacks = Callbacks;
+    let mut wgpu_instance = crate::util::INSTANCE.lock().unwrap();
+    let mut root_state = wgpu_instance.create_root_state();
+    let mut pipeline = main::Pipeline::new(&mut root_state, &callbacks);
+    let result = pipeline.start(5);
+    crate::expect_returned!(13, Some(result.0))
+}
@@ -29,3 +29,26 @@ fn main() -> Result<(), String> {
     }
     crate::expect_returned!(210, result.returned().map(|x| x.0))
 }
+
+#[test]
+fn pipeline_wrapper() -> Result<(), String> {
+    let mut wgpu_instance = crate::util::INSTANCE.lock().unwrap();
+    let callbacks = Callbacks;
+    let mut root_state = wgpu_instance.create_root_state();
+    let mut pipeline = main::Pipeline::with_join_stack_bytes(&mut root_state, &callbacks, 4096);
+    let mut result = pipeline.start();
+    while let Err(main::Yielded::Loop(..)) = result {
+        result = pipeline.resume_at_loop().map_err(|e| e.to_string())?;
+    }
+    let returned = result.ok().map(|x| x.0);
+    // resuming once the pipeline has returned is an error, not a crash
+    let misuse = pipeline.resume_at_loop().err();
+    let expected = caiman_rt::ResumeError::NotWaitingAt {
+        resumed_at: "loop",
+        waiting_at: None,
+    };
+    if misuse != Some(expected) {
+        return Err(format!("Expected {:?}, got {:?}", expected, misuse));
+    }
+    crate::expect_returned!(210, returned)
+}
@@ -0,0 +1,17 @@
+struct Callbacks;
+
+impl main::CpuFunctions for Callbacks {
+    fn add(&self, _: &mut dyn caiman_rt::State, x: i64, y: i64) -> main::outputs::add {
+        (x + y,)
+    }
+}
+
+#[test]
+fn main() -> Result<(), String> {
+    let callbacks = Callbacks;
+    let mut wgpu_instance = crate::util::INSTANCE.lock().unwrap();
+    let mut root_state = wgpu_instance.create_root_state();
+    let mut pipeline = main::Pipeline::new(&mut root_state, &callbacks);
+    let result = pipeline.start();
+    crate::expect_returned!(13, Some(result.0))
+}
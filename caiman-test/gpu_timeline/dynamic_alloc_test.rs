@@ -0,0 +1,42 @@
+// The harness puts the first slot's value at the start of the buffer, and the last slot's
+// value at the next address a slot can start at after the slice
+fn run(buffer_size: usize, expected: (i32, i32)) -> Result<(), String> {
+    use caiman_rt::wgpu;
+    const SLICE_LENGTH: usize = 4;
+    // the runtime aligns slots more strictly than their types
+    const LAST_SLOT_ADDRESS: u64 = 256;
+    let mut wgpu_instance = crate::util::INSTANCE.lock().unwrap();
+    let buffer = wgpu_instance
+        .device()
+        .create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: 1024,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+    let queue = wgpu_instance.queue();
+    queue.write_buffer(&buffer, 0, &10i32.to_le_bytes());
+    queue.write_buffer(&buffer, LAST_SLOT_ADDRESS, &32i32.to_le_bytes());
+    queue.submit([]);
+    let callbacks = Callbacks;
+    let mut root_state = wgpu_instance.create_root_state();
+    let mut pipeline = main::Pipeline::new(&mut root_state, &callbacks);
+    let buffer_alloc = caiman_rt::GpuBufferAllocator::new(&buffer, buffer_size);
+    let result = pipeline.start(SLICE_LENGTH as i64, buffer_alloc);
+    crate::expect_returned!(expected, Some((result.0, result.1)));
+}
+
+struct Callbacks;
+
+impl main::CpuFunctions for Callbacks {}
+
+#[test]
+fn main() -> Result<(), String> {
+    run(1024, (10, 32))
+}
+
+#[test]
+fn failure() -> Result<(), String> {
+    // room for the first slot and the slice, but not the last slot
+    run(20, (-1, -1))
+}
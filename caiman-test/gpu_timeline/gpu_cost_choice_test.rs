@@ -0,0 +1,32 @@
+use std::cell::Cell;
+
+struct Callbacks {
+    cpu_calls: Cell<u32>,
+}
+
+impl main::CpuFunctions for Callbacks {
+    fn simple_cpu(
+        &self,
+        _: &mut dyn caiman_rt::State,
+        _: i32,
+        _: i32,
+        _: i32,
+        x: i32,
+    ) -> main::outputs::simple_cpu {
+        self.cpu_calls.set(self.cpu_calls.get() + 1);
+        (x + 1,)
+    }
+}
+
+#[test]
+fn main() -> Result<(), String> {
+    let mut wgpu_instance = crate::util::INSTANCE.lock().unwrap();
+    let callbacks = Callbacks {
+        cpu_calls: Cell::new(0),
+    };
+    let mut root_state = wgpu_instance.create_root_state();
+    let mut pipeline = main::Pipeline::new(&mut root_state, &callbacks);
+    let returned = pipeline.start(&mut 4).0;
+    assert_eq!(callbacks.cpu_calls.get(), 1);
+    crate::expect_returned!(5, Some(returned))
+}
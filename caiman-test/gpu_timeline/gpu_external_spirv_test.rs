@@ -0,0 +1,13 @@
+struct Callbacks;
+
+impl main::CpuFunctions for Callbacks {}
+
+#[test]
+fn main() -> Result<(), String> {
+    let mut wgpu_instance = crate::util::INSTANCE.lock().unwrap();
+    let callbacks = Callbacks;
+    let mut root_state = wgpu_instance.create_root_state();
+    let mut pipeline = main::Pipeline::new(&mut root_state, &callbacks);
+    let result = pipeline.start(&mut 0);
+    crate::expect_returned!(1, Some(result.0))
+}
@@ -0,0 +1,23 @@
+struct Callbacks;
+
+impl main::CpuFunctions for Callbacks {
+    fn simple_cpu(
+        &self,
+        _: &mut dyn caiman_rt::State,
+        _: i32,
+        _: i32,
+        _: i32,
+        x: i32,
+    ) -> main::outputs::simple_cpu {
+        (x + 1,)
+    }
+}
+
+#[test]
+fn main() -> Result<(), String> {
+    let callbacks = Callbacks;
+    let mut host_state = caiman_rt::HostState::new();
+    let mut pipeline = main::Pipeline::new(&mut host_state, &callbacks);
+    let result = pipeline.start(&mut 4);
+    crate::expect_returned!(5, Some(result.0))
+}
@@ -0,0 +1,24 @@
+struct Callbacks;
+
+impl main::CpuFunctions for Callbacks {
+    fn simple_cpu(
+        &self,
+        _: &mut dyn caiman_rt::State,
+        _: i32,
+        _: i32,
+        _: i32,
+        x: i32,
+    ) -> main::outputs::simple_cpu {
+        (x + 1,)
+    }
+}
+
+#[test]
+fn main() -> Result<(), String> {
+    let mut wgpu_instance = crate::util::INSTANCE.lock().unwrap();
+    let callbacks = Callbacks;
+    let mut root_state = wgpu_instance.create_root_state();
+    let mut pipeline = main::Pipeline::new(&mut root_state, &callbacks);
+    let result = pipeline.start(&mut 4);
+    crate::expect_returned!(5, Some(result.0))
+}
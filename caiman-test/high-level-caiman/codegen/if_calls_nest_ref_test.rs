@@ -1,45 +1,6 @@
 struct Callbacks;
 
-impl main::CpuFunctions for Callbacks {
-    fn _add_i64_i64(&self, _: &mut dyn caiman_rt::State, a: i64, b: i64) -> (i64,) {
-        (a + b,)
-    }
-
-    fn _mul_i64_i64(&self, _: &mut dyn caiman_rt::State, a: i64, b: i64) -> (i64,) {
-        (a * b,)
-    }
-
-    fn _lor_bool_bool(&self, _: &mut dyn caiman_rt::State, a: i32, b: i32) -> (i32,) {
-        if a != 0 || b != 0 {
-            (1,)
-        } else {
-            (0,)
-        }
-    }
-    fn _lt_i64_i64(&self, _: &mut dyn caiman_rt::State, a: i64, b: i64) -> (i32,) {
-        if a < b {
-            (1,)
-        } else {
-            (0,)
-        }
-    }
-
-    fn _geq_i64_i64(&self, _: &mut dyn caiman_rt::State, a: i64, b: i64) -> (i32,) {
-        if a >= b {
-            (1,)
-        } else {
-            (0,)
-        }
-    }
-
-    fn _eq_i64_i64(&self, _: &mut dyn caiman_rt::State, a: i64, b: i64) -> (i32,) {
-        if a == b {
-            (1,)
-        } else {
-            (0,)
-        }
-    }
-}
+impl main::CpuFunctions for Callbacks {}
 
 #[test]
 fn main() -> Result<(), String> {
@@ -1,22 +1,6 @@
 struct Callbacks;
 
-impl main::CpuFunctions for Callbacks {
-    fn _mul_i64_i64(&self, _: &mut dyn caiman_rt::State, a: i64, b: i64) -> (i64,) {
-        (a * b,)
-    }
-
-    fn _add_i64_i64(&self, _: &mut dyn caiman_rt::State, a: i64, b: i64) -> (i64,) {
-        (a + b,)
-    }
-
-    fn _lt_i64_i64(&self, _: &mut dyn caiman_rt::State, a: i64, b: i64) -> (i32,) {
-        if a < b {
-            (1,)
-        } else {
-            (0,)
-        }
-    }
-}
+impl main::CpuFunctions for Callbacks {}
 
 #[test]
 fn main() -> Result<(), String> {
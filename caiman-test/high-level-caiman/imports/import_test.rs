@@ -0,0 +1,13 @@
+struct Callbacks;
+
+impl main::CpuFunctions for Callbacks {}
+
+#[test]
+fn main() -> Result<(), String> {
+    let callbacks = Callbacks;
+    let mut wgpu_instance = crate::util::INSTANCE.lock().unwrap();
+    let mut root_state = wgpu_instance.create_root_state();
+    let mut pipeline = main::Pipeline::new(&mut root_state, &callbacks);
+    let returned = pipeline.start(20).ok().map(|x| x.0);
+    crate::expect_returned!(41, returned)
+}
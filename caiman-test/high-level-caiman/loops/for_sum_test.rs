@@ -0,0 +1,26 @@
+struct Callbacks;
+
+impl main::CpuFunctions for Callbacks {}
+
+#[test]
+fn main() -> Result<(), String> {
+    let callbacks = Callbacks;
+    let mut wgpu_instance = crate::util::INSTANCE.lock().unwrap();
+    let mut root_state = wgpu_instance.create_root_state();
+    let mut pipeline = main::Pipeline::new(&mut root_state, &callbacks);
+    let n = 1000;
+    let returned = pipeline.start(n).ok().map(|x| x.0);
+    crate::expect_returned!((0..n).sum::<i64>(), returned)
+}
+
+#[test]
+fn long_loop() -> Result<(), String> {
+    // enough iterations to overflow the stack if each one were a call
+    let callbacks = Callbacks;
+    let mut wgpu_instance = crate::util::INSTANCE.lock().unwrap();
+    let mut root_state = wgpu_instance.create_root_state();
+    let mut pipeline = main::Pipeline::new(&mut root_state, &callbacks);
+    let n = 1_000_000;
+    let returned = pipeline.start(n).ok().map(|x| x.0);
+    crate::expect_returned!((0..n).sum::<i64>(), returned)
+}
@@ -0,0 +1,14 @@
+struct Callbacks;
+
+impl main::CpuFunctions for Callbacks {}
+
+#[test]
+fn main() -> Result<(), String> {
+    let callbacks = Callbacks;
+    let mut wgpu_instance = crate::util::INSTANCE.lock().unwrap();
+    let mut root_state = wgpu_instance.create_root_state();
+    let mut pipeline = main::Pipeline::new(&mut root_state, &callbacks);
+    let n = 10;
+    let returned = pipeline.start(n).ok().map(|x| x.0);
+    crate::expect_returned!(90 * 1000 + 100, returned)
+}
@@ -0,0 +1,18 @@
+struct Callbacks;
+
+impl main::CpuFunctions for Callbacks {}
+
+#[test]
+fn main() -> Result<(), String> {
+    let callbacks = Callbacks;
+    let mut wgpu_instance = crate::util::INSTANCE.lock().unwrap();
+    let mut root_state = wgpu_instance.create_root_state();
+    let mut pipeline = main::Pipeline::new(&mut root_state, &callbacks);
+    let returned = pipeline.start(7, 9).ok().map(|x| x.0);
+    crate::expect_returned!(
+        (0..7)
+            .map(|i| (0..9).map(|j| i * j).sum::<i64>())
+            .sum::<i64>(),
+        returned
+    )
+}
@@ -1,9 +1,6 @@
 struct Callbacks;
 
 impl main::CpuFunctions for Callbacks {
-    fn _add_i64_i64(&self, _: &mut dyn caiman_rt::State, a: i64, b: i64) -> (i64,) {
-        (a + b,)
-    }
     fn sort5(
         &self,
         _: &mut dyn caiman_rt::State,
@@ -17,9 +14,6 @@ impl main::CpuFunctions for Callbacks {
         arr.sort();
         (arr[0], arr[1], arr[2], arr[3], arr[4])
     }
-    fn _mul_i64_i64(&self, _: &mut dyn caiman_rt::State, a: i64, b: i64) -> (i64,) {
-        (a * b,)
-    }
 }
 
 #[test]
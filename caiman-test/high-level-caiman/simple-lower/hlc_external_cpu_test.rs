@@ -1,9 +1,6 @@
 struct Callbacks;
 
 impl main::CpuFunctions for Callbacks {
-    fn _add_i64_i64(&self, _: &mut dyn caiman_rt::State, a: i64, b: i64) -> (i64,) {
-        (a + b,)
-    }
     fn foof(&self, _: &mut dyn caiman_rt::State, a: i64) -> (i64,) {
         (a + 10,)
     }
@@ -1,27 +1,6 @@
 struct Callbacks;
 
-impl main::CpuFunctions for Callbacks {
-    fn _eq_i64_i64(&self, _: &mut dyn caiman_rt::State, a: i64, b: i64) -> (i32,) {
-        if a == b {
-            (1,)
-        } else {
-            (0,)
-        }
-    }
-    fn _eq_bool_bool(&self, _: &mut dyn caiman_rt::State, a: i32, b: i32) -> (i32,) {
-        if a == b {
-            (1,)
-        } else {
-            (0,)
-        }
-    }
-    fn _and_i64_i64(&self, _: &mut dyn caiman_rt::State, a: i64, b: i64) -> (i64,) {
-        (a & b,)
-    }
-    fn _shl_i64_i64(&self, _: &mut dyn caiman_rt::State, a: i64, b: i64) -> (i64,) {
-        (a << b,)
-    }
-}
+impl main::CpuFunctions for Callbacks {}
 
 #[test]
 fn main() -> Result<(), String> {
@@ -2,34 +2,6 @@ struct Callbacks;
 
 use main::outputs;
 impl main::CpuFunctions for Callbacks {
-    fn _add_i32_i32(&self, _: &mut dyn caiman_rt::State, a: i32, b: i32) -> outputs::_add_i32_i32 {
-        (a + b,)
-    }
-
-    fn _lt_i32_i32(&self, _: &mut dyn caiman_rt::State, a: i32, b: i32) -> outputs::_lt_i32_i32 {
-        if a < b {
-            (1,)
-        } else {
-            (0,)
-        }
-    }
-
-    fn _gt_i32_i32(&self, _: &mut dyn caiman_rt::State, a: i32, b: i32) -> outputs::_lt_i32_i32 {
-        if a > b {
-            (1,)
-        } else {
-            (0,)
-        }
-    }
-
-    fn _eq_i32_i32(&self, _: &mut dyn caiman_rt::State, a: i32, b: i32) -> outputs::_lt_i32_i32 {
-        if a == b {
-            (1,)
-        } else {
-            (0,)
-        }
-    }
-
     fn rec_sum_cpu(
         &self,
         _: &mut dyn caiman_rt::State,
@@ -0,0 +1,25 @@
+struct Callbacks;
+
+impl main::CpuFunctions for Callbacks {}
+
+#[test]
+fn main() -> Result<(), String> {
+    use caiman_rt::wgpu;
+    let mut wgpu_instance = crate::util::INSTANCE.lock().unwrap();
+    let buffer = wgpu_instance
+        .device()
+        .create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: 1024,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::MAP_READ
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+    let callbacks = Callbacks;
+    let mut root_state = wgpu_instance.create_root_state();
+    let mut pipeline = main::Pipeline::new(&mut root_state, &callbacks);
+    let buffer_alloc = caiman_rt::GpuBufferAllocator::new(&buffer, 1024);
+    let returned = pipeline.start(&mut 0, buffer_alloc).ok().map(|x| x.0);
+    crate::expect_returned!(1, returned);
+}
@@ -0,0 +1,21 @@
+struct Callbacks;
+
+impl main::CpuFunctions for Callbacks {
+    fn simple(
+        &self,
+        _: &mut dyn caiman_rt::State,
+        _: [u32; 3],
+        input_0: i32,
+    ) -> main::outputs::simple {
+        (input_0 + 1,)
+    }
+}
+
+#[test]
+fn main() -> Result<(), String> {
+    let callbacks = Callbacks;
+    let mut host_state = caiman_rt::HostState::new();
+    let mut pipeline = main::Pipeline::new(&mut host_state, &callbacks);
+    let returned = pipeline.start(&mut 0).ok().map(|x| x.0);
+    crate::expect_returned!(5, returned)
+}
@@ -1,10 +1,6 @@
 struct Callbacks;
 
 impl main::CpuFunctions for Callbacks {
-    fn _add_i32_i32(&self, _: &mut dyn caiman_rt::State, a: i32, b: i32) -> (i32,) {
-        (a + b,)
-    }
-
     fn simple_cpu(
         &self,
         _: &mut dyn caiman_rt::State,
@@ -0,0 +1,14 @@
+struct Callbacks;
+
+impl main::CpuFunctions for Callbacks {}
+
+#[test]
+fn main() -> Result<(), String> {
+    let mut wgpu_instance = crate::util::INSTANCE.lock().unwrap();
+    let callbacks = Callbacks;
+    let mut root_state = wgpu_instance.create_root_state();
+    let mut pipeline = main::Pipeline::new(&mut root_state, &callbacks);
+    let mut a = [1, 2, 3, 4];
+    let returned = pipeline.start(&mut a).ok().map(|x| x.0);
+    crate::expect_returned!(10, returned)
+}
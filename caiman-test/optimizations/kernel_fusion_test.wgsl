@@ -0,0 +1,56 @@
+struct fused_00__Input_0_ {
+    field_0_: i32,
+}
+
+struct fused_00__Output_0_ {
+    field_0_: i32,
+}
+
+struct fused_01__Input_0_ {
+    field_0_: i32,
+}
+
+struct fused_01__Output_0_ {
+    field_0_: i32,
+}
+
+@group(0) @binding(0) 
+var<storage> fused_00__input_0_: fused_00__Input_0_;
+@group(0) @binding(1) 
+var<storage, read_write> fused_00__output_0_: fused_00__Output_0_;
+@group(0) @binding(2) 
+var<storage, read_write> fused_01__output_0_: fused_01__Output_0_;
+
+fn fused_00__main() {
+    _ = (&fused_00__input_0_.field_0_);
+    _ = (&fused_00__output_0_.field_0_);
+    let _e7: fused_00__Input_0_ = fused_00__input_0_;
+    fused_00__output_0_.field_0_ = (_e7.field_0_ + 1);
+    return;
+}
+
+fn function() {
+    _ = (&fused_00__input_0_.field_0_);
+    fused_00__main();
+    return;
+}
+
+fn fused_01__main() {
+    _ = (&fused_00__output_0_.field_0_);
+    _ = (&fused_01__output_0_.field_0_);
+    let _e7: fused_00__Output_0_ = fused_00__output_0_;
+    fused_01__output_0_.field_0_ = (_e7.field_0_ * 2);
+    return;
+}
+
+fn function_1() {
+    _ = (&fused_00__output_0_.field_0_);
+    fused_01__main();
+    return;
+}
+
+@compute @workgroup_size(1, 1, 1) 
+fn main() {
+    function();
+    function_1();
+}
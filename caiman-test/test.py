@@ -80,11 +80,26 @@ class Compiler:
         return self.test_dir / ".." / "target" / "debug" / "caimanc"
 
     def compile(
-        self, input: Path, output: Path, explicate_only: bool = False
+        self,
+        input: Path,
+        output: Path,
+        explicate_only: bool = False,
+        backend: str = "wgpu",
+        opts: list[str] = [],
     ) -> subprocess.CompletedProcess:
-        args = [self._compiler_path(), "--input", input, "--output", output] + [
-            "--explicate_only"
-        ] * explicate_only
+        args = (
+            [
+                self._compiler_path(),
+                "--input",
+                input,
+                "--output",
+                output,
+                "--backend",
+                backend,
+            ]
+            + ["--explicate_only"] * explicate_only
+            + [arg for opt in opts for arg in ("--opt", opt)]
+        )
         return subprocess.run(
             args, capture_output=True, encoding="utf8", cwd=input.parent
         )
@@ -101,8 +116,10 @@ class HighLevelCaiman:
     def _compiler_path(self) -> Path:
         return self.test_dir / ".." / "target" / "debug" / "hlc"
 
-    def compile(self, input: Path, output: Path) -> subprocess.CompletedProcess:
-        args = [self._compiler_path(), "-o", output, input]
+    def compile(
+        self, input: Path, output: Path, backend: str = "wgpu"
+    ) -> subprocess.CompletedProcess:
+        args = [self._compiler_path(), "--backend", backend, "-o", output, input]
         return subprocess.run(
             args, capture_output=True, encoding="utf8", cwd=input.parent
         )
@@ -197,34 +214,48 @@ def process_inputs(
                 continue
 
         input_compiler = hlc if input_str.endswith(".cm") else compiler
-        rv = input_compiler.compile(input.absolute(), output)
-
-        if rv.returncode == 0:
-            eprint(Colorizer.grey(f"    pass: {relativized}"))
-            if not quiet and rv.stderr:
-                msg = pad_lines(rv.stderr, f"        {Colorizer.grey('|')} ")
-                print(msg, file=stderr)
-        else:
-            eprint(f"    {Colorizer.red('fail:')} {relativized}")
-            if not quiet:
-                msg = pad_lines(rv.stderr, f"        {Colorizer.red('|')} ")
-                print(msg, file=stderr)
-            ps.failures += 1
-            continue
-
-        lf.write(f"mod {input.stem};\n")
-        ps.compiled += 1
+        # a `<name>.cpu.rs` harness also tests the program compiled by the cpu backend,
+        # and a `<name>.fused.rs` harness the program compiled with `--opt fusion`
+        builds = [("wgpu", input.stem, ".rs", [])]
+        if input.with_suffix(".cpu.rs").exists():
+            builds.append(("cpu", input.stem + "_cpu", ".cpu.rs", []))
+        if input_compiler is compiler and input.with_suffix(".fused.rs").exists():
+            builds.append(("wgpu", input.stem + "_fused", ".fused.rs", ["fusion"]))
+        for backend, stem, harness_suffix, opts in builds:
+            output = test_dir / "src" / (stem + ".rs")
+            if opts:
+                rv = compiler.compile(
+                    input.absolute(), output, backend=backend, opts=opts
+                )
+            else:
+                rv = input_compiler.compile(input.absolute(), output, backend=backend)
+
+            if rv.returncode == 0:
+                eprint(Colorizer.grey(f"    pass: {relativized} ({backend})"))
+                if not quiet and rv.stderr:
+                    msg = pad_lines(rv.stderr, f"        {Colorizer.grey('|')} ")
+                    print(msg, file=stderr)
+            else:
+                eprint(f"    {Colorizer.red('fail:')} {relativized} ({backend})")
+                if not quiet:
+                    msg = pad_lines(rv.stderr, f"        {Colorizer.red('|')} ")
+                    print(msg, file=stderr)
+                ps.failures += 1
+                continue
 
-        test_file = input.with_suffix(".rs")
-        if not test_file.exists():
-            if not quiet:
-                eprint(Colorizer.grey("        | no Rust test file provided"))
-        else:
-            input_rs = Path("..") / relativized.with_suffix(".rs")
-            of = output.open(mode="a", encoding="utf8")
-            of.write(f'\ninclude!(r##"{input_rs}"##);\n')
-            of.close()
-            ps.linked += 1
+            lf.write(f"mod {stem};\n")
+            ps.compiled += 1
+
+            test_file = input.with_suffix(harness_suffix)
+            if not test_file.exists():
+                if not quiet:
+                    eprint(Colorizer.grey("        | no Rust test file provided"))
+            else:
+                input_rs = Path("..") / relativized.with_suffix(harness_suffix)
+                of = output.open(mode="a", encoding="utf8")
+                of.write(f'\ninclude!(r##"{input_rs}"##);\n')
+                of.close()
+                ps.linked += 1
 
     lf.close()
     return ps
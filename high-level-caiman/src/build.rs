@@ -0,0 +1,83 @@
+//! Compiling high-level Caiman programs from a Cargo build script, the
+//! counterpart of `caiman::build` for `.cm` files.
+
+use crate::error::{Error, ErrorKind, ErrorLocation, LocalError};
+use crate::{lower, normalize, parse, typing};
+use caiman::frontend::CompileOptions;
+use std::path::{Path, PathBuf};
+
+/// Lowers the program at `path` and everything it imports, recording the
+/// files it was read from in `dependencies`.
+fn lower_file(
+    path: &Path,
+    no_inference: bool,
+    dependencies: &mut Vec<PathBuf>,
+) -> Result<caiman::assembly::ast::Program, Error> {
+    let filename = path.to_string_lossy().to_string();
+    let located = |error: LocalError| Error {
+        error,
+        filename: filename.clone(),
+    };
+    let text = std::fs::read_to_string(path).map_err(|e| {
+        located(LocalError {
+            kind: ErrorKind::IO(e.to_string()),
+            location: ErrorLocation::Single(0, 0),
+        })
+    })?;
+    let (ast, files) = parse::parse_source(&text, &filename)?;
+    for file in files {
+        let file = PathBuf::from(file);
+        if !dependencies.contains(&file) {
+            dependencies.push(file);
+        }
+    }
+    let ast = normalize::normalize_ast(ast).map_err(located)?;
+    let ctx = typing::Context::new(&ast).map_err(located)?;
+    let final_ast = normalize::post_typecheck_norm(ast);
+    lower::lower(final_ast, &ctx, no_inference).map_err(located)
+}
+
+/// Compiles the program at `path` to `$OUT_DIR/<file stem>.rs` from a build
+/// script, returning where it was written.
+///
+/// High-level programs are lowered here, while `.cair` and `.ron` programs are
+/// passed on to `caiman::build::compile_to_out_dir`. Cargo is told to rerun
+/// the build script when the program, anything it imports, or any shader it
+/// uses changes, and errors are printed as `cargo:warning`s.
+///
+/// # Errors
+/// Returns an error if the program can't be read, or is rejected by any
+/// stage of compilation.
+pub fn compile_to_out_dir<P: AsRef<Path>>(
+    path: P,
+    options: CompileOptions,
+    no_inference: bool,
+) -> Result<PathBuf, Error> {
+    let path = path.as_ref();
+    let filename = path.to_string_lossy().to_string();
+    if path.extension().and_then(std::ffi::OsStr::to_str) != Some("cm") {
+        return caiman::build::compile_to_out_dir(path, options)
+            .map_err(|e| Error::caiman(e, filename));
+    }
+    let mut dependencies = vec![path.to_path_buf()];
+    let lowered = lower_file(path, no_inference, &mut dependencies);
+    caiman::build::rerun_if_changed(&dependencies);
+    let lowered = lowered.inspect_err(|e| caiman::build::report(&e.to_diagnostic()))?;
+    caiman::build::compile_program_to_out_dir(lowered, path, options)
+        .map_err(|e| Error::caiman(e, filename))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn imports_are_dependencies() {
+        let directory =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("../caiman-test/high-level-caiman/imports");
+        let path = directory.join("import_test.cm");
+        let mut dependencies = vec![path.clone()];
+        lower_file(&path, false, &mut dependencies).unwrap();
+        assert!(dependencies.contains(&directory.join("common.cm")));
+    }
+}
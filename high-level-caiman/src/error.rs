@@ -1,4 +1,5 @@
 #![allow(clippy::module_name_repetitions)]
+use caiman::diagnostics::{codes, Diagnostic, Position, Range, SourceSpan};
 use std::fmt;
 
 /// Struct containing information about a token's starting and ending
@@ -60,6 +61,9 @@ pub enum ErrorKind {
     SyntaxParsing(String),
     IO(String),
     TypeError(String),
+    /// An error reported by caiman while explicating, type checking, or
+    /// generating code for the lowered program
+    Caiman(Box<Diagnostic>),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -90,6 +94,9 @@ pub struct Error {
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let ErrorKind::Caiman(diagnostic) = &self.error.kind {
+            return write!(f, "{diagnostic}");
+        }
         match self.error.location {
             ErrorLocation::Single(l, c) => {
                 write!(f, "At \"{}\" {}:{}, \n  ", self.filename, l, c)?;
@@ -108,8 +115,52 @@ impl fmt::Display for Error {
             ErrorKind::SyntaxParsing(e) => write!(f, "Parsing Error: {e}"),
             ErrorKind::IO(e) => write!(f, "IO Error: {e}"),
             ErrorKind::TypeError(e) => write!(f, "Type Error: {e}"),
+            ErrorKind::Caiman(_) => unreachable!(),
+        }
+    }
+}
+
+impl From<&ErrorLocation> for Range {
+    fn from(location: &ErrorLocation) -> Self {
+        match location {
+            ErrorLocation::Single(l, c) => Self {
+                start: Position::new(*l, *c),
+                end: Position::new(*l, *c),
+            },
+            ErrorLocation::Double(info) => Self {
+                start: Position::new(info.start_ln_and_col.0, info.start_ln_and_col.1),
+                end: Position::new(info.end_ln_and_col.0, info.end_ln_and_col.1),
+            },
+        }
+    }
+}
+
+impl Error {
+    /// Wraps an error caiman reported for the program lowered from `filename`
+    #[must_use]
+    pub fn caiman(error: caiman::frontend::CompileError, filename: String) -> Self {
+        Self {
+            error: LocalError {
+                kind: ErrorKind::Caiman(Box::new(error.diagnostic)),
+                location: ErrorLocation::Single(0, 0),
+            },
+            filename,
         }
     }
+
+    /// Converts this error into a diagnostic that can be reported in either
+    /// the human readable or the JSON message format
+    #[must_use]
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        let (code, message) = match &self.error.kind {
+            ErrorKind::Caiman(diagnostic) => return (**diagnostic).clone(),
+            ErrorKind::SyntaxParsing(e) => (codes::SYNTAX, format!("Parsing Error: {e}")),
+            ErrorKind::IO(e) => (codes::IO, format!("IO Error: {e}")),
+            ErrorKind::TypeError(e) => (codes::FRONTEND_TYPE, format!("Type Error: {e}")),
+        };
+        let span = SourceSpan::new(&self.filename, (&self.error.location).into());
+        Diagnostic::error(code, message).with_primary(Some(span))
+    }
 }
 
 impl std::process::Termination for Error {
@@ -1,6 +1,7 @@
 #![warn(clippy::pedantic, clippy::nursery)]
 #![warn(clippy::all, clippy::correctness)]
 
+pub mod build;
 pub mod lower;
 pub mod parse;
 
@@ -2,14 +2,14 @@
 //! Invokes the AST -> HIR transformation and all related passes
 //! for this, then applies syntax-directed lowering of HIR to Caiman Assembly.
 
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
 use caiman::assembly::ast::{self as asm, MetaMapping};
 use caiman::explication::Hole;
 
 use crate::{
     enum_cast,
-    error::{type_error, LocalError},
+    error::{type_error, Info, LocalError},
     lower::IN_STEM,
     parse::ast::{self, DataType, Flow, SchedTerm, SchedulingFunc, SpecType, Tag},
     typing::{Context, LOCAL_TEMP_FLAGS},
@@ -17,8 +17,10 @@ use crate::{
 use caiman::ir;
 
 use super::{
+    known_range,
     sched_hir::{
-        DataMovement, Funclet, Funclets, HirBody, HirFuncCall, Specs, Terminator, TripleTag,
+        device_var_buffer, DataMovement, Funclet, Funclets, Hir, HirBody, HirFuncCall, Specs,
+        Terminator, TripleTag,
     },
     tuple_id,
 };
@@ -258,13 +260,18 @@ fn lower_load(dest: &str, typ: &DataType, src: &str, temp_id: usize) -> (Command
 /// # Arguments
 /// * `device` - the device to encode on
 /// * `device_vars` - the names of the variables to encode
+/// * `buffer` - the buffer to suballocate device variables from by default
+/// * `suballocs` - device variables suballocated from some other buffer
 /// * `encoder` - the name of the encoder to use
 /// * `tags` - the tags for the operation
 /// * `temp_id` - the next available temporary id
 /// * `f` - the funclet that contains the operation
+#[allow(clippy::too_many_arguments)]
 fn lower_begin_encode(
     device: &str,
     device_vars: &[(String, TripleTag)],
+    buffer: Option<&String>,
+    suballocs: &BTreeMap<String, String>,
     encoder: &str,
     tags: &TripleTag,
     temp_id: usize,
@@ -280,13 +287,21 @@ fn lower_begin_encode(
     // TODO: check if device variables should have reference semantics (as implemented here)
     let mut cmds = vec![];
     for (var, _) in device_vars {
-        cmds.push(Hole::Filled(asm::Command::Node(asm::NamedNode {
-            name: Some(asm::NodeId(var.clone())),
-            node: asm::Node::AllocTemporary {
+        let node = device_var_buffer(var, buffer, suballocs).map_or_else(
+            || asm::Node::AllocTemporary {
                 place: Hole::Filled(place),
                 buffer_flags: Hole::Filled(f.get_flags()[var]),
                 storage_type: Hole::Filled(f.get_storage_type(var).unwrap()),
             },
+            |buffer| asm::Node::StaticSubAlloc {
+                node: Hole::Filled(asm::NodeId(buffer.clone())),
+                place: Hole::Filled(place),
+                storage_type: Hole::Filled(f.get_storage_type(var).unwrap()),
+            },
+        );
+        cmds.push(Hole::Filled(asm::Command::Node(asm::NamedNode {
+            name: Some(asm::NodeId(var.clone())),
+            node,
         })));
     }
     cmds.push(Hole::Filled(asm::Command::Node(asm::NamedNode {
@@ -507,6 +522,8 @@ fn lower_instr(s: &HirBody, temp_id: usize, f: &Funclet) -> (CommandVec, usize)
         HirBody::BeginEncoding {
             device,
             device_vars,
+            buffer,
+            suballocs,
             tags,
             encoder,
             active_fences,
@@ -514,6 +531,8 @@ fn lower_instr(s: &HirBody, temp_id: usize, f: &Funclet) -> (CommandVec, usize)
         } => lower_begin_encode(
             device,
             device_vars,
+            buffer.as_ref(),
+            suballocs,
             &encoder.0,
             tags,
             temp_id,
@@ -822,7 +841,13 @@ pub fn tag_to_tag(t: &Tag) -> asm::Tag {
 
 /// Lowers a basic block into a caiman assembly funclet
 ///
-fn lower_block(funclet: &Funclet<'_>) -> asm::Funclet {
+/// Lowers a basic block into a caiman assembly funclet, along with where the
+/// funclet and each of its named nodes came from in the source.
+fn lower_block(funclet: &Funclet<'_>) -> (asm::Funclet, asm::FuncletSpans) {
+    let mut spans = asm::FuncletSpans {
+        funclet: known_range(funclet.info()),
+        nodes: HashMap::new(),
+    };
     let mut commands = vec![];
     let inputs = funclet.inputs();
     for idx in 0..inputs.len() {
@@ -837,9 +862,12 @@ fn lower_block(funclet: &Funclet<'_>) -> asm::Funclet {
     for cmd in funclet.stmts() {
         let (mut new_cmds, new_id) = lower_instr(cmd, temp_id, funclet);
         temp_id = new_id;
+        record_node_spans(&mut spans, &new_cmds, cmd.get_info());
         commands.append(&mut new_cmds);
     }
-    commands.extend(lower_terminator(funclet.terminator(), temp_id, funclet));
+    let terminator = lower_terminator(funclet.terminator(), temp_id, funclet);
+    record_node_spans(&mut spans, &terminator, funclet.terminator().get_info());
+    commands.extend(terminator);
     let get_tag = |name: &str| {
         let t = if name == "input" {
             funclet.get_input_tag(name)
@@ -863,7 +891,7 @@ fn lower_block(funclet: &Funclet<'_>) -> asm::Funclet {
             },
         )
     };
-    asm::Funclet {
+    let funclet = asm::Funclet {
         kind: ir::FuncletKind::ScheduleExplicit,
         header: asm::FuncletHeader {
             name: asm::FuncletId(funclet.name()),
@@ -885,17 +913,41 @@ fn lower_block(funclet: &Funclet<'_>) -> asm::Funclet {
             }),
         },
         commands,
+    };
+    (funclet, spans)
+}
+
+/// Records that the named nodes among `commands` were lowered from the statement at `info`.
+fn record_node_spans(spans: &mut asm::FuncletSpans, commands: &[Hole<asm::Command>], info: Info) {
+    for command in commands {
+        if let Hole::Filled(asm::Command::Node(asm::NamedNode {
+            name: Some(name), ..
+        })) = command
+        {
+            if let Some(range) = known_range(info) {
+                spans.nodes.insert(name.clone(), range);
+            }
+        }
     }
 }
 
-/// Lower a scheduling function into one or more caiman assembly funclet.
+/// Lower a scheduling function into one or more caiman assembly funclet, each
+/// with the source spans of its nodes, along with the tags deduced for the
+/// function's variables.
 /// # Errors
 /// Returns an error if the function is missing a spec.
+#[allow(clippy::type_complexity)]
 pub fn lower_schedule(
     ctx: &Context,
     func: SchedulingFunc,
     no_inference: bool,
-) -> Result<Vec<asm::Funclet>, LocalError> {
+) -> Result<
+    (
+        Vec<(asm::Funclet, asm::FuncletSpans)>,
+        HashMap<String, ast::Tags>,
+    ),
+    LocalError,
+> {
     let mut val = None;
     let mut timeline = None;
     let mut spatial = None;
@@ -922,5 +974,10 @@ pub fn lower_schedule(
             .ok_or_else(|| type_error(func.info, "Missing spatial spec"))?,
     };
     let blocks = Funclets::new(func, &specs, ctx, no_inference)?;
-    Ok(blocks.funclets().iter().map(lower_block).collect())
+    let tags = blocks
+        .deduced_tags()
+        .into_iter()
+        .map(|(var, tag)| (var, tag.into()))
+        .collect();
+    Ok((blocks.funclets().iter().map(lower_block).collect(), tags))
 }
@@ -15,7 +15,7 @@ use caiman::{
     ir,
 };
 
-use super::{binop_to_str, tuple_id};
+use super::{array_to_str, binop_to_str, tuple_id};
 
 /// Lower a spec term into a caiman assembly node.
 fn lower_spec_term(t: SpecTerm, dtype: &DataType) -> asm::Node {
@@ -29,7 +29,7 @@ fn lower_spec_term(t: SpecTerm, dtype: &DataType) -> asm::Node {
                 }),
                 type_id: Hole::Filled(asm::TypeId(String::from("bool"))),
             },
-            SpecLiteral::Float(v) | SpecLiteral::Int(v) => asm::Node::Constant {
+            SpecLiteral::Float(v, _) | SpecLiteral::Int(v, _) => asm::Node::Constant {
                 value: Hole::Filled(v),
                 type_id: Hole::Filled(dtype.asm_type()),
             },
@@ -231,6 +231,37 @@ fn lower_binop(
     ]
 }
 
+/// Lowers an array literal into a caiman assembly call and extract.
+fn lower_array(
+    dest: String,
+    elems: Vec<NestedExpr<SpecTerm>>,
+    type_ctx: &SpecInfo,
+) -> Vec<Hole<asm::Command>> {
+    let temp = tuple_id(std::slice::from_ref(&dest));
+    let function_id = array_to_str(&format!("{:#}", type_ctx.types[&dest]));
+    vec![
+        Hole::Filled(asm::Command::Node(asm::NamedNode {
+            name: Some(asm::NodeId(temp.clone())),
+            node: asm::Node::CallFunctionClass {
+                function_id: Hole::Filled(asm::FunctionClassId(function_id)),
+                arguments: Hole::Filled(
+                    elems
+                        .into_iter()
+                        .map(|e| Hole::Filled(asm::NodeId(term_to_name(e))))
+                        .collect(),
+                ),
+            },
+        })),
+        Hole::Filled(asm::Command::Node(asm::NamedNode {
+            name: Some(asm::NodeId(dest)),
+            node: asm::Node::ExtractResult {
+                node_id: Hole::Filled(asm::NodeId(temp)),
+                index: Hole::Filled(0),
+            },
+        })),
+    ]
+}
+
 /// Lowers a flattened spec assignment into an assembly command.
 /// This will convert things like additions and conditionals into assembly constructrs
 /// like external function calls and select nodes.
@@ -270,6 +301,13 @@ fn lower_spec_assign(
             templates,
             ..
         }) => lower_spec_call(lhs, &function, args, templates),
+        NestedExpr::Term(SpecTerm::Lit {
+            lit: SpecLiteral::Array(elems),
+            ..
+        }) => {
+            assert_eq!(lhs.len(), 1);
+            lower_array(lhs.swap_remove(0), elems, &global_ctx.specs[spec_name])
+        }
         NestedExpr::Term(t) => {
             assert_eq!(lhs.len(), 1);
             let node = lower_spec_term(t, &dtypes[&lhs[0]]);
@@ -293,7 +331,7 @@ fn lower_spec_assign(
                 &global_ctx.specs[spec_name],
             )
         }
-        NestedExpr::Uop { .. } => todo!(),
+        NestedExpr::Uop { .. } => unreachable!("Unary operators in specs are type errors"),
     }
 }
 /// Lower a list of spec statements into a list of assembly commands by appending
@@ -341,6 +379,7 @@ fn lower_spec_stmts(
                     )));
                 }
             }
+            SpecStmt::Loop { .. } => unreachable!("Loops should be desugared before lowering"),
         }
     }
     res
@@ -1,10 +1,10 @@
-
+use std::collections::HashMap;
 
 use crate::{
     error::{self, type_error, Info, LocalError},
     parse::ast::{
         Binop, ClassMembers, DataType, ExternDef, FloatSize, InputOrOutputVal, IntSize,
-        SchedulingFunc, TopLevel, Uop,
+        SchedulingFunc, SpecStmt, Tags, TopLevel, Uop,
     },
     typing::Context,
 };
@@ -53,14 +53,36 @@ impl DataType {
     /// that do not have FFI equivalents, return `None`.
     ///
     /// The types with equivalents are value types, not reference types.
+    /// Array lengths that name constants are resolved by the parser, so arrays
+    /// whose length is still a name have an erased length. Arrays with an
+    /// invalid length have no equivalent.
     #[must_use]
-    pub const fn ffi(&self) -> Option<asm::FFIType> {
+    pub fn ffi(&self) -> Option<asm::FFIType> {
         use asm::FFIType;
         match self {
             Self::Bool => Some(BOOL_FFI_TYPE),
+            Self::Int(IntSize::I8) => Some(FFIType::I8),
+            Self::Int(IntSize::I16) => Some(FFIType::I16),
             Self::Int(IntSize::I32) => Some(FFIType::I32),
             Self::Int(IntSize::I64) => Some(FFIType::I64),
+            Self::Int(IntSize::U8) => Some(FFIType::U8),
+            Self::Int(IntSize::U16) => Some(FFIType::U16),
+            Self::Int(IntSize::U32) => Some(FFIType::U32),
+            Self::Int(IntSize::U64) => Some(FFIType::U64),
+            Self::Int(IntSize::USize) => Some(FFIType::USize),
+            Self::Float(FloatSize::F32) => Some(FFIType::F32),
             Self::Float(FloatSize::F64) => Some(FFIType::F64),
+            Self::Array(element, _) => {
+                let element_type = Box::new(element.ffi()?);
+                Some(match self.array_length().ok()??.parse() {
+                    Ok(length) => FFIType::Array {
+                        element_type,
+                        length,
+                    },
+                    Err(_) => FFIType::ErasedLengthArray(element_type),
+                })
+            }
+            Self::Slice(element) => Some(FFIType::ErasedLengthArray(Box::new(element.ffi()?))),
             _ => None,
         }
     }
@@ -72,33 +94,30 @@ impl DataType {
     pub fn storage_type(&self) -> asm::FFIType {
         match self {
             Self::Ref(d) => d.storage_type(),
-            _ => self.ffi().unwrap_or_else(|| unimplemented!("Undefined type {self:?}")),
+            _ => self
+                .ffi()
+                .unwrap_or_else(|| unimplemented!("Undefined type {self:?}")),
         }
     }
 
-
     /// Converts a high-level caiman data type to a caiman assembly type id.
-    #[must_use] 
+    #[must_use]
     pub fn asm_type(&self) -> asm::TypeId {
         use asm::TypeId;
         match self {
             Self::Bool => TypeId(String::from("bool")),
-            Self::Int(IntSize::I32) => TypeId(String::from("i32")),
-            Self::Int(IntSize::I64) => TypeId(String::from("i64")),
-            Self::Float(FloatSize::F64) => TypeId(String::from("f64")),
+            Self::Int(size) => TypeId(size.to_string()),
+            Self::Float(size) => TypeId(size.to_string()),
             Self::BufferSpace => TypeId(String::from("BufferSpace")),
             Self::Event => TypeId(String::from("Event")),
             Self::UserDefined(name) => TypeId(name.clone()),
             Self::Encoder(_) => TypeId(String::from("Encoder")),
             Self::Fence(_) => TypeId(String::from("Fence")),
-            Self::Ref(t) => TypeId(format!(
-                "&{}",
-                t.asm_type()
-            )),
+            Self::Ref(t) => TypeId(format!("&{}", t.asm_type())),
+            Self::Array(..) | Self::Slice(_) | Self::GpuBuffer(_) => TypeId(format!("{self:#}")),
             x => unimplemented!("TODO: {x:?}"),
         }
     }
-    
 }
 
 /// Convert a high-level caiman data type to a caiman assembly type.
@@ -112,7 +131,32 @@ fn data_types_to_local_type(dts: &[DataType]) -> Vec<asm::TypeId> {
 /// Returns an error if the program is not well-typed or flattened.
 /// # Panics
 /// If lowering something with currently unsupported language features.
-pub fn lower(hlc: Vec<TopLevel>, typing_ctx: &Context, no_inference: bool) -> Result<asm::Program, error::LocalError> {
+pub fn lower(
+    hlc: Vec<TopLevel>,
+    typing_ctx: &Context,
+    no_inference: bool,
+) -> Result<asm::Program, error::LocalError> {
+    lower_with_tags(hlc, typing_ctx, no_inference).map(|(asm, _)| asm)
+}
+
+/// The tags deduced for the variables of each scheduling function, by function
+/// name and then by variable name
+pub type DeducedTags = HashMap<String, HashMap<String, Tags>>;
+
+/// Lower a high-level caiman program to caiman assembly, also returning the
+/// tags deduced for the variables of its scheduling functions.
+///
+/// Requires that the high-level caiman program is well-typed and flattened.
+/// # Errors
+/// Returns an error if the program is not well-typed or flattened.
+/// # Panics
+/// If lowering something with currently unsupported language features.
+#[allow(clippy::too_many_lines)]
+pub fn lower_with_tags(
+    hlc: Vec<TopLevel>,
+    typing_ctx: &Context,
+    no_inference: bool,
+) -> Result<(asm::Program, DeducedTags), error::LocalError> {
     // Preprocessing: (before this function)
     // 1. Match literals to literals in the spec
     // 2. Constant fold constants
@@ -139,9 +183,12 @@ pub fn lower(hlc: Vec<TopLevel>, typing_ctx: &Context, no_inference: bool) -> Re
             detailed: 2,
         },
         declarations: Vec::new(),
+        spans: HashMap::new(),
+        external_spans: HashMap::new(),
     };
     asm.declarations
         .extend(typing_ctx.type_decls.iter().cloned());
+    let mut tags = DeducedTags::new();
     for top in hlc {
         match top {
             TopLevel::Pipeline { name, entry, .. } => {
@@ -149,6 +196,7 @@ pub fn lower(hlc: Vec<TopLevel>, typing_ctx: &Context, no_inference: bool) -> Re
                     name,
                     funclet: asm::FuncletId(entry),
                     effect: Some(asm::EffectId(String::from("_loop_eff"))),
+                    recursion_limit: None,
                 };
                 asm.declarations.push(asm::Declaration::Pipeline(pipeline));
             }
@@ -162,10 +210,12 @@ pub fn lower(hlc: Vec<TopLevel>, typing_ctx: &Context, no_inference: bool) -> Re
                 };
                 for f in members {
                     match f {
-                        ClassMembers::SpatialFunclet(..) |
-                        ClassMembers::TimelineFunclet(..) |
-                        ClassMembers::ValueFunclet(..) => {
+                        ClassMembers::SpatialFunclet(..)
+                        | ClassMembers::TimelineFunclet(..)
+                        | ClassMembers::ValueFunclet(..) => {
+                            let spans = spec_spans(&f);
                             let funclet = lower_spec(f, &name, typing_ctx);
+                            asm.spans.insert(funclet.header.name.clone(), spans);
                             asm.declarations.push(asm::Declaration::Funclet(funclet));
                         }
                         ClassMembers::Extern {
@@ -176,7 +226,20 @@ pub fn lower(hlc: Vec<TopLevel>, typing_ctx: &Context, no_inference: bool) -> Re
                             output,
                             def,
                             info,
-                        } => asm.declarations.push(extern_to_asm(&name, device, pure, input, output, def, info, &class.name, typing_ctx.class_dimensions[&class.name.0])?),
+                        } => {
+                            asm.external_spans.insert(name.clone(), info_range(info));
+                            asm.declarations.push(extern_to_asm(
+                                &name,
+                                device,
+                                pure,
+                                input,
+                                output,
+                                def,
+                                info,
+                                &class.name,
+                                typing_ctx.class_dimensions[&class.name.0],
+                            )?);
+                        }
                     }
                 }
                 asm.declarations
@@ -190,11 +253,11 @@ pub fn lower(hlc: Vec<TopLevel>, typing_ctx: &Context, no_inference: bool) -> Re
                 statements,
                 info,
             } => {
-                let res = lower_schedule(
+                let (res, sched_tags) = lower_schedule(
                     typing_ctx,
                     SchedulingFunc {
                         info,
-                        name,
+                        name: name.clone(),
                         input,
                         output,
                         specs,
@@ -202,15 +265,20 @@ pub fn lower(hlc: Vec<TopLevel>, typing_ctx: &Context, no_inference: bool) -> Re
                     },
                     no_inference,
                 )?;
-                asm.declarations
-                    .extend(res.into_iter().map(asm::Declaration::Funclet));
+                for (funclet, spans) in res {
+                    asm.spans.insert(funclet.header.name.clone(), spans);
+                    asm.declarations.push(asm::Declaration::Funclet(funclet));
+                }
+                tags.insert(name, sched_tags);
             }
             // TODO: do something with this instead of handling in the parser to allow out of order uses
-            TopLevel::Typedef { .. } => (), 
+            TopLevel::Typedef { .. } => (),
+            // constants are only used as array lengths, which the parser resolves
+            TopLevel::Const { .. } => (),
             _ => todo!(),
         }
     }
-    Ok(asm)
+    Ok((asm, tags))
 }
 
 const fn binop_name(op: Binop) -> &'static str {
@@ -251,6 +319,16 @@ const fn uop_name(op: Uop) -> &'static str {
     }
 }
 
+/// The name of the built-in operation which constructs an array from its elements
+pub const ARRAY_OP: &str = "array";
+
+/// Gets the name of the external function which constructs an array of type
+/// `typ` from its elements.
+#[must_use]
+pub fn array_to_str(typ: &str) -> String {
+    format!("_{ARRAY_OP}_{typ}")
+}
+
 /// Converts a high-level caiman data type to an extern funclet id.
 #[must_use]
 pub fn binop_to_str(op: Binop, type_left: &str, type_right: &str) -> String {
@@ -277,6 +355,56 @@ pub fn tuple_id(names: &[String]) -> String {
     }
 }
 
+/// Where a spec funclet and the nodes defined by each of its assignments were
+/// written, for diagnostics about the lowered program.
+fn spec_spans(member: &ClassMembers) -> asm::FuncletSpans {
+    let mut spans = asm::FuncletSpans::default();
+    let spec = match member {
+        ClassMembers::ValueFunclet(spec)
+        | ClassMembers::SpatialFunclet(spec)
+        | ClassMembers::TimelineFunclet(spec) => spec,
+        ClassMembers::Extern { .. } => return spans,
+    };
+    spans.funclet = known_range(spec.info);
+    let mut statements: Vec<_> = spec.statements.iter().collect();
+    while let Some(statement) = statements.pop() {
+        match statement {
+            SpecStmt::Assign { info, lhs, .. } => {
+                if let Some(range) = known_range(*info) {
+                    let names: Vec<_> = lhs.iter().map(|(name, _)| name.clone()).collect();
+                    spans.nodes.insert(asm::NodeId(tuple_id(&names)), range);
+                    for name in names {
+                        spans.nodes.insert(asm::NodeId(name), range);
+                    }
+                }
+            }
+            SpecStmt::Returns(..) => (),
+            SpecStmt::Loop { body, .. } => statements.extend(body.iter()),
+        }
+    }
+    spans
+}
+
+/// The source range of `info`, or `None` if it doesn't come from the source
+/// file, as is the case for code the compiler made up.
+fn known_range(info: Info) -> Option<caiman::diagnostics::Range> {
+    (info.start_ln_and_col.0 != 0).then(|| info_range(info))
+}
+
+/// The source range of `info`, for diagnostics about the lowered program.
+const fn info_range(info: Info) -> caiman::diagnostics::Range {
+    caiman::diagnostics::Range {
+        start: caiman::diagnostics::Position {
+            line: info.start_ln_and_col.0,
+            column: info.start_ln_and_col.1,
+        },
+        end: caiman::diagnostics::Position {
+            line: info.end_ln_and_col.0,
+            column: info.end_ln_and_col.1,
+        },
+    }
+}
+
 /// Converts an extern def into an assembly external gpu info.
 /// Returns `None` if the extern def is `None`.
 fn get_gpu_info(def: Option<ExternDef>) -> Option<asm::ExternalGPUInfo> {
@@ -342,10 +470,12 @@ fn extern_to_asm(
     let template_args = if device == "gpu" {
         vec![]
     } else {
-        (0..num_dims).map(|_| asm::ExternalArgument{
-            name: None,
-            ffi_type: DataType::Int(IntSize::I32).ffi().unwrap(),
-        }).collect()
+        (0..num_dims)
+            .map(|_| asm::ExternalArgument {
+                name: None,
+                ffi_type: DataType::Int(IntSize::I32).ffi().unwrap(),
+            })
+            .collect()
     };
     Ok(asm::Declaration::ExternalFunction(asm::ExternalFunction {
         name: name.to_string(),
@@ -353,7 +483,7 @@ fn extern_to_asm(
             (d, true) if d == "cpu" => asm::ExternalFunctionKind::CPUPure,
             (d, false) if d == "cpu" => asm::ExternalFunctionKind::CPUEffect,
             (d, _) if d == "gpu" => asm::ExternalFunctionKind::GPU(
-                get_gpu_info(def).map_or_else(|| Err(type_error(info, 
+                get_gpu_info(def).map_or_else(|| Err(type_error(info,
                     &format!("{name} is declared to be a gpu external function but contains no GPU info"))), Ok)?,
             ),
             (d, _) => {
@@ -380,6 +510,6 @@ fn extern_to_asm(
             default: false,
             function_class: class_name.clone(),
         },
-        
+
     }))
 }
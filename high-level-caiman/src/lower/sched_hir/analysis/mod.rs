@@ -17,6 +17,7 @@ use super::{
 
 pub use continuations::{compute_continuations, Succs};
 pub use op_transform::op_transform_pass;
+pub use quot::deduce_sptl_quots;
 pub use quot::deduce_tmln_quots;
 pub use quot::deduce_val_quots;
 pub use record_expansion::transform_encode_pass;
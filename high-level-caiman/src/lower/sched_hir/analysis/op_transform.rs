@@ -3,7 +3,7 @@ use std::collections::HashMap;
 use crate::{
     enum_cast,
     lower::{
-        binop_to_str,
+        array_to_str, binop_to_str,
         sched_hir::{
             cfg::{BasicBlock, Cfg},
             HirBody, HirOp, OpType,
@@ -13,7 +13,7 @@ use crate::{
     parse::ast::{DataType, SchedTerm, Uop},
 };
 
-/// Transforms binary and unary operations into external FFI calls.
+/// Transforms binary and unary operations and array literals into external FFI calls.
 /// Also replaces dereferences with `ref_load` instructions.
 /// After this pass, all binary and unary operators, except referenceS
 /// will be replaced with external FFI calls or loads.
@@ -62,7 +62,9 @@ fn op_transform_instr(instr: &mut HirBody, data_types: &HashMap<String, DataType
                 typ: deref_data_type(data_types[src].clone()),
             }
         }
-        HirBody::Op { op, args, .. } => match op {
+        HirBody::Op {
+            op, args, dests, ..
+        } => match op {
             HirOp::Binary(bin) => {
                 assert_eq!(args.len(), 2);
                 let arg_l = enum_cast!(SchedTerm::Var { name, .. }, name, &args[0]);
@@ -70,8 +72,8 @@ fn op_transform_instr(instr: &mut HirBody, data_types: &HashMap<String, DataType
                 *op = HirOp::FFI(
                     binop_to_str(
                         *bin,
-                        &format!("{}", data_types[arg_l]),
-                        &format!("{}", data_types[arg_r]),
+                        &format!("{:#}", data_types[arg_l]),
+                        &format!("{:#}", data_types[arg_r]),
                     ),
                     OpType::Binary,
                 );
@@ -80,10 +82,17 @@ fn op_transform_instr(instr: &mut HirBody, data_types: &HashMap<String, DataType
                 assert_eq!(args.len(), 1);
                 let arg = enum_cast!(SchedTerm::Var { name, .. }, name, &args[0]);
                 *op = HirOp::FFI(
-                    uop_to_str(*unary, &format!("{}", data_types[arg])),
+                    uop_to_str(*unary, &format!("{:#}", data_types[arg])),
                     OpType::Unary,
                 );
             }
+            HirOp::Array => {
+                assert_eq!(dests.len(), 1);
+                *op = HirOp::FFI(
+                    array_to_str(&format!("{:#}", data_types[&dests[0].0])),
+                    OpType::Array,
+                );
+            }
             HirOp::Unary(Uop::Ref) | HirOp::FFI(_, OpType::External) => (),
             HirOp::Unary(Uop::Deref) => panic!("Unexpected deref op"),
             HirOp::FFI(_, _) => panic!("Unexpected transformed op"),
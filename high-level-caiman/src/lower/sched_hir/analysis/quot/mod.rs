@@ -1,8 +1,10 @@
 //! Quotient type checking and type deduction
 
+mod sptl_typing;
 mod tmln_typing;
 mod val_typing;
 
+pub use sptl_typing::deduce_sptl_quots;
 pub use tmln_typing::deduce_tmln_quots;
 pub use tmln_typing::*;
 pub use val_typing::deduce_val_quots;
@@ -0,0 +1,118 @@
+//! This file contains the logic for deducing the spatial quotients of a function.
+//! Spatial specs only name the buffer spaces passed into them, so unlike
+//! `val_typing` and `tmln_typing`, there's no unification to do. Each buffer
+//! passed to the function is matched up with a buffer space of the spatial spec
+//! and every device variable suballocated from that buffer is saved in the
+//! buffer's space.
+
+use std::collections::HashMap;
+
+use crate::{
+    error::{type_error, Info, LocalError},
+    lower::{
+        sched_hir::{cfg::Cfg, device_var_buffer, HirBody, TripleTag},
+        IN_STEM,
+    },
+    parse::ast::{DataType, Flow, Quotient, QuotientReference, SpecType, Tag},
+    typing::SpecInfo,
+};
+
+/// Deduces the spatial quotients of the buffers passed into a function and of
+/// the device variables suballocated from them.
+/// # Arguments
+/// * `inputs` - The input variables of the function
+/// * `cfg` - The control flow graph of the function
+/// * `spec_info` - The spatial spec of the function
+/// * `dtypes` - The data types of the variables in the function
+/// * `info` - The source info for the function
+/// * `spec_name` - The name of the spec
+/// # Errors
+/// Returns an error if a buffer is annotated with a buffer space the spec doesn't
+/// have or if there are more buffers than buffer spaces in the spec.
+pub fn deduce_sptl_quots(
+    inputs: &mut [(String, TripleTag)],
+    cfg: &mut Cfg,
+    spec_info: &SpecInfo,
+    dtypes: &HashMap<String, DataType>,
+    info: Info,
+    spec_name: &str,
+) -> Result<(), LocalError> {
+    let spaces: Vec<_> = spec_info
+        .sig
+        .input
+        .iter()
+        .filter(|(_, t)| matches!(t.base, DataType::BufferSpace))
+        .map(|(name, _)| name.clone())
+        .collect();
+    let mut unused_spaces = spaces.iter();
+    // map from buffer name to the spec node of its space
+    let mut buffer_spaces = HashMap::new();
+    for (name, tag) in inputs
+        .iter_mut()
+        .filter(|(name, _)| matches!(dtypes.get(name), Some(DataType::GpuBuffer(_))))
+    {
+        if tag.spatial.quot == Some(Quotient::None) {
+            continue;
+        }
+        let space = if let Some(annot) = &tag.spatial.quot_var.spec_var {
+            spaces
+                .iter()
+                .find(|s| *s == annot || format!("{IN_STEM}{s}") == *annot)
+                .ok_or_else(|| {
+                    type_error(
+                        info,
+                        &format!(
+                            "Buffer {name} is placed in {annot}, which is not a buffer space of {spec_name}",
+                        ),
+                    )
+                })?
+        } else {
+            unused_spaces.next().ok_or_else(|| {
+                type_error(
+                    info,
+                    &format!("Buffer {name} has no buffer space in {spec_name} to be placed in"),
+                )
+            })?
+        };
+        let space_node = format!("{IN_STEM}{space}");
+        tag.spatial.set_specified_info(Tag {
+            quot: Some(Quotient::Node),
+            quot_var: QuotientReference {
+                spec_type: SpecType::Spatial,
+                spec_var: Some(space_node.clone()),
+            },
+            flow: None,
+        });
+        if tag.spatial.flow.is_none() {
+            tag.spatial.flow = Some(Flow::Usable);
+        }
+        buffer_spaces.insert(name.clone(), space_node);
+    }
+    for block in cfg.blocks.values_mut() {
+        for stmt in &mut block.stmts {
+            if let HirBody::BeginEncoding {
+                buffer,
+                suballocs,
+                device_vars,
+                ..
+            } = stmt
+            {
+                for (var, tag) in device_vars {
+                    let space_node = device_var_buffer(var, buffer.as_ref(), suballocs)
+                        .and_then(|buffer| buffer_spaces.get(buffer));
+                    if let Some(space_node) = space_node {
+                        tag.spatial.override_unknown_info(Tag {
+                            quot: Some(Quotient::Node),
+                            quot_var: QuotientReference {
+                                spec_type: SpecType::Spatial,
+                                spec_var: Some(space_node.clone()),
+                            },
+                            flow: Some(Flow::Save),
+                        });
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
@@ -84,7 +84,7 @@ use crate::{
             cfg::{BasicBlock, Cfg, Edge, START_BLOCK_ID},
             HirBody, HirFuncCall, HirOp, OpType, Terminator, TripleTag,
         },
-        tuple_id,
+        tuple_id, ARRAY_OP,
     },
     parse::ast::{
         Binop, DataType, Quotient, QuotientReference, SchedLiteral, SchedTerm, SpecType, Tag,
@@ -245,7 +245,7 @@ fn unify_decl(
 ) -> Result<NodeEnv, LocalError> {
     match rhs {
         SchedTerm::Lit {
-            lit: SchedLiteral::Int(i),
+            lit: SchedLiteral::Int(i, _),
             info,
             tag,
         } => {
@@ -261,7 +261,7 @@ fn unify_decl(
             env = add_constraint(lhs, &ValQuot::Bool(*b), *info, env)?;
         }
         SchedTerm::Lit {
-            lit: SchedLiteral::Float(f),
+            lit: SchedLiteral::Float(f, _),
             info,
             tag,
         } => {
@@ -305,10 +305,11 @@ fn hir_op_to_binop(op: &HirOp) -> Binop {
                 "ashr" => Binop::AShr,
                 "land" => Binop::Land,
                 "lor" => Binop::Lor,
+                "index" => Binop::Index,
                 x => panic!("Unrecognized FFI binop: {x}"),
             }
         }
-        HirOp::Unary(_) => panic!("Not a binary operator"),
+        HirOp::Unary(_) | HirOp::Array => panic!("Not a binary operator"),
         HirOp::FFI(_, b) => panic!("Unexpected op type: {b:?}"),
     }
 }
@@ -383,6 +384,18 @@ fn unify_op(
                 )?;
             }
         }
+        HirOp::FFI(_, OpType::Array) => {
+            assert_eq!(dests.len(), 1);
+            env = add_constraint(
+                &dests[0].0,
+                &ValQuot::CallOne(
+                    String::from(ARRAY_OP),
+                    arg_names.iter().map(|x| MetaVar::new_var_name(x)).collect(),
+                ),
+                info,
+                env,
+            )?;
+        }
         HirOp::FFI(..) => todo!("Unimplemented operator"),
         _ => unreachable!(),
     }
@@ -127,15 +127,10 @@ impl TagAnalysis {
         }
         for (arg_name, arg_type) in input {
             let mut tg = arg_type.clone();
+            // references are saved wherever they were allocated unless annotated otherwise
             if matches!(data_types.get(arg_name), Some(DataType::Ref(_))) {
                 tg.spatial
                     .override_unknown_info(none_tag(SpecType::Spatial, Flow::Save));
-                if let Some(flow) = &tg.spatial.flow {
-                    assert!(
-                        *flow == Flow::Save,
-                        "Spatial tags for references must be save"
-                    );
-                }
             }
             let mut in_tg = override_none_usable(tg, &data_types[arg_name], flags.get(arg_name));
             if in_tg.value.quot.is_none() {
@@ -175,6 +170,11 @@ impl TagAnalysis {
         self.tags.get(var)
     }
 
+    /// Gets the variables with concrete information and their tags
+    pub fn tags(&self) -> impl Iterator<Item = (&String, &TripleTag)> {
+        self.tags.iter()
+    }
+
     /// Gets the input override for the specified variable or `None` if it was not
     /// overridden
     pub fn get_input_override(&self, var: &str) -> Option<&TripleTag> {
@@ -1,8 +1,13 @@
 #![allow(clippy::module_name_repetitions)]
-use std::collections::{BTreeSet, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
 use crate::{
-    enum_cast, lower::{lower_schedule::tag_to_tag, tuple_id}, parse::ast::{Binop, DataType, EncodedCommand, FullType, NestedExpr, SchedExpr, SchedFuncCall, SpecTerm, SpecType, Tag, Tags, TemplateArgs, TimelineOperation, Uop}
+    enum_cast,
+    lower::{lower_schedule::tag_to_tag, tuple_id},
+    parse::ast::{
+        Binop, DataType, EncodedCommand, FullType, NestedExpr, SchedExpr, SchedFuncCall,
+        SchedLiteral, SpecTerm, SpecType, Tag, Tags, TemplateArgs, TimelineOperation, Uop,
+    },
 };
 use caiman::assembly::ast as asm;
 
@@ -35,15 +40,19 @@ impl TripleTag {
         }
     }
     pub fn from_opt(tags: &Option<Tags>) -> Self {
-        tags.as_ref().map_or_else(|| Self::from_owned_opt(None), |tags| Self::from_tags(tags))
+        tags.as_ref()
+            .map_or_else(|| Self::from_owned_opt(None), |tags| Self::from_tags(tags))
     }
 
     pub fn from_owned_opt(tags: Option<Tags>) -> Self {
-        tags.map_or_else(|| Self {
+        tags.map_or_else(
+            || Self {
                 value: Tag::new_unspecified(SpecType::Value),
                 spatial: Tag::new_unspecified(SpecType::Spatial),
                 timeline: Tag::new_unspecified(SpecType::Timeline),
-            }, Self::from_tag_vec)
+            },
+            Self::from_tag_vec,
+        )
     }
 
     pub fn from_tag_vec(tags: Vec<Tag>) -> Self {
@@ -51,16 +60,16 @@ impl TripleTag {
         let mut spatial = None;
         let mut timeline = None;
         for tag in tags {
-                match tag.quot_var.spec_type {
-                    SpecType::Value => value = Some(tag.clone()),
-                    SpecType::Spatial => spatial = Some(tag.clone()),
-                    SpecType::Timeline => timeline = Some(tag.clone()),
-                }     
+            match tag.quot_var.spec_type {
+                SpecType::Value => value = Some(tag.clone()),
+                SpecType::Spatial => spatial = Some(tag.clone()),
+                SpecType::Timeline => timeline = Some(tag.clone()),
+            }
         }
         Self {
             value: value.unwrap_or_else(|| Tag::new_unspecified(SpecType::Value)),
             spatial: spatial.unwrap_or_else(|| Tag::new_unspecified(SpecType::Spatial)),
-            timeline: timeline.unwrap_or_else(|| Tag::new_unspecified(SpecType::Timeline),)
+            timeline: timeline.unwrap_or_else(|| Tag::new_unspecified(SpecType::Timeline)),
         }
     }
 
@@ -69,11 +78,11 @@ impl TripleTag {
         let mut spatial = None;
         let mut timeline = None;
         for tag in tags {
-                match tag.quot_var.spec_type {
-                    SpecType::Value => value = Some(tag.clone()),
-                    SpecType::Spatial => spatial = Some(tag.clone()),
-                    SpecType::Timeline => timeline = Some(tag.clone()),
-                }
+            match tag.quot_var.spec_type {
+                SpecType::Value => value = Some(tag.clone()),
+                SpecType::Spatial => spatial = Some(tag.clone()),
+                SpecType::Timeline => timeline = Some(tag.clone()),
+            }
         }
         Self {
             value: value.unwrap_or_else(|| Tag::new_unspecified(SpecType::Value)),
@@ -87,7 +96,8 @@ impl TripleTag {
     }
 
     pub fn from_fulltype_opt(ft: &Option<FullType>) -> Self {
-        ft.as_ref().map_or_else(|| Self::from_owned_opt(None), Self::from_fulltype)
+        ft.as_ref()
+            .map_or_else(|| Self::from_owned_opt(None), Self::from_fulltype)
     }
 
     /// Updates the tag so that all non-null parts of `other` are added to `self`
@@ -127,10 +137,8 @@ impl TripleTag {
             tag_to_tag(&self.timeline),
         ]
     }
-
 }
 
-
 impl From<TripleTag> for Tags {
     fn from(val: TripleTag) -> Self {
         vec![val.value, val.spatial, val.timeline]
@@ -235,6 +243,12 @@ pub enum HirBody {
     BeginEncoding {
         info: Info,
         device: String,
+        /// The buffer `device_vars` are suballocated from. If `None`,
+        /// each device variable is a separate temporary allocation
+        buffer: Option<Name>,
+        /// Device variables suballocated from a buffer other than `buffer`,
+        /// keyed by their name without the encoder prefix
+        suballocs: BTreeMap<Name, Name>,
         device_vars: Vec<(Name, TripleTag)>,
         tags: TripleTag,
         encoder: (String, TripleTag),
@@ -258,14 +272,14 @@ pub enum HirBody {
         tags: TripleTag,
     },
     /// A sync-fence folowed by copying all the encoded variables in `src` to
-    /// local ones in `dests`. 
+    /// local ones in `dests`.
     Sync {
         info: Info,
         /// the local versions of the encoded variables or the name of the record
         /// destination.
         dests: FillIn<(Name, TripleTag), Vec<(Name, TripleTag)>>,
         /// fence name or fence followed by all the variables being copied to the local device
-        srcs: FillIn<Name, Vec<Name>>, 
+        srcs: FillIn<Name, Vec<Name>>,
         // sync does not require an extraction
         tags: TripleTag,
     },
@@ -296,7 +310,7 @@ pub enum HirBody {
     Phi {
         info: Info,
         dest: Name,
-        /// Map from incoming block id to the incoming variable name 
+        /// Map from incoming block id to the incoming variable name
         /// from that block
         inputs: HashMap<usize, Name>,
         /// original name of the variable
@@ -304,15 +318,17 @@ pub enum HirBody {
     },
 }
 
-/// The type of an FFI external operation. 
+/// The type of an FFI external operation.
 #[derive(Clone, Debug, Copy, PartialEq, Eq, Hash)]
 pub enum OpType {
     /// An external operation for a built-in binary operation
     Binary,
     /// An external operation for a built-in unary operation
     Unary,
+    /// An external operation which builds an array from its elements
+    Array,
     /// An external operation for a user-supplied external function
-    External
+    External,
 }
 
 /// A high level IR external operation.
@@ -323,6 +339,8 @@ pub enum HirOp {
     Binary(Binop),
     /// an unlowered unary operation
     Unary(Uop),
+    /// an unlowered array literal
+    Array,
     /// a lowered operation into an external call
     FFI(Name, OpType),
 }
@@ -332,7 +350,9 @@ impl HirOp {
     /// Panics if the operation is not lowered.
     pub fn lower(&self) -> Name {
         match self {
-            Self::Binary(_) | Self::Unary(_) => panic!("Cannot lower unlowered operation"),
+            Self::Binary(_) | Self::Unary(_) | Self::Array => {
+                panic!("Cannot lower unlowered operation")
+            }
             Self::FFI(name, _) => name.clone(),
         }
     }
@@ -346,19 +366,21 @@ pub struct HirFuncCall {
     pub target: String,
     pub args: Vec<String>,
     pub tag: TripleTag,
-    /// The number of value template arguments that occur in `args` before the 
+    /// The number of value template arguments that occur in `args` before the
     /// normal arguments
     pub num_dims: usize,
 }
 
 impl HirFuncCall {
-    pub fn new(value: SchedFuncCall) ->Self {
+    pub fn new(value: SchedFuncCall) -> Self {
         if let NestedExpr::Term(SchedTerm::Var { name, .. }) = *value.target {
             let mut starting_args = vec![];
-            let num_dims = value.templates.as_ref().map_or(0, |t| if let TemplateArgs::Vals(v) = t {
-                v.len()
-            } else {
-                0
+            let num_dims = value.templates.as_ref().map_or(0, |t| {
+                if let TemplateArgs::Vals(v) = t {
+                    v.len()
+                } else {
+                    0
+                }
             });
             match value.templates {
                 Some(TemplateArgs::Vals(vs)) => {
@@ -369,14 +391,13 @@ impl HirFuncCall {
                             panic!("Invalid template argument {v:?}")
                         }
                     }
-                },
+                }
                 Some(TemplateArgs::Type(_)) => unimplemented!("Type template arguments"),
                 None => (),
-
             }
-            let args = starting_args.into_iter().chain(value.args
+            let args = starting_args
                 .into_iter()
-                .map(|a| {
+                .chain(value.args.into_iter().map(|a| {
                     enum_cast!(
                         SchedTerm::Var { name, .. },
                         name,
@@ -509,7 +530,12 @@ impl Hir for Terminator {
     fn get_info(&self) -> Info {
         match self {
             Self::Call(_, call) | Self::CaptureCall { call, .. } => call.info,
-            Self::Select { info, .. } | Self::Return { info, .. } | Self::FinalReturn(info, ..) | Self::None(info) | Self::Next(info, ..) | Self::Yield(info, ..) => *info,
+            Self::Select { info, .. }
+            | Self::Return { info, .. }
+            | Self::FinalReturn(info, ..)
+            | Self::None(info)
+            | Self::Next(info, ..)
+            | Self::Yield(info, ..) => *info,
         }
     }
     fn get_defs(&self) -> Option<Vec<String>> {
@@ -521,7 +547,11 @@ impl Hir for Terminator {
             }
             // we don't consider the defs of a select to be defs of this terminator,
             // but rather they are the defs of the left and right funclets
-            Self::FinalReturn(..) | Self::Select { .. } | Self::None(..) | Self::Next(..) | Self::Yield(..) => None,
+            Self::FinalReturn(..)
+            | Self::Select { .. }
+            | Self::None(..)
+            | Self::Next(..)
+            | Self::Yield(..) => None,
         }
     }
     fn get_write_uses(&self) -> Option<Vec<String>> {
@@ -538,12 +568,14 @@ impl Hir for Terminator {
             Self::Select { guard, .. } => {
                 uses.insert(guard.clone());
             }
-            Self::Return { rets, passthrough, ..}  => {
+            Self::Return {
+                rets, passthrough, ..
+            } => {
                 for node in rets.iter().chain(passthrough.iter()) {
                     uses.insert(node.clone());
                 }
             }
-            Self::FinalReturn(_, names) | Self::Next(_, names) | Self::Yield(_, names)=> {
+            Self::FinalReturn(_, names) | Self::Next(_, names) | Self::Yield(_, names) => {
                 uses.extend(names.iter().cloned());
             }
             Self::None(..) => (),
@@ -570,7 +602,9 @@ impl Hir for Terminator {
                     *name = f(name, UseType::Read);
                 }
             }
-            Self::Return { rets, passthrough, .. } => {
+            Self::Return {
+                rets, passthrough, ..
+            } => {
                 for node in rets.iter_mut().chain(passthrough.iter_mut()) {
                     *node = f(node, UseType::Read);
                 }
@@ -588,7 +622,11 @@ impl Hir for Terminator {
                     *dest = f(dest);
                 }
             }
-            Self::FinalReturn(..) | Self::Select { .. } | Self::None(..) | Self::Next(..) | Self::Yield(..) => (),
+            Self::FinalReturn(..)
+            | Self::Select { .. }
+            | Self::None(..)
+            | Self::Next(..)
+            | Self::Yield(..) => (),
         }
     }
 }
@@ -628,14 +666,9 @@ impl std::ops::DerefMut for HirInstr<'_> {
 impl HirBody {
     pub fn new(stmt: SchedStmt) -> Self {
         match stmt {
-            SchedStmt::Assign {
-                info,
-                lhs,
-                rhs,
-                ..
-            } => {   
-                if let SchedExpr::Term(SchedTerm::Var { name, tag, ..}) = lhs {
-                        let rhs = enum_cast!(SchedExpr::Term, rhs);
+            SchedStmt::Assign { info, lhs, rhs, .. } => {
+                if let SchedExpr::Term(SchedTerm::Var { name, tag, .. }) = lhs {
+                    let rhs = enum_cast!(SchedExpr::Term, rhs);
                     Self::RefStore {
                         info,
                         lhs_tags: TripleTag::from_opt(&tag),
@@ -644,8 +677,8 @@ impl HirBody {
                     }
                 } else {
                     panic!("Invalid assignment")
-                }       
-            },
+                }
+            }
             SchedStmt::Decl {
                 info,
                 lhs,
@@ -666,37 +699,69 @@ impl HirBody {
                     rhs,
                 }
             }
-            SchedStmt::Encode { info, stmt, encoder, cmd, .. } => {
-                match cmd {
-                    EncodedCommand::Copy => {
-                        assert_eq!(stmt.lhs.len(), 1);  
-                        Self::DeviceCopy { info, dest: stmt.lhs[0].0.clone(), 
-                            dest_tag: TripleTag::from_opt(&stmt.lhs[0].1), 
-                            src: enum_cast!(SchedTerm::Var {name, ..}, name, enum_cast!(SchedExpr::Term, stmt.rhs)), 
-                            dir: DataMovement::HostToDevice, encoder }
-                    },
-                    EncodedCommand::Invoke => {
-                        let dests = stmt.lhs.into_iter().map(|(nm, tag)| (nm, TripleTag::from_opt(&tag))).collect();
-                        if let SchedTerm::Call(info, call) = enum_cast!(SchedExpr::Term, stmt.rhs) {
-                            let func = HirFuncCall::new(call);
-                            Self::EncodeDo { info, dests, func, encoder}
-                        } else {
-                            panic!("Invalid encode")
+            SchedStmt::Encode {
+                info,
+                stmt,
+                encoder,
+                cmd,
+                ..
+            } => match cmd {
+                EncodedCommand::Copy => {
+                    assert_eq!(stmt.lhs.len(), 1);
+                    Self::DeviceCopy {
+                        info,
+                        dest: stmt.lhs[0].0.clone(),
+                        dest_tag: TripleTag::from_opt(&stmt.lhs[0].1),
+                        src: enum_cast!(
+                            SchedTerm::Var { name, .. },
+                            name,
+                            enum_cast!(SchedExpr::Term, stmt.rhs)
+                        ),
+                        dir: DataMovement::HostToDevice,
+                        encoder,
+                    }
+                }
+                EncodedCommand::Invoke => {
+                    let dests = stmt
+                        .lhs
+                        .into_iter()
+                        .map(|(nm, tag)| (nm, TripleTag::from_opt(&tag)))
+                        .collect();
+                    if let SchedTerm::Call(info, call) = enum_cast!(SchedExpr::Term, stmt.rhs) {
+                        let func = HirFuncCall::new(call);
+                        Self::EncodeDo {
+                            info,
+                            dests,
+                            func,
+                            encoder,
                         }
-                    },
+                    } else {
+                        panic!("Invalid encode")
+                    }
                 }
-            }
+            },
             SchedStmt::Decl { .. } => panic!("Invalid declaration"),
             SchedStmt::Return(..)
             | SchedStmt::Block(..)
             | SchedStmt::If { .. }
             | SchedStmt::Seq { .. }
+            | SchedStmt::Loop { .. }
             | SchedStmt::Call(..) => {
                 panic!("Unexpected stmt")
             }
             SchedStmt::Hole(info) => Self::Hole(info),
-            SchedStmt::InEdgeAnnotation { info, tags } => Self::InAnnotation(info, tags.into_iter().map(|(name, tags)| (name, TripleTag::from_tag_vec(tags))).collect()),
-            SchedStmt::OutEdgeAnnotation { info, tags } => Self::OutAnnotation(info, tags.into_iter().map(|(name, tags)| (name, TripleTag::from_tag_vec(tags))).collect()),
+            SchedStmt::InEdgeAnnotation { info, tags } => Self::InAnnotation(
+                info,
+                tags.into_iter()
+                    .map(|(name, tags)| (name, TripleTag::from_tag_vec(tags)))
+                    .collect(),
+            ),
+            SchedStmt::OutEdgeAnnotation { info, tags } => Self::OutAnnotation(
+                info,
+                tags.into_iter()
+                    .map(|(name, tags)| (name, TripleTag::from_tag_vec(tags)))
+                    .collect(),
+            ),
         }
     }
 
@@ -712,49 +777,127 @@ impl HirBody {
     /// Constructs a new `HirBody` from a constant declaration.
     fn from_const_decl(expr: SchedExpr, info: Info, lhs: Vec<(String, Option<FullType>)>) -> Self {
         match expr {
-            SchedExpr::Term(rhs) => {
-                match rhs {
-                    SchedTerm::Call(info, call) => {
-                        let target = enum_cast!(SchedTerm::Var { name, ..}, name, enum_cast!(SchedExpr::Term, &*call.target));
-                        Self::Op {
-                            info,
-                            dests: lhs.into_iter().map(|(name, tags)| (name, TripleTag::from_fulltype_opt(&tags))).collect(),
-                            op: HirOp::FFI(target.clone(), OpType::External),
-                            args: call.args.iter().map(|x| enum_cast!(SchedExpr::Term, x)).cloned().collect(),
-                        }
-                    },
-                    SchedTerm::TimelineOperation { info, op: TimelineOperation::Submit, arg, tag } => 
-                        Self::Submit { info, dest: lhs[0].0.clone(),
-                            src: enum_cast!(SchedTerm::Var {name, .. }, name, enum_cast!(SchedExpr::Term, *arg)), 
-                            tags: TripleTag::from_opt(&tag) }
-                        ,
-                    SchedTerm::TimelineOperation { info, op: TimelineOperation::Await, arg, tag } => {
-                        let arg_name = enum_cast!(SchedTerm::Var {name, .. }, name, enum_cast!(SchedExpr::Term, *arg));
-                        Self::Sync { info, dests: FillIn::Initial((lhs[0].0.clone(), TripleTag::from_fulltype_opt(&lhs[0].1))), 
-                            srcs: FillIn::Initial(arg_name), tags: TripleTag::from_opt(&tag) }
-                    },
-                    SchedTerm::EncodeBegin { info, device, tag, defs } => {
-                        Self::BeginEncoding {
-                            info,
-                            device,
-                            device_vars: defs.into_iter().map(|(name, tags)| (name, TripleTag::from_fulltype_opt(&tags))).collect(),
-                            tags: Self::to_tmln_tuple_tag(TripleTag::from_opt(&tag)),
-                            encoder: (lhs[0].0.clone(), TripleTag::from_fulltype_opt(&lhs[0].1)),
-                            active_fences: vec![],
-                        }
-                    },
-                    _ => Self::ConstDecl {
+            SchedExpr::Term(rhs) => match rhs {
+                SchedTerm::Call(info, call) => {
+                    let target = enum_cast!(
+                        SchedTerm::Var { name, .. },
+                        name,
+                        enum_cast!(SchedExpr::Term, &*call.target)
+                    );
+                    Self::Op {
                         info,
-                        lhs: lhs[0].0.clone(),
-                        lhs_tag: TripleTag::from_fulltype_opt(&lhs[0].1),
-                        rhs,
+                        dests: lhs
+                            .into_iter()
+                            .map(|(name, tags)| (name, TripleTag::from_fulltype_opt(&tags)))
+                            .collect(),
+                        op: HirOp::FFI(target.clone(), OpType::External),
+                        args: call
+                            .args
+                            .iter()
+                            .map(|x| enum_cast!(SchedExpr::Term, x))
+                            .cloned()
+                            .collect(),
+                    }
+                }
+                SchedTerm::TimelineOperation {
+                    info,
+                    op: TimelineOperation::Submit,
+                    arg,
+                    tag,
+                } => Self::Submit {
+                    info,
+                    dest: lhs[0].0.clone(),
+                    src: enum_cast!(
+                        SchedTerm::Var { name, .. },
+                        name,
+                        enum_cast!(SchedExpr::Term, *arg)
+                    ),
+                    tags: TripleTag::from_opt(&tag),
+                },
+                SchedTerm::TimelineOperation {
+                    info,
+                    op: TimelineOperation::Await,
+                    arg,
+                    tag,
+                } => {
+                    let arg_name = enum_cast!(
+                        SchedTerm::Var { name, .. },
+                        name,
+                        enum_cast!(SchedExpr::Term, *arg)
+                    );
+                    Self::Sync {
+                        info,
+                        dests: FillIn::Initial((
+                            lhs[0].0.clone(),
+                            TripleTag::from_fulltype_opt(&lhs[0].1),
+                        )),
+                        srcs: FillIn::Initial(arg_name),
+                        tags: TripleTag::from_opt(&tag),
+                    }
+                }
+                SchedTerm::EncodeBegin {
+                    info,
+                    device,
+                    buffer,
+                    suballocs,
+                    tag,
+                    defs,
+                } => Self::BeginEncoding {
+                    info,
+                    device,
+                    buffer,
+                    suballocs: suballocs.into_iter().collect(),
+                    device_vars: defs
+                        .into_iter()
+                        .map(|(name, tags)| (name, TripleTag::from_fulltype_opt(&tags)))
+                        .collect(),
+                    tags: Self::to_tmln_tuple_tag(TripleTag::from_opt(&tag)),
+                    encoder: (lhs[0].0.clone(), TripleTag::from_fulltype_opt(&lhs[0].1)),
+                    active_fences: vec![],
+                },
+                SchedTerm::Lit {
+                    info,
+                    lit: SchedLiteral::Array(elems),
+                    ..
+                } => {
+                    assert_eq!(lhs.len(), 1);
+                    Self::Op {
+                        info,
+                        dests: lhs
+                            .into_iter()
+                            .map(|(name, tags)| (name, TripleTag::from_fulltype_opt(&tags)))
+                            .collect(),
+                        op: HirOp::Array,
+                        args: elems
+                            .into_iter()
+                            .map(|x| enum_cast!(SchedExpr::Term, x))
+                            .collect(),
                     }
                 }
+                _ => Self::ConstDecl {
+                    info,
+                    lhs: lhs[0].0.clone(),
+                    lhs_tag: TripleTag::from_fulltype_opt(&lhs[0].1),
+                    rhs,
+                },
             },
-            SchedExpr::Binop { info, op: Binop::Dot, lhs: op_lhs, rhs: op_rhs} => {
+            SchedExpr::Binop {
+                info,
+                op: Binop::Dot,
+                lhs: op_lhs,
+                rhs: op_rhs,
+            } => {
                 assert_eq!(lhs.len(), 1);
-                let op_lhs_name = enum_cast!(SchedTerm::Var { name, .. }, name, enum_cast!(SchedExpr::Term, *op_lhs));
-                let rhs_name = enum_cast!(SchedTerm::Var { name, .. }, name, enum_cast!(SchedExpr::Term, *op_rhs));
+                let op_lhs_name = enum_cast!(
+                    SchedTerm::Var { name, .. },
+                    name,
+                    enum_cast!(SchedExpr::Term, *op_lhs)
+                );
+                let rhs_name = enum_cast!(
+                    SchedTerm::Var { name, .. },
+                    name,
+                    enum_cast!(SchedExpr::Term, *op_rhs)
+                );
                 Self::ConstDecl {
                     info,
                     lhs: lhs[0].0.clone(),
@@ -763,7 +906,7 @@ impl HirBody {
                         name: format!("{op_lhs_name}::{rhs_name}"),
                         tag: None,
                         info,
-                    }
+                    },
                 }
             }
             SchedExpr::Binop {
@@ -777,38 +920,50 @@ impl HirBody {
                 assert_eq!(lhs.len(), 1);
                 Self::Op {
                     info,
-                    dests: lhs.into_iter().map(|(name, tags)| (name, TripleTag::from_fulltype_opt(&tags))).collect(),
+                    dests: lhs
+                        .into_iter()
+                        .map(|(name, tags)| (name, TripleTag::from_fulltype_opt(&tags)))
+                        .collect(),
                     op: HirOp::Binary(op),
                     args: vec![lhs_term.clone(), rhs_term.clone()],
                 }
-            },
-            SchedExpr::Uop { 
-                info, op, expr
-            } => {
+            }
+            SchedExpr::Uop { info, op, expr } => {
                 let term = enum_cast!(SchedExpr::Term, *expr);
                 assert_eq!(lhs.len(), 1);
                 Self::Op {
                     info,
-                    dests: lhs.into_iter().map(|(name, tags)| (name, TripleTag::from_fulltype_opt(&tags))).collect(),
+                    dests: lhs
+                        .into_iter()
+                        .map(|(name, tags)| (name, TripleTag::from_fulltype_opt(&tags)))
+                        .collect(),
                     op: HirOp::Unary(op),
                     args: vec![term],
                 }
-            },
-            SchedExpr::Conditional { .. } => panic!("Inline conditonal expresssions not allowed in schedule"),
+            }
+            SchedExpr::Conditional { .. } => {
+                panic!("Inline conditonal expresssions not allowed in schedule")
+            }
         }
     }
-
 }
 impl Hir for HirBody {
     fn get_info(&self) -> Info {
         match self {
-            Self::RefStore { info, .. } | Self::RefLoad { info, .. } 
-            | Self::DeviceCopy { info, .. } | Self::BeginEncoding { info, .. } 
-            | Self::EncodeDo { info, .. } 
-            | Self::Submit { info, .. } | Self::ConstDecl { info, .. } 
-            | Self::VarDecl { info, .. } | Self::Hole(info) | Self::Op { info, .. } 
-            | Self::InAnnotation(info, ..) | Self::OutAnnotation(info, ..) 
-            | Self::Phi { info, .. } | Self::Sync { info, ..} => *info,
+            Self::RefStore { info, .. }
+            | Self::RefLoad { info, .. }
+            | Self::DeviceCopy { info, .. }
+            | Self::BeginEncoding { info, .. }
+            | Self::EncodeDo { info, .. }
+            | Self::Submit { info, .. }
+            | Self::ConstDecl { info, .. }
+            | Self::VarDecl { info, .. }
+            | Self::Hole(info)
+            | Self::Op { info, .. }
+            | Self::InAnnotation(info, ..)
+            | Self::OutAnnotation(info, ..)
+            | Self::Phi { info, .. }
+            | Self::Sync { info, .. } => *info,
         }
     }
     fn get_uses(&self, res: &mut BTreeSet<String>) {
@@ -835,11 +990,21 @@ impl Hir for HirBody {
                     term_get_uses(arg, res);
                 }
             }
-            Self::InAnnotation(..) | Self::OutAnnotation(..) | Self::Hole(..) | Self::BeginEncoding { .. } => (),
-            Self::Phi {inputs, ..} => {
+            Self::InAnnotation(..) | Self::OutAnnotation(..) | Self::Hole(..) => (),
+            Self::BeginEncoding {
+                buffer, suballocs, ..
+            } => {
+                res.extend(buffer.iter().chain(suballocs.values()).cloned());
+            }
+            Self::Phi { inputs, .. } => {
                 res.extend(inputs.iter().map(|(_, name)| name.clone()));
             }
-            Self::EncodeDo { dests, func, encoder, ..} => {
+            Self::EncodeDo {
+                dests,
+                func,
+                encoder,
+                ..
+            } => {
                 for arg in &func.args {
                     res.insert(arg.clone());
                 }
@@ -848,43 +1013,49 @@ impl Hir for HirBody {
                 }
                 res.insert(encoder.clone());
             }
-            Self::DeviceCopy { dest, src, encoder, .. } => {
+            Self::DeviceCopy {
+                dest, src, encoder, ..
+            } => {
                 res.insert(dest.clone());
                 res.insert(src.clone());
                 res.insert(encoder.clone());
             }
-            Self::Sync { srcs, .. } => {
-                match srcs {
-                    FillIn::Initial(name) => {
-                        res.insert(name.clone());
-                    }
-                    FillIn::Processed(srcs) => {
-                        res.extend(srcs.iter().cloned());
-                    }
+            Self::Sync { srcs, .. } => match srcs {
+                FillIn::Initial(name) => {
+                    res.insert(name.clone());
                 }
-            }
-            
+                FillIn::Processed(srcs) => {
+                    res.extend(srcs.iter().cloned());
+                }
+            },
         }
     }
 
     fn get_write_uses(&self) -> Option<Vec<String>> {
         match self {
-            Self::RefStore { lhs, ..} => Some(vec![lhs.clone()]),
-            Self::EncodeDo { dests, ..} => Some(dests.iter().map(|(name, _)| name.clone()).collect()),
+            Self::RefStore { lhs, .. } => Some(vec![lhs.clone()]),
+            Self::EncodeDo { dests, .. } => {
+                Some(dests.iter().map(|(name, _)| name.clone()).collect())
+            }
             Self::DeviceCopy { dest, .. } => Some(vec![dest.clone()]),
-            Self::ConstDecl { .. } | Self::VarDecl { .. } | Self::RefLoad { .. } 
-            | Self::Op { .. } | Self::Hole(..) | Self::InAnnotation(..) 
-            | Self::OutAnnotation(..) | Self::BeginEncoding { .. } 
+            Self::ConstDecl { .. }
+            | Self::VarDecl { .. }
+            | Self::RefLoad { .. }
+            | Self::Op { .. }
+            | Self::Hole(..)
+            | Self::InAnnotation(..)
+            | Self::OutAnnotation(..)
+            | Self::BeginEncoding { .. }
             | Self::Phi { .. }
-            | Self::Submit { .. } 
-            |Self::Sync { .. } => None,
+            | Self::Submit { .. }
+            | Self::Sync { .. } => None,
         }
     }
 
     fn get_defs(&self) -> Option<Vec<String>> {
         match self {
-            Self::ConstDecl { lhs,  .. } | Self::VarDecl { lhs, .. } 
-            | Self::RefLoad { dest: lhs, ..} | 
+            Self::ConstDecl { lhs,  .. } | Self::VarDecl { lhs, .. }
+            | Self::RefLoad { dest: lhs, ..} |
             Self::Phi { dest: lhs, ..} => {
                 Some(vec![lhs.clone()])
             }
@@ -895,7 +1066,7 @@ impl Hir for HirBody {
                 let mut res = device_vars.iter().map(|(name, _)| name.clone()).collect::<Vec<_>>();
                 res.push(encoder.0.clone());
                 Some(res)
-                
+
             }
             Self::Submit { dest, ..} => Some(vec![dest.clone()]),
             Self::Sync { dests, .. } => match dests {
@@ -906,7 +1077,7 @@ impl Hir for HirBody {
             // RefStore doesn't have a def bc it's a store to a reference
             | Self::RefStore { .. }
             | Self::InAnnotation(..)
-            | Self::OutAnnotation(..) 
+            | Self::OutAnnotation(..)
             | Self::DeviceCopy { .. }
             | Self::EncodeDo {..} => None,
         }
@@ -914,44 +1085,46 @@ impl Hir for HirBody {
 
     fn rename_defs(&mut self, f: &mut dyn FnMut(&str) -> String) {
         match self {
-            Self::ConstDecl { lhs, .. } | Self::VarDecl { lhs, .. } 
-            | Self::RefLoad { dest: lhs, ..} |
-            Self::Phi { dest: lhs, ..} => {
+            Self::ConstDecl { lhs, .. }
+            | Self::VarDecl { lhs, .. }
+            | Self::RefLoad { dest: lhs, .. }
+            | Self::Phi { dest: lhs, .. } => {
                 *lhs = f(lhs);
             }
-            Self::Op { dests, ..} => {
+            Self::Op { dests, .. } => {
                 for (name, _) in dests {
                     *name = f(name);
                 }
             }
-            Self::BeginEncoding { device_vars, encoder, ..  } => {
+            Self::BeginEncoding {
+                device_vars,
+                encoder,
+                ..
+            } => {
                 for (name, _) in device_vars {
                     *name = f(name);
                 }
                 *encoder = (f(&encoder.0), encoder.1.clone());
             }
-            Self::Submit { dest, ..} => {
+            Self::Submit { dest, .. } => {
                 *dest = f(dest);
-                
             }
-            Self::Sync { dests, ..} => {
-                match dests {
-                    FillIn::Initial((name, _)) => {
+            Self::Sync { dests, .. } => match dests {
+                FillIn::Initial((name, _)) => {
+                    *name = f(name);
+                }
+                FillIn::Processed(dests) => {
+                    for (name, _) in dests {
                         *name = f(name);
                     }
-                    FillIn::Processed(dests) => {
-                        for (name, _) in dests {
-                            *name = f(name);
-                        }
-                    }
                 }
-            }
+            },
             Self::Hole(..)
             | Self::RefStore { .. }
             | Self::InAnnotation(..)
-            | Self::OutAnnotation(..) 
-            | Self::EncodeDo {..} 
-            | Self::DeviceCopy {..}=> (),
+            | Self::OutAnnotation(..)
+            | Self::EncodeDo { .. }
+            | Self::DeviceCopy { .. } => (),
         }
     }
 
@@ -969,23 +1142,30 @@ impl Hir for HirBody {
                     term_rename_uses(arg, &mut |name| f(name, UseType::Read));
                 }
             }
-            Self::RefLoad { src, .. } | Self::Submit { src, ..} => {
+            Self::RefLoad { src, .. } | Self::Submit { src, .. } => {
                 *src = f(src, UseType::Read);
             }
             Self::Phi { .. } => {
                 // don't rename uses of phi nodes
-            },
+            }
             Self::InAnnotation(_, annots) | Self::OutAnnotation(_, annots) => {
                 for (name, _) in annots {
                     *name = f(name, UseType::Read);
                 }
             }
-            Self::DeviceCopy { src, dest, encoder, ..} => {
+            Self::DeviceCopy {
+                src, dest, encoder, ..
+            } => {
                 *src = f(src, UseType::Read);
                 *dest = f(dest, UseType::Write);
                 *encoder = f(encoder, UseType::Read);
             }
-            Self::EncodeDo { func, encoder, dests, ..} => {
+            Self::EncodeDo {
+                func,
+                encoder,
+                dests,
+                ..
+            } => {
                 for arg in &mut func.args {
                     *arg = f(arg, UseType::Read);
                 }
@@ -994,39 +1174,56 @@ impl Hir for HirBody {
                 }
                 *encoder = f(encoder, UseType::Read);
             }
-            Self::Sync { srcs, ..} => {
-                match srcs {
-                    FillIn::Initial(src) => {
+            Self::Sync { srcs, .. } => match srcs {
+                FillIn::Initial(src) => {
+                    *src = f(src, UseType::Read);
+                }
+                FillIn::Processed(srcs) => {
+                    for src in srcs {
                         *src = f(src, UseType::Read);
                     }
-                    FillIn::Processed(srcs) => {
-                        for src in srcs {
-                            *src = f(src, UseType::Read);
-                        }
-                    }
+                }
+            },
+            Self::BeginEncoding {
+                buffer, suballocs, ..
+            } => {
+                for buffer in buffer.iter_mut().chain(suballocs.values_mut()) {
+                    *buffer = f(buffer, UseType::Read);
                 }
             }
-            Self::Hole(..)
-            | Self::VarDecl { rhs: None, .. } 
-            | Self::BeginEncoding { .. } => (),
+            Self::Hole(..) | Self::VarDecl { rhs: None, .. } => (),
         }
     }
 }
 
 /// Convert a list of `SchedStmts` to a list of Hirs
 #[allow(clippy::module_name_repetitions)]
-pub fn stmts_to_hir(stmts: Vec<SchedStmt>, ) -> Vec<HirBody> {
+pub fn stmts_to_hir(stmts: Vec<SchedStmt>) -> Vec<HirBody> {
     stmts.into_iter().map(HirBody::new).collect()
 }
 
 /// Get the uses in a `SchedTerm`
+/// The buffer the device variable `var` of a `BeginEncoding` is suballocated
+/// from, or `None` if it's a separate temporary allocation.
+#[must_use]
+pub fn device_var_buffer<'a>(
+    var: &str,
+    buffer: Option<&'a Name>,
+    suballocs: &'a BTreeMap<Name, Name>,
+) -> Option<&'a Name> {
+    // device variables are renamed to `encoder::var` once records are expanded
+    let field = var.split_once("::").map_or(var, |(_, field)| field);
+    suballocs.get(field).or(buffer)
+}
+
 fn term_get_uses(t: &SchedTerm, res: &mut BTreeSet<String>) {
     match t {
         SchedTerm::Var { name, .. } => {
             res.insert(name.clone());
         }
         SchedTerm::Hole(..) | SchedTerm::Lit { .. } => (),
-        SchedTerm::Call(..) | SchedTerm::TimelineOperation { .. } 
+        SchedTerm::Call(..)
+        | SchedTerm::TimelineOperation { .. }
         | SchedTerm::EncodeBegin { .. } => panic!("Unexpected term"),
     }
 }
@@ -1036,7 +1233,8 @@ fn term_rename_uses(t: &mut SchedTerm, f: &mut dyn FnMut(&str) -> String) {
     match t {
         SchedTerm::Var { name, .. } => *name = f(name),
         SchedTerm::Hole(..) | SchedTerm::Lit { .. } => (),
-        SchedTerm::Call(..) | SchedTerm::TimelineOperation { .. } |
-        SchedTerm::EncodeBegin{ ..} => panic!("Unexpected term"),
+        SchedTerm::Call(..)
+        | SchedTerm::TimelineOperation { .. }
+        | SchedTerm::EncodeBegin { .. } => panic!("Unexpected term"),
     }
 }
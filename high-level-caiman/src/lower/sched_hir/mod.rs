@@ -7,11 +7,11 @@ use std::{
     rc::Rc,
 };
 
-use analysis::{bft_transform, deduce_tmln_quots};
+use analysis::{bft_transform, deduce_sptl_quots, deduce_tmln_quots};
 pub use hir::*;
 
 use crate::{
-    error::LocalError,
+    error::{Info, LocalError},
     parse::ast::{DataType, FlaggedType, FullType, IntSize, SchedulingFunc},
     typing::{
         Context, Mutability, SchedInfo, ENCODE_DST_FLAGS, ENCODE_IO_FLAGS, ENCODE_SRC_FLAGS,
@@ -358,6 +358,15 @@ impl<'a> Funclet<'a> {
         &self.block.stmts
     }
 
+    /// Gets the source location of the block, from its first statement through
+    /// its terminator
+    pub fn info(&self) -> Info {
+        Info::new_range(
+            &self.block.get_starting_info(),
+            &self.block.get_final_info(),
+        )
+    }
+
     /// Gets the number of dimensions of the scheduling function.
     /// That is, get the number of template value arguments of the function,
     /// template value arguments are passed first and are always i32. They
@@ -551,6 +560,7 @@ impl Funclets {
 
     /// Creates a new `Funclets` from a scheduling function by performing analyses
     /// and transforming the scheduling func into a canonical CFG of lowered HIR.
+    #[allow(clippy::too_many_lines)]
     pub fn new(
         f: SchedulingFunc,
         specs: &Specs,
@@ -606,6 +616,14 @@ impl Funclets {
         let specs_rc = Rc::new(specs.clone());
 
         if !no_inference {
+            deduce_sptl_quots(
+                &mut hir_inputs,
+                &mut cfg,
+                &ctx.specs[&specs.spatial.0],
+                &data_types,
+                f.info,
+                &specs.spatial.0,
+            )?;
             deduce_tmln_quots(
                 &mut hir_inputs,
                 &mut hir_outputs,
@@ -725,6 +743,21 @@ impl Funclets {
         v
     }
 
+    /// Gets the deduced tag of each variable at the end of the first funclet
+    /// it has a tag in, which is the funclet that defines it
+    pub fn deduced_tags(&self) -> HashMap<String, TripleTag> {
+        let mut funclets = self.funclets();
+        // the final block has the lowest id but comes last in the control flow
+        funclets.sort_by_key(|f| f.id() == cfg::FINAL_BLOCK_ID);
+        let mut tags = HashMap::new();
+        for f in funclets {
+            for (var, tag) in self.type_info.get_out_fact(f.id()).tags() {
+                tags.entry(var.clone()).or_insert_with(|| tag.clone());
+            }
+        }
+        tags
+    }
+
     /// Gets the name of the scheduling funclet for a given block
     fn funclet_name(&self, block_id: usize) -> String {
         if block_id == cfg::START_BLOCK_ID {
@@ -34,7 +34,7 @@ fn cfg_gen() {
                     lhs: vec![("y".to_string(), None)],
                     expr: Some(SchedExpr::Term(SchedTerm::Lit {
                         info: Info::default(),
-                        lit: SchedLiteral::Int(2.to_string()),
+                        lit: SchedLiteral::Int(2.to_string(), None),
                         tag: None,
                     })),
                     is_const: true,
@@ -44,7 +44,7 @@ fn cfg_gen() {
                     lhs: vec![("x".to_string(), None)],
                     expr: Some(SchedExpr::Term(SchedTerm::Lit {
                         info: Info::default(),
-                        lit: SchedLiteral::Int(4.to_string()),
+                        lit: SchedLiteral::Int(4.to_string(), None),
                         tag: None,
                     })),
                     is_const: true,
@@ -55,7 +55,7 @@ fn cfg_gen() {
                 lhs: vec![("x".to_string(), None)],
                 expr: Some(SchedExpr::Term(SchedTerm::Lit {
                     info: Info::default(),
-                    lit: SchedLiteral::Int(3.to_string()),
+                    lit: SchedLiteral::Int(3.to_string(), None),
                     tag: None,
                 })),
                 is_const: true,
@@ -70,7 +70,7 @@ fn cfg_gen() {
             }),
             rhs: SchedExpr::Term(SchedTerm::Lit {
                 info: Info::default(),
-                lit: SchedLiteral::Int(5.to_string()),
+                lit: SchedLiteral::Int(5.to_string(), None),
                 tag: None,
             }),
             lhs_is_ref: false,
@@ -117,7 +117,7 @@ fn if_gen() {
                 }),
                 rhs: SchedExpr::Term(SchedTerm::Lit {
                     info: Info::default(),
-                    lit: SchedLiteral::Int(String::from("1")),
+                    lit: SchedLiteral::Int(String::from("1"), None),
                     tag: None,
                 }),
                 lhs_is_ref: false,
@@ -133,7 +133,7 @@ fn if_gen() {
                     }),
                     rhs: SchedExpr::Term(SchedTerm::Lit {
                         info: Info::default(),
-                        lit: SchedLiteral::Int(String::from("2")),
+                        lit: SchedLiteral::Int(String::from("2"), None),
                         tag: None,
                     }),
                     lhs_is_ref: false,
@@ -187,7 +187,7 @@ fn if_ret() {
                 }),
                 rhs: SchedExpr::Term(SchedTerm::Lit {
                     info: Info::default(),
-                    lit: SchedLiteral::Int(String::from("1")),
+                    lit: SchedLiteral::Int(String::from("1"), None),
                     tag: None,
                 }),
                 lhs_is_ref: false,
@@ -203,7 +203,7 @@ fn if_ret() {
                     }),
                     rhs: SchedExpr::Term(SchedTerm::Lit {
                         info: Info::default(),
-                        lit: SchedLiteral::Int(String::from("2")),
+                        lit: SchedLiteral::Int(String::from("2"), None),
                         tag: None,
                     }),
                     lhs_is_ref: false,
@@ -7,6 +7,8 @@ mod normalize;
 mod parse;
 mod typing;
 
+use caiman::backend::BackendKind;
+use caiman::diagnostics::MessageFormat;
 use clap::Parser;
 use lower::lower;
 
@@ -23,7 +25,7 @@ struct Arguments {
     parse: bool,
 
     /// When this flag is enabled, the compiler will only lower the AST and
-    /// print the lowered caiman assembly AST.
+    /// print the lowered program as caiman assembly.
     #[clap(long)]
     lower: bool,
 
@@ -61,19 +63,39 @@ struct Arguments {
     /// variables.
     #[clap(long)]
     no_inference: bool,
+
+    /// Where the generated code runs work placed on the GPU, either `wgpu` or
+    /// `cpu`.
+    #[clap(long, default_value = "wgpu", takes_value = true)]
+    backend: BackendKind,
+
+    /// How errors are printed, either `human` or `json` (one diagnostic per
+    /// line).
+    #[clap(long, default_value = "human", takes_value = true)]
+    message_format: MessageFormat,
 }
 
-fn main() -> Result<(), error::Error> {
+fn main() -> std::process::ExitCode {
     let args = Arguments::parse();
-    compile_new_lang(args)
+    let message_format = args.message_format;
+    match compile_new_lang(args) {
+        Ok(()) => std::process::ExitCode::SUCCESS,
+        Err(e) if message_format == MessageFormat::Json => {
+            eprintln!("{}", e.to_diagnostic().to_json());
+            std::process::ExitCode::FAILURE
+        }
+        Err(e) => {
+            // same as returning the error from `main`, which the expected
+            // outputs of the frontend tests rely on
+            eprintln!("Error: {e:?}");
+            std::process::ExitCode::FAILURE
+        }
+    }
 }
 
 fn compile_new_lang(args: Arguments) -> Result<(), error::Error> {
     let ast = match args.filename.as_str() {
-        "-" => parse::parse_read(std::io::stdin(), "stdin").map_err(|e| error::Error {
-            error: e,
-            filename: "stdin".to_string(),
-        })?,
+        "-" => parse::parse_read(std::io::stdin(), "stdin")?,
         filename => parse::parse_file(filename)?,
     };
     let filename = if args.filename == "-" {
@@ -120,10 +142,18 @@ fn compile_new_lang(args: Arguments) -> Result<(), error::Error> {
     })?;
     if args.lower {
         if !args.quiet {
-            println!("{lowered:#?}");
+            let printed = caiman::assembly::printer::print_program(&lowered)
+                .map_err(|e| error::Error::caiman(e.into(), filename.clone()))?;
+            print!("{printed}");
         }
         return Ok(());
     }
-    caiman::explicate_and_execute(args.output, lowered, args.explicate_only);
-    Ok(())
+    caiman::explicate_and_execute(
+        args.output,
+        lowered,
+        &filename,
+        args.explicate_only,
+        args.backend.backend(),
+    )
+    .map_err(|e| error::Error::caiman(e, filename))
 }
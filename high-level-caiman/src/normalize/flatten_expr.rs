@@ -335,6 +335,35 @@ fn flatten_spec_term(t: SpecTerm, temp_num: usize) -> (Vec<SpecStmt>, usize, Nes
                 }),
             )
         }
+        SpecTerm::Lit {
+            info,
+            lit: SpecLiteral::Array(elems),
+        } => {
+            let (mut instrs, temp_num, new_elems) = flatten_call_args(
+                elems,
+                &build_spec_var_factory(info),
+                &build_spec_decl_factory(info),
+                temp_num,
+                &flatten_spec_term,
+            );
+            let temp_name = format!("_f{temp_num}");
+            instrs.push(SpecStmt::Assign {
+                lhs: vec![(temp_name.clone(), None)],
+                rhs: NestedExpr::Term(SpecTerm::Lit {
+                    info,
+                    lit: SpecLiteral::Array(new_elems),
+                }),
+                info,
+            });
+            (
+                instrs,
+                temp_num + 1,
+                NestedExpr::Term(SpecTerm::Var {
+                    info,
+                    name: temp_name,
+                }),
+            )
+        }
         SpecTerm::Lit { info, lit } => {
             let temp_name = format!("_f{temp_num}");
             (
@@ -354,7 +383,8 @@ fn flatten_spec_term(t: SpecTerm, temp_num: usize) -> (Vec<SpecStmt>, usize, Nes
 }
 
 /// Flattens the spec term so that all children are not nested expressions.
-/// Currently, the only spec term that this does useful work for is a call.
+/// Currently, the only spec terms that this does useful work for are calls
+/// and array literals.
 /// # Arguments
 /// * `term` - The spec term to flatten the children of
 /// * `temp_num` - The current number of temporary variables
@@ -410,6 +440,26 @@ fn flatten_spec_term_children(
                 }),
             )
         }
+        NestedExpr::Term(SpecTerm::Lit {
+            info,
+            lit: SpecLiteral::Array(elems),
+        }) => {
+            let (instrs, temp_num, new_elems) = flatten_call_args(
+                elems,
+                &build_spec_var_factory(info),
+                &build_spec_decl_factory(info),
+                temp_num,
+                &flatten_spec_term,
+            );
+            (
+                instrs,
+                temp_num,
+                NestedExpr::Term(SpecTerm::Lit {
+                    info,
+                    lit: SpecLiteral::Array(new_elems),
+                }),
+            )
+        }
         _ => (vec![], temp_num, term),
     }
 }
@@ -449,6 +499,9 @@ pub fn flatten_spec(stmts: Vec<SpecStmt>) -> Vec<SpecStmt> {
                 instrs.push(SpecStmt::Returns(info, new_ret));
                 res.extend(instrs);
             }
+            SpecStmt::Loop { info, .. } => {
+                unreachable!("{info}: Loops should be desugared before flattening")
+            }
         }
     }
     res
@@ -521,6 +574,39 @@ fn flatten_sched_term(
                 }),
             )
         }
+        SchedTerm::Lit {
+            info,
+            lit: SchedLiteral::Array(elems),
+            tag,
+        } => {
+            let (mut instrs, temp_num, new_elems) = flatten_call_args(
+                elems,
+                &build_sched_var_factory(info),
+                &build_sched_decl_factory(info, true),
+                temp_num,
+                &flatten_sched_term,
+            );
+            let temp_name = format!("_f{temp_num}");
+            instrs.push(SchedStmt::Decl {
+                lhs: vec![(temp_name.clone(), None)],
+                expr: Some(NestedExpr::Term(SchedTerm::Lit {
+                    info,
+                    lit: SchedLiteral::Array(new_elems),
+                    tag: tag.clone(),
+                })),
+                info,
+                is_const: true,
+            });
+            (
+                instrs,
+                temp_num + 1,
+                NestedExpr::Term(SchedTerm::Var {
+                    info,
+                    name: temp_name,
+                    tag,
+                }),
+            )
+        }
         SchedTerm::Lit { info, lit, tag } => {
             let temp_name = format!("_f{temp_num}");
             (
@@ -545,6 +631,8 @@ fn flatten_sched_term(
         SchedTerm::EncodeBegin {
             info,
             device,
+            buffer,
+            suballocs,
             tag,
             defs,
         } => {
@@ -555,6 +643,8 @@ fn flatten_sched_term(
                     expr: Some(NestedExpr::Term(SchedTerm::EncodeBegin {
                         info,
                         device,
+                        buffer,
+                        suballocs,
                         tag: tag.clone(),
                         defs,
                     })),
@@ -615,9 +705,9 @@ fn spec_to_sched(x: SpecExpr) -> SchedExpr {
             SpecTerm::Lit { info, lit } => SchedExpr::Term(SchedTerm::Lit {
                 info,
                 lit: match lit {
-                    SpecLiteral::Int(i) => SchedLiteral::Int(i),
+                    SpecLiteral::Int(i, size) => SchedLiteral::Int(i, size),
                     SpecLiteral::Bool(b) => SchedLiteral::Bool(b),
-                    SpecLiteral::Float(f) => SchedLiteral::Float(f),
+                    SpecLiteral::Float(f, size) => SchedLiteral::Float(f, size),
                     SpecLiteral::Tuple(exprs) => {
                         SchedLiteral::Tuple(exprs.into_iter().map(spec_to_sched).collect())
                     }
@@ -756,6 +846,28 @@ fn flatten_sched_term_children(
                 )),
             )
         }
+        NestedExpr::Term(SchedTerm::Lit {
+            info,
+            lit: SchedLiteral::Array(elems),
+            tag,
+        }) => {
+            let (instrs, temp_num, new_elems) = flatten_call_args(
+                elems,
+                &build_sched_var_factory(info),
+                &build_sched_decl_factory(info, true),
+                temp_num,
+                &flatten_sched_term,
+            );
+            (
+                instrs,
+                temp_num,
+                NestedExpr::Term(SchedTerm::Lit {
+                    info,
+                    lit: SchedLiteral::Array(new_elems),
+                    tag,
+                }),
+            )
+        }
         _ => (vec![], temp_num, term),
     }
 }
@@ -962,6 +1074,9 @@ fn flatten_sched_rec(stmts: Vec<SchedStmt>, mut temp_num: usize) -> (Vec<SchedSt
                     tag,
                 });
             }
+            SchedStmt::Loop { info, .. } => {
+                unreachable!("{info}: Loops should be desugared before flattening")
+            }
         }
     }
     (res, temp_num)
@@ -0,0 +1,1325 @@
+//! Desugars `while` and `for` loops into tail-recursive functions.
+//!
+//! A loop in a value specification becomes a new value funclet that takes the
+//! variables carried by the loop followed by the variables of the enclosing
+//! funclet that the loop uses (its invariants). The funclet calls itself with
+//! the next value of each carried variable and selects between the result of
+//! the recursive call and the current values based on the guard.
+//! For example:
+//!
+//! ```text
+//! val sum(n: i64) -> i64 {
+//!     acc :- for (acc: i64 = 0) i in 0..n {
+//!         returns acc + i
+//!     }
+//!     returns acc
+//! }
+//! ```
+//! becomes:
+//!
+//! ```text
+//! val _sum_acc(i: i64, acc: i64, n: i64) -> i64 {
+//!     _next0 :- i + 1
+//!     _next1 :- acc + i
+//!     _guard :- i < n
+//!     _rec0 :- _sum_acc(_next0, _next1, n)
+//!     returns _rec0 if _guard else acc
+//! }
+//!
+//! val sum(n: i64) -> i64 {
+//!     acc :- _sum_acc(0, 0, n)
+//!     returns acc
+//! }
+//! ```
+//!
+//! The generated funclet is named after the enclosing funclet and the variables
+//! the loop binds, so a loop nested in the loop above binding `row` would
+//! become `_sum_acc_row`.
+//!
+//! A loop in a schedule becomes a new scheduling function that implements the
+//! funclet generated for the matching loop of the schedule's value specification,
+//! which is the loop that binds the same variables. For example:
+//!
+//! ```text
+//! fn sum_impl(n: i64) -> i64 impls sum, time, space {
+//!     let acc = for (acc: i64 = 0) i in 0..n {
+//!         acc + i
+//!     };
+//!     acc
+//! }
+//! ```
+//! becomes:
+//!
+//! ```text
+//! fn _sum_impl_acc(i: i64, acc: i64, n: i64) -> i64 impls _sum_acc, time, space {
+//!     if i < n {
+//!         let _next0 = i + 1;
+//!         let _next1 = acc + i;
+//!         let _rec0 = _sum_impl_acc(_next0, _next1, n);
+//!         _rec0
+//!     } else {
+//!         acc
+//!     }
+//! }
+//!
+//! fn sum_impl(n: i64) -> i64 impls sum, time, space {
+//!     let _arg0_2 = n;
+//!     let acc = _sum_impl_acc(0, 0, _arg0_2);
+//!     acc
+//! }
+//! ```
+//!
+//! Next values which are already variables are passed to the recursive call
+//! directly, and the variables passed to the call that starts a schedule loop
+//! are copied first since the enclosing function may keep using them.
+//!
+//! Since the invariants of a scheduling function must line up with those of
+//! its specification, a schedule loop may only use the variables of the
+//! enclosing function that the matching specification loop uses.
+//!
+//! Each generated scheduling function lowers like any other, into blocks whose
+//! tail edges are a `ScheduleSelect` on the guard and a `ScheduleCall` back to
+//! the loop. Unlike other recursion, the recursive call of a loop is not broken
+//! by a yield: the backend compiles it as a call to the loop's function, with
+//! whatever follows the call pushed onto the join stack, so the whole loop runs
+//! within one call of the pipeline.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use crate::{
+    error::{type_error, HasInfo, Info, LocalError},
+    parse::ast::{
+        Binop, ClassMembers, DataType, FullType, LoopHeader, LoopVar, NestedExpr, Program,
+        SchedExpr, SchedFuncCall, SchedLiteral, SchedStmt, SchedTerm, SpecExpr, SpecFunclet,
+        SpecLiteral, SpecStmt, SpecTerm, TemplateArgs, TopLevel,
+    },
+};
+
+/// Terms of a language that loops can be written in
+trait LoopTerm: HasInfo + Sized {
+    /// Constructs a variable
+    fn var(info: Info, name: String) -> Self;
+    /// Constructs the integer literal `1`
+    fn one(info: Info) -> Self;
+    /// Constructs a tuple
+    fn tuple(info: Info, elems: Vec<NestedExpr<Self>>) -> Self;
+    /// Constructs a call to the function named `target`
+    fn call(info: Info, target: String, args: Vec<NestedExpr<Self>>) -> Self;
+    /// Returns the elements of `e` if it is a tuple literal
+    fn as_tuple(e: NestedExpr<Self>) -> Result<Vec<NestedExpr<Self>>, NestedExpr<Self>>;
+    /// Returns true if this term is a variable
+    fn is_var(&self) -> bool;
+    /// Adds the variables used by this term to `uses`
+    fn uses(&self, uses: &mut BTreeSet<String>);
+}
+
+impl LoopTerm for SpecTerm {
+    fn var(info: Info, name: String) -> Self {
+        Self::Var { info, name }
+    }
+
+    fn one(info: Info) -> Self {
+        Self::Lit {
+            info,
+            lit: SpecLiteral::Int(String::from("1"), None),
+        }
+    }
+
+    fn tuple(info: Info, elems: Vec<SpecExpr>) -> Self {
+        Self::Lit {
+            info,
+            lit: SpecLiteral::Tuple(elems),
+        }
+    }
+
+    fn call(info: Info, target: String, args: Vec<SpecExpr>) -> Self {
+        Self::Call {
+            info,
+            function: Box::new(SpecExpr::Term(Self::var(info, target))),
+            args,
+            templates: None,
+        }
+    }
+
+    fn as_tuple(e: SpecExpr) -> Result<Vec<SpecExpr>, SpecExpr> {
+        match e {
+            SpecExpr::Term(Self::Lit {
+                lit: SpecLiteral::Tuple(elems),
+                ..
+            }) => Ok(elems),
+            e => Err(e),
+        }
+    }
+
+    fn is_var(&self) -> bool {
+        matches!(self, Self::Var { .. })
+    }
+
+    fn uses(&self, uses: &mut BTreeSet<String>) {
+        match self {
+            Self::Var { name, .. } => {
+                uses.insert(name.clone());
+            }
+            Self::Lit {
+                lit: SpecLiteral::Tuple(elems) | SpecLiteral::Array(elems),
+                ..
+            } => {
+                for e in elems {
+                    expr_uses(e, uses);
+                }
+            }
+            Self::Lit { .. } => (),
+            Self::Call {
+                args, templates, ..
+            } => {
+                for e in args {
+                    expr_uses(e, uses);
+                }
+                template_uses(templates.as_ref(), uses);
+            }
+        }
+    }
+}
+
+impl LoopTerm for SchedTerm {
+    fn var(info: Info, name: String) -> Self {
+        Self::Var {
+            info,
+            name,
+            tag: None,
+        }
+    }
+
+    fn one(info: Info) -> Self {
+        Self::Lit {
+            info,
+            lit: SchedLiteral::Int(String::from("1"), None),
+            tag: None,
+        }
+    }
+
+    fn tuple(info: Info, elems: Vec<SchedExpr>) -> Self {
+        Self::Lit {
+            info,
+            lit: SchedLiteral::Tuple(elems),
+            tag: None,
+        }
+    }
+
+    fn call(info: Info, target: String, args: Vec<SchedExpr>) -> Self {
+        Self::Call(
+            info,
+            SchedFuncCall {
+                info,
+                target: Box::new(SchedExpr::Term(Self::var(info, target))),
+                templates: None,
+                args,
+                tag: None,
+                yield_call: false,
+            },
+        )
+    }
+
+    fn as_tuple(e: SchedExpr) -> Result<Vec<SchedExpr>, SchedExpr> {
+        match e {
+            SchedExpr::Term(Self::Lit {
+                lit: SchedLiteral::Tuple(elems),
+                ..
+            }) => Ok(elems),
+            e => Err(e),
+        }
+    }
+
+    fn is_var(&self) -> bool {
+        matches!(self, Self::Var { .. })
+    }
+
+    fn uses(&self, uses: &mut BTreeSet<String>) {
+        match self {
+            Self::Var { name, .. } => {
+                uses.insert(name.clone());
+            }
+            Self::Lit {
+                lit: SchedLiteral::Tuple(elems) | SchedLiteral::Array(elems),
+                ..
+            } => {
+                for e in elems {
+                    expr_uses(e, uses);
+                }
+            }
+            Self::Call(
+                _,
+                SchedFuncCall {
+                    args, templates, ..
+                },
+            ) => {
+                for e in args {
+                    expr_uses(e, uses);
+                }
+                template_uses(templates.as_ref(), uses);
+            }
+            Self::TimelineOperation { arg, .. } => expr_uses(arg, uses),
+            Self::EncodeBegin {
+                buffer, suballocs, ..
+            } => {
+                uses.extend(buffer.iter().cloned());
+                uses.extend(suballocs.iter().map(|(_, buffer)| buffer.clone()));
+            }
+            Self::Lit { .. } | Self::Hole(_) => (),
+        }
+    }
+}
+
+/// Adds the variables used by `e` to `uses`. Field names of records are not
+/// considered uses.
+fn expr_uses<T: LoopTerm>(e: &NestedExpr<T>, uses: &mut BTreeSet<String>) {
+    match e {
+        NestedExpr::Binop {
+            op: Binop::Dot,
+            lhs,
+            ..
+        } => expr_uses(lhs, uses),
+        NestedExpr::Binop { lhs, rhs, .. } => {
+            expr_uses(lhs, uses);
+            expr_uses(rhs, uses);
+        }
+        NestedExpr::Uop { expr, .. } => expr_uses(expr, uses),
+        NestedExpr::Conditional {
+            if_true,
+            guard,
+            if_false,
+            ..
+        } => {
+            expr_uses(if_true, uses);
+            expr_uses(guard, uses);
+            expr_uses(if_false, uses);
+        }
+        NestedExpr::Term(t) => t.uses(uses),
+    }
+}
+
+/// Adds the variables used by value template arguments to `uses`
+fn template_uses(templates: Option<&TemplateArgs>, uses: &mut BTreeSet<String>) {
+    if let Some(TemplateArgs::Vals(vals)) = templates {
+        for v in vals {
+            expr_uses(v, uses);
+        }
+    }
+}
+
+fn var<T: LoopTerm>(info: Info, name: String) -> NestedExpr<T> {
+    NestedExpr::Term(T::var(info, name))
+}
+
+fn is_var<T: LoopTerm>(e: &NestedExpr<T>) -> bool {
+    matches!(e, NestedExpr::Term(t) if t.is_var())
+}
+
+/// Constructs a single expression from a list of returned expressions,
+/// making a tuple if there is more than one
+fn returned<T: LoopTerm>(info: Info, mut elems: Vec<NestedExpr<T>>) -> NestedExpr<T> {
+    if elems.len() == 1 {
+        elems.pop().unwrap()
+    } else {
+        NestedExpr::Term(T::tuple(info, elems))
+    }
+}
+
+/// The name of the temporary holding the next value of the `i`th carried variable
+fn next_name(i: usize) -> String {
+    format!("_next{i}")
+}
+
+/// The name of the temporary holding the `i`th result of the recursive call
+fn rec_name(i: usize) -> String {
+    format!("_rec{i}")
+}
+
+/// Lists of temporaries and the expression assigned to them
+type Temporaries<E> = Vec<(Vec<String>, E)>;
+
+/// A loop with its header expanded so that the index of a `for` loop
+/// is the first carried variable.
+struct Loop<T, E> {
+    info: Info,
+    /// The name, type, and initial value of each carried variable
+    carried: Vec<(String, T, E)>,
+    /// Whether the first carried variable is the index of a `for` loop, which
+    /// is not a result of the loop
+    has_index: bool,
+    /// The expression for the next value of the index of a `for` loop
+    next_index: Option<E>,
+}
+
+impl<T, U: LoopTerm> Loop<T, NestedExpr<U>> {
+    /// Expands the header of a loop, converting the type of a `for` loop's index
+    /// with `index_type`. Returns the loop and its guard.
+    fn new(
+        info: Info,
+        state: Vec<LoopVar<T, NestedExpr<U>>>,
+        header: LoopHeader<NestedExpr<U>>,
+        index_type: impl Fn(DataType) -> T,
+    ) -> (Self, NestedExpr<U>) {
+        let mut carried = vec![];
+        let (next_index, guard) = match header {
+            LoopHeader::While(guard) => (None, guard),
+            LoopHeader::For {
+                name,
+                typ,
+                start,
+                end,
+            } => {
+                let index = || Box::new(var(info, name.clone()));
+                let next = NestedExpr::Binop {
+                    info,
+                    op: Binop::Add,
+                    lhs: index(),
+                    rhs: Box::new(NestedExpr::Term(U::one(info))),
+                };
+                let guard = NestedExpr::Binop {
+                    info,
+                    op: Binop::Lt,
+                    lhs: index(),
+                    rhs: Box::new(end),
+                };
+                carried.push((name.clone(), index_type(typ), start));
+                (Some(next), guard)
+            }
+        };
+        carried.extend(state.into_iter().map(|v| (v.name, v.typ, v.init)));
+        (
+            Self {
+                info,
+                carried,
+                has_index: next_index.is_some(),
+                next_index,
+            },
+            guard,
+        )
+    }
+
+    /// Gets the number of carried variables that are results of the loop
+    fn num_results(&self) -> usize {
+        self.carried.len() - usize::from(self.has_index)
+    }
+
+    /// Gets the carried variables that are results of the loop
+    fn results(&self) -> &[(String, T, NestedExpr<U>)] {
+        &self.carried[self.carried.len() - self.num_results()..]
+    }
+
+    /// Checks that `num_dests` variables are bound to the results of the loop
+    fn check_dests(&self, num_dests: usize) -> Result<(), LocalError> {
+        if self.num_results() == 0 {
+            return Err(type_error(
+                self.info,
+                "A loop must carry at least one variable",
+            ));
+        }
+        if num_dests != self.num_results() {
+            return Err(type_error(
+                self.info,
+                &format!(
+                    "Loop produces {} values but {num_dests} are bound",
+                    self.num_results()
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Gets the index of the first carried variable that is a result
+    fn first_result(&self) -> usize {
+        self.carried.len() - self.num_results()
+    }
+
+    /// Splits the final value of a loop body into a list of the temporaries
+    /// holding the next value of each carried variable and the expression
+    /// assigned to them, along with the arguments of the recursive call.
+    /// Temporaries are assigned one at a time when the final value is a tuple
+    /// and all at once otherwise. Next values which are already variables are
+    /// passed to the recursive call directly.
+    fn next_values(
+        &mut self,
+        e: NestedExpr<U>,
+    ) -> (Temporaries<NestedExpr<U>>, Vec<NestedExpr<U>>) {
+        let next_index = self.next_index.take();
+        let mut next: Vec<_> = next_index
+            .map(|e| (vec![next_name(0)], e))
+            .into_iter()
+            .collect();
+        let mut args: Vec<_> = self.next_args().take(usize::from(self.has_index)).collect();
+        let first = self.first_result();
+        match U::as_tuple(e) {
+            Ok(elems) if elems.len() == self.num_results() && elems.len() > 1 => {
+                for (i, e) in elems.into_iter().enumerate() {
+                    if is_var(&e) {
+                        args.push(e);
+                    } else {
+                        args.push(var(self.info, next_name(first + i)));
+                        next.push((vec![next_name(first + i)], e));
+                    }
+                }
+            }
+            Err(e) if self.num_results() == 1 && is_var(&e) => args.push(e),
+            e => {
+                let e = match e {
+                    Ok(elems) => NestedExpr::Term(U::tuple(self.info, elems)),
+                    Err(e) => e,
+                };
+                args.extend(self.next_args().skip(first));
+                next.push(((first..self.carried.len()).map(next_name).collect(), e));
+            }
+        }
+        (next, args)
+    }
+
+    /// Gets the temporaries holding the next value of each carried variable
+    fn next_args(&self) -> impl Iterator<Item = NestedExpr<U>> + '_ {
+        (0..self.carried.len()).map(|i| var(self.info, next_name(i)))
+    }
+
+    /// Constructs the call that starts the loop from the enclosing function
+    fn initial_call(self, name: String, invariants: &[String]) -> NestedExpr<U> {
+        let info = self.info;
+        let args = self
+            .carried
+            .into_iter()
+            .map(|(_, _, init)| init)
+            .chain(invariants.iter().map(|v| var(info, v.clone())))
+            .collect();
+        NestedExpr::Term(U::call(info, name, args))
+    }
+
+    /// Constructs the recursive call to the next iteration of the loop given
+    /// the next value of each carried variable
+    fn recursive_call(
+        &self,
+        name: String,
+        next: Vec<NestedExpr<U>>,
+        invariants: &[String],
+    ) -> NestedExpr<U> {
+        let args = next
+            .into_iter()
+            .chain(invariants.iter().map(|v| var(self.info, v.clone())))
+            .collect();
+        NestedExpr::Term(U::call(self.info, name, args))
+    }
+}
+
+/// The funclet generated for a loop in a value specification
+struct SpecLoop {
+    name: String,
+    /// The funclet the loop is written in
+    parent: String,
+    /// The variables the loop binds
+    dests: Vec<String>,
+    num_carried: usize,
+    /// The variables of the enclosing funclet used by the loop, which are
+    /// passed after the carried variables
+    invariants: Vec<(String, DataType)>,
+}
+
+/// The loops of a value specification
+struct SpecLoops {
+    /// The name of the funclet the loops being desugared are written in
+    funclet: String,
+    /// The loops, including nested ones
+    loops: Vec<SpecLoop>,
+    generated: Vec<TopLevel>,
+}
+
+/// The name of the funclet generated for a loop of `parent` binding `dests`.
+/// Top-level funclets are prefixed with an underscore, generated ones already are.
+fn loop_name<'a>(parent: &str, dests: impl IntoIterator<Item = &'a String>) -> String {
+    let dests: Vec<_> = dests.into_iter().map(String::as_str).collect();
+    if parent.starts_with('_') {
+        format!("{parent}_{}", dests.join("_"))
+    } else {
+        format!("_{parent}_{}", dests.join("_"))
+    }
+}
+
+/// Gets the variables used but not defined by a list of spec statements
+fn spec_free_vars(stmts: &[SpecStmt]) -> BTreeSet<String> {
+    let mut uses = BTreeSet::new();
+    let mut defs = HashSet::new();
+    for s in stmts {
+        match s {
+            SpecStmt::Assign { lhs, rhs, .. } => {
+                expr_uses(rhs, &mut uses);
+                defs.extend(lhs.iter().map(|(name, _)| name.clone()));
+            }
+            SpecStmt::Returns(_, e) => expr_uses(e, &mut uses),
+            SpecStmt::Loop {
+                lhs,
+                state,
+                header,
+                body,
+                ..
+            } => {
+                let mut inner = spec_free_vars(body);
+                let (index, inner_exprs) = header_parts(header);
+                for e in inner_exprs {
+                    expr_uses(e, &mut inner);
+                }
+                for v in state {
+                    inner.remove(&v.name);
+                    expr_uses(&v.init, &mut uses);
+                }
+                if let Some((index, start)) = index {
+                    inner.remove(index);
+                    expr_uses(start, &mut uses);
+                }
+                uses.extend(inner);
+                defs.extend(lhs.iter().map(|(name, _)| name.clone()));
+            }
+        }
+    }
+    uses.retain(|u| !defs.contains(u));
+    uses
+}
+
+/// Gets the index and initial value of the index of a loop if it is a `for`
+/// loop, along with the expressions of the header evaluated on each iteration
+fn header_parts<E>(header: &LoopHeader<E>) -> (Option<(&String, &E)>, Vec<&E>) {
+    match header {
+        LoopHeader::While(guard) => (None, vec![guard]),
+        LoopHeader::For {
+            name, start, end, ..
+        } => (Some((name, start)), vec![end]),
+    }
+}
+
+/// Desugars the loops of a list of spec statements
+/// # Arguments
+/// * `stmts` - The statements to desugar
+/// * `env` - The variables defined in the enclosing funclet and their types,
+///   if known
+/// * `loops` - The loops of the value specification being desugared
+fn desugar_spec_stmts(
+    stmts: Vec<SpecStmt>,
+    env: &HashMap<String, Option<DataType>>,
+    loops: &mut SpecLoops,
+) -> Result<Vec<SpecStmt>, LocalError> {
+    let mut env = env.clone();
+    for s in &stmts {
+        if let SpecStmt::Assign { lhs, .. } | SpecStmt::Loop { lhs, .. } = s {
+            env.extend(lhs.iter().cloned());
+        }
+    }
+    let mut res = vec![];
+    for s in stmts {
+        if let SpecStmt::Loop {
+            info,
+            lhs,
+            state,
+            header,
+            body,
+        } = s
+        {
+            let (lp, guard) = Loop::new(info, state, header, |t| t);
+            lp.check_dests(lhs.len())?;
+            let dests: Vec<_> = lhs.iter().map(|(name, _)| name.clone()).collect();
+            let rhs = desugar_spec_loop(lp, guard, body, dests, &env, loops)?;
+            res.push(SpecStmt::Assign { info, lhs, rhs });
+        } else {
+            res.push(s);
+        }
+    }
+    Ok(res)
+}
+
+/// Generates the funclet for a loop in a value specification and returns the
+/// call that starts the loop.
+fn desugar_spec_loop(
+    mut lp: Loop<DataType, SpecExpr>,
+    guard: SpecExpr,
+    mut body: Vec<SpecStmt>,
+    dests: Vec<String>,
+    env: &HashMap<String, Option<DataType>>,
+    loops: &mut SpecLoops,
+) -> Result<SpecExpr, LocalError> {
+    let info = lp.info;
+    let name = loop_name(&loops.funclet, &dests);
+    if loops.loops.iter().any(|l| l.name == name) {
+        return Err(type_error(
+            info,
+            &format!(
+                "Another loop in {} also binds {}, so a schedule could not tell them apart",
+                loops.funclet,
+                dests.join(", ")
+            ),
+        ));
+    }
+    let mut free = spec_free_vars(&body);
+    expr_uses(&guard, &mut free);
+    if let Some(e) = &lp.next_index {
+        expr_uses(e, &mut free);
+    }
+    for (v, _, _) in &lp.carried {
+        free.remove(v);
+    }
+    let mut invariants = vec![];
+    for v in free {
+        match env.get(&v) {
+            Some(Some(t)) => invariants.push((v, t.clone())),
+            Some(None) => {
+                return Err(type_error(
+                    info,
+                    &format!("{v} must have a type annotation to be used in a loop"),
+                ))
+            }
+            // not a local variable
+            None => (),
+        }
+    }
+    loops.loops.push(SpecLoop {
+        name: name.clone(),
+        parent: loops.funclet.clone(),
+        dests,
+        num_carried: lp.carried.len(),
+        invariants: invariants.clone(),
+    });
+
+    let Some(SpecStmt::Returns(ret_info, ret)) = body.pop() else {
+        return Err(type_error(
+            info,
+            "Loop body must end by returning the next value of each loop variable",
+        ));
+    };
+    let input: Vec<_> = lp
+        .carried
+        .iter()
+        .map(|(v, t, _)| (v.clone(), t.clone()))
+        .chain(invariants.iter().cloned())
+        .collect();
+    let body_env = input
+        .iter()
+        .map(|(v, t)| (v.clone(), Some(t.clone())))
+        .collect();
+    let parent = std::mem::replace(&mut loops.funclet, name.clone());
+    let statements = desugar_spec_stmts(body, &body_env, loops);
+    loops.funclet = parent;
+    let mut statements = statements?;
+    let (next, next_args) = lp.next_values(ret);
+    for (lhs, rhs) in next {
+        statements.push(SpecStmt::Assign {
+            info: ret_info,
+            lhs: lhs.into_iter().map(|v| (v, None)).collect(),
+            rhs,
+        });
+    }
+    statements.push(SpecStmt::Assign {
+        info,
+        lhs: vec![(String::from("_guard"), None)],
+        rhs: guard,
+    });
+    let invariant_names: Vec<_> = invariants.into_iter().map(|(v, _)| v).collect();
+    statements.push(SpecStmt::Assign {
+        info,
+        lhs: (0..lp.num_results()).map(|i| (rec_name(i), None)).collect(),
+        rhs: lp.recursive_call(name.clone(), next_args, &invariant_names),
+    });
+    let rets = lp
+        .results()
+        .iter()
+        .enumerate()
+        .map(|(i, (v, _, _))| SpecExpr::Conditional {
+            info,
+            if_true: Box::new(var(info, rec_name(i))),
+            guard: Box::new(var(info, String::from("_guard"))),
+            if_false: Box::new(var(info, v.clone())),
+        })
+        .collect();
+    statements.push(SpecStmt::Returns(info, returned(info, rets)));
+    let output = lp
+        .results()
+        .iter()
+        .map(|(_, t, _)| (None, t.clone()))
+        .collect();
+    loops.generated.push(TopLevel::FunctionClass {
+        info,
+        name: name.clone(),
+        members: vec![ClassMembers::ValueFunclet(SpecFunclet {
+            info,
+            name: name.clone(),
+            input,
+            output,
+            statements,
+        })],
+    });
+    Ok(lp.initial_call(name, &invariant_names))
+}
+
+/// The loops of a scheduling function
+struct SchedLoops<'a> {
+    /// The name of the function the loops being desugared are written in
+    func: String,
+    /// The specs implemented by the scheduling function
+    specs: &'a [String],
+    /// The value specification implemented by the scheduling function
+    value_spec: Option<&'a String>,
+    /// The value funclet implemented by the function the loops being desugared
+    /// are written in
+    spec: String,
+    /// The loops of the value specification
+    spec_loops: &'a [SpecLoop],
+    /// The names of the loops of the value specification matched so far
+    matched: Vec<String>,
+    /// The number of loops desugared so far
+    count: usize,
+    generated: Vec<TopLevel>,
+}
+
+impl<'a> SchedLoops<'a> {
+    /// Finds the loop of the value specification binding `dests` in the
+    /// funclet the loops being desugared implement
+    fn find_spec(&self, info: Info, dests: &[String]) -> Result<&'a SpecLoop, LocalError> {
+        let name = loop_name(&self.spec, dests);
+        self.spec_loops
+            .iter()
+            .find(|l| l.name == name)
+            .ok_or_else(|| {
+                type_error(
+                    info,
+                    &format!(
+                        "Loop binding {} has no matching loop in the value specification {}",
+                        dests.join(", "),
+                        self.spec
+                    ),
+                )
+            })
+    }
+
+    /// Gets an unused name for the function generated for a loop binding `dests`.
+    /// A schedule may implement the same loop more than once, once in each
+    /// branch of an `if` for example.
+    fn func_name(&self, dests: &[String]) -> String {
+        let name = loop_name(&self.func, dests);
+        let taken = |name: &String| {
+            self.generated
+                .iter()
+                .any(|g| matches!(g, TopLevel::SchedulingFunc { name: other, .. } if other == name))
+        };
+        if taken(&name) {
+            format!("{name}{}", self.count)
+        } else {
+            name
+        }
+    }
+
+    /// Gets the loop of the value specification that a loop in `func` does not
+    /// implement, when `func` implements some but not all of them
+    fn unmatched(&self) -> Option<&'a SpecLoop> {
+        if self.matched.is_empty() {
+            return None;
+        }
+        self.spec_loops.iter().find(|l| {
+            !self.matched.contains(&l.name)
+                && (Some(&l.parent) == self.value_spec || self.matched.contains(&l.parent))
+        })
+    }
+}
+
+/// Adds the variables defined by a list of schedule statements to `defs`.
+/// Does not look inside loop bodies.
+fn sched_defs<'a>(stmts: impl IntoIterator<Item = &'a SchedStmt>, defs: &mut HashSet<String>) {
+    for s in stmts {
+        match s {
+            SchedStmt::Decl { lhs: dests, .. }
+            | SchedStmt::Loop { dests, .. }
+            | SchedStmt::Seq { dests, .. } => {
+                defs.extend(dests.iter().map(|(name, _)| name.clone()));
+                if let SchedStmt::Seq { block, .. } = s {
+                    sched_defs(std::iter::once(&**block), defs);
+                }
+            }
+            SchedStmt::Encode { stmt, .. } => {
+                defs.extend(stmt.lhs.iter().map(|(name, _)| name.clone()));
+            }
+            SchedStmt::If {
+                true_block,
+                false_block,
+                ..
+            } => {
+                sched_defs(true_block, defs);
+                sched_defs(false_block, defs);
+            }
+            SchedStmt::Block(_, stmts) => sched_defs(stmts, defs),
+            SchedStmt::Assign { .. }
+            | SchedStmt::InEdgeAnnotation { .. }
+            | SchedStmt::OutEdgeAnnotation { .. }
+            | SchedStmt::Return(..)
+            | SchedStmt::Hole(_)
+            | SchedStmt::Call(..) => (),
+        }
+    }
+}
+
+/// Adds the variables used by a list of schedule statements to `uses` and the
+/// variables assigned to `assigns`. Variables used by a loop body but carried
+/// by the loop are not included.
+fn sched_uses<'a>(
+    stmts: impl IntoIterator<Item = &'a SchedStmt>,
+    uses: &mut BTreeSet<String>,
+    assigns: &mut BTreeSet<String>,
+) {
+    for s in stmts {
+        match s {
+            SchedStmt::Decl { expr, .. } => {
+                if let Some(e) = expr {
+                    expr_uses(e, uses);
+                }
+            }
+            SchedStmt::Assign { lhs, rhs, .. } => {
+                expr_uses(rhs, uses);
+                expr_uses(lhs, assigns);
+            }
+            SchedStmt::If {
+                guard,
+                true_block,
+                false_block,
+                ..
+            } => {
+                expr_uses(guard, uses);
+                sched_uses(true_block, uses, assigns);
+                sched_uses(false_block, uses, assigns);
+            }
+            SchedStmt::Block(_, stmts) => sched_uses(stmts, uses, assigns),
+            SchedStmt::Return(_, e) => expr_uses(e, uses),
+            SchedStmt::Call(_, call) => {
+                for e in &call.args {
+                    expr_uses(e, uses);
+                }
+                template_uses(call.templates.as_ref(), uses);
+            }
+            SchedStmt::Seq { block, .. } => sched_uses(std::iter::once(&**block), uses, assigns),
+            SchedStmt::Encode { stmt, encoder, .. } => {
+                expr_uses(&stmt.rhs, uses);
+                uses.insert(encoder.clone());
+            }
+            SchedStmt::Loop {
+                state,
+                header,
+                body,
+                ..
+            } => {
+                let (free, inner_assigns) = sched_loop_free_vars(state, header, body);
+                uses.extend(free);
+                assigns.extend(inner_assigns);
+                for v in state {
+                    expr_uses(&v.init, uses);
+                }
+                if let LoopHeader::For { start, .. } = header {
+                    expr_uses(start, uses);
+                }
+            }
+            SchedStmt::InEdgeAnnotation { .. }
+            | SchedStmt::OutEdgeAnnotation { .. }
+            | SchedStmt::Hole(_) => (),
+        }
+    }
+}
+
+/// Gets the variables used and assigned inside a loop that are defined
+/// outside of it
+fn sched_loop_free_vars<T>(
+    state: &[LoopVar<T, SchedExpr>],
+    header: &LoopHeader<SchedExpr>,
+    body: &[SchedStmt],
+) -> (BTreeSet<String>, BTreeSet<String>) {
+    let mut uses = BTreeSet::new();
+    let mut assigns = BTreeSet::new();
+    sched_uses(body, &mut uses, &mut assigns);
+    let (index, exprs) = header_parts(header);
+    for e in exprs {
+        expr_uses(e, &mut uses);
+    }
+    let mut defs = HashSet::new();
+    sched_defs(body, &mut defs);
+    defs.extend(state.iter().map(|v| v.name.clone()));
+    defs.extend(index.map(|(name, _)| name.clone()));
+    uses.retain(|u| !defs.contains(u));
+    assigns.retain(|u| !defs.contains(u));
+    (uses, assigns)
+}
+
+/// Constructs a scheduling type with no tags
+fn untagged(t: &FullType) -> FullType {
+    FullType {
+        base: t.base.clone(),
+        tags: vec![],
+    }
+}
+
+/// Constructs a scheduling type from a data type
+fn full_type(t: DataType) -> FullType {
+    FullType {
+        base: Some(t.into()),
+        tags: vec![],
+    }
+}
+
+/// Desugars the loops of a list of schedule statements
+/// # Arguments
+/// * `stmts` - The statements to desugar
+/// * `env` - The variables defined in the enclosing function
+/// * `loops` - The loops of the scheduling function being desugared
+fn desugar_sched_stmts(
+    stmts: Vec<SchedStmt>,
+    env: &HashSet<String>,
+    loops: &mut SchedLoops,
+) -> Result<Vec<SchedStmt>, LocalError> {
+    let mut res = vec![];
+    for s in stmts {
+        let s = match s {
+            SchedStmt::Loop {
+                info,
+                dests,
+                state,
+                header,
+                body,
+            } => {
+                let (free, assigns) = sched_loop_free_vars(&state, &header, &body);
+                let (lp, guard) = Loop::new(info, state, header, full_type);
+                lp.check_dests(dests.len())?;
+                let names: Vec<_> = dests.iter().map(|(name, _)| name.clone()).collect();
+                let (copies, call) =
+                    desugar_sched_loop(lp, guard, body, &names, (&free, &assigns), env, loops)?;
+                res.extend(copies);
+                SchedStmt::Decl {
+                    info,
+                    lhs: dests,
+                    is_const: true,
+                    expr: Some(call),
+                }
+            }
+            SchedStmt::If {
+                info,
+                guard,
+                tag,
+                true_block,
+                false_block,
+            } => SchedStmt::If {
+                info,
+                guard,
+                tag,
+                true_block: desugar_sched_stmts(true_block, env, loops)?,
+                false_block: desugar_sched_stmts(false_block, env, loops)?,
+            },
+            SchedStmt::Block(info, stmts) => {
+                SchedStmt::Block(info, desugar_sched_stmts(stmts, env, loops)?)
+            }
+            SchedStmt::Seq {
+                info,
+                dests,
+                block,
+                is_const,
+            } => {
+                let mut block = desugar_sched_stmts(vec![*block], env, loops)?;
+                SchedStmt::Seq {
+                    info,
+                    dests,
+                    block: Box::new(block.pop().unwrap()),
+                    is_const,
+                }
+            }
+            s => s,
+        };
+        res.push(s);
+    }
+    Ok(res)
+}
+
+/// Generates the scheduling function for a loop in a schedule and returns the
+/// call that starts the loop along with the statements that must precede it.
+/// # Arguments
+/// * `lp` - The loop
+/// * `guard` - The guard of the loop
+/// * `body` - The body of the loop
+/// * `dests` - The variables the loop binds
+/// * `free` - The variables used and the variables assigned but not defined by
+///   the loop
+/// * `env` - The variables defined in the enclosing function
+/// * `loops` - The loops of the scheduling function being desugared
+#[allow(clippy::too_many_lines)]
+fn desugar_sched_loop(
+    mut lp: Loop<FullType, SchedExpr>,
+    guard: SchedExpr,
+    mut body: Vec<SchedStmt>,
+    dests: &[String],
+    (free, assigns): (&BTreeSet<String>, &BTreeSet<String>),
+    env: &HashSet<String>,
+    loops: &mut SchedLoops,
+) -> Result<(Vec<SchedStmt>, SchedExpr), LocalError> {
+    let info = lp.info;
+    let k = loops.count;
+    let name = loops.func_name(dests);
+    let spec = loops.find_spec(info, dests)?;
+    loops.count += 1;
+    loops.matched.push(spec.name.clone());
+    if spec.num_carried != lp.carried.len() {
+        return Err(type_error(
+            info,
+            &format!(
+                "Loop carries {} variables but the matching loop in the value specification carries {}",
+                lp.carried.len(),
+                spec.num_carried
+            ),
+        ));
+    }
+    if let Some(v) = assigns.iter().find(|v| env.contains(*v)) {
+        return Err(type_error(
+            info,
+            &format!("Cannot assign to {v} inside a loop which does not define it. Carry it as a loop variable instead"),
+        ));
+    }
+    for v in free.iter().filter(|v| env.contains(*v)) {
+        if !spec.invariants.iter().any(|(inv, _)| inv == v) {
+            return Err(type_error(
+                info,
+                &format!("{v} is not used by the matching loop in the value specification. Carry it as a loop variable instead"),
+            ));
+        }
+    }
+    if let Some((v, _)) = spec.invariants.iter().find(|(v, _)| !env.contains(v)) {
+        return Err(type_error(
+            info,
+            &format!(
+                "{v} is used by the matching loop in the value specification but is not defined"
+            ),
+        ));
+    }
+    let spec_name = spec.name.clone();
+    let invariants = spec.invariants.clone();
+    let invariant_names: Vec<_> = invariants.iter().map(|(v, _)| v.clone()).collect();
+
+    let mut body_env: HashSet<_> = lp
+        .carried
+        .iter()
+        .map(|(v, _, _)| v.clone())
+        .chain(invariant_names.iter().cloned())
+        .collect();
+    sched_defs(&body, &mut body_env);
+    let decl = |lhs: Vec<String>, e| SchedStmt::Decl {
+        info,
+        lhs: lhs.into_iter().map(|v| (v, None)).collect(),
+        is_const: true,
+        expr: Some(e),
+    };
+    let mut true_block = vec![];
+    // compute the next index before the body in case the body shadows it
+    if let Some(index) = lp.next_index.take() {
+        true_block.push(decl(vec![next_name(0)], index));
+    }
+    // the parser guarantees that the body ends with the next values
+    let last = body.pop().unwrap();
+    // loops in the body are generated for and matched against this loop
+    let parent = (
+        std::mem::replace(&mut loops.func, name.clone()),
+        std::mem::replace(&mut loops.spec, spec_name.clone()),
+    );
+    let desugared = desugar_sched_stmts(body, &body_env, loops)
+        .and_then(|body| Ok((body, desugar_sched_stmts(vec![last], &body_env, loops)?)));
+    (loops.func, loops.spec) = parent;
+    let (body, mut last) = desugared?;
+    true_block.extend(body);
+    let next_args = match last.pop().unwrap() {
+        SchedStmt::Return(_, e) => {
+            let (next, args) = lp.next_values(e);
+            true_block.extend(next.into_iter().map(|(lhs, e)| decl(lhs, e)));
+            args
+        }
+        last => {
+            true_block.push(SchedStmt::Seq {
+                info,
+                dests: (lp.first_result()..lp.carried.len())
+                    .map(|i| (next_name(i), None))
+                    .collect(),
+                block: Box::new(last),
+                is_const: true,
+            });
+            lp.next_args().collect()
+        }
+    };
+    let rec_names: Vec<_> = (0..lp.num_results()).map(rec_name).collect();
+    true_block.push(decl(
+        rec_names.clone(),
+        lp.recursive_call(name.clone(), next_args, &invariant_names),
+    ));
+    true_block.push(SchedStmt::Return(
+        info,
+        returned(info, rec_names.into_iter().map(|v| var(info, v)).collect()),
+    ));
+    let false_block = vec![SchedStmt::Return(
+        info,
+        returned(
+            info,
+            lp.results()
+                .iter()
+                .map(|(v, _, _)| var(info, v.clone()))
+                .collect(),
+        ),
+    )];
+
+    let input = lp
+        .carried
+        .iter()
+        .map(|(v, t, _)| (v.clone(), Some(untagged(t))))
+        .chain(invariants.into_iter().map(|(v, t)| (v, Some(full_type(t)))))
+        .collect();
+    let output = lp.results().iter().map(|(_, t, _)| untagged(t)).collect();
+    let specs = loops
+        .specs
+        .iter()
+        .map(|s| {
+            if Some(s) == loops.value_spec {
+                spec_name.clone()
+            } else {
+                s.clone()
+            }
+        })
+        .collect();
+    loops.generated.push(TopLevel::SchedulingFunc {
+        info,
+        name: name.clone(),
+        input,
+        output,
+        specs,
+        statements: vec![SchedStmt::If {
+            info,
+            guard,
+            tag: None,
+            true_block,
+            false_block,
+        }],
+    });
+    let mut call = lp.initial_call(name, &invariant_names);
+    Ok((copy_args(&mut call, k), call))
+}
+
+/// Replaces each variable passed to `call` with a copy of it and returns the
+/// declarations of the copies. The caller may use the variables again after
+/// the call, which requires them to be saved, while the loop expects usable
+/// arguments.
+fn copy_args(call: &mut SchedExpr, k: usize) -> Vec<SchedStmt> {
+    let mut copies = vec![];
+    if let SchedExpr::Term(SchedTerm::Call(_, SchedFuncCall { args, .. })) = call {
+        for (i, arg) in args.iter_mut().enumerate() {
+            if is_var(arg) {
+                let info = arg.info();
+                let copy = format!("_arg{k}_{i}");
+                copies.push(SchedStmt::Decl {
+                    info,
+                    lhs: vec![(copy.clone(), None)],
+                    is_const: true,
+                    expr: Some(std::mem::replace(arg, var(info, copy))),
+                });
+            }
+        }
+    }
+    copies
+}
+
+/// Returns the location of the first loop in a list of spec statements
+fn find_spec_loop(stmts: &[SpecStmt]) -> Option<Info> {
+    stmts.iter().find_map(|s| match s {
+        SpecStmt::Loop { info, .. } => Some(*info),
+        _ => None,
+    })
+}
+
+/// Desugars all loops in the program into tail-recursive value funclets and
+/// scheduling functions. Returns the names of the generated scheduling functions.
+/// # Errors
+/// Returns an error if a loop is malformed or if the loops of a schedule do not
+/// match the loops of its value specification.
+pub fn desugar_loops(p: &mut Program) -> Result<HashSet<String>, LocalError> {
+    let mut spec_loops = HashMap::new();
+    let mut generated = vec![];
+    for decl in p.iter_mut() {
+        if let TopLevel::FunctionClass { members, .. } = decl {
+            for member in members {
+                match member {
+                    ClassMembers::ValueFunclet(SpecFunclet {
+                        name,
+                        input,
+                        statements,
+                        ..
+                    }) => {
+                        let mut loops = SpecLoops {
+                            funclet: name.clone(),
+                            loops: vec![],
+                            generated: vec![],
+                        };
+                        let env = input
+                            .iter()
+                            .map(|(v, t)| (v.clone(), Some(t.clone())))
+                            .collect();
+                        *statements =
+                            desugar_spec_stmts(std::mem::take(statements), &env, &mut loops)?;
+                        generated.extend(loops.generated);
+                        spec_loops.insert(name.clone(), loops.loops);
+                    }
+                    ClassMembers::TimelineFunclet(SpecFunclet { statements, .. })
+                    | ClassMembers::SpatialFunclet(SpecFunclet { statements, .. }) => {
+                        if let Some(info) = find_spec_loop(statements) {
+                            return Err(type_error(
+                                info,
+                                "Loops are only supported in value specifications",
+                            ));
+                        }
+                    }
+                    ClassMembers::Extern { .. } => (),
+                }
+            }
+        }
+    }
+    for decl in p.iter_mut() {
+        if let TopLevel::SchedulingFunc {
+            info,
+            name,
+            input,
+            specs,
+            statements,
+            ..
+        } = decl
+        {
+            let value_spec = specs.iter().find(|s| spec_loops.contains_key(*s));
+            let mut loops = SchedLoops {
+                func: name.clone(),
+                specs,
+                value_spec,
+                spec: value_spec.cloned().unwrap_or_default(),
+                spec_loops: value_spec.map_or(&[], |s| &spec_loops[s]),
+                matched: vec![],
+                count: 0,
+                generated: vec![],
+            };
+            let mut env: HashSet<_> = input.iter().map(|(v, _)| v.clone()).collect();
+            sched_defs(statements.iter(), &mut env);
+            *statements = desugar_sched_stmts(std::mem::take(statements), &env, &mut loops)?;
+            if let Some(missing) = loops.unmatched() {
+                return Err(type_error(
+                    *info,
+                    &format!(
+                        "{} has a loop binding {} but {name} does not",
+                        missing.parent,
+                        missing.dests.join(", ")
+                    ),
+                ));
+            }
+            generated.extend(loops.generated);
+        }
+    }
+    let loop_funcs = generated
+        .iter()
+        .filter_map(|decl| match decl {
+            TopLevel::SchedulingFunc { name, .. } => Some(name.clone()),
+            _ => None,
+        })
+        .collect();
+    p.extend(generated);
+    Ok(loop_funcs)
+}
@@ -1,5 +1,6 @@
 mod flatten_expr;
 mod if_to_seq;
+mod loops;
 mod record_expansion;
 mod sched_rename;
 mod yields;
@@ -12,17 +13,19 @@ use crate::{
 use self::{
     flatten_expr::{flatten_schedule, flatten_spec},
     if_to_seq::final_if_to_seq,
+    loops::desugar_loops,
     sched_rename::rename_vars,
     yields::CallGraph,
 };
 
-/// Normalizes the AST by renaming schedule variables, flattening nested
-/// expressions, converting conditional returns to sequences, and inserting
-/// yields.
+/// Normalizes the AST by desugaring loops, renaming schedule variables,
+/// flattening nested expressions, converting conditional returns to sequences,
+/// and inserting yields everywhere but the recursive calls of loops.
 /// # Errors
 /// If there is a type error in the AST caught during normalization.
 #[allow(clippy::module_name_repetitions)]
 pub fn normalize_ast(mut p: Program) -> Result<Program, LocalError> {
+    let loop_funcs = desugar_loops(&mut p)?;
     for decl in &mut p {
         match decl {
             TopLevel::SchedulingFunc {
@@ -48,7 +51,7 @@ pub fn normalize_ast(mut p: Program) -> Result<Program, LocalError> {
             _ => (),
         }
     }
-    let mut cg = CallGraph::new(&mut p);
+    let mut cg = CallGraph::new(&mut p, &loop_funcs);
     cg.insert_yields();
     Ok(p)
 }
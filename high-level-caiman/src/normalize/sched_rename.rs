@@ -180,6 +180,7 @@ fn rename_vars_rec<'a, T: Iterator<Item = &'a mut SchedStmt>>(
                 }
                 *encoder = get_cur_name(encoder, &cur_names);
             }
+            SchedStmt::Loop { .. } => unreachable!("Loops should be desugared before renaming"),
         }
     }
 }
@@ -262,9 +263,17 @@ fn rename_expr_uses(expr: &mut SchedExpr, cur_names: &HashMap<String, u64>) {
                 rename_expr_uses(e, cur_names);
             }
         }
-        SchedExpr::Term(
-            SchedTerm::Lit { .. } | SchedTerm::Hole(_) | SchedTerm::EncodeBegin { .. },
-        ) => {}
+        SchedExpr::Term(SchedTerm::EncodeBegin {
+            buffer, suballocs, ..
+        }) => {
+            for buffer in buffer
+                .iter_mut()
+                .chain(suballocs.iter_mut().map(|(_, buffer)| buffer))
+            {
+                *buffer = get_cur_name(buffer, cur_names);
+            }
+        }
+        SchedExpr::Term(SchedTerm::Lit { .. } | SchedTerm::Hole(_)) => {}
         SchedExpr::Term(SchedTerm::TimelineOperation { arg, .. }) => {
             rename_expr_uses(arg, cur_names);
         }
@@ -1,4 +1,6 @@
 //! Detects loops in the program call graph and inserts yields to break cycles.
+//! The calls a function desugared from a loop makes to itself are left out of
+//! the call graph, since the backend runs them without returning to the host.
 
 use std::collections::{BTreeMap, HashSet};
 
@@ -18,8 +20,8 @@ pub struct CallGraph<'a> {
 
 impl<'a> CallGraph<'a> {
     /// Constructs a new call graph from a program. Requires that the program is
-    /// flattened.
-    pub fn new(program: &'a mut Program) -> Self {
+    /// flattened. `loop_funcs` are the names of the functions desugared from loops.
+    pub fn new(program: &'a mut Program, loop_funcs: &HashSet<String>) -> Self {
         let mut adj: BTreeMap<_, Vec<(String, usize)>> = BTreeMap::new();
         let mut edges = Vec::new();
         for decl in program {
@@ -28,6 +30,11 @@ impl<'a> CallGraph<'a> {
             } = decl
             {
                 Self::search_for_calls(name, statements.iter_mut(), &mut adj, &mut edges);
+                if loop_funcs.contains(name) {
+                    if let Some(calls) = adj.get_mut(name.as_str()) {
+                        calls.retain(|(dest, _)| dest != name);
+                    }
+                }
             }
         }
         Self { adj, edges }
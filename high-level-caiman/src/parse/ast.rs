@@ -2,7 +2,7 @@ use std::{collections::BTreeSet, fmt::Display};
 
 use caiman::ir;
 
-use crate::error::{HasInfo, Info};
+use crate::error::{type_error, HasInfo, Info, LocalError};
 
 pub type Name = String;
 
@@ -10,18 +10,84 @@ pub type Arg<T> = (String, T);
 pub type MaybeArg<T> = (String, Option<T>);
 pub type NamedOutput<T> = (Option<String>, T);
 
-/// A numeric data type
+/// An integer data type, signed or unsigned
 #[derive(Clone, Debug, PartialEq, Eq, Hash, Copy)]
 pub enum IntSize {
+    I8,
+    I16,
     I32,
     I64,
+    U8,
+    U16,
+    U32,
+    U64,
+    USize,
 }
 
+impl IntSize {
+    /// All integer types
+    pub const ALL: [Self; 9] = [
+        Self::U8,
+        Self::U16,
+        Self::U32,
+        Self::U64,
+        Self::USize,
+        Self::I8,
+        Self::I16,
+        Self::I32,
+        Self::I64,
+    ];
+
+    /// Returns the integer type spelled by `suffix`, such as `u32` in `5u32`
+    #[must_use]
+    pub fn from_suffix(suffix: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|i| i.to_string() == suffix)
+    }
+}
+
+impl Display for IntSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::I8 => write!(f, "i8"),
+            Self::I16 => write!(f, "i16"),
+            Self::I32 => write!(f, "i32"),
+            Self::I64 => write!(f, "i64"),
+            Self::U8 => write!(f, "u8"),
+            Self::U16 => write!(f, "u16"),
+            Self::U32 => write!(f, "u32"),
+            Self::U64 => write!(f, "u64"),
+            Self::USize => write!(f, "usize"),
+        }
+    }
+}
+
+/// A floating point data type
 #[derive(Clone, Debug, PartialEq, Eq, Hash, Copy)]
 pub enum FloatSize {
+    F32,
     F64,
 }
 
+impl FloatSize {
+    /// All floating point types
+    pub const ALL: [Self; 2] = [Self::F32, Self::F64];
+
+    /// Returns the float type spelled by `suffix`, such as `f32` in `1.5f32`
+    #[must_use]
+    pub fn from_suffix(suffix: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|f| f.to_string() == suffix)
+    }
+}
+
+impl Display for FloatSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::F32 => write!(f, "f32"),
+            Self::F64 => write!(f, "f64"),
+        }
+    }
+}
+
 /// A core data type available in the value and scheduling languages
 /// Ex. i64, bool, i32 etc.
 ///
@@ -37,6 +103,8 @@ pub enum DataType {
     Float(FloatSize),
     Bool,
     BufferSpace,
+    /// A GPU buffer of the given size in bytes that values can be suballocated from
+    GpuBuffer(usize),
     /// A local event
     Event,
     /// A remote event, specifically an encoder
@@ -63,7 +131,10 @@ impl PartialEq for DataType {
         match (self, other) {
             (Self::Int(l0), Self::Int(r0)) => l0 == r0,
             (Self::Float(l0), Self::Float(r0)) => l0 == r0,
-            (Self::Array(..), Self::Array(..)) => todo!(),
+            (Self::GpuBuffer(l0), Self::GpuBuffer(r0)) => l0 == r0,
+            (Self::Array(l0, _), Self::Array(r0, _)) => {
+                l0 == r0 && self.array_length().ok() == other.array_length().ok()
+            }
             (Self::Slice(l0), Self::Slice(r0)) | (Self::Ref(l0), Self::Ref(r0)) => l0 == r0,
             (Self::UserDefined(l0), Self::UserDefined(r0)) => l0 == r0,
             _ => core::mem::discriminant(self) == core::mem::discriminant(other),
@@ -78,6 +149,46 @@ impl DataType {
     pub fn refines(&self, b: &Self) -> bool {
         self == b || matches!(self, Self::Ref(ref a) if b == a.as_ref())
     }
+
+    /// Creates an array type of `len` elements of type `element`, where `len`
+    /// is either an integer literal or the name of a constant.
+    #[must_use]
+    pub fn array(element: Self, len: &str) -> Self {
+        let info = Info::default();
+        let len = if len.chars().all(|c| c.is_ascii_digit()) {
+            SpecTerm::Lit {
+                info,
+                lit: SpecLiteral::Int(len.to_string(), None),
+            }
+        } else {
+            SpecTerm::Var {
+                info,
+                name: len.to_string(),
+            }
+        };
+        Self::Array(Box::new(element), Box::new(SpecExpr::Term(len)))
+    }
+
+    /// Gets the length of an array type, which is either an integer literal
+    /// or the name of a constant. Returns `None` if `self` is not an array.
+    /// # Errors
+    /// Returns a type error if the length of the array is not a literal or a name.
+    pub fn array_length(&self) -> Result<Option<&str>, LocalError> {
+        match self {
+            Self::Array(_, len) => match len.as_ref() {
+                SpecExpr::Term(SpecTerm::Lit {
+                    lit: SpecLiteral::Int(n, _),
+                    ..
+                }) => Ok(Some(n)),
+                SpecExpr::Term(SpecTerm::Var { name, .. }) => Ok(Some(name)),
+                x => Err(type_error(
+                    x.info(),
+                    "Array lengths must be an integer literal or the name of a constant",
+                )),
+            },
+            _ => Ok(None),
+        }
+    }
 }
 
 impl Eq for DataType {}
@@ -88,7 +199,11 @@ impl std::hash::Hash for DataType {
         match self {
             Self::Int(nt) => nt.hash(state),
             Self::Float(nt) => nt.hash(state),
-            Self::Array(..) => todo!(),
+            Self::GpuBuffer(size) => size.hash(state),
+            Self::Array(dt, _) => {
+                dt.hash(state);
+                self.array_length().ok().hash(state);
+            }
             Self::Slice(dt) | Self::Ref(dt) => dt.hash(state),
             Self::UserDefined(name) => name.hash(state),
             _ => {}
@@ -99,18 +214,31 @@ impl std::hash::Hash for DataType {
 impl Display for DataType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::Int(IntSize::I32) => write!(f, "i32"),
-            Self::Int(IntSize::I64) => write!(f, "i64"),
-            Self::Float(FloatSize::F64) => write!(f, "f64"),
+            Self::Int(size) => write!(f, "{size}"),
+            Self::Float(size) => write!(f, "{size}"),
             Self::Bool => write!(f, "bool"),
             Self::BufferSpace => write!(f, "BufferSpace"),
+            Self::GpuBuffer(size) => {
+                if f.alternate() {
+                    write!(f, "_gb{size}")
+                } else {
+                    write!(f, "GpuBuffer[{size}]")
+                }
+            }
             Self::Event => write!(f, "Event"),
             Self::Encoder(None) => write!(f, "Encoder"),
             Self::Fence(None) => write!(f, "Fence"),
-            Self::Array(..) => todo!(),
+            Self::Array(typ, _) => {
+                let len = self.array_length().ok().flatten().unwrap_or("?");
+                if f.alternate() {
+                    write!(f, "_a{len}_{typ:#}")
+                } else {
+                    write!(f, "[{typ}; {len}]")
+                }
+            }
             Self::Slice(typ) => {
                 if f.alternate() {
-                    write!(f, "_a_{typ}")
+                    write!(f, "_a_{typ:#}")
                 } else {
                     write!(f, "[{typ}]")
                 }
@@ -180,11 +308,25 @@ pub enum Uop {
     Deref,
 }
 
+impl Display for Uop {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Neg => write!(f, "-"),
+            Self::LNot => write!(f, "!"),
+            Self::Not => write!(f, "~"),
+            Self::Ref => write!(f, "&"),
+            Self::Deref => write!(f, "*"),
+        }
+    }
+}
+
 /// A literal in the spec languages
 #[derive(Clone, Debug)]
 pub enum SpecLiteral {
-    Int(String),
-    Float(String),
+    /// An integer literal and the type given by its suffix, if any
+    Int(String, Option<IntSize>),
+    /// A float literal and the type given by its suffix, if any
+    Float(String, Option<FloatSize>),
     Bool(bool),
     Array(Vec<SpecExpr>),
     Tuple(Vec<SpecExpr>),
@@ -193,8 +335,10 @@ pub enum SpecLiteral {
 /// A literal in the spec languages
 #[derive(Clone, Debug)]
 pub enum SchedLiteral {
-    Int(String),
-    Float(String),
+    /// An integer literal and the type given by its suffix, if any
+    Int(String, Option<IntSize>),
+    /// A float literal and the type given by its suffix, if any
+    Float(String, Option<FloatSize>),
     Bool(bool),
     Array(Vec<SchedExpr>),
     Tuple(Vec<SchedExpr>),
@@ -267,6 +411,31 @@ impl<T: HasInfo> HasInfo for NestedExpr<T> {
     }
 }
 
+/// A variable carried between iterations of a loop along with its type and
+/// initial value. Ex. `acc: i64 = 0`
+#[derive(Clone, Debug)]
+pub struct LoopVar<T, E> {
+    pub name: Name,
+    pub typ: T,
+    pub init: E,
+}
+
+/// The condition controlling how many times a loop runs
+#[derive(Clone, Debug)]
+pub enum LoopHeader<E> {
+    /// `while guard`, the loop runs as long as `guard` is true
+    While(E),
+    /// `for name: typ in start..end`, the loop runs once for each value of
+    /// the index `name` in the half open range `[start, end)`. The index is
+    /// carried between iterations but is not a result of the loop
+    For {
+        name: Name,
+        typ: DataType,
+        start: E,
+        end: E,
+    },
+}
+
 /// A statement in a specification function
 /// Supports all specifications
 #[derive(Clone, Debug)]
@@ -277,6 +446,20 @@ pub enum SpecStmt {
         rhs: SpecExpr,
     },
     Returns(Info, SpecExpr),
+    /// A loop whose body must end in a `returns` of the next value of each
+    /// loop variable. Ex:
+    /// ```text
+    /// acc :- for (acc: i64 = 0) i in 0..n {
+    ///     returns acc + i
+    /// }
+    /// ```
+    Loop {
+        info: Info,
+        lhs: Vec<(Name, Option<DataType>)>,
+        state: Vec<LoopVar<DataType, SpecExpr>>,
+        header: LoopHeader<SpecExpr>,
+        body: Vec<SpecStmt>,
+    },
 }
 /// AST-level quotient
 #[derive(Clone, Debug, PartialEq, Eq, Copy)]
@@ -612,6 +795,12 @@ pub enum SchedTerm {
     EncodeBegin {
         info: Info,
         device: Name,
+        /// The buffer the encoded variables are suballocated from, if any.
+        /// Otherwise each encoded variable gets its own allocation.
+        buffer: Option<Name>,
+        /// Encoded variables declared to be suballocated from a particular
+        /// buffer, along with that buffer. These take precedence over `buffer`.
+        suballocs: Vec<(Name, Name)>,
         tag: Option<Tags>,
         defs: Vec<MaybeArg<FullType>>,
     },
@@ -710,6 +899,20 @@ pub enum SchedStmt {
         cmd: EncodedCommand,
         tag: Option<Tags>,
     },
+    /// A loop whose body must end in an expression evaluating to the next
+    /// value of each loop variable. Ex:
+    /// ```text
+    /// let acc = for (acc: i64 = 0) i in 0..n {
+    ///     acc + i
+    /// };
+    /// ```
+    Loop {
+        info: Info,
+        dests: Vec<(Name, Option<FullType>)>,
+        state: Vec<LoopVar<FullType, SchedExpr>>,
+        header: LoopHeader<SchedExpr>,
+        body: Vec<SchedStmt>,
+    },
 }
 
 impl SchedStmt {
@@ -727,7 +930,8 @@ impl SchedStmt {
             | Self::Hole(info)
             | Self::Call(info, _)
             | Self::Seq { info, .. }
-            | Self::Encode { info, .. } => info,
+            | Self::Encode { info, .. }
+            | Self::Loop { info, .. } => info,
         }
     }
 }
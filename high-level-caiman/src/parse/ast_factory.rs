@@ -5,11 +5,11 @@
 use std::collections::{BTreeSet, HashMap};
 use std::iter;
 
-use lalrpop_util::{ParseError, lexer::Token};
+use lalrpop_util::{lexer::Token, ParseError};
 
 use super::ast::*;
-use crate::error::{CustomParsingError, HasInfo, Info};
 use crate::custom_parse_error;
+use crate::error::{CustomParsingError, HasInfo, Info};
 
 const MAJOR_VERSION: &str = "0";
 const MINOR_VERSION: &str = "1";
@@ -112,23 +112,41 @@ macro_rules! struct_variant_factory {
 /// parser state. The Factory keeps track of the byte offsets of each line
 /// so it can convert the byte offsets that lalrpop gives us to line
 /// and column numbers.
-/// 
+///
 /// Each factory function in the `ASTFactory` takes the byte offset of the starting
 /// and ending byte offsets and converts them into starting and ending line and column
-/// numbers. Macros are used to define these functions to avoid repeating the 
+/// numbers. Macros are used to define these functions to avoid repeating the
 /// same passing along of source location information
 pub struct ASTFactory {
     line_ending_byte_offsets: Vec<usize>,
     /// mapping of user-defined types. Handling this here
     /// requires that we declare a typedef before we use it
     type_map: HashMap<String, DataType>,
+    /// mapping of constants to their values, used to resolve array lengths.
+    /// Like typedefs, a constant must be declared before it's used as a length
+    const_map: HashMap<String, SpecExpr>,
+    /// How types and constants defined by imported files are handled
+    imported_types: ImportedTypes,
+}
+
+/// The state of the types and constants defined by the files imported by the
+/// file being parsed
+enum ImportedTypes {
+    /// Nothing has been imported yet
+    None,
+    /// An import has been parsed but the types and constants it defines are not
+    /// known. Undefined names are assumed to be defined by an import and
+    /// `deferred` records whether there were any
+    Pending { deferred: bool },
+    /// The types and constants defined by imported files are in the type and
+    /// constant maps
+    Known,
 }
 
 /// `LALRpop` parsing error using our custom error type, `CustomParsingError`
 type ParserError = ParseError<usize, Token<'static>, CustomParsingError>;
 
 impl ASTFactory {
-
     /// Creates a new `ASTFactory` from a string of caimain frontend code
     #[must_use]
     pub fn new(_filename: &str, s: &str) -> Self {
@@ -142,9 +160,39 @@ impl ASTFactory {
                 .filter_map(|(idx, b)| if *b == b'\n' { Some(idx) } else { None })
                 .collect(),
             type_map: HashMap::new(),
+            const_map: HashMap::new(),
+            imported_types: ImportedTypes::None,
+        }
+    }
+
+    /// Creates a new `ASTFactory` for a file whose imports define the
+    /// user-defined types in `types` and the constants in `consts`
+    #[must_use]
+    pub fn with_imported_types(
+        filename: &str,
+        s: &str,
+        types: HashMap<String, DataType>,
+        consts: HashMap<String, SpecExpr>,
+    ) -> Self {
+        Self {
+            type_map: types,
+            const_map: consts,
+            imported_types: ImportedTypes::Known,
+            ..Self::new(filename, s)
         }
     }
 
+    /// Returns true if a type or constant was assumed to be defined by an imported file,
+    /// in which case the file must be parsed again once the types of its
+    /// imports are known
+    #[must_use]
+    pub const fn deferred_imported_types(&self) -> bool {
+        matches!(
+            self.imported_types,
+            ImportedTypes::Pending { deferred: true }
+        )
+    }
+
     /// Returns the line and column number of the given byte offset
     /// # Panics
     /// Panics if the byte offset is greater than the length of the string
@@ -164,12 +212,12 @@ impl ASTFactory {
     }
 
     /// Construct an `Info` struct from a start and end byte offset
-    /// 
+    ///
     /// The `Info` struct contains the line and column number of the start and end
     #[must_use]
     pub fn info(&self, l: usize, r: usize) -> Info {
         Info {
-            start_ln_and_col: self.line_and_column(l), 
+            start_ln_and_col: self.line_and_column(l),
             end_ln_and_col: self.line_and_column(r),
         }
     }
@@ -179,47 +227,77 @@ impl ASTFactory {
     /// Returns an error if the resource is missing a binding or group field
     /// # Panics
     /// Panics if somehow we didn't exit early with an error
-    pub fn extern_resource(&self, l: usize, v: Vec<ResourceMembers>, r: usize) 
-        -> Result<ExternResource, ParserError> 
-    {
-            let mut binding = None;
-            let mut group = None;
-            let mut input = None;
-            let mut output = None;
-            let src_info = self.info(l, r);
-            for member in v {
-                match member {
-                    ResourceMembers::Input(val) => {
-                        input = Some(val.clone());
-                    }
-                    ResourceMembers::Output(val) => {
-                        output = Some(val.clone());
-                    }
-                    ResourceMembers::Numeric(name, val) if name == "binding" => {
-                        binding = Some(val.clone());
-                    }
-                    ResourceMembers::Numeric(name, val) if name == "group" => group = Some(val.clone()),
-                    m @ ResourceMembers::Numeric(..) => return Err(custom_parse_error!(src_info, "Invalid member '{}' in extern definition", m)),
+    pub fn extern_resource(
+        &self,
+        l: usize,
+        v: Vec<ResourceMembers>,
+        r: usize,
+    ) -> Result<ExternResource, ParserError> {
+        let mut binding = None;
+        let mut group = None;
+        let mut input = None;
+        let mut output = None;
+        let src_info = self.info(l, r);
+        for member in v {
+            match member {
+                ResourceMembers::Input(val) => {
+                    input = Some(val.clone());
+                }
+                ResourceMembers::Output(val) => {
+                    output = Some(val.clone());
+                }
+                ResourceMembers::Numeric(name, val) if name == "binding" => {
+                    binding = Some(val.clone());
+                }
+                ResourceMembers::Numeric(name, val) if name == "group" => group = Some(val.clone()),
+                m @ ResourceMembers::Numeric(..) => {
+                    return Err(custom_parse_error!(
+                        src_info,
+                        "Invalid member '{}' in extern definition",
+                        m
+                    ))
                 }
             }
-            if binding.is_none() {
-                return Err(custom_parse_error!(src_info, "Resource at {} missing field \"binding\"", src_info));
-            }
-            if group.is_none() {
-                return Err(custom_parse_error!(src_info, "Resource at {} missing field \"group\"", src_info));
-            }
-            if input.is_some() && output.is_some() || input.is_none() && output.is_none() {
-                return Err(custom_parse_error!(src_info, "Resource at {} must have exactly one input or output field", src_info));
-            }
-            Ok(ExternResource {
-                binding: binding.unwrap().parse().map_err(|e| custom_parse_error!(src_info, "Resource at {} has invalid binding {}", src_info, e))?,
-                group: group.unwrap().parse().map_err(|e| custom_parse_error!(src_info, "Resource at {} has invalid group {}", src_info, e))?,
-                caiman_val: match (input, output) {
-                    (Some(s), None) => InputOrOutputVal::Input(s),
-                    (None, Some(s)) => InputOrOutputVal::Output(s),
-                    _ => panic!("Resource at {src_info} must have exactly one input or output"),
-                },
-            })
+        }
+        if binding.is_none() {
+            return Err(custom_parse_error!(
+                src_info,
+                "Resource at {} missing field \"binding\"",
+                src_info
+            ));
+        }
+        if group.is_none() {
+            return Err(custom_parse_error!(
+                src_info,
+                "Resource at {} missing field \"group\"",
+                src_info
+            ));
+        }
+        if input.is_some() && output.is_some() || input.is_none() && output.is_none() {
+            return Err(custom_parse_error!(
+                src_info,
+                "Resource at {} must have exactly one input or output field",
+                src_info
+            ));
+        }
+        Ok(ExternResource {
+            binding: binding.unwrap().parse().map_err(|e| {
+                custom_parse_error!(
+                    src_info,
+                    "Resource at {} has invalid binding {}",
+                    src_info,
+                    e
+                )
+            })?,
+            group: group.unwrap().parse().map_err(|e| {
+                custom_parse_error!(src_info, "Resource at {} has invalid group {}", src_info, e)
+            })?,
+            caiman_val: match (input, output) {
+                (Some(s), None) => InputOrOutputVal::Input(s),
+                (None, Some(s)) => InputOrOutputVal::Output(s),
+                _ => panic!("Resource at {src_info} must have exactly one input or output"),
+            },
+        })
     }
 
     /// Constructs an extern definition from a list of members
@@ -227,9 +305,12 @@ impl ASTFactory {
     /// Returns an error if the definition is missing a path, entry, or dimensions field
     /// # Panics
     /// Panics if somehow we didn't exit early with an error
-    pub fn extern_def(&self, l: usize, members: Vec<ExternDefMembers>, r: usize) 
-        -> Result<ExternDef, ParserError> 
-    {
+    pub fn extern_def(
+        &self,
+        l: usize,
+        members: Vec<ExternDefMembers>,
+        r: usize,
+    ) -> Result<ExternDef, ParserError> {
         let info = self.info(l, r);
         let mut def = ExternDef {
             path: String::new(),
@@ -246,18 +327,35 @@ impl ASTFactory {
                 }
                 ExternDefMembers::Resource(r) => def.resources.push(r),
                 x => {
-                    return Err(custom_parse_error!(info, "Extern definition at {} has invalid member {}", info, x));
+                    return Err(custom_parse_error!(
+                        info,
+                        "Extern definition at {} has invalid member {}",
+                        info,
+                        x
+                    ));
                 }
             }
         }
         if def.path.is_empty() {
-            return Err(custom_parse_error!(info, "Extern definition at {} missing field \"path\"", info));
+            return Err(custom_parse_error!(
+                info,
+                "Extern definition at {} missing field \"path\"",
+                info
+            ));
         }
         if def.entry.is_empty() {
-            return Err(custom_parse_error!(info, "Extern definition at {} missing field \"path\"", info));
+            return Err(custom_parse_error!(
+                info,
+                "Extern definition at {} missing field \"path\"",
+                info
+            ));
         }
         if def.dimensions == usize::MAX {
-            return Err(custom_parse_error!(info, "Extern definition at {} missing field \"dimensions\"", info));
+            return Err(custom_parse_error!(
+                info,
+                "Extern definition at {} missing field \"dimensions\"",
+                info
+            ));
         }
         Ok(def)
     }
@@ -269,31 +367,37 @@ impl ASTFactory {
     pub fn const_expr(&self, expr: SpecExpr) -> Result<SpecExpr, ParserError> {
         fn sanitize_expr(expr: &SpecExpr) -> Result<(), ParserError> {
             match expr {
-                SpecExpr::Term(t) => {
-                    match t {
-                        SpecTerm::Lit {lit, ..} => {
-                            match lit {
-                                SpecLiteral::Int(_) | SpecLiteral::Bool(_) | SpecLiteral::Float(_) => Ok(()),
-                                SpecLiteral::Array(a) | SpecLiteral::Tuple(a) => {
-                                    for e in a {
-                                        sanitize_expr(e)?;
-                                    }
-                                    Ok(())
-                                } 
+                SpecExpr::Term(t) => match t {
+                    SpecTerm::Lit { lit, .. } => match lit {
+                        SpecLiteral::Int(..) | SpecLiteral::Bool(_) | SpecLiteral::Float(..) => {
+                            Ok(())
+                        }
+                        SpecLiteral::Array(a) | SpecLiteral::Tuple(a) => {
+                            for e in a {
+                                sanitize_expr(e)?;
                             }
-                        },
-                        SpecTerm::Var { .. } => Ok(()),
-                        SpecTerm::Call { info, .. } => Err(custom_parse_error!(*info, 
-                            "Non constant expression found in a constant context at {}", info)),
-                    }
+                            Ok(())
+                        }
+                    },
+                    SpecTerm::Var { .. } => Ok(()),
+                    SpecTerm::Call { info, .. } => Err(custom_parse_error!(
+                        *info,
+                        "Non constant expression found in a constant context at {}",
+                        info
+                    )),
                 },
-                SpecExpr::Binop { lhs, rhs, ..} => {
+                SpecExpr::Binop { lhs, rhs, .. } => {
                     sanitize_expr(lhs)?;
                     sanitize_expr(rhs)?;
                     Ok(())
-                },
-                SpecExpr::Uop { expr, ..} => sanitize_expr(expr),
-                SpecExpr::Conditional { if_true, guard, if_false, .. } => {
+                }
+                SpecExpr::Uop { expr, .. } => sanitize_expr(expr),
+                SpecExpr::Conditional {
+                    if_true,
+                    guard,
+                    if_false,
+                    ..
+                } => {
                     sanitize_expr(if_true)?;
                     sanitize_expr(guard)?;
                     sanitize_expr(if_false)
@@ -307,7 +411,7 @@ impl ASTFactory {
     /// are the annotatiions and input/output arguments for the schedules.
     #[allow(clippy::missing_const_for_fn)]
     fn finalize_data_type(t: DataType) -> DataType {
-        if let DataType::RemoteObj { all, ..} = t {
+        if let DataType::RemoteObj { all, .. } = t {
             DataType::Record(all)
         } else {
             t
@@ -318,7 +422,13 @@ impl ASTFactory {
     /// Flags/settings are optional
     /// # Errors
     /// Returns an error if the flags/settings are invalid
-    pub fn flagged_type(&self, l: usize, mut t: DataType, flags: Option<Vec<(String, Option<String>)>>, r: usize) -> Result<FlaggedType, ParserError> {
+    pub fn flagged_type(
+        &self,
+        l: usize,
+        mut t: DataType,
+        flags: Option<Vec<(String, Option<String>)>>,
+        r: usize,
+    ) -> Result<FlaggedType, ParserError> {
         t = Self::finalize_data_type(t);
         // are there a limited set of WGPU flags/setting we should check for?
         Ok(match flags {
@@ -327,23 +437,31 @@ impl ASTFactory {
                 let mut settings = BTreeSet::new();
                 for (key, val) in flags {
                     if let Some(val) = val {
-                        settings.insert(WGPUSettings::try_from_kv(&key, &val).map_err(|e| custom_parse_error!(self.info(l, r), "{e}"))?);
+                        settings.insert(
+                            WGPUSettings::try_from_kv(&key, &val)
+                                .map_err(|e| custom_parse_error!(self.info(l, r), "{e}"))?,
+                        );
                     } else {
-                        args.insert(key[..].try_into().map_err(|e| custom_parse_error!(self.info(l, r), "{e}"))?);
+                        args.insert(
+                            key[..]
+                                .try_into()
+                                .map_err(|e| custom_parse_error!(self.info(l, r), "{e}"))?,
+                        );
                     }
                 }
                 FlaggedType {
-                info: self.info(l, r),
-                base: t,
-                flags: args,
-                settings,
-            }},
+                    info: self.info(l, r),
+                    base: t,
+                    flags: args,
+                    settings,
+                }
+            }
             None => FlaggedType {
                 info: self.info(l, r),
                 base: t,
                 flags: BTreeSet::new(),
                 settings: BTreeSet::new(),
-            }
+            },
         })
     }
 
@@ -352,34 +470,79 @@ impl ASTFactory {
     /// # Errors
     /// Returns an error if the flags/settings are invalid
     #[allow(clippy::needless_pass_by_value)]
-    pub fn flagged_template_type(&self, l: usize, t: DataType, p: DataType, flags: Option<Vec<(String, Option<String>)>>, r: usize) -> Result<FlaggedType, ParserError> {
+    pub fn flagged_template_type(
+        &self,
+        l: usize,
+        t: DataType,
+        p: DataType,
+        flags: Option<Vec<(String, Option<String>)>>,
+        r: usize,
+    ) -> Result<FlaggedType, ParserError> {
         match t {
-            DataType::Encoder(None) => self.flagged_type(l, DataType::Encoder(Some(Box::new(p))), flags, r),
-            DataType::Fence(None) => self.flagged_type(l, DataType::Fence(Some(Box::new(p))), flags, r),
-            _ => Err(custom_parse_error!(self.info(l, r), "Invalid template type {p:?} to base type {t:?}"))
+            DataType::Encoder(None) => {
+                self.flagged_type(l, DataType::Encoder(Some(Box::new(p))), flags, r)
+            }
+            DataType::Fence(None) => {
+                self.flagged_type(l, DataType::Fence(Some(Box::new(p))), flags, r)
+            }
+            _ => Err(custom_parse_error!(
+                self.info(l, r),
+                "Invalid template type {p:?} to base type {t:?}"
+            )),
         }
-        
     }
 
     #[must_use]
-    pub fn tag(quot: Quotient, quot_var: QuotientReference, flow: Option<Option<Flow>>) -> Tag { 
+    pub fn tag(quot: Quotient, quot_var: QuotientReference, flow: Option<Option<Flow>>) -> Tag {
         Tag {
             quot: Some(quot),
             quot_var: quot_var,
-            flow: flow.flatten()
+            flow: flow.flatten(),
         }
     }
 
     #[must_use]
-    pub const fn flow_tag(quot_var: QuotientReference, flow: Option<Flow>) -> Tag { 
+    pub const fn flow_tag(quot_var: QuotientReference, flow: Option<Flow>) -> Tag {
         Tag {
             quot: None,
             quot_var: quot_var,
-            flow: flow
+            flow: flow,
         }
     }
 
-    struct_variant_factory!(import(path: String) -> TopLevel:TopLevel::Import);
+    /// Constructs an integer literal from a token with a type suffix, such as `5u32`
+    /// # Panics
+    /// If the token does not end with an integer type
+    #[must_use]
+    pub fn suffixed_int(token: &str) -> SchedLiteral {
+        let split = token.find(|c: char| !c.is_ascii_digit()).unwrap();
+        let (n, suffix) = token.split_at(split);
+        SchedLiteral::Int(String::from(n), Some(IntSize::from_suffix(suffix).unwrap()))
+    }
+
+    /// Constructs a float literal from a token with a type suffix, such as `1.5f32`
+    /// # Panics
+    /// If the token does not end with a float type
+    #[must_use]
+    pub fn suffixed_float(token: &str) -> SchedLiteral {
+        let split = token.find('f').unwrap();
+        let (n, suffix) = token.split_at(split);
+        SchedLiteral::Float(
+            String::from(n),
+            Some(FloatSize::from_suffix(suffix).unwrap()),
+        )
+    }
+
+    /// Constructs an import of the file at `path`
+    pub fn import(&mut self, l: usize, path: String, r: usize) -> TopLevel {
+        if matches!(self.imported_types, ImportedTypes::None) {
+            self.imported_types = ImportedTypes::Pending { deferred: false };
+        }
+        TopLevel::Import {
+            info: self.info(l, r),
+            path,
+        }
+    }
 
     /// Converts a scheduling expression to a specification expression or
     /// returns an error if the expression is invalid in a specification
@@ -390,18 +553,37 @@ impl ASTFactory {
             SchedExpr::Binop { info, op, lhs, rhs } => {
                 let lhs = Self::sched_to_spec_expr(*lhs)?;
                 let rhs = Self::sched_to_spec_expr(*rhs)?;
-                Ok(SpecExpr::Binop { info, op, lhs: Box::new(lhs), rhs: Box::new(rhs) })
-            },
+                Ok(SpecExpr::Binop {
+                    info,
+                    op,
+                    lhs: Box::new(lhs),
+                    rhs: Box::new(rhs),
+                })
+            }
             SchedExpr::Uop { info, op, expr } => {
                 let expr = Self::sched_to_spec_expr(*expr)?;
-                Ok(SpecExpr::Uop { info, op, expr: Box::new(expr) })
-            },
-            SchedExpr::Conditional { info, if_true, guard, if_false } => {
+                Ok(SpecExpr::Uop {
+                    info,
+                    op,
+                    expr: Box::new(expr),
+                })
+            }
+            SchedExpr::Conditional {
+                info,
+                if_true,
+                guard,
+                if_false,
+            } => {
                 let if_true = Self::sched_to_spec_expr(*if_true)?;
                 let guard = Self::sched_to_spec_expr(*guard)?;
                 let if_false = Self::sched_to_spec_expr(*if_false)?;
-                Ok(SpecExpr::Conditional { info, if_true: Box::new(if_true), guard: Box::new(guard), if_false: Box::new(if_false) })
-            },
+                Ok(SpecExpr::Conditional {
+                    info,
+                    if_true: Box::new(if_true),
+                    guard: Box::new(guard),
+                    if_false: Box::new(if_false),
+                })
+            }
             SchedExpr::Term(term) => Self::sched_to_spec_term(term).map(SpecExpr::Term),
         }
     }
@@ -425,11 +607,11 @@ impl ASTFactory {
                 let args = args.into_iter().map(Self::sched_to_spec_expr).collect::<Result<Vec<_>, _>>()?;
                 Ok(SpecTerm::Call { info, function: Box::new(target), args, templates })
             },
-            SchedTerm::TimelineOperation { info, .. } | SchedTerm::EncodeBegin { info, .. } => 
+            SchedTerm::TimelineOperation { info, .. } | SchedTerm::EncodeBegin { info, .. } =>
                 Err(custom_parse_error!(info, "Timeline operation cannot occur in this context")),
-            SchedTerm::Call(info, ..) => Err(custom_parse_error!(info, 
+            SchedTerm::Call(info, ..) => Err(custom_parse_error!(info,
                 "Cannot parameterize a function call with non-type template arguments nor specify a tag in this context")),
-            SchedTerm::Hole(info) => Err(custom_parse_error!(info, 
+            SchedTerm::Hole(info) => Err(custom_parse_error!(info,
                 "Holes cannot occur in this context")),
         }
     }
@@ -438,15 +620,21 @@ impl ASTFactory {
     /// error if the literal is invalid in a specification
     fn sched_to_spec_literal(lit: SchedLiteral) -> Result<SpecLiteral, ParserError> {
         match lit {
-            SchedLiteral::Int(i) => Ok(SpecLiteral::Int(i)),
+            SchedLiteral::Int(i, size) => Ok(SpecLiteral::Int(i, size)),
             SchedLiteral::Bool(b) => Ok(SpecLiteral::Bool(b)),
-            SchedLiteral::Float(f) => Ok(SpecLiteral::Float(f)),
+            SchedLiteral::Float(f, size) => Ok(SpecLiteral::Float(f, size)),
             SchedLiteral::Array(a) => {
-                let a = a.into_iter().map(Self::sched_to_spec_expr).collect::<Result<Vec<_>, _>>()?;
+                let a = a
+                    .into_iter()
+                    .map(Self::sched_to_spec_expr)
+                    .collect::<Result<Vec<_>, _>>()?;
                 Ok(SpecLiteral::Array(a))
-            },
+            }
             SchedLiteral::Tuple(t) => {
-                let t = t.into_iter().map(Self::sched_to_spec_expr).collect::<Result<Vec<_>, _>>()?;
+                let t = t
+                    .into_iter()
+                    .map(Self::sched_to_spec_expr)
+                    .collect::<Result<Vec<_>, _>>()?;
                 Ok(SpecLiteral::Tuple(t))
             }
         }
@@ -459,7 +647,7 @@ impl ASTFactory {
         lhs: Box::new(lhs),
         rhs: Box::new(rhs)
     });
-    struct_variant_factory!(range<T: HasInfo>(lhs: NestedExpr<T>, rhs: NestedExpr<T>) -> NestedExpr<T>:NestedExpr::Binop { 
+    struct_variant_factory!(range<T: HasInfo>(lhs: NestedExpr<T>, rhs: NestedExpr<T>) -> NestedExpr<T>:NestedExpr::Binop {
         op: Binop::Range,
         lhs: Box::new(lhs),
         rhs: Box::new(rhs)
@@ -468,8 +656,41 @@ impl ASTFactory {
         op: op,
         expr: Box::new(expr)
     });
-    struct_variant_factory!(conditional<T: HasInfo>(if_true: NestedExpr<T>, guard: NestedExpr<T>, 
-        if_false: NestedExpr<T>) -> NestedExpr<T>:NestedExpr::Conditional 
+
+    /// Constructs a unary operation, folding the negation of a number literal
+    /// into a negative literal such as `-5i16`
+    #[must_use]
+    pub fn unary(&self, l: usize, op: Uop, expr: SchedExpr, r: usize) -> SchedExpr {
+        match (op, expr) {
+            (
+                Uop::Neg,
+                NestedExpr::Term(SchedTerm::Lit {
+                    lit: SchedLiteral::Int(n, size),
+                    tag,
+                    ..
+                }),
+            ) if !n.starts_with('-') => NestedExpr::Term(SchedTerm::Lit {
+                info: self.info(l, r),
+                lit: SchedLiteral::Int(format!("-{n}"), size),
+                tag,
+            }),
+            (
+                Uop::Neg,
+                NestedExpr::Term(SchedTerm::Lit {
+                    lit: SchedLiteral::Float(n, size),
+                    tag,
+                    ..
+                }),
+            ) if !n.starts_with('-') => NestedExpr::Term(SchedTerm::Lit {
+                info: self.info(l, r),
+                lit: SchedLiteral::Float(format!("-{n}"), size),
+                tag,
+            }),
+            (op, expr) => self.uop(l, op, expr, r),
+        }
+    }
+    struct_variant_factory!(conditional<T: HasInfo>(if_true: NestedExpr<T>, guard: NestedExpr<T>,
+        if_false: NestedExpr<T>) -> NestedExpr<T>:NestedExpr::Conditional
     {
         guard: Box::new(guard),
         if_true: Box::new(if_true),
@@ -481,7 +702,13 @@ impl ASTFactory {
     /// Constructs a declaration in a specification
     /// # Errors
     /// Returns an error if the declaration cannot occur in a specification
-    pub fn spec_decl(&self, l: usize, lhs: Vec<(Name, Option<DataType>)>, rhs: SchedExpr, r: usize) -> Result<SpecStmt, ParserError> {
+    pub fn spec_decl(
+        &self,
+        l: usize,
+        lhs: Vec<(Name, Option<DataType>)>,
+        rhs: SchedExpr,
+        r: usize,
+    ) -> Result<SpecStmt, ParserError> {
         let rhs = Self::sched_to_spec_expr(rhs)?;
         Ok(SpecStmt::Assign {
             info: self.info(l, r),
@@ -498,8 +725,90 @@ impl ASTFactory {
         Ok(SpecStmt::Returns(self.info(l, r), e))
     }
 
+    /// Constructs a loop in a specification
+    /// # Errors
+    /// Returns an error if the loop header does not match the kind of loop or
+    /// an expression cannot occur in a specification
+    pub fn spec_loop(
+        &self,
+        l: usize,
+        lhs: Vec<(Name, Option<DataType>)>,
+        state: (bool, Vec<(Name, DataType, SchedExpr)>),
+        header: (Option<(Name, Option<DataType>, Name)>, SchedExpr),
+        body: Vec<SpecStmt>,
+        r: usize,
+    ) -> Result<SpecStmt, ParserError> {
+        let info = self.info(l, r);
+        let (is_for, state) = state;
+        let state = state
+            .into_iter()
+            .map(|(name, typ, init)| {
+                Ok::<_, ParserError>(LoopVar {
+                    name,
+                    typ,
+                    init: Self::sched_to_spec_expr(init)?,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let header = Self::loop_header(info, is_for, header, Self::sched_to_spec_expr)?;
+        Ok(SpecStmt::Loop {
+            info,
+            lhs,
+            state,
+            header,
+            body,
+        })
+    }
+
+    /// Constructs the header of a `while` loop if `is_for` is false or a `for`
+    /// loop if it is true, converting each expression with `convert`
+    /// # Errors
+    /// Returns an error if the header does not match the kind of loop or if
+    /// an expression cannot be converted
+    fn loop_header<E>(
+        info: Info,
+        is_for: bool,
+        header: (Option<(Name, Option<DataType>, Name)>, SchedExpr),
+        convert: fn(SchedExpr) -> Result<E, ParserError>,
+    ) -> Result<LoopHeader<E>, ParserError> {
+        match (is_for, header) {
+            (false, (None, guard)) => Ok(LoopHeader::While(convert(guard)?)),
+            (true, (Some((_, _, kw)), _)) if kw != "in" => Err(custom_parse_error!(
+                info,
+                "Expected 'in' after the index of for loop"
+            )),
+            (
+                true,
+                (
+                    Some((name, typ, _)),
+                    SchedExpr::Binop {
+                        op: Binop::Range,
+                        lhs,
+                        rhs,
+                        ..
+                    },
+                ),
+            ) => Ok(LoopHeader::For {
+                name,
+                typ: typ.unwrap_or(DataType::Int(IntSize::I64)),
+                start: convert(*lhs)?,
+                end: convert(*rhs)?,
+            }),
+            (true, (Some(_), _)) => Err(custom_parse_error!(
+                info,
+                "Expected a range of the form start..end in for loop"
+            )),
+            (true, (None, _)) => Err(custom_parse_error!(
+                info,
+                "Expected an index of the form i in start..end in for loop"
+            )),
+            (false, (Some(_), _)) => {
+                Err(custom_parse_error!(info, "Expected a guard in while loop"))
+            }
+        }
+    }
+
     struct_variant_factory!(spec_lit(lit: SpecLiteral) -> SpecTerm:SpecTerm::Lit);
-    struct_variant_factory!(spec_var(name: Name) -> SpecTerm:SpecTerm::Var);
 
     // scheduling statements
 
@@ -509,31 +818,31 @@ impl ASTFactory {
         lhs: lhs,
         rhs: rhs,
         lhs_is_ref: false
-    
+
     });
     struct_variant_factory!(sched_ref_assign(lhs: SchedExpr, rhs: SchedExpr) -> SchedStmt:SchedStmt::Assign {
         lhs: lhs,
         rhs: rhs,
         lhs_is_ref: true
-    
+
     });
     tuple_variant_factory!(sched_return(e: SchedExpr) -> SchedStmt:SchedStmt::Return);
     tuple_variant_factory!(sched_hole_stmt() -> SchedStmt:SchedStmt::Hole);
     tuple_variant_factory!(sched_call_stmt(call: SchedFuncCall) -> SchedStmt:SchedStmt::Call);
-    struct_variant_factory!(sched_if(tags: Option<Tags>, guard: SchedExpr, true_block: Vec<SchedStmt>, 
-        false_block: Option<SchedStmt>) -> SchedStmt:SchedStmt::If {
-            guard: guard,
-            tag: tags,
-            true_block: true_block,
-            false_block: false_block.map(|x| vec![x]).unwrap_or_default()
-        });
-    struct_variant_factory!(sched_matched_if(tags: Option<Tags>, guard: SchedExpr, true_block: Vec<SchedStmt>, 
-        false_block: SchedStmt) -> SchedStmt:SchedStmt::If {
-            guard: guard,
-            tag: tags,
-            true_block: true_block,
-            false_block: vec![false_block]
-        });
+    struct_variant_factory!(sched_if(tags: Option<Tags>, guard: SchedExpr, true_block: Vec<SchedStmt>,
+    false_block: Option<SchedStmt>) -> SchedStmt:SchedStmt::If {
+        guard: guard,
+        tag: tags,
+        true_block: true_block,
+        false_block: false_block.map(|x| vec![x]).unwrap_or_default()
+    });
+    struct_variant_factory!(sched_matched_if(tags: Option<Tags>, guard: SchedExpr, true_block: Vec<SchedStmt>,
+    false_block: SchedStmt) -> SchedStmt:SchedStmt::If {
+        guard: guard,
+        tag: tags,
+        true_block: true_block,
+        false_block: vec![false_block]
+    });
 
     tuple_variant_factory!(sched_block(stmts: Vec<SchedStmt>) -> SchedStmt:SchedStmt::Block);
 
@@ -542,17 +851,19 @@ impl ASTFactory {
     struct_variant_factory!(sched_lit(lit: SchedLiteral, tag: Option<Tags>) -> SchedTerm:SchedTerm::Lit);
     struct_variant_factory!(sched_var(name: Name, tag: Option<Tags>) -> SchedTerm:SchedTerm::Var);
     tuple_variant_factory!(sched_hole_expr() -> SchedTerm:SchedTerm::Hole);
-    struct_variant_factory!(sched_submit(tag: Option<Tags>, e: SchedExpr) -> 
+    struct_variant_factory!(sched_submit(tag: Option<Tags>, e: SchedExpr) ->
         SchedTerm:SchedTerm::TimelineOperation { op: TimelineOperation::Submit, arg: Box::new(e), tag: tag });
-    struct_variant_factory!(sched_await(tag: Option<Tags>, e: SchedExpr) -> 
+    struct_variant_factory!(sched_await(tag: Option<Tags>, e: SchedExpr) ->
         SchedTerm:SchedTerm::TimelineOperation { op: TimelineOperation::Await, arg: Box::new(e), tag: tag });
-    struct_variant_factory!(sched_begin_encode(tag: Option<Tags>, device: Name) ->
-        SchedTerm:SchedTerm::EncodeBegin { 
-            device: device,
-            defs: vec![],
-            tag: tag 
-        });
-
+    struct_variant_factory!(sched_begin_encode(tag: Option<Tags>, device: Name, buffer: Option<Name>,
+    suballocs: Option<Vec<(Name, Name)>>) ->
+    SchedTerm:SchedTerm::EncodeBegin {
+        device: device,
+        buffer: buffer,
+        suballocs: suballocs.unwrap_or_default(),
+        defs: vec![],
+        tag: tag
+    });
 
     // scheduling function calls:
 
@@ -569,12 +880,15 @@ impl ASTFactory {
     /// # Errors
     /// Returns an error if the templates are not valid in a scheduling context
     pub fn template_args(&self, templates: Vec<SchedExpr>) -> Result<TemplateArgs, ParserError> {
-        Ok(TemplateArgs::Vals(templates.into_iter()
-            .map(Self::sched_to_spec_expr)
-            .collect::<Result<Vec<_>, _>>()?
-            .into_iter()
-            .map(|e| self.const_expr(e))
-            .collect::<Result<Vec<_>, _>>()?))
+        Ok(TemplateArgs::Vals(
+            templates
+                .into_iter()
+                .map(Self::sched_to_spec_expr)
+                .collect::<Result<Vec<_>, _>>()?
+                .into_iter()
+                .map(|e| self.const_expr(e))
+                .collect::<Result<Vec<_>, _>>()?,
+        ))
     }
 
     tuple_variant_factory!(sched_call_expr(call: SchedFuncCall) -> SchedTerm:SchedTerm::Call);
@@ -583,50 +897,99 @@ impl ASTFactory {
     /// # Errors
     /// Returns an error if the statement is not a valid encoded statement
     #[allow(clippy::needless_pass_by_value)]
-    pub fn sched_encode(&self, l: usize, encoder: Name, command: Name, stmt: EncodedStmt, tag: Option<Tags>, r: usize) -> Result<SchedStmt, ParserError> {
+    pub fn sched_encode(
+        &self,
+        l: usize,
+        encoder: Name,
+        command: Name,
+        stmt: EncodedStmt,
+        tag: Option<Tags>,
+        r: usize,
+    ) -> Result<SchedStmt, ParserError> {
         let info = self.info(l, r);
-        let cmd = if command == "copy" { EncodedCommand::Copy} else if command == "call" { EncodedCommand::Invoke} else {
-            return Err(custom_parse_error!(info, "Unrecognized encode operation {command}"))
+        let cmd = if command == "copy" {
+            EncodedCommand::Copy
+        } else if command == "call" {
+            EncodedCommand::Invoke
+        } else {
+            return Err(custom_parse_error!(
+                info,
+                "Unrecognized encode operation {command}"
+            ));
         };
         Ok(SchedStmt::Encode {
             info,
             stmt,
             encoder,
             cmd,
-            tag
+            tag,
         })
     }
-    
-    struct_variant_factory!(sched_let_decl(lhs: Vec<(String, Option<FullType>)>, rhs: SchedExpr) 
-        -> SchedStmt:SchedStmt::Decl {
-            is_const: true,
-            expr: Some(rhs),
-            lhs: lhs
-        });
-
-    struct_variant_factory!(sched_var_decl(lhs: Vec<(String, Option<FullType>)>, rhs: Option<SchedExpr>) 
-        -> SchedStmt:SchedStmt::Decl {
-            is_const: false,
-            expr: rhs,
-            lhs: lhs
-        });
-
-    struct_variant_factory!(sched_const_seq(lhs: Vec<(String, Option<FullType>)>, rhs: SchedStmt) 
-        -> SchedStmt:SchedStmt::Seq {
-            dests: lhs,
-            block: Box::new(rhs),
-            is_const: true
-        });
+
+    struct_variant_factory!(sched_let_decl(lhs: Vec<(String, Option<FullType>)>, rhs: SchedExpr)
+    -> SchedStmt:SchedStmt::Decl {
+        is_const: true,
+        expr: Some(rhs),
+        lhs: lhs
+    });
+
+    struct_variant_factory!(sched_var_decl(lhs: Vec<(String, Option<FullType>)>, rhs: Option<SchedExpr>)
+    -> SchedStmt:SchedStmt::Decl {
+        is_const: false,
+        expr: rhs,
+        lhs: lhs
+    });
+
+    struct_variant_factory!(sched_const_seq(lhs: Vec<(String, Option<FullType>)>, rhs: SchedStmt)
+    -> SchedStmt:SchedStmt::Seq {
+        dests: lhs,
+        block: Box::new(rhs),
+        is_const: true
+    });
 
     struct_variant_factory!(sched_var_seq(lhs: Vec<(String, Option<FullType>)>, rhs: SchedStmt)
-        -> SchedStmt:SchedStmt::Seq {
-            dests: lhs,
-            block: Box::new(rhs),
-            is_const: false
-        });
+    -> SchedStmt:SchedStmt::Seq {
+        dests: lhs,
+        block: Box::new(rhs),
+        is_const: false
+    });
+
+    /// Constructs a loop in a schedule
+    /// # Errors
+    /// Returns an error if the loop header does not match the kind of loop
+    pub fn sched_loop(
+        &self,
+        l: usize,
+        dests: Vec<(Name, Option<FullType>)>,
+        state: (bool, Vec<(Name, FullType, SchedExpr)>),
+        header: (Option<(Name, Option<DataType>, Name)>, SchedExpr),
+        body: Vec<SchedStmt>,
+        r: usize,
+    ) -> Result<SchedStmt, ParserError> {
+        let info = self.info(l, r);
+        let (is_for, state) = state;
+        let state = state
+            .into_iter()
+            .map(|(name, typ, init)| LoopVar { name, typ, init })
+            .collect();
+        let header = Self::loop_header(info, is_for, header, Ok)?;
+        Ok(SchedStmt::Loop {
+            info,
+            dests,
+            state,
+            header,
+            body,
+        })
+    }
 
     #[must_use]
-    pub fn encoded_stmt(&self, l: usize, lhs: Vec<(String, Option<Vec<Tag>>)>, rhs: SchedExpr, r: usize) -> EncodedStmt {
+    pub fn encoded_stmt(
+        &self,
+        l: usize,
+        lhs: Vec<(String, Option<Vec<Tag>>)>,
+        rhs: SchedExpr,
+        r: usize,
+    ) -> EncodedStmt {
         EncodedStmt {
             info: self.info(l, r),
             lhs,
@@ -635,106 +998,202 @@ impl ASTFactory {
     }
     // TOP-Level:
 
-    #[must_use] 
-    pub fn value_funclet(&self, l: usize, name: String, input: Vec<Arg<DataType>>, 
-        output: Option<Vec<NamedOutput<DataType>>>, statements: Vec<SpecStmt>, r: usize) 
-        -> ClassMembers {
-            ClassMembers::ValueFunclet(SpecFunclet {
-                info: self.info(l, r),
-                name,
-                input,
-                output: output.unwrap_or_default(),
-                statements,
-            })
+    #[must_use]
+    pub fn value_funclet(
+        &self,
+        l: usize,
+        name: String,
+        input: Vec<Arg<DataType>>,
+        output: Option<Vec<NamedOutput<DataType>>>,
+        statements: Vec<SpecStmt>,
+        r: usize,
+    ) -> ClassMembers {
+        ClassMembers::ValueFunclet(SpecFunclet {
+            info: self.info(l, r),
+            name,
+            input,
+            output: output.unwrap_or_default(),
+            statements,
+        })
     }
 
-    #[must_use] 
-    pub fn space_funclet(&self, l: usize, name: String, input: Vec<Arg<DataType>>, 
-        output: Vec<NamedOutput<DataType>>, statements: Vec<SpecStmt>, r: usize) 
-        -> ClassMembers {
-            ClassMembers::SpatialFunclet(SpecFunclet {
-                info: self.info(l, r),
-                name,
-                input,
-                output,
-                statements,
-            })
+    #[must_use]
+    pub fn space_funclet(
+        &self,
+        l: usize,
+        name: String,
+        input: Vec<Arg<DataType>>,
+        output: Vec<NamedOutput<DataType>>,
+        statements: Vec<SpecStmt>,
+        r: usize,
+    ) -> ClassMembers {
+        ClassMembers::SpatialFunclet(SpecFunclet {
+            info: self.info(l, r),
+            name,
+            input,
+            output,
+            statements,
+        })
     }
 
-    #[must_use] 
-    pub fn time_funclet(&self, l: usize, name: String, input: Vec<Arg<DataType>>, 
-        output: Vec<NamedOutput<DataType>>, statements: Vec<SpecStmt>, r: usize) 
-        -> ClassMembers {
-            ClassMembers::TimelineFunclet(SpecFunclet {
-                info: self.info(l, r),
-                name,
-                input,
-                output,
-                statements,
-            })
+    #[must_use]
+    pub fn time_funclet(
+        &self,
+        l: usize,
+        name: String,
+        input: Vec<Arg<DataType>>,
+        output: Vec<NamedOutput<DataType>>,
+        statements: Vec<SpecStmt>,
+        r: usize,
+    ) -> ClassMembers {
+        ClassMembers::TimelineFunclet(SpecFunclet {
+            info: self.info(l, r),
+            name,
+            input,
+            output,
+            statements,
+        })
     }
 
-    struct_variant_factory!(function_class(name: String, members: Vec<ClassMembers>) 
+    struct_variant_factory!(function_class(name: String, members: Vec<ClassMembers>)
         -> TopLevel:TopLevel::FunctionClass);
 
-    struct_variant_factory!(sched_function(name: String, input: Vec<MaybeArg<FullType>>, 
-        output: Option<Vec<FullType>>, specs: Vec<String>, statements: Vec<SchedStmt>) 
-        -> TopLevel:TopLevel::SchedulingFunc {
-            name: name,
-            input: input,
-            output: output.unwrap_or_default(),
-            specs: specs,
-            statements: statements
-        });
-
-    struct_variant_factory!(extern_func(device: String, name: String, input: Vec<(Option<String>, DataType)>, 
-        output: Option<Vec<NamedOutput<DataType>>>, def: Option<ExternDef>) -> ClassMembers:ClassMembers::Extern {
-            device: device,
-            def: def,
-            name: name,
-            input: input,
-            output: output.unwrap_or_default(),
-            pure: false
-        });
-
-    struct_variant_factory!(extern_pure_func(device: String, name: String, input: Vec<(Option<String>, DataType)>, 
-        output: Option<Vec<NamedOutput<DataType>>>, def: Option<ExternDef>) -> ClassMembers:ClassMembers::Extern {
-            device: device,
-            def: def,
-            name: name,
-            input: input,
-            output: output.unwrap_or_default(),
-            pure: true
-        });
-    
+    struct_variant_factory!(sched_function(name: String, input: Vec<MaybeArg<FullType>>,
+    output: Option<Vec<FullType>>, specs: Vec<String>, statements: Vec<SchedStmt>)
+    -> TopLevel:TopLevel::SchedulingFunc {
+        name: name,
+        input: input,
+        output: output.unwrap_or_default(),
+        specs: specs,
+        statements: statements
+    });
+
+    struct_variant_factory!(extern_func(device: String, name: String, input: Vec<(Option<String>, DataType)>,
+    output: Option<Vec<NamedOutput<DataType>>>, def: Option<ExternDef>) -> ClassMembers:ClassMembers::Extern {
+        device: device,
+        def: def,
+        name: name,
+        input: input,
+        output: output.unwrap_or_default(),
+        pure: false
+    });
+
+    struct_variant_factory!(extern_pure_func(device: String, name: String, input: Vec<(Option<String>, DataType)>,
+    output: Option<Vec<NamedOutput<DataType>>>, def: Option<ExternDef>) -> ClassMembers:ClassMembers::Extern {
+        device: device,
+        def: def,
+        name: name,
+        input: input,
+        output: output.unwrap_or_default(),
+        pure: true
+    });
+
     /// Constructs a function class for a single class member (value or external function)
     #[must_use]
     pub fn singleton_function_class(&self, member: ClassMembers) -> TopLevel {
-        TopLevel::FunctionClass { info: member.get_info(), name: member.get_name(), members: vec![member] }
+        TopLevel::FunctionClass {
+            info: member.get_info(),
+            name: member.get_name(),
+            members: vec![member],
+        }
     }
 
     struct_variant_factory!(pipeline(name: String, entry: String) -> TopLevel:TopLevel::Pipeline);
 
-    pub fn type_def(&mut self, l: usize, name: Name, typ: DataType, r:usize) -> TopLevel {
+    pub fn type_def(&mut self, l: usize, name: Name, typ: DataType, r: usize) -> TopLevel {
         self.type_map.insert(name.clone(), typ.clone());
-        TopLevel::Typedef { info: self.info(l, r), name, typ: typ.into() }
+        TopLevel::Typedef {
+            info: self.info(l, r),
+            name,
+            typ: typ.into(),
+        }
     }
 
-    /// Replaces a user-defined type with a concrete type
+    /// Replaces a user-defined type with a concrete type. If the type is not
+    /// found but may be defined by an import, it is left as a user-defined type
     /// # Errors
     /// Returns an error if the user-defined type is not found
-    #[allow(clippy::needless_pass_by_value)]
-    pub fn user_defined_type(&self, l: usize, name: String, r:usize, ) -> Result<DataType, ParserError> {
+    pub fn user_defined_type(
+        &mut self,
+        l: usize,
+        name: String,
+        r: usize,
+    ) -> Result<DataType, ParserError> {
         let info = self.info(l, r);
-        self.type_map.get(&name).cloned().ok_or_else(|| custom_parse_error!(info, "Undefined type {name}"))
+        if let Some(t) = self.type_map.get(&name) {
+            return Ok(t.clone());
+        }
+        if let ImportedTypes::Pending { deferred } = &mut self.imported_types {
+            *deferred = true;
+            return Ok(DataType::UserDefined(name));
+        }
+        Err(custom_parse_error!(info, "Undefined type {name}"))
+    }
+
+    /// Constructs an array type whose length is the constant `len`, replacing
+    /// the constant with its value. If the constant is not found, the length
+    /// is left as a name, which gives the array an erased length
+    /// # Errors
+    /// Returns an error if the constant is not an integer
+    pub fn named_length_array(
+        &mut self,
+        l: usize,
+        element: DataType,
+        len: String,
+        r: usize,
+    ) -> Result<DataType, ParserError> {
+        match self.const_map.get(&len) {
+            Some(SpecExpr::Term(SpecTerm::Lit {
+                lit: SpecLiteral::Int(n, _),
+                ..
+            })) => {
+                return Ok(DataType::array(element, n));
+            }
+            Some(_) => {
+                return Err(custom_parse_error!(
+                    self.info(l, r),
+                    "Array length {len} is not an integer constant"
+                ))
+            }
+            None => (),
+        }
+        if let ImportedTypes::Pending { deferred } = &mut self.imported_types {
+            *deferred = true;
+        }
+        Ok(DataType::array(element, &len))
+    }
+
+    /// Constructs a GPU buffer type of `size` bytes.
+    /// # Errors
+    /// Returns an error if the size is zero or doesn't fit in a `usize`
+    pub fn gpu_buffer_type(&self, l: usize, size: &str, r: usize) -> Result<DataType, ParserError> {
+        match size.parse::<usize>() {
+            Ok(size) if size > 0 => Ok(DataType::GpuBuffer(size)),
+            _ => Err(custom_parse_error!(
+                self.info(l, r),
+                "Invalid buffer size {size}"
+            )),
+        }
     }
 
     /// Constructs a constant definition from a name and expression. Checks that
     /// the expression is a valid constant expression and returns an error if not
     /// # Errors
     /// Returns an error if the expression is not a constant expression
-    pub fn const_def(&self, l: usize, name: Name, expr: SchedExpr, r: usize) -> Result<TopLevel, ParserError> {
-        self.const_expr(Self::sched_to_spec_expr(expr)?).map(|expr| TopLevel::Const { info: self.info(l, r), name, expr })
+    pub fn const_def(
+        &mut self,
+        l: usize,
+        name: Name,
+        expr: SchedExpr,
+        r: usize,
+    ) -> Result<TopLevel, ParserError> {
+        let expr = self.const_expr(Self::sched_to_spec_expr(expr)?)?;
+        self.const_map.insert(name.clone(), expr.clone());
+        Ok(TopLevel::Const {
+            info: self.info(l, r),
+            name,
+            expr,
+        })
     }
 
     /// Constructs a program from a list of top level declarations, checking the
@@ -742,22 +1201,40 @@ impl ASTFactory {
     /// Constructs a high-level-caiman program
     /// # Errors
     /// Returns an error if the program is not a valid high-level-caiman program
-    pub fn program(&self, maj_min: &str, patch: &str, prog: Program) -> Result<Program, ParserError> {
+    pub fn program(
+        &self,
+        maj_min: &str,
+        patch: &str,
+        prog: Program,
+    ) -> Result<Program, ParserError> {
         let split_maj_min: Vec<_> = maj_min.split('.').collect();
         if split_maj_min.len() != 2 {
-            return Err(custom_parse_error!(Info {
-                start_ln_and_col: (0, 0),
-                end_ln_and_col: (0, 0),
-            }, "Invalid version string: {}.{}", maj_min, patch));
+            return Err(custom_parse_error!(
+                Info {
+                    start_ln_and_col: (0, 0),
+                    end_ln_and_col: (0, 0),
+                },
+                "Invalid version string: {}.{}",
+                maj_min,
+                patch
+            ));
         }
         let maj = split_maj_min[0];
         let min = split_maj_min[1];
         if (MAJOR_VERSION, MINOR_VERSION, PATCH_VERSION) != (maj, min, patch) {
-            return Err(custom_parse_error!(Info {
-                start_ln_and_col: (0, 0),
-                end_ln_and_col: (0, 0),
-            }, "Version mismatch: expected {}.{}.{} but found {}.{}.{}", 
-                MAJOR_VERSION, MINOR_VERSION, PATCH_VERSION, maj, min, patch));
+            return Err(custom_parse_error!(
+                Info {
+                    start_ln_and_col: (0, 0),
+                    end_ln_and_col: (0, 0),
+                },
+                "Version mismatch: expected {}.{}.{} but found {}.{}.{}",
+                MAJOR_VERSION,
+                MINOR_VERSION,
+                PATCH_VERSION,
+                maj,
+                min,
+                patch
+            ));
         }
         Ok(prog)
     }
@@ -768,7 +1245,12 @@ impl ASTFactory {
     /// back to a record. See `finalize_data_type`.
     /// # Errors
     /// Returns an error if the remote object has invalid settings or flags
-    pub fn class_type(&self, l:usize, v: Vec<Arg<FlaggedType>>, r:usize) -> Result<DataType, ParserError> {
+    pub fn class_type(
+        &self,
+        l: usize,
+        v: Vec<Arg<FlaggedType>>,
+        r: usize,
+    ) -> Result<DataType, ParserError> {
         let mut all = Vec::new();
         let mut read = BTreeSet::new();
         let mut write = BTreeSet::new();
@@ -776,13 +1258,20 @@ impl ASTFactory {
         for (name, typ) in v {
             all.push((name.clone(), typ.base));
             if !typ.settings.is_empty() {
-                return Err(custom_parse_error!(info, "Settings are not implemented yet"));
+                return Err(custom_parse_error!(
+                    info,
+                    "Settings are not implemented yet"
+                ));
             }
             for f in typ.flags {
                 match f {
-                    WGPUFlags::MapRead => { read.insert(name.clone()); },
-                    WGPUFlags::CopyDst => { write.insert(name.clone()); },
-                    WGPUFlags::Storage => {},
+                    WGPUFlags::MapRead => {
+                        read.insert(name.clone());
+                    }
+                    WGPUFlags::CopyDst => {
+                        write.insert(name.clone());
+                    }
+                    WGPUFlags::Storage => {}
                     _ => return Err(custom_parse_error!(info, "Unimplemented flag {f:?}")),
                 };
             }
@@ -0,0 +1,306 @@
+//! Loads the files imported by a program and merges them into a single program.
+//!
+//! An `import` names a file relative to the directory of the importing file.
+//! A path without an extension refers to a `.cm` file, so `import sorting`
+//! loads `sorting.cm` and `import "lib/reduce.cm"` loads `lib/reduce.cm`.
+//! Each file is loaded once no matter how many files import it, and the
+//! definitions of imported files precede those of the importing file in the
+//! merged program.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use crate::error::{type_error, Error, ErrorKind, ErrorLocation, Info, LocalError};
+
+use super::{
+    ast::{ClassMembers, DataType, Program, SpecExpr, TopLevel},
+    ast_factory::ASTFactory,
+    parse_string,
+};
+
+/// The kinds of top-level names. Names of different kinds may be the same.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum NameKind {
+    Function,
+    Type,
+    Const,
+    Pipeline,
+}
+
+impl std::fmt::Display for NameKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Function => write!(f, "Function"),
+            Self::Type => write!(f, "Type"),
+            Self::Const => write!(f, "Constant"),
+            Self::Pipeline => write!(f, "Pipeline"),
+        }
+    }
+}
+
+/// Gets the names defined by a top-level declaration
+fn defined_names(decl: &TopLevel) -> Vec<(NameKind, &String)> {
+    match decl {
+        TopLevel::FunctionClass { name, members, .. } => {
+            let mut names = vec![(NameKind::Function, name)];
+            for m in members {
+                if let ClassMembers::ValueFunclet(f)
+                | ClassMembers::TimelineFunclet(f)
+                | ClassMembers::SpatialFunclet(f) = m
+                {
+                    names.push((NameKind::Function, &f.name));
+                } else if let ClassMembers::Extern { name, .. } = m {
+                    names.push((NameKind::Function, name));
+                }
+            }
+            names.sort();
+            names.dedup();
+            names
+        }
+        TopLevel::SchedulingFunc { name, .. } => vec![(NameKind::Function, name)],
+        TopLevel::Typedef { name, .. } => vec![(NameKind::Type, name)],
+        TopLevel::Const { name, .. } => vec![(NameKind::Const, name)],
+        TopLevel::Pipeline { name, .. } => vec![(NameKind::Pipeline, name)],
+        TopLevel::Import { .. } => vec![],
+    }
+}
+
+/// Gets the location of a top-level declaration
+const fn decl_info(decl: &TopLevel) -> Info {
+    match decl {
+        TopLevel::FunctionClass { info, .. }
+        | TopLevel::SchedulingFunc { info, .. }
+        | TopLevel::Typedef { info, .. }
+        | TopLevel::Const { info, .. }
+        | TopLevel::Pipeline { info, .. }
+        | TopLevel::Import { info, .. } => *info,
+    }
+}
+
+/// Attaches a filename to an error
+fn in_file(filename: &str) -> impl Fn(LocalError) -> Error + '_ {
+    move |error| Error {
+        error,
+        filename: filename.to_string(),
+    }
+}
+
+/// Loads a program and the files it imports
+pub struct Loader {
+    /// The canonical path and name of the files currently being loaded, each
+    /// imported by the one before it
+    stack: Vec<(PathBuf, String)>,
+    /// The files that have been loaded or are being loaded
+    loaded: HashSet<PathBuf>,
+    /// The types defined by the files loaded so far
+    types: HashMap<String, DataType>,
+    /// The constants defined by the files loaded so far
+    consts: HashMap<String, SpecExpr>,
+    /// The file and location of the definition of each top-level name
+    defs: HashMap<(NameKind, String), (String, Info)>,
+    /// The merged program
+    program: Program,
+    /// The file each declaration of the merged program was read from
+    files: Vec<String>,
+}
+
+impl Loader {
+    pub fn new() -> Self {
+        Self {
+            stack: vec![],
+            loaded: HashSet::new(),
+            types: HashMap::new(),
+            consts: HashMap::new(),
+            defs: HashMap::new(),
+            program: vec![],
+            files: vec![],
+        }
+    }
+
+    /// Loads the program in `src`, which was read from the file at `path` if
+    /// it has one. Imports are resolved relative to `dir`.
+    /// # Errors
+    /// Returns an error if a file cannot be parsed or loaded, if the imports
+    /// form a cycle, or if a name is defined more than once
+    pub fn load(
+        self,
+        src: &str,
+        filename: &str,
+        path: Option<&Path>,
+        dir: &Path,
+    ) -> Result<Program, Error> {
+        self.load_with_files(src, filename, path, dir)
+            .map(|(program, _)| program)
+    }
+
+    /// Like `load`, but also returns the file each declaration of the merged
+    /// program was read from
+    /// # Errors
+    /// Same as `load`
+    pub fn load_with_files(
+        mut self,
+        src: &str,
+        filename: &str,
+        path: Option<&Path>,
+        dir: &Path,
+    ) -> Result<(Program, Vec<String>), Error> {
+        if let Some(path) = path.and_then(|p| p.canonicalize().ok()) {
+            self.loaded.insert(path.clone());
+            self.stack.push((path, filename.to_string()));
+        }
+        self.load_source(src, filename, dir)?;
+        Ok((self.program, self.files))
+    }
+
+    /// Parses a file, loads its imports, and adds its declarations to the
+    /// merged program
+    fn load_source(&mut self, src: &str, filename: &str, dir: &Path) -> Result<(), Error> {
+        let mut astf = ASTFactory::new(filename, src);
+        let mut prog = parse_string(src, &mut astf).map_err(in_file(filename))?;
+        for decl in &prog {
+            if let TopLevel::Import { info, path } = decl {
+                self.load_import(filename, dir, *info, path)?;
+            }
+        }
+        if astf.deferred_imported_types() {
+            let mut astf = ASTFactory::with_imported_types(
+                filename,
+                src,
+                self.types.clone(),
+                self.consts.clone(),
+            );
+            prog = parse_string(src, &mut astf).map_err(in_file(filename))?;
+        }
+        for decl in prog {
+            if matches!(decl, TopLevel::Import { .. }) {
+                continue;
+            }
+            let info = decl_info(&decl);
+            for (kind, name) in defined_names(&decl) {
+                if let Some((other_file, other_info)) = self.defs.get(&(kind, name.clone())) {
+                    return Err(in_file(filename)(type_error(
+                        info,
+                        &format!(
+                            "{kind} {name} is already defined at \"{other_file}\" {other_info}"
+                        ),
+                    )));
+                }
+                self.defs
+                    .insert((kind, name.clone()), (filename.to_string(), info));
+            }
+            match &decl {
+                TopLevel::Typedef { name, typ, .. } => {
+                    self.types.insert(name.clone(), typ.base.clone());
+                }
+                TopLevel::Const { name, expr, .. } => {
+                    self.consts.insert(name.clone(), expr.clone());
+                }
+                _ => (),
+            }
+            self.program.push(decl);
+            self.files.push(filename.to_string());
+        }
+        Ok(())
+    }
+
+    /// Loads the file imported by `importer` at `path`, unless it has already
+    /// been loaded
+    fn load_import(
+        &mut self,
+        importer: &str,
+        dir: &Path,
+        info: Info,
+        path: &str,
+    ) -> Result<(), Error> {
+        let mut path = dir.join(path);
+        if path.extension().is_none() {
+            path.set_extension("cm");
+        }
+        let io_error = |e: std::io::Error| {
+            in_file(importer)(LocalError {
+                kind: ErrorKind::IO(format!("Cannot import {}: {e}", path.display())),
+                location: ErrorLocation::Double(info),
+            })
+        };
+        let canonical = path.canonicalize().map_err(io_error)?;
+        let filename = path.display().to_string();
+        if let Some(start) = self.stack.iter().position(|(p, _)| p == &canonical) {
+            let cycle: Vec<_> = self.stack[start..]
+                .iter()
+                .map(|(_, name)| name.as_str())
+                .chain(std::iter::once(filename.as_str()))
+                .collect();
+            return Err(in_file(importer)(type_error(
+                info,
+                &format!("Import cycle: {}", cycle.join(" -> ")),
+            )));
+        }
+        if !self.loaded.insert(canonical.clone()) {
+            return Ok(());
+        }
+        let src = std::fs::read_to_string(&canonical).map_err(io_error)?;
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        self.stack.push((canonical, filename.clone()));
+        self.load_source(&src, &filename, dir)?;
+        self.stack.pop();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bad_import(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../caiman-test/high-level-caiman/imports/bad")
+            .join(name)
+    }
+
+    fn load_file(path: &Path) -> Result<Program, Error> {
+        let src = std::fs::read_to_string(path).unwrap();
+        let filename = path.display().to_string();
+        Loader::new().load(&src, &filename, Some(path), path.parent().unwrap())
+    }
+
+    fn location(error: &Error) -> Info {
+        match error.error.location {
+            ErrorLocation::Double(info) => info,
+            ErrorLocation::Single(..) => panic!("{error}"),
+        }
+    }
+
+    #[test]
+    fn import_cycle() {
+        let cycle_a = bad_import("cycle_a.cm");
+        let error = load_file(&cycle_a).unwrap_err();
+        // reported at `import cycle_a` in the file that closes the cycle
+        assert!(error.filename.ends_with("cycle_b.cm"), "{error}");
+        assert_eq!(location(&error).start_ln_and_col, (3, 1));
+        let a = cycle_a.display().to_string();
+        let b = bad_import("cycle_b.cm").display().to_string();
+        assert!(
+            error
+                .to_string()
+                .ends_with(&format!("Import cycle: {a} -> {b} -> {a}")),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn duplicate_definition() {
+        let error = load_file(&bad_import("duplicate_time.cm")).unwrap_err();
+        // reported at the second definition, naming the first
+        assert!(error.filename.ends_with("duplicate_time.cm"), "{error}");
+        assert_eq!(location(&error).start_ln_and_col, (6, 1));
+        let common = bad_import("../common.cm").display().to_string();
+        assert!(
+            error.to_string().ends_with(&format!(
+                "Function time is already defined at \"{common}\" 5:1 - 5:43"
+            )),
+            "{error}"
+        );
+    }
+}
@@ -1,13 +1,14 @@
 pub mod ast;
 pub mod ast_factory;
+mod imports;
 pub mod parser;
 
-use std::{fs::File, path::Path};
+use std::{fs::File, io::Read, path::Path};
 
 use crate::error::{CustomParsingError, Error, ErrorKind, ErrorLocation, LocalError};
 use lalrpop_util::ParseError;
 
-use self::ast_factory::ASTFactory;
+use self::{ast_factory::ASTFactory, imports::Loader};
 
 /// Construct a `LocalError` from a `ParsingError`
 fn make_error((l, c): (usize, usize), p: &str) -> LocalError {
@@ -45,45 +46,56 @@ fn parse_error_to_error(
     }
 }
 
-fn parse_string(buf: &str, filename: &str) -> Result<ast::Program, LocalError> {
+fn parse_string(buf: &str, ast_factory: &mut ASTFactory) -> Result<ast::Program, LocalError> {
     let parser = parser::ProgramParser::new();
-    let mut ast_factory = ASTFactory::new(filename, buf);
     parser
-        .parse(&mut ast_factory, buf)
-        .map_err(|e| parse_error_to_error(e, &ast_factory))
+        .parse(ast_factory, buf)
+        .map_err(|e| parse_error_to_error(e, ast_factory))
 }
 
-/// Parses a string into an AST
+/// Parses a string into an AST, loading any imported files relative to the
+/// current directory
 /// # Errors
-/// Returns an error if the string cannot be parsed
+/// Returns an error if the string or an imported file cannot be parsed
 /// # Panics
 /// Panics if the string cannot be read
 #[allow(clippy::module_name_repetitions)]
-pub fn parse_read<R: std::io::Read>(
-    mut input: R,
-    filename: &str,
-) -> Result<ast::Program, LocalError> {
+pub fn parse_read<R: std::io::Read>(mut input: R, filename: &str) -> Result<ast::Program, Error> {
     let mut buf = String::new();
     input.read_to_string(&mut buf).unwrap();
-    parse_string(&buf, filename)
+    Loader::new().load(&buf, filename, None, Path::new("."))
 }
 
-/// Parses a file into an AST
+/// Parses the contents of the file `filename`, which may differ from what is
+/// saved on disk, loading any imported files relative to the directory of the
+/// file. Also returns the file each top-level declaration was read from.
 ///
 /// # Errors
-/// Returns an error if the file cannot be opened or if the file cannot be parsed
+/// Returns an error if the source or an imported file cannot be parsed
+pub fn parse_source(src: &str, filename: &str) -> Result<(ast::Program, Vec<String>), Error> {
+    let input_path = Path::new(filename);
+    let dir = input_path.parent().unwrap_or_else(|| Path::new("."));
+    Loader::new().load_with_files(src, filename, Some(input_path), dir)
+}
+
+/// Parses a file into an AST, loading any imported files relative to the
+/// directory of the file
+///
+/// # Errors
+/// Returns an error if the file cannot be opened or if the file or an imported
+/// file cannot be parsed
 #[allow(clippy::module_name_repetitions)]
 pub fn parse_file(filename: &str) -> Result<ast::Program, Error> {
     let input_path = Path::new(filename);
-    let input_file = File::open(input_path).map_err(|e| Error {
+    let io_error = |e: std::io::Error| Error {
         error: LocalError {
             kind: ErrorKind::IO(e.to_string()),
             location: ErrorLocation::Single(0, 0),
         },
         filename: filename.to_string(),
-    })?;
-    parse_read(input_file, filename).map_err(|e| Error {
-        error: e,
-        filename: filename.to_string(),
-    })
+    };
+    let mut input_file = File::open(input_path).map_err(io_error)?;
+    let mut buf = String::new();
+    input_file.read_to_string(&mut buf).map_err(io_error)?;
+    parse_source(&buf, filename).map(|(program, _)| program)
 }
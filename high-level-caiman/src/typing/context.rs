@@ -1,9 +1,8 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 use crate::error::{type_error, Info, LocalError};
-use crate::lower::binop_to_str;
 use crate::parse::ast::{
-    ExternDef, FlaggedType, FullType, IntSize, SpecExpr, SpecFunclet, SpecStmt, SpecTerm,
+    ExternDef, FloatSize, FullType, IntSize, SpecExpr, SpecFunclet, SpecStmt, SpecTerm,
 };
 use crate::typing::{
     ENCODE_DST_FLAGS, ENCODE_IO_FLAGS, ENCODE_SRC_FLAGS, ENCODE_STORAGE_FLAGS, LOCAL_TEMP_FLAGS,
@@ -20,14 +19,59 @@ use super::specs::collect_spec;
 use super::types::DTypeConstraint;
 use super::{
     sig_match, Context, DTypeEnv, Mutability, NamedSignature, SchedInfo, SchedOrExtern, Signature,
-    SpecInfo, SpecType, TypedBinop,
+    SpecInfo, SpecType, TypedOp,
 };
 
+/// Gets the type declarations for a value type named `name` which is stored as
+/// `storage_type`: the value itself, a local reference, and the GPU references
+/// used when encoding, which are named after their flags.
+fn value_type_decls(name: &str, storage_type: &asm::FFIType) -> Vec<asm::Declaration> {
+    let local = |name: String, data| {
+        asm::Declaration::TypeDecl(asm::TypeDecl::Local(asm::LocalType { name, data }))
+    };
+    let gpu_ref = |name: String, buffer_flags| {
+        local(
+            name,
+            asm::LocalTypeInfo::Ref {
+                storage_type: storage_type.clone(),
+                storage_place: ir::Place::Gpu,
+                buffer_flags,
+            },
+        )
+    };
+    vec![
+        local(
+            name.to_string(),
+            asm::LocalTypeInfo::NativeValue {
+                storage_type: storage_type.clone(),
+            },
+        ),
+        local(
+            format!("&{name}"),
+            asm::LocalTypeInfo::Ref {
+                storage_type: storage_type.clone(),
+                storage_place: ir::Place::Local,
+                buffer_flags: LOCAL_TEMP_FLAGS,
+            },
+        ),
+        gpu_ref(format!("{name}::gs"), ENCODE_SRC_FLAGS),
+        gpu_ref(format!("{name}::gd"), ENCODE_DST_FLAGS),
+        gpu_ref(format!("{name}::g"), ENCODE_STORAGE_FLAGS),
+        gpu_ref(format!("{name}::gds"), ENCODE_IO_FLAGS),
+        gpu_ref(format!("&{name}::gs"), ENCODE_SRC_FLAGS),
+        gpu_ref(format!("&{name}::gds"), ENCODE_IO_FLAGS),
+    ]
+}
+
 /// Gets a list of type declarations for the base types used in the program.
-#[allow(clippy::too_many_lines)]
 fn gen_type_decls(_tl: &[TopLevel]) -> Vec<asm::Declaration> {
     // TODO: collect used types instead of declaring every possible type?
-    vec![
+    let numeric_types: Vec<_> = IntSize::ALL
+        .into_iter()
+        .map(DataType::Int)
+        .chain(FloatSize::ALL.into_iter().map(DataType::Float))
+        .collect();
+    let mut decls = vec![
         asm::Declaration::TypeDecl(asm::TypeDecl::Local(asm::LocalType {
             name: String::from("BufferSpace"),
             data: asm::LocalTypeInfo::BufferSpace,
@@ -48,123 +92,164 @@ fn gen_type_decls(_tl: &[TopLevel]) -> Vec<asm::Declaration> {
                 queue_place: ir::Place::Gpu,
             },
         })),
-        asm::Declaration::TypeDecl(asm::TypeDecl::FFI(asm::FFIType::I64)),
-        asm::Declaration::TypeDecl(asm::TypeDecl::FFI(BOOL_FFI_TYPE)),
-        asm::Declaration::TypeDecl(asm::TypeDecl::Local(asm::LocalType {
+    ];
+    // the storage type of bools is one of the numeric types
+    for typ in &numeric_types {
+        decls.push(asm::Declaration::TypeDecl(asm::TypeDecl::FFI(
+            typ.ffi().unwrap(),
+        )));
+    }
+    decls.push(asm::Declaration::TypeDecl(asm::TypeDecl::Local(
+        asm::LocalType {
             name: String::from("bool"),
             data: asm::LocalTypeInfo::NativeValue {
                 storage_type: BOOL_FFI_TYPE,
             },
-        })),
-        asm::Declaration::TypeDecl(asm::TypeDecl::Local(asm::LocalType {
+        },
+    )));
+    decls.push(asm::Declaration::TypeDecl(asm::TypeDecl::Local(
+        asm::LocalType {
             name: String::from("&bool"),
             data: asm::LocalTypeInfo::Ref {
                 storage_type: BOOL_FFI_TYPE,
                 storage_place: ir::Place::Local,
                 buffer_flags: LOCAL_TEMP_FLAGS,
             },
-        })),
-        asm::Declaration::TypeDecl(asm::TypeDecl::Local(asm::LocalType {
-            name: String::from("i64"),
-            data: asm::LocalTypeInfo::NativeValue {
-                storage_type: asm::FFIType::I64,
-            },
-        })),
-        asm::Declaration::TypeDecl(asm::TypeDecl::Local(asm::LocalType {
-            name: String::from("&i64"),
-            data: asm::LocalTypeInfo::Ref {
-                storage_type: asm::FFIType::I64,
-                storage_place: ir::Place::Local,
-                buffer_flags: LOCAL_TEMP_FLAGS,
-            },
-        })),
-        asm::Declaration::TypeDecl(asm::TypeDecl::Local(asm::LocalType {
-            name: String::from("i64::gs"),
-            data: asm::LocalTypeInfo::Ref {
-                storage_type: asm::FFIType::I64,
-                storage_place: ir::Place::Gpu,
-                buffer_flags: ENCODE_SRC_FLAGS,
-            },
-        })),
-        asm::Declaration::TypeDecl(asm::TypeDecl::Local(asm::LocalType {
-            name: String::from("i64::gd"),
-            data: asm::LocalTypeInfo::Ref {
-                storage_type: asm::FFIType::I64,
-                storage_place: ir::Place::Gpu,
-                buffer_flags: ENCODE_DST_FLAGS,
-            },
-        })),
-        asm::Declaration::TypeDecl(asm::TypeDecl::Local(asm::LocalType {
-            name: String::from("i64::g"),
-            data: asm::LocalTypeInfo::Ref {
-                storage_type: asm::FFIType::I64,
-                storage_place: ir::Place::Gpu,
-                buffer_flags: ENCODE_STORAGE_FLAGS,
-            },
-        })),
-        asm::Declaration::TypeDecl(asm::TypeDecl::Local(asm::LocalType {
-            name: String::from("i32"),
-            data: asm::LocalTypeInfo::NativeValue {
-                storage_type: asm::FFIType::I32,
-            },
-        })),
-        asm::Declaration::TypeDecl(asm::TypeDecl::Local(asm::LocalType {
-            name: String::from("&i32"),
-            data: asm::LocalTypeInfo::Ref {
-                storage_type: asm::FFIType::I32,
-                storage_place: ir::Place::Local,
-                buffer_flags: LOCAL_TEMP_FLAGS,
-            },
-        })),
-        asm::Declaration::TypeDecl(asm::TypeDecl::Local(asm::LocalType {
-            name: String::from("&i32::gs"),
-            data: asm::LocalTypeInfo::Ref {
-                storage_type: asm::FFIType::I32,
-                storage_place: ir::Place::Gpu,
-                buffer_flags: ENCODE_SRC_FLAGS,
-            },
-        })),
-        asm::Declaration::TypeDecl(asm::TypeDecl::Local(asm::LocalType {
-            name: String::from("i32::gs"),
-            data: asm::LocalTypeInfo::Ref {
-                storage_type: asm::FFIType::I32,
-                storage_place: ir::Place::Gpu,
-                buffer_flags: ENCODE_SRC_FLAGS,
-            },
-        })),
-        asm::Declaration::TypeDecl(asm::TypeDecl::Local(asm::LocalType {
-            name: String::from("i32::gd"),
-            data: asm::LocalTypeInfo::Ref {
-                storage_type: asm::FFIType::I32,
-                storage_place: ir::Place::Gpu,
-                buffer_flags: ENCODE_DST_FLAGS,
-            },
-        })),
-        asm::Declaration::TypeDecl(asm::TypeDecl::Local(asm::LocalType {
-            name: String::from("i32::g"),
-            data: asm::LocalTypeInfo::Ref {
-                storage_type: asm::FFIType::I32,
-                storage_place: ir::Place::Gpu,
-                buffer_flags: ENCODE_STORAGE_FLAGS,
-            },
-        })),
-        asm::Declaration::TypeDecl(asm::TypeDecl::Local(asm::LocalType {
-            name: String::from("&i32::gds"),
-            data: asm::LocalTypeInfo::Ref {
-                storage_type: asm::FFIType::I32,
-                storage_place: ir::Place::Gpu,
-                buffer_flags: ENCODE_IO_FLAGS,
-            },
-        })),
-        asm::Declaration::TypeDecl(asm::TypeDecl::Local(asm::LocalType {
-            name: String::from("i32::gds"),
-            data: asm::LocalTypeInfo::Ref {
-                storage_type: asm::FFIType::I32,
-                storage_place: ir::Place::Gpu,
-                buffer_flags: ENCODE_IO_FLAGS,
-            },
-        })),
-    ]
+        },
+    )));
+    for typ in &numeric_types {
+        decls.extend(value_type_decls(&typ.asm_type().0, &typ.ffi().unwrap()));
+    }
+    decls
+}
+
+/// Determines if `var` is one of the device variables of the encoder or fence
+/// with type `typ`.
+fn is_device_var(typ: Option<&DataType>, var: &str) -> bool {
+    match typ {
+        Some(DataType::Encoder(Some(obj)) | DataType::Fence(Some(obj))) => {
+            matches!(&**obj, DataType::RemoteObj { all, .. } if all.iter().any(|(name, _)| name == var))
+        }
+        _ => false,
+    }
+}
+
+/// Adds `typ` and any array, slice, or buffer types nested within it to
+/// `aggregates`, keyed by their assembly type name, along with the location of
+/// the declaration `info` that first used them.
+/// # Errors
+/// Returns an error if an array's length is not a literal or a name.
+fn collect_aggregate_types(
+    typ: &DataType,
+    info: Info,
+    aggregates: &mut BTreeMap<String, (DataType, Info)>,
+) -> Result<(), LocalError> {
+    match typ {
+        DataType::Ref(t) => collect_aggregate_types(t, info, aggregates)?,
+        DataType::GpuBuffer(_) => {
+            aggregates
+                .entry(typ.asm_type().0)
+                .or_insert_with(|| (typ.clone(), info));
+        }
+        DataType::Array(elem, _) | DataType::Slice(elem) => {
+            typ.array_length()?;
+            collect_aggregate_types(elem, info, aggregates)?;
+            aggregates
+                .entry(typ.asm_type().0)
+                .or_insert_with(|| (typ.clone(), info));
+        }
+        _ => (),
+    }
+    Ok(())
+}
+
+/// Gets the location of each function class, class member, and schedule, by name.
+fn declaration_infos(tl: &[TopLevel]) -> HashMap<String, Info> {
+    let mut infos = HashMap::new();
+    for decl in tl {
+        match decl {
+            TopLevel::FunctionClass {
+                info,
+                name,
+                members,
+            } => {
+                infos.insert(name.clone(), *info);
+                for member in members {
+                    infos.insert(member.get_name(), member.get_info());
+                }
+            }
+            TopLevel::SchedulingFunc { info, name, .. } => {
+                infos.insert(name.clone(), *info);
+            }
+            _ => (),
+        }
+    }
+    infos
+}
+
+/// Gets the type declarations for every array, slice, and buffer type used by a
+/// well-typed program. Unlike the base types, these can't be declared
+/// up front since their element types and sizes are unbounded.
+/// # Errors
+/// Returns an error if an array's length is not a literal or a name, or if an
+/// array's elements have no assembly equivalent.
+fn gen_aggregate_type_decls(
+    tl: &[TopLevel],
+    ctx: &Context,
+) -> Result<Vec<asm::Declaration>, LocalError> {
+    let infos = declaration_infos(tl);
+    let info_of = |name: &String| infos.get(name).copied().unwrap_or_default();
+    let sig_types = ctx
+        .signatures
+        .iter()
+        .map(|(name, sig)| (name, sig))
+        .chain(ctx.scheds.iter().map(|(name, s)| (name, s.sig())))
+        .flat_map(|(name, sig)| {
+            sig.input
+                .iter()
+                .chain(sig.output.iter())
+                .map(move |ft| (name, &ft.base))
+        });
+    let var_types = ctx
+        .specs
+        .iter()
+        .flat_map(|(name, spec)| spec.types.values().map(move |t| (name, t)))
+        .chain(ctx.scheds.iter().flat_map(|(name, s)| match s {
+            SchedOrExtern::Sched(s) => Box::new(s.types.values().map(move |t| (name, t)))
+                as Box<dyn Iterator<Item = (&String, &DataType)>>,
+            SchedOrExtern::Extern(_) => Box::new(std::iter::empty()),
+        }));
+    let mut aggregates = BTreeMap::new();
+    for (name, typ) in sig_types.chain(var_types) {
+        collect_aggregate_types(typ, info_of(name), &mut aggregates)?;
+    }
+    let mut decls = vec![];
+    for (name, (typ, info)) in aggregates {
+        if let DataType::GpuBuffer(byte_size) = typ {
+            // suballocations share the flags of their buffer, so a buffer
+            // must allow everything an encoded variable can be used for
+            decls.push(asm::Declaration::TypeDecl(asm::TypeDecl::Local(
+                asm::LocalType {
+                    name,
+                    data: asm::LocalTypeInfo::Buffer {
+                        storage_place: ir::Place::Gpu,
+                        static_layout_opt: Some(ir::StaticBufferLayout {
+                            alignment_bits: 0,
+                            byte_size,
+                        }),
+                        flags: ENCODE_IO_FLAGS,
+                    },
+                },
+            )));
+            continue;
+        }
+        let ffi = typ
+            .ffi()
+            .ok_or_else(|| type_error(info, &format!("Arrays of {typ} are not supported")))?;
+        decls.push(asm::Declaration::TypeDecl(asm::TypeDecl::FFI(ffi.clone())));
+        decls.extend(value_type_decls(&name, &ffi));
+    }
+    Ok(decls)
 }
 
 fn get_other_decls() -> Vec<asm::Declaration> {
@@ -394,22 +479,56 @@ fn type_check_schedules(tl: &[TopLevel], mut ctx: Context) -> Result<Context, Lo
             for (k, v) in env.flags {
                 sched_info.flags.insert(k, v);
             }
+            // fences only have the suballocations propagated from their encoder,
+            // so errors are reported for the encoder
+            let mut buffered: Vec<_> = env
+                .buffered
+                .iter()
+                .filter(|(name, _)| {
+                    !matches!(
+                        name.split_once("::")
+                            .and_then(|(base, _)| sched_info.types.get(base)),
+                        Some(DataType::Fence(_))
+                    )
+                })
+                .collect();
+            buffered.sort_by_key(|(name, _)| *name);
+            for (name, (buffer, info)) in buffered {
+                if !matches!(sched_info.types.get(buffer), Some(DataType::GpuBuffer(_))) {
+                    return Err(type_error(
+                        *info,
+                        &format!("{buffer} is not a GPU buffer and cannot be encoded into"),
+                    ));
+                }
+                if let Some((encoder, var)) = name.split_once("::") {
+                    if !is_device_var(sched_info.types.get(encoder), var) {
+                        return Err(type_error(
+                            *info,
+                            &format!(
+                                "{var} is not encoded by {encoder} and cannot be suballocated"
+                            ),
+                        ));
+                    }
+                }
+            }
+            // suballocations have the flags of the buffer they come from
+            for (name, flags) in &mut sched_info.flags {
+                if matches!(name.split_once("::"), Some((base, _)) if env.buffered.contains_key(base))
+                    || env.buffered.contains_key(name)
+                {
+                    *flags = ENCODE_IO_FLAGS;
+                }
+            }
         }
     }
     Ok(ctx)
 }
 
 /// Adds extern info for a given set of typed operators.
-fn add_ext_ops(externs: &HashSet<TypedBinop>, mut ctx: Context) -> Context {
-    for TypedBinop {
-        op,
-        op_l,
-        op_r,
-        ret,
-    } in externs
-    {
-        let op_name = binop_to_str(*op, &format!("{op_l:#}"), &format!("{op_r:#}")).to_string();
-        let sig = Signature::new(vec![op_l.clone(), op_r.clone()], vec![ret.clone()], 0);
+fn add_ext_ops(externs: &HashSet<TypedOp>, mut ctx: Context) -> Context {
+    for op in externs {
+        let op_name = op.name();
+        let sig = Signature::new(op.args.clone(), vec![op.ret.clone()], 0);
         ctx.signatures.insert(op_name.clone(), sig.clone());
         ctx.scheds.insert(op_name, SchedOrExtern::Extern(sig));
     }
@@ -417,22 +536,16 @@ fn add_ext_ops(externs: &HashSet<TypedBinop>, mut ctx: Context) -> Context {
 }
 
 /// Returns a list of extern declarations needed for a given set of typed operators.
-fn get_extern_decls(existing_externs: &HashSet<TypedBinop>) -> Vec<asm::Declaration> {
+fn get_extern_decls(existing_externs: &HashSet<TypedOp>) -> Vec<asm::Declaration> {
     let mut res = vec![];
-    for TypedBinop {
-        op,
-        op_l,
-        op_r,
-        ret,
-    } in existing_externs
-    {
-        let op_name = binop_to_str(*op, &format!("{op_l:#}"), &format!("{op_r:#}")).to_string();
+    for op in existing_externs {
+        let op_name = op.name();
         res.extend(
             [
                 asm::Declaration::FunctionClass(asm::FunctionClass {
                     name: asm::FunctionClassId(op_name.clone()),
-                    input_types: vec![op_l.asm_type(), op_r.asm_type()],
-                    output_types: vec![ret.asm_type()],
+                    input_types: op.args.iter().map(DataType::asm_type).collect(),
+                    output_types: vec![op.ret.asm_type()],
                 }),
                 asm::Declaration::ExternalFunction(asm::ExternalFunction {
                     name: op_name.clone(),
@@ -441,19 +554,17 @@ fn get_extern_decls(existing_externs: &HashSet<TypedBinop>) -> Vec<asm::Declarat
                         default: false,
                         function_class: asm::FunctionClassId(op_name.clone()),
                     },
-                    input_args: vec![
-                        asm::ExternalArgument {
-                            name: None,
-                            ffi_type: op_l.ffi().unwrap(),
-                        },
-                        asm::ExternalArgument {
+                    input_args: op
+                        .args
+                        .iter()
+                        .map(|arg| asm::ExternalArgument {
                             name: None,
-                            ffi_type: op_r.ffi().unwrap(),
-                        },
-                    ],
+                            ffi_type: arg.ffi().unwrap(),
+                        })
+                        .collect(),
                     output_types: vec![asm::ExternalArgument {
                         name: None,
-                        ffi_type: ret.ffi().unwrap(),
+                        ffi_type: op.ret.ffi().unwrap(),
                     }],
                 }),
             ]
@@ -715,16 +826,6 @@ fn collect_sched_signatures(tl: &[TopLevel], mut ctx: Context) -> Result<Context
     Ok(ctx)
 }
 
-fn collect_user_defined_types(tl: &[TopLevel]) -> HashMap<String, FlaggedType> {
-    let mut res = HashMap::new();
-    for decl in tl {
-        if let TopLevel::Typedef { name, typ, .. } = decl {
-            res.insert(name.clone(), typ.clone());
-        }
-    }
-    res
-}
-
 impl Context {
     /// Creates a global context from a list of top-level declarations.
     /// # Errors
@@ -739,7 +840,6 @@ impl Context {
             signatures: HashMap::new(),
             scheds: HashMap::new(),
             externs: HashSet::new(),
-            user_types: collect_user_defined_types(tl),
             class_dimensions: HashMap::new(),
             called_specs: HashSet::new(),
             trivial_tmlns: HashSet::new(),
@@ -747,6 +847,9 @@ impl Context {
         let ctx = collect_type_signatures(tl, ctx)?;
         let ctx = collect_sched_signatures(tl, ctx)?;
         let ctx = type_check_spec(tl, ctx)?;
-        type_check_schedules(tl, ctx)
+        let mut ctx = type_check_schedules(tl, ctx)?;
+        let mut aggregate_decls = gen_aggregate_type_decls(tl, &ctx)?;
+        ctx.type_decls.append(&mut aggregate_decls);
+        Ok(ctx)
     }
 }
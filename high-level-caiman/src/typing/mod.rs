@@ -7,7 +7,8 @@ mod specs;
 
 use crate::{
     error::{type_error, Info, LocalError},
-    parse::ast::{Binop, DataType, FlaggedType, FullType, SpecType, Tag, Uop, WGPUFlags},
+    lower::{array_to_str, binop_to_str},
+    parse::ast::{Binop, DataType, FlaggedType, FullType, IntSize, SpecType, Tag, Uop, WGPUFlags},
 };
 use caiman::{assembly::ast as asm, ir};
 use types::constraint_to_wildcard_vq;
@@ -327,6 +328,10 @@ pub struct DTypeEnv {
     flags: HashMap<String, ir::BufferFlags>,
     /// A side condition that `(sub, sup)` must be an element of the subtype relation.
     side_conditions: HashSet<(String, String)>,
+    /// Map from encoders and fences whose device variables are suballocated from
+    /// a buffer to that buffer and where it was named. A device variable declared
+    /// to come from a buffer of its own has an entry `encoder::variable`.
+    buffered: HashMap<String, (String, Info)>,
 }
 
 impl Default for DTypeEnv {
@@ -335,6 +340,7 @@ impl Default for DTypeEnv {
             env: Env::new(),
             flags: HashMap::new(),
             side_conditions: HashSet::new(),
+            buffered: HashMap::new(),
         }
     }
 }
@@ -431,6 +437,31 @@ impl DTypeEnv {
         flag.apply_flag(f);
     }
 
+    /// Marks the device variables of the encoder or fence `name` as being
+    /// suballocated from `buffer`.
+    pub fn add_buffered(&mut self, name: &str, buffer: &str, info: Info) {
+        self.buffered
+            .insert(name.to_string(), (buffer.to_string(), info));
+    }
+
+    /// Marks the device variables of `dest` as living wherever the device
+    /// variables of `src` live.
+    pub fn propagate_buffered(&mut self, src: &str, dest: &str) {
+        let propagated: Vec<_> = self
+            .buffered
+            .iter()
+            .filter_map(|(name, buffer)| {
+                if name == src {
+                    Some((dest.to_string(), buffer.clone()))
+                } else {
+                    let (base, var) = name.split_once("::")?;
+                    (base == src).then(|| (format!("{dest}::{var}"), buffer.clone()))
+                }
+            })
+            .collect();
+        self.buffered.extend(propagated);
+    }
+
     /// Adds a constraint that a variable must have a certain type.
     /// # Errors
     /// Returns an error if unification fails.
@@ -523,8 +554,6 @@ pub struct SchedInfo {
     pub value: String,
     /// Name of the timeline spec.
     pub timeline: String,
-    /// Name of the spatial spec.
-    pub spatial: String,
     /// The type signature of the schedule.
     pub dtype_sig: Signature,
     /// Map from variable name to type.
@@ -645,7 +674,6 @@ impl SchedInfo {
             dtype_sig: sig,
             value: val.unwrap(),
             timeline: timeline.unwrap(),
-            spatial: spatial.unwrap(),
             types: HashMap::new(),
             defined_names: HashMap::new(),
             flags: HashMap::new(),
@@ -775,8 +803,6 @@ pub struct Context {
     pub scheds: HashMap<String, SchedOrExtern>,
     /// Set of external function names.
     pub externs: HashSet<String>,
-    /// User defined types. Map from type name to type.
-    pub user_types: HashMap<String, FlaggedType>,
     /// Map from class name to the class's dimensions (number of value template arguments)
     pub class_dimensions: HashMap<String, usize>,
     /// The set of spec classes which are called from another spec.
@@ -785,25 +811,44 @@ pub struct Context {
     pub trivial_tmlns: HashSet<String>,
 }
 
-/// A typed binary operation.
+/// A built-in operation which is lowered to an external function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum BuiltinOp {
+    Binop(Binop),
+    /// Construction of an array from its elements.
+    Array,
+}
+
+/// A typed built-in operation.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
-struct TypedBinop {
-    op: Binop,
-    /// The type of the left operand.
-    op_l: DataType,
-    /// The type of the right operand.
-    op_r: DataType,
+struct TypedOp {
+    op: BuiltinOp,
+    /// The types of the operands.
+    args: Vec<DataType>,
     /// The type of the result.
     ret: DataType,
 }
 
-/// An unresolved typed binary operation containing type variables
+impl TypedOp {
+    /// Gets the name of the external function which implements the operation.
+    fn name(&self) -> String {
+        match self.op {
+            BuiltinOp::Binop(op) => binop_to_str(
+                op,
+                &format!("{:#}", self.args[0]),
+                &format!("{:#}", self.args[1]),
+            ),
+            BuiltinOp::Array => array_to_str(&format!("{:#}", self.ret)),
+        }
+    }
+}
+
+/// An unresolved typed built-in operation containing type variables
 /// instead of concrete types.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
-struct UnresolvedTypedBinop {
-    op: Binop,
-    op_l: String,
-    op_r: String,
+struct UnresolvedTypedOp {
+    op: BuiltinOp,
+    args: Vec<String>,
     ret: String,
 }
 
@@ -854,7 +899,42 @@ fn binop_to_contraints(
             let a = a.instantiate(env);
             (a.clone(), a.clone(), a)
         }
-        Binop::Dot | Binop::Range | Binop::Index | Binop::Cons => panic!("Operator not lowered"),
+        Binop::Index => {
+            let elem = env.new_temp_type();
+            let a = DTypeConstraint::Array(Box::new(DTypeConstraint::Var(elem.clone())), None);
+            let a = a.instantiate(env);
+            let i = DTypeConstraint::Int(None);
+            let i = i.instantiate(env);
+            (a, i, Constraint::Var(elem))
+        }
+        Binop::Dot | Binop::Range | Binop::Cons => panic!("Operator not lowered"),
+    }
+}
+
+/// Checks that an integer literal with a type suffix, such as `300u8`, fits in
+/// the type it names.
+/// # Errors
+/// Returns a type error if the literal is out of range of its type.
+fn check_int_literal(n: &str, size: Option<IntSize>, info: Info) -> Result<(), LocalError> {
+    let Some(size) = size else {
+        return Ok(());
+    };
+    let (min, max) = match size {
+        IntSize::I8 => (i128::from(i8::MIN), i128::from(i8::MAX)),
+        IntSize::I16 => (i128::from(i16::MIN), i128::from(i16::MAX)),
+        IntSize::I32 => (i128::from(i32::MIN), i128::from(i32::MAX)),
+        IntSize::I64 => (i128::from(i64::MIN), i128::from(i64::MAX)),
+        IntSize::U8 => (0, i128::from(u8::MAX)),
+        IntSize::U16 => (0, i128::from(u16::MAX)),
+        IntSize::U32 => (0, i128::from(u32::MAX)),
+        IntSize::U64 | IntSize::USize => (0, i128::from(u64::MAX)),
+    };
+    match n.parse::<i128>() {
+        Ok(value) if (min..=max).contains(&value) => Ok(()),
+        _ => Err(type_error(
+            info,
+            &format!("Literal {n}{size} is out of range for {size}"),
+        )),
     }
 }
 
@@ -937,5 +1017,6 @@ pub const fn is_value_dtype(t: &DataType) -> bool {
             | DataType::Bool
             | DataType::Ref(_)
             | DataType::Array(_, _)
+            | DataType::Slice(_)
     )
 }
@@ -12,7 +12,7 @@ use crate::{
 use std::iter::once;
 
 use super::{
-    binop_to_contraints,
+    binop_to_contraints, check_int_literal,
     types::{DTypeConstraint, RecordConstraint},
     unification::SubtypeConstraint,
     uop_to_contraints, Context, DTypeEnv, Mutability,
@@ -87,6 +87,9 @@ pub fn collect_sched_names<'a, T: Iterator<Item = &'a SchedStmt>>(
             | SchedStmt::InEdgeAnnotation { .. }
             | SchedStmt::OutEdgeAnnotation { .. }
             | SchedStmt::Hole(_) => (),
+            SchedStmt::Loop { .. } => {
+                unreachable!("Loops should be desugared before type checking")
+            }
         }
     }
     Ok(())
@@ -191,10 +194,38 @@ fn collect_assign_lit(
         env.add_dtype_constraint(dest_name, anot.base.clone(), info)?;
     }
     match lit {
-        SchedLiteral::Int(_) => env.add_constraint(dest_name, DTypeConstraint::Int(None), info),
-        SchedLiteral::Float(_) => env.add_constraint(dest_name, DTypeConstraint::Float(None), info),
+        SchedLiteral::Int(n, size) => {
+            check_int_literal(n, *size, info)?;
+            env.add_constraint(dest_name, DTypeConstraint::Int(*size), info)
+        }
+        SchedLiteral::Float(_, size) => {
+            env.add_constraint(dest_name, DTypeConstraint::Float(*size), info)
+        }
         SchedLiteral::Bool(_) => env.add_constraint(dest_name, DTypeConstraint::Bool, info),
-        _ => todo!(),
+        SchedLiteral::Array(elems) => {
+            let elems: Vec<&String> = elems
+                .iter()
+                .map(|e| {
+                    enum_cast!(
+                        SchedTerm::Var { name, .. },
+                        name,
+                        enum_cast!(SchedExpr::Term, e)
+                    )
+                })
+                .collect();
+            for elem in elems.iter().skip(1) {
+                env.add_var_equiv(elem, elems[0], info)?;
+            }
+            let elem_type = elems
+                .first()
+                .map_or(DTypeConstraint::Any, |e| DTypeConstraint::Var((*e).clone()));
+            env.add_constraint(
+                dest_name,
+                DTypeConstraint::Array(Box::new(elem_type), Some(elems.len().to_string())),
+                info,
+            )
+        }
+        SchedLiteral::Tuple(_) => todo!(),
     }
 }
 
@@ -526,6 +557,7 @@ fn collect_timeline_op(
     let dest_name = &dest[0].0;
     match op {
         TimelineOperation::Submit => {
+            env.propagate_buffered(arg_name, dest_name);
             let inner = format!("!{dest_name}");
             env.add_constraint(&inner, DTypeConstraint::Any, info)?;
             env.add_constraint(
@@ -560,6 +592,8 @@ fn collect_begin_encode(
     env: &mut DTypeEnv,
     dest: &[(String, Option<FullType>)],
     _defs: &[(String, Option<FullType>)],
+    buffer: Option<&String>,
+    suballocs: &[(String, String)],
     info: Info,
 ) -> Result<(), LocalError> {
     if dest.len() != 1 {
@@ -578,6 +612,12 @@ fn collect_begin_encode(
     {
         env.add_dtype_constraint(dest_name, anot.base.clone(), info)?;
     }
+    if let Some(buffer) = buffer {
+        env.add_buffered(dest_name, buffer, info);
+    }
+    for (var, buffer) in suballocs {
+        env.add_buffered(&format!("{dest_name}::{var}"), buffer, info);
+    }
     env.add_constraint(
         dest_name,
         DTypeConstraint::Encoder(Box::new(DTypeConstraint::Any)),
@@ -685,10 +725,17 @@ fn collect_sched_helper<'a, T: Iterator<Item = &'a SchedStmt>>(
             }
             SchedStmt::Decl {
                 lhs,
-                expr: Some(SchedExpr::Term(SchedTerm::EncodeBegin { info, defs, .. })),
+                expr:
+                    Some(SchedExpr::Term(SchedTerm::EncodeBegin {
+                        info,
+                        defs,
+                        buffer,
+                        suballocs,
+                        ..
+                    })),
                 ..
             } => {
-                collect_begin_encode(env, lhs, defs, *info)?;
+                collect_begin_encode(env, lhs, defs, buffer.as_ref(), suballocs, *info)?;
             }
             SchedStmt::InEdgeAnnotation { .. }
             | SchedStmt::OutEdgeAnnotation { .. }
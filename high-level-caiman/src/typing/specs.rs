@@ -3,7 +3,7 @@ use std::collections::{HashMap, HashSet};
 use crate::{
     enum_cast,
     error::{type_error, Info, LocalError},
-    lower::tuple_id,
+    lower::{tuple_id, ARRAY_OP},
     parse::ast::{
         Binop, DataType, FlaggedType, IntSize, SpecExpr, SpecLiteral, SpecStmt, SpecTerm,
         TemplateArgs,
@@ -11,9 +11,9 @@ use crate::{
 };
 
 use super::{
-    binop_to_contraints,
+    binop_to_contraints, check_int_literal,
     types::{DTypeConstraint, MetaVar, ValQuot},
-    DTypeEnv, NodeEnv, Signature, SpecInfo, TypedBinop, UnresolvedTypedBinop,
+    BuiltinOp, DTypeEnv, NodeEnv, Signature, SpecInfo, TypedOp, UnresolvedTypedOp,
 };
 
 /// Collects all names defined in a given spec, including inputs and outputs
@@ -39,6 +39,7 @@ fn collect_spec_names(
                 }
             }
             SpecStmt::Returns(..) => (),
+            SpecStmt::Loop { .. } => unreachable!("Loops should be desugared before type checking"),
         }
     }
     for i in 0..ctx.sig.num_dims {
@@ -254,12 +255,15 @@ fn collect_spec_assign_term(
 ) -> Result<(), LocalError> {
     match t {
         SpecTerm::Lit { lit, info } => {
+            if let SpecLiteral::Int(n, size) = lit {
+                check_int_literal(n, *size, *info)?;
+            }
             ctx.nodes.add_quotient(
                 &lhs[0].0,
                 match lit {
-                    SpecLiteral::Int(i) => ValQuot::Int(i.clone()),
+                    SpecLiteral::Int(i, _) => ValQuot::Int(i.clone()),
                     SpecLiteral::Bool(b) => ValQuot::Bool(*b),
-                    SpecLiteral::Float(f) => ValQuot::Float(f.clone()),
+                    SpecLiteral::Float(f, _) => ValQuot::Float(f.clone()),
                     _ => todo!("Unimplemented literal type in spec"),
                 },
             );
@@ -270,9 +274,9 @@ fn collect_spec_assign_term(
             ctx.types.add_constraint(
                 &lhs[0].0,
                 match lit {
-                    SpecLiteral::Int(_) => DTypeConstraint::Int(None),
+                    SpecLiteral::Int(_, size) => DTypeConstraint::Int(*size),
                     SpecLiteral::Bool(_) => DTypeConstraint::Bool,
-                    SpecLiteral::Float(_) => DTypeConstraint::Float(None),
+                    SpecLiteral::Float(_, size) => DTypeConstraint::Float(*size),
                     _ => todo!("Unimplemented literal type in spec"),
                 },
                 *info,
@@ -353,7 +357,7 @@ fn collect_spec_assign_bop(
     op_l: &SpecExpr,
     op_r: &SpecExpr,
     op: Binop,
-    externs: &mut HashSet<UnresolvedTypedBinop>,
+    externs: &mut HashSet<UnresolvedTypedOp>,
     lhs: &[(String, Option<DataType>)],
     ctx: &mut SpecEnvs,
     info: Info,
@@ -375,10 +379,9 @@ fn collect_spec_assign_bop(
             .add_raw_constraint(name1, &left_constraint, info)?;
         ctx.types
             .add_raw_constraint(name2, &right_constraint, info)?;
-        externs.insert(UnresolvedTypedBinop {
-            op,
-            op_l: name1.clone(),
-            op_r: name2.clone(),
+        externs.insert(UnresolvedTypedOp {
+            op: BuiltinOp::Binop(op),
+            args: vec![name1.clone(), name2.clone()],
             ret: lhs[0].0.clone(),
         });
         ctx.nodes.add_quotient(
@@ -395,6 +398,59 @@ fn collect_spec_assign_bop(
     Ok(())
 }
 
+/// Collects all types of variables used in a given statement for an assignment
+/// `lhs :- [elems]`.
+///
+/// # Panics
+/// Panics if the statement is not lowered.
+fn collect_spec_assign_array(
+    elems: &[SpecExpr],
+    externs: &mut HashSet<UnresolvedTypedOp>,
+    lhs: &[(String, Option<DataType>)],
+    ctx: &mut SpecEnvs,
+    info: Info,
+) -> Result<(), LocalError> {
+    let elems: Vec<String> = elems
+        .iter()
+        .map(|e| {
+            enum_cast!(
+                SpecTerm::Var { name, .. },
+                name,
+                enum_cast!(SpecExpr::Term, e)
+            )
+            .clone()
+        })
+        .collect();
+    for elem in elems.iter().skip(1) {
+        ctx.types.add_var_equiv(elem, &elems[0], info)?;
+    }
+    let elem_type = elems
+        .first()
+        .map_or(DTypeConstraint::Any, |e| DTypeConstraint::Var(e.clone()));
+    ctx.types.add_constraint(
+        &lhs[0].0,
+        DTypeConstraint::Array(Box::new(elem_type), Some(elems.len().to_string())),
+        info,
+    )?;
+    if let Some(annot) = &lhs[0].1 {
+        ctx.types
+            .add_dtype_constraint(&lhs[0].0, annot.clone(), info)?;
+    }
+    ctx.nodes.add_quotient(
+        &lhs[0].0,
+        ValQuot::CallOne(
+            String::from(ARRAY_OP),
+            elems.iter().map(|x| MetaVar::new_class_name(x)).collect(),
+        ),
+    );
+    externs.insert(UnresolvedTypedOp {
+        op: BuiltinOp::Array,
+        args: elems,
+        ret: lhs[0].0.clone(),
+    });
+    Ok(())
+}
+
 /// Resolves all types for defined variables in a given spec.
 fn resolve_types(
     env: &DTypeEnv,
@@ -537,7 +593,7 @@ pub(super) fn collect_spec(
     ctx: &mut SpecInfo,
     signatures: &HashMap<String, Signature>,
     dimensions: &HashMap<String, usize>,
-) -> Result<(HashSet<TypedBinop>, HashSet<String>), LocalError> {
+) -> Result<(HashSet<TypedOp>, HashSet<String>), LocalError> {
     let mut unresolved_externs = HashSet::new();
     let names = collect_spec_names(stmts, ctx)?;
     let mut env = SpecEnvs::new();
@@ -546,6 +602,12 @@ pub(super) fn collect_spec(
     for stmt in stmts {
         match stmt {
             SpecStmt::Assign { lhs, rhs, .. } => match rhs {
+                SpecExpr::Term(SpecTerm::Lit {
+                    lit: SpecLiteral::Array(elems),
+                    info,
+                }) => {
+                    collect_spec_assign_array(elems, &mut unresolved_externs, lhs, &mut env, *info)?
+                }
                 SpecExpr::Term(t) => {
                     collect_spec_assign_term(
                         t,
@@ -577,9 +639,15 @@ pub(super) fn collect_spec(
                     *info,
                 )?,
 
-                SpecExpr::Uop { .. } => todo!(),
+                SpecExpr::Uop { op, info, .. } => {
+                    return Err(type_error(
+                        *info,
+                        &format!("Unary operator {op} is not supported in a spec"),
+                    ))
+                }
             },
             SpecStmt::Returns(info, e) => collect_spec_returns(&mut env, ctx, e, *info)?,
+            SpecStmt::Loop { .. } => unreachable!("Loops should be desugared before type checking"),
         }
     }
     resolve_types(&env.types, &names, ctx)?;
@@ -587,10 +655,9 @@ pub(super) fn collect_spec(
     Ok((
         unresolved_externs
             .into_iter()
-            .map(|u| TypedBinop {
+            .map(|u| TypedOp {
                 op: u.op,
-                op_l: ctx.types[&u.op_l].clone(),
-                op_r: ctx.types[&u.op_r].clone(),
+                args: u.args.iter().map(|arg| ctx.types[arg].clone()).collect(),
                 ret: ctx.types[&u.ret].clone(),
             })
             .collect::<HashSet<_>>(),
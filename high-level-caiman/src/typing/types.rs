@@ -13,6 +13,8 @@ pub enum CDataType {
     Int,
     Float,
     Ref,
+    /// An array or slice, with children for the element type and the length
+    Array,
     Record,
     Encoder,
     Fence,
@@ -21,14 +23,28 @@ pub enum CDataType {
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ADataType {
+    I8,
+    I16,
     I32,
     I64,
+    U8,
+    U16,
+    U32,
+    U64,
+    USize,
+    F32,
     F64,
     Bool,
     BufferSpace,
+    /// A GPU buffer of the given size in bytes
+    GpuBuffer(usize),
     Event,
     SpecEncoder,
     SpecFence,
+    /// The length of an array
+    Length(String),
+    /// The length of a slice, which is unknown
+    Unsized,
 }
 
 impl Kind for CDataType {}
@@ -56,6 +72,7 @@ pub enum DTypeConstraint {
     Float(Option<FloatSize>),
     Bool,
     BufferSpace,
+    GpuBuffer(usize),
     Event,
     /// Any numeric type.
     Num,
@@ -68,6 +85,10 @@ pub enum DTypeConstraint {
     /// A reference constraint which contains a dtype constraint
     /// that will be instantiated to a new inner data type constraint.
     RefN(Box<DTypeConstraint>),
+    /// An array of elements adhering to the inner constraint. The length
+    /// is unconstrained if it is `None`.
+    Array(Box<DTypeConstraint>, Option<String>),
+    Slice(Box<DTypeConstraint>),
     // Encoder(Constraint<CDataType, ADataType>),
     //Fence(Constraint<CDataType, ADataType>),
     Encoder(Box<DTypeConstraint>),
@@ -108,6 +129,7 @@ impl DTypeConstraint {
             | Self::Float(_)
             | Self::Bool
             | Self::BufferSpace
+            | Self::GpuBuffer(_)
             | Self::Event
             | Self::Num
             | Self::Any
@@ -116,6 +138,8 @@ impl DTypeConstraint {
             | Self::SpecEncoder
             | Self::SpecFence => self,
             Self::RefN(x) => Self::RefN(Box::new(x.into_subtypeable())),
+            Self::Array(x, len) => Self::Array(Box::new(x.into_subtypeable()), len),
+            Self::Slice(x) => Self::Slice(Box::new(x.into_subtypeable())),
             Self::Encoder(x) => Self::Encoder(Box::new(x.into_subtypeable())),
             Self::Fence(x) => Self::Fence(Box::new(x.into_subtypeable())),
             Self::Record(r) => Self::Record(Self::record_into_subtypeable(r)),
@@ -131,8 +155,15 @@ impl DTypeConstraint {
 impl From<IntSize> for ADataType {
     fn from(i: IntSize) -> Self {
         match i {
+            IntSize::I8 => Self::I8,
+            IntSize::I16 => Self::I16,
             IntSize::I32 => Self::I32,
             IntSize::I64 => Self::I64,
+            IntSize::U8 => Self::U8,
+            IntSize::U16 => Self::U16,
+            IntSize::U32 => Self::U32,
+            IntSize::U64 => Self::U64,
+            IntSize::USize => Self::USize,
         }
     }
 }
@@ -141,8 +172,15 @@ impl TryFrom<ADataType> for IntSize {
     type Error = String;
     fn try_from(i: ADataType) -> Result<Self, Self::Error> {
         match i {
+            ADataType::I8 => Ok(Self::I8),
+            ADataType::I16 => Ok(Self::I16),
             ADataType::I32 => Ok(Self::I32),
             ADataType::I64 => Ok(Self::I64),
+            ADataType::U8 => Ok(Self::U8),
+            ADataType::U16 => Ok(Self::U16),
+            ADataType::U32 => Ok(Self::U32),
+            ADataType::U64 => Ok(Self::U64),
+            ADataType::USize => Ok(Self::USize),
             _ => Err(format!("Cannot convert {i:?} to IntSize")),
         }
     }
@@ -151,6 +189,7 @@ impl TryFrom<ADataType> for IntSize {
 impl From<FloatSize> for ADataType {
     fn from(f: FloatSize) -> Self {
         match f {
+            FloatSize::F32 => Self::F32,
             FloatSize::F64 => Self::F64,
         }
     }
@@ -160,6 +199,7 @@ impl TryFrom<ADataType> for FloatSize {
     type Error = String;
     fn try_from(f: ADataType) -> Result<Self, Self::Error> {
         match f {
+            ADataType::F32 => Ok(Self::F32),
             ADataType::F64 => Ok(Self::F64),
             _ => Err(format!("Cannot convert {f:?} to FloatSize")),
         }
@@ -234,9 +274,21 @@ impl DTypeConstraint {
             }
             Self::Bool => Constraint::Atom(ADataType::Bool),
             Self::BufferSpace => Constraint::Atom(ADataType::BufferSpace),
+            Self::GpuBuffer(size) => Constraint::Atom(ADataType::GpuBuffer(size)),
             Self::Event => Constraint::Atom(ADataType::Event),
             Self::Ref(x) => Constraint::Term(CDataType::Ref, vec![x]),
             Self::RefN(x) => Constraint::Term(CDataType::Ref, vec![x.instantiate(env)]),
+            Self::Array(x, len) => {
+                let len = len.map_or_else(
+                    || Constraint::Var(env.new_temp_type()),
+                    |len| Constraint::Atom(ADataType::Length(len)),
+                );
+                Constraint::Term(CDataType::Array, vec![x.instantiate(env), len])
+            }
+            Self::Slice(x) => Constraint::Term(
+                CDataType::Array,
+                vec![x.instantiate(env), Constraint::Atom(ADataType::Unsized)],
+            ),
             Self::Encoder(typ) => Constraint::Term(CDataType::Encoder, vec![typ.instantiate(env)]),
             Self::Fence(public) => {
                 Constraint::Term(CDataType::Fence, vec![public.instantiate(env)])
@@ -279,11 +331,14 @@ impl TryFrom<DTypeConstraint> for DataType {
             DTypeConstraint::Float(None) => Ok(Self::Float(DEFAULT_FLOAT_SIZE)),
             DTypeConstraint::Bool => Ok(Self::Bool),
             DTypeConstraint::BufferSpace => Ok(Self::BufferSpace),
+            DTypeConstraint::GpuBuffer(size) => Ok(Self::GpuBuffer(size)),
             DTypeConstraint::Event => Ok(Self::Event),
             DTypeConstraint::Ref(x) => Ok(Self::Ref(Box::new(Self::try_from(
                 DTypeConstraint::try_from(x).map_err(|_| ())?,
             )?))),
             DTypeConstraint::RefN(x) => Ok(Self::Ref(Box::new(Self::try_from(*x)?))),
+            DTypeConstraint::Array(x, Some(len)) => Ok(Self::array(Self::try_from(*x)?, &len)),
+            DTypeConstraint::Slice(x) => Ok(Self::Slice(Box::new(Self::try_from(*x)?))),
             DTypeConstraint::Record(RecordConstraint::Record { fields, .. }) => {
                 let mut mp = Vec::new();
                 for (k, v) in fields {
@@ -316,6 +371,7 @@ impl TryFrom<DTypeConstraint> for DataType {
             DTypeConstraint::SpecFence => Ok(Self::Fence(None)),
             DTypeConstraint::Any
             | DTypeConstraint::Var(_)
+            | DTypeConstraint::Array(_, None)
             | DTypeConstraint::Record(RecordConstraint::Var(_) | RecordConstraint::Any)
             | DTypeConstraint::RemoteObj { .. } => Err(()),
         }
@@ -328,6 +384,7 @@ impl TryFrom<Constraint<CDataType, ADataType>> for DTypeConstraint {
         match c {
             Constraint::Atom(ADataType::Bool) => Ok(Self::Bool),
             Constraint::Atom(ADataType::BufferSpace) => Ok(Self::BufferSpace),
+            Constraint::Atom(ADataType::GpuBuffer(size)) => Ok(Self::GpuBuffer(size)),
             Constraint::Atom(ADataType::Event) => Ok(Self::Event),
             Constraint::Term(CDataType::Num, mut v) => {
                 let d = v.swap_remove(0);
@@ -362,6 +419,21 @@ impl TryFrom<Constraint<CDataType, ADataType>> for DTypeConstraint {
                 Ok(Self::Ref(d))
             }
             Constraint::Var(_) => Ok(Self::Any),
+            Constraint::Term(CDataType::Array, mut v) => {
+                assert_eq!(
+                    v.len(),
+                    2,
+                    "Array constraint should have exactly two children"
+                );
+                let len = v.pop().unwrap();
+                let elem = Box::new(Self::try_from(v.pop().unwrap())?);
+                match len {
+                    Constraint::Atom(ADataType::Length(len)) => Ok(Self::Array(elem, Some(len))),
+                    Constraint::Atom(ADataType::Unsized) => Ok(Self::Slice(elem)),
+                    Constraint::Var(_) => Ok(Self::Array(elem, None)),
+                    _ => Err(format!("Invalid array length {len:?}")),
+                }
+            }
             Constraint::Term(CDataType::Encoder, mut v) => {
                 assert_eq!(
                     v.len(),
@@ -433,13 +505,18 @@ fn set_dtypes_to_constraints(set: BTreeSet<String>) -> BTreeMap<String, DTypeCon
 impl From<DataType> for DTypeConstraint {
     fn from(dt: DataType) -> Self {
         match dt {
-            DataType::Int(IntSize::I32) => Self::Int(Some(IntSize::I32)),
-            DataType::Int(IntSize::I64) => Self::Int(Some(IntSize::I64)),
-            DataType::Float(FloatSize::F64) => Self::Float(Some(FloatSize::F64)),
+            DataType::Int(size) => Self::Int(Some(size)),
+            DataType::Float(size) => Self::Float(Some(size)),
             DataType::Bool => Self::Bool,
             DataType::BufferSpace => Self::BufferSpace,
+            DataType::GpuBuffer(size) => Self::GpuBuffer(size),
             DataType::Event => Self::Event,
             DataType::Ref(x) => Self::RefN(Box::new(Self::from(*x))),
+            DataType::Array(ref x, _) => Self::Array(
+                Box::new(Self::from((**x).clone())),
+                dt.array_length().ok().flatten().map(String::from),
+            ),
+            DataType::Slice(x) => Self::Slice(Box::new(Self::from(*x))),
             DataType::Encoder(None) => Self::SpecEncoder,
             DataType::Fence(None) => Self::SpecFence,
             DataType::Encoder(Some(x)) => Self::Encoder(Box::new(Self::from(*x))),
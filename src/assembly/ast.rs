@@ -1,9 +1,9 @@
+use crate::explication::Hole;
 use crate::ir;
 use crate::rust_wgpu_backend::ffi;
 use serde_derive::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::hash::{Hash, Hasher};
-use crate::explication::Hole;
 
 #[macro_export]
 macro_rules! def_assembly_id_type {
@@ -96,7 +96,7 @@ pub enum FFIType {
     CpuBufferRef(Box<FFIType>),
     GpuFence,
     // useful for debugging stuff
-    Unknown
+    Unknown,
 }
 
 impl std::fmt::Display for FFIType {
@@ -137,18 +137,23 @@ pub struct RemoteNodeId {
 
 impl std::fmt::Display for RemoteNodeId {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{}.{}", self.funclet, match &self.node {
-            None => "None".to_string(),
-            Some(n) => format!("{}", n)
-        });
+        write!(
+            f,
+            "{}.{}",
+            self.funclet,
+            match &self.node {
+                None => "None".to_string(),
+                Some(n) => format!("{}", n),
+            }
+        );
         Ok(())
     }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Tag {
-    pub quot: Hole<RemoteNodeId>,   // What a given value maps to in a specification
-    pub flow: Hole<ir::Flow>,       // How this value transforms relative to the specification
+    pub quot: Hole<RemoteNodeId>, // What a given value maps to in a specification
+    pub flow: Hole<ir::Flow>,     // How this value transforms relative to the specification
 }
 
 // Super Jank, but whatever
@@ -252,7 +257,7 @@ macro_rules! node_display {
                 match self {
                     $(Node::$name { $($arg,)* } => {
                         writeln!(f, "{} {{ ", stringify!($name));
-                        $(write!(f, "\t{} : ", stringify!($arg)); 
+                        $(write!(f, "\t{} : ", stringify!($arg));
                             map_display!($arg $arg_type f);
                             writeln!(f, "");)*
                         writeln!(f, "}}");
@@ -307,6 +312,16 @@ pub enum TailEdge {
         yielded_nodes: Hole<Vec<Hole<NodeId>>>,
         continuation_join: Hole<NodeId>,
     },
+
+    // Split space - where the computation will be observed
+    DynamicAllocFromBuffer {
+        buffer: Hole<NodeId>,
+        dynamic_allocation_size_slots: Hole<Vec<Hole<Option<NodeId>>>>,
+        success_funclet_id: Hole<FuncletId>,
+        failure_funclet_id: Hole<FuncletId>,
+        arguments: Hole<Vec<Hole<NodeId>>>,
+        continuation_join: Hole<NodeId>,
+    },
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -484,6 +499,8 @@ pub struct Pipeline {
     pub name: String,
     pub funclet: FuncletId,
     pub effect: Option<EffectId>,
+    #[serde(default)]
+    pub recursion_limit: Option<usize>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -502,4 +519,16 @@ pub struct Program {
     pub path: String,
     pub version: Version,
     pub declarations: Vec<Declaration>,
-}
\ No newline at end of file
+    // where each funclet and named node was written, recorded by the parser for diagnostics
+    #[serde(skip)]
+    pub spans: HashMap<FuncletId, FuncletSpans>,
+    // where each external function was declared, by name
+    #[serde(skip)]
+    pub external_spans: HashMap<String, crate::diagnostics::Range>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FuncletSpans {
+    pub funclet: Option<crate::diagnostics::Range>,
+    pub nodes: HashMap<NodeId, crate::diagnostics::Range>,
+}
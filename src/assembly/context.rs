@@ -5,30 +5,19 @@ use crate::assembly::ast::{
 };
 use crate::assembly::table::Table;
 use crate::debug_info::{DebugInfo, FuncletDebugMap};
+use crate::diagnostics::{codes, Diagnostic, Range, SourceSpan};
 use crate::explication::expir;
 use crate::explication::Hole;
+use crate::frontend::{CompileError, CompileErrorKind};
 use crate::rust_wgpu_backend::ffi;
 use debug_ignore::DebugIgnore;
 use std::collections::{HashMap, HashSet};
 
-// Utility stuff
-pub fn reject_hole<T>(h: Hole<T>) -> T {
-    match h {
-        Hole::Filled(v) => v,
-        Hole::Empty => unreachable!("Unimplemented Hole"),
-    }
-}
-
-pub fn reject_opt<T>(h: Option<T>) -> T {
-    match h {
-        Some(v) => v,
-        None => unreachable!("Unimplemented Hole"),
-    }
-}
-
 #[derive(Debug)]
 pub struct Context {
     pub path: String,
+    // the file being lowered, which errors are reported against
+    pub filename: String,
     pub ffi_type_table: Table<FFIType>,
     pub local_type_table: Table<String>,
     // map from names of native types to the storage type
@@ -43,6 +32,11 @@ pub struct Context {
     pub funclet_indices: FuncletIndices,
     pub function_classes: Table<FunctionClassId>,
     pub effects: Table<EffectId>,
+    // source positions from the parser, carried through to the debug info
+    pub spans: HashMap<FuncletId, ast::FuncletSpans>,
+    pub external_spans: HashMap<String, crate::diagnostics::Range>,
+    // the shaders GPU kernels are implemented by, keyed by the path the program names them with
+    pub shader_modules: HashMap<String, crate::shadergen::ShaderModule>,
 }
 
 #[derive(Debug)]
@@ -111,17 +105,18 @@ impl FuncletIndices {
         }
     }
 
-    pub fn insert(&mut self, name: String, location: expir::Place) {
-        match location {
-            expir::Place::Local => self.local_funclet_table.push(FuncletId(name.clone())),
+    // returns false if the name was already taken
+    pub fn insert(&mut self, name: String, location: expir::Place) -> bool {
+        let inserted = match location {
+            expir::Place::Local => self.local_funclet_table.try_push(FuncletId(name.clone())),
             expir::Place::Cpu => self
                 .external_funclet_table
-                .push(ExternalFunctionId(name.clone())),
+                .try_push(ExternalFunctionId(name.clone())),
             expir::Place::Gpu => self
                 .external_funclet_table
-                .push(ExternalFunctionId(name.clone())),
-        }
-        self.funclet_kind_map.insert(name, location);
+                .try_push(ExternalFunctionId(name.clone())),
+        };
+        inserted && self.funclet_kind_map.insert(name, location).is_none()
     }
 
     pub fn get_index(&self, name: &FuncletId) -> Option<usize> {
@@ -143,17 +138,13 @@ impl FuncletIndices {
                 .get(&ExternalFunctionId(name.clone())),
         })
     }
-
-    pub fn require_funclet(&self, name: &String) -> usize {
-        self.get_funclet(name)
-            .expect(&format!("Unknown funclet name {}", name))
-    }
 }
 
 impl Context {
-    pub fn new(program: &ast::Program) -> Context {
+    pub fn new(program: &ast::Program, filename: &str) -> Result<Context, CompileError> {
         let mut context = Context {
             path: "".to_string(),
+            filename: filename.to_string(),
             ffi_type_table: Table::new(),
             local_type_table: Table::new(),
             native_type_map: HashMap::new(),
@@ -163,33 +154,52 @@ impl Context {
             variable_map: HashMap::new(),
             meta_map: None,
             location: LocationNames::new(),
+            spans: HashMap::new(),
+            external_spans: HashMap::new(),
+            shader_modules: HashMap::new(),
         };
-        context.setup_context(program);
-        context
+        context.setup_context(program)?;
+        Ok(context)
     }
 
     // Take a pass over the program to construct the initial context
     // We only do this once and assume the invariants are maintained by construction
     // Note that a context without this makes little sense, so we can't build an "empty context"
-    fn setup_context(&mut self, program: &ast::Program) {
+    fn setup_context(&mut self, program: &ast::Program) -> Result<(), CompileError> {
         self.path = program.path.clone();
+        self.spans = program.spans.clone();
+        self.external_spans = program.external_spans.clone();
         for declaration in &program.declarations {
             match declaration {
                 ast::Declaration::TypeDecl(typ) => match typ {
-                    ast::TypeDecl::FFI(t) => self.ffi_type_table.push(t.clone()),
+                    ast::TypeDecl::FFI(t) => {
+                        if !self.ffi_type_table.try_push(t.clone()) {
+                            return Err(self.error_at(None, format!("Duplicate ffi type {:?}", t)));
+                        }
+                    }
                     ast::TypeDecl::Local(t) => {
-                        self.local_type_table.push(t.name.clone());
+                        if !self.local_type_table.try_push(t.name.clone()) {
+                            return Err(self.error_at(None, format!("Duplicate type {}", t.name)));
+                        }
                         match &t.data {
                             ast::LocalTypeInfo::NativeValue { storage_type } => {
-                                self.native_type_map.insert(t.name.clone(), storage_type.clone());
+                                self.native_type_map
+                                    .insert(t.name.clone(), storage_type.clone());
                             }
                             _ => {}
                         }
                     }
                 },
                 ast::Declaration::Funclet(f) => {
-                    self.funclet_indices
-                        .insert(f.header.name.0.clone(), expir::Place::Local);
+                    if !self
+                        .funclet_indices
+                        .insert(f.header.name.0.clone(), expir::Place::Local)
+                    {
+                        let range = self.spans.get(&f.header.name).and_then(|s| s.funclet);
+                        return Err(
+                            self.error_at(range, format!("Duplicate funclet {}", f.header.name))
+                        );
+                    }
                     let mut var_map = HashMap::new();
                     for (index, arg) in f.header.args.iter().enumerate() {
                         match &arg.name {
@@ -235,197 +245,214 @@ impl Context {
                         ast::ExternalFunctionKind::CPUEffect => expir::Place::Cpu,
                         ast::ExternalFunctionKind::GPU(_) => expir::Place::Gpu,
                     };
-                    self.funclet_indices.insert(f.name.clone(), location);
+                    if !self.funclet_indices.insert(f.name.clone(), location) {
+                        let range = self.external_spans.get(&f.name).copied();
+                        return Err(self.error_at(range, format!("Duplicate funclet {}", f.name)));
+                    }
                 }
                 ast::Declaration::FunctionClass(f) => {
-                    self.function_classes.push(f.name.clone());
+                    if !self.function_classes.try_push(f.name.clone()) {
+                        return Err(
+                            self.error_at(None, format!("Duplicate function class {}", f.name))
+                        );
+                    }
                 }
                 ast::Declaration::Effect(f) => {
-                    self.effects.push(f.name.clone());
+                    if !self.effects.try_push(f.name.clone()) {
+                        return Err(self.error_at(None, format!("Duplicate effect {}", f.name)));
+                    }
                 }
                 _ => {}
             }
         }
+        Ok(())
     }
 
-    pub fn external_lookup(&self, id: &ExternalFunctionId) -> expir::ExternalFunctionId {
-        ffi::ExternalFunctionId(
-            self.funclet_indices
-                .external_funclet_table
-                .get(id)
-                .expect(format!("Unknown external funclet {:?}", id).as_str()),
-        )
+    // an error at the given range of the file being lowered, or at the whole file
+    pub fn error_at(&self, range: Option<Range>, message: String) -> CompileError {
+        let span = if self.filename.is_empty() {
+            None
+        } else {
+            let start = crate::diagnostics::Position::new(1, 1);
+            let range = range.unwrap_or(Range { start, end: start });
+            Some(SourceSpan::new(&self.filename, range))
+        };
+        let diagnostic = Diagnostic::error(codes::LOWERING, message).with_primary(span);
+        CompileError::new(CompileErrorKind::Lowering, diagnostic)
     }
 
-    pub fn effect_lookup(&self, effect: &EffectId) -> ffi::EffectId {
-        ffi::EffectId(
-            self.effects
-                .get(effect)
-                .expect(format!("Unknown effect {:?}", effect).as_str()),
-        )
+    // an error at the node being lowered, or failing that at its funclet
+    pub fn error(&self, message: String) -> CompileError {
+        let spans = self.spans.get(&self.location.funclet_name);
+        let node = match (&self.location.node_name, spans) {
+            (Some(node), Some(spans)) => spans.nodes.get(node).copied(),
+            _ => None,
+        };
+        self.error_at(node.or(spans.and_then(|s| s.funclet)), message)
     }
 
-    pub fn ffi_type_id(&self, name: &ast::FFIType) -> crate::rust_wgpu_backend::ffi::TypeId {
+    pub fn external_lookup(
+        &self,
+        id: &ExternalFunctionId,
+    ) -> Result<expir::ExternalFunctionId, CompileError> {
+        self.funclet_indices
+            .external_funclet_table
+            .get(id)
+            .map(ffi::ExternalFunctionId)
+            .ok_or_else(|| self.error(format!("Unknown external funclet {}", id)))
+    }
+
+    pub fn effect_lookup(&self, effect: &EffectId) -> Result<ffi::EffectId, CompileError> {
+        self.effects
+            .get(effect)
+            .map(ffi::EffectId)
+            .ok_or_else(|| self.error(format!("Unknown effect {}", effect)))
+    }
+
+    pub fn ffi_type_id(&self, name: &ast::FFIType) -> Result<ffi::TypeId, CompileError> {
         match self.ffi_type_table.get_index(name) {
-            Some(i) => crate::rust_wgpu_backend::ffi::TypeId(i),
-            None => panic!("Un-indexed FFI type {:?}", name),
+            Some(i) => Ok(ffi::TypeId(i)),
+            None => Err(self.error(format!("Undeclared ffi type {:?}", name))),
         }
     }
 
-    pub fn local_type_id(&self, name: &String) -> usize {
+    pub fn local_type_id(&self, name: &String) -> Result<usize, CompileError> {
         match self.local_type_table.get_index(name) {
-            Some(t) => t,
-            None => panic!("Unknown local type {:?}", name),
+            Some(t) => Ok(t),
+            None => Err(self.error(format!("Unknown local type {}", name))),
         }
     }
 
-    pub fn loc_type_id(&self, typ: &ast::TypeId) -> usize {
+    pub fn loc_type_id(&self, typ: &ast::TypeId) -> Result<usize, CompileError> {
         self.local_type_id(&typ.0)
     }
 
-    pub fn explicit_node_id(&self, funclet: &FuncletId, node: &Hole<NodeId>) -> expir::Quotient {
+    pub fn explicit_node_id(
+        &self,
+        funclet: &FuncletId,
+        node: &Hole<NodeId>,
+    ) -> Result<expir::Quotient, CompileError> {
         match self.variable_map.get(funclet) {
             Some(f) => match node {
-                Hole::Empty => expir::Quotient::None,
-                Hole::Filled(var) => f
-                    .get(var)
-                    .expect(format!("Unknown node {} in funclet {}", var, funclet).as_str())
-                    .clone(),
+                Hole::Empty => Ok(expir::Quotient::None),
+                Hole::Filled(var) => f.get(var).cloned().ok_or_else(|| {
+                    self.error(format!("Unknown node {} in funclet {}", var, funclet))
+                }),
             },
-            None => panic!("Unknown funclet name {}", funclet),
+            None => Err(self.error(format!("Unknown funclet name {}", funclet))),
         }
     }
 
-    pub fn function_class_id(&self, f: &FunctionClassId) -> expir::FunctionClassId {
+    pub fn function_class_id(
+        &self,
+        f: &FunctionClassId,
+    ) -> Result<expir::FunctionClassId, CompileError> {
         self.function_classes
             .get(f)
-            .expect(format!("Unknown function class {:?}", f).as_str())
+            .ok_or_else(|| self.error(format!("Unknown function class {}", f)))
     }
 
     // TODO: Note that the funclet name gets thrown out here, is this a problem?
-    pub fn remote_id(&self, f: &RemoteNodeId) -> expir::Quotient {
+    pub fn remote_id(&self, f: &RemoteNodeId) -> Result<expir::Quotient, CompileError> {
         match &f.node {
-            None => expir::Quotient::None,
+            None => Ok(expir::Quotient::None),
             Some(node) => {
-                let funclet_id = self.meta_lookup(&f.funclet);
+                let funclet_id = self.meta_lookup(&f.funclet)?;
                 self.explicit_node_id(&funclet_id, node)
             }
         }
     }
 
-    pub fn funclet_id(&self, f: &FuncletId) -> expir::FuncletId {
+    pub fn funclet_id(&self, f: &FuncletId) -> Result<expir::FuncletId, CompileError> {
         self.funclet_indices
             .get_funclet(&f.0)
-            .expect(format!("Unknown funclet {:?}", f).as_str())
+            .ok_or_else(|| self.error(format!("Unknown funclet {}", f)))
     }
 
-    pub fn external_funclet_id(&self, f: &ExternalFunctionId) -> expir::ExternalFunctionId {
-        expir::ExternalFunctionId {
-            0: self
-                .funclet_indices
-                .get_funclet(&f.0)
-                .expect(format!("Unknown funclet {:?}", f).as_str()),
-        }
+    pub fn external_funclet_id(
+        &self,
+        f: &ExternalFunctionId,
+    ) -> Result<expir::ExternalFunctionId, CompileError> {
+        self.funclet_indices
+            .get_funclet(&f.0)
+            .map(ffi::ExternalFunctionId)
+            .ok_or_else(|| self.error(format!("Unknown funclet {}", f)))
     }
 
-    pub fn node_id(&self, var: &NodeId) -> expir::NodeId {
+    pub fn node_id(&self, var: &NodeId) -> Result<expir::NodeId, CompileError> {
         let funclet = &self.location.funclet_name;
-        let var_error = format!("Unknown variable name {:?} in funclet {:?}", var, &funclet);
-        match self
+        let quot = self
             .variable_map
             .get(funclet)
-            .unwrap()
-            .get(var)
-            .expect(&var_error)
-        {
+            .and_then(|variables| variables.get(var))
+            .ok_or_else(|| {
+                self.error(format!(
+                    "Unknown variable name {} in funclet {}",
+                    var, funclet
+                ))
+            })?;
+        match quot {
             expir::Quotient::None => {
-                panic!("Invalid None node {:?} in funclet {:?}", var, &funclet)
+                Err(self.error(format!("Invalid None node {} in funclet {}", var, funclet)))
             }
             expir::Quotient::Input { index }
             | expir::Quotient::Output { index }
-            | expir::Quotient::Node { node_id: index } => *index,
+            | expir::Quotient::Node { node_id: index } => Ok(*index),
         }
     }
 
     // gets the associated value, timeline, and spatial results from the given list of tags
     // note that this will return holes for any missing result
-    pub fn tag_lookup(&self, operations: &Vec<Hole<ast::Tag>>) -> TagSet {
+    pub fn tag_lookup(&self, operations: &Vec<Hole<ast::Tag>>) -> Result<TagSet, CompileError> {
         let mut result = TagSet {
             value: Hole::Empty,
             timeline: Hole::Empty,
             spatial: Hole::Empty,
         };
-        let error = "Holes in operational lists unsupported";
+        let error = || self.error("Holes in operational lists unsupported".to_string());
         for operation in operations {
-            let unwrapped = operation.as_ref().opt().expect(&error);
-            let remote = unwrapped.quot.as_ref().opt().expect(&error);
-            let (fnid, kind) = self.meta_lookup_loc(&remote.funclet);
-            let quot = self.explicit_node_id(
-                &fnid,
-                &remote
-                    .node
-                    .as_ref()
-                    .cloned()
-                    .map(|n| n.opt().expect(&error))
-                    .into(),
-            );
+            let unwrapped = operation.as_ref().opt().ok_or_else(error)?;
+            let remote = unwrapped.quot.as_ref().opt().ok_or_else(error)?;
+            let (fnid, kind) = self.meta_lookup_loc(&remote.funclet)?;
+            let node = match &remote.node {
+                None => Hole::Empty,
+                Some(node) => Hole::Filled(node.as_ref().opt().ok_or_else(error)?.clone()),
+            };
+            let quot = self.explicit_node_id(&fnid, &node)?;
             let tag = Hole::Filled(expir::Tag {
                 quot,
                 flow: unwrapped.flow.clone(),
             });
-            match kind {
-                expir::FuncletKind::Value => match result.value {
-                    Hole::Empty => {
-                        result.value = tag;
-                    }
-                    Hole::Filled(old) => {
-                        panic!(
-                            "Duplicate definitions using value: {:?} and {:?}",
-                            old, quot
-                        )
-                    }
-                },
-                expir::FuncletKind::Timeline => match result.timeline {
-                    Hole::Empty => {
-                        result.timeline = tag;
-                    }
-                    Hole::Filled(old) => {
-                        panic!(
-                            "Duplicate definitions using timeline: {:?} and {:?}",
-                            old, quot
-                        )
-                    }
-                },
-                expir::FuncletKind::Spatial => match result.spatial {
-                    Hole::Empty => {
-                        result.spatial = tag;
-                    }
-                    Hole::Filled(old) => {
-                        panic!(
-                            "Duplicate definitions using spatial: {:?} and {:?}",
-                            old, quot
-                        )
-                    }
-                },
-                _ => {
-                    unreachable!()
+            let (slot, language) = match kind {
+                expir::FuncletKind::Value => (&mut result.value, "value"),
+                expir::FuncletKind::Timeline => (&mut result.timeline, "timeline"),
+                expir::FuncletKind::Spatial => (&mut result.spatial, "spatial"),
+                _ => unreachable!("meta names only refer to specs"),
+            };
+            match slot {
+                Hole::Empty => *slot = tag,
+                Hole::Filled(old) => {
+                    return Err(self.error(format!(
+                        "Duplicate definitions using {}: {:?} and {:?}",
+                        language, old, quot
+                    )))
                 }
             }
         }
-        result
+        Ok(result)
     }
 
     // extremely stupid, but it works
     pub fn operational_lookup(
         &self,
         operations: &Hole<Vec<Hole<ast::RemoteNodeId>>>,
-    ) -> OperationSet {
+    ) -> Result<OperationSet, CompileError> {
         match operations.as_ref() {
-            Hole::Empty => OperationSet {
+            Hole::Empty => Ok(OperationSet {
                 value: Hole::Empty,
                 timeline: Hole::Empty,
                 spatial: Hole::Empty,
-            },
+            }),
             Hole::Filled(ops) => {
                 let tags = ops.iter().map(|quot| {
                     Hole::Filled(ast::Tag {
@@ -434,32 +461,39 @@ impl Context {
                         flow: Hole::Empty,
                     })
                 });
-                let result = self.tag_lookup(&tags.collect());
-                OperationSet {
+                let result = self.tag_lookup(&tags.collect())?;
+                Ok(OperationSet {
                     value: result.value.opt().map(|t| t.quot).into(),
                     timeline: result.timeline.opt().map(|t| t.quot).into(),
                     spatial: result.spatial.opt().map(|t| t.quot).into(),
-                }
+                })
             }
         }
     }
 
-    fn meta_lookup_loc(&self, meta: &MetaId) -> (FuncletId, expir::FuncletKind) {
-        let error = format!("{} doesn't have a meta map", &self.location.funclet_name);
-        let mapping = self.meta_map.as_ref().unwrap_or_else(|| panic!(error));
+    fn meta_lookup_loc(
+        &self,
+        meta: &MetaId,
+    ) -> Result<(FuncletId, expir::FuncletKind), CompileError> {
+        let mapping = self.meta_map.as_ref().ok_or_else(|| {
+            self.error(format!(
+                "{} doesn't have a meta map",
+                &self.location.funclet_name
+            ))
+        })?;
         if mapping.value.0 == *meta {
-            (mapping.value.1.clone(), expir::FuncletKind::Value)
+            Ok((mapping.value.1.clone(), expir::FuncletKind::Value))
         } else if mapping.timeline.0 == *meta {
-            (mapping.timeline.1.clone(), expir::FuncletKind::Timeline)
+            Ok((mapping.timeline.1.clone(), expir::FuncletKind::Timeline))
         } else if mapping.spatial.0 == *meta {
-            (mapping.spatial.1.clone(), expir::FuncletKind::Spatial)
+            Ok((mapping.spatial.1.clone(), expir::FuncletKind::Spatial))
         } else {
-            panic!("Invalid meta name {}", meta)
+            Err(self.error(format!("Invalid meta name {}", meta)))
         }
     }
 
-    pub fn meta_lookup(&self, meta: &MetaId) -> FuncletId {
-        self.meta_lookup_loc(meta).0
+    pub fn meta_lookup(&self, meta: &MetaId) -> Result<FuncletId, CompileError> {
+        Ok(self.meta_lookup_loc(meta)?.0)
     }
 
     pub fn set_meta_map(&mut self, meta_map: ast::MetaMapping) {
@@ -474,6 +508,7 @@ impl Context {
         match self {
             Context {
                 path,
+                filename,
                 ffi_type_table,
                 local_type_table,
                 native_type_map,
@@ -483,6 +518,9 @@ impl Context {
                 funclet_indices,
                 function_classes,
                 effects,
+                mut spans,
+                external_spans,
+                shader_modules,
             } => {
                 let type_map = local_type_table
                     .drain("_UNNAMED_TYPE_".to_string())
@@ -500,13 +538,19 @@ impl Context {
                     .map(|s| s.0)
                     .enumerate()
                     .collect();
-                let external_function_map = funclet_indices
+                let external_function_map: HashMap<usize, String> = funclet_indices
                     .external_funclet_table
                     .drain(ExternalFunctionId("_UNNAMED_EXTERNAL_".to_string()))
                     .into_iter()
                     .map(|s| s.0)
                     .enumerate()
                     .collect();
+                let external_function_spans = external_function_map
+                    .iter()
+                    .filter_map(|(index, name)| {
+                        external_spans.get(name).map(|range| (*index, *range))
+                    })
+                    .collect();
                 let mut funclet_map = HashMap::new();
                 for (index, funclet) in funclet_indices
                     .local_funclet_table
@@ -518,11 +562,19 @@ impl Context {
                     for (node, quot) in variable_map.get_mut(&funclet).unwrap().drain() {
                         node_map.insert(quot, node.0);
                     }
+                    let funclet_spans = spans.remove(&funclet).unwrap_or_default();
+                    let node_spans = funclet_spans
+                        .nodes
+                        .into_iter()
+                        .map(|(node, range)| (node.0, range))
+                        .collect();
                     funclet_map.insert(
                         index,
                         FuncletDebugMap {
                             name: funclet.0,
                             node_map,
+                            span: funclet_spans.funclet,
+                            node_spans,
                         },
                     );
                 }
@@ -531,7 +583,9 @@ impl Context {
                     ffi_type_map,
                     function_class_map,
                     external_function_map,
+                    external_function_spans,
                     funclet_map,
+                    source_file: filename,
                 }
             }
         }
@@ -3,36 +3,45 @@ use crate::assembly::ast::FFIType;
 use crate::assembly::ast::NodeId;
 use crate::assembly::context;
 use crate::assembly::context::Context;
-use crate::assembly::parser;
 use crate::explication::expir;
 use crate::explication::Hole;
+use crate::frontend::{self, CompileError};
 use crate::ir::ffi;
 use crate::stable_vec::StableVec;
-use crate::{assembly, frontend};
 use paste::paste;
-use std::any::Any;
 use std::collections::{BTreeSet, HashMap};
 
 // for reading GPU stuff
+use crate::shadergen::{ShaderError, ShaderModule};
 use std::convert::TryFrom;
-use std::fs::File;
-use std::io::Read;
-use std::path::Path;
-
-use super::context::reject_opt;
+use std::path::{Path, PathBuf};
+
+// lowers something that may be a hole
+fn lower_hole<T, U>(
+    hole: &Hole<T>,
+    lower: impl FnOnce(&T) -> Result<U, CompileError>,
+) -> Result<Hole<U>, CompileError> {
+    Ok(hole.as_ref().opt().map(lower).transpose()?.into())
+}
 
-pub fn undefined<T>(h: Hole<T>) -> T {
-    match h {
-        Hole::Filled(v) => v,
-        Hole::Empty => panic!(""),
-    }
+// lowers a list where both the list and each of its elements may be holes
+fn lower_holes<T, U>(
+    holes: &Hole<Vec<Hole<T>>>,
+    lower: impl Fn(&T) -> Result<U, CompileError>,
+) -> Result<Hole<Box<[Hole<U>]>>, CompileError> {
+    lower_hole(holes, |holes| {
+        holes.iter().map(|hole| lower_hole(hole, &lower)).collect()
+    })
 }
 
-pub fn ffi_to_ffi(value: FFIType, context: &mut Context) -> ffi::Type {
-    fn box_map(b: Box<[FFIType]>, context: &mut Context) -> Box<[ffi::TypeId]> {
+pub fn ffi_to_ffi(value: FFIType, context: &mut Context) -> Result<ffi::Type, CompileError> {
+    fn box_map(
+        b: Box<[FFIType]>,
+        context: &mut Context,
+    ) -> Result<Box<[ffi::TypeId]>, CompileError> {
         b.iter().map(|x| context.ffi_type_id(x)).collect()
     }
-    match value {
+    Ok(match value {
         ast::FFIType::F32 => ffi::Type::F32,
         ast::FFIType::F64 => ffi::Type::F64,
         ast::FFIType::U8 => ffi::Type::U8,
@@ -48,122 +57,108 @@ pub fn ffi_to_ffi(value: FFIType, context: &mut Context) -> ffi::Type {
             element_type,
             length,
         } => ffi::Type::Array {
-            element_type: context.ffi_type_id(element_type.as_ref()),
+            element_type: context.ffi_type_id(element_type.as_ref())?,
             length,
         },
         ast::FFIType::ErasedLengthArray(element_type) => ffi::Type::ErasedLengthArray {
-            element_type: context.ffi_type_id(element_type.as_ref()),
+            element_type: context.ffi_type_id(element_type.as_ref())?,
         },
-        ast::FFIType::Struct {
-            fields,
-            byte_alignment,
-            byte_size,
-        } => todo!(),
+        ast::FFIType::Struct { .. } => {
+            return Err(context.error("Struct ffi types aren't supported yet".to_string()))
+        }
         ast::FFIType::Tuple(element_types) => ffi::Type::Tuple {
-            fields: box_map(element_types.into_boxed_slice(), context),
+            fields: box_map(element_types.into_boxed_slice(), context)?,
         },
         ast::FFIType::ConstRef(element_type) => ffi::Type::ConstRef {
-            element_type: context.ffi_type_id(element_type.as_ref()),
+            element_type: context.ffi_type_id(element_type.as_ref())?,
         },
         ast::FFIType::MutRef(element_type) => ffi::Type::MutRef {
-            element_type: context.ffi_type_id(element_type.as_ref()),
+            element_type: context.ffi_type_id(element_type.as_ref())?,
         },
         ast::FFIType::ConstSlice(element_type) => ffi::Type::ConstSlice {
-            element_type: context.ffi_type_id(element_type.as_ref()),
+            element_type: context.ffi_type_id(element_type.as_ref())?,
         },
         ast::FFIType::MutSlice(element_type) => ffi::Type::MutSlice {
-            element_type: context.ffi_type_id(element_type.as_ref()),
+            element_type: context.ffi_type_id(element_type.as_ref())?,
         },
         ast::FFIType::GpuBufferRef(element_type) => ffi::Type::GpuBufferRef {
-            element_type: context.ffi_type_id(element_type.as_ref()),
+            element_type: context.ffi_type_id(element_type.as_ref())?,
         },
         ast::FFIType::GpuBufferSlice(element_type) => ffi::Type::GpuBufferSlice {
-            element_type: context.ffi_type_id(element_type.as_ref()),
+            element_type: context.ffi_type_id(element_type.as_ref())?,
         },
         ast::FFIType::GpuBufferAllocator => ffi::Type::GpuBufferAllocator,
         ast::FFIType::CpuBufferAllocator => ffi::Type::CpuBufferAllocator,
         ast::FFIType::CpuBufferRef(element_type) => ffi::Type::CpuBufferRef {
-            element_type: context.ffi_type_id(element_type.as_ref()),
+            element_type: context.ffi_type_id(element_type.as_ref())?,
         },
         ast::FFIType::GpuFence => ffi::Type::GpuFence,
-        ast::FFIType::Unknown => unreachable!(),
-    }
+        ast::FFIType::Unknown => return Err(context.error("Unknown ffi type".to_string())),
+    })
 }
 
 // Translation
 
 fn ir_version(version: &ast::Version) -> (u32, u32, u32) {
-    let result = (
-        u32::try_from(version.major).unwrap(),
-        u32::try_from(version.minor).unwrap(),
-        u32::try_from(version.detailed).unwrap(),
-    );
-    assert_eq!(result, (0, 0, 2));
-    result
+    // out of range versions are reported as unsupported by the frontend
+    (
+        u32::try_from(version.major).unwrap_or(u32::MAX),
+        u32::try_from(version.minor).unwrap_or(u32::MAX),
+        u32::try_from(version.detailed).unwrap_or(u32::MAX),
+    )
 }
 
 fn ir_external_gpu_resource(
     d: &ast::ExternalGpuFunctionResourceBinding,
     input_args: &Vec<Option<ast::NodeId>>,
     output_args: &Vec<Option<ast::NodeId>>,
+    external: &ast::ExternalFunction,
     context: &mut Context,
-) -> ffi::GpuKernelResourceBinding {
-    fn local_name(
-        val: &ast::NodeId,
-        input_args: &Vec<Option<ast::NodeId>>,
-        output_args: &Vec<Option<ast::NodeId>>,
-    ) -> usize {
-        let mut index = 0;
-        for arg in input_args {
-            if Some(val) == arg.as_ref() {
-                return index;
-            }
-            index += 1;
-        }
-        let mut index = 0;
-        for arg in output_args {
-            if Some(val) == arg.as_ref() {
-                return index;
-            }
-            index += 1;
-        }
-        panic!("Unknown GPU variable {:?}", val)
-    }
+) -> Result<ffi::GpuKernelResourceBinding, CompileError> {
+    let local_name = |val: &ast::NodeId| {
+        input_args
+            .iter()
+            .position(|arg| Some(val) == arg.as_ref())
+            .or_else(|| output_args.iter().position(|arg| Some(val) == arg.as_ref()))
+            .ok_or_else(|| {
+                context.error_at(
+                    context.external_spans.get(&external.name).copied(),
+                    format!("Unknown GPU variable {} in {}", val, external.name),
+                )
+            })
+    };
     let group = d.group.clone();
     let binding = d.binding.clone();
-    let input = d
-        .input
-        .as_ref()
-        .map(|x| local_name(&x, input_args, output_args));
-    let output = d
-        .output
-        .as_ref()
-        .map(|x| local_name(&x, input_args, output_args));
-    ffi::GpuKernelResourceBinding {
+    let input = d.input.as_ref().map(local_name).transpose()?;
+    let output = d.output.as_ref().map(local_name).transpose()?;
+    Ok(ffi::GpuKernelResourceBinding {
         group,
         binding,
         input,
         output,
-    }
+    })
 }
 
-fn ir_external(external: &ast::ExternalFunction, context: &mut Context) -> ffi::ExternalFunction {
+fn ir_external(
+    external: &ast::ExternalFunction,
+    context: &mut Context,
+) -> Result<ffi::ExternalFunction, CompileError> {
     let mut input_types = Vec::new();
     let mut input_args = Vec::new();
     let mut output_args = Vec::new();
     let mut output_types = Vec::new();
 
     for arg in &external.input_args {
-        input_types.push(context.ffi_type_id(&arg.ffi_type));
+        input_types.push(context.ffi_type_id(&arg.ffi_type)?);
         input_args.push(arg.name.clone());
     }
     for arg in &external.output_types {
-        output_types.push(context.ffi_type_id(&arg.ffi_type));
+        output_types.push(context.ffi_type_id(&arg.ffi_type)?);
         let arg_name = arg.name.clone();
         output_args.push(arg_name);
     }
 
-    match &external.kind {
+    Ok(match &external.kind {
         ast::ExternalFunctionKind::CPUEffect => {
             ffi::ExternalFunction::CpuEffectfulOperation(ffi::CpuEffectfulOperation {
                 name: external.name.clone(),
@@ -186,38 +181,21 @@ fn ir_external(external: &ast::ExternalFunction, context: &mut Context) -> ffi::
                     resource,
                     &input_args,
                     &output_args,
+                    external,
                     context,
-                ));
+                )?);
             }
 
-            let input_path = Path::new(&binding_info.shader_module);
-            let program_path = Path::new(&context.path);
-            let extension = input_path.extension().unwrap();
-            let mut input_file = match File::open(program_path.join(input_path)) {
-                Err(why) => panic!("Couldn't open {}: {}", input_path.display(), why),
-                Ok(file) => file,
-            };
-            let mut content = String::new();
-            match input_file.read_to_string(&mut content) {
-                Err(why) => panic!("Couldn't read file: {}", why),
-                Ok(_) => (),
-            };
-            let shader_module = if extension == "comp" {
-                match crate::shadergen::ShaderModule::from_glsl(content.as_str()) {
-                    Err(why) => panic!("Couldn't parse as glsl: {}", why),
-                    Ok(sm) => sm,
-                }
-            } else if extension == "wgsl" {
-                match crate::shadergen::ShaderModule::from_wgsl(content.as_str()) {
-                    Err(why) => panic!("Couldn't parse as glsl: {}", why),
-                    Ok(sm) => sm,
-                }
-            } else {
-                panic!(
-                    "Unsupported extension for filename {:?}, .wgsl or .comp expected",
-                    input_path
-                )
-            };
+            let shader_module = context
+                .shader_modules
+                .get(&binding_info.shader_module)
+                .cloned()
+                .ok_or_else(|| {
+                    context.error_at(
+                        context.external_spans.get(&external.name).copied(),
+                        format!("Shader {} wasn't read", binding_info.shader_module),
+                    )
+                })?;
             ffi::ExternalFunction::GpuKernel(ffi::GpuKernel {
                 name: external.name.clone(),
                 input_types: input_types.into_boxed_slice(),
@@ -228,10 +206,13 @@ fn ir_external(external: &ast::ExternalFunction, context: &mut Context) -> ffi::
                 shader_module,
             })
         }
-    }
+    })
 }
 
-fn ir_native_interface(program: &ast::Program, context: &mut Context) -> ffi::NativeInterface {
+fn ir_native_interface(
+    program: &ast::Program,
+    context: &mut Context,
+) -> Result<ffi::NativeInterface, CompileError> {
     let mut types = StableVec::new();
     let mut external_functions = StableVec::new();
     let mut effects = StableVec::new();
@@ -239,39 +220,42 @@ fn ir_native_interface(program: &ast::Program, context: &mut Context) -> ffi::Na
     for declaration in &program.declarations {
         match declaration {
             ast::Declaration::TypeDecl(ast::TypeDecl::FFI(t)) => {
-                types.add(ffi_to_ffi(t.clone(), context));
+                types.add(ffi_to_ffi(t.clone(), context)?);
             }
             ast::Declaration::ExternalFunction(external) => {
-                external_functions.add(ir_external(external, context));
+                external_functions.add(ir_external(external, context)?);
             }
             ast::Declaration::Effect(effect) => {
-                effects.add(ir_effect(effect, context));
+                effects.add(ir_effect(effect, context)?);
             }
             _ => {}
         }
     }
 
-    ffi::NativeInterface {
+    Ok(ffi::NativeInterface {
         types,
         external_functions,
         effects,
-    }
+    })
 }
 
-fn ir_type_decl(type_decl: &ast::TypeDecl, context: &mut Context) -> Option<expir::Type> {
+fn ir_type_decl(
+    type_decl: &ast::TypeDecl,
+    context: &mut Context,
+) -> Result<Option<expir::Type>, CompileError> {
     match type_decl {
         ast::TypeDecl::Local(typ) => {
-            Some(match &typ.data {
+            Ok(Some(match &typ.data {
                 // only supported custom types atm
                 ast::LocalTypeInfo::NativeValue { storage_type } => expir::Type::NativeValue {
-                    storage_type: context.ffi_type_id(&storage_type),
+                    storage_type: context.ffi_type_id(&storage_type)?,
                 },
                 ast::LocalTypeInfo::Ref {
                     storage_type,
                     storage_place,
                     buffer_flags,
                 } => expir::Type::Ref {
-                    storage_type: context.ffi_type_id(&storage_type),
+                    storage_type: context.ffi_type_id(&storage_type)?,
                     storage_place: storage_place.clone(),
                     buffer_flags: buffer_flags.clone(),
                 },
@@ -292,15 +276,15 @@ fn ir_type_decl(type_decl: &ast::TypeDecl, context: &mut Context) -> Option<expi
                 },
                 ast::LocalTypeInfo::Event {} => expir::Type::Event {},
                 ast::LocalTypeInfo::BufferSpace => expir::Type::BufferSpace,
-            })
+            }))
         }
-        ast::TypeDecl::FFI(name) => None,
+        ast::TypeDecl::FFI(name) => Ok(None),
     }
 }
 
 macro_rules! lower_element {
     ($arg:ident [$arg_type:ident] $context:ident) => {
-        $arg.as_ref().opt().map(|v| v.iter().map(|e| lower_element!(e $arg_type $context)).collect()).into()
+        lower_hole($arg, |v| v.iter().map(|e| Ok(lower_element!(e $arg_type $context))).collect())?
     };
     ($arg:ident Immediate $context:ident) => {
         // different enough we use a custom function
@@ -314,27 +298,25 @@ macro_rules! lower_element {
         $arg.clone()
     };
     ($arg:ident ExternalFunction $context:ident) => {
-        $arg.as_ref().opt().map(|n| $context.external_funclet_id(n)).into()
+        lower_hole($arg, |n| $context.external_funclet_id(n))?
     };
     ($arg:ident ValueFunction $context:ident) => {
-        $arg.as_ref().opt().map(|n| $context.function_class_id(n)).into()
+        lower_hole($arg, |n| $context.function_class_id(n))?
     };
     ($arg:ident Operation $context:ident) => {
-        $arg.as_ref().opt().map(|n| $context.node_id(n)).into()
+        lower_hole($arg, |n| $context.node_id(n))?
     };
     ($arg:ident RemoteOperation $context:ident) => {
-        $arg.as_ref().opt().map(|r| $context.remote_id(r)).into()
+        lower_hole($arg, |r| $context.remote_id(r))?
     };
     ($arg:ident Place $context:ident) => {
         $arg.clone()
     };
     ($arg:ident Funclet $context:ident) => {
-        $arg.as_ref().opt().map(|n| $context.funclet_id(n)).into()
+        lower_hole($arg, |n| $context.funclet_id(n))?
     };
     ($arg:ident StorageType $context:ident) => {
-        $arg.as_ref().opt().map(|n| match n {
-            ffi => $context.ffi_type_id(ffi),
-        }).into()
+        lower_hole($arg, |ffi| $context.ffi_type_id(ffi))?
     };
     ($arg:ident BufferFlags $context:ident) => {
         $arg.clone()
@@ -345,16 +327,19 @@ macro_rules! lower_node {
     ($($_lang:ident $name:ident ($($arg:ident : $arg_type:tt,)*) -> $_output:ident;)*) => {
         paste! {
             /*
-             * Lowers by rejecting every hole in the node
+             * Lowers every name in the node, leaving its holes for explication
              */
-            pub fn ir_non_constant_node(node : &ast::Node, context: &Context) -> expir::Node {
-                match node {
+            pub fn ir_non_constant_node(
+                node : &ast::Node,
+                context: &Context
+            ) -> Result<expir::Node, CompileError> {
+                Ok(match node {
                     $(ast::Node::$name { $($arg,)* } => {
                         expir::Node::$name {
                             $($arg : lower_element!($arg $arg_type context),)*
                         }
                     }),*
-                }
+                })
             }
         }
     }
@@ -362,64 +347,98 @@ macro_rules! lower_node {
 
 with_operations!(lower_node);
 
-fn ir_node(node: &ast::Node, context: &Context) -> expir::Node {
+fn parse_constant<T>(
+    value: &str,
+    type_id: &ast::TypeId,
+    context: &Context,
+) -> Result<T, CompileError>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    value.parse::<T>().map_err(|error| {
+        context.error(format!(
+            "Invalid constant {} of type {}: {}",
+            value, type_id, error
+        ))
+    })
+}
+
+fn ir_node(node: &ast::Node, context: &Context) -> Result<expir::Node, CompileError> {
     match &node {
         ast::Node::Constant { value, type_id } => {
             // we don't allow holes for constants
-            let error = format!("Constant {:?} cannot have explication holes", &node);
-            let unwrapped_value = value.as_ref().opt().expect(&error).clone();
-            let unwrapped_type = type_id.as_ref().opt().expect(&error).clone();
-            let parsed_value = match context.native_type_map.get(&unwrapped_type.0) {
-                None => panic!("{:?} must have a native type", type_id),
+            let error =
+                || context.error(format!("Constant {} cannot have explication holes", node));
+            let value = value.as_ref().opt().ok_or_else(error)?;
+            let type_id = type_id.as_ref().opt().ok_or_else(error)?;
+            let parsed_value = match context.native_type_map.get(&type_id.0) {
+                None => return Err(context.error(format!("{} must have a native type", type_id))),
                 Some(t) => match t {
                     ast::FFIType::U64 => {
-                        expir::Constant::U64(unwrapped_value.parse::<u64>().unwrap())
+                        expir::Constant::U64(parse_constant(value, type_id, context)?)
                     }
                     ast::FFIType::I32 => {
-                        expir::Constant::I32(unwrapped_value.parse::<i32>().unwrap())
+                        expir::Constant::I32(parse_constant(value, type_id, context)?)
                     }
                     ast::FFIType::I64 => {
-                        expir::Constant::I64(unwrapped_value.parse::<i64>().unwrap())
+                        expir::Constant::I64(parse_constant(value, type_id, context)?)
+                    }
+                    ast::FFIType::I8 => {
+                        expir::Constant::I8(parse_constant(value, type_id, context)?)
+                    }
+                    ast::FFIType::I16 => {
+                        expir::Constant::I16(parse_constant(value, type_id, context)?)
+                    }
+                    ast::FFIType::U8 => {
+                        expir::Constant::U8(parse_constant(value, type_id, context)?)
+                    }
+                    ast::FFIType::U16 => {
+                        expir::Constant::U16(parse_constant(value, type_id, context)?)
+                    }
+                    ast::FFIType::U32 => {
+                        expir::Constant::U32(parse_constant(value, type_id, context)?)
+                    }
+                    ast::FFIType::USize => {
+                        expir::Constant::USize(parse_constant(value, type_id, context)?)
+                    }
+                    ast::FFIType::F32 => {
+                        expir::Constant::F32(parse_constant(value, type_id, context)?)
+                    }
+                    ast::FFIType::F64 => {
+                        expir::Constant::F64(parse_constant(value, type_id, context)?)
+                    }
+                    _ => {
+                        return Err(context.error(format!("Unsupported constant type {}", type_id)))
                     }
-                    _ => panic!("Unsupported constant type {:?}", type_id),
                 },
             };
-            expir::Node::Constant {
+            Ok(expir::Node::Constant {
                 value: Hole::Filled(parsed_value),
-                type_id: Hole::Filled(context.loc_type_id(&unwrapped_type)),
-            }
+                type_id: Hole::Filled(context.loc_type_id(type_id)?),
+            })
         }
         _ => ir_non_constant_node(node, context),
     }
 }
 
-fn ir_tail_edge(tail: &ast::TailEdge, context: &mut Context) -> expir::TailEdge {
-    match tail {
+fn ir_tail_edge(
+    tail: &ast::TailEdge,
+    context: &mut Context,
+) -> Result<expir::TailEdge, CompileError> {
+    let context = &*context;
+    let node_id = |n: &NodeId| context.node_id(n);
+    let funclet_id = |f: &ast::FuncletId| context.funclet_id(f);
+    Ok(match tail {
         ast::TailEdge::DebugHole { inputs } => expir::TailEdge::DebugHole {
-            inputs: inputs.iter().map(|n| context.node_id(n)).collect(),
+            inputs: inputs.iter().map(node_id).collect::<Result<_, _>>()?,
         },
         ast::TailEdge::Return { return_values } => expir::TailEdge::Return {
-            return_values: return_values
-                .as_ref()
-                .opt()
-                .map(|v| {
-                    v.iter()
-                        .map(|n| n.as_ref().opt().map(|id| context.node_id(id)).into())
-                        .collect()
-                })
-                .into(),
+            return_values: lower_holes(return_values, node_id)?,
         },
         ast::TailEdge::Jump { join, arguments } => expir::TailEdge::Jump {
-            join: join.as_ref().opt().map(|n| context.node_id(n)).into(),
-            arguments: arguments
-                .as_ref()
-                .opt()
-                .map(|args| {
-                    args.iter()
-                        .map(|o| o.as_ref().opt().map(|n| context.node_id(n)).into())
-                        .collect()
-                })
-                .into(),
+            join: lower_hole(join, node_id)?,
+            arguments: lower_holes(arguments, node_id)?,
         },
         ast::TailEdge::ScheduleCall {
             operations,
@@ -427,30 +446,14 @@ fn ir_tail_edge(tail: &ast::TailEdge, context: &mut Context) -> expir::TailEdge
             callee_arguments,
             continuation_join,
         } => {
-            let operation_set = context.operational_lookup(operations);
+            let operation_set = context.operational_lookup(operations)?;
             expir::TailEdge::ScheduleCall {
                 value_operation: operation_set.value,
                 timeline_operation: operation_set.timeline,
                 spatial_operation: operation_set.spatial,
-                callee_funclet_id: callee_funclet_id
-                    .as_ref()
-                    .opt()
-                    .map(|f| context.funclet_id(f))
-                    .into(),
-                callee_arguments: callee_arguments
-                    .as_ref()
-                    .opt()
-                    .map(|args| {
-                        args.iter()
-                            .map(|o| o.as_ref().opt().map(|n| context.node_id(n)).into())
-                            .collect()
-                    })
-                    .into(),
-                continuation_join: continuation_join
-                    .as_ref()
-                    .opt()
-                    .map(|n| context.node_id(n))
-                    .into(),
+                callee_funclet_id: lower_hole(callee_funclet_id, funclet_id)?,
+                callee_arguments: lower_holes(callee_arguments, node_id)?,
+                continuation_join: lower_hole(continuation_join, node_id)?,
             }
         }
         ast::TailEdge::ScheduleSelect {
@@ -460,35 +463,15 @@ fn ir_tail_edge(tail: &ast::TailEdge, context: &mut Context) -> expir::TailEdge
             callee_arguments,
             continuation_join,
         } => {
-            let operation_set = context.operational_lookup(operations);
+            let operation_set = context.operational_lookup(operations)?;
             expir::TailEdge::ScheduleSelect {
                 value_operation: operation_set.value,
                 timeline_operation: operation_set.timeline,
                 spatial_operation: operation_set.spatial,
-                condition: condition.as_ref().opt().map(|n| context.node_id(n)).into(),
-                callee_funclet_ids: callee_funclet_ids
-                    .as_ref()
-                    .opt()
-                    .map(|args| {
-                        args.iter()
-                            .map(|o| o.as_ref().opt().map(|f| context.funclet_id(f)).into())
-                            .collect()
-                    })
-                    .into(),
-                callee_arguments: callee_arguments
-                    .as_ref()
-                    .opt()
-                    .map(|args| {
-                        args.iter()
-                            .map(|o| o.as_ref().opt().map(|n| context.node_id(n)).into())
-                            .collect()
-                    })
-                    .into(),
-                continuation_join: continuation_join
-                    .as_ref()
-                    .opt()
-                    .map(|n| context.node_id(n))
-                    .into(),
+                condition: lower_hole(condition, node_id)?,
+                callee_funclet_ids: lower_holes(callee_funclet_ids, funclet_id)?,
+                callee_arguments: lower_holes(callee_arguments, node_id)?,
+                continuation_join: lower_hole(continuation_join, node_id)?,
             }
         }
         ast::TailEdge::ScheduleCallYield {
@@ -497,33 +480,36 @@ fn ir_tail_edge(tail: &ast::TailEdge, context: &mut Context) -> expir::TailEdge
             yielded_nodes,
             continuation_join,
         } => {
-            let operation_set = context.operational_lookup(operations);
+            let operation_set = context.operational_lookup(operations)?;
             expir::TailEdge::ScheduleCallYield {
                 value_operation: operation_set.value,
                 timeline_operation: operation_set.timeline,
                 spatial_operation: operation_set.spatial,
-                external_function_id: external_function_id
-                    .as_ref()
-                    .opt()
-                    .map(|id| context.external_funclet_id(id))
-                    .into(),
-                yielded_nodes: yielded_nodes
-                    .as_ref()
-                    .opt()
-                    .map(|args| {
-                        args.iter()
-                            .map(|o| o.as_ref().opt().map(|n| context.node_id(n)).into())
-                            .collect()
-                    })
-                    .into(),
-                continuation_join: continuation_join
-                    .as_ref()
-                    .opt()
-                    .map(|n| context.node_id(n))
-                    .into(),
+                external_function_id: lower_hole(external_function_id, |id| {
+                    context.external_funclet_id(id)
+                })?,
+                yielded_nodes: lower_holes(yielded_nodes, node_id)?,
+                continuation_join: lower_hole(continuation_join, node_id)?,
             }
         }
-    }
+        ast::TailEdge::DynamicAllocFromBuffer {
+            buffer,
+            dynamic_allocation_size_slots,
+            success_funclet_id,
+            failure_funclet_id,
+            arguments,
+            continuation_join,
+        } => expir::TailEdge::DynamicAllocFromBuffer {
+            buffer: lower_hole(buffer, node_id)?,
+            arguments: lower_holes(arguments, node_id)?,
+            dynamic_allocation_size_slots: lower_holes(dynamic_allocation_size_slots, |slot| {
+                slot.as_ref().map(node_id).transpose()
+            })?,
+            success_funclet_id: lower_hole(success_funclet_id, funclet_id)?,
+            failure_funclet_id: lower_hole(failure_funclet_id, funclet_id)?,
+            continuation_join: lower_hole(continuation_join, node_id)?,
+        },
+    })
 }
 
 // updates the location in the context value funclet
@@ -532,7 +518,7 @@ fn ir_schedule_binding(
     implicit_tags: &(ast::Tag, ast::Tag),
     meta_map: &ast::MetaMapping,
     context: &mut Context,
-) -> expir::FuncletSpecBinding {
+) -> Result<expir::FuncletSpecBinding, CompileError> {
     context.set_meta_map(meta_map.clone());
 
     struct TagBindings {
@@ -553,39 +539,39 @@ fn ir_schedule_binding(
     };
 
     for arg in &funclet_header.args {
-        let tags = context.tag_lookup(&arg.tags.iter().map(|t| Hole::Filled(t.clone())).collect());
+        let tags =
+            context.tag_lookup(&arg.tags.iter().map(|t| Hole::Filled(t.clone())).collect())?;
         input_tags.value_tags.push(tags.value.clone());
         input_tags.spatial_tags.push(tags.spatial.clone());
         input_tags.timeline_tags.push(tags.timeline.clone());
     }
 
     for ret in &funclet_header.ret {
-        let tags = context.tag_lookup(&ret.tags.iter().map(|t| Hole::Filled(t.clone())).collect());
+        let tags =
+            context.tag_lookup(&ret.tags.iter().map(|t| Hole::Filled(t.clone())).collect())?;
         output_tags.value_tags.push(tags.value.clone());
         output_tags.spatial_tags.push(tags.spatial.clone());
         output_tags.timeline_tags.push(tags.timeline.clone());
     }
 
     // get and validate the implicit tags to be timeline tags
-    let implicit_in_lookup = context.tag_lookup(&vec![Hole::Filled(implicit_tags.0.clone())]);
-    let implicit_out_lookup = context.tag_lookup(&vec![Hole::Filled(implicit_tags.1.clone())]);
+    let implicit_in_lookup = context.tag_lookup(&vec![Hole::Filled(implicit_tags.0.clone())])?;
+    let implicit_out_lookup = context.tag_lookup(&vec![Hole::Filled(implicit_tags.1.clone())])?;
 
     // we want an error to make sure user input isn't being thrown out quietly
-    let error_tags = implicit_tags.clone();
-    let error_in = format!(
-        "Implicit tag {:?} invalid: implicit tags must be for the timeline",
-        error_tags.0
-    );
-    let error_out = format!(
-        "Implicit tag {:?} invalid: implicit tags must be for the timeline",
-        error_tags.1
-    );
-    assert!(implicit_in_lookup.value.opt().is_none(), error_in);
-    assert!(implicit_in_lookup.spatial.opt().is_none(), error_in);
-    assert!(implicit_out_lookup.value.opt().is_none(), error_out);
-    assert!(implicit_out_lookup.spatial.opt().is_none(), error_out);
-
-    expir::FuncletSpecBinding::ScheduleExplicit {
+    for (tag, lookup) in [
+        (&implicit_tags.0, &implicit_in_lookup),
+        (&implicit_tags.1, &implicit_out_lookup),
+    ] {
+        if lookup.value.is_filled() || lookup.spatial.is_filled() {
+            return Err(context.error(format!(
+                "Implicit tag {:?} invalid: implicit tags must be for the timeline",
+                tag
+            )));
+        }
+    }
+
+    Ok(expir::FuncletSpecBinding::ScheduleExplicit {
         value: expir::FuncletSpec {
             funclet_id_opt: context.funclet_indices.get_funclet(&meta_map.value.1 .0),
             input_tags: input_tags.value_tags.into_boxed_slice(),
@@ -611,24 +597,23 @@ fn ir_schedule_binding(
             implicit_in_tag: Hole::Filled(Default::default()),
             implicit_out_tag: Hole::Filled(Default::default()),
         },
-    }
+    })
 }
 
 fn ir_spec_binding(
     funclet_header: &ast::FuncletHeader,
     context: &mut Context,
-) -> expir::FuncletSpecBinding {
+) -> Result<expir::FuncletSpecBinding, CompileError> {
     match &funclet_header.binding {
-        ast::FuncletBinding::None => expir::FuncletSpecBinding::None,
+        ast::FuncletBinding::None => Ok(expir::FuncletSpecBinding::None),
         ast::FuncletBinding::SpecBinding(ast::FunctionClassBinding {
             default,
             function_class,
         }) => {
-            let value_function_id_opt =
-                Some(context.function_classes.get(&function_class).unwrap());
-            expir::FuncletSpecBinding::Value {
+            let value_function_id_opt = Some(context.function_class_id(function_class)?);
+            Ok(expir::FuncletSpecBinding::Value {
                 value_function_id_opt,
-            }
+            })
         }
         ast::FuncletBinding::ScheduleBinding(ast::ScheduleBinding {
             implicit_tags,
@@ -637,21 +622,25 @@ fn ir_spec_binding(
     }
 }
 
-fn ir_funclet(funclet: &ast::Funclet, context: &mut Context) -> expir::Funclet {
+fn ir_funclet(
+    funclet: &ast::Funclet,
+    context: &mut Context,
+) -> Result<expir::Funclet, CompileError> {
     context.location.funclet_name = funclet.header.name.clone();
+    context.location.node_name = None;
     // note that this is stateful, updates the value_funclet in context potentially
-    let spec_binding = ir_spec_binding(&funclet.header, context);
+    let spec_binding = ir_spec_binding(&funclet.header, context)?;
     let mut input_types = Vec::new();
     let mut output_types = Vec::new();
     let mut nodes = Vec::new();
     let mut tail_edge = Hole::Empty;
 
     for arg in &funclet.header.args {
-        input_types.push(context.loc_type_id(&arg.typ));
+        input_types.push(context.loc_type_id(&arg.typ)?);
     }
 
     for arg in &funclet.header.ret {
-        output_types.push(context.loc_type_id(&arg.typ));
+        output_types.push(context.loc_type_id(&arg.typ)?);
     }
 
     for command in &funclet.commands {
@@ -659,45 +648,50 @@ fn ir_funclet(funclet: &ast::Funclet, context: &mut Context) -> expir::Funclet {
             Hole::Empty => nodes.push(Hole::Empty),
             Hole::Filled(ast::Command::Node(node)) => {
                 context.location.node_name = node.name.clone();
-                nodes.push(Hole::Filled(ir_node(&node.node, context)));
+                nodes.push(Hole::Filled(ir_node(&node.node, context)?));
             }
             Hole::Filled(ast::Command::TailEdge(tail)) => {
-                if tail_edge.opt().is_some() {
-                    panic!("More than one tail edge in {:?}", funclet.header.name);
+                context.location.node_name = None;
+                if tail_edge.is_filled() {
+                    return Err(context.error(format!(
+                        "More than one tail edge in {}",
+                        funclet.header.name
+                    )));
                 }
-                tail_edge = Hole::Filled(ir_tail_edge(tail, context));
+                tail_edge = Hole::Filled(ir_tail_edge(tail, context)?);
             }
         }
     }
 
     // help avoid reuse issues
     context.reset_meta_map();
+    context.location = context::LocationNames::new();
 
-    expir::Funclet {
+    Ok(expir::Funclet {
         kind: funclet.kind.clone(),
         spec_binding,
         input_types: input_types.into_boxed_slice(),
         output_types: output_types.into_boxed_slice(),
         nodes: nodes.into_boxed_slice(),
         tail_edge,
-    }
+    })
 }
 
 fn ir_function_class(
     declarations: &Vec<ast::Declaration>,
     function: &ast::FunctionClass,
     context: &mut Context,
-) -> expir::FunctionClass {
+) -> Result<expir::FunctionClass, CompileError> {
     let mut input_types = Vec::new();
     let mut output_types = Vec::new();
     let mut default_funclet_id = None;
     let mut external_function_ids = BTreeSet::new();
 
     for typ in &function.input_types {
-        input_types.push(context.loc_type_id(&typ));
+        input_types.push(context.loc_type_id(&typ)?);
     }
     for typ in &function.output_types {
-        output_types.push(context.loc_type_id(&typ));
+        output_types.push(context.loc_type_id(&typ)?);
     }
 
     // not efficient, but whatever
@@ -706,15 +700,17 @@ fn ir_function_class(
             ast::Declaration::Funclet(f) => match &f.header.binding {
                 ast::FuncletBinding::SpecBinding(binding) => {
                     if binding.function_class == function.name {
-                        let current_id = context
-                            .funclet_indices
-                            .get_funclet(&f.header.name.0)
-                            .unwrap();
+                        let current_id = context.funclet_id(&f.header.name)?;
                         if binding.default {
                             default_funclet_id = match default_funclet_id {
                                 None => Some(current_id),
                                 Some(_) => {
-                                    panic!("Duplicate default ids for {:?}", function.name.clone())
+                                    let range =
+                                        context.spans.get(&f.header.name).and_then(|s| s.funclet);
+                                    return Err(context.error_at(
+                                        range,
+                                        format!("Duplicate default ids for {}", function.name),
+                                    ));
                                 }
                             }
                         }
@@ -725,31 +721,37 @@ fn ir_function_class(
             ast::Declaration::ExternalFunction(f) => {
                 if f.value_function_binding.function_class == function.name {
                     if f.value_function_binding.default {
-                        panic!(
-                            "{:?} uses default, which is unsupported for external functions",
-                            f.name
-                        )
+                        return Err(context.error_at(
+                            context.external_spans.get(&f.name).copied(),
+                            format!(
+                                "{} uses default, which is unsupported for external functions",
+                                f.name
+                            ),
+                        ));
                     }
-                    external_function_ids.insert(ffi::ExternalFunctionId(
-                        context.funclet_indices.get_funclet(&f.name).unwrap(),
-                    ));
+                    external_function_ids.insert(
+                        context.external_funclet_id(&ast::ExternalFunctionId(f.name.clone()))?,
+                    );
                 }
             }
             _ => {}
         }
     }
 
-    expir::FunctionClass {
+    Ok(expir::FunctionClass {
         name_opt: Some(function.name.0.clone()),
         input_types: input_types.into_boxed_slice(),
         output_types: output_types.into_boxed_slice(),
         default_funclet_id,
         external_function_ids,
-    }
+    })
 }
 
-fn ir_effect(declaration: &ast::EffectDeclaration, context: &mut Context) -> ffi::Effect {
-    match &declaration.effect {
+fn ir_effect(
+    declaration: &ast::EffectDeclaration,
+    context: &mut Context,
+) -> Result<ffi::Effect, CompileError> {
+    Ok(match &declaration.effect {
         ast::Effect::Unrestricted => ffi::Effect::Unrestricted,
         ast::Effect::FullyConnected {
             effectful_function_ids,
@@ -757,25 +759,32 @@ fn ir_effect(declaration: &ast::EffectDeclaration, context: &mut Context) -> ffi
             effectful_function_ids: effectful_function_ids
                 .iter()
                 .map(|fid| context.external_lookup(fid))
-                .collect(),
+                .collect::<Result<_, _>>()?,
         },
-    }
+    })
 }
 
-fn ir_pipeline(pipeline: &ast::Pipeline, context: &mut Context) -> expir::Pipeline {
-    expir::Pipeline {
+fn ir_pipeline(
+    pipeline: &ast::Pipeline,
+    context: &mut Context,
+) -> Result<expir::Pipeline, CompileError> {
+    Ok(expir::Pipeline {
         name: pipeline.name.clone(),
-        entry_funclet: context
-            .funclet_indices
-            .get_funclet(&pipeline.funclet.0)
-            .unwrap()
-            .clone(),
-        effect_id_opt: pipeline.effect.as_ref().map(|e| context.effect_lookup(e)),
-    }
+        entry_funclet: context.funclet_id(&pipeline.funclet)?,
+        effect_id_opt: pipeline
+            .effect
+            .as_ref()
+            .map(|e| context.effect_lookup(e))
+            .transpose()?,
+        recursion_limit_opt: pipeline.recursion_limit,
+    })
 }
 
-fn ir_program(program: &ast::Program, context: &mut Context) -> expir::Program {
-    let native_interface = ir_native_interface(&program, context);
+fn ir_program(
+    program: &ast::Program,
+    context: &mut Context,
+) -> Result<expir::Program, CompileError> {
+    let native_interface = ir_native_interface(&program, context)?;
     let mut types = StableVec::new();
     let mut funclets = StableVec::new();
     let mut function_classes = StableVec::new();
@@ -783,7 +792,7 @@ fn ir_program(program: &ast::Program, context: &mut Context) -> expir::Program {
 
     for declaration in &program.declarations {
         match declaration {
-            ast::Declaration::TypeDecl(t) => match ir_type_decl(t, context) {
+            ast::Declaration::TypeDecl(t) => match ir_type_decl(t, context)? {
                 Some(typ) => {
                     types.add(typ);
                 }
@@ -794,68 +803,72 @@ fn ir_program(program: &ast::Program, context: &mut Context) -> expir::Program {
                 // some duplicate looping, but whatever
             }
             ast::Declaration::FunctionClass(c) => {
-                function_classes.add(ir_function_class(&program.declarations, c, context));
+                function_classes.add(ir_function_class(&program.declarations, c, context)?);
             }
             ast::Declaration::Funclet(f) => {
-                funclets.add(ir_funclet(f, context));
+                funclets.add(ir_funclet(f, context)?);
             }
             ast::Declaration::Pipeline(p) => {
-                pipelines.push(ir_pipeline(p, context));
+                pipelines.push(ir_pipeline(p, context)?);
             }
             ast::Declaration::Effect(effect) => {}
         }
     }
 
-    expir::Program {
+    Ok(expir::Program {
         native_interface,
         types,
         funclets,
         function_classes,
         pipelines,
-    }
+    })
 }
 
 // check that the assumptions we make about the program hold
-fn check_assumptions(program: &ast::Program) {
+fn check_assumptions(program: &ast::Program, context: &mut Context) -> Result<(), CompileError> {
     // These are meant to make life a bit easier by making assumptions explicit
     for declaration in &program.declarations {
         match declaration {
             ast::Declaration::Funclet(funclet) => {
+                context.location.funclet_name = funclet.header.name.clone();
                 for (current, command) in funclet.commands.iter().enumerate() {
                     match command {
                         Hole::Empty => {
-                            assert!(
-                                funclet.kind == ast::FuncletKind::ScheduleExplicit,
-                                "Cannot have a command hole in non-scheduling funclet {}",
-                                &funclet.header.name
-                            );
+                            if funclet.kind != ast::FuncletKind::ScheduleExplicit {
+                                context.location.node_name = None;
+                                return Err(context.error(format!(
+                                    "Cannot have a command hole in non-scheduling funclet {}",
+                                    &funclet.header.name
+                                )));
+                            }
                         }
                         // check that an extract result follows a call in order
                         Hole::Filled(ast::Command::Node(node)) => match &node.node {
                             ast::Node::ExtractResult { node_id, index } => {
-                                let error = format!(
-                                    "Invalid use of ExtractResult for node {} in funclet {}, 
-                                    ExtractResult must apply to a previous call in order",
-                                    node.name.clone().unwrap_or(ast::NodeId("_".to_string())),
-                                    &funclet.header.name
-                                );
-                                let offset = index.as_ref().opt().expect(&error);
-                                match funclet.commands.get(current - offset - 1).expect(&error) {
+                                context.location.node_name = node.name.clone();
+                                let error = || {
+                                    context.error(format!(
+                                        "Invalid use of ExtractResult for node {} in funclet {}, \
+                                        ExtractResult must apply to a previous call in order",
+                                        node.name.clone().unwrap_or(ast::NodeId("_".to_string())),
+                                        &funclet.header.name
+                                    ))
+                                };
+                                let offset = index.as_ref().opt().ok_or_else(error)?;
+                                let call = current
+                                    .checked_sub(offset + 1)
+                                    .and_then(|call| funclet.commands.get(call))
+                                    .ok_or_else(error)?;
+                                match call {
                                     Hole::Empty => {}
                                     Hole::Filled(ast::Command::Node(check_node)) => {
                                         match &check_node.node {
-                                            ast::Node::CallFunctionClass {
-                                                function_id,
-                                                arguments,
-                                            } => {}
-                                            ast::Node::EncodingEvent {
-                                                local_past,
-                                                remote_local_pasts,
-                                            } => {}
-                                            _ => panic!(error),
+                                            ast::Node::CallFunctionClass { .. } => {}
+                                            ast::Node::EncodingEvent { .. } => {}
+                                            _ => return Err(error()),
                                         }
                                     }
-                                    _ => panic!(error),
+                                    _ => return Err(error()),
                                 }
                             }
                             _ => {}
@@ -867,20 +880,58 @@ fn check_assumptions(program: &ast::Program) {
             _ => {}
         }
     }
+    context.location = context::LocationNames::new();
+    Ok(())
 }
 
-pub fn lower(mut original: ast::Program) -> frontend::ExplicationDefinition {
-    // should probably handle errors with a result, future problem though
-    check_assumptions(&original);
-    let mut context = Context::new(&original);
-    // dbg!(&original);
-    // todo!();
+// Reads every shader the program's GPU kernels are implemented by, keyed by the path the
+//   program names it with
+// This happens before lowering so that a bad shader is reported against the shader itself
+pub fn read_shaders(
+    program: &ast::Program,
+) -> Result<HashMap<String, ShaderModule>, (PathBuf, ShaderError)> {
+    let mut shader_modules = HashMap::new();
+    for declaration in &program.declarations {
+        if let ast::Declaration::ExternalFunction(ast::ExternalFunction {
+            kind: ast::ExternalFunctionKind::GPU(info),
+            ..
+        }) = declaration
+        {
+            if shader_modules.contains_key(&info.shader_module) {
+                continue;
+            }
+            let path = Path::new(&program.path).join(&info.shader_module);
+            let shader_module = ShaderModule::read(&path).map_err(|error| (path, error))?;
+            shader_modules.insert(info.shader_module.clone(), shader_module);
+        }
+    }
+    Ok(shader_modules)
+}
+
+// Errors are reported against `filename`, which may be empty for programs with no file
+pub fn lower(
+    original: ast::Program,
+    filename: &str,
+) -> Result<frontend::ExplicationDefinition, CompileError> {
+    let shader_modules = read_shaders(&original)
+        .map_err(|(path, error)| frontend::shader_error(&path.to_string_lossy(), &error))?;
+    lower_with_shaders(original, shader_modules, filename)
+}
+
+pub fn lower_with_shaders(
+    original: ast::Program,
+    shader_modules: HashMap<String, ShaderModule>,
+    filename: &str,
+) -> Result<frontend::ExplicationDefinition, CompileError> {
+    let mut context = Context::new(&original, filename)?;
+    check_assumptions(&original, &mut context)?;
+    context.shader_modules = shader_modules;
     let version = ir_version(&original.version);
-    let program = ir_program(&original, &mut context);
+    let program = ir_program(&original, &mut context)?;
     let debug_info = context.drain_into_debug_info();
-    frontend::ExplicationDefinition {
+    Ok(frontend::ExplicationDefinition {
         version,
         debug_info,
         program,
-    }
+    })
 }
@@ -3,6 +3,7 @@ pub mod lowering_pass;
 pub mod parser;
 #[macro_use]
 pub mod ast;
+pub mod printer;
 
 // #[cfg(feature = "assembly")]
 mod context;
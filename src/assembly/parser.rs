@@ -8,9 +8,10 @@ use pest_consume::{match_nodes, Error, Parser};
 #[grammar = "src/assembly/caimanir.pest"]
 struct CaimanAssemblyParser;
 
+use crate::diagnostics::{Position, Range};
+use crate::explication::Hole;
 use crate::{assembly, frontend, ir};
 use assembly::ast;
-use crate::explication::Hole;
 use ast::{
     ExternalFunctionId, FFIType, FuncletId, FunctionClassId, MetaId, NodeId, RemoteNodeId,
     StorageTypeId, TypeId,
@@ -25,7 +26,29 @@ struct UserData {}
 type ParseResult<T> = std::result::Result<T, Error<Rule>>;
 type Node<'i> = pest_consume::Node<'i, Rule, UserData>;
 
-const PHI_QUALIFIER: &str = "_PHI_";
+pub const PHI_QUALIFIER: &str = "_PHI_";
+// the start of the names the printer escapes because the grammar can't spell them
+pub const ESCAPE_PREFIX: &str = "_esc_";
+
+// undoes the printer's escaping, where `_` is written as `__` and other characters the
+//   grammar doesn't allow as `_<hex>_`
+pub fn unescape(s: &str) -> Option<String> {
+    let mut chars = s.strip_prefix(ESCAPE_PREFIX)?.chars();
+    let mut result = String::new();
+    while let Some(c) = chars.next() {
+        if c != '_' {
+            result.push(c);
+            continue;
+        }
+        let hex: String = chars.by_ref().take_while(|&c| c != '_').collect();
+        if hex.is_empty() {
+            result.push('_');
+        } else {
+            result.push(char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?);
+        }
+    }
+    Some(result)
+}
 
 // helper stuff
 
@@ -105,6 +128,9 @@ impl CaimanAssemblyParser {
     fn schedule_yield_sep(_input: Node) -> ParseResult<()> {
         unreachable!()
     }
+    fn dynamic_alloc_sep(_input: Node) -> ParseResult<()> {
+        unreachable!()
+    }
     fn extract_sep(_input: Node) -> ParseResult<()> {
         unreachable!()
     }
@@ -201,6 +227,9 @@ impl CaimanAssemblyParser {
     fn pipeline_sep(_input: Node) -> ParseResult<()> {
         unreachable!()
     }
+    fn pipeline_recursion_sep(_input: Node) -> ParseResult<()> {
+        unreachable!()
+    }
 
     fn EOI(_input: Node) -> ParseResult<()> {
         unreachable!()
@@ -209,7 +238,12 @@ impl CaimanAssemblyParser {
     // real declarations
 
     fn id(input: Node) -> ParseResult<String> {
-        input.as_str().parse::<String>().map_err(|e| input.error(e))
+        let id = input.as_str();
+        if id.starts_with(ESCAPE_PREFIX) {
+            unescape(id).ok_or_else(|| input.error(format!("Invalid escaped name {}", id)))
+        } else {
+            Ok(id.to_string())
+        }
     }
 
     fn n(input: Node) -> ParseResult<usize> {
@@ -765,9 +799,17 @@ impl CaimanAssemblyParser {
     }
 
     fn assign(input: Node) -> ParseResult<ast::NodeId> {
-        Ok(match_nodes!(input.into_children();
-            [name(name)] => NodeId(name)
-        ))
+        let error = input.error(format!(
+            "Names starting with {} are reserved for phi nodes",
+            PHI_QUALIFIER
+        ));
+        let name = match_nodes!(input.into_children();
+            [name(name)] => name
+        );
+        if name.starts_with(PHI_QUALIFIER) {
+            return Err(error);
+        }
+        Ok(NodeId(name))
     }
 
     fn n_elements(input: Node) -> ParseResult<Vec<Hole<usize>>> {
@@ -1224,8 +1266,8 @@ impl CaimanAssemblyParser {
 
     fn spec_mapping(input: Node) -> ParseResult<(Hole<Vec<Hole<ast::RemoteNodeId>>>)> {
         Ok(match_nodes!(input.into_children();
-            [hole] => Hole::Empty,
-            [quotient_hole(operations)..] => Hole::Filled(operations.collect())
+            [quotient_hole(operations)..] => Hole::Filled(operations.collect()),
+            [hole] => Hole::Empty
         ))
     }
 
@@ -1304,6 +1346,44 @@ impl CaimanAssemblyParser {
         ))
     }
 
+    fn size_slot_hole(input: Node) -> ParseResult<Hole<Option<NodeId>>> {
+        Ok(match_nodes!(input.into_children();
+            [name(name)] => Hole::Filled(Some(NodeId(name))),
+            [none] => Hole::Filled(None),
+            [hole] => Hole::Empty
+        ))
+    }
+
+    fn size_slot_elements(input: Node) -> ParseResult<Vec<Hole<Option<NodeId>>>> {
+        Ok(match_nodes!(input.into_children();
+            [size_slot_hole(slots)..] => slots.collect()
+        ))
+    }
+
+    fn size_slot_box(input: Node) -> ParseResult<Hole<Vec<Hole<Option<NodeId>>>>> {
+        Ok(match_nodes!(input.into_children();
+            [size_slot_elements(slots)] => Hole::Filled(slots),
+            [hole] => Hole::Empty
+        ))
+    }
+
+    fn dynamic_alloc_node(input: Node) -> ParseResult<ast::TailEdge> {
+        Ok(match_nodes!(input.into_children();
+            [dynamic_alloc_sep, name_hole(buffer),
+                size_slot_box(dynamic_allocation_size_slots),
+                name_hole(success_funclet_id), name_hole(failure_funclet_id),
+                name_call(arguments), name_hole(continuation_join)] =>
+                ast::TailEdge::DynamicAllocFromBuffer {
+                    buffer: buffer.opt().map(|s| NodeId(s)).into(),
+                    dynamic_allocation_size_slots,
+                    success_funclet_id: success_funclet_id.opt().map(|s| FuncletId(s)).into(),
+                    failure_funclet_id: failure_funclet_id.opt().map(|s| FuncletId(s)).into(),
+                    arguments,
+                    continuation_join: continuation_join.opt().map(|s| NodeId(s)).into()
+                }
+        ))
+    }
+
     fn tail_edge(input: Node) -> ParseResult<ast::TailEdge> {
         Ok(match_nodes!(input.into_children();
             [debug_hole_node(t)] => t,
@@ -1312,6 +1392,7 @@ impl CaimanAssemblyParser {
             [schedule_call_node(t)] => t,
             [schedule_select_node(t)] => t,
             [schedule_yield_node(t)] => t,
+            [dynamic_alloc_node(t)] => t,
         ))
     }
 
@@ -1768,14 +1849,30 @@ impl CaimanAssemblyParser {
         ))
     }
 
+    fn pipeline_recursion(input: Node) -> ParseResult<usize> {
+        Ok(match_nodes!(input.into_children();
+            [pipeline_recursion_sep, n(n)] => n
+        ))
+    }
+
     fn pipeline(input: Node) -> ParseResult<ast::Pipeline> {
         Ok(match_nodes!(input.into_children();
             [pipeline_sep, str(name), name(funclet)] => ast::Pipeline{
-                name, funclet: FuncletId(funclet), effect: None
+                name, funclet: FuncletId(funclet), effect: None, recursion_limit: None
+            },
+            [pipeline_sep, str(name), name(funclet), pipeline_recursion(limit)] =>
+            ast::Pipeline{
+                name, funclet: FuncletId(funclet), effect: None, recursion_limit: Some(limit)
             },
             [pipeline_sep, str(name), name(funclet), pipeline_effect(effect)] =>
             ast::Pipeline{
-                name, funclet: FuncletId(funclet), effect: Some(effect)
+                name, funclet: FuncletId(funclet), effect: Some(effect), recursion_limit: None
+            },
+            [pipeline_sep, str(name), name(funclet), pipeline_effect(effect),
+                pipeline_recursion(limit)] =>
+            ast::Pipeline{
+                name, funclet: FuncletId(funclet), effect: Some(effect),
+                recursion_limit: Some(limit)
             }
         ))
     }
@@ -1785,23 +1882,117 @@ impl CaimanAssemblyParser {
             [version(version), declaration(declarations).., EOI] => ast::Program {
                 path: "".to_string(),
                 version,
-                declarations: declarations.collect()
+                declarations: declarations.collect(),
+                spans: HashMap::new(),
+                external_spans: HashMap::new(),
             }
         ))
     }
 }
 
+fn range_of(pair: &Pair<Rule>) -> Range {
+    let span = pair.as_span();
+    let (start_line, start_column) = span.start_pos().line_col();
+    let (end_line, end_column) = span.end_pos().line_col();
+    Range {
+        start: Position::new(start_line, start_column),
+        end: Position::new(end_line, end_column),
+    }
+}
+
+fn name_of(pair: Pair<Rule>) -> Option<String> {
+    pair.into_inner()
+        .find(|p| p.as_rule() == Rule::name)
+        .map(|p| p.as_str().trim_start_matches('%').to_string())
+}
+
+// records where each funclet, each named node, and each external function was written
+// this walks the raw parse tree separately so the parsing functions above stay span-free
+fn collect_spans(
+    pair: Pair<Rule>,
+    spans: &mut HashMap<FuncletId, ast::FuncletSpans>,
+    external_spans: &mut HashMap<String, Range>,
+) {
+    match pair.as_rule() {
+        Rule::external_function => {
+            let range = range_of(&pair);
+            if let Some(name) = name_of(pair) {
+                external_spans.insert(name, range);
+            }
+        }
+        Rule::value_funclet
+        | Rule::timeline_funclet
+        | Rule::spatial_funclet
+        | Rule::schedule_funclet => {
+            let funclet_range = range_of(&pair);
+            let mut inner = pair.into_inner();
+            let name = match inner.next().and_then(name_of) {
+                Some(name) => name,
+                None => return,
+            };
+            let mut funclet_spans = ast::FuncletSpans {
+                funclet: Some(funclet_range),
+                nodes: HashMap::new(),
+            };
+            for command in inner {
+                let assign = command
+                    .clone()
+                    .into_inner()
+                    .flatten()
+                    .find(|p| p.as_rule() == Rule::assign);
+                if let Some(node_name) = assign.and_then(name_of) {
+                    funclet_spans
+                        .nodes
+                        .insert(NodeId(node_name), range_of(&command));
+                }
+            }
+            spans.insert(FuncletId(name), funclet_spans);
+        }
+        _ => {
+            for child in pair.into_inner() {
+                collect_spans(child, spans, external_spans);
+            }
+        }
+    }
+}
+
 pub fn parse(path: &str, code: &str) -> ParseResult<ast::Program> {
     // necessary to have an empty user data for checking stuff
     let user_data = UserData {};
     // CaimanAssemblyParser::parse(Rule::program, code);
     let parsed = CaimanAssemblyParser::parse_with_userdata(Rule::program, code, user_data)?;
-    let mut result = CaimanAssemblyParser::program(parsed.single()?);
+    let root = parsed.single()?;
+    let mut spans = HashMap::new();
+    let mut external_spans = HashMap::new();
+    collect_spans(root.as_pair().clone(), &mut spans, &mut external_spans);
+    let mut result = CaimanAssemblyParser::program(root);
     match &mut result {
         Ok(ref mut program) => {
             program.path = path.to_string();
+            program.spans = spans;
+            program.external_spans = external_spans;
         }
         _ => {}
     };
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_leading_underscores() {
+        let program = parse("", "version 0.0.2\n\nffi i64;\nnative_value %_0 : i64;\n").unwrap();
+        let names: Vec<_> = program
+            .declarations
+            .iter()
+            .filter_map(|declaration| match declaration {
+                ast::Declaration::TypeDecl(ast::TypeDecl::Local(local)) => Some(&local.name),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(names, ["_0"]);
+        assert!(parse("", "version 0.0.2\n\nffi i64;\nnative_value %0_ : i64;\n").is_err());
+    }
+}
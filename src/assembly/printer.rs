@@ -0,0 +1,1871 @@
+// Prints assembly programs back out as text that the parser accepts
+//
+// The parser adds a phi node for every funclet argument, so phi nodes are never printed;
+//   references to a phi are written as the argument it reads (or `phi-$meta.%arg` remotely)
+// Names the grammar can't spell, such as the `x.1` or `rec::field` produced by the
+//   high-level frontend, are escaped to valid names consistently across the program, in a
+//   way that never gives two names the same spelling
+
+use crate::assembly::ast;
+use crate::assembly::parser::{ESCAPE_PREFIX, PHI_QUALIFIER};
+use crate::debug_info::DebugInfo;
+use crate::explication::Hole;
+use crate::ir;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+const INDENT: &str = "    ";
+
+// something the grammar can't express, such as a hole in a constant
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrintError {
+    pub message: String,
+}
+
+impl std::fmt::Display for PrintError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+type Printed<T> = Result<T, PrintError>;
+
+fn print_error<T>(message: String) -> Printed<T> {
+    Err(PrintError { message })
+}
+
+fn unrepresentable<T: std::fmt::Debug, U>(what: &T) -> Printed<U> {
+    print_error(format!("{:?} cannot be written as assembly", what))
+}
+
+fn filled<'a, T>(hole: &'a Hole<T>, what: &str) -> Printed<&'a T> {
+    match hole {
+        Hole::Filled(v) => Ok(v),
+        Hole::Empty => print_error(format!("{} cannot be a hole in assembly", what)),
+    }
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_digit() => s.chars().all(|c| c.is_ascii_digit()),
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+// a name the grammar can spell is printed as is, unless it could be mistaken for an escaped
+//   name; the rest are prefixed with `_esc_`, with `_` written as `__` and every character
+//   the grammar doesn't allow as `_<hex>_`, which the parser undoes
+fn ident(s: &str) -> String {
+    if is_identifier(s) && !s.starts_with(ESCAPE_PREFIX) {
+        return s.to_string();
+    }
+    let mut result = ESCAPE_PREFIX.to_string();
+    for c in s.chars() {
+        if c == '_' {
+            result.push_str("__");
+        } else if c.is_ascii_alphanumeric() {
+            result.push(c);
+        } else {
+            write!(result, "_{:x}_", c as u32).unwrap();
+        }
+    }
+    result
+}
+
+fn name(s: &str) -> String {
+    format!("%{}", ident(s))
+}
+
+fn string(s: &str) -> Printed<String> {
+    if s.contains('\n') || (s.contains('"') && s.contains('\'')) {
+        unrepresentable(&s)
+    } else if s.contains('"') {
+        Ok(format!("'{}'", s))
+    } else {
+        Ok(format!("\"{}\"", s))
+    }
+}
+
+fn place(place: &ir::Place) -> &'static str {
+    match place {
+        ir::Place::Local => "local",
+        ir::Place::Cpu => "cpu",
+        ir::Place::Gpu => "gpu",
+    }
+}
+
+fn place_hole(hole: &Hole<ir::Place>) -> &'static str {
+    hole.as_ref().opt().map_or("?", place)
+}
+
+fn buffer_flags(flags: &ir::BufferFlags) -> String {
+    let names = [
+        (flags.map_read, "map_read"),
+        (flags.map_write, "map_write"),
+        (flags.copy_src, "copy_src"),
+        (flags.copy_dst, "copy_dst"),
+        (flags.storage, "storage"),
+        (flags.uniform, "uniform"),
+    ];
+    let set: Vec<_> = names
+        .iter()
+        .filter(|(set, _)| *set)
+        .map(|(_, n)| *n)
+        .collect();
+    format!("[{}]", set.join(", "))
+}
+
+fn flow(flow: &Hole<ir::Flow>) -> &'static str {
+    match flow {
+        Hole::Empty => "?",
+        Hole::Filled(ir::Flow::Dead) => "dead",
+        Hole::Filled(ir::Flow::Usable) => "usable",
+        Hole::Filled(ir::Flow::Saved) => "saved",
+        Hole::Filled(ir::Flow::Need) => "need",
+    }
+}
+
+pub fn ffi_type(typ: &ast::FFIType) -> Printed<String> {
+    use ast::FFIType;
+    let parameterized = |n: &str, t: &FFIType| Ok(format!("{}<{}>", n, ffi_type(t)?));
+    Ok(match typ {
+        FFIType::F32 => "f32".to_string(),
+        FFIType::F64 => "f64".to_string(),
+        FFIType::U8 => "u8".to_string(),
+        FFIType::U16 => "u16".to_string(),
+        FFIType::U32 => "u32".to_string(),
+        FFIType::U64 => "u64".to_string(),
+        FFIType::USize => "usize".to_string(),
+        FFIType::I8 => "i8".to_string(),
+        FFIType::I16 => "i16".to_string(),
+        FFIType::I32 => "i32".to_string(),
+        FFIType::I64 => "i64".to_string(),
+        FFIType::GpuBufferAllocator => "gpu_buffer_allocator".to_string(),
+        FFIType::CpuBufferAllocator => "cpu_buffer_allocator".to_string(),
+        FFIType::Array {
+            element_type,
+            length,
+        } => format!("array<{}, {}>", ffi_type(element_type)?, length),
+        FFIType::ErasedLengthArray(t) => parameterized("erased_length_array", t)?,
+        FFIType::ConstRef(t) => parameterized("const_ref", t)?,
+        FFIType::MutRef(t) => parameterized("mut_ref", t)?,
+        FFIType::ConstSlice(t) => parameterized("const_slice", t)?,
+        FFIType::MutSlice(t) => parameterized("mut_slice", t)?,
+        FFIType::GpuBufferRef(t) => parameterized("gpu_buffer_ref", t)?,
+        FFIType::GpuBufferSlice(t) => parameterized("gpu_buffer_slice", t)?,
+        FFIType::CpuBufferRef(t) => parameterized("cpu_buffer_ref", t)?,
+        FFIType::Tuple(fields) if !fields.is_empty() => format!(
+            "tuple<{}>",
+            fields
+                .iter()
+                .map(ffi_type)
+                .collect::<Printed<Vec<_>>>()?
+                .join(", ")
+        ),
+        FFIType::Tuple(_) | FFIType::Struct { .. } | FFIType::GpuFence | FFIType::Unknown => {
+            return unrepresentable(typ)
+        }
+    })
+}
+
+fn ffi_type_hole(hole: &Hole<ast::FFIType>) -> Printed<String> {
+    hole.as_ref().opt().map_or(Ok("?".to_string()), ffi_type)
+}
+
+fn typ(t: &ast::TypeId) -> String {
+    name(&t.0)
+}
+
+fn impl_box(binding: &ast::FunctionClassBinding) -> String {
+    format!(
+        "[impl {}@{}]",
+        if binding.default { "default " } else { "" },
+        ident(&binding.function_class.0)
+    )
+}
+
+struct Printer<'a> {
+    out: String,
+    // the argument each named phi node reads, by funclet
+    phis: HashMap<&'a ast::FuncletId, HashMap<&'a ast::NodeId, &'a ast::NodeId>>,
+    // the funclet being printed
+    funclet: Option<&'a ast::Funclet>,
+    // names given to unnamed nodes that the grammar requires to be named
+    fresh_names: HashMap<usize, String>,
+}
+
+impl<'a> Printer<'a> {
+    fn new(program: &'a ast::Program) -> Self {
+        let mut phis = HashMap::new();
+        for declaration in &program.declarations {
+            if let ast::Declaration::Funclet(funclet) = declaration {
+                let mut funclet_phis = HashMap::new();
+                for command in &funclet.commands {
+                    if let Hole::Filled(ast::Command::Node(ast::NamedNode {
+                        name: Some(phi),
+                        node:
+                            ast::Node::Phi {
+                                index: Hole::Filled(index),
+                            },
+                    })) = command
+                    {
+                        let arg = funclet
+                            .header
+                            .args
+                            .get(*index)
+                            .and_then(|a| a.name.as_ref());
+                        if let Some(arg) = arg {
+                            funclet_phis.insert(phi, arg);
+                        }
+                    }
+                }
+                phis.insert(&funclet.header.name, funclet_phis);
+            }
+        }
+        Printer {
+            out: String::new(),
+            phis,
+            funclet: None,
+            fresh_names: HashMap::new(),
+        }
+    }
+
+    fn line(&mut self, text: &str) {
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    fn node_ref(&self, node: &ast::NodeId) -> String {
+        let arg = self
+            .funclet
+            .and_then(|f| self.phis.get(&f.header.name))
+            .and_then(|phis| phis.get(node));
+        name(&arg.unwrap_or(&node).0)
+    }
+
+    fn node_hole(&self, hole: &Hole<ast::NodeId>) -> String {
+        hole.as_ref()
+            .opt()
+            .map_or("?".to_string(), |n| self.node_ref(n))
+    }
+
+    fn node_list(&self, nodes: &[Hole<ast::NodeId>]) -> String {
+        nodes
+            .iter()
+            .map(|n| self.node_hole(n))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    fn node_box(&self, hole: &Hole<Vec<Hole<ast::NodeId>>>) -> String {
+        match hole {
+            Hole::Empty => "?".to_string(),
+            Hole::Filled(nodes) => format!("[{}]", self.node_list(nodes)),
+        }
+    }
+
+    // a box that may be written as a lone name
+    fn node_box_single(&self, hole: &Hole<Vec<Hole<ast::NodeId>>>) -> String {
+        match hole {
+            Hole::Filled(nodes) if nodes.len() == 1 && nodes[0].is_filled() => {
+                self.node_hole(&nodes[0])
+            }
+            _ => self.node_box(hole),
+        }
+    }
+
+    // arguments directly follow what they're applied to, so a hole is spaced apart
+    fn node_call(&self, hole: &Hole<Vec<Hole<ast::NodeId>>>) -> String {
+        match hole {
+            Hole::Empty => " ?".to_string(),
+            Hole::Filled(nodes) => format!("({})", self.node_list(nodes)),
+        }
+    }
+
+    fn meta_funclet(&self, meta: &ast::MetaId) -> Option<&'a ast::FuncletId> {
+        let binding = match &self.funclet?.header.binding {
+            ast::FuncletBinding::ScheduleBinding(binding) => binding,
+            _ => return None,
+        };
+        let map = &binding.meta_map;
+        [&map.value, &map.timeline, &map.spatial]
+            .iter()
+            .find(|(m, _)| m == meta)
+            .map(|(_, f)| f)
+    }
+
+    fn quotient(&self, quot: &ast::RemoteNodeId) -> String {
+        let meta = format!("${}", ident(&quot.funclet.0));
+        match &quot.node {
+            None => meta,
+            Some(Hole::Empty) => format!("{}.?", meta),
+            Some(Hole::Filled(node)) => {
+                let arg = self
+                    .meta_funclet(&quot.funclet)
+                    .and_then(|f| self.phis.get(f))
+                    .and_then(|phis| phis.get(node))
+                    .map(|arg| arg.0.as_str())
+                    .or_else(|| node.0.strip_prefix(PHI_QUALIFIER));
+                match arg {
+                    Some(arg) => format!("phi-{}.{}", meta, name(arg)),
+                    None => format!("{}.{}", meta, name(&node.0)),
+                }
+            }
+        }
+    }
+
+    fn quotient_hole(&self, hole: &Hole<ast::RemoteNodeId>) -> String {
+        hole.as_ref()
+            .opt()
+            .map_or("?".to_string(), |q| self.quotient(q))
+    }
+
+    fn spec_mapping(&self, hole: &Hole<Vec<Hole<ast::RemoteNodeId>>>) -> String {
+        match hole {
+            Hole::Empty => " ?".to_string(),
+            Hole::Filled(quots) => format!(
+                "[{}]",
+                quots
+                    .iter()
+                    .map(|q| self.quotient_hole(q))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+
+    fn tag(&self, tag: &ast::Tag) -> Printed<String> {
+        Ok(format!(
+            "{}-{}",
+            self.quotient(filled(&tag.quot, "The quotient of a tag")?),
+            flow(&tag.flow)
+        ))
+    }
+
+    fn schedule_typ(&self, arg: &ast::FuncletArgument) -> Printed<String> {
+        let mut result = String::new();
+        for tag in &arg.tags {
+            write!(result, "{} ", self.tag(tag)?).unwrap();
+        }
+        result.push_str(&typ(&arg.typ));
+        Ok(result)
+    }
+
+    fn funclet_arg(&self, arg: &ast::FuncletArgument, schedule: bool) -> Printed<String> {
+        let typ = if schedule {
+            self.schedule_typ(arg)?
+        } else {
+            typ(&arg.typ)
+        };
+        Ok(match &arg.name {
+            None => typ,
+            Some(n) => format!("{} : {}", name(&n.0), typ),
+        })
+    }
+
+    fn funclet_args(&self, args: &[ast::FuncletArgument], schedule: bool) -> Printed<String> {
+        Ok(args
+            .iter()
+            .map(|a| self.funclet_arg(a, schedule))
+            .collect::<Printed<Vec<_>>>()?
+            .join(", "))
+    }
+
+    fn funclet_return(&self, ret: &[ast::FuncletArgument], schedule: bool) -> Printed<String> {
+        if ret.len() == 1 && ret[0].name.is_none() {
+            self.funclet_arg(&ret[0], schedule)
+        } else {
+            Ok(format!("[{}]", self.funclet_args(ret, schedule)?))
+        }
+    }
+
+    fn assign(&self, index: usize, named: &ast::NamedNode) -> String {
+        match &named.name {
+            Some(n) => format!("{} = ", name(&n.0)),
+            None => format!("{} = ", name(&self.fresh_names[&index])),
+        }
+    }
+
+    // returns `None` for the phi nodes the parser adds back
+    fn node(&self, index: usize, named: &ast::NamedNode) -> Printed<Option<String>> {
+        use ast::Node;
+        let assign = || self.assign(index, named);
+        let op = |h: &Hole<ast::NodeId>| self.node_hole(h);
+        let quot = |h: &Hole<ast::RemoteNodeId>| self.quotient_hole(h);
+        if let (Some(n), false) = (&named.name, matches!(named.node, Node::Phi { .. })) {
+            if n.0.starts_with(PHI_QUALIFIER) {
+                return print_error(format!(
+                    "{} is reserved for phi nodes and cannot name {}",
+                    n.0, named.node
+                ));
+            }
+        }
+        Ok(Some(match &named.node {
+            Node::Phi { .. } => return Ok(None),
+            Node::None {} => return unrepresentable(&named.node),
+            Node::ExtractResult { node_id, index } => format!(
+                "{}extract {} {}",
+                assign(),
+                op(node_id),
+                filled(index, "An extract index")?
+            ),
+            Node::Constant { value, type_id } => format!(
+                "{}constant {} {}",
+                assign(),
+                typ(filled(type_id, "The type of a constant")?),
+                filled(value, "The value of a constant")?
+            ),
+            Node::CallFunctionClass {
+                function_id,
+                arguments,
+            } => format!(
+                "{}call @{}{}",
+                assign(),
+                ident(&filled(function_id, "A called function class")?.0),
+                self.node_call(arguments)
+            ),
+            Node::Select {
+                condition,
+                true_case,
+                false_case,
+            } => format!(
+                "{}select {} {} {}",
+                assign(),
+                op(condition),
+                op(true_case),
+                op(false_case)
+            ),
+            Node::AllocTemporary {
+                place,
+                storage_type,
+                buffer_flags: flags,
+            } => format!(
+                "{}alloc-temporary {} {} {}",
+                assign(),
+                place_hole(place),
+                buffer_flags(filled(flags, "Buffer flags")?),
+                ffi_type_hole(storage_type)?
+            ),
+            Node::Drop { node } => format!("drop {}", op(node)),
+            Node::StaticSubAlloc {
+                node,
+                place,
+                storage_type,
+            } => format!(
+                "{}static-sub-alloc {} {} {}",
+                assign(),
+                place_hole(place),
+                ffi_type_hole(storage_type)?,
+                op(node)
+            ),
+            Node::StaticSplit {
+                spatial_operation,
+                node,
+                sizes,
+                place,
+            } => format!(
+                "{}static-split {} {} [{}] {}",
+                assign(),
+                place_hole(place),
+                op(node),
+                filled(sizes, "Split sizes")?
+                    .iter()
+                    .map(|s| filled(s, "A split size").map(|s| s.to_string()))
+                    .collect::<Printed<Vec<_>>>()?
+                    .join(", "),
+                quot(spatial_operation)
+            ),
+            Node::StaticMerge {
+                spatial_operation,
+                nodes,
+                place,
+            } => format!(
+                "{}static-merge {} {} {}",
+                assign(),
+                place_hole(place),
+                quot(spatial_operation),
+                self.node_box(nodes)
+            ),
+            Node::ReadRef {
+                storage_type,
+                source,
+            } => format!(
+                "{}read-ref {} {}",
+                assign(),
+                ffi_type_hole(storage_type)?,
+                op(source)
+            ),
+            Node::BorrowRef {
+                storage_type,
+                source,
+            } => format!(
+                "{}borrow-ref {} {}",
+                assign(),
+                ffi_type_hole(storage_type)?,
+                op(source)
+            ),
+            Node::WriteRef {
+                storage_type,
+                destination,
+                source,
+            } => format!(
+                "write-ref {} {} -> {}",
+                ffi_type_hole(storage_type)?,
+                op(source),
+                op(destination)
+            ),
+            Node::LocalDoBuiltin {
+                operation,
+                inputs,
+                outputs,
+            } => format!(
+                "local-do-builtin {}{} -> {}",
+                quot(operation),
+                self.node_call(inputs),
+                self.node_box_single(outputs)
+            ),
+            Node::LocalDoExternal {
+                operation,
+                external_function_id,
+                inputs,
+                outputs,
+            } => format!(
+                "local-do-external {} {}{} -> {}",
+                external_function_id
+                    .as_ref()
+                    .opt()
+                    .map_or("?".to_string(), |f| name(&f.0)),
+                quot(operation),
+                self.node_call(inputs),
+                self.node_box_single(outputs)
+            ),
+            Node::LocalCopy { input, output } => {
+                format!("local-copy {} -> {}", op(input), op(output))
+            }
+            Node::BeginEncoding {
+                place,
+                event,
+                encoded,
+                fences,
+            } => format!(
+                "{}begin-encoding {} {} {} {}",
+                assign(),
+                place_hole(place),
+                quot(event),
+                self.node_box(encoded),
+                self.node_box_single(fences)
+            ),
+            Node::EncodeDoExternal {
+                encoder,
+                operation,
+                external_function_id,
+                inputs,
+                outputs,
+            } => format!(
+                "encode-do {} {} {}{} -> {}",
+                op(encoder),
+                external_function_id
+                    .as_ref()
+                    .opt()
+                    .map_or("?".to_string(), |f| name(&f.0)),
+                quot(operation),
+                self.node_call(inputs),
+                self.node_box_single(outputs)
+            ),
+            Node::EncodeCopy {
+                encoder,
+                input,
+                output,
+            } => format!(
+                "encode-copy {} {} -> {}",
+                op(encoder),
+                op(input),
+                op(output)
+            ),
+            Node::Submit { encoder, event } => {
+                format!("{}submit {} {}", assign(), op(encoder), quot(event))
+            }
+            Node::SyncFence { fence, event } => {
+                format!("sync-fence {} {}", op(fence), quot(event))
+            }
+            Node::InlineJoin {
+                funclet,
+                captures,
+                continuation,
+            } => format!(
+                "{}inline-join {} {} {}",
+                assign(),
+                funclet
+                    .as_ref()
+                    .opt()
+                    .map_or("?".to_string(), |f| name(&f.0)),
+                self.node_box(captures),
+                op(continuation)
+            ),
+            Node::SerializedJoin {
+                funclet,
+                captures,
+                continuation,
+            } => format!(
+                "{}serialized-join {} {} {}",
+                assign(),
+                funclet
+                    .as_ref()
+                    .opt()
+                    .map_or("?".to_string(), |f| name(&f.0)),
+                self.node_box(captures),
+                op(continuation)
+            ),
+            Node::DefaultJoin {} => format!("{}default-join", assign()),
+            Node::PromiseCaptures {
+                count,
+                continuation,
+            } => format!(
+                "{}promise-captures {} {}",
+                assign(),
+                filled(count, "A capture count")?,
+                op(continuation)
+            ),
+            Node::FulfillCaptures {
+                continuation,
+                haves,
+                needs,
+            } => format!(
+                "{}fulfill-captures {} {} {}",
+                assign(),
+                op(continuation),
+                self.node_box(haves),
+                self.node_box(needs)
+            ),
+            Node::EncodingEvent {
+                local_past,
+                remote_local_pasts,
+            } => format!(
+                "{}encoding-event {} {}",
+                assign(),
+                op(local_past),
+                self.node_box(remote_local_pasts)
+            ),
+            Node::SubmissionEvent { local_past } => {
+                format!("{}submission-event {}", assign(), op(local_past))
+            }
+            Node::SynchronizationEvent {
+                local_past,
+                remote_local_past,
+            } => format!(
+                "{}synchronization-event {} {}",
+                assign(),
+                op(local_past),
+                op(remote_local_past)
+            ),
+            Node::SeparatedBufferSpaces { count, space } => format!(
+                "{}separated-buffer-space {} {}",
+                assign(),
+                filled(count, "A buffer space count")?,
+                op(space)
+            ),
+        }))
+    }
+
+    fn tail_edge(&self, tail: &ast::TailEdge) -> String {
+        use ast::TailEdge;
+        let op = |h: &Hole<ast::NodeId>| self.node_hole(h);
+        match tail {
+            TailEdge::DebugHole { inputs } => format!(
+                "debug-hole [{}]",
+                inputs
+                    .iter()
+                    .map(|n| self.node_ref(n))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            TailEdge::Return { return_values } => {
+                format!("return {}", self.node_box_single(return_values))
+            }
+            TailEdge::Jump { join, arguments } => {
+                format!("jump {} {}", op(join), self.node_box_single(arguments))
+            }
+            TailEdge::ScheduleCall {
+                operations,
+                callee_funclet_id,
+                callee_arguments,
+                continuation_join,
+            } => format!(
+                "schedule-call {}{}{} {}",
+                callee_funclet_id
+                    .as_ref()
+                    .opt()
+                    .map_or("?".to_string(), |f| name(&f.0)),
+                self.spec_mapping(operations),
+                self.node_call(callee_arguments),
+                op(continuation_join)
+            ),
+            TailEdge::ScheduleSelect {
+                operations,
+                condition,
+                callee_funclet_ids,
+                callee_arguments,
+                continuation_join,
+            } => format!(
+                "schedule-select {} {} {}{} {}",
+                op(condition),
+                match callee_funclet_ids {
+                    Hole::Empty => "?".to_string(),
+                    Hole::Filled(ids) => format!(
+                        "[{}]",
+                        ids.iter()
+                            .map(|f| f.as_ref().opt().map_or("?".to_string(), |f| name(&f.0)))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ),
+                },
+                self.spec_mapping(operations).trim_start(),
+                self.node_call(callee_arguments),
+                op(continuation_join)
+            ),
+            TailEdge::ScheduleCallYield {
+                operations,
+                external_function_id,
+                yielded_nodes,
+                continuation_join,
+            } => format!(
+                "schedule-call-yield {}{}{} {}",
+                external_function_id
+                    .as_ref()
+                    .opt()
+                    .map_or("?".to_string(), |f| name(&f.0)),
+                self.spec_mapping(operations),
+                self.node_call(yielded_nodes),
+                op(continuation_join)
+            ),
+            TailEdge::DynamicAllocFromBuffer {
+                buffer,
+                dynamic_allocation_size_slots,
+                success_funclet_id,
+                failure_funclet_id,
+                arguments,
+                continuation_join,
+            } => {
+                let funclet = |f: &Hole<ast::FuncletId>| {
+                    f.as_ref().opt().map_or("?".to_string(), |f| name(&f.0))
+                };
+                format!(
+                    "dynamic-alloc {} {} {} {}{} {}",
+                    op(buffer),
+                    match dynamic_allocation_size_slots {
+                        Hole::Empty => "?".to_string(),
+                        Hole::Filled(slots) => format!(
+                            "[{}]",
+                            slots
+                                .iter()
+                                .map(|slot| match slot {
+                                    Hole::Empty => "?".to_string(),
+                                    Hole::Filled(None) => "none".to_string(),
+                                    Hole::Filled(Some(n)) => self.node_ref(n),
+                                })
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        ),
+                    },
+                    funclet(success_funclet_id),
+                    funclet(failure_funclet_id),
+                    self.node_call(arguments),
+                    op(continuation_join)
+                )
+            }
+        }
+    }
+
+    // names the nodes the grammar requires to be named, avoiding every name in the funclet
+    fn name_unnamed_nodes(&mut self, funclet: &ast::Funclet) {
+        self.fresh_names.clear();
+        let mut used: HashSet<String> = funclet
+            .header
+            .args
+            .iter()
+            .chain(funclet.header.ret.iter())
+            .filter_map(|a| a.name.as_ref().map(|n| ident(&n.0)))
+            .collect();
+        for command in &funclet.commands {
+            if let Hole::Filled(ast::Command::Node(ast::NamedNode { name: Some(n), .. })) = command
+            {
+                used.insert(ident(&n.0));
+            }
+        }
+        let mut next = 0;
+        for (index, command) in funclet.commands.iter().enumerate() {
+            if let Hole::Filled(ast::Command::Node(ast::NamedNode { name: None, node })) = command {
+                if let ast::Node::Phi { .. } = node {
+                    continue;
+                }
+                let fresh = loop {
+                    let candidate = format!("_{}", next);
+                    next += 1;
+                    if used.insert(candidate.clone()) {
+                        break candidate;
+                    }
+                };
+                self.fresh_names.insert(index, fresh);
+            }
+        }
+    }
+
+    fn funclet(&mut self, funclet: &'a ast::Funclet) -> Printed<()> {
+        self.funclet = Some(funclet);
+        self.name_unnamed_nodes(funclet);
+        let header = &funclet.header;
+        let schedule = funclet.kind == ir::FuncletKind::ScheduleExplicit;
+        if schedule {
+            let binding = match &header.binding {
+                ast::FuncletBinding::ScheduleBinding(binding) => binding,
+                _ => return unrepresentable(header),
+            };
+            let map = &binding.meta_map;
+            let meta = |m: &ast::MetaId| format!("${}", ident(&m.0));
+            let heading = format!(
+                "schedule[value {} = {}, timeline {} = {}, spatial {} = {}]",
+                meta(&map.value.0),
+                name(&map.value.1 .0),
+                meta(&map.timeline.0),
+                name(&map.timeline.1 .0),
+                meta(&map.spatial.0),
+                name(&map.spatial.1 .0),
+            );
+            self.line(&heading);
+            let signature = format!(
+                "{}<{}, {}>({}) -> {}",
+                name(&header.name.0),
+                self.tag(&binding.implicit_tags.0)?,
+                self.tag(&binding.implicit_tags.1)?,
+                self.funclet_args(&header.args, true)?,
+                self.funclet_return(&header.ret, true)?
+            );
+            self.line(&signature);
+            self.line("{");
+        } else {
+            let kind = match funclet.kind {
+                ir::FuncletKind::Value => "value",
+                ir::FuncletKind::Timeline => "timeline",
+                ir::FuncletKind::Spatial => "spatial",
+                _ => return unrepresentable(&funclet.kind),
+            };
+            let binding = match &header.binding {
+                ast::FuncletBinding::SpecBinding(binding) => impl_box(binding),
+                _ => String::new(),
+            };
+            let signature = format!(
+                "{}{} {}({}) -> {} {{",
+                kind,
+                binding,
+                name(&header.name.0),
+                self.funclet_args(&header.args, false)?,
+                self.funclet_return(&header.ret, false)?
+            );
+            self.line(&signature);
+        }
+        for (index, command) in funclet.commands.iter().enumerate() {
+            let text = match command {
+                Hole::Empty => Some("???".to_string()),
+                Hole::Filled(ast::Command::Node(node)) => self.node(index, node)?,
+                Hole::Filled(ast::Command::TailEdge(tail)) => Some(self.tail_edge(tail)),
+            };
+            if let Some(text) = text {
+                self.line(&format!("{}{};", INDENT, text));
+            }
+        }
+        self.line("}");
+        self.funclet = None;
+        Ok(())
+    }
+
+    fn external_function(&mut self, external: &ast::ExternalFunction) -> Printed<()> {
+        let location = match &external.kind {
+            ast::ExternalFunctionKind::CPUPure => "external-cpu-pure",
+            ast::ExternalFunctionKind::CPUEffect => "external-cpu",
+            ast::ExternalFunctionKind::GPU(_) => "external-gpu",
+        };
+        let args = |args: &[ast::ExternalArgument]| {
+            Ok(args
+                .iter()
+                .map(|a| match &a.name {
+                    None => ffi_type(&a.ffi_type),
+                    Some(n) => Ok(format!("{} : {}", name(&n.0), ffi_type(&a.ffi_type)?)),
+                })
+                .collect::<Printed<Vec<_>>>()?
+                .join(", "))
+        };
+        let signature = format!(
+            "{}{} {}({}) -> [{}]",
+            location,
+            impl_box(&external.value_function_binding),
+            name(&external.name),
+            args(&external.input_args)?,
+            args(&external.output_types)?
+        );
+        match &external.kind {
+            ast::ExternalFunctionKind::GPU(info) => {
+                self.line(&signature);
+                self.line("{");
+                self.line(&format!(
+                    "{}path : {},",
+                    INDENT,
+                    string(&info.shader_module)?
+                ));
+                self.line(&format!(
+                    "{}entry : {},",
+                    INDENT,
+                    string(&info.entry_point)?
+                ));
+                self.line(&format!(
+                    "{}dimensionality : {},",
+                    INDENT, info.dimensionality
+                ));
+                for resource in &info.resource_bindings {
+                    let mut fields = vec![
+                        format!("group : {}", resource.group),
+                        format!("binding : {}", resource.binding),
+                    ];
+                    if let Some(input) = &resource.input {
+                        fields.push(format!("input : {}", name(&input.0)));
+                    }
+                    if let Some(output) = &resource.output {
+                        fields.push(format!("output : {}", name(&output.0)));
+                    }
+                    self.line(&format!("{}resource {{ {} }},", INDENT, fields.join(", ")));
+                }
+                self.line("}");
+            }
+            _ => self.line(&format!("{};", signature)),
+        }
+        Ok(())
+    }
+
+    fn type_decl(&mut self, decl: &ast::TypeDecl) -> Printed<()> {
+        let text = match decl {
+            ast::TypeDecl::FFI(t) => format!("ffi {};", ffi_type(t)?),
+            ast::TypeDecl::Local(local) => {
+                let n = name(&local.name);
+                match &local.data {
+                    ast::LocalTypeInfo::NativeValue { storage_type } => {
+                        format!("native_value {} : {};", n, ffi_type(storage_type)?)
+                    }
+                    ast::LocalTypeInfo::Ref {
+                        storage_type,
+                        storage_place,
+                        buffer_flags: flags,
+                    } => format!(
+                        "ref {} : {}-{}<flags={}>;",
+                        n,
+                        ffi_type(storage_type)?,
+                        place(storage_place),
+                        buffer_flags(flags)
+                    ),
+                    ast::LocalTypeInfo::Fence { queue_place } => {
+                        format!("fence {} : {};", n, place(queue_place))
+                    }
+                    ast::LocalTypeInfo::Buffer {
+                        storage_place,
+                        static_layout_opt,
+                        flags,
+                    } => {
+                        let layout = match static_layout_opt {
+                            Some(layout) => layout,
+                            None => return unrepresentable(local),
+                        };
+                        format!(
+                            "buffer {} : {}<flags={}, alignment_bits={}, byte_size={}>;",
+                            n,
+                            place(storage_place),
+                            buffer_flags(flags),
+                            layout.alignment_bits,
+                            layout.byte_size
+                        )
+                    }
+                    ast::LocalTypeInfo::Encoder { queue_place } => {
+                        format!("encoder {} : {};", n, place(queue_place))
+                    }
+                    ast::LocalTypeInfo::Event => format!("event {};", n),
+                    ast::LocalTypeInfo::BufferSpace => format!("buffer_space {};", n),
+                }
+            }
+        };
+        self.line(&text);
+        Ok(())
+    }
+
+    fn declaration(&mut self, declaration: &'a ast::Declaration) -> Printed<()> {
+        match declaration {
+            ast::Declaration::TypeDecl(decl) => self.type_decl(decl)?,
+            ast::Declaration::ExternalFunction(external) => self.external_function(external)?,
+            ast::Declaration::FunctionClass(class) => {
+                let types =
+                    |types: &[ast::TypeId]| types.iter().map(typ).collect::<Vec<_>>().join(", ");
+                let ret = if class.output_types.len() == 1 {
+                    types(&class.output_types)
+                } else {
+                    format!("[{}]", types(&class.output_types))
+                };
+                let text = format!(
+                    "function @{}({}) -> {};",
+                    ident(&class.name.0),
+                    types(&class.input_types),
+                    ret
+                );
+                self.line(&text);
+            }
+            ast::Declaration::Funclet(funclet) => self.funclet(funclet)?,
+            ast::Declaration::Effect(effect) => {
+                let text = match &effect.effect {
+                    ast::Effect::Unrestricted => format!("effect {};", name(&effect.name.0)),
+                    ast::Effect::FullyConnected {
+                        effectful_function_ids,
+                    } => format!(
+                        "effect<{}> {};",
+                        effectful_function_ids
+                            .iter()
+                            .map(|f| name(&f.0))
+                            .collect::<Vec<_>>()
+                            .join(", "),
+                        name(&effect.name.0)
+                    ),
+                };
+                self.line(&text);
+            }
+            ast::Declaration::Pipeline(pipeline) => {
+                let effect = pipeline
+                    .effect
+                    .as_ref()
+                    .map_or(String::new(), |e| format!(", effect {}", name(&e.0)));
+                let recursion = pipeline
+                    .recursion_limit
+                    .map_or(String::new(), |limit| format!(", recursion {}", limit));
+                let text = format!(
+                    "pipeline {} = {}{}{};",
+                    string(&pipeline.name)?,
+                    name(&pipeline.funclet.0),
+                    effect,
+                    recursion
+                );
+                self.line(&text);
+            }
+        }
+        Ok(())
+    }
+}
+
+// Writes `program` as assembly text
+// Fails on anything the grammar can't express, such as holes in constants
+pub fn print_program(program: &ast::Program) -> Result<String, PrintError> {
+    let mut printer = Printer::new(program);
+    let version = &program.version;
+    printer.line(&format!(
+        "version {}.{}.{}",
+        version.major, version.minor, version.detailed
+    ));
+    let mut previous_funclet = true;
+    for declaration in &program.declarations {
+        // blank lines around funclets and externals, and between groups of other declarations
+        let block = match declaration {
+            ast::Declaration::Funclet(_) => true,
+            ast::Declaration::ExternalFunction(ast::ExternalFunction {
+                kind: ast::ExternalFunctionKind::GPU(_),
+                ..
+            }) => true,
+            _ => false,
+        };
+        if block || previous_funclet {
+            printer.line("");
+        }
+        previous_funclet = block;
+        printer.declaration(declaration)?;
+    }
+    Ok(printer.out)
+}
+
+// Recovering assembly from explicated programs
+
+fn generated_name(base: &str, used: &mut HashSet<String>) -> String {
+    let mut candidate = base.to_string();
+    while !used.insert(candidate.clone()) {
+        candidate.push('_');
+    }
+    candidate
+}
+
+// names for the contents of an explicated funclet
+struct FuncletNames<'a> {
+    debug_info: &'a DebugInfo,
+    funclets: &'a HashMap<ir::FuncletId, String>,
+    // meta names and funclets of the value, timeline, and spatial specs
+    specs: [(ast::MetaId, ir::FuncletId); 3],
+    args: Vec<String>,
+    nodes: Vec<String>,
+}
+
+impl<'a> FuncletNames<'a> {
+    fn funclet(&self, id: &ir::FuncletId) -> ast::FuncletId {
+        ast::FuncletId(self.funclets[id].clone())
+    }
+
+    fn typ(&self, id: &ir::TypeId) -> ast::TypeId {
+        ast::TypeId(self.debug_info.type_map[id].clone())
+    }
+
+    fn node(&self, id: &ir::NodeId) -> ast::NodeId {
+        ast::NodeId(self.nodes[*id].clone())
+    }
+
+    // the name of a node of one of the specs, which are printed as written
+    fn spec_node(&self, spec: usize, quot: &ir::Quotient) -> Printed<Option<Hole<ast::NodeId>>> {
+        let funclet = self.specs[spec].1;
+        if let ir::Quotient::None = quot {
+            return Ok(None);
+        }
+        match self
+            .debug_info
+            .funclet_map
+            .get(&funclet)
+            .and_then(|f| f.node_map.get(quot))
+        {
+            Some(n) => Ok(Some(Hole::Filled(ast::NodeId(n.clone())))),
+            None => print_error(format!(
+                "No name for {:?} in {}",
+                quot, self.funclets[&funclet]
+            )),
+        }
+    }
+
+    fn remote(&self, spec: usize, quot: &ir::Quotient) -> Printed<ast::RemoteNodeId> {
+        Ok(ast::RemoteNodeId {
+            funclet: self.specs[spec].0.clone(),
+            node: self.spec_node(spec, quot)?,
+        })
+    }
+
+    // which spec a remote operation refers to, by the name of the field holding it
+    fn remote_field(&self, field: &str, quot: &ir::Quotient) -> Printed<ast::RemoteNodeId> {
+        match field {
+            "event" => self.remote(1, quot),
+            "spatial_operation" => self.remote(2, quot),
+            _ => self.remote(0, quot),
+        }
+    }
+
+    fn tag(&self, spec: usize, tag: &ir::Tag) -> Printed<ast::Tag> {
+        Ok(ast::Tag {
+            quot: Hole::Filled(self.remote(spec, &tag.quot)?),
+            flow: Hole::Filled(tag.flow),
+        })
+    }
+
+    fn operations(
+        &self,
+        value: &ir::Quotient,
+        timeline: &ir::Quotient,
+        spatial: &ir::Quotient,
+    ) -> Printed<Hole<Vec<Hole<ast::RemoteNodeId>>>> {
+        Ok(Hole::Filled(vec![
+            Hole::Filled(self.remote(0, value)?),
+            Hole::Filled(self.remote(1, timeline)?),
+            Hole::Filled(self.remote(2, spatial)?),
+        ]))
+    }
+
+    fn nodes(&self, nodes: &[ir::NodeId]) -> Hole<Vec<Hole<ast::NodeId>>> {
+        Hole::Filled(nodes.iter().map(|n| Hole::Filled(self.node(n))).collect())
+    }
+}
+
+macro_rules! recover_element {
+    ($arg:ident [$arg_type:ident] $names:ident) => {
+        Hole::Filled(
+            $arg.iter()
+                .map(|v| Ok(recover_element!(v $arg_type $names)))
+                .collect::<Printed<_>>()?,
+        )
+    };
+    ($arg:ident Immediate $names:ident) => {
+        Hole::Filled(match $arg {
+            ir::Constant::I32(c) => c.to_string(),
+            ir::Constant::I64(c) => c.to_string(),
+            ir::Constant::U64(c) => c.to_string(),
+            ir::Constant::I8(c) => c.to_string(),
+            ir::Constant::I16(c) => c.to_string(),
+            ir::Constant::U8(c) => c.to_string(),
+            ir::Constant::U16(c) => c.to_string(),
+            ir::Constant::U32(c) => c.to_string(),
+            ir::Constant::USize(c) => c.to_string(),
+            ir::Constant::F32(c) => c.to_string(),
+            ir::Constant::F64(c) => c.to_string(),
+        })
+    };
+    ($arg:ident Type $names:ident) => {
+        Hole::Filled($names.typ($arg))
+    };
+    ($arg:ident Index $names:ident) => {
+        Hole::Filled(*$arg)
+    };
+    ($arg:ident ExternalFunction $names:ident) => {
+        Hole::Filled(ast::ExternalFunctionId(
+            $names.debug_info.external_function_map[&$arg.0].clone(),
+        ))
+    };
+    ($arg:ident ValueFunction $names:ident) => {
+        Hole::Filled(ast::FunctionClassId(
+            $names.debug_info.function_class_map[$arg].clone(),
+        ))
+    };
+    ($arg:ident Operation $names:ident) => {
+        Hole::Filled($names.node($arg))
+    };
+    ($arg:ident RemoteOperation $names:ident) => {
+        Hole::Filled($names.remote_field(stringify!($arg), $arg)?)
+    };
+    ($arg:ident Place $names:ident) => {
+        Hole::Filled(*$arg)
+    };
+    ($arg:ident Funclet $names:ident) => {
+        Hole::Filled($names.funclet($arg))
+    };
+    ($arg:ident StorageType $names:ident) => {
+        Hole::Filled($names.debug_info.ffi_type_map[&$arg.0].clone())
+    };
+    ($arg:ident BufferFlags $names:ident) => {
+        Hole::Filled(*$arg)
+    };
+}
+
+macro_rules! recover_node {
+    ($($_lang:ident $name:ident ($($arg:ident : $arg_type:tt,)*) -> $_output:ident;)*) => {
+        fn recover_node(node: &ir::Node, names: &FuncletNames) -> Printed<ast::Node> {
+            Ok(match node {
+                $(ir::Node::$name { $($arg,)* } => ast::Node::$name {
+                    $($arg: recover_element!($arg $arg_type names),)*
+                },)*
+            })
+        }
+    };
+}
+
+with_operations!(recover_node);
+
+fn recover_tail_edge(tail: &ir::TailEdge, names: &FuncletNames) -> Printed<ast::TailEdge> {
+    let node = |n: &ir::NodeId| Hole::Filled(names.node(n));
+    Ok(match tail {
+        ir::TailEdge::Return { return_values } => ast::TailEdge::Return {
+            return_values: names.nodes(return_values),
+        },
+        ir::TailEdge::Jump { join, arguments } => ast::TailEdge::Jump {
+            join: node(join),
+            arguments: names.nodes(arguments),
+        },
+        ir::TailEdge::ScheduleCall {
+            value_operation,
+            timeline_operation,
+            spatial_operation,
+            callee_funclet_id,
+            callee_arguments,
+            continuation_join,
+        } => ast::TailEdge::ScheduleCall {
+            operations: names.operations(value_operation, timeline_operation, spatial_operation)?,
+            callee_funclet_id: Hole::Filled(names.funclet(callee_funclet_id)),
+            callee_arguments: names.nodes(callee_arguments),
+            continuation_join: node(continuation_join),
+        },
+        ir::TailEdge::ScheduleSelect {
+            value_operation,
+            timeline_operation,
+            spatial_operation,
+            condition,
+            callee_funclet_ids,
+            callee_arguments,
+            continuation_join,
+        } => ast::TailEdge::ScheduleSelect {
+            operations: names.operations(value_operation, timeline_operation, spatial_operation)?,
+            condition: node(condition),
+            callee_funclet_ids: Hole::Filled(
+                callee_funclet_ids
+                    .iter()
+                    .map(|f| Hole::Filled(names.funclet(f)))
+                    .collect(),
+            ),
+            callee_arguments: names.nodes(callee_arguments),
+            continuation_join: node(continuation_join),
+        },
+        ir::TailEdge::ScheduleCallYield {
+            value_operation,
+            timeline_operation,
+            spatial_operation,
+            external_function_id,
+            yielded_nodes,
+            continuation_join,
+        } => ast::TailEdge::ScheduleCallYield {
+            operations: names.operations(value_operation, timeline_operation, spatial_operation)?,
+            external_function_id: Hole::Filled(ast::ExternalFunctionId(
+                names.debug_info.external_function_map[&external_function_id.0].clone(),
+            )),
+            yielded_nodes: names.nodes(yielded_nodes),
+            continuation_join: node(continuation_join),
+        },
+        ir::TailEdge::DynamicAllocFromBuffer {
+            buffer,
+            arguments,
+            dynamic_allocation_size_slots,
+            success_funclet_id,
+            failure_funclet_id,
+            continuation_join,
+        } => ast::TailEdge::DynamicAllocFromBuffer {
+            buffer: node(buffer),
+            dynamic_allocation_size_slots: Hole::Filled(
+                dynamic_allocation_size_slots
+                    .iter()
+                    .map(|slot| Hole::Filled(slot.as_ref().map(|n| names.node(n))))
+                    .collect(),
+            ),
+            success_funclet_id: Hole::Filled(names.funclet(success_funclet_id)),
+            failure_funclet_id: Hole::Filled(names.funclet(failure_funclet_id)),
+            arguments: names.nodes(arguments),
+            continuation_join: node(continuation_join),
+        },
+        ir::TailEdge::DebugHole { inputs } => ast::TailEdge::DebugHole {
+            inputs: inputs.iter().map(|n| names.node(n)).collect(),
+        },
+    })
+}
+
+fn recover_schedule_funclet(
+    funclet_id: ir::FuncletId,
+    funclet: &ir::Funclet,
+    metas: [ast::MetaId; 3],
+    funclets: &HashMap<ir::FuncletId, String>,
+    debug_info: &DebugInfo,
+) -> Printed<ast::Funclet> {
+    let (value, timeline, spatial) = match &funclet.spec_binding {
+        ir::FuncletSpecBinding::ScheduleExplicit {
+            value,
+            timeline,
+            spatial,
+        } => (value, timeline, spatial),
+        _ => return print_error(format!("{} is not bound to specs", funclets[&funclet_id])),
+    };
+    let spec_id = |spec: &ir::FuncletSpec| match spec.funclet_id_opt {
+        Some(id) => Ok(id),
+        None => print_error(format!("{} has an unbound spec", funclets[&funclet_id])),
+    };
+    let [value_meta, timeline_meta, spatial_meta] = metas;
+    let debug_names = debug_info.funclet_map.get(&funclet_id).map(|f| &f.node_map);
+    let debug_name = |quot: ir::Quotient| debug_names.and_then(|m| m.get(&quot)).cloned();
+
+    // explication renumbers nodes, so only the arguments and results keep their names
+    let mut used = HashSet::new();
+    let args: Vec<_> = (0..funclet.input_types.len())
+        .map(|index| debug_name(ir::Quotient::Input { index }))
+        .collect();
+    let rets: Vec<_> = (0..funclet.output_types.len())
+        .map(|index| debug_name(ir::Quotient::Output { index }))
+        .collect();
+    used.extend(args.iter().chain(rets.iter()).flatten().cloned());
+    let args: Vec<_> = args
+        .into_iter()
+        .enumerate()
+        .map(|(i, n)| n.unwrap_or_else(|| generated_name(&format!("_arg{}", i), &mut used)))
+        .collect();
+    let nodes = funclet
+        .nodes
+        .iter()
+        .enumerate()
+        .map(|(i, node)| match node {
+            ir::Node::Phi { index } => args[*index].clone(),
+            _ => generated_name(&format!("_{}", i), &mut used),
+        })
+        .collect();
+
+    let names = FuncletNames {
+        debug_info,
+        funclets,
+        specs: [
+            (value_meta, spec_id(value)?),
+            (timeline_meta, spec_id(timeline)?),
+            (spatial_meta, spec_id(spatial)?),
+        ],
+        args,
+        nodes,
+    };
+    let specs = [value, timeline, spatial];
+    let tags = |select: fn(&ir::FuncletSpec) -> &[ir::Tag], index: usize| {
+        specs
+            .iter()
+            .enumerate()
+            .map(|(spec, s)| names.tag(spec, &select(s)[index]))
+            .collect::<Printed<_>>()
+    };
+    let header_args = funclet
+        .input_types
+        .iter()
+        .enumerate()
+        .map(|(index, t)| {
+            Ok(ast::FuncletArgument {
+                name: Some(ast::NodeId(names.args[index].clone())),
+                typ: names.typ(t),
+                tags: tags(|s| &s.input_tags, index)?,
+            })
+        })
+        .collect::<Printed<_>>()?;
+    let header_rets = funclet
+        .output_types
+        .iter()
+        .enumerate()
+        .map(|(index, t)| {
+            Ok(ast::FuncletArgument {
+                name: rets[index].clone().map(ast::NodeId),
+                typ: names.typ(t),
+                tags: tags(|s| &s.output_tags, index)?,
+            })
+        })
+        .collect::<Printed<_>>()?;
+
+    let mut commands: Vec<_> = funclet
+        .nodes
+        .iter()
+        .enumerate()
+        .map(|(index, node)| {
+            let name = match node {
+                ir::Node::Phi { .. } => format!("{}{}", PHI_QUALIFIER, names.nodes[index]),
+                _ => names.nodes[index].clone(),
+            };
+            Ok(Hole::Filled(ast::Command::Node(ast::NamedNode {
+                name: Some(ast::NodeId(name)),
+                node: recover_node(node, &names)?,
+            })))
+        })
+        .collect::<Printed<_>>()?;
+    commands.push(Hole::Filled(ast::Command::TailEdge(recover_tail_edge(
+        &funclet.tail_edge,
+        &names,
+    )?)));
+
+    let meta_map = ast::MetaMapping {
+        value: (names.specs[0].0.clone(), names.funclet(&names.specs[0].1)),
+        timeline: (names.specs[1].0.clone(), names.funclet(&names.specs[1].1)),
+        spatial: (names.specs[2].0.clone(), names.funclet(&names.specs[2].1)),
+    };
+    Ok(ast::Funclet {
+        kind: ir::FuncletKind::ScheduleExplicit,
+        header: ast::FuncletHeader {
+            name: ast::FuncletId(funclets[&funclet_id].clone()),
+            args: header_args,
+            ret: header_rets,
+            binding: ast::FuncletBinding::ScheduleBinding(ast::ScheduleBinding {
+                implicit_tags: (
+                    names.tag(1, &timeline.implicit_in_tag)?,
+                    names.tag(1, &timeline.implicit_out_tag)?,
+                ),
+                meta_map,
+            }),
+        },
+        commands,
+    })
+}
+
+// Rebuilds `original` with every schedule funclet replaced by its explicated version in
+//   `program`, adding the funclets explication created after the other declarations
+// Everything else is kept as written, since the IR loses details like shader paths
+// Nodes are renamed by their index in the explicated funclet, as explication renumbers them
+pub fn explicated_program(
+    original: &ast::Program,
+    program: &ir::Program,
+    debug_info: &DebugInfo,
+) -> Result<ast::Program, PrintError> {
+    let mut used: HashSet<String> = debug_info
+        .funclet_map
+        .values()
+        .map(|f| f.name.clone())
+        .collect();
+    let funclets: HashMap<ir::FuncletId, String> = program
+        .funclets
+        .iter()
+        .map(|(id, _)| {
+            let name = match debug_info.funclet_map.get(&id) {
+                Some(f) => f.name.clone(),
+                None => generated_name(&format!("_funclet{}", id), &mut used),
+            };
+            (id, name)
+        })
+        .collect();
+    let ids: HashMap<&String, ir::FuncletId> =
+        funclets.iter().map(|(id, name)| (name, *id)).collect();
+
+    let metas_of = |funclet: &ast::Funclet| match &funclet.header.binding {
+        ast::FuncletBinding::ScheduleBinding(binding) => Some([
+            binding.meta_map.value.0.clone(),
+            binding.meta_map.timeline.0.clone(),
+            binding.meta_map.spatial.0.clone(),
+        ]),
+        _ => None,
+    };
+    let mut declarations = Vec::new();
+    let mut last_metas = None;
+    for declaration in &original.declarations {
+        declarations.push(match declaration {
+            ast::Declaration::Funclet(funclet)
+                if funclet.kind == ir::FuncletKind::ScheduleExplicit =>
+            {
+                let id = ids[&funclet.header.name.0];
+                let metas = match metas_of(funclet) {
+                    Some(metas) => metas,
+                    None => return unrepresentable(&funclet.header),
+                };
+                last_metas = Some(metas.clone());
+                ast::Declaration::Funclet(recover_schedule_funclet(
+                    id,
+                    &program.funclets[id],
+                    metas,
+                    &funclets,
+                    debug_info,
+                )?)
+            }
+            _ => declaration.clone(),
+        });
+    }
+    for (id, funclet) in program.funclets.iter() {
+        if debug_info.funclet_map.contains_key(&id) {
+            continue;
+        }
+        let metas = last_metas.clone().unwrap_or_else(|| {
+            [
+                ast::MetaId("val".to_string()),
+                ast::MetaId("time".to_string()),
+                ast::MetaId("space".to_string()),
+            ]
+        });
+        declarations.push(ast::Declaration::Funclet(recover_schedule_funclet(
+            id, funclet, metas, &funclets, debug_info,
+        )?));
+    }
+    Ok(ast::Program {
+        path: original.path.clone(),
+        version: original.version.clone(),
+        declarations,
+        spans: HashMap::new(),
+        external_spans: HashMap::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembly::parser::{parse, unescape};
+    use crate::frontend::{self, CompileData, CompileOptions};
+    use proptest::prelude::*;
+    use std::path::{Path, PathBuf};
+
+    // the same programs test.py compiles, leaving out the unfinished `_wip` examples and the
+    //   baselines written in older syntax
+    fn cair_files(dir: &Path, files: &mut Vec<PathBuf>) {
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                cair_files(&path, files);
+            } else if path.to_string_lossy().ends_with("test.cair") {
+                files.push(path);
+            }
+        }
+    }
+
+    fn test_programs() -> Vec<(PathBuf, String)> {
+        let mut files = Vec::new();
+        cair_files(
+            &Path::new(env!("CARGO_MANIFEST_DIR")).join("caiman-test"),
+            &mut files,
+        );
+        files.sort();
+        files
+            .into_iter()
+            .map(|path| {
+                let text = std::fs::read_to_string(&path).unwrap();
+                (path, text)
+            })
+            .collect()
+    }
+
+    fn directory(path: &Path) -> String {
+        path.parent().unwrap().to_string_lossy().to_string()
+    }
+
+    fn print(path: &Path, program: &ast::Program) -> String {
+        print_program(program).unwrap_or_else(|e| panic!("{}: {}", path.display(), e))
+    }
+
+    fn reprint(path: &Path, text: &str) -> String {
+        match parse(&directory(path), text) {
+            Ok(program) => print(path, &program),
+            Err(e) => panic!(
+                "{} printed as unparsable assembly:\n{}\n{}",
+                path.display(),
+                e,
+                text
+            ),
+        }
+    }
+
+    #[test]
+    fn escapes_names() {
+        assert_eq!(ident("x_1"), "x_1");
+        assert_eq!(ident("_in_x"), "_in_x");
+        assert_eq!(ident("12"), "12");
+        assert_eq!(ident("x.1"), "_esc_x_2e_1");
+        assert_eq!(ident("x_2e_1"), "x_2e_1");
+        assert_eq!(ident("enc::x"), "_esc_enc_3a__3a_x");
+        assert_eq!(ident("1x"), "_esc_1x");
+        assert_eq!(ident("_esc_x"), "_esc___esc__x");
+        assert_eq!(ident("_PHI_x"), "_PHI_x");
+    }
+
+    // names built from pieces that are easy to confuse once escaped, or any string at all
+    fn names() -> impl Strategy<Value = String> {
+        const PIECES: [&str; 14] = [
+            "x", "1", "_", ".", "::", "_2e_", "_esc_", "_PHI_", "__", "e", "-", "é", " ", "%",
+        ];
+        prop_oneof![
+            prop::collection::vec(prop::sample::select(PIECES.to_vec()), 1..6)
+                .prop_map(|pieces| pieces.concat()),
+            any::<String>(),
+        ]
+    }
+
+    proptest! {
+        // escaping never gives two names the same spelling, always gives one the parser
+        //   accepts, and can be undone
+        #[test]
+        fn escaping_is_injective(name in names(), other in names()) {
+            let printed = ident(&name);
+            prop_assert!(is_identifier(&printed), "{:?} printed as {:?}", name, printed);
+            if printed != name {
+                prop_assert_eq!(unescape(&printed), Some(name.clone()));
+            }
+            if other != name {
+                prop_assert_ne!(ident(&other), printed);
+            }
+        }
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        // programs whose nodes have generated names print as programs with the same nodes
+        #[test]
+        fn generated_names_round_trip(
+            names in prop::collection::btree_set(
+                names().prop_filter("x and phi names are taken", |name| {
+                    name != "x" && !name.starts_with(PHI_QUALIFIER)
+                }),
+                1..20,
+            )
+        ) {
+            check_generated_names_round_trip(names.into_iter().collect());
+        }
+    }
+
+    fn check_generated_names_round_trip(names: Vec<String>) {
+        let mut program = parse(
+            "",
+            "version 0.0.2
+
+ffi i64;
+native_value %i64 : i64;
+
+function @main() -> %i64;
+
+value[impl default @main] %value() -> %i64 {
+    %x = constant %i64 4;
+    return %x;
+}
+",
+        )
+        .unwrap();
+        for declaration in program.declarations.iter_mut() {
+            if let ast::Declaration::Funclet(funclet) = declaration {
+                let constant = funclet.commands[0].clone();
+                let mut commands: Vec<_> = names
+                    .iter()
+                    .map(|name| {
+                        let mut command = constant.clone();
+                        if let Hole::Filled(ast::Command::Node(node)) = &mut command {
+                            node.name = Some(ast::NodeId(name.clone()));
+                        }
+                        command
+                    })
+                    .collect();
+                commands.extend(funclet.commands.drain(..));
+                funclet.commands = commands;
+            }
+        }
+        let path = Path::new("generated.cair");
+        let printed = print(path, &program);
+        let reparsed = parse("", &printed).unwrap_or_else(|e| panic!("{}\n{}", e, printed));
+        let mut parsed_names = Vec::new();
+        for declaration in reparsed.declarations.iter() {
+            if let ast::Declaration::Funclet(funclet) = declaration {
+                for command in funclet.commands.iter() {
+                    if let Hole::Filled(ast::Command::Node(ast::NamedNode {
+                        name: Some(name),
+                        node: ast::Node::Constant { .. },
+                    })) = command
+                    {
+                        parsed_names.push(name.0.clone());
+                    }
+                }
+            }
+        }
+        assert_eq!(
+            parsed_names,
+            [names.as_slice(), &["x".to_string()]].concat()
+        );
+        assert_eq!(reprint(path, &printed), printed);
+    }
+
+    #[test]
+    fn phi_names_are_reserved() {
+        let text = "version 0.0.2
+
+ffi i64;
+native_value %i64 : i64;
+
+function @main() -> %i64;
+
+value[impl default @main] %value() -> %i64 {
+    %_PHI_x = constant %i64 4;
+    return %_PHI_x;
+}
+";
+        let error = parse("", text).unwrap_err();
+        assert!(
+            error.to_string().contains("reserved for phi nodes"),
+            "{}",
+            error
+        );
+        let escaped = text.replace("%_PHI_x", "%_esc___PHI__x");
+        let error = parse("", &escaped).unwrap_err();
+        assert!(
+            error.to_string().contains("reserved for phi nodes"),
+            "{}",
+            error
+        );
+
+        let mut program = parse("", &text.replace("%_PHI_x", "%x")).unwrap();
+        for declaration in program.declarations.iter_mut() {
+            if let ast::Declaration::Funclet(funclet) = declaration {
+                if let Hole::Filled(ast::Command::Node(node)) = &mut funclet.commands[0] {
+                    node.name = Some(ast::NodeId("_PHI_x".to_string()));
+                }
+            }
+        }
+        let error = print_program(&program).unwrap_err();
+        assert!(
+            error.message.contains("reserved for phi nodes"),
+            "{}",
+            error.message
+        );
+    }
+
+    #[test]
+    fn reports_what_it_cannot_print() {
+        let text = "version 0.0.2
+
+ffi i64;
+native_value %i64 : i64;
+
+function @main() -> %i64;
+
+value[impl default @main] %value() -> %i64 {
+    %x = constant %i64 4;
+    return %x;
+}
+";
+        let mut program = parse("", text).unwrap();
+        for declaration in program.declarations.iter_mut() {
+            if let ast::Declaration::Funclet(funclet) = declaration {
+                for command in funclet.commands.iter_mut() {
+                    if let Hole::Filled(ast::Command::Node(ast::NamedNode {
+                        node: ast::Node::Constant { value, .. },
+                        ..
+                    })) = command
+                    {
+                        *value = Hole::Empty;
+                    }
+                }
+            }
+        }
+        let error = print_program(&program).unwrap_err();
+        assert_eq!(
+            error.message,
+            "The value of a constant cannot be a hole in assembly"
+        );
+    }
+
+    // printing a parsed program and parsing it again must print the same text
+    #[test]
+    fn round_trip_is_idempotent() {
+        let mut checked = 0;
+        for (path, text) in test_programs() {
+            let program = match parse(&directory(&path), &text) {
+                Ok(program) => program,
+                Err(e) => panic!("{} doesn't parse: {}", path.display(), e),
+            };
+            let printed = print(&path, &program);
+            assert_eq!(reprint(&path, &printed), printed, "{}", path.display());
+            checked += 1;
+        }
+        assert!(checked > 0);
+    }
+
+    fn check(path: &Path, text: &str) -> Result<(), frontend::CompileError> {
+        let data = CompileData {
+            path: directory(path),
+            filename: path.to_string_lossy().to_string(),
+            input_string: text.to_string(),
+        };
+        frontend::check_caiman(data, CompileOptions::default())
+    }
+
+    fn explicate(path: &Path, text: &str) -> String {
+        let program = parse(&directory(path), text).unwrap();
+        let definition = crate::explication::explicate(
+            crate::assembly::lowering_pass::lower(program.clone(), "").unwrap(),
+        )
+        .unwrap();
+        let explicated = explicated_program(&program, &definition.program, &definition.debug_info)
+            .unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+        print(path, &explicated)
+    }
+
+    // an explicated program prints as a fully explicit schedule that still type checks
+    //   and that explication leaves alone
+    #[test]
+    fn explicated_programs_round_trip() {
+        let mut checked = 0;
+        for (path, text) in test_programs() {
+            if let Err(e) = check(&path, &text) {
+                panic!("{} doesn't check: {}", path.display(), e);
+            }
+            let explicit = explicate(&path, &text);
+            assert!(!explicit.contains('?'), "{}:\n{}", path.display(), explicit);
+            assert_eq!(reprint(&path, &explicit), explicit, "{}", path.display());
+            if let Err(e) = check(&path, &explicit) {
+                panic!(
+                    "{} no longer checks once explicated: {}\n{}",
+                    path.display(),
+                    e,
+                    explicit
+                );
+            }
+            assert_eq!(explicate(&path, &explicit), explicit, "{}", path.display());
+            checked += 1;
+        }
+        assert!(checked > 0);
+    }
+}
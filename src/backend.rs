@@ -0,0 +1,168 @@
+// Backends turn a type checked program into code
+//
+// Every backend emits a Rust module per pipeline built on `caiman_rt`, and they differ in where
+// the work a program places on the GPU runs. The wgpu backend needs a device and queue at run
+// time, while the cpu backend keeps GPU buffers in host memory and runs kernels through the
+// `CpuFunctions` trait, so the same program can run on machines without a GPU.
+
+use crate::debug_info::DebugInfo;
+use crate::ir;
+use crate::rust_wgpu_backend::code_generator::Target;
+use crate::rust_wgpu_backend::codegen::CodeGen;
+use crate::rust_wgpu_backend::layout::LayoutError;
+use serde_derive::{Deserialize, Serialize};
+use std::str::FromStr;
+
+pub trait Backend {
+    /// Generates the code for every pipeline of `program`, which must have been type checked.
+    /// Fails if the program stores a value somewhere its type has no layout.
+    fn generate(
+        &self,
+        program: &ir::Program,
+        debug_info: &DebugInfo,
+        print_codegen_debug_info: bool,
+    ) -> Result<String, LayoutError>;
+}
+
+/// Runs GPU work through wgpu
+pub struct WgpuBackend;
+
+/// Runs everything on the host. Each kernel becomes a method of `CpuFunctions`, which by
+/// default calls a cpu operation implementing the same function, if there is one.
+pub struct CpuBackend;
+
+fn generate_rust(
+    program: &ir::Program,
+    debug_info: &DebugInfo,
+    print_codegen_debug_info: bool,
+    target: Target,
+) -> Result<String, LayoutError> {
+    let mut codegen = CodeGen::new(program, debug_info);
+    codegen.set_print_codgen_debug_info(print_codegen_debug_info);
+    codegen.set_target(target);
+    codegen.generate()
+}
+
+impl Backend for WgpuBackend {
+    fn generate(
+        &self,
+        program: &ir::Program,
+        debug_info: &DebugInfo,
+        print_codegen_debug_info: bool,
+    ) -> Result<String, LayoutError> {
+        generate_rust(program, debug_info, print_codegen_debug_info, Target::Wgpu)
+    }
+}
+
+impl Backend for CpuBackend {
+    fn generate(
+        &self,
+        program: &ir::Program,
+        debug_info: &DebugInfo,
+        print_codegen_debug_info: bool,
+    ) -> Result<String, LayoutError> {
+        generate_rust(program, debug_info, print_codegen_debug_info, Target::Cpu)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum BackendKind {
+    #[default]
+    Wgpu,
+    Cpu,
+}
+
+impl BackendKind {
+    pub fn backend(self) -> &'static dyn Backend {
+        match self {
+            BackendKind::Wgpu => &WgpuBackend,
+            BackendKind::Cpu => &CpuBackend,
+        }
+    }
+}
+
+impl FromStr for BackendKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "wgpu" => Ok(BackendKind::Wgpu),
+            "cpu" => Ok(BackendKind::Cpu),
+            other => Err(format!(
+                "Unknown backend {}, expected `wgpu` or `cpu`",
+                other
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frontend::{self, CompileData, CompileOptions};
+    use std::path::Path;
+
+    fn compile_edited(file: &str, backend: BackendKind, edit: impl Fn(String) -> String) -> String {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("caiman-test")
+            .join(file);
+        let data = CompileData {
+            path: path.parent().unwrap().to_string_lossy().to_string(),
+            filename: path.to_string_lossy().to_string(),
+            input_string: edit(std::fs::read_to_string(&path).unwrap()),
+        };
+        let options = CompileOptions {
+            backend,
+            ..Default::default()
+        };
+        frontend::compile_caiman(data, options).unwrap()
+    }
+
+    fn compile(file: &str, backend: BackendKind) -> String {
+        compile_edited(file, backend, |text| text)
+    }
+
+    #[test]
+    fn parses_backend_kinds() {
+        assert_eq!("wgpu".parse(), Ok(BackendKind::Wgpu));
+        assert_eq!("cpu".parse(), Ok(BackendKind::Cpu));
+        assert!("vulkan".parse::<BackendKind>().is_err());
+    }
+
+    #[test]
+    fn cpu_backend_does_not_use_wgpu() {
+        let wgpu = compile("gpu_timeline/gpu_external_test.cair", BackendKind::Wgpu);
+        assert!(wgpu.contains("create_compute_pipeline"));
+        let cpu = compile("gpu_timeline/gpu_external_test.cair", BackendKind::Cpu);
+        assert!(!cpu.contains("wgpu"));
+        assert!(cpu.contains("fn simple(&self"));
+    }
+
+    #[test]
+    fn generates_pipeline_wrappers() {
+        let code = compile("basics/copy_add_test.cair", BackendKind::Wgpu);
+        assert!(code.contains("pub fn start(&mut self, x : i64) -> PipelineOutputTuple<'callee>"));
+        assert!(code.contains("pub const JOIN_STACK_BYTES : usize = 0;"));
+        let code = compile("control_flow/rec_sum_test.cair", BackendKind::Wgpu);
+        assert!(code.contains("-> Result<PipelineOutputTuple<'callee>, Yielded>"));
+        assert!(code.contains("// The join stack of `main` has no bound"));
+        assert!(!code.contains("JOIN_STACK_BYTES"));
+        assert!(code.contains("pub fn with_join_stack_bytes("));
+        let code = compile_edited(
+            "control_flow/rec_sum_test.cair",
+            BackendKind::Wgpu,
+            |text| text.replace("effect %eff;", "effect %eff, recursion 4;"),
+        );
+        assert!(
+            code.contains("pub const JOIN_STACK_BYTES : usize = caiman_rt::max_join_stack_bytes(")
+        );
+        assert!(code.contains("5 * (std::mem::size_of::<ClosureHeader>()"));
+        assert!(code.contains("pub fn new("));
+    }
+
+    #[test]
+    fn cpu_backend_falls_back_to_cpu_operations() {
+        let cpu = compile("gpu_timeline/gpu_host_fallback_test.cair", BackendKind::Cpu);
+        assert!(cpu.contains("self.simple_cpu(state, "));
+    }
+}
@@ -0,0 +1,282 @@
+// Compiling Caiman programs from a Cargo build script
+//
+// `compile_to_out_dir` compiles a program into `OUT_DIR`, where the crate being built can
+//   `include!(concat!(env!("OUT_DIR"), "/<name>.rs"))` it. It tells Cargo to rerun the build
+//   script whenever the program or a shader it references changes, and prints every diagnostic
+//   as a `cargo:warning` so it shows up in the output of `cargo build`.
+//
+// High-level Caiman is lowered by hlc, which depends on this crate, so `.cm` programs are built
+//   with `hlc::build::compile_to_out_dir` instead. It lowers the program and hands the assembly
+//   to `compile_program_to_out_dir`.
+
+use crate::assembly::ast;
+use crate::diagnostics::{codes, Diagnostic};
+use crate::explication;
+use crate::frontend::{
+    self, CompileData, CompileError, CompileErrorKind, CompileMode, CompileOptions,
+};
+use std::path::{Path, PathBuf};
+
+fn usage_error(message: String) -> CompileError {
+    CompileError::new(
+        CompileErrorKind::Usage,
+        Diagnostic::error(codes::USAGE, message),
+    )
+}
+
+fn io_error(path: &Path, why: std::io::Error) -> CompileError {
+    CompileError::new(
+        CompileErrorKind::Io,
+        Diagnostic::error(
+            codes::IO,
+            format!("Couldn't access {}: {}", path.display(), why),
+        ),
+    )
+}
+
+fn out_dir() -> Result<PathBuf, CompileError> {
+    match std::env::var_os("OUT_DIR") {
+        Some(out_dir) => Ok(PathBuf::from(out_dir)),
+        None => Err(usage_error(
+            "OUT_DIR isn't set, Caiman programs can only be compiled to it from a build script"
+                .to_string(),
+        )),
+    }
+}
+
+// the shader files `program` reads, which lowering resolves relative to `program.path`
+pub fn shader_paths(program: &ast::Program) -> Vec<PathBuf> {
+    let directory = Path::new(&program.path);
+    program
+        .declarations
+        .iter()
+        .filter_map(|declaration| match declaration {
+            ast::Declaration::ExternalFunction(ast::ExternalFunction {
+                kind: ast::ExternalFunctionKind::GPU(info),
+                ..
+            }) => Some(directory.join(&info.shader_module)),
+            _ => None,
+        })
+        .collect()
+}
+
+// reports a failed compilation to Cargo, one line of the diagnostic per warning
+pub fn report(diagnostic: &Diagnostic) {
+    for line in diagnostic.to_string().lines() {
+        println!("cargo:warning={}", line);
+    }
+}
+
+pub fn rerun_if_changed(paths: &[PathBuf]) {
+    for path in paths {
+        println!("cargo:rerun-if-changed={}", path.display());
+    }
+}
+
+fn output_path(source: &Path, out_dir: &Path) -> Result<PathBuf, CompileError> {
+    match source.file_stem() {
+        Some(stem) => Ok(out_dir.join(stem).with_extension("rs")),
+        None => Err(usage_error(format!(
+            "{} doesn't name a file",
+            source.display()
+        ))),
+    }
+}
+
+fn write_output(code: &str, source: &Path, out_dir: &Path) -> Result<PathBuf, CompileError> {
+    let output = output_path(source, out_dir)?;
+    std::fs::create_dir_all(out_dir).map_err(|why| io_error(out_dir, why))?;
+    std::fs::write(&output, code).map_err(|why| io_error(&output, why))?;
+    Ok(output)
+}
+
+fn generate_program(
+    program: ast::Program,
+    filename: &str,
+    options: &CompileOptions,
+) -> Result<String, CompileError> {
+    let mut definition = explication::explicate(frontend::lower_assembly(program, filename)?)?;
+    frontend::check_and_optimize(&mut definition, &options.optimizations)?;
+    frontend::generate(&definition, options)
+}
+
+// compiles `source` into `out_dir`, recording every file the result depends on in `dependencies`
+//   as it learns about them, so they're known even when a later stage fails
+fn compile_into(
+    source: &Path,
+    options: &CompileOptions,
+    out_dir: &Path,
+    dependencies: &mut Vec<PathBuf>,
+) -> Result<PathBuf, CompileError> {
+    dependencies.push(source.to_path_buf());
+    let compile_mode = match source.extension().and_then(std::ffi::OsStr::to_str) {
+        Some("cair") => CompileMode::Assembly,
+        Some("ron") => CompileMode::RON,
+        Some("cm") => {
+            return Err(usage_error(format!(
+                "{} is high-level Caiman, which is built with hlc::build::compile_to_out_dir",
+                source.display()
+            )))
+        }
+        _ => {
+            return Err(usage_error(format!(
+                "Unsupported file extension for {}, .cair or .ron expected",
+                source.display()
+            )))
+        }
+    };
+    let compile_data = CompileData {
+        path: source
+            .parent()
+            .map(|parent| parent.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        filename: source.to_string_lossy().to_string(),
+        input_string: std::fs::read_to_string(source).map_err(|why| io_error(source, why))?,
+    };
+    let code = match compile_mode {
+        CompileMode::Assembly => {
+            let program = frontend::parse_assembly(&compile_data)?;
+            dependencies.extend(shader_paths(&program));
+            generate_program(program, &compile_data.filename, options)?
+        }
+        // RON definitions carry their shaders with them
+        CompileMode::RON => {
+            let mut definition = frontend::read_ron(&compile_data)?;
+            frontend::check_and_optimize(&mut definition, &options.optimizations)?;
+            frontend::generate(&definition, options)?
+        }
+    };
+    write_output(&code, source, out_dir)
+}
+
+// compiles the `.cair` or `.ron` program at `path` to `$OUT_DIR/<file stem>.rs`, returning where
+//   it was written
+pub fn compile_to_out_dir<P: AsRef<Path>>(
+    path: P,
+    options: CompileOptions,
+) -> Result<PathBuf, CompileError> {
+    let mut dependencies = Vec::new();
+    let result = out_dir()
+        .and_then(|out_dir| compile_into(path.as_ref(), &options, &out_dir, &mut dependencies));
+    rerun_if_changed(&dependencies);
+    if let Err(error) = &result {
+        report(&error.diagnostic);
+    }
+    result
+}
+
+// compiles an assembly program some other frontend produced from `source` to
+//   `$OUT_DIR/<file stem of source>.rs`
+// the caller is responsible for telling Cargo about `source` and anything it imports
+pub fn compile_program_to_out_dir(
+    program: ast::Program,
+    source: &Path,
+    options: CompileOptions,
+) -> Result<PathBuf, CompileError> {
+    rerun_if_changed(&shader_paths(&program));
+    let filename = source.to_string_lossy().to_string();
+    let result = out_dir().and_then(|out_dir| {
+        let code = generate_program(program, &filename, &options)?;
+        write_output(&code, source, &out_dir)
+    });
+    if let Err(error) = &result {
+        report(&error.diagnostic);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_file(file: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("caiman-test")
+            .join(file)
+    }
+
+    fn out_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join("caiman-build-tests").join(name)
+    }
+
+    #[test]
+    fn writes_assembly_and_its_shaders_as_dependencies() {
+        let source = test_file("gpu_timeline/gpu_external_test.cair");
+        let out_dir = out_dir("assembly");
+        let mut dependencies = Vec::new();
+        let output = compile_into(
+            &source,
+            &CompileOptions::default(),
+            &out_dir,
+            &mut dependencies,
+        )
+        .unwrap();
+        assert_eq!(output, out_dir.join("gpu_external_test.rs"));
+        assert!(std::fs::read_to_string(&output)
+            .unwrap()
+            .contains("create_compute_pipeline"));
+        assert_eq!(
+            dependencies,
+            vec![
+                source.clone(),
+                source.parent().unwrap().join("gpu_external.comp")
+            ]
+        );
+    }
+
+    #[test]
+    fn writes_ron() {
+        let source = test_file("ron/pipeline_1_test.ron");
+        let mut dependencies = Vec::new();
+        let output = compile_into(
+            &source,
+            &CompileOptions::default(),
+            &out_dir("ron"),
+            &mut dependencies,
+        )
+        .unwrap();
+        assert!(output.ends_with("pipeline_1_test.rs"));
+        assert_eq!(dependencies, vec![source]);
+    }
+
+    #[test]
+    fn runs_optimizations() {
+        let source = test_file("optimizations/kernel_fusion_test.cair");
+        let options = CompileOptions {
+            optimizations: vec![frontend::Optimization::Fusion],
+            ..CompileOptions::default()
+        };
+        let output = compile_into(
+            &source,
+            &options,
+            &out_dir("optimizations"),
+            &mut Vec::new(),
+        )
+        .unwrap();
+        assert!(std::fs::read_to_string(&output).unwrap().contains("fused_"));
+    }
+
+    #[test]
+    fn keeps_dependencies_of_failed_compilations() {
+        let source = test_file("malformed/undeclared_node.cair");
+        let mut dependencies = Vec::new();
+        let error = compile_into(
+            &source,
+            &CompileOptions::default(),
+            &out_dir("malformed"),
+            &mut dependencies,
+        )
+        .unwrap_err();
+        assert_eq!(error.kind, CompileErrorKind::Lowering);
+        assert_eq!(dependencies, vec![source]);
+        let high_level = test_file("high-level-caiman/types/array_ret_test.cm");
+        let error = compile_into(
+            &high_level,
+            &CompileOptions::default(),
+            &out_dir("malformed"),
+            &mut Vec::new(),
+        )
+        .unwrap_err();
+        assert_eq!(error.kind, CompileErrorKind::Usage);
+    }
+}
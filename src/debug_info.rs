@@ -1,4 +1,5 @@
 use crate::assembly;
+use crate::diagnostics::{Range, SourceSpan};
 use crate::explication::expir;
 use crate::ir;
 use crate::rust_wgpu_backend::ffi;
@@ -24,7 +25,12 @@ pub struct DebugInfo {
     pub ffi_type_map: HashMap<usize, assembly::ast::FFIType>,
     pub function_class_map: HashMap<usize, String>,
     pub external_function_map: HashMap<usize, String>,
+    #[serde(default)]
+    pub external_function_spans: HashMap<usize, Range>,
     pub funclet_map: HashMap<usize, FuncletDebugMap>,
+    // the file the program was read from, used to report source spans
+    #[serde(default)]
+    pub source_file: String,
 }
 
 #[derive(Serialize, Deserialize, Debug, Default)]
@@ -33,6 +39,11 @@ pub struct FuncletDebugMap {
     pub name: String,
     // we need to use the quotient to differentiate which thing to index into
     pub node_map: HashMap<ir::Quotient, String>,
+    // where the funclet and its named nodes were written, if they came from assembly
+    #[serde(default)]
+    pub span: Option<Range>,
+    #[serde(default)]
+    pub node_spans: HashMap<String, Range>,
 }
 
 impl DebugInfo {
@@ -58,15 +69,43 @@ impl DebugInfo {
             .unwrap_or(&unknown(index))
             .clone()
     }
+    pub fn external_function_span(&self, index: &usize) -> Option<SourceSpan> {
+        self.external_function_spans
+            .get(index)
+            .map(|range| SourceSpan::new(&self.source_file, *range))
+    }
     pub fn funclet(&self, index: &usize) -> String {
         self.funclet_map
             .get(index)
             .map(|f| f.name.clone())
             .unwrap_or(unknown(index))
     }
+    pub fn funclet_span(&self, index: &usize) -> Option<SourceSpan> {
+        self.funclet_map
+            .get(index)
+            .and_then(|f| f.span)
+            .map(|range| SourceSpan::new(&self.source_file, range))
+    }
+    // falls back to the span of the whole funclet for unnamed nodes
+    pub fn node_span(&self, funclet_index: &usize, node_index: usize) -> Option<SourceSpan> {
+        let quot = ir::Quotient::Node {
+            node_id: node_index,
+        };
+        self.funclet_map
+            .get(funclet_index)
+            .and_then(|f| f.node_map.get(&quot).and_then(|n| f.node_spans.get(n)))
+            .map(|range| SourceSpan::new(&self.source_file, *range))
+            .or_else(|| self.funclet_span(funclet_index))
+    }
     pub fn quot(&self, funclet_index: &usize, quotient: &ir::Quotient) -> String {
         match self.funclet_map.get(funclet_index) {
-            None => format!("{}.{} : (funclet {}, quotient {:?})", unknown(funclet_index), unknown_quot(quotient), funclet_index, quotient),
+            None => format!(
+                "{}.{} : (funclet {}, quotient {:?})",
+                unknown(funclet_index),
+                unknown_quot(quotient),
+                funclet_index,
+                quotient
+            ),
             Some(f) => format!(
                 "{}.{} : (funclet {}, quotient {:?})",
                 &f.name,
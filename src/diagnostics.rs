@@ -0,0 +1,230 @@
+// Compiler diagnostics shared by `caimanc` and `hlc`
+// A diagnostic is what we report to the user (or to an editor / CI annotation)
+// It is deliberately decoupled from the error types of the individual passes,
+//   which convert themselves into diagnostics at the driver boundary
+
+use serde_derive::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+// Stable error codes
+// These are part of the JSON output, so existing codes should never be renumbered
+pub mod codes {
+    pub const SYNTAX: &str = "E0001";
+    pub const IO: &str = "E0002";
+    pub const VERSION: &str = "E0003";
+    pub const FRONTEND_TYPE: &str = "E0004";
+    pub const TYPE_CHECK: &str = "E0005";
+    // the compiler panicked, reported by tools that keep running afterwards
+    pub const INTERNAL: &str = "E0006";
+    // a type declared in the native interface can't be laid out as declared
+    pub const LAYOUT: &str = "E0007";
+    // explication couldn't fill in the holes of a program
+    pub const EXPLICATION: &str = "E0008";
+    // lowering the assembly failed, usually because it names something undeclared
+    pub const LOWERING: &str = "E0009";
+    // the driver was asked for a stage that doesn't exist for the given input
+    pub const USAGE: &str = "E0010";
+    // a shader implementing a GPU kernel couldn't be read, or naga rejected it
+    pub const SHADER: &str = "E0011";
+    // a GPU kernel's bindings or dimensionality disagree with the shader implementing it
+    pub const KERNEL_INTERFACE: &str = "E0012";
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Note => write!(f, "note"),
+        }
+    }
+}
+
+// lines and columns are 1-indexed, matching what editors display
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Position {
+    pub fn new(line: usize, column: usize) -> Position {
+        Position { line, column }
+    }
+}
+
+// A range within some file that is not yet known
+// The assembly parser records these, and the file is attached when reporting
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct SourceSpan {
+    pub file: String,
+    pub start: Position,
+    pub end: Position,
+}
+
+impl SourceSpan {
+    pub fn new(file: &str, range: Range) -> SourceSpan {
+        SourceSpan {
+            file: file.to_string(),
+            start: range.start,
+            end: range.end,
+        }
+    }
+}
+
+impl fmt::Display for SourceSpan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.start.line, self.start.column)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    pub span: SourceSpan,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: String,
+    pub message: String,
+    pub primary: Option<SourceSpan>,
+    pub secondary: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, code: &str, message: String) -> Diagnostic {
+        Diagnostic {
+            severity,
+            code: code.to_string(),
+            message,
+            primary: None,
+            secondary: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn error(code: &str, message: String) -> Diagnostic {
+        Diagnostic::new(Severity::Error, code, message)
+    }
+
+    pub fn with_primary(mut self, span: Option<SourceSpan>) -> Self {
+        self.primary = span;
+        self
+    }
+
+    pub fn with_label(mut self, span: SourceSpan, message: String) -> Self {
+        self.secondary.push(Label { span, message });
+        self
+    }
+
+    pub fn with_note(mut self, note: String) -> Self {
+        self.notes.push(note);
+        self
+    }
+
+    // one diagnostic per line, as expected by `--message-format=json` consumers
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    pub fn render(&self, format: MessageFormat) -> String {
+        match format {
+            MessageFormat::Human => self.to_string(),
+            MessageFormat::Json => self.to_json(),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}[{}]: {}", self.severity, self.code, self.message)?;
+        if let Some(span) = &self.primary {
+            write!(f, "\n  --> {}", span)?;
+        }
+        for label in &self.secondary {
+            write!(f, "\n  ::: {}: {}", label.span, label.message)?;
+        }
+        for note in &self.notes {
+            write!(f, "\n  = note: {}", note)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MessageFormat {
+    #[default]
+    Human,
+    Json,
+}
+
+impl FromStr for MessageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(MessageFormat::Human),
+            "json" => Ok(MessageFormat::Json),
+            other => Err(format!(
+                "Unknown message format {}, expected `human` or `json`",
+                other
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example() -> Diagnostic {
+        let range = Range {
+            start: Position::new(3, 5),
+            end: Position::new(3, 12),
+        };
+        Diagnostic::error(codes::TYPE_CHECK, "Usable != Saved".to_string())
+            .with_primary(Some(SourceSpan::new("main.cair", range)))
+            .with_label(SourceSpan::new("main.cair", range), "used here".to_string())
+            .with_note("In funclet %main".to_string())
+    }
+
+    #[test]
+    fn test_json_shape() {
+        let json: serde_json::Value = serde_json::from_str(&example().to_json()).unwrap();
+        assert_eq!(json["severity"], "error");
+        assert_eq!(json["code"], "E0005");
+        assert_eq!(json["primary"]["file"], "main.cair");
+        assert_eq!(json["primary"]["start"]["line"], 3);
+        assert_eq!(json["primary"]["end"]["column"], 12);
+        assert_eq!(json["secondary"][0]["message"], "used here");
+        assert_eq!(json["notes"][0], "In funclet %main");
+    }
+
+    #[test]
+    fn test_human_format() {
+        assert_eq!(
+            example().to_string(),
+            "error[E0005]: Usable != Saved\n  --> main.cair:3:5\n  ::: main.cair:3:5: used here\n  = note: In funclet %main"
+        );
+        assert_eq!("json".parse(), Ok(MessageFormat::Json));
+        assert!("xml".parse::<MessageFormat>().is_err());
+    }
+}
@@ -1,12 +1,16 @@
 mod context;
+pub mod cost;
+pub mod error;
 pub mod expir;
 mod explicator;
 mod explicator_macros;
 mod util;
 
 use crate::stable_vec::StableVec;
-use crate::{debug_info::DebugInfo, ir};
+use crate::{debug_info::DebugInfo, ir, type_system};
 use context::{InState, StaticContext};
+use error::Error;
+use expir::FuncletId;
 use serde_derive::{Deserialize, Serialize};
 
 use self::explicator::{
@@ -76,19 +80,24 @@ where
     }
 }
 
+// how many fillings of a schedule funclet we offer the type checker before giving up on it
+const SEARCH_LIMIT: usize = 64;
+
 /*
  * adds nodes and fills in ?/??? to build schedules that have all the operations
- * does not actually _store_ any information, and as a result, does not need to backtrack
+ * takes the first filling it finds for each funclet, which the search may revisit later
  * note that this is _not_ done funclet-by-funclet to support adding control flow later
  */
-fn schedule_funclet_operations(context: &StaticContext) -> StableVec<expir::Funclet> {
+fn schedule_funclet_operations(
+    context: &StaticContext,
+) -> Result<StableVec<expir::Funclet>, Error> {
     let mut result = StableVec::new();
     let mut new_funclets = Vec::new();
     for (funclet_id, funclet) in context.program.funclets.iter() {
         match &funclet.kind {
             ir::FuncletKind::ScheduleExplicit => {
                 let (current, mut to_add) =
-                    explicate_schedule_funclet_operation(funclet_id, context);
+                    explicate_schedule_funclet_operation(funclet_id, 0, context)?;
                 result.add(current);
                 new_funclets.append(&mut to_add)
             }
@@ -100,47 +109,137 @@ fn schedule_funclet_operations(context: &StaticContext) -> StableVec<expir::Func
     for new_funclet in new_funclets.drain(..) {
         result.add(new_funclet);
     }
-    result
+    Ok(result)
+}
+
+/*
+ * backtracks over the fillings of a schedule funclet the type checker rejected
+ * each filling of the operation pass is tried with each filling of the storage pass
+ *   until one checks against the rest of the program
+ * leaves the first filling in place if none do, so the type checker can say why
+ */
+fn search_schedule_funclet(
+    funclet_id: FuncletId,
+    original: &expir::Funclet,
+    program: &mut ir::Program,
+    context: &mut StaticContext,
+) {
+    let first_operations = context.program.funclets[funclet_id].clone();
+    let first = program.funclets[funclet_id].clone();
+    let mut attempts = 0;
+    'operations: for operations_to_skip in 0.. {
+        // the operation pass reads the funclet as written, the storage pass reads its result
+        context.program.funclets[funclet_id] = original.clone();
+        let (operations, extra) =
+            match explicate_schedule_funclet_operation(funclet_id, operations_to_skip, context) {
+                Ok(filling) => filling,
+                Err(_) => break,
+            };
+        context.program.funclets[funclet_id] = operations;
+        // the funclets this filling adds are added in the same order the first pass adds them,
+        //   so they get the ids the filling expects
+        let added = match add_funclets(extra, program, context) {
+            Ok(added) => added,
+            Err(_) => continue,
+        };
+        for storage_to_skip in 0.. {
+            if attempts == SEARCH_LIMIT {
+                remove_funclets(&added, program, context);
+                break 'operations;
+            }
+            attempts += 1;
+            match explicate_schedule_funclet_storage(funclet_id, storage_to_skip, context) {
+                Ok(candidate) => program.funclets[funclet_id] = candidate,
+                Err(_) => break,
+            };
+            if type_system::check_funclet(program, funclet_id, context.debug_info).is_ok() {
+                return;
+            }
+        }
+        remove_funclets(&added, program, context);
+    }
+    context.program.funclets[funclet_id] = first_operations;
+    program.funclets[funclet_id] = first;
+}
+
+// adds funclets made by the operation pass to both programs, returning their ids
+fn add_funclets(
+    funclets: Vec<expir::Funclet>,
+    program: &mut ir::Program,
+    context: &mut StaticContext,
+) -> Result<Vec<FuncletId>, Error> {
+    let mut added = Vec::new();
+    for funclet in funclets {
+        let kind = funclet.kind.clone();
+        let funclet_id = context.program.funclets.add(funclet);
+        added.push(funclet_id);
+        let lowered = match kind {
+            ir::FuncletKind::ScheduleExplicit => {
+                explicate_schedule_funclet_storage(funclet_id, 0, context)
+            }
+            _ => lower_spec_funclet(&funclet_id, context),
+        };
+        match lowered {
+            Ok(lowered) => assert_eq!(program.funclets.add(lowered), funclet_id),
+            Err(error) => {
+                context.program.funclets.remove(funclet_id);
+                added.pop();
+                remove_funclets(&added, program, context);
+                return Err(error);
+            }
+        }
+    }
+    Ok(added)
 }
 
-fn explicate_funclets(context: &mut StaticContext) -> StableVec<ir::Funclet> {
-    context.program.funclets = schedule_funclet_operations(&context);
-    context
+// removes funclets added by `add_funclets`, freeing their ids to be handed out in the same order
+fn remove_funclets(added: &[FuncletId], program: &mut ir::Program, context: &mut StaticContext) {
+    for funclet_id in added.iter().rev() {
+        context.program.funclets.remove(*funclet_id);
+        program.funclets.remove(*funclet_id);
+    }
+}
+
+fn explicate_funclets(context: &mut StaticContext) -> Result<ir::Program, Error> {
+    let originals = context.program.funclets.clone();
+    context.program.funclets = schedule_funclet_operations(&context)?;
+    let funclets = context
         .program
         .funclets
         .iter()
         .map(|(funclet_id, funclet)| match funclet.kind {
             ir::FuncletKind::ScheduleExplicit => {
-                explicate_schedule_funclet_storage(funclet_id, &context)
+                explicate_schedule_funclet_storage(funclet_id, 0, &context)
             }
             _ => lower_spec_funclet(&funclet_id, &context),
         })
-        .collect()
-}
+        .collect::<Result<_, _>>()?;
+    let mut program = ir::Program {
+        native_interface: context.program.native_interface.clone(),
+        types: context.program.types.clone(),
+        funclets,
+        function_classes: context.program.function_classes.clone(),
+        pipelines: context.program.pipelines.clone(),
+    };
 
-fn explicate_program(program: expir::Program, debug_info: &DebugInfo) -> ir::Program {
-    let mut context = StaticContext::new(program, debug_info);
-    let explicated_funclets = explicate_funclets(&mut context);
-
-    match context {
-        StaticContext {
-            program: expir::Program {
-                native_interface,
-                types,
-                funclets,
-                function_classes,
-                pipelines,
-            },
-            debug_info: _,
-            spec_explication_data: _,
-        }  => ir::Program {
-            native_interface,
-            types,
-            funclets: explicated_funclets,
-            function_classes,
-            pipelines,
+    // the first filling of every funclet is in place, so each can be checked against the others
+    for (funclet_id, original) in originals.iter() {
+        if original.kind == ir::FuncletKind::ScheduleExplicit
+            && type_system::check_funclet(&program, funclet_id, context.debug_info).is_err()
+        {
+            search_schedule_funclet(funclet_id, original, &mut program, context);
         }
     }
+    Ok(program)
+}
+
+fn explicate_program(
+    program: expir::Program,
+    debug_info: &DebugInfo,
+    cost_model: &dyn cost::CostModel,
+) -> Result<ir::Program, Error> {
+    let mut context = StaticContext::new(program, debug_info, cost_model);
+    explicate_funclets(&mut context)
 }
 
 // it's probably best to do the lowering pass like this,
@@ -151,19 +250,27 @@ fn explicate_program(program: expir::Program, debug_info: &DebugInfo) -> ir::Pro
 //   but debugging explication is gonna be even harder without names...
 pub fn explicate(
     definition: crate::frontend::ExplicationDefinition,
-) -> crate::frontend::Definition {
+) -> Result<crate::frontend::Definition, Error> {
+    explicate_with_cost_model(definition, &cost::TransferCostModel::default())
+}
+
+// explicates with the given cost model deciding which implementations to try first
+pub fn explicate_with_cost_model(
+    definition: crate::frontend::ExplicationDefinition,
+    cost_model: &dyn cost::CostModel,
+) -> Result<crate::frontend::Definition, Error> {
     match definition {
         crate::frontend::ExplicationDefinition {
             version,
             debug_info,
             program,
         } => {
-            let ir_program = explicate_program(program, &debug_info);
-            crate::frontend::Definition {
+            let ir_program = explicate_program(program, &debug_info, cost_model)?;
+            Ok(crate::frontend::Definition {
                 version,
                 debug_info,
                 program: ir_program,
-            }
+            })
         }
     }
 }
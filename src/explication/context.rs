@@ -3,6 +3,8 @@ pub mod outstate;
 pub mod schedule_scope_data;
 pub mod staticcontext;
 
+use super::cost::CostModel;
+use super::error::Error;
 use super::expir::BufferFlags;
 use super::util::*;
 use super::Hole;
@@ -14,6 +16,7 @@ use crate::rust_wgpu_backend::ffi;
 use crate::stable_vec;
 use crate::stable_vec::StableVec;
 use debug_ignore::DebugIgnore;
+use std::cell::{Cell, RefCell};
 use std::collections::{HashMap, HashSet, VecDeque};
 
 #[derive(Debug)]
@@ -28,8 +31,38 @@ pub struct StaticContext<'context> {
 
     pub debug_info: &'context DebugInfo,
 
+    // what explication consults to decide which implementation to try first
+    pub cost_model: &'context dyn CostModel,
+
     // information found about a given spec funclet
     pub spec_explication_data: HashMap<FuncletId, SpecFuncletData>,
+
+    // bookkeeping for the search, which may explicate a funclet several times over
+    pub search: SearchState,
+}
+
+// the explicators only ever see the context immutably, hence the cells
+#[derive(Debug, Default)]
+pub struct SearchState {
+    // how many complete fillings of the current funclet to pass over before accepting one
+    // this is how we ask for the next filling when the type checker rejects the last
+    solutions_to_skip: Cell<usize>,
+
+    // the furthest hole the search ran out of ways to fill
+    // reported when the funclet has no filling at all
+    dead_end: RefCell<Option<DeadEnd>>,
+
+    // why the funclet can't be filled at all, like something explication can't do yet
+    // the first one found ends the search
+    failure: RefCell<Option<Error>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DeadEnd {
+    pub funclet_id: FuncletId,
+    // the node we were explicating, which is one past the last node for the tail edge
+    pub node_id: NodeId,
+    pub message: String,
 }
 
 // this information is static, and doesn't change as explication progresses
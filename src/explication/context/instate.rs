@@ -39,19 +39,11 @@ impl InState {
 
     // general utility
 
+    // describes a hole in the current node, for the funclet it's reported with
     pub fn hole_error(&self, context: &StaticContext) -> String {
         format!(
-            "TODO Hole in funclet {} for node {}",
-            context
-                .debug_info
-                .funclet(&self.get_latest_scope().funclet_id),
-            context.debug_info.node_expir(
-                self.get_current_funclet_id(),
-                self.get_current_node(context)
-                    .as_ref()
-                    .opt()
-                    .expect("Unreachable")
-            )
+            "the storage pass can't fill a hole {}",
+            self.current_node_error(context)
         )
     }
 
@@ -23,6 +23,26 @@ impl OperationOutState {
         self.nodes.drain(..).collect()
     }
 
+    // adds several nodes in place of the one at `node_id`,
+    //   moving the nodes already added after it down to make room
+    // no nodes at all removes the node, moving the later nodes back up
+    pub fn add_nodes_in_place_of(&mut self, node_id: NodeId, nodes: Vec<expir::Node>) {
+        let count = nodes.len();
+        // ids after node_id are at least 1, so this can't underflow when removing the node
+        let map = |id: NodeId| if id > node_id { id + count - 1 } else { id };
+        for node in self.nodes.iter_mut() {
+            if let Hole::Filled(filled) = node {
+                *filled = filled.map_referenced_nodes(map);
+            }
+        }
+        if let Some(tail_edge) = &mut self.tail_edge {
+            *tail_edge = tail_edge.map_referenced_nodes(map);
+        }
+        for node in nodes.into_iter().rev() {
+            self.add_node(node);
+        }
+    }
+
     pub fn set_tail_edge(&mut self, tail_edge: expir::TailEdge) {
         assert!(self.tail_edge.is_none());
         self.tail_edge = Some(tail_edge)
@@ -79,4 +99,4 @@ impl StorageOutState {
     pub fn expect_tail_edge(&mut self) -> ir::TailEdge {
         self.tail_edge.as_ref().expect("No tail edge found").clone()
     }
-}
\ No newline at end of file
+}
@@ -13,16 +13,86 @@ impl<'context> StaticContext<'context> {
     pub fn new(
         program: expir::Program,
         debug_info: &'context DebugInfo,
+        cost_model: &'context dyn CostModel,
     ) -> StaticContext<'context> {
         let mut result = StaticContext {
             program,
             debug_info,
+            cost_model,
             spec_explication_data: HashMap::new(),
+            search: SearchState::default(),
         };
         result.initialize_declarations();
         result
     }
 
+    // search
+
+    // starts a fresh search of a funclet, which accepts the filling after the given number
+    pub fn reset_search(&self, solutions_to_skip: usize) {
+        self.search.solutions_to_skip.set(solutions_to_skip);
+        self.search.dead_end.replace(None);
+        self.search.failure.replace(None);
+    }
+
+    // called with each complete filling of a funclet
+    // returns false for the fillings the search was asked to pass over
+    pub fn accept_solution(&self) -> bool {
+        let remaining = self.search.solutions_to_skip.get();
+        if remaining == 0 {
+            true
+        } else {
+            self.search.solutions_to_skip.set(remaining - 1);
+            false
+        }
+    }
+
+    // notes that the search has nothing left to try at the current node of the state
+    // only the furthest such node is kept, since that's where the filling got stuck
+    pub fn record_dead_end(&self, state: &InState, message: String) {
+        let funclet_id = state.get_current_funclet_id();
+        let node_id = state.get_current_node_id().unwrap();
+        let mut dead_end = self.search.dead_end.borrow_mut();
+        let further = match dead_end.as_ref() {
+            None => true,
+            Some(current) => current.funclet_id == funclet_id && current.node_id < node_id,
+        };
+        if further {
+            *dead_end = Some(DeadEnd {
+                funclet_id,
+                node_id,
+                message,
+            });
+        }
+    }
+
+    pub fn take_dead_end(&self) -> Option<DeadEnd> {
+        self.search.dead_end.take()
+    }
+
+    // notes why the funclet can't be filled at all
+    // the explicators give up on the filling after this, and the search reports it
+    pub fn record_failure(&self, error: Error) {
+        self.search.failure.borrow_mut().get_or_insert(error);
+    }
+
+    // notes that the funclet needs something explication can't do yet
+    pub fn record_unsupported(&self, message: String) {
+        self.record_failure(Error::Unsupported { message });
+    }
+
+    // notes a hole of the given funclet that only an earlier pass could have filled
+    pub fn record_unfilled_hole(&self, funclet_id: FuncletId, message: String) {
+        self.record_failure(Error::UnfilledHole {
+            funclet: self.debug_info.funclet(&funclet_id),
+            message,
+        });
+    }
+
+    pub fn take_failure(&self) -> Option<Error> {
+        self.search.failure.take()
+    }
+
     // setup
 
     fn initialize_declarations(&mut self) {
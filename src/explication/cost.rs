@@ -0,0 +1,279 @@
+use crate::ir;
+use crate::rust_wgpu_backend::ffi;
+use std::convert::TryFrom;
+
+// Estimates what the choices explication makes will cost when the program runs
+// Explication tries the cheapest implementation of a function class first,
+//   and only falls back to the others when the cheaper ones don't type check
+// Costs are in abstract units, they only need to be comparable with each other
+pub trait CostModel: std::fmt::Debug {
+    // the cost of moving a value of the given type from one place to another
+    fn transfer_cost(
+        &self,
+        type_id: ffi::TypeId,
+        from: ir::Place,
+        to: ir::Place,
+        native_interface: &ffi::NativeInterface,
+    ) -> u64;
+
+    // the cost of running an external function with its work placed on the given place
+    // is none when the function can't run there at all
+    fn implementation_cost(
+        &self,
+        external_function_id: ffi::ExternalFunctionId,
+        place: ir::Place,
+        native_interface: &ffi::NativeInterface,
+    ) -> Option<u64>;
+}
+
+// The cost model used unless explication is given another
+// Charges for the bytes a function reads and writes where it runs,
+//   plus the bytes copied to and from the GPU and a fixed cost for each kernel dispatch
+#[derive(Debug, Clone)]
+pub struct TransferCostModel {
+    // cost of touching a byte on the CPU
+    pub cpu_byte_cost: u64,
+    // cost of touching a byte on the GPU
+    pub gpu_byte_cost: u64,
+    // cost of copying a byte between the CPU and the GPU
+    pub transfer_byte_cost: u64,
+    // cost of dispatching a kernel, charged once per dimension of the dispatch
+    pub dispatch_cost: u64,
+}
+
+impl Default for TransferCostModel {
+    fn default() -> Self {
+        TransferCostModel {
+            cpu_byte_cost: 4,
+            gpu_byte_cost: 1,
+            transfer_byte_cost: 2,
+            dispatch_cost: 1024,
+        }
+    }
+}
+
+fn byte_size(type_id: ffi::TypeId, native_interface: &ffi::NativeInterface) -> u64 {
+    let size = native_interface.types[type_id.0].estimate_size(&native_interface.types);
+    u64::try_from(size).unwrap_or(u64::MAX)
+}
+
+fn bytes_touched(types: &[ffi::TypeId], native_interface: &ffi::NativeInterface) -> u64 {
+    types.iter().fold(0, |total, type_id| {
+        total.saturating_add(byte_size(*type_id, native_interface))
+    })
+}
+
+impl CostModel for TransferCostModel {
+    fn transfer_cost(
+        &self,
+        type_id: ffi::TypeId,
+        from: ir::Place,
+        to: ir::Place,
+        native_interface: &ffi::NativeInterface,
+    ) -> u64 {
+        // local values and CPU buffers share memory, so only crossing to the GPU copies
+        let on_gpu = |place| place == ir::Place::Gpu;
+        if on_gpu(from) == on_gpu(to) {
+            0
+        } else {
+            byte_size(type_id, native_interface).saturating_mul(self.transfer_byte_cost)
+        }
+    }
+
+    fn implementation_cost(
+        &self,
+        external_function_id: ffi::ExternalFunctionId,
+        place: ir::Place,
+        native_interface: &ffi::NativeInterface,
+    ) -> Option<u64> {
+        let function = &native_interface.external_functions[external_function_id.0];
+        let inputs = function.get_input_types().unwrap_or(&[]);
+        let outputs = function.get_output_types().unwrap_or(&[]);
+        let touched = bytes_touched(inputs, native_interface)
+            .saturating_add(bytes_touched(outputs, native_interface));
+        match (function, place) {
+            (ffi::ExternalFunction::CpuPureOperation(_), ir::Place::Local)
+            | (ffi::ExternalFunction::CpuEffectfulOperation(_), ir::Place::Local) => {
+                Some(touched.saturating_mul(self.cpu_byte_cost))
+            }
+            (ffi::ExternalFunction::GpuKernel(kernel), ir::Place::Gpu) => {
+                // the inputs come from and the outputs go back to the host
+                let transfers = inputs
+                    .iter()
+                    .map(|type_id| {
+                        self.transfer_cost(*type_id, ir::Place::Local, place, native_interface)
+                    })
+                    .chain(outputs.iter().map(|type_id| {
+                        self.transfer_cost(*type_id, place, ir::Place::Local, native_interface)
+                    }))
+                    .fold(0u64, u64::saturating_add);
+                let dispatch = self
+                    .dispatch_cost
+                    .saturating_mul(kernel.dimensionality.max(1) as u64);
+                Some(
+                    touched
+                        .saturating_mul(self.gpu_byte_cost)
+                        .saturating_add(transfers)
+                        .saturating_add(dispatch),
+                )
+            }
+            _ => None,
+        }
+    }
+}
+
+// Where an implementation of a call placed on the given place runs, and what that costs
+// An implementation that can't run where the call is placed may still run locally, as long
+//   as its results are copied over to the place the call was placed on afterwards
+// Is none when the implementation can run neither there nor locally
+pub fn placement_cost(
+    external_function_id: ffi::ExternalFunctionId,
+    place: ir::Place,
+    native_interface: &ffi::NativeInterface,
+    cost_model: &dyn CostModel,
+) -> Option<(ir::Place, u64)> {
+    if let Some(cost) =
+        cost_model.implementation_cost(external_function_id, place, native_interface)
+    {
+        return Some((place, cost));
+    }
+    let cost =
+        cost_model.implementation_cost(external_function_id, ir::Place::Local, native_interface)?;
+    let function = &native_interface.external_functions[external_function_id.0];
+    let copies = function
+        .get_output_types()
+        .unwrap_or(&[])
+        .iter()
+        .map(|type_id| {
+            cost_model.transfer_cost(*type_id, ir::Place::Local, place, native_interface)
+        })
+        .fold(0u64, u64::saturating_add);
+    Some((ir::Place::Local, cost.saturating_add(copies)))
+}
+
+// Orders the given implementations of a call placed on the given place from cheapest to most
+//   expensive, along with where each runs
+// Implementations that can't run are left out
+pub fn rank_implementations(
+    external_function_ids: impl IntoIterator<Item = ffi::ExternalFunctionId>,
+    place: ir::Place,
+    native_interface: &ffi::NativeInterface,
+    cost_model: &dyn CostModel,
+) -> Vec<(ffi::ExternalFunctionId, ir::Place, u64)> {
+    let mut ranked: Vec<_> = external_function_ids
+        .into_iter()
+        .filter_map(|id| {
+            placement_cost(id, place, native_interface, cost_model)
+                .map(|(runs_on, cost)| (id, runs_on, cost))
+        })
+        .collect();
+    // the sort is stable, so ties keep the order the function class lists them in
+    ranked.sort_by_key(|(_, _, cost)| *cost);
+    ranked
+}
+
+// An implementation explication settled on for a call to a function class
+#[derive(Debug, Clone)]
+pub struct Choice {
+    pub funclet_id: ir::FuncletId,
+    pub node_id: ir::NodeId,
+    // the spec call the node implements, which keeps its name when explication adds nodes
+    pub value_funclet_id: ir::FuncletId,
+    pub operation_id: ir::NodeId,
+    pub function_class_id: ir::FunctionClassId,
+    pub place: ir::Place,
+    pub chosen: ffi::ExternalFunctionId,
+    // every implementation of the class with what it would cost here, cheapest first
+    // the ones that can't run at this place have no cost
+    pub alternatives: Vec<(ffi::ExternalFunctionId, Option<u64>)>,
+}
+
+fn encoder_place(funclet: &ir::Funclet, encoder: ir::NodeId) -> Option<ir::Place> {
+    match &funclet.nodes[encoder] {
+        ir::Node::BeginEncoding { place, .. } => Some(*place),
+        _ => None,
+    }
+}
+
+// Where a schedule node calling an external function was placed
+// A local call whose results are encoded as copies was placed where the copies go,
+//   since that's how explication runs a call it placed there on the CPU
+fn node_place(funclet: &ir::Funclet, node: &ir::Node) -> Option<ir::Place> {
+    match node {
+        ir::Node::LocalDoExternal { outputs, .. } => {
+            let copied_to = funclet.nodes.iter().find_map(|other| match other {
+                ir::Node::EncodeCopy { encoder, input, .. } if outputs.contains(input) => {
+                    encoder_place(funclet, *encoder)
+                }
+                _ => None,
+            });
+            Some(copied_to.unwrap_or(ir::Place::Local))
+        }
+        ir::Node::EncodeDoExternal { encoder, .. } => encoder_place(funclet, *encoder),
+        _ => None,
+    }
+}
+
+// Finds every call of a function class in the schedules of an explicated program
+pub fn program_choices(program: &ir::Program, cost_model: &dyn CostModel) -> Vec<Choice> {
+    let mut choices = Vec::new();
+    for (funclet_id, funclet) in program.funclets.iter() {
+        let value_funclet_id = match &funclet.spec_binding {
+            ir::FuncletSpecBinding::ScheduleExplicit { value, .. } => match value.funclet_id_opt {
+                Some(value_funclet_id) => value_funclet_id,
+                None => continue,
+            },
+            _ => continue,
+        };
+        for (node_id, node) in funclet.nodes.iter().enumerate() {
+            let (operation, chosen) = match node {
+                ir::Node::LocalDoExternal {
+                    operation,
+                    external_function_id,
+                    ..
+                }
+                | ir::Node::EncodeDoExternal {
+                    operation,
+                    external_function_id,
+                    ..
+                } => (operation, *external_function_id),
+                _ => continue,
+            };
+            let (operation_id, function_class_id) = match operation {
+                ir::Quotient::Node { node_id } => {
+                    match &program.funclets[value_funclet_id].nodes[*node_id] {
+                        ir::Node::CallFunctionClass { function_id, .. } => (*node_id, *function_id),
+                        _ => continue,
+                    }
+                }
+                _ => continue,
+            };
+            let place = match node_place(funclet, node) {
+                Some(place) => place,
+                None => continue,
+            };
+            let class = &program.function_classes[function_class_id];
+            let mut alternatives: Vec<_> = class
+                .external_function_ids
+                .iter()
+                .map(|id| {
+                    let cost = placement_cost(*id, place, &program.native_interface, cost_model);
+                    (*id, cost.map(|(_, cost)| cost))
+                })
+                .collect();
+            // implementations that can't run here go last
+            alternatives.sort_by_key(|(_, cost)| cost.unwrap_or(u64::MAX));
+            choices.push(Choice {
+                funclet_id,
+                node_id,
+                value_funclet_id,
+                operation_id,
+                function_class_id,
+                place,
+                chosen,
+                alternatives,
+            });
+        }
+    }
+    choices
+}
@@ -0,0 +1,63 @@
+use crate::diagnostics::SourceSpan;
+use std::fmt;
+
+// Reasons explication can give up on a program
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    // a hole was left somewhere explication can't fill it, like a flow or a spec funclet
+    UnfilledHole {
+        funclet: String,
+        message: String,
+    },
+    // no way to fill the holes of the given schedule funclet was found
+    // the hole the search got furthest before running out of options is reported with it
+    NoSolution {
+        funclet: String,
+        hole: Option<String>,
+        span: Option<SourceSpan>,
+    },
+    // the program needs something explication can't do yet
+    Unsupported {
+        message: String,
+    },
+    // a node doesn't fit the rest of the schedule, like submitting something that isn't an encoder
+    Malformed {
+        message: String,
+    },
+    // explication failed one of its own checks
+    Internal {
+        message: String,
+    },
+}
+
+impl Error {
+    // where in the source the error happened, when we know
+    pub fn span(&self) -> Option<SourceSpan> {
+        match self {
+            Error::NoSolution { span, .. } => span.clone(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::UnfilledHole { funclet, message } => {
+                write!(f, "Unfilled hole in funclet {}: {}", funclet, message)
+            }
+            Error::NoSolution { funclet, hole, .. } => {
+                write!(f, "No explication solution found for funclet {}", funclet)?;
+                match hole {
+                    Some(hole) => write!(f, ", no way to fill {}", hole),
+                    None => Ok(()),
+                }
+            }
+            Error::Unsupported { message } => {
+                write!(f, "Explication does not support this program: {}", message)
+            }
+            Error::Malformed { message } => write!(f, "Malformed schedule: {}", message),
+            Error::Internal { message } => write!(f, "Explication failed: {}", message),
+        }
+    }
+}
@@ -55,11 +55,14 @@ macro_rules! map_refs {
         $arg.clone().opt().map(|x| $map(x)).into()
     };
     ($map:ident, $arg:ident : [Operation]) => {
-        $arg.as_ref().opt().map(|lst| {
-            lst.iter()
-                .map(|arg_hole| arg_hole.clone())
-                .collect()
-        }).into()
+        $arg.as_ref()
+            .opt()
+            .map(|lst| {
+                lst.iter()
+                    .map(|arg_hole| arg_hole.clone().opt().map(|x| $map(x)).into())
+                    .collect()
+            })
+            .into()
     };
     ($_map:ident, $arg:ident : $_arg_type:tt) => {
         $arg.clone()
@@ -103,17 +106,18 @@ with_operations!(make_nodes);
 
 pub type Quotient = crate::ir::Quotient;
 pub type Flow = crate::ir::Flow;
-#[derive(
-    Serialize, Deserialize, Debug, Clone, PartialEq, Eq
-)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct Tag {
-    pub quot: Quotient, // What a given value maps to in a specification
-    pub flow: Hole<Flow>,     // How this value transforms relative to the specification
+    pub quot: Quotient,   // What a given value maps to in a specification
+    pub flow: Hole<Flow>, // How this value transforms relative to the specification
 }
 
 impl Default for Tag {
     fn default() -> Self {
-        Self { quot: Default::default(), flow: Hole::Filled(crate::ir::Flow::Usable) }
+        Self {
+            quot: Default::default(),
+            flow: Hole::Filled(crate::ir::Flow::Usable),
+        }
     }
 }
 
@@ -159,6 +163,16 @@ pub enum TailEdge {
         yielded_nodes: Hole<Box<[Hole<NodeId>]>>,
         continuation_join: Hole<NodeId>,
     },
+
+    // Split space - where the computation will be observed
+    DynamicAllocFromBuffer {
+        buffer: Hole<NodeId>,
+        arguments: Hole<Box<[Hole<NodeId>]>>,
+        dynamic_allocation_size_slots: Hole<Box<[Hole<Option<NodeId>>]>>,
+        success_funclet_id: Hole<FuncletId>,
+        failure_funclet_id: Hole<FuncletId>,
+        continuation_join: Hole<NodeId>,
+    },
     // Here for now as a type system debugging tool
     // Always passes type checking, but fails codegen
     DebugHole {
@@ -169,6 +183,110 @@ pub enum TailEdge {
     },
 }
 
+fn map_hole(node: &Hole<NodeId>, map: &mut impl FnMut(NodeId) -> NodeId) -> Hole<NodeId> {
+    node.clone().opt().map(|x| map(x)).into()
+}
+
+fn map_holes(
+    nodes: &Hole<Box<[Hole<NodeId>]>>,
+    map: &mut impl FnMut(NodeId) -> NodeId,
+) -> Hole<Box<[Hole<NodeId>]>> {
+    nodes
+        .as_ref()
+        .opt()
+        .map(|nodes| nodes.iter().map(|node| map_hole(node, map)).collect())
+        .into()
+}
+
+impl TailEdge {
+    pub fn map_referenced_nodes(&self, mut map: impl FnMut(NodeId) -> NodeId) -> Self {
+        let map = &mut map;
+        match self {
+            TailEdge::Return { return_values } => TailEdge::Return {
+                return_values: map_holes(return_values, map),
+            },
+            TailEdge::Jump { join, arguments } => TailEdge::Jump {
+                join: map_hole(join, map),
+                arguments: map_holes(arguments, map),
+            },
+            TailEdge::ScheduleCall {
+                value_operation,
+                timeline_operation,
+                spatial_operation,
+                callee_funclet_id,
+                callee_arguments,
+                continuation_join,
+            } => TailEdge::ScheduleCall {
+                value_operation: value_operation.clone(),
+                timeline_operation: timeline_operation.clone(),
+                spatial_operation: spatial_operation.clone(),
+                callee_funclet_id: callee_funclet_id.clone(),
+                callee_arguments: map_holes(callee_arguments, map),
+                continuation_join: map_hole(continuation_join, map),
+            },
+            TailEdge::ScheduleSelect {
+                value_operation,
+                timeline_operation,
+                spatial_operation,
+                condition,
+                callee_funclet_ids,
+                callee_arguments,
+                continuation_join,
+            } => TailEdge::ScheduleSelect {
+                value_operation: value_operation.clone(),
+                timeline_operation: timeline_operation.clone(),
+                spatial_operation: spatial_operation.clone(),
+                condition: map_hole(condition, map),
+                callee_funclet_ids: callee_funclet_ids.clone(),
+                callee_arguments: map_holes(callee_arguments, map),
+                continuation_join: map_hole(continuation_join, map),
+            },
+            TailEdge::ScheduleCallYield {
+                value_operation,
+                timeline_operation,
+                spatial_operation,
+                external_function_id,
+                yielded_nodes,
+                continuation_join,
+            } => TailEdge::ScheduleCallYield {
+                value_operation: value_operation.clone(),
+                timeline_operation: timeline_operation.clone(),
+                spatial_operation: spatial_operation.clone(),
+                external_function_id: external_function_id.clone(),
+                yielded_nodes: map_holes(yielded_nodes, map),
+                continuation_join: map_hole(continuation_join, map),
+            },
+            TailEdge::DynamicAllocFromBuffer {
+                buffer,
+                arguments,
+                dynamic_allocation_size_slots,
+                success_funclet_id,
+                failure_funclet_id,
+                continuation_join,
+            } => TailEdge::DynamicAllocFromBuffer {
+                buffer: map_hole(buffer, map),
+                arguments: map_holes(arguments, map),
+                dynamic_allocation_size_slots: dynamic_allocation_size_slots
+                    .as_ref()
+                    .opt()
+                    .map(|slots| {
+                        slots
+                            .iter()
+                            .map(|slot| slot.clone().opt().map(|x| x.map(|x| map(x))).into())
+                            .collect()
+                    })
+                    .into(),
+                success_funclet_id: success_funclet_id.clone(),
+                failure_funclet_id: failure_funclet_id.clone(),
+                continuation_join: map_hole(continuation_join, map),
+            },
+            TailEdge::DebugHole { inputs } => TailEdge::DebugHole {
+                inputs: inputs.iter().map(|x| map(*x)).collect(),
+            },
+        }
+    }
+}
+
 pub type FuncletKind = ir::FuncletKind;
 
 // TODO: macro
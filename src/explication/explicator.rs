@@ -5,6 +5,7 @@ use priority_queue::PriorityQueue;
 use std::collections::HashMap;
 
 use crate::explication::context::{InState, OperationOutState, StaticContext, StorageOutState};
+use crate::explication::error::Error;
 use crate::explication::expir;
 use crate::explication::expir::{FuncletId, NodeId};
 use crate::explication::explicator_macros;
@@ -18,47 +19,87 @@ use crate::{explication, frontend, ir};
 use super::expir::Funclet;
 use super::explicator_macros::force_lower_node;
 
-fn explicate_tag(tag: expir::Tag, context: &StaticContext) -> ir::Tag {
-    let error = format!("Unimplemented flow hole in with quotient {:?}", &tag);
-    ir::Tag {
-        quot: tag.quot,
-        flow: tag.flow.opt().expect(&error),
+fn unfilled_hole(funclet: &FuncletId, message: String, context: &StaticContext) -> Error {
+    Error::UnfilledHole {
+        funclet: context.debug_info.funclet(funclet),
+        message,
+    }
+}
+
+// the error for a funclet we found no filling for, pointing at the hole we got stuck on
+fn no_solution(funclet: &FuncletId, context: &StaticContext) -> Error {
+    if let Some(error) = context.take_failure() {
+        return error;
+    }
+    let dead_end = context.take_dead_end();
+    let span = match &dead_end {
+        Some(dead_end) => context
+            .debug_info
+            .node_span(&dead_end.funclet_id, dead_end.node_id),
+        None => context.debug_info.funclet_span(funclet),
+    };
+    Error::NoSolution {
+        funclet: context.debug_info.funclet(funclet),
+        hole: dead_end.map(|dead_end| dead_end.message),
+        span,
     }
 }
 
+fn explicate_tag(
+    funclet: &FuncletId,
+    tag: expir::Tag,
+    context: &StaticContext,
+) -> Result<ir::Tag, Error> {
+    let flow = match tag.flow.opt() {
+        Some(flow) => flow,
+        None => {
+            let message = format!("Unimplemented flow hole with quotient {:?}", &tag.quot);
+            return Err(unfilled_hole(funclet, message, context));
+        }
+    };
+    Ok(ir::Tag {
+        quot: tag.quot,
+        flow,
+    })
+}
+
 fn explicate_funclet_spec(
+    funclet: &FuncletId,
     spec: &expir::FuncletSpec,
     state: &StorageOutState,
     context: &StaticContext,
-) -> ir::FuncletSpec {
-    let error = format!("Unimplemented Hole in specification {:?}", spec);
-    ir::FuncletSpec {
+) -> Result<ir::FuncletSpec, Error> {
+    let implicit_tag = |tag: &Hole<expir::Tag>| match tag.clone().opt() {
+        Some(tag) => explicate_tag(funclet, tag, context),
+        None => {
+            let message = format!("Unimplemented Hole in specification {:?}", spec);
+            Err(unfilled_hole(funclet, message, context))
+        }
+    };
+    Ok(ir::FuncletSpec {
         funclet_id_opt: spec.funclet_id_opt,
         input_tags: spec
             .input_tags
             .iter()
-            .map(|t| explicate_tag(t.clone().opt().unwrap_or_default(), context))
-            .collect(),
+            .map(|t| explicate_tag(funclet, t.clone().opt().unwrap_or_default(), context))
+            .collect::<Result<_, _>>()?,
         output_tags: spec
             .output_tags
             .iter()
-            .map(|t| explicate_tag(t.clone().opt().unwrap_or_default(), context))
-            .collect(),
-        implicit_in_tag: explicate_tag(spec.implicit_in_tag.clone().opt().expect(&error), context),
-        implicit_out_tag: explicate_tag(
-            spec.implicit_out_tag.clone().opt().expect(&error),
-            context,
-        ),
-    }
+            .map(|t| explicate_tag(funclet, t.clone().opt().unwrap_or_default(), context))
+            .collect::<Result<_, _>>()?,
+        implicit_in_tag: implicit_tag(&spec.implicit_in_tag)?,
+        implicit_out_tag: implicit_tag(&spec.implicit_out_tag)?,
+    })
 }
 
 fn explicate_spec_binding(
     funclet: &FuncletId,
     state: Option<&StorageOutState>,
     context: &StaticContext,
-) -> ir::FuncletSpecBinding {
+) -> Result<ir::FuncletSpecBinding, Error> {
     let current = context.get_funclet(&funclet);
-    match &current.spec_binding {
+    Ok(match &current.spec_binding {
         expir::FuncletSpecBinding::None => ir::FuncletSpecBinding::None,
         expir::FuncletSpecBinding::Value {
             value_function_id_opt,
@@ -75,11 +116,11 @@ fn explicate_spec_binding(
             spatial,
             timeline,
         } => ir::FuncletSpecBinding::ScheduleExplicit {
-            value: explicate_funclet_spec(value, state.unwrap(), context),
-            spatial: explicate_funclet_spec(spatial, state.unwrap(), context),
-            timeline: explicate_funclet_spec(timeline, state.unwrap(), context),
+            value: explicate_funclet_spec(funclet, value, state.unwrap(), context)?,
+            spatial: explicate_funclet_spec(funclet, spatial, state.unwrap(), context)?,
+            timeline: explicate_funclet_spec(funclet, timeline, state.unwrap(), context)?,
         },
-    }
+    })
 }
 
 /*
@@ -87,20 +128,20 @@ fn explicate_spec_binding(
  *   we also fill in specific operations that are purely type-directed
  *   we do not attempt to actually put data in storage
  * Returns the updated funclet first, then any new funclets to add second
+ * Skips over the given number of fillings, so repeated calls enumerate them in order
  */
 pub fn explicate_schedule_funclet_operation(
     funclet_id: FuncletId,
+    solutions_to_skip: usize,
     context: &StaticContext,
-) -> (expir::Funclet, Vec<expir::Funclet>) {
+) -> Result<(expir::Funclet, Vec<expir::Funclet>), Error> {
+    context.reset_search(solutions_to_skip);
     let mut state = InState::new_operation(funclet_id, context);
     state.next_node();
     let funclet = context.get_funclet(&funclet_id);
     match operation_explicator::explicate_node(state, context) {
-        None => panic!(
-            "No explication solution found for funclet {:?}",
-            context.debug_info.funclet(&funclet_id)
-        ),
-        Some(mut result) => (
+        None => Err(no_solution(&funclet_id, context)),
+        Some(mut result) => Ok((
             expir::Funclet {
                 kind: funclet.kind.clone(),
                 spec_binding: funclet.spec_binding.clone(),
@@ -110,37 +151,44 @@ pub fn explicate_schedule_funclet_operation(
                 nodes: result.drain_nodes().into_boxed_slice(),
             },
             vec![],
-        ),
+        )),
     }
 }
 
 /*
  * The second pass of explication, where we assume we have the operations we need
  *   and now we need to actually put the stuff in the correct storage at the right time
+ * Skips over fillings the same way as the operation pass
  */
 pub fn explicate_schedule_funclet_storage(
     funclet_id: FuncletId,
+    solutions_to_skip: usize,
     context: &StaticContext,
-) -> ir::Funclet {
+) -> Result<ir::Funclet, Error> {
+    context.reset_search(solutions_to_skip);
     let mut state = InState::new_storage(funclet_id, context);
     state.next_node();
     let funclet = context.get_funclet(&funclet_id);
     match storage_explicator::explicate_node(state, context) {
-        None => panic!(
-            "No explication solution found for funclet {:?}",
-            context.debug_info.funclet(&funclet_id)
-        ),
+        None => Err(no_solution(&funclet_id, context)),
         Some(mut result) => {
-            assert!(result.is_to_fill_empty());
-            let spec_binding = explicate_spec_binding(&funclet_id, Some(&result), context);
-            ir::Funclet {
+            if !result.is_to_fill_empty() {
+                return Err(Error::Internal {
+                    message: format!(
+                        "Allocations of {} were left unfilled",
+                        context.debug_info.funclet(&funclet_id)
+                    ),
+                });
+            }
+            let spec_binding = explicate_spec_binding(&funclet_id, Some(&result), context)?;
+            Ok(ir::Funclet {
                 kind: funclet.kind.clone(),
                 spec_binding,
                 input_types: funclet.input_types.clone(),
                 output_types: funclet.output_types.clone(),
                 tail_edge: result.expect_tail_edge(),
                 nodes: result.drain_nodes().into_boxed_slice(),
-            }
+            })
         }
     }
 }
@@ -149,73 +197,83 @@ pub fn explicate_schedule_funclet_storage(
  * Forcibly lowers a tail edge, specifically used for spec functions
  * The funclet id is passed in rather than the tail edge for error context
  */
-fn lower_spec_tail_edge(funclet: &FuncletId, context: &StaticContext) -> ir::TailEdge {
+fn lower_spec_tail_edge(
+    funclet: &FuncletId,
+    context: &StaticContext,
+) -> Result<ir::TailEdge, Error> {
     let debug_funclet = context.debug_info.funclet(funclet);
-    let tail_edge = context
-        .get_funclet(funclet)
-        .tail_edge
-        .as_ref()
-        .opt()
-        .expect(&format!("Missing tail edge for funclet {}", &debug_funclet));
-    let error = format!(
-        "Tail edge {:?} is part of the spec funclet {} and cannot have holes in it",
-        tail_edge, debug_funclet
-    );
+    let tail_edge = match context.get_funclet(funclet).tail_edge.as_ref().opt() {
+        Some(tail_edge) => tail_edge,
+        None => {
+            let message = format!("Missing tail edge for funclet {}", &debug_funclet);
+            return Err(unfilled_hole(funclet, message, context));
+        }
+    };
+    let error = || {
+        let message = format!(
+            "Tail edge {:?} is part of the spec funclet {} and cannot have holes in it",
+            tail_edge, debug_funclet
+        );
+        unfilled_hole(funclet, message, context)
+    };
+    let filled = |values: &Hole<Box<[Hole<NodeId>]>>| {
+        values
+            .as_ref()
+            .opt()
+            .ok_or_else(error)?
+            .iter()
+            .map(|v| v.clone().opt().ok_or_else(error))
+            .collect::<Result<_, _>>()
+    };
     match tail_edge {
-        expir::TailEdge::Return { return_values } => ir::TailEdge::Return {
-            return_values: return_values
-                .as_ref()
-                .opt()
-                .expect(&error)
-                .iter()
-                .map(|v| v.clone().opt().expect(&error))
-                .collect(),
-        },
-        expir::TailEdge::Jump { join, arguments } => ir::TailEdge::Jump {
-            join: join.as_ref().opt().expect(&error).clone(),
-            arguments: arguments
-                .as_ref()
-                .opt()
-                .expect(&error)
-                .iter()
-                .map(|v| v.clone().opt().expect(&error))
-                .collect(),
-        },
-        expir::TailEdge::DebugHole { inputs } => ir::TailEdge::DebugHole {
+        expir::TailEdge::Return { return_values } => Ok(ir::TailEdge::Return {
+            return_values: filled(return_values)?,
+        }),
+        expir::TailEdge::Jump { join, arguments } => Ok(ir::TailEdge::Jump {
+            join: join.as_ref().opt().ok_or_else(error)?.clone(),
+            arguments: filled(arguments)?,
+        }),
+        expir::TailEdge::DebugHole { inputs } => Ok(ir::TailEdge::DebugHole {
             inputs: inputs.clone(),
-        },
-        edge => {
-            panic!(
+        }),
+        edge => Err(Error::Unsupported {
+            message: format!(
                 "Spec funclet {} has disallowed tail edge {:?}",
                 debug_funclet, edge
-            )
-        }
+            ),
+        }),
     }
 }
 
-pub fn lower_spec_funclet(funclet: &FuncletId, context: &StaticContext) -> ir::Funclet {
+pub fn lower_spec_funclet(
+    funclet: &FuncletId,
+    context: &StaticContext,
+) -> Result<ir::Funclet, Error> {
     let func = context.get_funclet(&funclet);
     let kind = func.kind.clone();
-    let spec_binding = explicate_spec_binding(funclet, None, context);
+    let spec_binding = explicate_spec_binding(funclet, None, context)?;
     let input_types = func.input_types.clone();
     let output_types = func.output_types.clone();
     let debug_funclet = &context.debug_info.funclet(&funclet);
-    let error = format!("Cannot have a hole in spec funclet {:?}", debug_funclet);
     let nodes = func
         .nodes
         .iter()
-        .map(|n| {
-            explicator_macros::force_lower_node(&n.as_ref().opt().expect(&error), &debug_funclet)
+        .map(|n| match n.as_ref().opt() {
+            Some(node) => Ok(explicator_macros::force_lower_node(&node, &debug_funclet)),
+            None => {
+                let message = format!("Cannot have a hole in spec funclet {:?}", debug_funclet);
+                Err(unfilled_hole(funclet, message, context))
+            }
         })
-        .collect();
-    let tail_edge = lower_spec_tail_edge(&funclet, context);
+        .collect::<Result<_, _>>()?;
+    let tail_edge = lower_spec_tail_edge(&funclet, context)?;
 
-    ir::Funclet {
+    Ok(ir::Funclet {
         kind,
         spec_binding,
         input_types,
         output_types,
         nodes,
         tail_edge,
-    }
+    })
 }
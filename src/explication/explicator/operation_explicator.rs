@@ -1,6 +1,7 @@
 use ron::value;
 
 use crate::explication::context::{staticcontext, InState, OperationOutState, StaticContext};
+use crate::explication::cost;
 use crate::explication::expir;
 use crate::explication::expir::{FuncletId, NodeId};
 use crate::explication::explicator_macros;
@@ -52,15 +53,19 @@ fn explicate_phi_node(
     }
 }
 
+// builds the nodes that do an operation with the given implementation, which runs on the given
+//   place, or none if the implementation can't be run from this node
 fn build_do_operation<T>(
     operation: &Hole<expir::Quotient>,
     external_function_id: Option<&Hole<expir::ExternalFunctionId>>,
+    // where the work of the operation is placed, which decides what implementations can run
+    place: Place,
     node_builder: T,
     state: InState,
     context: &StaticContext,
 ) -> Option<OperationOutState>
 where
-    T: Fn(expir::Quotient, Option<expir::ExternalFunctionId>) -> expir::Node,
+    T: Fn(expir::Quotient, Option<expir::ExternalFunctionId>, Place) -> Option<Vec<expir::Node>>,
 {
     fn read_external_id<'a>(
         function_class_id: &Hole<usize>,
@@ -124,73 +129,78 @@ where
     };
 
     for operation_to_try in operations_to_try {
-        let mut new_state = state.clone();
         let location = Location {
             funclet_id: value_funclet_id,
             quot: operation_to_try.clone(),
         };
         let base_node_id = location.node_id(context).unwrap();
 
-        let external_id = match external_function_id {
-            None => None,
-            Some(Hole::Filled(id)) => Some(id.clone()),
-            Some(Hole::Empty) => {
-                match context.get_node(Location {
-                    funclet_id: value_funclet_id,
-                    quot: operation_to_try.clone(),
-                }) {
-                    expir::Node::CallFunctionClass {
-                        function_id,
-                        arguments,
-                    } => {
-                        Some(
-                            read_external_id(function_id, &value_funclet_id, context)
-                                .external_function_ids
-                                .first()
-                                .expect(&format!(
-                                    "No external function found to implement {}",
-                                    context
-                                        .debug_info
-                                        // now safe to unwrap wrt debugging
-                                        .external_function(function_id.as_ref().opt().unwrap())
-                                ))
-                                .clone(),
-                        )
-                    }
-                    _ => unreachable!(),
-                }
-            }
+        // an external hole could be filled by any implementation of the function class
+        // we try the ones the cost model expects to be cheapest first, wherever they run
+        let external_ids_to_try = match external_function_id {
+            None => vec![(None, place)],
+            Some(Hole::Filled(id)) => vec![(Some(id.clone()), place)],
+            Some(Hole::Empty) => match context.get_node(location.clone()) {
+                expir::Node::CallFunctionClass {
+                    function_id,
+                    arguments,
+                } => cost::rank_implementations(
+                    read_external_id(function_id, &value_funclet_id, context)
+                        .external_function_ids
+                        .iter()
+                        .cloned(),
+                    place.clone(),
+                    &context.program.native_interface,
+                    context.cost_model,
+                )
+                .drain(..)
+                .map(|(id, runs_on, _)| (Some(id), runs_on))
+                .collect(),
+                _ => unreachable!(),
+            },
         };
-        match external_id {
-            None => {}
-            Some(index) => {
-                for offset in 0..context
-                    .program
-                    .native_interface
-                    .external_functions
-                    .get_expect(index.0)
-                    .get_output_types()
-                    .unwrap()
-                    .len()
-                {
-                    let offset_location =
-                        Location::new(value_funclet_id, base_node_id + offset + 1);
-                    new_state.add_value_operation(offset_location, context);
+
+        for (external_id, runs_on) in external_ids_to_try {
+            let nodes = match node_builder(operation_to_try.clone(), external_id.clone(), runs_on) {
+                Some(nodes) => nodes,
+                None => continue,
+            };
+            let node_id = state.get_current_node_id().unwrap();
+            let mut new_state = state.clone();
+            match &external_id {
+                None => {}
+                Some(index) => {
+                    for offset in 0..context
+                        .program
+                        .native_interface
+                        .external_functions
+                        .get_expect(index.0)
+                        .get_output_types()
+                        .unwrap()
+                        .len()
+                    {
+                        let offset_location =
+                            Location::new(value_funclet_id, base_node_id + offset + 1);
+                        new_state.add_value_operation(offset_location, context);
+                    }
                 }
             }
-        }
 
-        new_state.add_value_operation(location, context);
-        let node = node_builder(operation_to_try, external_id);
-        new_state.next_node();
-        match explicate_node(new_state, context) {
-            None => {}
-            Some(mut out) => {
-                out.add_node(node);
-                return Some(out);
+            new_state.add_value_operation(location.clone(), context);
+            new_state.next_node();
+            match explicate_node(new_state, context) {
+                None => {}
+                Some(mut out) => {
+                    out.add_nodes_in_place_of(node_id, nodes);
+                    return Some(out);
+                }
             }
         }
     }
+    context.record_dead_end(
+        &state,
+        format!("the operation {}", state.current_node_error(context)),
+    );
     None
 }
 
@@ -204,13 +214,14 @@ fn explicate_local_do_builtin(
     build_do_operation(
         operation,
         None,
-        |operation_to_try, external| {
+        Place::Local,
+        |operation_to_try, external, _| {
             assert!(external.is_none());
-            expir::Node::LocalDoBuiltin {
+            Some(vec![expir::Node::LocalDoBuiltin {
                 operation: Hole::Filled(operation_to_try),
                 inputs: inputs.clone(),
                 outputs: outputs.clone(),
-            }
+            }])
         },
         state,
         context,
@@ -228,11 +239,14 @@ fn explicate_local_do_external(
     build_do_operation(
         operation,
         Some(external_function_id),
-        |operation_to_try, external_to_try| expir::Node::LocalDoExternal {
-            operation: Hole::Filled(operation_to_try),
-            external_function_id: Hole::Filled(external_to_try.unwrap()),
-            inputs: inputs.clone(),
-            outputs: outputs.clone(),
+        Place::Local,
+        |operation_to_try, external_to_try, _| {
+            Some(vec![expir::Node::LocalDoExternal {
+                operation: Hole::Filled(operation_to_try),
+                external_function_id: Hole::Filled(external_to_try.unwrap()),
+                inputs: inputs.clone(),
+                outputs: outputs.clone(),
+            }])
         },
         state,
         context,
@@ -248,34 +262,338 @@ fn explicate_encode_do(
     state: InState,
     context: &StaticContext,
 ) -> Option<OperationOutState> {
+    // the encoder was begun on the place the encoded work runs
+    let place = match encoder
+        .as_ref()
+        .opt()
+        .map(|encoder| get_expect_box(&state.get_current_funclet(context).nodes, *encoder))
+    {
+        Some(Hole::Filled(expir::Node::BeginEncoding {
+            place: Hole::Filled(place),
+            ..
+        })) => place.clone(),
+        _ => Place::Gpu,
+    };
     build_do_operation(
         operation,
         Some(external_function_id),
-        |operation_to_try, external_to_try| expir::Node::EncodeDoExternal {
-            operation: Hole::Filled(operation_to_try),
-            external_function_id: Hole::Filled(external_to_try.unwrap()),
-            inputs: inputs.clone(),
-            outputs: outputs.clone(),
-            encoder: encoder.clone()
+        place,
+        |operation_to_try, external_to_try, runs_on| {
+            let external_to_try = external_to_try.unwrap();
+            if runs_on == place {
+                Some(vec![expir::Node::EncodeDoExternal {
+                    operation: Hole::Filled(operation_to_try),
+                    external_function_id: Hole::Filled(external_to_try),
+                    inputs: inputs.clone(),
+                    outputs: outputs.clone(),
+                    encoder: encoder.clone(),
+                }])
+            } else {
+                do_locally_and_encode_copies(
+                    operation_to_try,
+                    external_to_try,
+                    encoder,
+                    inputs,
+                    outputs,
+                    &state,
+                    context,
+                )
+            }
         },
-        state,
+        state.clone(),
         context,
     )
 }
 
+// Runs an encoded call locally instead, then encodes copies of its results into the outputs
+//   the call would have written
+// The inputs the encoding copied from local storage are read from there, and the call can't be
+//   run locally if any other input is only on the place of the encoding
+fn do_locally_and_encode_copies(
+    operation: expir::Quotient,
+    external_function_id: expir::ExternalFunctionId,
+    encoder: &Hole<NodeId>,
+    inputs: &Hole<Box<[Hole<NodeId>]>>,
+    outputs: &Hole<Box<[Hole<NodeId>]>>,
+    state: &InState,
+    context: &StaticContext,
+) -> Option<Vec<expir::Node>> {
+    let node_id = state.get_current_node_id().unwrap();
+    let funclet = state.get_current_funclet(context);
+    let encoder = encoder.as_ref().opt()?.clone();
+    let outputs = outputs
+        .as_ref()
+        .opt()?
+        .iter()
+        .map(|output| output.clone().opt())
+        .collect::<Option<Vec<_>>>()?;
+    let output_types = context
+        .program
+        .native_interface
+        .external_functions
+        .get_expect(external_function_id.0)
+        .get_output_types()?;
+    if output_types.len() != outputs.len() {
+        return None;
+    }
+
+    // a local input is either a value or a ref the call reads, in which case it has a type
+    let local_input = |input: NodeId| match get_expect_box(&funclet.nodes, input) {
+        Hole::Filled(expir::Node::AllocTemporary {
+            place,
+            storage_type,
+            ..
+        })
+        | Hole::Filled(expir::Node::StaticSubAlloc {
+            place,
+            storage_type,
+            ..
+        }) => match (place, storage_type) {
+            (Hole::Filled(Place::Local), Hole::Filled(storage_type)) => {
+                Some((input, Some(storage_type.clone())))
+            }
+            _ => None,
+        },
+        Hole::Filled(expir::Node::Phi {
+            index: Hole::Filled(index),
+        }) => match context.get_type(get_expect_box(&funclet.input_types, *index)) {
+            expir::Type::Ref {
+                storage_type,
+                storage_place: Place::Local,
+                ..
+            } => Some((input, Some(storage_type.clone()))),
+            expir::Type::Ref { .. } | expir::Type::Buffer { .. } => None,
+            _ => Some((input, None)),
+        },
+        Hole::Filled(expir::Node::BeginEncoding { .. }) | Hole::Empty => None,
+        Hole::Filled(_) => Some((input, None)),
+    };
+    // an input on the GPU is read from whatever was copied into it
+    let local_source = |input: NodeId| {
+        let copied_from = funclet.nodes[..node_id].iter().find_map(|node| match node {
+            Hole::Filled(expir::Node::EncodeCopy {
+                input: Hole::Filled(source),
+                output: Hole::Filled(destination),
+                ..
+            }) if *destination == input => Some(*source),
+            _ => None,
+        });
+        local_input(copied_from.unwrap_or(input))
+    };
+    let sources = match inputs {
+        Hole::Empty => None,
+        Hole::Filled(inputs) => Some(
+            inputs
+                .iter()
+                .map(|input| match input {
+                    Hole::Empty => Some(None),
+                    Hole::Filled(input) => local_source(*input).map(Some),
+                })
+                .collect::<Option<Vec<_>>>()?,
+        ),
+    };
+
+    // refs are read first, then the temporaries are allocated, then the call writes them
+    let mut nodes = Vec::new();
+    let local_inputs = sources.map(|sources| {
+        sources
+            .into_iter()
+            .map(|source| match source {
+                None => Hole::Empty,
+                Some((value, None)) => Hole::Filled(value),
+                Some((reference, Some(storage_type))) => {
+                    nodes.push(expir::Node::ReadRef {
+                        storage_type: Hole::Filled(storage_type),
+                        source: Hole::Filled(reference),
+                    });
+                    Hole::Filled(node_id + nodes.len() - 1)
+                }
+            })
+            .collect::<Box<[_]>>()
+    });
+    let temporary_ids: Vec<NodeId> = (0..outputs.len())
+        .map(|index| node_id + nodes.len() + index)
+        .collect();
+    nodes.extend(
+        output_types
+            .iter()
+            .map(|storage_type| expir::Node::AllocTemporary {
+                place: Hole::Filled(Place::Local),
+                storage_type: Hole::Filled(storage_type.clone()),
+                buffer_flags: Hole::Filled(ir::BufferFlags {
+                    map_read: true,
+                    map_write: true,
+                    copy_src: true,
+                    copy_dst: true,
+                    ..ir::BufferFlags::new()
+                }),
+            }),
+    );
+    nodes.push(expir::Node::LocalDoExternal {
+        operation: Hole::Filled(operation),
+        external_function_id: Hole::Filled(external_function_id),
+        inputs: local_inputs.map_or(Hole::Empty, Hole::Filled),
+        outputs: Hole::Filled(temporary_ids.iter().map(|id| Hole::Filled(*id)).collect()),
+    });
+    nodes.extend(
+        temporary_ids
+            .iter()
+            .zip(outputs.iter())
+            .map(|(temporary, output)| expir::Node::EncodeCopy {
+                encoder: Hole::Filled(encoder),
+                input: Hole::Filled(*temporary),
+                output: Hole::Filled(*output),
+            }),
+    );
+    Some(nodes)
+}
+
+// Fills a ??? hole with the value operations that can be done locally from this point
+// `nodes` are the ones the hole has been filled with so far, starting at the id of the hole
+// Operations are done in the order `find_satisfied_operations` gives them, and we try doing
+//   each next one before leaving the rest to the nodes after the hole, so the search tries
+//   one filling per number of operations rather than every order of them
+fn explicate_empty_node(
+    nodes: Vec<expir::Node>,
+    state: InState,
+    context: &StaticContext,
+) -> Option<OperationOutState> {
+    let node_id = state.get_current_node_id().unwrap();
+    let value_funclet_id = state
+        .get_funclet_spec(
+            state.get_current_funclet_id(),
+            &SpecLanguage::Value,
+            context,
+        )
+        .funclet_id_opt
+        .unwrap();
+
+    let next_operation = state
+        .find_satisfied_operations(&value_funclet_id, context)
+        .into_iter()
+        .find_map(|operation| {
+            let location = Location {
+                funclet_id: value_funclet_id,
+                quot: operation.clone(),
+            };
+            // the implementation to call, if any, and the storage types of the results
+            match context.get_node(location.clone()) {
+                expir::Node::Constant { .. } => {
+                    let types = &context
+                        .get_node_type_information(
+                            &value_funclet_id,
+                            &location.node_id(context).unwrap(),
+                        )
+                        .output_types;
+                    let storage_types = types
+                        .iter()
+                        .map(|typ| state.expect_native_storage_type(typ, context))
+                        .collect::<Vec<_>>();
+                    Some((location, None, storage_types))
+                }
+                expir::Node::CallFunctionClass { function_id, .. } => {
+                    let function_class = context.program.function_classes.get_expect(
+                        function_id
+                            .as_ref()
+                            .opt()
+                            .expect(&format!(
+                                "Hole found in {}",
+                                context.debug_info.funclet(&value_funclet_id)
+                            ))
+                            .clone(),
+                    );
+                    let (id, _, _) = cost::rank_implementations(
+                        function_class.external_function_ids.iter().cloned(),
+                        Place::Local,
+                        &context.program.native_interface,
+                        context.cost_model,
+                    )
+                    .into_iter()
+                    .find(|(_, runs_on, _)| *runs_on == Place::Local)?;
+                    let storage_types = context
+                        .program
+                        .native_interface
+                        .external_functions
+                        .get_expect(id.0)
+                        .get_output_types()?
+                        .to_vec();
+                    Some((location, Some(id), storage_types))
+                }
+                _ => None,
+            }
+        });
+
+    if let Some((location, external_id, storage_types)) = next_operation {
+        let mut new_state = state.clone();
+        let base_node_id = location.node_id(context).unwrap();
+        if external_id.is_some() {
+            for offset in 0..storage_types.len() {
+                let offset_location = Location::new(value_funclet_id, base_node_id + offset + 1);
+                new_state.add_value_operation(offset_location, context);
+            }
+        }
+        new_state.add_value_operation(location.clone(), context);
+
+        // the results are written to temporaries, then read back out as values
+        let mut new_nodes = nodes.clone();
+        let first_temporary = node_id + new_nodes.len();
+        let temporary_ids: Vec<NodeId> =
+            (first_temporary..first_temporary + storage_types.len()).collect();
+        new_nodes.extend(
+            storage_types
+                .iter()
+                .map(|storage_type| expir::Node::AllocTemporary {
+                    place: Hole::Filled(Place::Local),
+                    storage_type: Hole::Filled(storage_type.clone()),
+                    buffer_flags: Hole::Filled(ir::BufferFlags {
+                        map_read: true,
+                        map_write: true,
+                        copy_src: true,
+                        copy_dst: true,
+                        ..ir::BufferFlags::new()
+                    }),
+                }),
+        );
+        let outputs = Hole::Filled(temporary_ids.iter().map(|id| Hole::Filled(*id)).collect());
+        new_nodes.push(match external_id {
+            None => expir::Node::LocalDoBuiltin {
+                operation: Hole::Filled(location.quot.clone()),
+                inputs: Hole::Filled(Box::new([])),
+                outputs,
+            },
+            Some(id) => expir::Node::LocalDoExternal {
+                operation: Hole::Filled(location.quot.clone()),
+                external_function_id: Hole::Filled(id),
+                inputs: Hole::Empty,
+                outputs,
+            },
+        });
+        new_nodes.extend(temporary_ids.iter().zip(storage_types.iter()).map(
+            |(temporary, storage_type)| expir::Node::ReadRef {
+                storage_type: Hole::Filled(storage_type.clone()),
+                source: Hole::Filled(*temporary),
+            },
+        ));
+
+        if let Some(out) = explicate_empty_node(new_nodes, new_state, context) {
+            return Some(out);
+        }
+    }
+
+    let mut new_state = state;
+    new_state.next_node();
+    let mut out = explicate_node(new_state, context)?;
+    out.add_nodes_in_place_of(node_id, nodes);
+    Some(out)
+}
+
 pub fn explicate_node(state: InState, context: &StaticContext) -> Option<OperationOutState> {
-    let debug_funclet = context.debug_info.funclet(&state.get_current_funclet_id());
     if state.is_end_of_funclet(context) {
         explicate_tail_edge(&state, context)
     } else {
         let current_node = state.get_current_node(context);
         match current_node {
-            Hole::Empty => {
-                let mut new_state = state.clone();
-                new_state.add_explication_hole();
-                explicate_node(new_state, context);
-                todo!()
-            }
+            Hole::Empty => explicate_empty_node(Vec::new(), state, context),
             Hole::Filled(expir::Node::Phi { index }) => explicate_phi_node(
                 index
                     .as_ref()
@@ -345,10 +663,16 @@ pub fn explicate_tail_edge(state: &InState, context: &StaticContext) -> Option<O
             let error = format!("Unimplemented hole in tail edge {:?}", tail_edge);
             let mut result = OperationOutState::new();
             result.set_tail_edge(tail_edge.clone());
-            Some(result)
+            Some(result).filter(|_| context.accept_solution())
         }
+        // the only tail edge we can fill without being told where to go is a return
+        //   the storage pass then finds the values to return
         Hole::Empty => {
-            todo!()
+            let mut result = OperationOutState::new();
+            result.set_tail_edge(expir::TailEdge::Return {
+                return_values: Hole::Empty,
+            });
+            Some(result).filter(|_| context.accept_solution())
         }
     }
 }
@@ -17,22 +17,45 @@ use crate::rust_wgpu_backend::ffi;
 use super::force_lower_node;
 use super::operation_explicator;
 
+// the contents of a hole the operation pass should have filled
+// an empty one ends the search, which reports it as an unfilled hole of the current funclet
+fn expect_filled<'a, T>(
+    hole: &'a Hole<T>,
+    error: &str,
+    state: &InState,
+    context: &StaticContext,
+) -> Option<&'a T> {
+    let filled = hole.as_ref().opt();
+    if filled.is_none() {
+        context.record_unfilled_hole(state.get_current_funclet_id(), error.to_string());
+    }
+    filled
+}
+
+// like `expect_filled`, for a list of holes that must all be filled as well
+fn expect_all_filled<T: Clone>(
+    holes: &Hole<Box<[Hole<T>]>>,
+    error: &str,
+    state: &InState,
+    context: &StaticContext,
+) -> Option<Box<[T]>> {
+    expect_filled(holes, error, state, context)?
+        .iter()
+        .map(|hole| expect_filled(hole, error, state, context).cloned())
+        .collect()
+}
+
+// ends the search over a node that doesn't fit the rest of the schedule
+fn malformed<T>(message: String, context: &StaticContext) -> Option<T> {
+    context.record_failure(explication::error::Error::Malformed { message });
+    None
+}
+
 fn explicate_phi_node(
     index: usize,
     state: InState,
     context: &StaticContext,
 ) -> Option<StorageOutState> {
-    let error = format!(
-        "TODO Hole in node {}",
-        context.debug_info.node_expir(
-            state.get_current_funclet_id(),
-            state
-                .get_current_node(context)
-                .as_ref()
-                .opt()
-                .expect("Unreachable")
-        )
-    );
     let mut new_state = state.clone();
     let funclet_id = state.get_current_funclet_id();
     let node_id = state.get_current_node_id().unwrap();
@@ -69,18 +92,8 @@ fn explicate_allocate_temporary(
     mut state: InState,
     context: &StaticContext,
 ) -> Option<StorageOutState> {
-    let error = format!(
-        "TODO Hole in node {}",
-        context.debug_info.node_expir(
-            state.get_current_funclet_id(),
-            state
-                .get_current_node(context)
-                .as_ref()
-                .opt()
-                .expect("Unreachable")
-        )
-    );
-    let buffer_flags = expir_buffer_flags.as_ref().opt().expect(&error).clone();
+    let error = state.hole_error(context);
+    let buffer_flags = expect_filled(expir_buffer_flags, &error, &state, context)?.clone();
     match (expir_place, expir_storage_type) {
         (Hole::Filled(storage_place), Hole::Filled(storage_type)) => {
             state.add_storage_node(
@@ -142,6 +155,57 @@ fn explicate_allocate_temporary(
     }
 }
 
+// Suballocations are references into a buffer given to the funclet
+// they share the buffer's flags, since they live in the same allocation
+fn explicate_static_sub_alloc(
+    expir_node: &Hole<NodeId>,
+    expir_place: &Hole<expir::Place>,
+    expir_storage_type: &Hole<ffi::TypeId>,
+    mut state: InState,
+    context: &StaticContext,
+) -> Option<StorageOutState> {
+    let error = state.hole_error(context);
+    let buffer_node = expect_filled(expir_node, &error, &state, context)?.clone();
+    let place = expect_filled(expir_place, &error, &state, context)?.clone();
+    let storage_type = expect_filled(expir_storage_type, &error, &state, context)?.clone();
+    let buffer_flags = match &state.get_node_information(&buffer_node, context).typ {
+        Hole::Filled(expir::Type::Buffer { flags, .. }) => flags.clone(),
+        _ => {
+            return malformed(
+                format!(
+                    "Suballocating from something other than a buffer {}",
+                    state.node_error(buffer_node, context)
+                ),
+                context,
+            )
+        }
+    };
+    state.add_storage_node(
+        state.get_current_node_id().unwrap(),
+        Hole::Filled(expir::Type::Ref {
+            storage_place: place.clone(),
+            storage_type: storage_type.clone(),
+            buffer_flags,
+        }),
+        context,
+    );
+
+    let node = ir::Node::StaticSubAlloc {
+        node: buffer_node,
+        place,
+        storage_type,
+    };
+
+    state.next_node();
+    match explicate_node(state, context) {
+        None => None,
+        Some(mut out) => {
+            out.add_node(node);
+            Some(out)
+        }
+    }
+}
+
 // Enumerates all the ways we can attempt to fill the "current" arguments
 // This is intended to work for both inputs and outputs
 // Note that the argument "instantiation_bounds" defines inputs vs outputs
@@ -178,9 +242,13 @@ fn enumerate_fill_attempts(
                 }
             }
             None => {
-                let target_type = expected_types
-                    .get(offset)
-                    .expect(&error(&format!("Missing argument index {}", offset)));
+                let Some(target_type) = expected_types.get(offset) else {
+                    // there's nothing to fill an argument the operation doesn't take with
+                    context.record_failure(explication::error::Error::Malformed {
+                        message: error(&format!("Missing argument index {}", offset)),
+                    });
+                    return Vec::new();
+                };
                 let attempts_to_try = match &instantiation_bounds {
                     Some(bounds) => state.find_matching_instantiations(
                         LocationTriple::new_value(bounds.get(offset).unwrap().clone()),
@@ -252,11 +320,9 @@ fn attempt_empty_allocation_fill(
             let found_storage_type = unpack_if_equal(storage_type, target_storage_type);
             match (found_place, found_storage_type) {
                 (Some(p), Some(t)) => {
-                    let b = buffer_flags
-                        .as_ref()
-                        .opt()
-                        .expect(&state.hole_error(context))
-                        .clone();
+                    let b =
+                        expect_filled(buffer_flags, &state.hole_error(context), &state, context)?
+                            .clone();
                     state.set_storage_type(
                         output_id,
                         expir::Type::Ref {
@@ -284,11 +350,8 @@ fn attempt_empty_allocation_fill(
             let found_storage_type = unpack_if_equal(storage_type, target_storage_type);
             match (found_place, found_storage_type) {
                 (Some(p), Some(t)) => {
-                    let n = node
-                        .as_ref()
-                        .opt()
-                        .expect(&state.hole_error(context))
-                        .clone();
+                    let n =
+                        expect_filled(node, &state.hole_error(context), &state, context)?.clone();
                     state.set_storage_type(
                         output_id,
                         expir::Type::Ref {
@@ -339,21 +402,20 @@ where
         .funclet_id_opt
         .unwrap();
 
-    let operation = expir_operation
-        .as_ref()
-        .opt()
-        .expect(&state.hole_error(context))
-        .clone();
+    let operation =
+        expect_filled(expir_operation, &state.hole_error(context), &state, context)?.clone();
 
     let value_node_id = match operation {
         expir::Quotient::Node { node_id } => node_id,
-        _ => panic!(
-            "Expected node operation for {}",
-            context.debug_info.node_expir(
-                state.get_current_funclet_id(),
-                state.get_current_node(context).as_ref().opt().unwrap()
+        _ => {
+            return malformed(
+                format!(
+                    "Expected node operation {}",
+                    state.current_node_error(context)
+                ),
+                context,
             )
-        ),
+        }
     };
 
     let node_location = Location::new(value_funclet_id, value_node_id);
@@ -467,6 +529,13 @@ where
         }
     }
 
+    context.record_dead_end(
+        &state,
+        format!(
+            "the inputs and outputs {}",
+            state.current_node_error(context)
+        ),
+    );
     None
 }
 
@@ -500,11 +569,13 @@ fn explicate_local_do_external(
     state: InState,
     context: &StaticContext,
 ) -> Option<StorageOutState> {
-    let external_function_id = expir_external_function_id
-        .as_ref()
-        .opt()
-        .expect(&state.hole_error(context))
-        .clone();
+    let external_function_id = expect_filled(
+        expir_external_function_id,
+        &state.hole_error(context),
+        &state,
+        context,
+    )?
+    .clone();
 
     build_do_operation(
         expir_operation,
@@ -531,23 +602,24 @@ fn explicate_encode_do(
     state: InState,
     context: &StaticContext,
 ) -> Option<StorageOutState> {
-    let external_function_id = expir_external_function_id
-        .as_ref()
-        .opt()
-        .expect(&state.hole_error(context))
-        .clone();
+    let external_function_id = expect_filled(
+        expir_external_function_id,
+        &state.hole_error(context),
+        &state,
+        context,
+    )?
+    .clone();
 
-    let encoder = expir_encoder
-        .as_ref()
-        .opt()
-        .expect(&state.hole_error(context))
-        .clone();
+    let encoder =
+        expect_filled(expir_encoder, &state.hole_error(context), &state, context)?.clone();
 
-    let external_function_id = expir_external_function_id
-        .as_ref()
-        .opt()
-        .expect(&state.hole_error(context))
-        .clone();
+    let external_function_id = expect_filled(
+        expir_external_function_id,
+        &state.hole_error(context),
+        &state,
+        context,
+    )?
+    .clone();
 
     build_do_operation(
         expir_operation,
@@ -573,48 +645,69 @@ fn explicate_write_ref(
     state: InState,
     context: &StaticContext,
 ) -> Option<StorageOutState> {
-    let error = format!(
-        "TODO Hole in node {}",
-        context.debug_info.node_expir(
-            state.get_current_funclet_id(),
-            state
-                .get_current_node(context)
-                .as_ref()
-                .opt()
-                .expect("Unreachable")
-        )
-    );
-
     let storage_types = match expir_storage_type {
         Hole::Filled(x) => vec![x.clone()],
-        Hole::Empty => todo!("{}", error),
+        Hole::Empty => (0..context.program.native_interface.types.len())
+            .map(|i| ffi::TypeId(i))
+            .collect_vec(),
     };
 
     for storage_type in storage_types.iter() {
         let sources = match expir_source {
             Hole::Filled(x) => vec![x.clone()],
-            Hole::Empty => todo!("{}", error),
+            Hole::Empty => state
+                .find_all_instantiations(
+                    &expir::Type::NativeValue {
+                        storage_type: storage_type.clone(),
+                    },
+                    context,
+                )
+                .iter()
+                .map(|loc| loc.node_id(context).unwrap())
+                .collect(),
         };
 
         for source in sources.iter() {
+            let instantiation = match state.get_node_information(&source, context).instantiation {
+                Some(ref inst) => inst.clone(),
+                None => continue,
+            };
+
             let destinations = match expir_destination {
                 Hole::Filled(x) => vec![x.clone()],
-                Hole::Empty => todo!("{}", error),
+                Hole::Empty => state
+                    .find_all_storage_nodes(
+                        &expir::Type::Ref {
+                            storage_type: storage_type.clone(),
+                            storage_place: expir::Place::Local,
+                            buffer_flags: expir::BufferFlags::new(),
+                        },
+                        context,
+                    )
+                    .iter()
+                    .map(|loc| loc.node_id(context).unwrap())
+                    .collect(),
             };
 
             for destination in destinations.iter() {
                 let mut new_state = state.clone();
-                let info = state.get_node_information(&source, context);
-                new_state.set_instantiation(
-                    destination.clone(),
-                    info.instantiation.clone().expect(&format!(
-                        "Missing instantiation for node {}",
-                        context
-                            .debug_info
-                            .node(&state.get_current_funclet_id(), source.clone())
-                    )),
-                    context,
-                );
+
+                // writing to an allocation we haven't filled yet decides what it allocates
+                let to_fill = match &state.get_node_information(destination, context).typ {
+                    Hole::Filled(_) => None,
+                    Hole::Empty => match attempt_empty_allocation_fill(
+                        destination.clone(),
+                        expir::Place::Local,
+                        storage_type.clone(),
+                        &mut new_state,
+                        context,
+                    ) {
+                        Some(node) => Some(node),
+                        None => continue,
+                    },
+                };
+
+                new_state.set_instantiation(destination.clone(), instantiation.clone(), context);
                 let node = ir::Node::WriteRef {
                     storage_type: storage_type.clone(),
                     source: source.clone(),
@@ -625,6 +718,9 @@ fn explicate_write_ref(
                     None => {}
                     Some(mut out) => {
                         out.add_node(node);
+                        if let Some(node_to_fill) = to_fill {
+                            out.add_to_fill(destination.clone(), node_to_fill);
+                        }
                         return Some(out);
                     }
                 }
@@ -632,6 +728,10 @@ fn explicate_write_ref(
         }
     }
 
+    context.record_dead_end(
+        &state,
+        format!("the write {}", state.current_node_error(context)),
+    );
     None
 }
 
@@ -641,18 +741,6 @@ fn explicate_borrow_ref(
     state: InState,
     context: &StaticContext,
 ) -> Option<StorageOutState> {
-    let error = format!(
-        "TODO Hole in node {}",
-        context.debug_info.node_expir(
-            state.get_current_funclet_id(),
-            state
-                .get_current_node(context)
-                .as_ref()
-                .opt()
-                .expect("Unreachable")
-        )
-    );
-
     let storage_types = match expir_storage_type {
         Hole::Filled(x) => vec![x.clone()],
         Hole::Empty => (0..context.program.native_interface.types.len())
@@ -692,16 +780,7 @@ fn explicate_borrow_ref(
             let mut new_state = state.clone();
 
             new_state.add_storage_node(schedule_node, info.typ.clone(), context);
-            new_state.set_instantiation(
-                schedule_node,
-                info.instantiation.clone().expect(&format!(
-                    "Missing instantiation for node {}",
-                    context
-                        .debug_info
-                        .node(&state.get_current_funclet_id(), source.clone())
-                )),
-                context,
-            );
+            new_state.set_instantiation(schedule_node, instantiation, context);
 
             let node = ir::Node::BorrowRef {
                 storage_type: storage_type.clone(),
@@ -718,6 +797,10 @@ fn explicate_borrow_ref(
         }
     }
 
+    context.record_dead_end(
+        &state,
+        format!("the borrow {}", state.current_node_error(context)),
+    );
     None
 }
 
@@ -727,17 +810,6 @@ fn explicate_read_ref(
     state: InState,
     context: &StaticContext,
 ) -> Option<StorageOutState> {
-    let error = format!(
-        "TODO Hole in node {}",
-        context.debug_info.node_expir(
-            state.get_current_funclet_id(),
-            state
-                .get_current_node(context)
-                .as_ref()
-                .opt()
-                .expect("Unreachable")
-        )
-    );
     let storage_types = match expir_storage_type {
         Hole::Filled(x) => vec![x.clone()],
         Hole::Empty => (0..context.program.native_interface.types.len())
@@ -767,9 +839,8 @@ fn explicate_read_ref(
             let schedule_node = state.get_current_node_id().unwrap();
             let instantiation = match info.instantiation.clone() {
                 Some(inst) => inst,
-                None => {
-                    return None;
-                }
+                // nothing has been written to this reference for us to read
+                None => continue,
             };
 
             let mut new_state = state.clone();
@@ -796,6 +867,10 @@ fn explicate_read_ref(
             }
         }
     }
+    context.record_dead_end(
+        &state,
+        format!("the read {}", state.current_node_error(context)),
+    );
     None
 }
 
@@ -805,28 +880,18 @@ fn explicate_local_copy(
     state: InState,
     context: &StaticContext,
 ) -> Option<StorageOutState> {
-    let error = format!(
-        "TODO Hole in node {}",
-        context.debug_info.node_expir(
-            state.get_current_funclet_id(),
-            state
-                .get_current_node(context)
-                .as_ref()
-                .opt()
-                .expect("Unreachable")
-        )
-    );
+    let error = state.hole_error(context);
     let mut new_state = state.clone();
-    let input = expir_input.as_ref().opt().expect(&error).clone();
-    let output = expir_output.as_ref().opt().expect(&error).clone();
+    let input = expect_filled(expir_input, &error, &state, context)?.clone();
+    let output = expect_filled(expir_output, &error, &state, context)?.clone();
 
     let info = state.get_node_information(&input, context);
-    let input_instantiation = info.instantiation.as_ref().expect(&format!(
-        "Missing instantiation for node {}",
-        context
-            .debug_info
-            .node(&state.get_current_funclet_id(), input)
-    ));
+    let Some(input_instantiation) = info.instantiation.as_ref() else {
+        return malformed(
+            format!("Missing instantiation {}", state.node_error(input, context)),
+            context,
+        );
+    };
     match &input_instantiation.value {
         Some(_) => {
             new_state.set_instantiation(output, input_instantiation.clone(), context);
@@ -851,29 +916,19 @@ fn explicate_encode_copy(
     state: InState,
     context: &StaticContext,
 ) -> Option<StorageOutState> {
-    let error = format!(
-        "TODO Hole in node {}",
-        context.debug_info.node_expir(
-            state.get_current_funclet_id(),
-            state
-                .get_current_node(context)
-                .as_ref()
-                .opt()
-                .expect("Unreachable")
-        )
-    );
+    let error = state.hole_error(context);
     let mut new_state = state.clone();
-    let input = expir_input.as_ref().opt().expect(&error).clone();
-    let output = expir_output.as_ref().opt().expect(&error).clone();
-    let encoder = expir_encoder.as_ref().opt().expect(&error).clone();
+    let input = expect_filled(expir_input, &error, &state, context)?.clone();
+    let output = expect_filled(expir_output, &error, &state, context)?.clone();
+    let encoder = expect_filled(expir_encoder, &error, &state, context)?.clone();
 
     let info = state.get_node_information(&input, context);
-    let input_instantiation = info.instantiation.as_ref().expect(&format!(
-        "Missing instantiation for node {}",
-        context
-            .debug_info
-            .node(&state.get_current_funclet_id(), input)
-    ));
+    let Some(input_instantiation) = info.instantiation.as_ref() else {
+        return malformed(
+            format!("Missing instantiation {}", state.node_error(input, context)),
+            context,
+        );
+    };
     match &input_instantiation.value {
         Some(input_value) => {
             new_state.set_instantiation(
@@ -907,19 +962,9 @@ fn explicate_begin_encoding(
     mut state: InState,
     context: &StaticContext,
 ) -> Option<StorageOutState> {
-    let error = format!(
-        "TODO Hole in node {}",
-        context.debug_info.node_expir(
-            state.get_current_funclet_id(),
-            state
-                .get_current_node(context)
-                .as_ref()
-                .opt()
-                .expect("Unreachable")
-        )
-    );
-    let place = expir_place.as_ref().opt().expect(&error).clone();
-    let event = expir_event.as_ref().opt().expect(&error).clone();
+    let error = state.hole_error(context);
+    let place = expect_filled(expir_place, &error, &state, context)?.clone();
+    let event = expect_filled(expir_event, &error, &state, context)?.clone();
     // TODO: is this correct?
     let event_loc = match event {
         ir::Quotient::Node { node_id } => match place {
@@ -950,18 +995,12 @@ fn explicate_begin_encoding(
     state.set_instantiation(current_node, timeline_loc.clone(), context);
 
     let mut encoded = Vec::new();
-    for node in expir_encoded.as_ref().opt().expect(&error).iter() {
-        let schedule_node = node.as_ref().opt().expect(&error);
+    for node in expect_filled(expir_encoded, &error, &state, context)?.iter() {
+        let schedule_node = expect_filled(node, &error, &state, context)?;
         state.set_instantiation(schedule_node.clone(), timeline_loc.clone(), context);
         encoded.push(schedule_node.clone());
     }
-    let fences = expir_fences
-        .as_ref()
-        .opt()
-        .expect(&error)
-        .iter()
-        .map(|e| e.as_ref().opt().expect(&error).clone())
-        .collect();
+    let fences = expect_all_filled(expir_fences, &error, &state, context)?;
 
     let node = ir::Node::BeginEncoding {
         place,
@@ -985,47 +1024,39 @@ fn explicate_submit(
     mut state: InState,
     context: &StaticContext,
 ) -> Option<StorageOutState> {
-    let error = format!(
-        "TODO Hole in node {}",
-        context.debug_info.node_expir(
-            state.get_current_funclet_id(),
-            state
-                .get_current_node(context)
-                .as_ref()
-                .opt()
-                .expect("Unreachable")
-        )
-    );
-    let encoder = expir_encoder.as_ref().opt().expect(&error);
-    let event = expir_event.as_ref().opt().expect(&error).clone();
+    let error = state.hole_error(context);
+    let encoder = expect_filled(expir_encoder, &error, &state, context)?;
+    let event = expect_filled(expir_event, &error, &state, context)?.clone();
 
     let place = match &state.get_node_information(encoder, context).typ {
         Hole::Filled(expir::Type::Encoder { queue_place }) => queue_place.clone(),
-        typ => panic!(
-            "Expected an encoder, got {:?} in node {}",
-            typ,
-            context.debug_info.node(
-                &state.get_current_funclet_id(),
-                state.get_current_node_id().unwrap()
+        _ => {
+            return malformed(
+                format!(
+                    "Submitting something other than an encoder {}",
+                    state.current_node_error(context)
+                ),
+                context,
             )
-        ),
+        }
     };
 
-    let encoder_timeline_status = state
-        .get_node_information(encoder, context)
-        .instantiation
-        .as_ref()
-        .expect(&format!(
-            "missing instantiation {}",
-            state.node_error(encoder.clone(), context)
-        ))
-        .timeline
-        .as_ref()
-        .expect(&format!(
-            "missing timeline instantiation {}",
-            state.node_error(encoder.clone(), context)
-        ))
-        .quot;
+    let encoder_timeline_status = match &state.get_node_information(encoder, context).instantiation
+    {
+        Some(LocationTriple {
+            timeline: Some(timeline),
+            ..
+        }) => timeline.quot,
+        _ => {
+            return malformed(
+                format!(
+                    "Missing timeline instantiation {}",
+                    state.node_error(encoder.clone(), context)
+                ),
+                context,
+            )
+        }
+    };
     let new_timeline_status = state.get_triple_for_spec(
         state.get_current_funclet_id(),
         &SpecLanguage::Timeline,
@@ -1067,35 +1098,25 @@ fn explicate_sync_fence(
     mut state: InState,
     context: &StaticContext,
 ) -> Option<StorageOutState> {
-    let error = format!(
-        "TODO Hole in node {}",
-        context.debug_info.node_expir(
-            state.get_current_funclet_id(),
-            state
-                .get_current_node(context)
-                .as_ref()
-                .opt()
-                .expect("Unreachable")
-        )
-    );
-    let fence = expir_fence.as_ref().opt().expect(&error);
-    let event = expir_event.as_ref().opt().expect(&error).clone();
-
-    let fence_timeline_status = state
-        .get_node_information(fence, context)
-        .instantiation
-        .as_ref()
-        .expect(&format!(
-            "missing instantiation {}",
-            state.node_error(fence.clone(), context)
-        ))
-        .timeline
-        .as_ref()
-        .expect(&format!(
-            "missing timeline instantiation {}",
-            state.node_error(fence.clone(), context)
-        ))
-        .quot;
+    let error = state.hole_error(context);
+    let fence = expect_filled(expir_fence, &error, &state, context)?;
+    let event = expect_filled(expir_event, &error, &state, context)?.clone();
+
+    let fence_timeline_status = match &state.get_node_information(fence, context).instantiation {
+        Some(LocationTriple {
+            timeline: Some(timeline),
+            ..
+        }) => timeline.quot,
+        _ => {
+            return malformed(
+                format!(
+                    "Missing timeline instantiation {}",
+                    state.node_error(fence.clone(), context)
+                ),
+                context,
+            )
+        }
+    };
 
     let timeline_loc = state.get_triple_for_spec(
         state.get_current_funclet_id(),
@@ -1135,23 +1156,15 @@ pub fn explicate_node(state: InState, context: &StaticContext) -> Option<Storage
         let current_node = state.get_current_node(context);
         match current_node {
             Hole::Empty => {
-                let mut new_state = state.clone();
-                new_state.add_explication_hole();
-                explicate_node(new_state, context);
-                todo!()
+                context.record_unsupported(format!(
+                    "Explicating the ??? hole at node {} of {}",
+                    state.get_current_node_id().unwrap(),
+                    debug_funclet
+                ));
+                None
             }
             Hole::Filled(expir::Node::Phi { index }) => explicate_phi_node(
-                index
-                    .as_ref()
-                    .opt()
-                    .expect(&format!(
-                        "Cannot have a hole for index in Phi node {}",
-                        context.debug_info.node_expir(
-                            state.get_current_funclet_id(),
-                            current_node.as_ref().opt().unwrap()
-                        )
-                    ))
-                    .clone(),
+                expect_filled(index, &state.hole_error(context), &state, context)?.clone(),
                 state,
                 context,
             ),
@@ -1160,6 +1173,11 @@ pub fn explicate_node(state: InState, context: &StaticContext) -> Option<Storage
                 storage_type,
                 buffer_flags,
             }) => explicate_allocate_temporary(place, storage_type, buffer_flags, state, context),
+            Hole::Filled(expir::Node::StaticSubAlloc {
+                node,
+                place,
+                storage_type,
+            }) => explicate_static_sub_alloc(node, place, storage_type, state, context),
             Hole::Filled(expir::Node::LocalDoBuiltin {
                 operation,
                 inputs,
@@ -1246,11 +1264,6 @@ fn explicate_return(
     state: &InState,
     context: &StaticContext,
 ) -> Option<StorageOutState> {
-    let error = format!(
-        "TODO Hole in tail_edge of funclet {}",
-        context.debug_info.funclet(&state.get_current_funclet_id())
-    );
-    let mut result = StorageOutState::new();
     let funclet_id = state.get_current_funclet_id();
     let funclet = context.get_funclet(&funclet_id);
 
@@ -1258,10 +1271,11 @@ fn explicate_return(
         Hole::Filled(values) => values.clone(),
         Hole::Empty => funclet.output_types.iter().map(|_| Hole::Empty).collect(),
     };
-    let mut return_values = Vec::new();
-    let mut no_matching_type = false;
+
+    // every combination of nodes that could be returned, in the order we should try them
+    let mut attempts = vec![Vec::new()];
     for (index, ret) in return_values_todo.iter().enumerate() {
-        match ret {
+        let candidates: Vec<NodeId> = match ret {
             Hole::Filled(node_id) => {
                 let expected_remote = LocationTriple::new_triple_mapped(
                     spec_output,
@@ -1276,10 +1290,10 @@ fn explicate_return(
                     .clone()
                     .unwrap_or(LocationTriple::new());
                 if expected_remote.is_subset_of(&actual_remote, context) {
-                    return_values.push(node_id.clone());
+                    vec![node_id.clone()]
                 } else {
                     // note that we will fail if types don't match
-                    no_matching_type = true;
+                    vec![]
                 }
             }
             Hole::Empty => {
@@ -1291,56 +1305,69 @@ fn explicate_return(
                     context,
                 )
                 .triple_ignoring_none();
-                
+
                 let target_type = context.get_type(get_expect_box(&funclet.output_types, index));
-                match state
+                // TODO: values instantiated outside this funclet would need explicating here
+                state
                     .find_matching_instantiations(target_location_triple, target_type, context)
-                    .first()
-                {
-                    // we couldn't find anything in our funclet
-                    None => {
-                        no_matching_type = true;
-                    }
-                    Some(instantiation) => {
-                        if instantiation.funclet_id != funclet_id {
-                            // TODO try and explicate something
-                            todo!()
-                        };
-                        return_values.push(instantiation.node_id(context).unwrap());
-                    }
-                };
+                    .iter()
+                    .filter(|instantiation| instantiation.funclet_id == funclet_id)
+                    .map(|instantiation| instantiation.node_id(context).unwrap())
+                    .collect()
             }
+        };
+        if candidates.is_empty() {
+            context.record_dead_end(
+                state,
+                format!(
+                    "return value {} of {}",
+                    index,
+                    context.debug_info.funclet(&funclet_id)
+                ),
+            );
+            return None;
         }
+        attempts = attempts
+            .iter()
+            .flat_map(|attempt| {
+                candidates.iter().map(move |candidate| {
+                    let mut new_attempt = attempt.clone();
+                    new_attempt.push(candidate.clone());
+                    new_attempt
+                })
+            })
+            .collect();
     }
-    result.set_tail_edge(ir::TailEdge::Return {
-        return_values: return_values.into_boxed_slice(),
-    });
-    if no_matching_type {
-        None
-    } else {
-        Some(result)
+
+    for return_values in attempts {
+        if context.accept_solution() {
+            let mut result = StorageOutState::new();
+            result.set_tail_edge(ir::TailEdge::Return {
+                return_values: return_values.into_boxed_slice(),
+            });
+            return Some(result);
+        }
     }
+    None
 }
 
 pub fn explicate_tail_edge(state: &InState, context: &StaticContext) -> Option<StorageOutState> {
     match state.get_current_tail_edge(context) {
         Hole::Filled(tail_edge) => {
-            let error = format!("Unimplemented hole in tail edge {:?}", tail_edge);
-            match tail_edge {
+            let error = format!(
+                "tail edge {:?} has a hole the storage pass can't fill",
+                tail_edge
+            );
+            let result = match tail_edge {
                 expir::TailEdge::Return { return_values } => {
-                    explicate_return(return_values, state, context)
+                    // returns are the one tail edge with more than one way to fill it
+                    return explicate_return(return_values, state, context);
                 }
                 expir::TailEdge::Jump { join, arguments } => {
                     let mut result = StorageOutState::new();
                     let tail_edge = ir::TailEdge::Jump {
-                        join: join.as_ref().opt().expect(&error).clone(),
-                        arguments: arguments
-                            .as_ref()
-                            .opt()
-                            .expect(&error)
-                            .iter()
-                            .map(|v| v.clone().opt().expect(&error))
-                            .collect(),
+                        join: expect_filled(join, &error, state, context)?.clone(),
+                        arguments: expect_all_filled(arguments, &error, state, context)?,
                     };
                     result.set_tail_edge(tail_edge);
                     Some(result)
@@ -1355,18 +1382,42 @@ pub fn explicate_tail_edge(state: &InState, context: &StaticContext) -> Option<S
                 } => {
                     let mut result = StorageOutState::new();
                     let tail_edge = ir::TailEdge::ScheduleCall {
-                        value_operation: value_operation.clone().opt().expect(&error).clone(),
-                        timeline_operation: timeline_operation.clone().opt().expect(&error).clone(),
-                        spatial_operation: spatial_operation.clone().opt().expect(&error).clone(),
-                        callee_funclet_id: callee_funclet_id.clone().opt().expect(&error).clone(),
-                        callee_arguments: callee_arguments
-                            .as_ref()
-                            .opt()
-                            .expect(&error)
-                            .iter()
-                            .map(|v| v.clone().opt().expect(&error))
-                            .collect(),
-                        continuation_join: continuation_join.clone().opt().expect(&error).clone(),
+                        value_operation: expect_filled(value_operation, &error, state, context)?
+                            .clone(),
+                        timeline_operation: expect_filled(
+                            timeline_operation,
+                            &error,
+                            state,
+                            context,
+                        )?
+                        .clone(),
+                        spatial_operation: expect_filled(
+                            spatial_operation,
+                            &error,
+                            state,
+                            context,
+                        )?
+                        .clone(),
+                        callee_funclet_id: expect_filled(
+                            callee_funclet_id,
+                            &error,
+                            state,
+                            context,
+                        )?
+                        .clone(),
+                        callee_arguments: expect_all_filled(
+                            callee_arguments,
+                            &error,
+                            state,
+                            context,
+                        )?,
+                        continuation_join: expect_filled(
+                            continuation_join,
+                            &error,
+                            state,
+                            context,
+                        )?
+                        .clone(),
                     };
                     result.set_tail_edge(tail_edge);
                     Some(result)
@@ -1382,25 +1433,42 @@ pub fn explicate_tail_edge(state: &InState, context: &StaticContext) -> Option<S
                 } => {
                     let mut result = StorageOutState::new();
                     let tail_edge = ir::TailEdge::ScheduleSelect {
-                        value_operation: value_operation.clone().opt().expect(&error).clone(),
-                        timeline_operation: timeline_operation.clone().opt().expect(&error).clone(),
-                        spatial_operation: spatial_operation.clone().opt().expect(&error).clone(),
-                        condition: condition.clone().opt().expect(&error).clone(),
-                        callee_funclet_ids: callee_funclet_ids
-                            .as_ref()
-                            .opt()
-                            .expect(&error)
-                            .iter()
-                            .map(|v| v.clone().opt().expect(&error))
-                            .collect(),
-                        callee_arguments: callee_arguments
-                            .as_ref()
-                            .opt()
-                            .expect(&error)
-                            .iter()
-                            .map(|v| v.clone().opt().expect(&error))
-                            .collect(),
-                        continuation_join: continuation_join.clone().opt().expect(&error).clone(),
+                        value_operation: expect_filled(value_operation, &error, state, context)?
+                            .clone(),
+                        timeline_operation: expect_filled(
+                            timeline_operation,
+                            &error,
+                            state,
+                            context,
+                        )?
+                        .clone(),
+                        spatial_operation: expect_filled(
+                            spatial_operation,
+                            &error,
+                            state,
+                            context,
+                        )?
+                        .clone(),
+                        condition: expect_filled(condition, &error, state, context)?.clone(),
+                        callee_funclet_ids: expect_all_filled(
+                            callee_funclet_ids,
+                            &error,
+                            state,
+                            context,
+                        )?,
+                        callee_arguments: expect_all_filled(
+                            callee_arguments,
+                            &error,
+                            state,
+                            context,
+                        )?,
+                        continuation_join: expect_filled(
+                            continuation_join,
+                            &error,
+                            state,
+                            context,
+                        )?
+                        .clone(),
                     };
                     result.set_tail_edge(tail_edge);
                     Some(result)
@@ -1415,22 +1483,80 @@ pub fn explicate_tail_edge(state: &InState, context: &StaticContext) -> Option<S
                 } => {
                     let mut result = StorageOutState::new();
                     let tail_edge = ir::TailEdge::ScheduleCallYield {
-                        value_operation: value_operation.clone().opt().expect(&error).clone(),
-                        timeline_operation: timeline_operation.clone().opt().expect(&error).clone(),
-                        spatial_operation: spatial_operation.clone().opt().expect(&error).clone(),
-                        external_function_id: external_function_id
-                            .clone()
-                            .opt()
-                            .expect(&error)
+                        value_operation: expect_filled(value_operation, &error, state, context)?
                             .clone(),
-                        yielded_nodes: yielded_nodes
-                            .as_ref()
-                            .opt()
-                            .expect(&error)
-                            .iter()
-                            .map(|v| v.clone().opt().expect(&error))
-                            .collect(),
-                        continuation_join: continuation_join.clone().opt().expect(&error).clone(),
+                        timeline_operation: expect_filled(
+                            timeline_operation,
+                            &error,
+                            state,
+                            context,
+                        )?
+                        .clone(),
+                        spatial_operation: expect_filled(
+                            spatial_operation,
+                            &error,
+                            state,
+                            context,
+                        )?
+                        .clone(),
+                        external_function_id: expect_filled(
+                            external_function_id,
+                            &error,
+                            state,
+                            context,
+                        )?
+                        .clone(),
+                        yielded_nodes: expect_all_filled(yielded_nodes, &error, state, context)?,
+                        continuation_join: expect_filled(
+                            continuation_join,
+                            &error,
+                            state,
+                            context,
+                        )?
+                        .clone(),
+                    };
+                    result.set_tail_edge(tail_edge);
+                    Some(result)
+                }
+                expir::TailEdge::DynamicAllocFromBuffer {
+                    buffer,
+                    arguments,
+                    dynamic_allocation_size_slots,
+                    success_funclet_id,
+                    failure_funclet_id,
+                    continuation_join,
+                } => {
+                    let mut result = StorageOutState::new();
+                    let tail_edge = ir::TailEdge::DynamicAllocFromBuffer {
+                        buffer: expect_filled(buffer, &error, state, context)?.clone(),
+                        arguments: expect_all_filled(arguments, &error, state, context)?,
+                        dynamic_allocation_size_slots: expect_all_filled(
+                            dynamic_allocation_size_slots,
+                            &error,
+                            state,
+                            context,
+                        )?,
+                        success_funclet_id: expect_filled(
+                            success_funclet_id,
+                            &error,
+                            state,
+                            context,
+                        )?
+                        .clone(),
+                        failure_funclet_id: expect_filled(
+                            failure_funclet_id,
+                            &error,
+                            state,
+                            context,
+                        )?
+                        .clone(),
+                        continuation_join: expect_filled(
+                            continuation_join,
+                            &error,
+                            state,
+                            context,
+                        )?
+                        .clone(),
                     };
                     result.set_tail_edge(tail_edge);
                     Some(result)
@@ -1443,10 +1569,15 @@ pub fn explicate_tail_edge(state: &InState, context: &StaticContext) -> Option<S
                     result.set_tail_edge(tail_edge);
                     Some(result)
                 }
-            }
+            };
+            result.filter(|_| context.accept_solution())
         }
         Hole::Empty => {
-            todo!()
+            context.record_unsupported(format!(
+                "Explicating the missing tail edge of {}",
+                context.debug_info.funclet(&state.get_current_funclet_id())
+            ));
+            None
         }
     }
 }
@@ -1,8 +1,13 @@
+use crate::backend::BackendKind;
+use crate::debug_info::DebugInfo;
+use crate::diagnostics::{codes, Diagnostic, Position, Range, SourceSpan};
 use crate::explication;
 use crate::ir;
-use crate::debug_info::DebugInfo;
+use crate::rust_wgpu_backend::ffi;
+use crate::version::{self, Version};
 use serde_derive::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::default::Default;
 
 #[derive(Serialize, Deserialize, Debug, Default)]
@@ -29,6 +34,9 @@ pub enum CompileMode {
 #[derive(Serialize, Deserialize, Debug, Default)]
 pub struct CompileData {
     pub path: String,
+    // the input file as given by the user, reported in diagnostics
+    #[serde(default)]
+    pub filename: String,
     pub input_string: String,
 }
 
@@ -36,30 +44,247 @@ pub struct CompileData {
 pub struct CompileOptions {
     pub print_codegen_debug_info: bool,
     pub compile_mode: CompileMode,
+    #[serde(default)]
+    pub backend: BackendKind,
+    // run in order once the program type checks, after which it's checked again
+    #[serde(default)]
+    pub optimizations: Vec<Optimization>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
+// A pass rewriting a type checked program into an equivalent one
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Optimization {
+    // merges GPU dispatches encoded back to back into one kernel, see `ir::fusion`
+    Fusion,
+}
+
+impl Optimization {
+    fn run(self, definition: &mut Definition) -> Result<(), CompileError> {
+        match self {
+            Optimization::Fusion => {
+                ir::fusion::fuse_program(&mut definition.program, &mut definition.debug_info)
+                    .map_err(|message| {
+                        CompileError::new(
+                            CompileErrorKind::Internal,
+                            Diagnostic::error(
+                                codes::INTERNAL,
+                                format!("Kernel fusion failed: {}", message),
+                            ),
+                        )
+                    })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for Optimization {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fusion" => Ok(Optimization::Fusion),
+            other => Err(format!("Unknown optimization {}, expected `fusion`", other)),
+        }
+    }
+}
+
+// The stage of compilation that rejected a program
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompileErrorKind {
+    Syntax,
+    Version,
+    Lowering,
+    Layout,
+    Explication,
+    TypeCheck,
+    Io,
+    // the compiler was asked to do something that doesn't apply to its input
+    Usage,
+    Shader,
+    KernelInterface,
+    Internal,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
 pub struct CompileError {
-    pub message: String,
+    pub kind: CompileErrorKind,
+    pub diagnostic: Diagnostic,
+}
+
+impl CompileError {
+    pub fn new(kind: CompileErrorKind, diagnostic: Diagnostic) -> CompileError {
+        CompileError { kind, diagnostic }
+    }
 }
 
 impl std::fmt::Display for CompileError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{}", self.message)
+        write!(f, "{}", self.diagnostic)
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+impl From<explication::error::Error> for CompileError {
+    fn from(error: explication::error::Error) -> Self {
+        let (kind, code) = match error {
+            explication::error::Error::Internal { .. } => {
+                (CompileErrorKind::Internal, codes::INTERNAL)
+            }
+            _ => (CompileErrorKind::Explication, codes::EXPLICATION),
+        };
+        let diagnostic = Diagnostic::error(code, error.to_string()).with_primary(error.span());
+        CompileError::new(kind, diagnostic)
+    }
+}
+
+// the compiler produced something it can't write back out as assembly
+impl From<crate::assembly::printer::PrintError> for CompileError {
+    fn from(error: crate::assembly::printer::PrintError) -> Self {
+        let diagnostic = Diagnostic::error(codes::INTERNAL, error.to_string());
+        CompileError::new(CompileErrorKind::Internal, diagnostic)
+    }
+}
+
+// code generation stored a value somewhere its type has no layout
+impl From<crate::rust_wgpu_backend::layout::LayoutError> for CompileError {
+    fn from(error: crate::rust_wgpu_backend::layout::LayoutError) -> Self {
+        let diagnostic = Diagnostic::error(codes::LAYOUT, error.to_string());
+        CompileError::new(CompileErrorKind::Layout, diagnostic)
+    }
+}
+
+// programs built in memory, like those lowered from the high-level language, have no file
+fn file_span(filename: &str) -> Option<SourceSpan> {
+    if filename.is_empty() {
+        None
+    } else {
+        let start = Position::new(1, 1);
+        Some(SourceSpan::new(filename, Range { start, end: start }))
+    }
+}
+
+fn version_error(message: String, filename: &str) -> CompileError {
+    let diagnostic = Diagnostic::error(codes::VERSION, message).with_primary(file_span(filename));
+    CompileError::new(CompileErrorKind::Version, diagnostic)
+}
+
+// assembly is never rewritten, the current grammar reads anything an older one did
+fn check_version(version: Version, filename: &str) -> Result<(), CompileError> {
+    if version::is_supported(version, version::MIGRATIONS) {
+        return Ok(());
+    }
+    let supported = version::supported_versions(version::MIGRATIONS);
+    Err(version_error(
+        version::unsupported(version, &supported),
+        filename,
+    ))
+}
+
+fn ron_error(filename: &str, why: ron::Error) -> CompileError {
+    let position = Position::new(why.position.line, why.position.col);
+    let range = Range {
+        start: position,
+        end: position,
+    };
+    let diagnostic = Diagnostic::error(codes::SYNTAX, format!("Parse error: {}", why.code))
+        .with_primary(Some(SourceSpan::new(filename, range)));
+    CompileError::new(CompileErrorKind::Syntax, diagnostic)
+}
+
+// reads a RON definition, migrating it to the current version first if it's older
+pub fn read_ron(compile_data: &CompileData) -> Result<Definition, CompileError> {
+    read_ron_with(compile_data, version::MIGRATIONS)
+}
+
+fn read_ron_with(
+    compile_data: &CompileData,
+    migrations: &[version::Migration],
+) -> Result<Definition, CompileError> {
+    let filename = &compile_data.filename;
+    let text = &compile_data.input_string;
+    let version = version::ron_version(text).map_err(|why| ron_error(filename, why))?;
+    let text = version::migrate(text, version, migrations)
+        .map_err(|message| version_error(message, filename))?;
+    let mut definition: Definition =
+        ron::from_str(&text).map_err(|why| ron_error(filename, why))?;
+    definition.version = version::CURRENT;
+    Ok(definition)
+}
+
+fn parse_error(
+    filename: &str,
+    why: pest_consume::Error<crate::assembly::parser::Rule>,
+) -> CompileError {
+    let range = match why.line_col {
+        pest::error::LineColLocation::Pos((line, column)) => Range {
+            start: Position::new(line, column),
+            end: Position::new(line, column),
+        },
+        pest::error::LineColLocation::Span((start_line, start_column), (end_line, end_column)) => {
+            Range {
+                start: Position::new(start_line, start_column),
+                end: Position::new(end_line, end_column),
+            }
+        }
+    };
+    let diagnostic = Diagnostic::error(
+        codes::SYNTAX,
+        format!("Parse error: {}", why.variant.message()),
+    )
+    .with_primary(Some(SourceSpan::new(filename, range)));
+    CompileError::new(CompileErrorKind::Syntax, diagnostic)
+}
+
+// The stages of compilation, for drivers that stop partway or start from a lowered program
+
+pub fn parse_assembly(
+    compile_data: &CompileData,
+) -> Result<crate::assembly::ast::Program, CompileError> {
+    crate::assembly::parser::parse(&compile_data.path, &compile_data.input_string)
+        .map_err(|why| parse_error(&compile_data.filename, why))
+}
+
+// Every problem in the shader is reported, the first as the primary location
+// Problems naga can't locate (all of them, for SPIR-V) become notes instead of labels
+pub(crate) fn shader_error(path: &str, error: &crate::shadergen::ShaderError) -> CompileError {
+    let span = |location: Option<(usize, usize)>| {
+        location.map(|(line, column)| {
+            let start = Position::new(line, column);
+            SourceSpan::new(path, Range { start, end: start })
+        })
+    };
+    let mut diagnostics = error.diagnostics.iter();
+    let first = diagnostics.next().expect("shader errors aren't empty");
+    let mut diagnostic = Diagnostic::error(codes::SHADER, first.message.clone())
+        .with_primary(span(first.location).or_else(|| file_span(path)));
+    for other in diagnostics {
+        diagnostic = match span(other.location) {
+            Some(span) => diagnostic.with_label(span, other.message.clone()),
+            None => diagnostic.with_note(other.message.clone()),
+        };
     }
+    CompileError::new(CompileErrorKind::Shader, diagnostic)
+}
+
+pub fn lower_assembly(
+    program: crate::assembly::ast::Program,
+    filename: &str,
+) -> Result<ExplicationDefinition, CompileError> {
+    let version = &program.version;
+    let version = [version.major, version.minor, version.detailed]
+        .map(|n| u32::try_from(n).unwrap_or(u32::MAX));
+    check_version((version[0], version[1], version[2]), filename)?;
+    let shader_modules = crate::assembly::lowering_pass::read_shaders(&program)
+        .map_err(|(path, error)| shader_error(&path.to_string_lossy(), &error))?;
+    crate::assembly::lowering_pass::lower_with_shaders(program, shader_modules, filename)
 }
 
 // #[cfg(feature = "assembly")]
 fn read_assembly(compile_data: CompileData) -> Result<ExplicationDefinition, CompileError> {
-    let program = crate::assembly::parser::parse(&compile_data.path, &compile_data.input_string);
-    // dbg!(&program);
-    match program {
-        Err(why) => Err(CompileError {
-            message: format!("Parse error: {}", why),
-        }),
-        // Ok(_) => todo!()
-        Ok(v) => Ok(crate::assembly::lowering_pass::lower(v)),
-    }
+    let program = parse_assembly(&compile_data)?;
+    lower_assembly(program, &compile_data.filename)
 }
 
 // #[cfg(not(feature = "assembly"))]
@@ -74,32 +299,268 @@ fn read_definition(
     compile_mode: CompileMode,
 ) -> Result<Definition, CompileError> {
     match compile_mode {
-        CompileMode::Assembly => read_assembly(compile_data).map(explication::explicate),
-        CompileMode::RON => match ron::from_str(&compile_data.input_string) {
-            Err(why) => Err(CompileError {
-                message: format!("Parse error at {}: {}", why.position, why),
-            }),
-            Ok(v) => Ok(v),
-        },
+        CompileMode::Assembly => Ok(explication::explicate(read_assembly(compile_data)?)?),
+        CompileMode::RON => read_ron(&compile_data),
     }
 }
 
+fn read_checked_definition(
+    compile_data: CompileData,
+    options: &CompileOptions,
+) -> Result<Definition, CompileError> {
+    let mut definition = read_definition(compile_data, options.compile_mode.clone())?;
+    // dbg!(&definition);
+    check_and_optimize(&mut definition, &options.optimizations)?;
+    Ok(definition)
+}
+
+// checks an explicated program, then runs `optimizations` over it in order and checks the result
+pub fn check_and_optimize(
+    definition: &mut Definition,
+    optimizations: &[Optimization],
+) -> Result<(), CompileError> {
+    check_definition(definition)?;
+    if !optimizations.is_empty() {
+        for optimization in optimizations.iter() {
+            optimization.run(definition)?;
+        }
+        check_definition(definition)?;
+    }
+    Ok(())
+}
+
+// checks everything about an explicated program that code generation relies on
+pub fn check_definition(definition: &Definition) -> Result<(), CompileError> {
+    //ir::validation::validate_program(&definition.program);
+    definition
+        .program
+        .native_interface
+        .validate_layouts()
+        .map_err(|(type_id, error)| {
+            CompileError::new(
+                CompileErrorKind::Layout,
+                Diagnostic::error(codes::LAYOUT, format!("Type #{}: {}", type_id.0, error)),
+            )
+        })?;
+    check_shaders(&definition.program)?;
+    check_kernel_interfaces(definition)?;
+    check_types(definition)
+}
+
+// shaders read from source were validated then, but RON definitions and rewritten programs
+//   may carry modules that never were
+fn check_shaders(program: &ir::Program) -> Result<(), CompileError> {
+    for (_, external_function) in program.native_interface.external_functions.iter() {
+        if let ffi::ExternalFunction::GpuKernel(kernel) = external_function {
+            kernel.shader_module.emit_wgsl().map_err(|error| {
+                let mut error = shader_error("", &error);
+                error.diagnostic.message =
+                    format!("Kernel {}: {}", kernel.name, error.diagnostic.message);
+                error
+            })?;
+        }
+    }
+    Ok(())
+}
+
+// the first mismatch is reported at the kernel's declaration, the rest as labels in the shader
+fn check_kernel_interfaces(definition: &Definition) -> Result<(), CompileError> {
+    let native_interface = &definition.program.native_interface;
+    for (index, external_function) in native_interface.external_functions.iter() {
+        let kernel = match external_function {
+            ffi::ExternalFunction::GpuKernel(kernel) => kernel,
+            _ => continue,
+        };
+        let mismatches =
+            ir::validation::check_gpu_kernel_interface(kernel, &native_interface.types);
+        if mismatches.is_empty() {
+            continue;
+        }
+        let shader_path = kernel.shader_module.path().unwrap_or("");
+        let shader_span = |mismatch: &ir::validation::KernelInterfaceMismatch| {
+            mismatch
+                .global
+                .and_then(|global| kernel.shader_module.global_variable_location(global))
+                .filter(|_| !shader_path.is_empty())
+                .map(|(line, column)| {
+                    let start = Position::new(line, column);
+                    SourceSpan::new(shader_path, Range { start, end: start })
+                })
+        };
+        let declaration = definition
+            .debug_info
+            .external_function_span(&index)
+            .filter(|span| !span.file.is_empty())
+            .or_else(|| file_span(&definition.debug_info.source_file));
+        let first = &mismatches[0];
+        let mut diagnostic = Diagnostic::error(
+            codes::KERNEL_INTERFACE,
+            format!("Kernel {}: {}", kernel.name, first.message),
+        );
+        diagnostic = match (declaration, shader_span(first)) {
+            (Some(declaration), Some(span)) => diagnostic
+                .with_primary(Some(declaration))
+                .with_label(span, first.message.clone()),
+            (declaration, span) => diagnostic.with_primary(declaration.or(span)),
+        };
+        for mismatch in &mismatches[1..] {
+            diagnostic = match shader_span(mismatch) {
+                Some(span) => diagnostic.with_label(span, mismatch.message.clone()),
+                None => diagnostic.with_note(mismatch.message.clone()),
+            };
+        }
+        return Err(CompileError::new(
+            CompileErrorKind::KernelInterface,
+            diagnostic,
+        ));
+    }
+    Ok(())
+}
+
+fn check_types(definition: &Definition) -> Result<(), CompileError> {
+    crate::type_system::check_program(&definition.program, &definition.debug_info).map_err(
+        |error| {
+            CompileError::new(
+                CompileErrorKind::TypeCheck,
+                error.to_diagnostic(&definition.debug_info),
+            )
+        },
+    )
+}
+
+// runs every stage up to and including type checking, without generating code
+pub fn check_caiman(
+    compile_data: CompileData,
+    options: CompileOptions,
+) -> Result<(), CompileError> {
+    read_checked_definition(compile_data, &options).map(|_| ())
+}
+
 pub fn compile_caiman(
     compile_data: CompileData,
     options: CompileOptions,
 ) -> Result<String, CompileError> {
-    let mut definition = read_definition(compile_data, options.compile_mode)?;
-    // dbg!(&definition);
-    assert_eq!(definition.version, (0, 0, 2));
-    //ir::validation::validate_program(&definition.program);
-    match crate::type_system::check_program(&definition.program, &definition.debug_info) {
-        Ok(_) => (),
-        Err(error) => panic!("Type checking failed:\n{}", error),
+    let definition = read_checked_definition(compile_data, &options)?;
+    generate(&definition, &options)
+}
+
+// generates code for a definition that passed `check_definition`
+pub fn generate(definition: &Definition, options: &CompileOptions) -> Result<String, CompileError> {
+    Ok(options.backend.backend().generate(
+        &definition.program,
+        &definition.debug_info,
+        options.print_codegen_debug_info,
+    )?)
+}
+
+// describes how many joins each pipeline can have waiting on its join stack at once, one line
+//   per pipeline
+pub fn join_stack_report(definition: &Definition) -> String {
+    let funclet_name = |funclet_id: &ir::FuncletId| definition.debug_info.funclet(funclet_id);
+    let mut report = String::new();
+    for (pipeline_name, bound) in ir::join_stack::program_bounds(&definition.program) {
+        let description = match &bound {
+            ir::join_stack::JoinStackBound::Unbounded { cycle } => format!(
+                "unbounded, going around {} pushes joins every time (give the pipeline a \
+                recursion limit to bound it)",
+                cycle
+                    .iter()
+                    .chain(cycle.first())
+                    .map(funclet_name)
+                    .collect::<Vec<_>>()
+                    .join(" -> ")
+            ),
+            ir::join_stack::JoinStackBound::Bounded(alternatives) => {
+                let alternatives = alternatives
+                    .iter()
+                    .filter(|pending| !pending.is_empty())
+                    .map(|pending| {
+                        pending
+                            .iter()
+                            .map(|((funclet_id, capture_count), count)| {
+                                format!(
+                                    "{} x {} capturing {}",
+                                    count,
+                                    funclet_name(funclet_id),
+                                    capture_count
+                                )
+                            })
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    })
+                    .collect::<Vec<_>>();
+                if alternatives.is_empty() {
+                    "no joins".to_string()
+                } else {
+                    format!(
+                        "at most {} joins, the larger of [{}] (JOIN_STACK_BYTES in the \
+                        generated code)",
+                        bound.max_join_count().unwrap(),
+                        alternatives.join("] or [")
+                    )
+                }
+            }
+        };
+        report.push_str(&format!("{}: {}\n", pipeline_name, description));
     }
-    let mut codegen = crate::rust_wgpu_backend::codegen::CodeGen::new(&definition.program, &definition.debug_info);
-    codegen.set_print_codgen_debug_info(options.print_codegen_debug_info);
-    let output_string = codegen.generate();
-    Ok(output_string)
+    report
+}
+
+pub fn print_join_stacks(
+    compile_data: CompileData,
+    options: CompileOptions,
+) -> Result<String, CompileError> {
+    let definition = read_checked_definition(compile_data, &options)?;
+    Ok(join_stack_report(&definition))
+}
+
+// describes the implementation explication chose for each call of a function class, one line
+//   per call, along with what the cost model thought of the implementations it passed over
+pub fn choice_report(
+    definition: &Definition,
+    cost_model: &dyn explication::cost::CostModel,
+) -> String {
+    let debug_info = &definition.debug_info;
+    let implementation = |(id, cost): &(ffi::ExternalFunctionId, Option<u64>)| match cost {
+        Some(cost) => format!("{} (cost {})", debug_info.external_function(&id.0), cost),
+        None => format!("{} (can't run here)", debug_info.external_function(&id.0)),
+    };
+    let mut report = String::new();
+    for choice in explication::cost::program_choices(&definition.program, cost_model) {
+        let (chosen, passed_over): (Vec<_>, Vec<_>) = choice
+            .alternatives
+            .iter()
+            .partition(|(id, _)| *id == choice.chosen);
+        let mut description = format!(
+            "{}: {} on {}, chose {}",
+            debug_info.node(&choice.value_funclet_id, choice.operation_id),
+            debug_info.function_class(&choice.function_class_id),
+            choice.place,
+            chosen
+                .first()
+                .map(|alternative| implementation(alternative))
+                .unwrap_or_else(|| debug_info.external_function(&choice.chosen.0))
+        );
+        if !passed_over.is_empty() {
+            let passed_over: Vec<_> = passed_over.iter().map(|a| implementation(a)).collect();
+            description.push_str(&format!(" over {}", passed_over.join(", ")));
+        }
+        report.push_str(&description);
+        report.push('\n');
+    }
+    if report.is_empty() {
+        report.push_str("no calls to function classes to choose implementations for\n");
+    }
+    report
+}
+
+pub fn explain_choices(
+    compile_data: CompileData,
+    options: CompileOptions,
+) -> Result<String, CompileError> {
+    let definition = read_checked_definition(compile_data, &options)?;
+    let cost_model = explication::cost::TransferCostModel::default();
+    Ok(choice_report(&definition, &cost_model))
 }
 
 pub fn explicate_caiman(
@@ -108,7 +569,470 @@ pub fn explicate_caiman(
 ) -> Result<String, CompileError> {
     let pretty = ron::ser::PrettyConfig::new().enumerate_arrays(true);
     let mut definition = read_definition(compile_data, options.compile_mode)?;
-    assert_eq!(definition.version, (0, 0, 2));
     let output_string_result = ron::ser::to_string_pretty(&definition, pretty);
     Ok(output_string_result.unwrap())
 }
+
+// rewrites a RON definition written against an older version for the current one
+pub fn upgrade_caiman(
+    compile_data: CompileData,
+    options: CompileOptions,
+) -> Result<String, CompileError> {
+    upgrade_with(compile_data, options, version::MIGRATIONS)
+}
+
+fn upgrade_with(
+    compile_data: CompileData,
+    options: CompileOptions,
+    migrations: &[version::Migration],
+) -> Result<String, CompileError> {
+    if let CompileMode::Assembly = options.compile_mode {
+        return Err(CompileError::new(
+            CompileErrorKind::Usage,
+            Diagnostic::error(
+                codes::USAGE,
+                "Only RON definitions can be upgraded".to_string(),
+            ),
+        ));
+    }
+    let definition = read_ron_with(&compile_data, migrations)?;
+    let pretty = ron::ser::PrettyConfig::new();
+    Ok(ron::ser::to_string_pretty(&definition, pretty).unwrap())
+}
+
+// explicates an assembly program and prints the result back out as assembly,
+//   keeping the names and declarations of the input
+pub fn explicate_caiman_assembly(
+    compile_data: CompileData,
+    options: CompileOptions,
+) -> Result<String, CompileError> {
+    if let CompileMode::RON = options.compile_mode {
+        return Err(CompileError::new(
+            CompileErrorKind::Usage,
+            Diagnostic::error(
+                codes::USAGE,
+                "Only assembly input can be explicated to assembly".to_string(),
+            ),
+        ));
+    }
+    let program = parse_assembly(&compile_data)?;
+    let definition = lower_assembly(program.clone(), &compile_data.filename)?;
+    let definition = explication::explicate(definition)?;
+    let explicated = crate::assembly::printer::explicated_program(
+        &program,
+        &definition.program,
+        &definition.debug_info,
+    )?;
+    Ok(crate::assembly::printer::print_program(&explicated)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn compile(file: &str) -> Result<String, CompileError> {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("caiman-test")
+            .join("malformed")
+            .join(file);
+        let data = CompileData {
+            path: path.parent().unwrap().to_string_lossy().to_string(),
+            filename: path.to_string_lossy().to_string(),
+            input_string: std::fs::read_to_string(&path).unwrap(),
+        };
+        compile_caiman(data, CompileOptions::default())
+    }
+
+    fn ron_data(text: String) -> CompileData {
+        CompileData {
+            path: String::new(),
+            filename: "program.ron".to_string(),
+            input_string: text,
+        }
+    }
+
+    fn assembly_data(text: &str) -> CompileData {
+        CompileData {
+            path: String::new(),
+            filename: "program.cair".to_string(),
+            input_string: text.to_string(),
+        }
+    }
+
+    fn ron_options() -> CompileOptions {
+        CompileOptions {
+            compile_mode: CompileMode::RON,
+            ..Default::default()
+        }
+    }
+
+    fn rename_cpu_operation(text: &str) -> Result<String, String> {
+        Ok(version::rename_identifier(
+            text,
+            "CpuOperation",
+            "CpuPureOperation",
+        ))
+    }
+
+    // a made-up older version that named pure CPU externals differently
+    const MIGRATIONS: &[version::Migration] = &[version::Migration {
+        from: (0, 0, 1),
+        to: version::CURRENT,
+        upgrade: rename_cpu_operation,
+    }];
+
+    #[test]
+    fn upgrades_archived_programs() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("caiman-test")
+            .join("ron")
+            .join("pipeline_1_test.ron");
+        let text = std::fs::read_to_string(path)
+            .unwrap()
+            .replace(&format!("{:?}", version::CURRENT), "(0, 0, 1)")
+            .replace("CpuPureOperation", "CpuOperation");
+        let error = upgrade_caiman(ron_data(text.clone()), ron_options()).unwrap_err();
+        assert_eq!(error.kind, CompileErrorKind::Version);
+        let upgraded = upgrade_with(ron_data(text), ron_options(), MIGRATIONS).unwrap();
+        assert_eq!(version::ron_version(&upgraded).unwrap(), version::CURRENT);
+        assert!(!upgraded.contains("CpuOperation("), "{}", upgraded);
+        check_caiman(ron_data(upgraded), ron_options()).unwrap();
+    }
+
+    #[test]
+    fn rejects_unsupported_assembly_versions() {
+        let older = WRITE_HOLES.replace("version 0.0.2", "version 0.0.1");
+        let error = check_caiman(assembly_data(&older), CompileOptions::default()).unwrap_err();
+        assert_eq!(error.kind, CompileErrorKind::Version);
+        assert!(
+            error.diagnostic.message.contains("supports 0.0.2"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn only_upgrades_ron() {
+        let error = upgrade_caiman(assembly_data(WRITE_HOLES), CompileOptions::default());
+        assert_eq!(error.unwrap_err().kind, CompileErrorKind::Usage);
+    }
+
+    #[test]
+    fn rejects_unsupported_ron_versions() {
+        let text = "(version : (9, 0, 0), debug_info : (), program : ())".to_string();
+        let error = check_caiman(ron_data(text), ron_options()).unwrap_err();
+        assert_eq!(error.kind, CompileErrorKind::Version);
+        assert!(
+            error.diagnostic.message.contains("supports 0.0.2"),
+            "{}",
+            error
+        );
+    }
+
+    // writing to %a_ref first leaves nothing to read from %b_ref, so explication has to go back
+    const WRITE_HOLES: &str = "version 0.0.2
+
+ffi i64;
+event %event0;
+buffer_space %buffspace;
+native_value %i64 : i64;
+
+function @main() -> %i64;
+
+value[impl default @main] %value() -> %i64 {
+    %x = constant %i64 4;
+    return %x;
+}
+
+timeline %time(%e : %event0) -> %event0 {
+    return %e;
+}
+
+spatial %space(%bs : %buffspace) -> %buffspace {
+    return %bs;
+}
+
+schedule[value $val = %value, timeline $time = %time, spatial $space = %space]
+%foo<$time-usable, $time-usable>() ->
+[%out : $val.%x-usable $time-usable $space-usable %i64] {
+    %a_ref = alloc-temporary local [] i64;
+    %b_ref = alloc-temporary local [] i64;
+    %x_ref = alloc-temporary local [] i64;
+    local-do-builtin $val.%x() -> %x_ref;
+    %x = read-ref i64 %x_ref;
+    write-ref ? %x -> ?;
+    %result = read-ref i64 %b_ref;
+    return %result;
+}
+
+pipeline \"main\" = %foo;
+";
+
+    #[test]
+    fn explication_backtracks_out_of_dead_ends() {
+        let options = CompileOptions::default();
+        let explicated = explicate_caiman_assembly(assembly_data(WRITE_HOLES), options).unwrap();
+        assert!(
+            explicated.contains("write-ref i64 %_4 -> %_1;"),
+            "{}",
+            explicated
+        );
+        check_caiman(assembly_data(WRITE_HOLES), CompileOptions::default()).unwrap();
+    }
+
+    const FLOAT_CONSTANT: &str = "version 0.0.2
+
+ffi f32;
+event %event0;
+buffer_space %buffspace;
+native_value %f32 : f32;
+
+function @main() -> %f32;
+
+value[impl default @main] %value() -> %f32 {
+    %x = constant %f32 -1.25e1;
+    return %x;
+}
+
+timeline %time(%e : %event0) -> %event0 {
+    return %e;
+}
+
+spatial %space(%bs : %buffspace) -> %buffspace {
+    return %bs;
+}
+
+schedule[value $val = %value, timeline $time = %time, spatial $space = %space]
+%foo<$time-usable, $time-usable>() ->
+[%out : $val.%x-usable $time-usable $space-usable %f32] {
+    %x_ref = alloc-temporary local [] f32;
+    local-do-builtin $val.%x() -> %x_ref;
+    %x = read-ref f32 %x_ref;
+    return %x;
+}
+
+pipeline \"main\" = %foo;
+";
+
+    #[test]
+    fn float_constants_keep_their_value() {
+        let generated = compile_caiman(assembly_data(FLOAT_CONSTANT), CompileOptions::default());
+        let generated = generated.unwrap();
+        assert!(generated.contains(", -12.5);"), "{}", generated);
+    }
+
+    #[test]
+    fn unfillable_holes_are_located() {
+        let error = compile("unwritten_temporary.cair").unwrap_err();
+        assert!(
+            error.diagnostic.message.contains("no way to fill the read"),
+            "{}",
+            error
+        );
+        let span = error
+            .diagnostic
+            .primary
+            .expect("explication error without a location");
+        assert_eq!(span.start.line, 27);
+    }
+
+    // the GPU implementation comes first, but the call is placed locally
+    const IMPLEMENTATION_HOLE: &str = "version 0.0.2
+
+ffi i32;
+event %event0;
+buffer_space %buffspace;
+native_value %i32 : i32;
+
+function @op(%i32) -> %i32;
+function @main() -> %i32;
+
+external-gpu[impl @op] %op_gpu(%x : i32) -> [%out : i32]
+{
+    path : \"gpu_external.comp\",
+    entry : \"main\",
+    dimensionality : 1,
+    resource {
+        group : 0,
+        binding : 0,
+        input : %x
+    },
+    resource {
+        group : 0,
+        binding : 1,
+        output : %out
+    }
+}
+
+external-cpu-pure[impl @op] %op_cpu(i32) -> i32;
+
+value[impl default @main] %main() -> %i32 {
+    %x = constant %i32 2;
+    %y_t = call @op(%x);
+    %y = extract %y_t 0;
+    return %y;
+}
+
+timeline %time(%e : %event0) -> %event0 {
+    return %e;
+}
+
+spatial %space(%bs : %buffspace) -> %buffspace {
+    return %bs;
+}
+
+schedule[value $val = %main, timeline $time = %time, spatial $space = %space]
+%main_head<$time-usable, $time-usable>() ->
+[%out : $val.%y-usable $time-usable $space-usable %i32] {
+    %x_ref = alloc-temporary local [] i32;
+    local-do-builtin $val.%x() -> %x_ref;
+    %x = read-ref i32 %x_ref;
+    %y_ref = alloc-temporary local [] i32;
+    local-do-external ? $val.%y_t(%x) -> %y_ref;
+    %y = read-ref i32 %y_ref;
+    return %y;
+}
+
+pipeline \"main\" = %main_head;
+";
+
+    #[test]
+    fn explication_chooses_implementations_by_cost() {
+        // the kernel's shader is found relative to the program
+        let shader_directory =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("caiman-test/gpu_timeline");
+        let data = || CompileData {
+            path: shader_directory.to_string_lossy().to_string(),
+            ..assembly_data(IMPLEMENTATION_HOLE)
+        };
+        let explicated = explicate_caiman_assembly(data(), CompileOptions::default()).unwrap();
+        assert!(
+            explicated.contains("local-do-external %op_cpu"),
+            "{}",
+            explicated
+        );
+        let report = explain_choices(data(), CompileOptions::default()).unwrap();
+        assert!(report.contains("chose op_cpu (cost "), "{}", report);
+        assert!(
+            report.contains("over op_gpu (can't run here)"),
+            "{}",
+            report
+        );
+    }
+
+    #[test]
+    fn explication_runs_encoded_calls_where_they_cost_less() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("caiman-test/gpu_timeline/gpu_cost_choice_test.cair");
+        let data = || CompileData {
+            path: path.parent().unwrap().to_string_lossy().to_string(),
+            filename: path.to_string_lossy().to_string(),
+            input_string: std::fs::read_to_string(&path).unwrap(),
+        };
+        let explicated = explicate_caiman_assembly(data(), CompileOptions::default()).unwrap();
+        assert!(
+            explicated.contains("local-do-external %simple_cpu"),
+            "{}",
+            explicated
+        );
+        assert!(!explicated.contains("encode-do"), "{}", explicated);
+        let report = explain_choices(data(), CompileOptions::default()).unwrap();
+        assert!(report.contains("foo.y_t"), "{}", report);
+        assert!(
+            report.contains("on GPU, chose simple_cpu (cost "),
+            "{}",
+            report
+        );
+        assert!(report.contains("over simple (cost "), "{}", report);
+    }
+
+    #[test]
+    fn lowering_errors_point_at_the_node() {
+        let error = compile("undeclared_node.cair").unwrap_err();
+        let diagnostic = &error.diagnostic;
+        assert_eq!(diagnostic.code, codes::LOWERING);
+        assert!(diagnostic.message.contains("y_ref"), "{}", diagnostic);
+        let primary = diagnostic.primary.as_ref().unwrap();
+        assert!(
+            primary.file.ends_with("undeclared_node.cair"),
+            "{}",
+            diagnostic
+        );
+        assert_eq!(primary.start.line, 28);
+    }
+
+    #[test]
+    fn shader_errors_point_into_the_shader() {
+        let error = compile("invalid_shader.cair").unwrap_err();
+        let diagnostic = &error.diagnostic;
+        assert_eq!(diagnostic.code, codes::SHADER);
+        let primary = diagnostic.primary.as_ref().unwrap();
+        assert!(
+            primary.file.ends_with("invalid_shader.comp"),
+            "{}",
+            diagnostic
+        );
+        assert_eq!(primary.start.line, 16);
+        assert_eq!(diagnostic.secondary.len(), 1);
+        assert_eq!(diagnostic.secondary[0].span.start.line, 17);
+    }
+
+    #[test]
+    fn kernel_bindings_are_checked_against_the_shader() {
+        let error = compile("mistyped_binding.cair").unwrap_err();
+        let diagnostic = &error.diagnostic;
+        assert_eq!(diagnostic.code, codes::KERNEL_INTERFACE);
+        assert!(
+            diagnostic.message.contains("binding 0 has type"),
+            "{}",
+            diagnostic
+        );
+        let primary = diagnostic.primary.as_ref().unwrap();
+        assert!(
+            primary.file.ends_with("mistyped_binding.cair"),
+            "{}",
+            diagnostic
+        );
+        assert_eq!(primary.start.line, 16);
+        let labels = &diagnostic.secondary;
+        assert_eq!(labels.len(), 2, "{}", diagnostic);
+        assert!(labels[0].span.file.ends_with("mistyped_binding.comp"));
+        assert_eq!(labels[0].span.start.line, 3);
+        assert!(labels[1].message.contains("read-only"), "{}", diagnostic);
+        assert_eq!(labels[1].span.start.line, 7);
+    }
+
+    // every program in caiman-test/malformed is rejected by the stage we expect, without panicking
+    #[test]
+    fn malformed_inputs_are_errors() {
+        let cases = [
+            ("missing_semicolon.cair", CompileErrorKind::Syntax),
+            ("old_version.cair", CompileErrorKind::Version),
+            ("undeclared_node.cair", CompileErrorKind::Lowering),
+            ("undeclared_spec_node.cair", CompileErrorKind::Lowering),
+            ("flow_hole.cair", CompileErrorKind::Explication),
+            ("unwritten_temporary.cair", CompileErrorKind::Explication),
+            ("copy_hole.cair", CompileErrorKind::Explication),
+            ("submit_non_encoder.cair", CompileErrorKind::Explication),
+            ("wrong_place.cair", CompileErrorKind::TypeCheck),
+            ("local_sub_alloc.cair", CompileErrorKind::TypeCheck),
+            ("external_arity.cair", CompileErrorKind::TypeCheck),
+            ("gpu_u8_temporary.cair", CompileErrorKind::Layout),
+            ("invalid_shader.cair", CompileErrorKind::Shader),
+            ("mistyped_binding.cair", CompileErrorKind::KernelInterface),
+        ];
+        let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("caiman-test/malformed");
+        let programs = std::fs::read_dir(directory)
+            .unwrap()
+            .filter(|entry| entry.as_ref().unwrap().path().extension().unwrap() == "cair");
+        assert_eq!(programs.count(), cases.len());
+        for (file, kind) in cases.iter() {
+            match std::panic::catch_unwind(|| compile(file)) {
+                Ok(Ok(_)) => panic!("{} compiled", file),
+                Ok(Err(error)) => assert_eq!(error.kind, *kind, "{}: {}", file, error),
+                Err(_) => panic!("{} panicked", file),
+            }
+        }
+    }
+}
@@ -0,0 +1,1350 @@
+// Runs GPU kernels on the CPU by walking the naga IR of their shader module
+//
+// Every invocation of the dispatch runs to completion, one after another, so kernels that
+// synchronize invocations with barriers are rejected rather than given a wrong answer.
+// Memory is modeled as byte buffers laid out as WGSL lays them out, which lets bound buffers
+// be exchanged with the rest of the interpreter as raw bytes.
+
+use super::{error, Result};
+use naga::{
+    AddressSpace, ArraySize, AtomicFunction, BinaryOperator, Binding, Block, BuiltIn,
+    ConstantInner, Expression, Function, Handle, MathFunction, Module, RelationalFunction,
+    ScalarKind, ScalarValue, ShaderStage, Statement, SwitchValue, Type, TypeInner, UnaryOperator,
+    VectorSize,
+};
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy)]
+struct Scalar {
+    width: naga::Bytes,
+    value: ScalarValue,
+}
+
+// What a pointer points to; pointers into vectors and matrices have no type handle
+#[derive(Debug, Clone, Copy)]
+enum Pointee {
+    Type(Handle<Type>),
+    Scalar {
+        kind: ScalarKind,
+        width: naga::Bytes,
+    },
+    Vector {
+        size: VectorSize,
+        kind: ScalarKind,
+        width: naga::Bytes,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Pointer {
+    region: usize,
+    offset: u32,
+    pointee: Pointee,
+}
+
+#[derive(Debug, Clone)]
+enum KernelValue {
+    Scalar(Scalar),
+    Vector(Vec<Scalar>),
+    // Arrays, structs, and the columns of matrices
+    Composite(Vec<KernelValue>),
+    Pointer(Pointer),
+}
+
+// How control leaves a block
+enum Flow {
+    Next,
+    Break,
+    Continue,
+    Return(Option<KernelValue>),
+}
+
+struct Frame<'f> {
+    function: &'f Function,
+    arguments: Vec<KernelValue>,
+    // The value of each expression once it has been evaluated
+    values: Vec<Option<KernelValue>>,
+    // The memory region of each local variable
+    locals: Vec<usize>,
+}
+
+struct Machine<'m> {
+    module: &'m Module,
+    layouter: naga::proc::Layouter,
+    regions: Vec<Vec<u8>>,
+    // The memory region of each global variable
+    globals: Vec<usize>,
+}
+
+/// Runs the compute entry point `entry_point` of `module` over the given number of
+/// workgroups. `buffers` holds the contents of the resource bound at each `(group, binding)`
+/// and is updated with whatever the kernel writes to them.
+pub fn dispatch(
+    module: &Module,
+    entry_point: &str,
+    workgroups: [u32; 3],
+    buffers: &mut HashMap<(u32, u32), Vec<u8>>,
+) -> Result<()> {
+    let entry_point = module
+        .entry_points
+        .iter()
+        .find(|ep| ep.name == entry_point && ep.stage == ShaderStage::Compute)
+        .ok_or_else(|| error(format!("No compute entry point named {}", entry_point)))?;
+    let mut layouter = naga::proc::Layouter::default();
+    layouter
+        .update(&module.types, &module.constants)
+        .map_err(|e| error(e.to_string()))?;
+    let mut machine = Machine {
+        module,
+        layouter,
+        regions: Vec::new(),
+        globals: Vec::new(),
+    };
+
+    let mut bound = Vec::new();
+    let mut private = Vec::new();
+    let mut workgroup = Vec::new();
+    for (handle, global) in module.global_variables.iter() {
+        let region = machine.regions.len();
+        machine.globals.push(region);
+        if let Some(binding) = &global.binding {
+            let key = (binding.group, binding.binding);
+            let buffer = buffers.get(&key).ok_or_else(|| {
+                error(format!(
+                    "No buffer is bound at group {} binding {}",
+                    key.0, key.1
+                ))
+            })?;
+            machine.regions.push(buffer.clone());
+            bound.push((key, region));
+        } else {
+            machine.regions.push(Vec::new());
+            match global.space {
+                AddressSpace::WorkGroup => workgroup.push(handle),
+                _ => private.push(handle),
+            }
+        }
+    }
+
+    let [size_x, size_y, size_z] = entry_point.workgroup_size;
+    for group_z in 0..workgroups[2] {
+        for group_y in 0..workgroups[1] {
+            for group_x in 0..workgroups[0] {
+                for handle in workgroup.iter() {
+                    machine.reset_global(*handle)?;
+                }
+                for local_z in 0..size_z {
+                    for local_y in 0..size_y {
+                        for local_x in 0..size_x {
+                            for handle in private.iter() {
+                                machine.reset_global(*handle)?;
+                            }
+                            let invocation = Invocation {
+                                workgroup_id: [group_x, group_y, group_z],
+                                local_id: [local_x, local_y, local_z],
+                                workgroup_size: entry_point.workgroup_size,
+                                workgroups,
+                            };
+                            let arguments = entry_point
+                                .function
+                                .arguments
+                                .iter()
+                                .map(|argument| {
+                                    machine.built_in_argument(
+                                        &invocation,
+                                        argument.ty,
+                                        argument.binding.as_ref(),
+                                    )
+                                })
+                                .collect::<Result<Vec<_>>>()?;
+                            machine.call(&entry_point.function, arguments)?;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    for (key, region) in bound {
+        buffers.insert(key, std::mem::take(&mut machine.regions[region]));
+    }
+    Ok(())
+}
+
+struct Invocation {
+    workgroup_id: [u32; 3],
+    local_id: [u32; 3],
+    workgroup_size: [u32; 3],
+    workgroups: [u32; 3],
+}
+
+fn uint(value: u32) -> Scalar {
+    Scalar {
+        width: 4,
+        value: ScalarValue::Uint(value as u64),
+    }
+}
+
+fn boolean(value: bool) -> Scalar {
+    Scalar {
+        width: naga::BOOL_WIDTH,
+        value: ScalarValue::Bool(value),
+    }
+}
+
+fn uvec3(values: [u32; 3]) -> KernelValue {
+    KernelValue::Vector(values.iter().map(|x| uint(*x)).collect())
+}
+
+// The stride between the columns of a matrix
+fn column_stride(rows: VectorSize, width: naga::Bytes) -> u32 {
+    match rows {
+        VectorSize::Bi => 2 * width as u32,
+        _ => 4 * width as u32,
+    }
+}
+
+impl Scalar {
+    // Truncates the value to its width
+    fn normalize(self) -> Self {
+        let bits = self.width as u32 * 8;
+        let value = match self.value {
+            ScalarValue::Sint(v) if bits < 64 => {
+                let shift = 64 - bits;
+                ScalarValue::Sint((v << shift) >> shift)
+            }
+            ScalarValue::Uint(v) if bits < 64 => ScalarValue::Uint(v & ((1u64 << bits) - 1)),
+            ScalarValue::Float(v) if bits == 32 => ScalarValue::Float(v as f32 as f64),
+            value => value,
+        };
+        Self { value, ..self }
+    }
+
+    fn to_bits(self) -> u64 {
+        match self.value {
+            ScalarValue::Sint(v) => v as u64,
+            ScalarValue::Uint(v) => v,
+            ScalarValue::Float(v) if self.width == 4 => (v as f32).to_bits() as u64,
+            ScalarValue::Float(v) => v.to_bits(),
+            ScalarValue::Bool(v) => v as u64,
+        }
+    }
+
+    fn from_bits(kind: ScalarKind, width: naga::Bytes, bits: u64) -> Self {
+        let value = match kind {
+            ScalarKind::Sint => ScalarValue::Sint(bits as i64),
+            ScalarKind::Uint => ScalarValue::Uint(bits),
+            ScalarKind::Float if width == 4 => {
+                ScalarValue::Float(f32::from_bits(bits as u32) as f64)
+            }
+            ScalarKind::Float => ScalarValue::Float(f64::from_bits(bits)),
+            ScalarKind::Bool => ScalarValue::Bool(bits != 0),
+        };
+        Self { width, value }.normalize()
+    }
+
+    fn as_bool(self) -> Result<bool> {
+        match self.value {
+            ScalarValue::Bool(v) => Ok(v),
+            value => Err(error(format!("Expected a boolean but found {:?}", value))),
+        }
+    }
+
+    fn as_index(self) -> Result<u32> {
+        match self.value {
+            ScalarValue::Sint(v) if v >= 0 => Ok(v as u32),
+            ScalarValue::Uint(v) => Ok(v as u32),
+            value => Err(error(format!("{:?} is not an index", value))),
+        }
+    }
+
+    fn as_float(self) -> Result<f64> {
+        match self.value {
+            ScalarValue::Float(v) => Ok(v),
+            value => Err(error(format!("Expected a float but found {:?}", value))),
+        }
+    }
+
+    // Converts the value to another scalar type as an `As` expression with a width would
+    fn convert(self, kind: ScalarKind, width: naga::Bytes) -> Self {
+        let value = match (kind, self.value) {
+            (ScalarKind::Sint, ScalarValue::Sint(v)) => ScalarValue::Sint(v),
+            (ScalarKind::Sint, ScalarValue::Uint(v)) => ScalarValue::Sint(v as i64),
+            (ScalarKind::Sint, ScalarValue::Float(v)) => ScalarValue::Sint(v as i64),
+            (ScalarKind::Sint, ScalarValue::Bool(v)) => ScalarValue::Sint(v as i64),
+            (ScalarKind::Uint, ScalarValue::Sint(v)) => ScalarValue::Uint(v as u64),
+            (ScalarKind::Uint, ScalarValue::Uint(v)) => ScalarValue::Uint(v),
+            (ScalarKind::Uint, ScalarValue::Float(v)) => ScalarValue::Uint(v as u64),
+            (ScalarKind::Uint, ScalarValue::Bool(v)) => ScalarValue::Uint(v as u64),
+            (ScalarKind::Float, ScalarValue::Sint(v)) => ScalarValue::Float(v as f64),
+            (ScalarKind::Float, ScalarValue::Uint(v)) => ScalarValue::Float(v as f64),
+            (ScalarKind::Float, ScalarValue::Float(v)) => ScalarValue::Float(v),
+            (ScalarKind::Float, ScalarValue::Bool(v)) => ScalarValue::Float(v as u8 as f64),
+            (ScalarKind::Bool, ScalarValue::Sint(v)) => ScalarValue::Bool(v != 0),
+            (ScalarKind::Bool, ScalarValue::Uint(v)) => ScalarValue::Bool(v != 0),
+            (ScalarKind::Bool, ScalarValue::Float(v)) => ScalarValue::Bool(v != 0.0),
+            (ScalarKind::Bool, ScalarValue::Bool(v)) => ScalarValue::Bool(v),
+        };
+        Self { width, value }.normalize()
+    }
+}
+
+fn binary_scalar(op: BinaryOperator, a: Scalar, b: Scalar) -> Result<Scalar> {
+    use std::cmp::Ordering;
+    use BinaryOperator as B;
+    use ScalarValue::*;
+    let compare = |ordering: Option<Ordering>| -> Result<Scalar> {
+        let ordering = ordering.ok_or_else(|| error(format!("Cannot compare {:?} and {:?}", a, b)));
+        Ok(boolean(match op {
+            B::Equal => ordering.map_or(false, |o| o == Ordering::Equal),
+            B::NotEqual => ordering.map_or(true, |o| o != Ordering::Equal),
+            B::Less => ordering? == Ordering::Less,
+            B::LessEqual => ordering? != Ordering::Greater,
+            B::Greater => ordering? == Ordering::Greater,
+            _ => ordering? != Ordering::Less,
+        }))
+    };
+    let shift = |amount: ScalarValue| match amount {
+        Uint(s) => Ok((s % (a.width as u64 * 8)) as u32),
+        Sint(s) => Ok((s as u64 % (a.width as u64 * 8)) as u32),
+        _ => Err(error(format!("Cannot shift by {:?}", amount))),
+    };
+    let value = match (op, a.value, b.value) {
+        (B::Equal | B::NotEqual | B::Less | B::LessEqual | B::Greater | B::GreaterEqual, x, y) => {
+            return compare(match (x, y) {
+                (Sint(x), Sint(y)) => x.partial_cmp(&y),
+                (Uint(x), Uint(y)) => x.partial_cmp(&y),
+                (Float(x), Float(y)) => x.partial_cmp(&y),
+                (Bool(x), Bool(y)) => x.partial_cmp(&y),
+                _ => None,
+            })
+        }
+        (B::Add, Sint(x), Sint(y)) => Sint(x.wrapping_add(y)),
+        (B::Add, Uint(x), Uint(y)) => Uint(x.wrapping_add(y)),
+        (B::Add, Float(x), Float(y)) => Float(x + y),
+        (B::Subtract, Sint(x), Sint(y)) => Sint(x.wrapping_sub(y)),
+        (B::Subtract, Uint(x), Uint(y)) => Uint(x.wrapping_sub(y)),
+        (B::Subtract, Float(x), Float(y)) => Float(x - y),
+        (B::Multiply, Sint(x), Sint(y)) => Sint(x.wrapping_mul(y)),
+        (B::Multiply, Uint(x), Uint(y)) => Uint(x.wrapping_mul(y)),
+        (B::Multiply, Float(x), Float(y)) => Float(x * y),
+        // Integer division by zero results in the dividend, as in WGSL
+        (B::Divide, Sint(x), Sint(y)) => Sint(if y == 0 { x } else { x.wrapping_div(y) }),
+        (B::Divide, Uint(x), Uint(y)) => Uint(if y == 0 { x } else { x / y }),
+        (B::Divide, Float(x), Float(y)) => Float(x / y),
+        (B::Modulo, Sint(x), Sint(y)) => Sint(if y == 0 { 0 } else { x.wrapping_rem(y) }),
+        (B::Modulo, Uint(x), Uint(y)) => Uint(if y == 0 { 0 } else { x % y }),
+        (B::Modulo, Float(x), Float(y)) => Float(x % y),
+        (B::And, Sint(x), Sint(y)) => Sint(x & y),
+        (B::And, Uint(x), Uint(y)) => Uint(x & y),
+        (B::And | B::LogicalAnd, Bool(x), Bool(y)) => Bool(x & y),
+        (B::InclusiveOr, Sint(x), Sint(y)) => Sint(x | y),
+        (B::InclusiveOr, Uint(x), Uint(y)) => Uint(x | y),
+        (B::InclusiveOr | B::LogicalOr, Bool(x), Bool(y)) => Bool(x | y),
+        (B::ExclusiveOr, Sint(x), Sint(y)) => Sint(x ^ y),
+        (B::ExclusiveOr, Uint(x), Uint(y)) => Uint(x ^ y),
+        (B::ExclusiveOr, Bool(x), Bool(y)) => Bool(x ^ y),
+        (B::ShiftLeft, Sint(x), s) => Sint(((x as u64) << shift(s)?) as i64),
+        (B::ShiftLeft, Uint(x), s) => Uint(x << shift(s)?),
+        (B::ShiftRight, Sint(x), s) => Sint(x >> shift(s)?),
+        (B::ShiftRight, Uint(x), s) => Uint(x >> shift(s)?),
+        (op, x, y) => {
+            return Err(error(format!(
+                "{:?} is undefined on {:?} and {:?}",
+                op, x, y
+            )))
+        }
+    };
+    Ok(Scalar {
+        width: a.width,
+        value,
+    }
+    .normalize())
+}
+
+fn unary_scalar(op: UnaryOperator, a: Scalar) -> Result<Scalar> {
+    let value = match (op, a.value) {
+        (UnaryOperator::Negate, ScalarValue::Sint(x)) => ScalarValue::Sint(x.wrapping_neg()),
+        (UnaryOperator::Negate, ScalarValue::Float(x)) => ScalarValue::Float(-x),
+        (UnaryOperator::Not, ScalarValue::Sint(x)) => ScalarValue::Sint(!x),
+        (UnaryOperator::Not, ScalarValue::Uint(x)) => ScalarValue::Uint(!x),
+        (UnaryOperator::Not, ScalarValue::Bool(x)) => ScalarValue::Bool(!x),
+        (op, x) => return Err(error(format!("{:?} is undefined on {:?}", op, x))),
+    };
+    Ok(Scalar { value, ..a }.normalize())
+}
+
+// Rounds half-way cases to even, as WGSL does
+fn round_even(x: f64) -> f64 {
+    let rounded = x.round();
+    if (x - x.trunc()).abs() == 0.5 {
+        2.0 * (x / 2.0).round()
+    } else {
+        rounded
+    }
+}
+
+fn math_scalar(fun: MathFunction, args: &[Scalar]) -> Result<Scalar> {
+    use MathFunction as M;
+    use ScalarValue::*;
+    let a = args[0];
+    let float = |f: fn(f64) -> f64| -> Result<ScalarValue> { Ok(Float(f(a.as_float()?))) };
+    let value = match (fun, a.value) {
+        (M::Abs, Sint(x)) => Sint(x.wrapping_abs()),
+        (M::Abs, Uint(x)) => Uint(x),
+        (M::Abs, Float(x)) => Float(x.abs()),
+        (M::Min | M::Max, _) => {
+            let b = args[1];
+            let less = binary_scalar(BinaryOperator::Less, b, a)?.as_bool()?;
+            if less == (fun == M::Min) {
+                b.value
+            } else {
+                a.value
+            }
+        }
+        (M::Clamp, _) => {
+            let low = math_scalar(M::Max, &[a, args[1]])?;
+            return math_scalar(M::Min, &[low, args[2]]);
+        }
+        (M::Saturate, Float(x)) => Float(x.max(0.0).min(1.0)),
+        (M::Cos, _) => float(f64::cos)?,
+        (M::Cosh, _) => float(f64::cosh)?,
+        (M::Sin, _) => float(f64::sin)?,
+        (M::Sinh, _) => float(f64::sinh)?,
+        (M::Tan, _) => float(f64::tan)?,
+        (M::Tanh, _) => float(f64::tanh)?,
+        (M::Acos, _) => float(f64::acos)?,
+        (M::Asin, _) => float(f64::asin)?,
+        (M::Atan, _) => float(f64::atan)?,
+        (M::Atan2, Float(y)) => Float(y.atan2(args[1].as_float()?)),
+        (M::Radians, _) => float(f64::to_radians)?,
+        (M::Degrees, _) => float(f64::to_degrees)?,
+        (M::Ceil, _) => float(f64::ceil)?,
+        (M::Floor, _) => float(f64::floor)?,
+        (M::Round, _) => float(round_even)?,
+        (M::Fract, Float(x)) => Float(x - x.floor()),
+        (M::Trunc, _) => float(f64::trunc)?,
+        (M::Exp, _) => float(f64::exp)?,
+        (M::Exp2, _) => float(f64::exp2)?,
+        (M::Log, _) => float(f64::ln)?,
+        (M::Log2, _) => float(f64::log2)?,
+        (M::Pow, Float(x)) => Float(x.powf(args[1].as_float()?)),
+        (M::Sqrt, _) => float(f64::sqrt)?,
+        (M::InverseSqrt, Float(x)) => Float(1.0 / x.sqrt()),
+        (M::Sign, Sint(x)) => Sint(x.signum()),
+        (M::Sign, Float(x)) => Float(if x == 0.0 { 0.0 } else { x.signum() }),
+        (M::Fma, Float(x)) => Float(x.mul_add(args[1].as_float()?, args[2].as_float()?)),
+        (M::Mix, Float(x)) => {
+            let (y, t) = (args[1].as_float()?, args[2].as_float()?);
+            Float(x * (1.0 - t) + y * t)
+        }
+        (M::Step, Float(edge)) => Float(if args[1].as_float()? < edge { 0.0 } else { 1.0 }),
+        (M::CountOneBits, Sint(_) | Uint(_)) => {
+            Uint((a.to_bits() & width_mask(a.width)).count_ones() as u64)
+        }
+        (M::ReverseBits, Sint(_) | Uint(_)) => {
+            let bits = (a.to_bits().reverse_bits()) >> (64 - a.width as u32 * 8);
+            return Ok(Scalar::from_bits(kind_of(a.value), a.width, bits));
+        }
+        (fun, value) => {
+            return Err(error(format!(
+                "Math function {:?} on {:?} is unsupported",
+                fun, value
+            )))
+        }
+    };
+    let value = match (a.value, value) {
+        (Sint(_), Uint(count)) => Sint(count as i64),
+        (_, value) => value,
+    };
+    Ok(Scalar {
+        width: a.width,
+        value,
+    }
+    .normalize())
+}
+
+fn width_mask(width: naga::Bytes) -> u64 {
+    if width >= 8 {
+        u64::MAX
+    } else {
+        (1u64 << (width as u32 * 8)) - 1
+    }
+}
+
+fn kind_of(value: ScalarValue) -> ScalarKind {
+    match value {
+        ScalarValue::Sint(_) => ScalarKind::Sint,
+        ScalarValue::Uint(_) => ScalarKind::Uint,
+        ScalarValue::Float(_) => ScalarKind::Float,
+        ScalarValue::Bool(_) => ScalarKind::Bool,
+    }
+}
+
+impl KernelValue {
+    fn scalar(&self) -> Result<Scalar> {
+        match self {
+            Self::Scalar(scalar) => Ok(*scalar),
+            value => Err(error(format!("Expected a scalar but found {:?}", value))),
+        }
+    }
+
+    fn pointer(&self) -> Result<Pointer> {
+        match self {
+            Self::Pointer(pointer) => Ok(*pointer),
+            value => Err(error(format!("Expected a pointer but found {:?}", value))),
+        }
+    }
+
+    fn index(&self, index: u32) -> Result<Self> {
+        let element = match self {
+            Self::Vector(components) => components.get(index as usize).copied().map(Self::Scalar),
+            Self::Composite(elements) => elements.get(index as usize).cloned(),
+            _ => None,
+        };
+        element.ok_or_else(|| error(format!("Cannot index {:?} at {}", self, index)))
+    }
+
+    // Applies `f` to each component of scalars and vectors of the same size
+    fn zip_with(values: &[Self], mut f: impl FnMut(&[Scalar]) -> Result<Scalar>) -> Result<Self> {
+        let size = values
+            .iter()
+            .filter_map(|value| match value {
+                Self::Vector(components) => Some(components.len()),
+                _ => None,
+            })
+            .max();
+        let component = |value: &Self, index: usize| match value {
+            Self::Scalar(scalar) => Ok(*scalar),
+            Self::Vector(components) => components
+                .get(index)
+                .copied()
+                .ok_or_else(|| error("Mismatched vector sizes".to_string())),
+            value => Err(error(format!("{:?} is not a scalar or a vector", value))),
+        };
+        match size {
+            None => {
+                let scalars = values
+                    .iter()
+                    .map(|value| component(value, 0))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(Self::Scalar(f(&scalars)?))
+            }
+            Some(size) => Ok(Self::Vector(
+                (0..size)
+                    .map(|index| {
+                        let scalars = values
+                            .iter()
+                            .map(|value| component(value, index))
+                            .collect::<Result<Vec<_>>>()?;
+                        f(&scalars)
+                    })
+                    .collect::<Result<_>>()?,
+            )),
+        }
+    }
+
+    fn components(&self) -> Result<Vec<Scalar>> {
+        match self {
+            Self::Scalar(scalar) => Ok(vec![*scalar]),
+            Self::Vector(components) => Ok(components.clone()),
+            value => Err(error(format!("{:?} is not a scalar or a vector", value))),
+        }
+    }
+}
+
+impl<'m> Machine<'m> {
+    fn inner(&self, pointee: Pointee) -> Cow<'m, TypeInner> {
+        match pointee {
+            Pointee::Type(ty) => Cow::Borrowed(&self.module.types[ty].inner),
+            Pointee::Scalar { kind, width } => Cow::Owned(TypeInner::Scalar { kind, width }),
+            Pointee::Vector { size, kind, width } => {
+                Cow::Owned(TypeInner::Vector { size, kind, width })
+            }
+        }
+    }
+
+    fn constant(&self, handle: Handle<naga::Constant>) -> Result<KernelValue> {
+        match &self.module.constants[handle].inner {
+            ConstantInner::Scalar { width, value } => Ok(KernelValue::Scalar(Scalar {
+                width: *width,
+                value: *value,
+            })),
+            ConstantInner::Composite { ty, components } => {
+                let components = components
+                    .iter()
+                    .map(|component| self.constant(*component))
+                    .collect::<Result<Vec<_>>>()?;
+                self.compose(*ty, components)
+            }
+        }
+    }
+
+    fn compose(&self, ty: Handle<Type>, components: Vec<KernelValue>) -> Result<KernelValue> {
+        match self.module.types[ty].inner {
+            TypeInner::Vector { .. } => {
+                let mut scalars = Vec::new();
+                for component in components.iter() {
+                    scalars.extend(component.components()?);
+                }
+                Ok(KernelValue::Vector(scalars))
+            }
+            _ => Ok(KernelValue::Composite(components)),
+        }
+    }
+
+    fn array_length(
+        &self,
+        size: ArraySize,
+        region: usize,
+        offset: u32,
+        stride: u32,
+    ) -> Result<u32> {
+        match size {
+            ArraySize::Constant(length) => self.constant(length)?.scalar()?.as_index(),
+            ArraySize::Dynamic => {
+                Ok((self.regions[region].len() as u32).saturating_sub(offset) / stride)
+            }
+        }
+    }
+
+    fn read_bits(&self, region: usize, offset: u32, width: naga::Bytes) -> Result<u64> {
+        use std::convert::TryInto;
+        let start = offset as usize;
+        let bytes = self.regions[region]
+            .get(start..start + width as usize)
+            .ok_or_else(|| error(format!("Out of bounds read at byte {}", offset)))?;
+        Ok(match width {
+            1 => bytes[0] as u64,
+            2 => u16::from_ne_bytes(bytes.try_into().unwrap()) as u64,
+            4 => u32::from_ne_bytes(bytes.try_into().unwrap()) as u64,
+            8 => u64::from_ne_bytes(bytes.try_into().unwrap()),
+            _ => return Err(error(format!("Unsupported scalar width {}", width))),
+        })
+    }
+
+    fn write_bits(
+        &mut self,
+        region: usize,
+        offset: u32,
+        width: naga::Bytes,
+        bits: u64,
+    ) -> Result<()> {
+        let start = offset as usize;
+        let bytes = self.regions[region]
+            .get_mut(start..start + width as usize)
+            .ok_or_else(|| error(format!("Out of bounds write at byte {}", offset)))?;
+        match width {
+            1 => bytes.copy_from_slice(&[bits as u8]),
+            2 => bytes.copy_from_slice(&(bits as u16).to_ne_bytes()),
+            4 => bytes.copy_from_slice(&(bits as u32).to_ne_bytes()),
+            8 => bytes.copy_from_slice(&bits.to_ne_bytes()),
+            _ => return Err(error(format!("Unsupported scalar width {}", width))),
+        }
+        Ok(())
+    }
+
+    fn read(&self, inner: &TypeInner, region: usize, offset: u32) -> Result<KernelValue> {
+        match *inner {
+            TypeInner::Scalar { kind, width } | TypeInner::Atomic { kind, width } => {
+                let bits = self.read_bits(region, offset, width)?;
+                Ok(KernelValue::Scalar(Scalar::from_bits(kind, width, bits)))
+            }
+            TypeInner::Vector { size, kind, width } => Ok(KernelValue::Vector(
+                (0..size as u32)
+                    .map(|index| {
+                        let bits = self.read_bits(region, offset + index * width as u32, width)?;
+                        Ok(Scalar::from_bits(kind, width, bits))
+                    })
+                    .collect::<Result<_>>()?,
+            )),
+            TypeInner::Matrix {
+                columns,
+                rows,
+                width,
+            } => {
+                let column = TypeInner::Vector {
+                    size: rows,
+                    kind: ScalarKind::Float,
+                    width,
+                };
+                Ok(KernelValue::Composite(
+                    (0..columns as u32)
+                        .map(|index| {
+                            self.read(&column, region, offset + index * column_stride(rows, width))
+                        })
+                        .collect::<Result<_>>()?,
+                ))
+            }
+            TypeInner::Array { base, size, stride } => {
+                let length = self.array_length(size, region, offset, stride)?;
+                let base = &self.module.types[base].inner;
+                Ok(KernelValue::Composite(
+                    (0..length)
+                        .map(|index| self.read(base, region, offset + index * stride))
+                        .collect::<Result<_>>()?,
+                ))
+            }
+            TypeInner::Struct { ref members, .. } => Ok(KernelValue::Composite(
+                members
+                    .iter()
+                    .map(|member| {
+                        self.read(
+                            &self.module.types[member.ty].inner,
+                            region,
+                            offset + member.offset,
+                        )
+                    })
+                    .collect::<Result<_>>()?,
+            )),
+            ref inner => Err(error(format!("Cannot load a value of type {:?}", inner))),
+        }
+    }
+
+    fn write(
+        &mut self,
+        inner: &TypeInner,
+        region: usize,
+        offset: u32,
+        value: &KernelValue,
+    ) -> Result<()> {
+        match (inner, value) {
+            (
+                TypeInner::Scalar { kind, width } | TypeInner::Atomic { kind, width },
+                KernelValue::Scalar(scalar),
+            ) => {
+                let scalar = scalar.convert(*kind, *width);
+                self.write_bits(region, offset, *width, scalar.to_bits())
+            }
+            (TypeInner::Vector { kind, width, .. }, KernelValue::Vector(components)) => {
+                for (index, component) in components.iter().enumerate() {
+                    let component = component.convert(*kind, *width);
+                    let offset = offset + index as u32 * *width as u32;
+                    self.write_bits(region, offset, *width, component.to_bits())?;
+                }
+                Ok(())
+            }
+            (TypeInner::Matrix { rows, width, .. }, KernelValue::Composite(columns)) => {
+                let column_type = TypeInner::Vector {
+                    size: *rows,
+                    kind: ScalarKind::Float,
+                    width: *width,
+                };
+                for (index, column) in columns.iter().enumerate() {
+                    let offset = offset + index as u32 * column_stride(*rows, *width);
+                    self.write(&column_type, region, offset, column)?;
+                }
+                Ok(())
+            }
+            (TypeInner::Array { base, stride, .. }, KernelValue::Composite(elements)) => {
+                let base = &self.module.types[*base].inner;
+                for (index, element) in elements.iter().enumerate() {
+                    self.write(base, region, offset + index as u32 * stride, element)?;
+                }
+                Ok(())
+            }
+            (TypeInner::Struct { members, .. }, KernelValue::Composite(fields)) => {
+                for (member, field) in members.iter().zip(fields.iter()) {
+                    let member_type = &self.module.types[member.ty].inner;
+                    self.write(member_type, region, offset + member.offset, field)?;
+                }
+                Ok(())
+            }
+            (inner, value) => Err(error(format!(
+                "Cannot store {:?} as a value of type {:?}",
+                value, inner
+            ))),
+        }
+    }
+
+    // Allocates a zeroed region holding a value of type `ty`, initialized to `init` if given
+    fn allocate(
+        &mut self,
+        ty: Handle<Type>,
+        init: Option<Handle<naga::Constant>>,
+    ) -> Result<usize> {
+        let region = self.regions.len();
+        self.regions
+            .push(vec![0u8; self.layouter[ty].size as usize]);
+        if let Some(init) = init {
+            let value = self.constant(init)?;
+            self.write(&self.module.types[ty].inner, region, 0, &value)?;
+        }
+        Ok(region)
+    }
+
+    fn reset_global(&mut self, handle: Handle<naga::GlobalVariable>) -> Result<()> {
+        let global = &self.module.global_variables[handle];
+        let region = self.globals[handle.index()];
+        self.regions[region] = vec![0u8; self.layouter[global.ty].size as usize];
+        if let Some(init) = global.init {
+            let value = self.constant(init)?;
+            self.write(&self.module.types[global.ty].inner, region, 0, &value)?;
+        }
+        Ok(())
+    }
+
+    fn built_in_argument(
+        &self,
+        invocation: &Invocation,
+        ty: Handle<Type>,
+        binding: Option<&Binding>,
+    ) -> Result<KernelValue> {
+        match binding {
+            Some(Binding::BuiltIn(built_in)) => {
+                let [size_x, size_y, _] = invocation.workgroup_size;
+                let [local_x, local_y, local_z] = invocation.local_id;
+                Ok(match built_in {
+                    BuiltIn::GlobalInvocationId => {
+                        let mut id = [0; 3];
+                        for axis in 0..3 {
+                            id[axis] = invocation.workgroup_id[axis]
+                                * invocation.workgroup_size[axis]
+                                + invocation.local_id[axis];
+                        }
+                        uvec3(id)
+                    }
+                    BuiltIn::LocalInvocationId => uvec3(invocation.local_id),
+                    BuiltIn::LocalInvocationIndex => {
+                        KernelValue::Scalar(uint((local_z * size_y + local_y) * size_x + local_x))
+                    }
+                    BuiltIn::WorkGroupId => uvec3(invocation.workgroup_id),
+                    BuiltIn::WorkGroupSize => uvec3(invocation.workgroup_size),
+                    BuiltIn::NumWorkGroups => uvec3(invocation.workgroups),
+                    built_in => {
+                        return Err(error(format!(
+                            "{:?} is not a compute shader input",
+                            built_in
+                        )))
+                    }
+                })
+            }
+            Some(Binding::Location { .. }) => Err(error(
+                "Compute shaders cannot take location inputs".to_string(),
+            )),
+            None => match &self.module.types[ty].inner {
+                TypeInner::Struct { members, .. } => Ok(KernelValue::Composite(
+                    members
+                        .iter()
+                        .map(|member| {
+                            self.built_in_argument(invocation, member.ty, member.binding.as_ref())
+                        })
+                        .collect::<Result<_>>()?,
+                )),
+                _ => Err(error("Entry point argument has no binding".to_string())),
+            },
+        }
+    }
+
+    fn call(
+        &mut self,
+        function: &'m Function,
+        arguments: Vec<KernelValue>,
+    ) -> Result<Option<KernelValue>> {
+        let base = self.regions.len();
+        let mut frame = Frame {
+            function,
+            arguments,
+            values: vec![None; function.expressions.len()],
+            locals: Vec::new(),
+        };
+        for (_, local) in function.local_variables.iter() {
+            frame.locals.push(self.allocate(local.ty, local.init)?);
+        }
+        let flow = self.block(&mut frame, &function.body)?;
+        self.regions.truncate(base);
+        match flow {
+            Flow::Return(value) => Ok(value),
+            _ => Ok(None),
+        }
+    }
+
+    fn value(&mut self, frame: &mut Frame<'m>, handle: Handle<Expression>) -> Result<KernelValue> {
+        if let Some(value) = &frame.values[handle.index()] {
+            return Ok(value.clone());
+        }
+        let value = self.evaluate(frame, handle)?;
+        frame.values[handle.index()] = Some(value.clone());
+        Ok(value)
+    }
+
+    fn access(&mut self, base: KernelValue, index: u32) -> Result<KernelValue> {
+        let pointer = match base {
+            KernelValue::Pointer(pointer) => pointer,
+            value => return value.index(index),
+        };
+        let (offset, pointee) = match *self.inner(pointer.pointee) {
+            TypeInner::Array { base, size, stride } => {
+                let length = self.array_length(size, pointer.region, pointer.offset, stride)?;
+                if index >= length {
+                    return Err(error(format!(
+                        "Index {} is out of bounds for an array of length {}",
+                        index, length
+                    )));
+                }
+                (index * stride, Pointee::Type(base))
+            }
+            TypeInner::Vector { kind, width, .. } => {
+                (index * width as u32, Pointee::Scalar { kind, width })
+            }
+            TypeInner::Matrix { rows, width, .. } => (
+                index * column_stride(rows, width),
+                Pointee::Vector {
+                    size: rows,
+                    kind: ScalarKind::Float,
+                    width,
+                },
+            ),
+            TypeInner::Struct { ref members, .. } => {
+                let member = members
+                    .get(index as usize)
+                    .ok_or_else(|| error(format!("No struct member {}", index)))?;
+                (member.offset, Pointee::Type(member.ty))
+            }
+            ref inner => return Err(error(format!("Cannot index into {:?}", inner))),
+        };
+        Ok(KernelValue::Pointer(Pointer {
+            region: pointer.region,
+            offset: pointer.offset + offset,
+            pointee,
+        }))
+    }
+
+    fn load(&self, pointer: Pointer) -> Result<KernelValue> {
+        self.read(&self.inner(pointer.pointee), pointer.region, pointer.offset)
+    }
+
+    fn store(&mut self, pointer: Pointer, value: &KernelValue) -> Result<()> {
+        let inner = self.inner(pointer.pointee);
+        self.write(&inner, pointer.region, pointer.offset, value)
+    }
+
+    fn evaluate(
+        &mut self,
+        frame: &mut Frame<'m>,
+        handle: Handle<Expression>,
+    ) -> Result<KernelValue> {
+        let expression = &frame.function.expressions[handle];
+        match *expression {
+            Expression::Access { base, index } => {
+                let base = self.value(frame, base)?;
+                let index = self.value(frame, index)?.scalar()?.as_index()?;
+                self.access(base, index)
+            }
+            Expression::AccessIndex { base, index } => {
+                let base = self.value(frame, base)?;
+                self.access(base, index)
+            }
+            Expression::Constant(constant) => self.constant(constant),
+            Expression::Splat { size, value } => {
+                let value = self.value(frame, value)?.scalar()?;
+                Ok(KernelValue::Vector(vec![value; size as usize]))
+            }
+            Expression::Swizzle {
+                size,
+                vector,
+                pattern,
+            } => {
+                let vector = self.value(frame, vector)?;
+                Ok(KernelValue::Vector(
+                    pattern[..size as usize]
+                        .iter()
+                        .map(|component| vector.index(*component as u32)?.scalar())
+                        .collect::<Result<_>>()?,
+                ))
+            }
+            Expression::Compose { ty, ref components } => {
+                let components = components
+                    .iter()
+                    .map(|component| self.value(frame, *component))
+                    .collect::<Result<Vec<_>>>()?;
+                self.compose(ty, components)
+            }
+            Expression::FunctionArgument(index) => Ok(frame.arguments[index as usize].clone()),
+            Expression::GlobalVariable(global) => Ok(KernelValue::Pointer(Pointer {
+                region: self.globals[global.index()],
+                offset: 0,
+                pointee: Pointee::Type(self.module.global_variables[global].ty),
+            })),
+            Expression::LocalVariable(local) => Ok(KernelValue::Pointer(Pointer {
+                region: frame.locals[local.index()],
+                offset: 0,
+                pointee: Pointee::Type(frame.function.local_variables[local].ty),
+            })),
+            Expression::Load { pointer } => {
+                let pointer = self.value(frame, pointer)?.pointer()?;
+                self.load(pointer)
+            }
+            Expression::Unary { op, expr } => {
+                let value = self.value(frame, expr)?;
+                KernelValue::zip_with(&[value], |x| unary_scalar(op, x[0]))
+            }
+            Expression::Binary { op, left, right } => {
+                let left = self.value(frame, left)?;
+                let right = self.value(frame, right)?;
+                KernelValue::zip_with(&[left, right], |x| binary_scalar(op, x[0], x[1]))
+            }
+            Expression::Select {
+                condition,
+                accept,
+                reject,
+            } => {
+                let condition = self.value(frame, condition)?;
+                let accept = self.value(frame, accept)?;
+                let reject = self.value(frame, reject)?;
+                match condition {
+                    KernelValue::Scalar(condition) => {
+                        Ok(if condition.as_bool()? { accept } else { reject })
+                    }
+                    condition => KernelValue::zip_with(&[condition, accept, reject], |x| {
+                        Ok(if x[0].as_bool()? { x[1] } else { x[2] })
+                    }),
+                }
+            }
+            Expression::Relational { fun, argument } => {
+                let argument = self.value(frame, argument)?;
+                match fun {
+                    RelationalFunction::All | RelationalFunction::Any => {
+                        let components = argument
+                            .components()?
+                            .into_iter()
+                            .map(|x| x.as_bool())
+                            .collect::<Result<Vec<_>>>()?;
+                        Ok(KernelValue::Scalar(boolean(match fun {
+                            RelationalFunction::All => components.iter().all(|x| *x),
+                            _ => components.iter().any(|x| *x),
+                        })))
+                    }
+                    fun => KernelValue::zip_with(&[argument], |x| {
+                        let x = x[0].as_float()?;
+                        Ok(boolean(match fun {
+                            RelationalFunction::IsNan => x.is_nan(),
+                            RelationalFunction::IsInf => x.is_infinite(),
+                            RelationalFunction::IsFinite => x.is_finite(),
+                            _ => x.is_normal(),
+                        }))
+                    }),
+                }
+            }
+            Expression::Math {
+                fun,
+                arg,
+                arg1,
+                arg2,
+                arg3,
+            } => {
+                let mut args = vec![self.value(frame, arg)?];
+                for extra in [arg1, arg2, arg3].iter().flatten() {
+                    args.push(self.value(frame, *extra)?);
+                }
+                match fun {
+                    MathFunction::Dot => {
+                        let mut sum: Option<Scalar> = None;
+                        let products = KernelValue::zip_with(&args, |x| {
+                            binary_scalar(BinaryOperator::Multiply, x[0], x[1])
+                        })?;
+                        for product in products.components()? {
+                            sum = Some(match sum {
+                                None => product,
+                                Some(sum) => binary_scalar(BinaryOperator::Add, sum, product)?,
+                            });
+                        }
+                        Ok(KernelValue::Scalar(sum.unwrap()))
+                    }
+                    MathFunction::Length => {
+                        let components = args[0].components()?;
+                        let mut sum = 0.0;
+                        for component in components.iter() {
+                            sum += component.as_float()?.powi(2);
+                        }
+                        Ok(KernelValue::Scalar(
+                            Scalar {
+                                width: components[0].width,
+                                value: ScalarValue::Float(sum.sqrt()),
+                            }
+                            .normalize(),
+                        ))
+                    }
+                    fun => KernelValue::zip_with(&args, |x| math_scalar(fun, x)),
+                }
+            }
+            Expression::As {
+                expr,
+                kind,
+                convert,
+            } => {
+                let value = self.value(frame, expr)?;
+                KernelValue::zip_with(&[value], |x| {
+                    Ok(match convert {
+                        Some(width) => x[0].convert(kind, width),
+                        None => Scalar::from_bits(kind, x[0].width, x[0].to_bits()),
+                    })
+                })
+            }
+            Expression::CallResult(_) | Expression::AtomicResult { .. } => Err(error(
+                "Result used before the statement producing it".to_string(),
+            )),
+            Expression::ArrayLength(array) => {
+                let pointer = self.value(frame, array)?.pointer()?;
+                match *self.inner(pointer.pointee) {
+                    TypeInner::Array { size, stride, .. } => Ok(KernelValue::Scalar(uint(
+                        self.array_length(size, pointer.region, pointer.offset, stride)?,
+                    ))),
+                    ref inner => Err(error(format!("{:?} has no length", inner))),
+                }
+            }
+            ref expression => Err(error(format!(
+                "Unsupported kernel expression {:?}",
+                expression
+            ))),
+        }
+    }
+
+    fn block(&mut self, frame: &mut Frame<'m>, block: &'m Block) -> Result<Flow> {
+        for statement in block.iter() {
+            match self.statement(frame, statement)? {
+                Flow::Next => (),
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    fn statement(&mut self, frame: &mut Frame<'m>, statement: &'m Statement) -> Result<Flow> {
+        match *statement {
+            Statement::Emit(ref range) => {
+                for handle in range.clone() {
+                    let value = self.evaluate(frame, handle)?;
+                    frame.values[handle.index()] = Some(value);
+                }
+            }
+            Statement::Block(ref block) => return self.block(frame, block),
+            Statement::If {
+                condition,
+                ref accept,
+                ref reject,
+            } => {
+                let condition = self.value(frame, condition)?.scalar()?.as_bool()?;
+                return self.block(frame, if condition { accept } else { reject });
+            }
+            Statement::Switch {
+                selector,
+                ref cases,
+            } => {
+                let selector = self.value(frame, selector)?.scalar()?;
+                let matches = |value: &SwitchValue| match (*value, selector.value) {
+                    (SwitchValue::I32(case), ScalarValue::Sint(selector)) => {
+                        case as i64 == selector
+                    }
+                    (SwitchValue::U32(case), ScalarValue::Uint(selector)) => {
+                        case as u64 == selector
+                    }
+                    _ => false,
+                };
+                let start = cases
+                    .iter()
+                    .position(|case| matches(&case.value))
+                    .or_else(|| {
+                        cases
+                            .iter()
+                            .position(|case| case.value == SwitchValue::Default)
+                    });
+                if let Some(start) = start {
+                    for case in cases[start..].iter() {
+                        match self.block(frame, &case.body)? {
+                            Flow::Next if case.fall_through => (),
+                            Flow::Next | Flow::Break => break,
+                            flow => return Ok(flow),
+                        }
+                    }
+                }
+            }
+            Statement::Loop {
+                ref body,
+                ref continuing,
+                break_if,
+            } => loop {
+                match self.block(frame, body)? {
+                    Flow::Break => break,
+                    Flow::Return(value) => return Ok(Flow::Return(value)),
+                    Flow::Next | Flow::Continue => (),
+                }
+                if let Flow::Return(value) = self.block(frame, continuing)? {
+                    return Ok(Flow::Return(value));
+                }
+                if let Some(break_if) = break_if {
+                    if self.value(frame, break_if)?.scalar()?.as_bool()? {
+                        break;
+                    }
+                }
+            },
+            Statement::Break => return Ok(Flow::Break),
+            Statement::Continue => return Ok(Flow::Continue),
+            Statement::Return { value } => {
+                let value = match value {
+                    Some(value) => Some(self.value(frame, value)?),
+                    None => None,
+                };
+                return Ok(Flow::Return(value));
+            }
+            Statement::Store { pointer, value } => {
+                let pointer = self.value(frame, pointer)?.pointer()?;
+                let value = self.value(frame, value)?;
+                self.store(pointer, &value)?;
+            }
+            Statement::Atomic {
+                pointer,
+                ref fun,
+                value,
+                result,
+            } => {
+                let pointer = self.value(frame, pointer)?.pointer()?;
+                let value = self.value(frame, value)?.scalar()?;
+                let old = self.load(pointer)?.scalar()?;
+                let (new, result_value) = match *fun {
+                    AtomicFunction::Add => (binary_scalar(BinaryOperator::Add, old, value)?, None),
+                    AtomicFunction::Subtract => {
+                        (binary_scalar(BinaryOperator::Subtract, old, value)?, None)
+                    }
+                    AtomicFunction::And => (binary_scalar(BinaryOperator::And, old, value)?, None),
+                    AtomicFunction::ExclusiveOr => (
+                        binary_scalar(BinaryOperator::ExclusiveOr, old, value)?,
+                        None,
+                    ),
+                    AtomicFunction::InclusiveOr => (
+                        binary_scalar(BinaryOperator::InclusiveOr, old, value)?,
+                        None,
+                    ),
+                    AtomicFunction::Min => (math_scalar(MathFunction::Min, &[old, value])?, None),
+                    AtomicFunction::Max => (math_scalar(MathFunction::Max, &[old, value])?, None),
+                    AtomicFunction::Exchange { compare: None } => (value, None),
+                    AtomicFunction::Exchange {
+                        compare: Some(compare),
+                    } => {
+                        let compare = self.value(frame, compare)?.scalar()?;
+                        let exchanged =
+                            binary_scalar(BinaryOperator::Equal, old, compare)?.as_bool()?;
+                        let result = KernelValue::Composite(vec![
+                            KernelValue::Scalar(old),
+                            KernelValue::Scalar(boolean(exchanged)),
+                        ]);
+                        (if exchanged { value } else { old }, Some(result))
+                    }
+                };
+                self.store(pointer, &KernelValue::Scalar(new))?;
+                frame.values[result.index()] =
+                    Some(result_value.unwrap_or(KernelValue::Scalar(old)));
+            }
+            Statement::Call {
+                function,
+                ref arguments,
+                result,
+            } => {
+                let arguments = arguments
+                    .iter()
+                    .map(|argument| self.value(frame, *argument))
+                    .collect::<Result<Vec<_>>>()?;
+                let value = self.call(&self.module.functions[function], arguments)?;
+                if let Some(result) = result {
+                    let value = value
+                        .ok_or_else(|| error("Function call did not return a value".to_string()))?;
+                    frame.values[result.index()] = Some(value);
+                }
+            }
+            Statement::Barrier(_) => {
+                return Err(error(
+                    "Barriers are unsupported since invocations run one at a time".to_string(),
+                ))
+            }
+            ref statement => {
+                return Err(error(format!(
+                    "Unsupported kernel statement {:?}",
+                    statement
+                )))
+            }
+        }
+        Ok(Flow::Next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(source: &str, workgroups: [u32; 3], buffers: &[&[u32]]) -> Vec<Vec<u32>> {
+        let module = naga::front::wgsl::parse_str(source).unwrap();
+        let mut bound = HashMap::new();
+        for (binding, buffer) in buffers.iter().enumerate() {
+            let bytes = buffer.iter().flat_map(|x| x.to_ne_bytes()).collect();
+            bound.insert((0, binding as u32), bytes);
+        }
+        dispatch(&module, "main", workgroups, &mut bound).unwrap();
+        (0..buffers.len())
+            .map(|binding| {
+                bound[&(0, binding as u32)]
+                    .chunks_exact(4)
+                    .map(|chunk| u32::from_ne_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_invocations() {
+        let source = "
+            @group(0) @binding(0) var<storage, read> input : array<u32>;
+            @group(0) @binding(1) var<storage, read_write> output : array<u32>;
+            @compute @workgroup_size(2)
+            fn main(@builtin(global_invocation_id) id : vec3<u32>) {
+                output[id.x] = input[id.x] * id.x + 1u;
+            }";
+        let buffers = run(source, [2, 1, 1], &[&[5, 6, 7, 8], &[0, 0, 0, 0]]);
+        assert_eq!(buffers[1], vec![1, 7, 15, 25]);
+    }
+
+    #[test]
+    fn test_control_flow() {
+        let source = "
+            struct Pair { a : i32, b : i32 }
+            @group(0) @binding(0) var<storage, read_write> pair : Pair;
+            fn gcd(x : i32, y : i32) -> i32 {
+                var a = x;
+                var b = y;
+                loop {
+                    if b == 0 { break; }
+                    let t = a % b;
+                    a = b;
+                    b = t;
+                }
+                return a;
+            }
+            @compute @workgroup_size(1)
+            fn main() {
+                var sum = 0;
+                for (var i = 0; i < 4; i++) {
+                    if i == 2 { continue; }
+                    sum += i;
+                }
+                pair.a = gcd(pair.a, pair.b);
+                pair.b = select(sum, -sum, pair.a > 3);
+            }";
+        let buffers = run(source, [1, 1, 1], &[&[12, 18]]);
+        assert_eq!(buffers[0], vec![6, (-4i32) as u32]);
+    }
+
+    #[test]
+    fn test_barrier_rejected() {
+        let source = "
+            @group(0) @binding(0) var<storage, read_write> output : u32;
+            @compute @workgroup_size(1)
+            fn main() {
+                workgroupBarrier();
+                output = 1u;
+            }";
+        let module = naga::front::wgsl::parse_str(source).unwrap();
+        let mut bound = HashMap::new();
+        bound.insert((0, 0), vec![0u8; 4]);
+        assert!(dispatch(&module, "main", [1, 1, 1], &mut bound).is_err());
+    }
+}
@@ -0,0 +1,1310 @@
+// A reference interpreter for Caiman IR programs
+//
+// Value funclets are evaluated directly as the reference semantics of a program, and
+// scheduling funclets are executed the way the code generated by `rust_wgpu_backend` would
+// execute them, except that every place is host memory and GPU kernels run on the CPU (see
+// `kernel`). This allows checking the behavior of a program without a GPU and comparing the
+// results of a schedule against its value specification.
+
+mod kernel;
+mod value;
+
+pub use value::Value;
+
+use crate::ir;
+use crate::ir::ffi;
+use crate::rust_wgpu_backend::builtins::{BuiltinOperation, ScalarValue};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::rc::Rc;
+
+#[derive(Debug)]
+pub struct Error {
+    pub message: String,
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Interpreter error: {}", self.message)
+    }
+}
+
+impl std::error::Error for Error {}
+
+fn error(message: String) -> Error {
+    Error { message }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+// Value funclets may call function classes they implement, so evaluation gives up past this depth
+const MAX_CALL_DEPTH: usize = 256;
+
+/// A reference to a slot holding a native value, shared by every node result referring to it
+#[derive(Debug, Clone)]
+pub struct Ref {
+    place: ir::Place,
+    storage_type: ffi::TypeId,
+    slot: Rc<RefCell<Option<Value>>>,
+}
+
+impl Ref {
+    pub fn new(place: ir::Place, storage_type: ffi::TypeId, value_opt: Option<Value>) -> Self {
+        Self {
+            place,
+            storage_type,
+            slot: Rc::new(RefCell::new(value_opt)),
+        }
+    }
+
+    pub fn place(&self) -> ir::Place {
+        self.place
+    }
+
+    pub fn storage_type(&self) -> ffi::TypeId {
+        self.storage_type
+    }
+
+    /// The value held by the slot, or `None` if nothing has been written to it yet
+    pub fn get(&self) -> Option<Value> {
+        self.slot.borrow().clone()
+    }
+
+    pub fn set(&self, value: Value) {
+        *self.slot.borrow_mut() = Some(value);
+    }
+}
+
+#[derive(Debug)]
+enum JoinPoint {
+    // Returns to the host
+    Root,
+    Funclet {
+        funclet_id: ir::FuncletId,
+        captures: Box<[NodeResult]>,
+        continuation: Join,
+    },
+}
+
+/// A join point: where a scheduling funclet continues once it returns
+#[derive(Debug, Clone)]
+pub struct Join(Rc<JoinPoint>);
+
+#[derive(Debug, Clone)]
+enum Command {
+    Copy {
+        input: NodeResult,
+        output: NodeResult,
+    },
+    Dispatch {
+        external_function_id: ffi::ExternalFunctionId,
+        workgroups: [u32; 3],
+        inputs: Box<[NodeResult]>,
+        outputs: Box<[NodeResult]>,
+    },
+}
+
+/// Commands encoded for a queue that run once they are submitted
+#[derive(Debug, Clone)]
+pub struct Encoder {
+    place: ir::Place,
+    commands: Vec<Command>,
+}
+
+/// The result of a node of a scheduling funclet
+#[derive(Debug, Clone)]
+pub enum NodeResult {
+    Value(Value),
+    Ref(Ref),
+    Buffer { place: ir::Place },
+    Fence { place: ir::Place },
+    Encoder(Encoder),
+    Join(Join),
+}
+
+impl NodeResult {
+    /// The value of a local value or the value held by a ref
+    pub fn value(&self) -> Result<Value> {
+        match self {
+            Self::Value(value) => Ok(value.clone()),
+            Self::Ref(reference) => reference
+                .get()
+                .ok_or_else(|| error("Read of an uninitialized ref".to_string())),
+            result => Err(error(format!("{:?} is not a value", result))),
+        }
+    }
+
+    fn reference(&self) -> Result<&Ref> {
+        match self {
+            Self::Ref(reference) => Ok(reference),
+            result => Err(error(format!("{:?} is not a ref", result))),
+        }
+    }
+
+    fn join(&self) -> Result<Join> {
+        match self {
+            Self::Join(join) => Ok(join.clone()),
+            result => Err(error(format!("{:?} is not a join", result))),
+        }
+    }
+}
+
+/// Implementations of the CPU operations in a program's native interface that are not built-in
+/// operators. Operations without an implementation fail when they are called.
+pub trait CpuFunctions {
+    fn call_pure(
+        &self,
+        operation: &ffi::CpuPureOperation,
+        _arguments: &[Value],
+    ) -> std::result::Result<Vec<Value>, String> {
+        Err(format!("No implementation of {}", operation.name))
+    }
+
+    /// Called by `Interpreter::run` when a pipeline yields to this operation, returning the
+    /// values the pipeline resumes with
+    fn call_effectful(
+        &mut self,
+        operation: &ffi::CpuEffectfulOperation,
+        _arguments: &[NodeResult],
+    ) -> std::result::Result<Vec<NodeResult>, String> {
+        Err(format!("No implementation of {}", operation.name))
+    }
+}
+
+/// What a pipeline did when it stopped running
+#[derive(Debug)]
+pub enum Outcome {
+    Returned(Vec<NodeResult>),
+    Yielded {
+        external_function_id: ffi::ExternalFunctionId,
+        yielded: Vec<NodeResult>,
+        continuation: Join,
+    },
+}
+
+// What to do after running a scheduling funclet
+enum Step {
+    Enter {
+        funclet_id: ir::FuncletId,
+        arguments: Vec<NodeResult>,
+        default_join: Join,
+    },
+    Invoke {
+        join: Join,
+        arguments: Vec<NodeResult>,
+    },
+    Yield {
+        external_function_id: ffi::ExternalFunctionId,
+        yielded: Vec<NodeResult>,
+        continuation: Join,
+    },
+}
+
+pub struct Interpreter<'program, 'callbacks> {
+    program: &'program ir::Program,
+    callbacks: &'callbacks mut dyn CpuFunctions,
+}
+
+impl<'program, 'callbacks> Interpreter<'program, 'callbacks> {
+    pub fn new(
+        program: &'program ir::Program,
+        callbacks: &'callbacks mut dyn CpuFunctions,
+    ) -> Self {
+        Self { program, callbacks }
+    }
+
+    /// Starts the pipeline named `pipeline_name`, running it until it returns or yields
+    pub fn start(&mut self, pipeline_name: &str, arguments: Vec<NodeResult>) -> Result<Outcome> {
+        let pipeline = self
+            .program
+            .pipelines
+            .iter()
+            .find(|pipeline| pipeline.name == pipeline_name)
+            .ok_or_else(|| error(format!("No pipeline named {}", pipeline_name)))?;
+        self.execute(Step::Enter {
+            funclet_id: pipeline.entry_funclet,
+            arguments,
+            default_join: Join(Rc::new(JoinPoint::Root)),
+        })
+    }
+
+    /// Resumes a pipeline that yielded, passing the results of the operation it yielded to
+    pub fn resume(&mut self, continuation: Join, arguments: Vec<NodeResult>) -> Result<Outcome> {
+        self.execute(Step::Invoke {
+            join: continuation,
+            arguments,
+        })
+    }
+
+    /// Runs a pipeline to completion, calling `CpuFunctions::call_effectful` whenever it yields
+    pub fn run(
+        &mut self,
+        pipeline_name: &str,
+        arguments: Vec<NodeResult>,
+    ) -> Result<Vec<NodeResult>> {
+        let mut outcome = self.start(pipeline_name, arguments)?;
+        loop {
+            match outcome {
+                Outcome::Returned(results) => return Ok(results),
+                Outcome::Yielded {
+                    external_function_id,
+                    yielded,
+                    continuation,
+                } => {
+                    let operation = self.program.native_interface.external_functions
+                        [external_function_id.0]
+                        .get_cpu_effectful_operation()
+                        .ok_or_else(|| {
+                            error(format!(
+                                "Yield to {:?}, which is not an effectful operation",
+                                external_function_id
+                            ))
+                        })?;
+                    let resuming = self
+                        .callbacks
+                        .call_effectful(operation, &yielded)
+                        .map_err(error)?;
+                    outcome = self.resume(continuation, resuming)?;
+                }
+            }
+        }
+    }
+
+    /// Evaluates a value funclet on the given arguments
+    pub fn evaluate_value_funclet(
+        &mut self,
+        funclet_id: ir::FuncletId,
+        arguments: &[Value],
+    ) -> Result<Vec<Value>> {
+        self.evaluate_value_funclet_at_depth(funclet_id, arguments, 0)
+    }
+
+    fn execute(&mut self, mut step: Step) -> Result<Outcome> {
+        loop {
+            step = match step {
+                Step::Enter {
+                    funclet_id,
+                    arguments,
+                    default_join,
+                } => self
+                    .run_scheduling_funclet(funclet_id, arguments, default_join)
+                    .map_err(|e| error(format!("In funclet #{}: {}", funclet_id, e.message)))?,
+                Step::Invoke { join, arguments } => match &*join.0 {
+                    JoinPoint::Root => return Ok(Outcome::Returned(arguments)),
+                    JoinPoint::Funclet {
+                        funclet_id,
+                        captures,
+                        continuation,
+                    } => Step::Enter {
+                        funclet_id: *funclet_id,
+                        arguments: captures.iter().cloned().chain(arguments).collect(),
+                        default_join: continuation.clone(),
+                    },
+                },
+                Step::Yield {
+                    external_function_id,
+                    yielded,
+                    continuation,
+                } => {
+                    return Ok(Outcome::Yielded {
+                        external_function_id,
+                        yielded,
+                        continuation,
+                    })
+                }
+            };
+        }
+    }
+
+    fn run_scheduling_funclet(
+        &mut self,
+        funclet_id: ir::FuncletId,
+        arguments: Vec<NodeResult>,
+        default_join: Join,
+    ) -> Result<Step> {
+        let funclet = &self.program.funclets[funclet_id];
+        if funclet.kind != ir::FuncletKind::ScheduleExplicit {
+            return Err(error(format!(
+                "{:?} is not a scheduling funclet",
+                funclet.kind
+            )));
+        }
+        if arguments.len() != funclet.input_types.len() {
+            return Err(error(format!(
+                "Expected {} arguments but got {}",
+                funclet.input_types.len(),
+                arguments.len()
+            )));
+        }
+
+        let mut default_join_opt = Some(default_join);
+        let mut node_results: Vec<Option<NodeResult>> = vec![None; funclet.nodes.len()];
+        for (node_id, node) in funclet.nodes.iter().enumerate() {
+            let result_opt = self
+                .run_scheduling_node(
+                    funclet,
+                    node,
+                    &arguments,
+                    &mut node_results,
+                    &mut default_join_opt,
+                )
+                .map_err(|e| error(format!("At node #{} {:?}: {}", node_id, node, e.message)))?;
+            if result_opt.is_some() {
+                node_results[node_id] = result_opt;
+            }
+        }
+
+        let get = |node_id: &ir::NodeId| {
+            node_results[*node_id]
+                .clone()
+                .ok_or_else(|| error(format!("Node #{} has no result", node_id)))
+        };
+        let get_all =
+            |node_ids: &[ir::NodeId]| node_ids.iter().map(get).collect::<Result<Vec<_>>>();
+        match &funclet.tail_edge {
+            ir::TailEdge::Return { return_values } => Ok(Step::Invoke {
+                join: default_join_opt
+                    .ok_or_else(|| error("No default join to return to".to_string()))?,
+                arguments: get_all(return_values)?,
+            }),
+            ir::TailEdge::Jump { join, arguments } => Ok(Step::Invoke {
+                join: get(join)?.join()?,
+                arguments: get_all(arguments)?,
+            }),
+            ir::TailEdge::ScheduleCall {
+                callee_funclet_id,
+                callee_arguments,
+                continuation_join,
+                ..
+            } => Ok(Step::Enter {
+                funclet_id: *callee_funclet_id,
+                arguments: get_all(callee_arguments)?,
+                default_join: get(continuation_join)?.join()?,
+            }),
+            ir::TailEdge::ScheduleSelect {
+                condition,
+                callee_funclet_ids,
+                callee_arguments,
+                continuation_join,
+                ..
+            } => {
+                if callee_funclet_ids.len() != 2 {
+                    return Err(error("Select must have exactly two callees".to_string()));
+                }
+                let branch = if get(condition)?.value()?.is_true()? {
+                    0
+                } else {
+                    1
+                };
+                Ok(Step::Enter {
+                    funclet_id: callee_funclet_ids[branch],
+                    arguments: get_all(callee_arguments)?,
+                    default_join: get(continuation_join)?.join()?,
+                })
+            }
+            ir::TailEdge::ScheduleCallYield {
+                external_function_id,
+                yielded_nodes,
+                continuation_join,
+                ..
+            } => Ok(Step::Yield {
+                external_function_id: *external_function_id,
+                yielded: get_all(yielded_nodes)?,
+                continuation: get(continuation_join)?.join()?,
+            }),
+            ir::TailEdge::DynamicAllocFromBuffer {
+                buffer,
+                arguments,
+                dynamic_allocation_size_slots,
+                success_funclet_id,
+                continuation_join,
+                ..
+            } => {
+                let place = match get(buffer)? {
+                    NodeResult::Buffer { place } => place,
+                    result => return Err(error(format!("Cannot allocate from {:?}", result))),
+                };
+                // Buffers here are host memory without a capacity, so allocation always succeeds
+                let success_funclet = &self.program.funclets[*success_funclet_id];
+                if success_funclet.input_types.len()
+                    != arguments.len() + dynamic_allocation_size_slots.len()
+                {
+                    return Err(error(format!(
+                        "Funclet #{} does not take {} arguments and {} allocations",
+                        success_funclet_id,
+                        arguments.len(),
+                        dynamic_allocation_size_slots.len()
+                    )));
+                }
+                let mut success_arguments = get_all(arguments)?;
+                for type_id in success_funclet.input_types[arguments.len()..].iter() {
+                    match &self.program.types[*type_id] {
+                        ir::Type::Ref { storage_type, .. } => {
+                            success_arguments.push(NodeResult::Ref(Ref::new(
+                                place,
+                                *storage_type,
+                                None,
+                            )));
+                        }
+                        typ => return Err(error(format!("Cannot allocate a {:?}", typ))),
+                    }
+                }
+                Ok(Step::Enter {
+                    funclet_id: *success_funclet_id,
+                    arguments: success_arguments,
+                    default_join: get(continuation_join)?.join()?,
+                })
+            }
+            ir::TailEdge::DebugHole { .. } => Err(error("Reached a debug hole".to_string())),
+        }
+    }
+
+    fn run_scheduling_node(
+        &mut self,
+        funclet: &ir::Funclet,
+        node: &ir::Node,
+        arguments: &[NodeResult],
+        node_results: &mut [Option<NodeResult>],
+        default_join_opt: &mut Option<Join>,
+    ) -> Result<Option<NodeResult>> {
+        let get = |node_results: &[Option<NodeResult>], node_id: &ir::NodeId| {
+            node_results[*node_id]
+                .clone()
+                .ok_or_else(|| error(format!("Node #{} has no result", node_id)))
+        };
+        let get_all = |node_results: &[Option<NodeResult>], node_ids: &[ir::NodeId]| {
+            node_ids
+                .iter()
+                .map(|node_id| get(node_results, node_id))
+                .collect::<Result<Vec<_>>>()
+        };
+        let native_interface = &self.program.native_interface;
+        let result = match node {
+            ir::Node::None => return Ok(None),
+            ir::Node::Phi { index } => arguments[*index].clone(),
+            ir::Node::AllocTemporary {
+                place,
+                storage_type,
+                ..
+            } => NodeResult::Ref(Ref::new(*place, *storage_type, None)),
+            ir::Node::Drop { node } => {
+                node_results[*node] = None;
+                return Ok(None);
+            }
+            ir::Node::StaticSubAlloc {
+                node,
+                place,
+                storage_type,
+            } => match get(node_results, node)? {
+                NodeResult::Buffer {
+                    place: buffer_place,
+                } if buffer_place == *place => {
+                    NodeResult::Ref(Ref::new(*place, *storage_type, None))
+                }
+                result => return Err(error(format!("Cannot allocate from {:?}", result))),
+            },
+            ir::Node::ReadRef { source, .. } => {
+                NodeResult::Value(get(node_results, source)?.value()?)
+            }
+            ir::Node::BorrowRef { source, .. } => {
+                NodeResult::Ref(get(node_results, source)?.reference()?.clone())
+            }
+            ir::Node::WriteRef {
+                destination,
+                source,
+                ..
+            } => {
+                let value = get(node_results, source)?.value()?;
+                get(node_results, destination)?.reference()?.set(value);
+                return Ok(None);
+            }
+            ir::Node::LocalCopy { input, output } => {
+                let value = get(node_results, input)?.value()?;
+                get(node_results, output)?.reference()?.set(value);
+                return Ok(None);
+            }
+            ir::Node::LocalDoBuiltin {
+                operation,
+                inputs,
+                outputs,
+            } => {
+                let output = get(node_results, &outputs[0])?;
+                let output = output.reference()?;
+                let value = match self.spec_node(funclet, operation)? {
+                    ir::Node::Constant { value, .. } => {
+                        Value::from_constant(native_interface, output.storage_type(), value)?
+                    }
+                    ir::Node::Select { .. } => {
+                        let inputs = get_all(node_results, inputs)?;
+                        if inputs[0].value()?.is_true()? {
+                            inputs[1].value()?
+                        } else {
+                            inputs[2].value()?
+                        }
+                    }
+                    node => return Err(error(format!("{:?} is not a built-in", node))),
+                };
+                output.set(value);
+                return Ok(None);
+            }
+            ir::Node::LocalDoExternal {
+                external_function_id,
+                inputs,
+                outputs,
+                ..
+            } => {
+                let operation = native_interface.external_functions[external_function_id.0]
+                    .get_cpu_pure_operation()
+                    .ok_or_else(|| {
+                        error(format!("{:?} is not a CPU operation", external_function_id))
+                    })?;
+                let arguments = get_all(node_results, inputs)?
+                    .iter()
+                    .map(NodeResult::value)
+                    .collect::<Result<Vec<_>>>()?;
+                let results = self.call_cpu_pure_operation(operation, &arguments)?;
+                for (output, value) in outputs.iter().zip(results) {
+                    get(node_results, output)?.reference()?.set(value);
+                }
+                return Ok(None);
+            }
+            ir::Node::BeginEncoding { place, .. } => NodeResult::Encoder(Encoder {
+                place: *place,
+                commands: Vec::new(),
+            }),
+            ir::Node::EncodeCopy {
+                encoder,
+                input,
+                output,
+            } => {
+                let command = Command::Copy {
+                    input: get(node_results, input)?,
+                    output: get(node_results, output)?,
+                };
+                self.encoder(node_results, *encoder)?.commands.push(command);
+                return Ok(None);
+            }
+            ir::Node::EncodeDoExternal {
+                encoder,
+                external_function_id,
+                inputs,
+                outputs,
+                ..
+            } => {
+                let kernel = native_interface.external_functions[external_function_id.0]
+                    .get_gpu_kernel()
+                    .ok_or_else(|| {
+                        error(format!("{:?} is not a GPU kernel", external_function_id))
+                    })?;
+                let mut inputs = get_all(node_results, inputs)?;
+                let arguments = inputs.split_off(kernel.dimensionality);
+                let workgroups = workgroup_counts(
+                    &inputs
+                        .iter()
+                        .map(NodeResult::value)
+                        .collect::<Result<Vec<_>>>()?,
+                )?;
+                let command = Command::Dispatch {
+                    external_function_id: *external_function_id,
+                    workgroups,
+                    inputs: arguments.into_boxed_slice(),
+                    outputs: get_all(node_results, outputs)?.into_boxed_slice(),
+                };
+                self.encoder(node_results, *encoder)?.commands.push(command);
+                return Ok(None);
+            }
+            ir::Node::Submit { encoder, .. } => {
+                let Encoder { place, commands } = self.encoder(node_results, *encoder)?.clone();
+                node_results[*encoder] = None;
+                for command in commands {
+                    self.run_command(command)?;
+                }
+                NodeResult::Fence { place }
+            }
+            ir::Node::SyncFence { fence, .. } => match node_results[*fence].take() {
+                Some(NodeResult::Fence { .. }) => return Ok(None),
+                result => return Err(error(format!("Expected a fence but found {:?}", result))),
+            },
+            ir::Node::DefaultJoin => NodeResult::Join(
+                default_join_opt
+                    .take()
+                    .ok_or_else(|| error("No default join point".to_string()))?,
+            ),
+            ir::Node::InlineJoin {
+                funclet: funclet_id,
+                captures,
+                continuation,
+            }
+            | ir::Node::SerializedJoin {
+                funclet: funclet_id,
+                captures,
+                continuation,
+            } => NodeResult::Join(Join(Rc::new(JoinPoint::Funclet {
+                funclet_id: *funclet_id,
+                captures: get_all(node_results, captures)?.into_boxed_slice(),
+                continuation: get(node_results, continuation)?.join()?,
+            }))),
+            node => return Err(error(format!("Unsupported node {:?}", node))),
+        };
+        Ok(Some(result))
+    }
+
+    // The node of the value specification of `funclet` that `operation` refers to
+    fn spec_node(
+        &self,
+        funclet: &'program ir::Funclet,
+        operation: &ir::Quotient,
+    ) -> Result<&'program ir::Node> {
+        let value_funclet_id = funclet
+            .spec_binding
+            .get_value_spec()
+            .funclet_id_opt
+            .ok_or_else(|| error("No value specification".to_string()))?;
+        match operation {
+            ir::Quotient::Node { node_id } => {
+                Ok(&self.program.funclets[value_funclet_id].nodes[*node_id])
+            }
+            quotient => Err(error(format!("{:?} is not a value node", quotient))),
+        }
+    }
+
+    fn encoder<'results>(
+        &self,
+        node_results: &'results mut [Option<NodeResult>],
+        encoder: ir::NodeId,
+    ) -> Result<&'results mut Encoder> {
+        match &mut node_results[encoder] {
+            Some(NodeResult::Encoder(encoder)) => Ok(encoder),
+            result => Err(error(format!("Expected an encoder but found {:?}", result))),
+        }
+    }
+
+    fn run_command(&mut self, command: Command) -> Result<()> {
+        match command {
+            Command::Copy { input, output } => {
+                let value = input.value()?;
+                output.reference()?.set(value);
+            }
+            Command::Dispatch {
+                external_function_id,
+                workgroups,
+                inputs,
+                outputs,
+            } => {
+                let native_interface = &self.program.native_interface;
+                let kernel = native_interface.external_functions[external_function_id.0]
+                    .get_gpu_kernel()
+                    .unwrap();
+                let input_bytes = inputs
+                    .iter()
+                    .zip(kernel.input_types.iter())
+                    .map(|(input, storage_type)| match input {
+                        NodeResult::Ref(reference) => match reference.get() {
+                            Some(value) => {
+                                value.to_bytes(native_interface, *storage_type).map(Some)
+                            }
+                            None => Ok(None),
+                        },
+                        input => input
+                            .value()?
+                            .to_bytes(native_interface, *storage_type)
+                            .map(Some),
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                let output_bytes = self.dispatch_kernel(kernel, workgroups, input_bytes)?;
+                for ((output, bytes), storage_type) in outputs
+                    .iter()
+                    .zip(output_bytes)
+                    .zip(kernel.output_types.iter())
+                {
+                    if let Some(bytes) = bytes {
+                        let value = Value::from_bytes(native_interface, *storage_type, &bytes)?;
+                        output.reference()?.set(value);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Runs a kernel given the contents of the buffers of its inputs, where `None` is an
+    // uninitialized buffer, and returns the contents of the buffers of its outputs
+    fn dispatch_kernel(
+        &self,
+        kernel: &ffi::GpuKernel,
+        workgroups: [u32; 3],
+        inputs: Vec<Option<Vec<u8>>>,
+    ) -> Result<Vec<Option<Vec<u8>>>> {
+        let native_interface = &self.program.native_interface;
+        let mut buffers = HashMap::new();
+        for binding in kernel.resource_bindings.iter() {
+            let bytes = match (binding.input, binding.output) {
+                (Some(input), _) => match &inputs[input] {
+                    Some(bytes) => bytes.clone(),
+                    None => {
+                        vec![0u8; value::byte_size(native_interface, kernel.input_types[input])?]
+                    }
+                },
+                (None, Some(output)) => {
+                    vec![0u8; value::byte_size(native_interface, kernel.output_types[output])?]
+                }
+                (None, None) => continue,
+            };
+            buffers.insert((binding.group as u32, binding.binding as u32), bytes);
+        }
+        kernel::dispatch(
+            kernel.shader_module.module(),
+            &kernel.entry_point,
+            workgroups,
+            &mut buffers,
+        )
+        .map_err(|e| error(format!("In kernel {}: {}", kernel.name, e.message)))?;
+        let mut outputs = vec![None; kernel.output_types.len()];
+        for binding in kernel.resource_bindings.iter() {
+            if let Some(output) = binding.output {
+                outputs[output] = buffers.remove(&(binding.group as u32, binding.binding as u32));
+            }
+        }
+        Ok(outputs)
+    }
+
+    fn call_cpu_pure_operation(
+        &self,
+        operation: &ffi::CpuPureOperation,
+        arguments: &[Value],
+    ) -> Result<Vec<Value>> {
+        let results = match BuiltinOperation::recognize(operation, &self.program.native_interface) {
+            Some(builtin) => evaluate_builtin(
+                &self.program.native_interface,
+                &builtin,
+                operation,
+                arguments,
+            )
+            .map(|result| vec![result]),
+            None => self.callbacks.call_pure(operation, arguments),
+        };
+        let results =
+            results.map_err(|message| error(format!("In {}: {}", operation.name, message)))?;
+        if results.len() != operation.output_types.len() {
+            return Err(error(format!(
+                "{} returned {} values instead of {}",
+                operation.name,
+                results.len(),
+                operation.output_types.len()
+            )));
+        }
+        Ok(results)
+    }
+
+    fn evaluate_value_funclet_at_depth(
+        &mut self,
+        funclet_id: ir::FuncletId,
+        arguments: &[Value],
+        depth: usize,
+    ) -> Result<Vec<Value>> {
+        let funclet = &self.program.funclets[funclet_id];
+        if funclet.kind != ir::FuncletKind::Value {
+            return Err(error(format!(
+                "Funclet #{} is not a value funclet",
+                funclet_id
+            )));
+        }
+        if arguments.len() != funclet.input_types.len() {
+            return Err(error(format!(
+                "Funclet #{} expects {} arguments but got {}",
+                funclet_id,
+                funclet.input_types.len(),
+                arguments.len()
+            )));
+        }
+
+        let mut node_results: Vec<Box<[Value]>> = Vec::with_capacity(funclet.nodes.len());
+        for (node_id, node) in funclet.nodes.iter().enumerate() {
+            let results = self
+                .evaluate_value_node(node, arguments, &node_results, depth)
+                .map_err(|e| {
+                    error(format!(
+                        "In funclet #{} at node #{} {:?}: {}",
+                        funclet_id, node_id, node, e.message
+                    ))
+                })?;
+            node_results.push(results);
+        }
+
+        let single = |node_id: &ir::NodeId| match &*node_results[*node_id] {
+            [value] => Ok(value.clone()),
+            _ => Err(error(format!(
+                "Node #{} of funclet #{} does not have a single result",
+                node_id, funclet_id
+            ))),
+        };
+        match &funclet.tail_edge {
+            ir::TailEdge::Return { return_values } => return_values.iter().map(single).collect(),
+            tail_edge => Err(error(format!(
+                "Funclet #{} ends with {:?}, which a value funclet cannot",
+                funclet_id, tail_edge
+            ))),
+        }
+    }
+
+    fn evaluate_value_node(
+        &mut self,
+        node: &ir::Node,
+        arguments: &[Value],
+        node_results: &[Box<[Value]>],
+        depth: usize,
+    ) -> Result<Box<[Value]>> {
+        let single = |node_id: &ir::NodeId| match &*node_results[*node_id] {
+            [value] => Ok(value.clone()),
+            _ => Err(error(format!(
+                "Node #{} does not have a single result",
+                node_id
+            ))),
+        };
+        let value = match node {
+            ir::Node::None => return Ok(Box::new([])),
+            ir::Node::Phi { index } => arguments[*index].clone(),
+            ir::Node::ExtractResult { node_id, index } => node_results[*node_id]
+                .get(*index)
+                .cloned()
+                .ok_or_else(|| error(format!("Node #{} has no result {}", node_id, index)))?,
+            ir::Node::Constant { value, type_id } => match &self.program.types[*type_id] {
+                ir::Type::NativeValue { storage_type } => {
+                    Value::from_constant(&self.program.native_interface, *storage_type, value)?
+                }
+                typ => return Err(error(format!("Constant of type {:?}", typ))),
+            },
+            ir::Node::Select {
+                condition,
+                true_case,
+                false_case,
+            } => {
+                if single(condition)?.is_true()? {
+                    single(true_case)?
+                } else {
+                    single(false_case)?
+                }
+            }
+            ir::Node::CallFunctionClass {
+                function_id,
+                arguments,
+            } => {
+                let arguments = arguments.iter().map(single).collect::<Result<Vec<_>>>()?;
+                return Ok(self
+                    .call_function_class(*function_id, &arguments, depth)?
+                    .into_boxed_slice());
+            }
+            node => {
+                return Err(error(format!(
+                    "{:?} cannot appear in a value funclet",
+                    node
+                )))
+            }
+        };
+        Ok(Box::new([value]))
+    }
+
+    // Calls the first implementation of a function class that can be evaluated: a CPU
+    // operation or GPU kernel implementing it, then its default funclet, then any value funclet
+    // bound to it
+    fn call_function_class(
+        &mut self,
+        function_class_id: ir::FunctionClassId,
+        arguments: &[Value],
+        depth: usize,
+    ) -> Result<Vec<Value>> {
+        let function_class = &self.program.function_classes[function_class_id];
+        let native_interface = &self.program.native_interface;
+        for external_function_id in function_class.external_function_ids.iter() {
+            match &native_interface.external_functions[external_function_id.0] {
+                ffi::ExternalFunction::CpuPureOperation(operation) => {
+                    return self.call_cpu_pure_operation(operation, arguments)
+                }
+                ffi::ExternalFunction::GpuKernel(kernel) => {
+                    let (dimensions, arguments) = arguments.split_at(kernel.dimensionality);
+                    let workgroups = workgroup_counts(dimensions)?;
+                    let inputs = arguments
+                        .iter()
+                        .zip(kernel.input_types.iter())
+                        .map(|(argument, storage_type)| {
+                            argument.to_bytes(native_interface, *storage_type).map(Some)
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+                    let outputs = self.dispatch_kernel(kernel, workgroups, inputs)?;
+                    return outputs
+                        .iter()
+                        .zip(kernel.output_types.iter())
+                        .map(|(bytes, storage_type)| match bytes {
+                            Some(bytes) => {
+                                Value::from_bytes(native_interface, *storage_type, bytes)
+                            }
+                            None => Err(error(format!(
+                                "Kernel {} has an output without a binding",
+                                kernel.name
+                            ))),
+                        })
+                        .collect();
+                }
+                ffi::ExternalFunction::CpuEffectfulOperation(_) => (),
+            }
+        }
+
+        let funclet_id = function_class.default_funclet_id.or_else(|| {
+            self.program
+                .funclets
+                .iter()
+                .find_map(|(funclet_id, funclet)| match funclet.spec_binding {
+                    ir::FuncletSpecBinding::Value {
+                        value_function_id_opt: Some(id),
+                    } if id == function_class_id && funclet.kind == ir::FuncletKind::Value => {
+                        Some(funclet_id)
+                    }
+                    _ => None,
+                })
+        });
+        match funclet_id {
+            _ if depth >= MAX_CALL_DEPTH => Err(error(format!(
+                "Exceeded the maximum call depth of {}",
+                MAX_CALL_DEPTH
+            ))),
+            Some(funclet_id) => {
+                self.evaluate_value_funclet_at_depth(funclet_id, arguments, depth + 1)
+            }
+            None => Err(error(format!(
+                "Function class #{} has no implementation that can be evaluated",
+                function_class_id
+            ))),
+        }
+    }
+}
+
+// Pads the dimensions of a dispatch to three, where missing dimensions are a single workgroup
+fn workgroup_counts(dimensions: &[Value]) -> Result<[u32; 3]> {
+    let mut workgroups = [1u32; 3];
+    if dimensions.len() > 3 {
+        return Err(error(format!(
+            "{} dimensions are too many",
+            dimensions.len()
+        )));
+    }
+    for (count, dimension) in workgroups.iter_mut().zip(dimensions.iter()) {
+        *count = dimension
+            .as_i128()
+            .and_then(|value| TryFrom::try_from(value).ok())
+            .ok_or_else(|| error(format!("{:?} is not a workgroup count", dimension)))?;
+    }
+    Ok(workgroups)
+}
+
+// Applies a built-in operation the way the code generated for it would
+fn evaluate_builtin(
+    native_interface: &ffi::NativeInterface,
+    builtin: &BuiltinOperation,
+    operation: &ffi::CpuPureOperation,
+    arguments: &[Value],
+) -> std::result::Result<Value, String> {
+    match builtin {
+        BuiltinOperation::Scalar(scalar) => {
+            let arguments = arguments
+                .iter()
+                .map(|argument| match (argument.as_i128(), argument.as_f64()) {
+                    (Some(value), _) => Ok(ScalarValue::Int(value)),
+                    (_, Some(value)) => Ok(ScalarValue::Float(value)),
+                    _ => Err(format!("{:?} is not a scalar", argument)),
+                })
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            let output = operation.output_types[0];
+            match scalar.evaluate(&arguments)? {
+                ScalarValue::Int(value) => Value::from_i128(native_interface, output, value),
+                ScalarValue::Float(value) => Value::from_f64(native_interface, output, value),
+            }
+            .map_err(|error| error.message)
+        }
+        BuiltinOperation::Index => {
+            let elements = match &arguments[0] {
+                Value::Array(elements) => elements,
+                value => return Err(format!("{:?} is not an array", value)),
+            };
+            let index = arguments[1]
+                .as_i128()
+                .ok_or_else(|| format!("{:?} is not an index", arguments[1]))?;
+            usize::try_from(index)
+                .ok()
+                .and_then(|index| elements.get(index))
+                .cloned()
+                .ok_or_else(|| {
+                    format!(
+                        "Index {} is out of bounds for an array of length {}",
+                        index,
+                        elements.len()
+                    )
+                })
+        }
+        BuiltinOperation::Array => Ok(Value::Array(arguments.to_vec().into_boxed_slice())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frontend::{self, CompileData, Definition};
+    use std::path::Path;
+
+    struct NoFunctions;
+
+    impl CpuFunctions for NoFunctions {}
+
+    // The CPU operations of the corpus programs, implemented as their Rust harnesses do
+    struct CorpusFunctions;
+
+    impl CpuFunctions for CorpusFunctions {
+        fn call_pure(
+            &self,
+            operation: &ffi::CpuPureOperation,
+            arguments: &[Value],
+        ) -> std::result::Result<Vec<Value>, String> {
+            Ok(vec![match (operation.name.as_str(), arguments) {
+                ("add", [Value::I32(a), Value::I32(b)]) => Value::I32(a + b),
+                ("add", [Value::I64(a), Value::I64(b)]) => Value::I64(a + b),
+                ("gt", [Value::I64(a), Value::I64(b)]) => Value::I64(i64::from(a > b)),
+                _ => return Err(format!("No implementation of {}", operation.name)),
+            }])
+        }
+
+        fn call_effectful(
+            &mut self,
+            _operation: &ffi::CpuEffectfulOperation,
+            _arguments: &[NodeResult],
+        ) -> std::result::Result<Vec<NodeResult>, String> {
+            Ok(vec![])
+        }
+    }
+
+    fn load(source: &str) -> ir::Program {
+        let definition: Definition = ron::from_str(source).unwrap();
+        definition.program
+    }
+
+    // Compiles a program of the caiman-test corpus as far as code generation would start
+    fn load_corpus(file: &str) -> ir::Program {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("caiman-test")
+            .join(file);
+        let compile_data = CompileData {
+            path: path.parent().unwrap().to_string_lossy().to_string(),
+            filename: path.to_string_lossy().to_string(),
+            input_string: std::fs::read_to_string(&path).unwrap(),
+        };
+        let program = frontend::parse_assembly(&compile_data).unwrap();
+        let definition = crate::explication::explicate(
+            frontend::lower_assembly(program, &compile_data.filename).unwrap(),
+        )
+        .unwrap();
+        frontend::check_definition(&definition).unwrap();
+        definition.program
+    }
+
+    // What the Rust harness next to a corpus program expects its pipeline to return
+    fn corpus_expected_return(file: &str) -> i128 {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("caiman-test")
+            .join(file)
+            .with_extension("rs");
+        let harness = std::fs::read_to_string(&path).unwrap();
+        let (_, expected) = harness.split_once("expect_returned!(").unwrap();
+        let (expected, _) = expected.split_once(',').unwrap();
+        expected.trim().parse().unwrap()
+    }
+
+    // A local ref holding `value`, for pipelines whose harness passes `&mut value`
+    fn local_ref(program: &ir::Program, value: Value) -> NodeResult {
+        let storage_type = program
+            .native_interface
+            .types
+            .iter()
+            .find(|(_, typ)| {
+                matches!(
+                    (&value, typ),
+                    (Value::I32(_), ffi::Type::I32) | (Value::I64(_), ffi::Type::I64)
+                )
+            })
+            .map(|(index, _)| ffi::TypeId(index))
+            .unwrap();
+        NodeResult::Ref(Ref::new(ir::Place::Local, storage_type, Some(value)))
+    }
+
+    #[test]
+    fn test_corpus_pipelines() {
+        let no_arguments = |_: &ir::Program| vec![];
+        let cases: [(&str, &dyn Fn(&ir::Program) -> Vec<NodeResult>); 8] = [
+            // schedule-select
+            ("basics/select_true_test.cair", &no_arguments),
+            ("basics/select_2_args_test.cair", &no_arguments),
+            // schedule-call, with the rest of the caller as the callee's continuation
+            ("basics/call_func_test.cair", &no_arguments),
+            ("control_flow/rec_sum_test.cair", &no_arguments),
+            // GPU kernels, around selects and calls
+            ("gpu_timeline/gpu_external_test.cair", &|program| {
+                vec![local_ref(program, Value::I32(0))]
+            }),
+            ("gpu_timeline/gpu_encode_select_test.cair", &|program| {
+                vec![
+                    local_ref(program, Value::I32(1)),
+                    NodeResult::Value(Value::I32(0)),
+                ]
+            }),
+            ("gpu_timeline/gpu_submit_call_test.cair", &|program| {
+                vec![local_ref(program, Value::I32(0))]
+            }),
+            ("gpu_timeline/gpu_external_jump_test.cair", &|program| {
+                vec![local_ref(program, Value::I32(0))]
+            }),
+        ];
+        for (file, arguments) in cases {
+            let program = load_corpus(file);
+            let mut callbacks = CorpusFunctions;
+            let mut interpreter = Interpreter::new(&program, &mut callbacks);
+            let results = interpreter
+                .run("main", arguments(&program))
+                .unwrap_or_else(|e| panic!("{}: {}", file, e));
+            assert_eq!(
+                results[0].value().unwrap().as_i128(),
+                Some(corpus_expected_return(file)),
+                "{}",
+                file
+            );
+        }
+    }
+
+    #[test]
+    fn test_pipeline_matches_value_function() {
+        let program = load(include_str!(
+            "../../caiman-test/ron/pipeline_value_function_test.ron"
+        ));
+        let mut callbacks = NoFunctions;
+        let mut interpreter = Interpreter::new(&program, &mut callbacks);
+        let input = Ref::new(ir::Place::Local, ffi::TypeId(0), Some(Value::I32(1)));
+        let results = interpreter
+            .run("pipeline_with_value_function", vec![NodeResult::Ref(input)])
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].value().unwrap(), Value::I32(2));
+        let expected = interpreter
+            .evaluate_value_funclet(10, &[Value::I32(1)])
+            .unwrap();
+        assert_eq!(expected, vec![Value::I32(2)]);
+    }
+
+    #[test]
+    fn test_yield_and_resume() {
+        let program = load(include_str!(
+            "../../caiman-test/ron/pipeline_looping_value_function_test.ron"
+        ));
+        let mut callbacks = NoFunctions;
+        let mut interpreter = Interpreter::new(&program, &mut callbacks);
+        let buffer = Ref::new(ir::Place::Gpu, ffi::TypeId(0), Some(Value::I32(0)));
+        let arguments = vec![
+            NodeResult::Ref(buffer.clone()),
+            NodeResult::Fence {
+                place: ir::Place::Gpu,
+            },
+        ];
+        let mut outcome = interpreter.start("looping_pipeline", arguments).unwrap();
+        for _ in 0..5 {
+            match outcome {
+                Outcome::Yielded {
+                    external_function_id,
+                    continuation,
+                    ..
+                } => {
+                    assert_eq!(external_function_id, ffi::ExternalFunctionId(3));
+                    outcome = interpreter.resume(continuation, vec![]).unwrap();
+                }
+                Outcome::Returned(_) => panic!("The pipeline should loop forever"),
+            }
+        }
+        assert_eq!(buffer.get(), Some(Value::I32(6)));
+    }
+
+    #[test]
+    fn test_recursive_value_function_fails() {
+        let program = load(include_str!(
+            "../../caiman-test/ron/pipeline_looping_value_function_test.ron"
+        ));
+        let mut callbacks = NoFunctions;
+        let mut interpreter = Interpreter::new(&program, &mut callbacks);
+        assert!(interpreter
+            .evaluate_value_funclet(19, &[Value::I32(0)])
+            .is_err());
+    }
+
+    #[test]
+    fn test_builtins() {
+        let mut types = crate::stable_vec::StableVec::new();
+        let u8_type = ffi::TypeId(types.add(ffi::Type::U8));
+        let i64_type = ffi::TypeId(types.add(ffi::Type::I64));
+        let array_type = ffi::TypeId(types.add(ffi::Type::Array {
+            element_type: i64_type,
+            length: 2,
+        }));
+        let native_interface = ffi::NativeInterface {
+            types,
+            ..Default::default()
+        };
+        let evaluate = |name: &str, inputs: &[ffi::TypeId], output, arguments: &[Value]| {
+            let operation = ffi::CpuPureOperation {
+                name: name.to_string(),
+                input_types: inputs.to_vec().into_boxed_slice(),
+                output_types: vec![output].into_boxed_slice(),
+            };
+            let builtin = BuiltinOperation::recognize(&operation, &native_interface).unwrap();
+            evaluate_builtin(&native_interface, &builtin, &operation, arguments)
+        };
+        assert_eq!(
+            evaluate(
+                "_add_u8_u8",
+                &[u8_type, u8_type],
+                u8_type,
+                &[Value::U8(200), Value::U8(100)]
+            ),
+            Ok(Value::U8(44))
+        );
+        let array = evaluate(
+            "_array__a2_i64",
+            &[i64_type, i64_type],
+            array_type,
+            &[Value::I64(3), Value::I64(4)],
+        )
+        .unwrap();
+        let index = |index| {
+            evaluate(
+                "_index__a2_i64_i64",
+                &[array_type, i64_type],
+                i64_type,
+                &[array.clone(), Value::I64(index)],
+            )
+        };
+        assert_eq!(index(1), Ok(Value::I64(4)));
+        assert!(index(2).is_err());
+    }
+
+    #[test]
+    fn test_constants() {
+        let mut types = crate::stable_vec::StableVec::new();
+        let f32_type = ffi::TypeId(types.add(ffi::Type::F32));
+        let u64_type = ffi::TypeId(types.add(ffi::Type::U64));
+        let native_interface = ffi::NativeInterface {
+            types,
+            ..Default::default()
+        };
+        let constant = |storage_type, constant| {
+            Value::from_constant(&native_interface, storage_type, &constant).unwrap()
+        };
+        assert_eq!(constant(f32_type, ir::Constant::F32(2.5)), Value::F32(2.5));
+        assert_eq!(
+            constant(u64_type, ir::Constant::U64(5_000_000_000)),
+            Value::U64(5_000_000_000)
+        );
+    }
+}
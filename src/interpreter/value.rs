@@ -0,0 +1,270 @@
+use super::{error, Result};
+use crate::ir;
+use crate::ir::ffi;
+use crate::rust_wgpu_backend::layout::LayoutRules;
+
+/// A value of a native (`ffi`) type
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    F32(f32),
+    F64(f64),
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    USize(usize),
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    Array(Box<[Value]>),
+    Struct(Box<[Value]>),
+}
+
+macro_rules! scalar_types {
+    ($macro:ident) => {
+        $macro! {
+            F32 f32, F64 f64, U8 u8, U16 u16, U32 u32, U64 u64, USize usize,
+            I8 i8, I16 i16, I32 i32, I64 i64
+        }
+    };
+}
+
+impl Value {
+    /// Builds the value of `storage_type` with the given integer, converting it as an `as` cast
+    /// would
+    pub fn from_i128(
+        native_interface: &ffi::NativeInterface,
+        storage_type: ffi::TypeId,
+        value: i128,
+    ) -> Result<Self> {
+        macro_rules! convert {
+            ($($variant:ident $rust_type:ident),*) => {
+                match &native_interface.types[storage_type.0] {
+                    $(ffi::Type::$variant => Ok(Self::$variant(value as $rust_type)),)*
+                    typ => Err(error(format!("Cannot make an integer of type {:?}", typ))),
+                }
+            };
+        }
+        scalar_types!(convert)
+    }
+
+    /// Builds the value of `storage_type` with the given float, converting it as an `as` cast
+    /// would
+    pub fn from_f64(
+        native_interface: &ffi::NativeInterface,
+        storage_type: ffi::TypeId,
+        value: f64,
+    ) -> Result<Self> {
+        macro_rules! convert {
+            ($($variant:ident $rust_type:ident),*) => {
+                match &native_interface.types[storage_type.0] {
+                    $(ffi::Type::$variant => Ok(Self::$variant(value as $rust_type)),)*
+                    typ => Err(error(format!("Cannot make a float of type {:?}", typ))),
+                }
+            };
+        }
+        scalar_types!(convert)
+    }
+
+    /// Builds the value of `storage_type` for an IR constant
+    pub fn from_constant(
+        native_interface: &ffi::NativeInterface,
+        storage_type: ffi::TypeId,
+        constant: &ir::Constant,
+    ) -> Result<Self> {
+        let value = match constant {
+            ir::Constant::F32(value) => {
+                return Self::from_f64(native_interface, storage_type, *value as f64)
+            }
+            ir::Constant::F64(value) => {
+                return Self::from_f64(native_interface, storage_type, *value)
+            }
+            ir::Constant::I32(value) => *value as i128,
+            ir::Constant::I64(value) => *value as i128,
+            ir::Constant::U64(value) => *value as i128,
+            ir::Constant::I8(value) => *value as i128,
+            ir::Constant::I16(value) => *value as i128,
+            ir::Constant::U8(value) => *value as i128,
+            ir::Constant::U16(value) => *value as i128,
+            ir::Constant::U32(value) => *value as i128,
+            ir::Constant::USize(value) => *value as i128,
+        };
+        Self::from_i128(native_interface, storage_type, value)
+    }
+
+    /// The value of a scalar as an integer, or `None` if it isn't an integer
+    pub fn as_i128(&self) -> Option<i128> {
+        Some(match self {
+            Self::U8(value) => *value as i128,
+            Self::U16(value) => *value as i128,
+            Self::U32(value) => *value as i128,
+            Self::U64(value) => *value as i128,
+            Self::USize(value) => *value as i128,
+            Self::I8(value) => *value as i128,
+            Self::I16(value) => *value as i128,
+            Self::I32(value) => *value as i128,
+            Self::I64(value) => *value as i128,
+            _ => return None,
+        })
+    }
+
+    /// The value of a scalar as a float, or `None` if it isn't a float
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Self::F32(value) => Some(*value as f64),
+            Self::F64(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Whether a condition holds, where any nonzero value is true
+    pub fn is_true(&self) -> Result<bool> {
+        if let Some(value) = self.as_i128() {
+            Ok(value != 0)
+        } else if let Some(value) = self.as_f64() {
+            Ok(value != 0.0)
+        } else {
+            Err(error(format!("{:?} cannot be used as a condition", self)))
+        }
+    }
+
+    /// The all-zero value of `storage_type`
+    pub fn zero(
+        native_interface: &ffi::NativeInterface,
+        storage_type: ffi::TypeId,
+    ) -> Result<Self> {
+        let size = byte_size(native_interface, storage_type)?;
+        Self::from_bytes(native_interface, storage_type, &vec![0u8; size])
+    }
+
+    /// Encodes the value as `storage_type` would be laid out in a buffer
+    pub fn to_bytes(
+        &self,
+        native_interface: &ffi::NativeInterface,
+        storage_type: ffi::TypeId,
+    ) -> Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        self.write_bytes(native_interface, storage_type, &mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn write_bytes(
+        &self,
+        native_interface: &ffi::NativeInterface,
+        storage_type: ffi::TypeId,
+        bytes: &mut Vec<u8>,
+    ) -> Result<()> {
+        macro_rules! encode {
+            ($($variant:ident $rust_type:ident),*) => {
+                match (&native_interface.types[storage_type.0], self) {
+                    $((ffi::Type::$variant, Self::$variant(value)) => {
+                        bytes.extend_from_slice(&value.to_ne_bytes());
+                    })*
+                    (ffi::Type::Array { element_type, length }, Self::Array(elements))
+                        if elements.len() == *length =>
+                    {
+                        for element in elements.iter() {
+                            element.write_bytes(native_interface, *element_type, bytes)?;
+                        }
+                    }
+                    (ffi::Type::ErasedLengthArray { element_type }, Self::Array(elements)) => {
+                        for element in elements.iter() {
+                            element.write_bytes(native_interface, *element_type, bytes)?;
+                        }
+                    }
+                    (ffi::Type::Struct { fields, .. }, Self::Struct(values))
+                        if fields.len() == values.len() =>
+                    {
+                        let start = bytes.len();
+                        for (field, value) in fields.iter().zip(values.iter()) {
+                            bytes.resize(start + field.byte_offset, 0);
+                            value.write_bytes(native_interface, field.type_id, bytes)?;
+                        }
+                        bytes.resize(start + byte_size(native_interface, storage_type)?, 0);
+                    }
+                    (typ, _) => {
+                        return Err(error(format!("Cannot encode {:?} as {:?}", self, typ)))
+                    }
+                }
+            };
+        }
+        scalar_types!(encode);
+        Ok(())
+    }
+
+    /// Decodes a value of `storage_type` from the way it is laid out in a buffer
+    pub fn from_bytes(
+        native_interface: &ffi::NativeInterface,
+        storage_type: ffi::TypeId,
+        bytes: &[u8],
+    ) -> Result<Self> {
+        use std::convert::TryInto;
+        let too_short = || {
+            error(format!(
+                "{} bytes are too few for a value of type {:?}",
+                bytes.len(),
+                native_interface.types[storage_type.0]
+            ))
+        };
+        macro_rules! decode {
+            ($($variant:ident $rust_type:ident),*) => {
+                match &native_interface.types[storage_type.0] {
+                    $(ffi::Type::$variant => {
+                        let size = std::mem::size_of::<$rust_type>();
+                        let bytes = bytes.get(..size).ok_or_else(too_short)?;
+                        Self::$variant($rust_type::from_ne_bytes(bytes.try_into().unwrap()))
+                    })*
+                    ffi::Type::Array { element_type, length } => {
+                        let size = byte_size(native_interface, *element_type)?;
+                        if bytes.len() < size * length {
+                            return Err(too_short());
+                        }
+                        Self::Array(
+                            (0..*length)
+                                .map(|index| {
+                                    Self::from_bytes(
+                                        native_interface,
+                                        *element_type,
+                                        &bytes[index * size..],
+                                    )
+                                })
+                                .collect::<Result<_>>()?,
+                        )
+                    }
+                    ffi::Type::ErasedLengthArray { element_type } => {
+                        let size = byte_size(native_interface, *element_type)?;
+                        Self::Array(
+                            bytes
+                                .chunks_exact(size)
+                                .map(|chunk| Self::from_bytes(native_interface, *element_type, chunk))
+                                .collect::<Result<_>>()?,
+                        )
+                    }
+                    ffi::Type::Struct { fields, .. } => Self::Struct(
+                        fields
+                            .iter()
+                            .map(|field| {
+                                let bytes = bytes.get(field.byte_offset..).ok_or_else(too_short)?;
+                                Self::from_bytes(native_interface, field.type_id, bytes)
+                            })
+                            .collect::<Result<_>>()?,
+                    ),
+                    typ => return Err(error(format!("Cannot decode a value of type {:?}", typ))),
+                }
+            };
+        }
+        Ok(scalar_types!(decode))
+    }
+}
+
+/// The number of bytes `storage_type` takes up in a buffer
+pub fn byte_size(
+    native_interface: &ffi::NativeInterface,
+    storage_type: ffi::TypeId,
+) -> Result<usize> {
+    native_interface
+        .calculate_type_layout(storage_type, LayoutRules::Host)
+        .map(|layout| layout.size)
+        .map_err(|why| error(why.to_string()))
+}
@@ -60,12 +60,12 @@ impl BufferFlags {
     }
 
     pub fn is_subset_of(&self, other: &Self) -> bool {
-        ((self.map_read & other.map_read) == self.map_read) &&
-        ((self.map_write & other.map_write) == self.map_write) &&
-        ((self.copy_src & other.copy_src) == self.copy_src) &&
-        ((self.copy_dst & other.copy_dst) == self.copy_dst) &&
-        ((self.storage & other.storage) == self.storage) &&
-        ((self.uniform & other.uniform) == self.uniform)
+        ((self.map_read & other.map_read) == self.map_read)
+            && ((self.map_write & other.map_write) == self.map_write)
+            && ((self.copy_src & other.copy_src) == self.copy_src)
+            && ((self.copy_dst & other.copy_dst) == self.copy_dst)
+            && ((self.storage & other.storage) == self.storage)
+            && ((self.uniform & other.uniform) == self.uniform)
     }
 }
 
@@ -96,10 +96,11 @@ impl std::fmt::Display for BufferFlags {
 }
 
 pub use crate::rust_wgpu_backend::ffi;
+use crate::rust_wgpu_backend::layout::{LayoutError, LayoutRules};
 
 pub mod analysis;
-#[cfg(feature = "fusion")]
 pub mod fusion;
+pub mod join_stack;
 pub mod validation;
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -111,28 +112,70 @@ pub enum Place {
 
 impl std::fmt::Display for Place {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{}", match self {
-            Place::Local => "Local",
-            Place::Cpu => "CPU",
-            Place::Gpu => "GPU",
-        });
+        write!(
+            f,
+            "{}",
+            match self {
+                Place::Local => "Local",
+                Place::Cpu => "CPU",
+                Place::Gpu => "GPU",
+            }
+        );
         Ok(())
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
 pub enum Constant {
     I32(i32),
     I64(i64),
     U64(u64),
+    I8(i8),
+    I16(i16),
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    USize(usize),
+    F32(f32),
+    F64(f64),
 }
 
 impl std::fmt::Display for Constant {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            Constant::I32(c) => {write!(f, "{}i32", c);}
-            Constant::I64(c) => {write!(f, "{}i64", c);},
-            Constant::U64(c) => {write!(f, "{}u64", c);},
+            Constant::I32(c) => {
+                write!(f, "{}i32", c);
+            }
+            Constant::I64(c) => {
+                write!(f, "{}i64", c);
+            }
+            Constant::U64(c) => {
+                write!(f, "{}u64", c);
+            }
+            Constant::I8(c) => {
+                write!(f, "{}i8", c);
+            }
+            Constant::I16(c) => {
+                write!(f, "{}i16", c);
+            }
+            Constant::U8(c) => {
+                write!(f, "{}u8", c);
+            }
+            Constant::U16(c) => {
+                write!(f, "{}u16", c);
+            }
+            Constant::U32(c) => {
+                write!(f, "{}u32", c);
+            }
+            Constant::USize(c) => {
+                write!(f, "{}usize", c);
+            }
+            Constant::F32(c) => {
+                write!(f, "{}f32", c);
+            }
+            Constant::F64(c) => {
+                write!(f, "{}f64", c);
+            }
         };
         Ok(())
     }
@@ -313,14 +356,18 @@ pub struct StaticBufferLayout {
 }
 
 impl StaticBufferLayout {
+    // Allocates `storage_type` in a buffer on `place`, laid out the way that place reads it
     pub fn alloc_static(
         &mut self,
         native_interface: &ffi::NativeInterface,
+        place: Place,
         storage_type: StorageTypeId,
-    ) {
+    ) -> Result<(), LayoutError> {
         // To do check alignment compatibility
-        let storage_size = native_interface.calculate_type_byte_size(storage_type);
-        let alignment_bits = native_interface.calculate_type_alignment_bits(storage_type);
+        let layout =
+            native_interface.calculate_type_layout(storage_type, LayoutRules::for_place(place))?;
+        let storage_size = layout.size;
+        let alignment_bits = layout.alignment.trailing_zeros() as usize;
         let starting_alignment_offset = 1usize << self.alignment_bits;
         let additional_alignment_offset = if alignment_bits > self.alignment_bits {
             let alignment_offset = 1usize << alignment_bits;
@@ -330,24 +377,38 @@ impl StaticBufferLayout {
         };
         let total_byte_size = storage_size + additional_alignment_offset;
 
-        assert!(self.byte_size >= total_byte_size);
-        self.byte_size -= total_byte_size;
+        self.take(total_byte_size)?;
         self.alignment_bits =
             (total_byte_size + starting_alignment_offset).trailing_zeros() as usize;
+        Ok(())
     }
 
-    pub fn split_static(&mut self, native_interface: &ffi::NativeInterface, size: usize) -> Self {
+    pub fn split_static(
+        &mut self,
+        native_interface: &ffi::NativeInterface,
+        size: usize,
+    ) -> Result<Self, LayoutError> {
         let predecessor_static_layout = Self {
             byte_size: size,
             alignment_bits: self.alignment_bits,
         };
 
-        assert!(self.byte_size >= size);
-        self.byte_size -= size;
+        self.take(size)?;
         let starting_alignment_offset = 1usize << self.alignment_bits;
         self.alignment_bits = (size + starting_alignment_offset).trailing_zeros() as usize;
 
-        return predecessor_static_layout;
+        return Ok(predecessor_static_layout);
+    }
+
+    fn take(&mut self, byte_size: usize) -> Result<(), LayoutError> {
+        if byte_size > self.byte_size {
+            return Err(LayoutError::OutOfSpace {
+                byte_size,
+                available: self.byte_size,
+            });
+        }
+        self.byte_size -= byte_size;
+        Ok(())
     }
 
     pub fn merge_static_left(
@@ -438,14 +499,18 @@ pub enum TailEdge {
     // SyncFence { fence : NodeId, immediate_funclet : FuncletId, deferred_funclet : FuncletId, arguments : Box<[NodeId]>, continuation_join : NodeId },
 
     // Split space - where the computation will be observed
-    /*DynamicAllocFromBuffer {
+    // Allocates from a buffer at runtime, one allocation per size slot
+    // Some(slot) allocates as many elements as the slot holds, None allocates a single element
+    // The success funclet takes the arguments followed by the allocations,
+    //   and the failure funclet takes just the arguments
+    DynamicAllocFromBuffer {
         buffer: NodeId,
         arguments: Box<[NodeId]>,
         dynamic_allocation_size_slots: Box<[Option<NodeId>]>,
         success_funclet_id: FuncletId,
         failure_funclet_id: FuncletId,
         continuation_join: NodeId,
-    },*/
+    },
     // Here for now as a type system debugging tool
     // Always passes type checking, but fails codegen
     DebugHole {
@@ -588,6 +653,10 @@ pub struct Pipeline {
     pub name: String,
     pub entry_funclet: FuncletId,
     pub effect_id_opt: Option<ffi::EffectId>,
+    // How many times the pipeline may go around a cycle of funclets that pushes joins, which is
+    //   what bounds the join stack of a recursive pipeline
+    #[serde(default)]
+    pub recursion_limit_opt: Option<usize>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
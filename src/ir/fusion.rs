@@ -1,9 +1,11 @@
+use crate::debug_info::DebugInfo;
 use crate::ir;
 use crate::rust_wgpu_backend::ffi;
 use crate::shadergen::{FuseDescriptor, FuseSource, FusedResource, ShaderModule};
 
+use std::cmp::Reverse;
 use std::collections::hash_map::{Entry, HashMap};
-use std::collections::HashSet;
+use std::collections::{BinaryHeap, HashSet};
 use std::ops::Range;
 
 use super::analysis::LiveRangeMap;
@@ -27,6 +29,8 @@ pub struct Opportunity {
     pub inputs: Vec<ir::NodeId>,
     /// The concrete outputs of the fused kernel.
     pub outputs: Vec<ir::NodeId>,
+    /// The scheduling nodes of the dispatches being fused, in order.
+    pub dispatches: Vec<ir::NodeId>,
 }
 
 #[derive(Debug)]
@@ -43,16 +47,26 @@ struct SlotState {
     sources: Vec<(usize, u32, u32)>,
 }
 
+// The GPU kernel `id` names, which is an error for any other external function
+fn gpu_kernel(prog: &ir::Program, id: ir::ExternalFunctionId) -> Result<&ffi::GpuKernel, String> {
+    prog.native_interface.external_functions[id.0]
+        .get_gpu_kernel()
+        .ok_or_else(|| format!("External function #{} is not a GPU kernel", id.0))
+}
+
 #[derive(Debug)]
 struct FuseState<'a> {
     prog: &'a ir::Program,
     start: ir::NodeId,
     live_ranges: &'a LiveRangeMap,
     workgroup_dimensions: [Option<ir::NodeId>; 3],
+    encoder: ir::NodeId,
+    dimensionality: usize,
     local_size: [u32; 3],
     next_binding: u32,
     /// (shader source, entry point)
     kernels: Vec<(&'a ShaderModule, &'a str)>,
+    dispatches: Vec<ir::NodeId>,
     resources: HashMap<(usize, u32, u32), FusedResource>,
     slots: HashMap<ir::NodeId, SlotState>,
 }
@@ -63,10 +77,8 @@ impl<'a> FuseState<'a> {
         live_ranges: &'a LiveRangeMap,
         start: ir::NodeId,
         dispatch: DispatchInfo,
-    ) -> FuseState<'a> {
-        let kernel = prog.native_interface.external_functions[dispatch.id.0]
-            .get_gpu_kernel()
-            .expect("kernel fusion: not a GPU kernel!");
+    ) -> Result<FuseState<'a>, String> {
+        let kernel = gpu_kernel(prog, dispatch.id)?;
 
         let local_size = kernel.shader_module.local_size(&kernel.entry_point);
 
@@ -75,17 +87,20 @@ impl<'a> FuseState<'a> {
             start,
             live_ranges,
             workgroup_dimensions: dispatch.workgroup_dimensions,
+            encoder: dispatch.encoder,
+            dimensionality: kernel.dimensionality,
             local_size,
             next_binding: 0,
             kernels: Vec::new(),
+            dispatches: Vec::new(),
             resources: HashMap::new(),
             slots: HashMap::new(),
         };
 
         // TODO: Shader setup is duplicated here
-        let result = state.fuse(dispatch);
+        let result = state.fuse(dispatch)?;
         assert!(result);
-        return state;
+        return Ok(state);
     }
 
     fn register(
@@ -102,7 +117,9 @@ impl<'a> FuseState<'a> {
             Entry::Occupied(mut entry) => {
                 let state = entry.get_mut();
                 assert_eq!(ty, state.ty, "mismatched kernel types");
-                state.input |= is_input;
+                // A slot written by an earlier kernel is read from the fused kernel's own
+                // output, so its contents before the dispatch don't matter
+                state.input |= is_input && !state.output;
                 state.output |= !is_input;
                 state.sources.push(key);
                 state.binding
@@ -124,17 +141,17 @@ impl<'a> FuseState<'a> {
             .insert(key, FusedResource::Binding { group: 0, binding });
     }
 
-    pub fn fuse(&mut self, dispatch: DispatchInfo) -> bool {
-        if self.workgroup_dimensions != dispatch.workgroup_dimensions {
-            return false;
+    pub fn fuse(&mut self, dispatch: DispatchInfo) -> Result<bool, String> {
+        if self.workgroup_dimensions != dispatch.workgroup_dimensions
+            || self.encoder != dispatch.encoder
+        {
+            return Ok(false);
         }
 
-        let kernel = self.prog.native_interface.external_functions[dispatch.id.0]
-            .get_gpu_kernel()
-            .expect("kernel fusion: not a GPU kernel!");
+        let kernel = gpu_kernel(self.prog, dispatch.id)?;
 
         if self.local_size != kernel.shader_module.local_size(&kernel.entry_point) {
-            return false;
+            return Ok(false);
         }
 
         let module_index = self.kernels.len();
@@ -148,7 +165,12 @@ impl<'a> FuseState<'a> {
                 .resource_bindings
                 .iter()
                 .find(|r| r.input == Some(in_index))
-                .expect("unknown input");
+                .ok_or_else(|| {
+                    format!(
+                        "Kernel {} has no binding for input {}",
+                        kernel.name, in_index
+                    )
+                })?;
             dependency_chain |= self.slots.contains_key(input);
             self.register(module_index, *input, ty, true, resource);
         }
@@ -159,7 +181,12 @@ impl<'a> FuseState<'a> {
                 .resource_bindings
                 .iter()
                 .find(|r| r.output == Some(out_index))
-                .expect("unknown output");
+                .ok_or_else(|| {
+                    format!(
+                        "Kernel {} has no binding for output {}",
+                        kernel.name, out_index
+                    )
+                })?;
             self.register(module_index, *output, ty, false, resource);
         }
 
@@ -168,9 +195,10 @@ impl<'a> FuseState<'a> {
         if (self.kernels.is_empty() || dependency_chain) {
             self.kernels
                 .push((&kernel.shader_module, &kernel.entry_point));
-            return true;
+            self.dispatches.push(dispatch.node);
+            return Ok(true);
         } else {
-            return false;
+            return Ok(false);
         }
     }
 
@@ -250,7 +278,9 @@ impl<'a> FuseState<'a> {
         // Each GPU binding should have at most one input slot and at most one output slot
         // assigned to it. Otherwise we would be merging equivalent inputs or equivalent outputs,
         // which is out-of-scope
-        for (&slot, state) in self.slots.iter() {
+        let mut slots: Vec<_> = self.slots.iter().collect();
+        slots.sort_by_key(|(_, state)| state.binding);
+        for (&slot, state) in slots {
             // If this is elided, we'll turn it into a global variable.
             // We won't even bother adding it to the input/output slots
             if elided_temps.contains(&slot) {
@@ -300,6 +330,7 @@ impl<'a> FuseState<'a> {
             name: format!("fused_funclet{}_node{}", funclet_id, self.start),
             input_types: input_types.into_boxed_slice(),
             output_types: output_types.into_boxed_slice(),
+            dimensionality: self.dimensionality,
             entry_point: "main".to_owned(),
             resource_bindings: resource_bindings.into_boxed_slice(),
             shader_module,
@@ -310,12 +341,19 @@ impl<'a> FuseState<'a> {
             dimensions: self.workgroup_dimensions,
             inputs,
             outputs,
+            dispatches: self.dispatches,
         });
     }
 }
 
 #[derive(Debug, Clone)]
 struct DispatchInfo {
+    /// The scheduling node encoding this dispatch.
+    node: ir::NodeId,
+    /// The encoder the dispatch is encoded into.
+    encoder: ir::NodeId,
+    /// The value node the dispatch implements.
+    operation: ir::Quotient,
     /// The ID of the kernel we're dispatching.
     id: ir::ExternalFunctionId,
     /// IDs of the scheduling nodes providing the kernel dimensions, if they're assigned.
@@ -327,22 +365,30 @@ struct DispatchInfo {
 }
 
 impl DispatchInfo {
-    fn from_node(prog: &ir::Program, node: &ir::Node) -> Option<Self> {
-        use ir::Node::{CallExternalGpuCompute, EncodeDo};
-        let EncodeDo { operation, inputs, outputs, .. } = node else {
-            return None;
-        };
-        let remote = &prog.funclets[operation.funclet_id].nodes[operation.node_id];
-        let CallExternalGpuCompute { external_function_id, dimensions, .. } = remote else {
+    fn from_node(prog: &ir::Program, id: ir::NodeId, node: &ir::Node) -> Option<Self> {
+        let ir::Node::EncodeDoExternal {
+            encoder,
+            operation,
+            external_function_id,
+            inputs,
+            outputs,
+        } = node
+        else {
             return None;
         };
+        let kernel =
+            prog.native_interface.external_functions[external_function_id.0].get_gpu_kernel()?;
+        let dimensionality = kernel.dimensionality;
         let mut true_dims = [None; 3];
-        for i in 0..dimensions.len() {
+        for i in 0..dimensionality {
             true_dims[i] = Some(inputs[i]);
         }
-        let true_inputs = Vec::from(&inputs[dimensions.len()..]);
+        let true_inputs = Vec::from(&inputs[dimensionality..]);
         let true_outputs = Vec::from(&outputs[..]);
         return Some(Self {
+            node: id,
+            encoder: *encoder,
+            operation: *operation,
             id: *external_function_id,
             workgroup_dimensions: true_dims,
             inputs: true_inputs,
@@ -362,7 +408,7 @@ impl FusionInfo {
         funclet_id: ir::FuncletId,
         funclet: &ir::Funclet,
         live_ranges: &LiveRangeMap,
-    ) -> Self {
+    ) -> Result<Self, String> {
         // Nothing goes wrong if we run this on a non-scheduling funclet.
         // But there is literally zero reason to run this on a non-scheduling funclet, so it's
         // probably a bug if it ever gets called on one...
@@ -379,11 +425,11 @@ impl FusionInfo {
             }
 
             // First: is this even a kernel?
-            if let Some(dispatch) = DispatchInfo::from_node(prog, node) {
+            if let Some(dispatch) = DispatchInfo::from_node(prog, id, node) {
                 // Alright, it's a kernel. Are we already fusing?
                 if let Some(fs) = state.as_mut() {
                     // We're already fusing. Can we add this?
-                    if fs.fuse(dispatch.clone()) {
+                    if fs.fuse(dispatch.clone())? {
                         // Yes, we can fuse the current node into our existing dispatch!
                         continue;
                     } else {
@@ -395,11 +441,11 @@ impl FusionInfo {
                             &mut opportunities,
                             &mut elided_temps,
                         );
-                        state = Some(FuseState::new(prog, live_ranges, id, dispatch));
+                        state = Some(FuseState::new(prog, live_ranges, id, dispatch)?);
                     }
                 } else {
                     // Ok, we're not already fusing. Let's start!
-                    state = Some(FuseState::new(prog, live_ranges, id, dispatch));
+                    state = Some(FuseState::new(prog, live_ranges, id, dispatch)?);
                 }
             } else {
                 // Alright, it's not a kernel. If we were in the middle of fusing, finish the job.
@@ -419,9 +465,529 @@ impl FusionInfo {
             );
         }
 
-        return Self {
+        return Ok(Self {
             opportunities,
             elided_temps,
+        });
+    }
+}
+
+/// Fuses the runs of GPU dispatches `FusionInfo` finds in every scheduling funclet of `prog`,
+/// returning how many fused kernels were added.
+///
+/// The fused dispatch takes the place of the first dispatch of its run, and the rest become
+/// `None` nodes. The value funclet the schedule implements is rewritten to call the fused
+/// kernel through a new function class, renumbering its nodes so that the results of the fused
+/// call directly follow it. A run is left alone if anything other than its dispatches refers
+/// to a value node the rewrite removes.
+///
+/// Fails if a dispatch doesn't match the kernel it names, which the type checker rules out.
+pub fn fuse_program(prog: &mut ir::Program, debug_info: &mut DebugInfo) -> Result<usize, String> {
+    let funclet_ids: Vec<ir::FuncletId> = prog
+        .funclets
+        .iter()
+        .filter(|(_, funclet)| funclet.kind == ir::FuncletKind::ScheduleExplicit)
+        .map(|(funclet_id, _)| funclet_id)
+        .collect();
+    let mut fused = 0;
+    for funclet_id in funclet_ids {
+        // Every rewrite removes dispatches, so this runs out of opportunities eventually
+        loop {
+            let funclet = &prog.funclets[funclet_id];
+            let live_ranges = super::analysis::live_ranges(funclet);
+            let info = FusionInfo::within_funclet(prog, funclet_id, funclet, &live_ranges)?;
+            let rewrite = info
+                .opportunities
+                .into_iter()
+                .find_map(|opportunity| Rewrite::plan(prog, funclet_id, opportunity));
+            match rewrite {
+                Some(rewrite) => rewrite.apply(prog, debug_info),
+                None => break,
+            }
+            fused += 1;
+        }
+    }
+    Ok(fused)
+}
+
+/// A fusion opportunity, along with every funclet it changes.
+struct Rewrite {
+    opportunity: Opportunity,
+    funclet_id: ir::FuncletId,
+    value_funclet_id: ir::FuncletId,
+    /// The arguments of the fused call, including the workgroup dimensions.
+    dimensions: Vec<ir::NodeId>,
+    input_types: Vec<ir::TypeId>,
+    output_types: Vec<ir::TypeId>,
+    /// Where the fused call is in the rewritten value funclet.
+    fused_call: ir::NodeId,
+    /// Where each node of the value funclet moved to, if it's still there.
+    renumbering: HashMap<ir::NodeId, ir::NodeId>,
+    /// The rewritten funclets, with the fused call and dispatch left to fill in.
+    funclets: Vec<(ir::FuncletId, ir::Funclet)>,
+}
+
+impl Rewrite {
+    fn plan(
+        prog: &ir::Program,
+        funclet_id: ir::FuncletId,
+        opportunity: Opportunity,
+    ) -> Option<Self> {
+        let funclet = &prog.funclets[funclet_id];
+        let value_funclet_id = funclet.spec_binding.get_value_spec().funclet_id_opt?;
+        let value_funclet = &prog.funclets[value_funclet_id];
+        let dispatches = opportunity
+            .dispatches
+            .iter()
+            .map(|&node| DispatchInfo::from_node(prog, node, &funclet.nodes[node]))
+            .collect::<Option<Vec<_>>>()?;
+
+        // The value call each dispatch implements
+        let mut calls = Vec::new();
+        for dispatch in dispatches.iter() {
+            let ir::Quotient::Node { node_id } = dispatch.operation else {
+                return None;
+            };
+            let ir::Node::CallFunctionClass {
+                function_id,
+                arguments,
+            } = &value_funclet.nodes[node_id]
+            else {
+                return None;
+            };
+            calls.push((node_id, *function_id, arguments));
+        }
+
+        // Inputs take the value they had when first read, and outputs the value last written
+        let dimensionality = opportunity.kernel.dimensionality;
+        let mut arguments = calls[0].2[..dimensionality].to_vec();
+        let dimensions = dispatches[0].workgroup_dimensions[..dimensionality]
+            .iter()
+            .map(|dimension| dimension.unwrap())
+            .collect();
+        let mut input_types = Vec::new();
+        for slot in opportunity.inputs.iter() {
+            let (index, input) = dispatches
+                .iter()
+                .enumerate()
+                .find_map(|(index, dispatch)| {
+                    let input = dispatch.inputs.iter().position(|input| input == slot)?;
+                    Some((index, input))
+                })?;
+            let (_, function_id, call_arguments) = calls[index];
+            arguments.push(call_arguments[dimensionality + input]);
+            input_types.push(prog.function_classes[function_id].input_types[input]);
+        }
+        let mut results = HashMap::new();
+        let mut output_types = Vec::new();
+        for (fused_output, slot) in opportunity.outputs.iter().enumerate() {
+            let (index, output) =
+                dispatches
+                    .iter()
+                    .enumerate()
+                    .rev()
+                    .find_map(|(index, dispatch)| {
+                        let output = dispatch.outputs.iter().position(|output| output == slot)?;
+                        Some((index, output))
+                    })?;
+            let (call, function_id, _) = calls[index];
+            // The type checker expects the results of a call to directly follow it
+            let result = call + 1 + output;
+            match value_funclet.nodes.get(result) {
+                Some(ir::Node::ExtractResult { node_id, index })
+                    if (*node_id, *index) == (call, output) => {}
+                _ => return None,
+            }
+            results.insert(result, fused_output);
+            output_types.push(prog.function_classes[function_id].output_types[output]);
+        }
+
+        // The calls and their results are removed, and the fused call takes the place of the
+        // last call
+        let call_ids: HashSet<_> = calls.iter().map(|(call, _, _)| *call).collect();
+        let mut removed = call_ids.clone();
+        for (node_id, node) in value_funclet.nodes.iter().enumerate() {
+            if let ir::Node::ExtractResult { node_id: call, .. } = node {
+                if call_ids.contains(call) {
+                    removed.insert(node_id);
+                }
+            }
+        }
+        let fused_position = calls.last().unwrap().0;
+        if arguments.iter().any(|argument| removed.contains(argument)) {
+            return None;
+        }
+
+        // Kahn's algorithm, preferring the original order
+        let mut dependencies = HashMap::new();
+        for (node_id, node) in value_funclet.nodes.iter().enumerate() {
+            if removed.contains(&node_id) {
+                continue;
+            }
+            let mut referenced = Vec::new();
+            let mut gone = false;
+            let _ = node.map_referenced_nodes(|referenced_id| {
+                if !removed.contains(&referenced_id) {
+                    referenced.push(referenced_id);
+                } else if results.contains_key(&referenced_id) {
+                    referenced.push(fused_position);
+                } else {
+                    gone = true;
+                }
+                referenced_id
+            });
+            if gone {
+                return None;
+            }
+            dependencies.insert(node_id, referenced);
+        }
+        dependencies.insert(fused_position, arguments.clone());
+        let mut waiting_on: HashMap<ir::NodeId, usize> = HashMap::new();
+        let mut dependents: HashMap<ir::NodeId, Vec<ir::NodeId>> = HashMap::new();
+        for (&node_id, referenced) in dependencies.iter() {
+            waiting_on.insert(node_id, referenced.len());
+            for &referenced_id in referenced.iter() {
+                dependents.entry(referenced_id).or_default().push(node_id);
+            }
+        }
+        let mut ready: BinaryHeap<Reverse<ir::NodeId>> = waiting_on
+            .iter()
+            .filter(|(_, count)| **count == 0)
+            .map(|(node_id, _)| Reverse(*node_id))
+            .collect();
+        let mut renumbering = HashMap::new();
+        let mut order = Vec::new();
+        let mut next = 0;
+        while let Some(Reverse(node_id)) = ready.pop() {
+            order.push(node_id);
+            renumbering.insert(node_id, next);
+            next += if node_id == fused_position {
+                1 + opportunity.outputs.len()
+            } else {
+                1
+            };
+            for dependent in dependents.remove(&node_id).unwrap_or_default() {
+                let count = waiting_on.get_mut(&dependent).unwrap();
+                *count -= 1;
+                if *count == 0 {
+                    ready.push(Reverse(dependent));
+                }
+            }
+        }
+        if order.len() != dependencies.len() {
+            return None;
+        }
+        let fused_call = renumbering[&fused_position];
+        renumbering.remove(&fused_position);
+        for (&result, &fused_output) in results.iter() {
+            renumbering.insert(result, fused_call + 1 + fused_output);
+        }
+
+        let mut nodes = Vec::new();
+        for node_id in order {
+            if node_id == fused_position {
+                nodes.push(ir::Node::CallFunctionClass {
+                    // filled in by `apply`, once the function class exists
+                    function_id: 0,
+                    arguments: arguments
+                        .iter()
+                        .map(|argument| renumbering[argument])
+                        .collect(),
+                });
+                for index in 0..opportunity.outputs.len() {
+                    nodes.push(ir::Node::ExtractResult {
+                        node_id: fused_call,
+                        index,
+                    });
+                }
+            } else {
+                nodes.push(
+                    value_funclet.nodes[node_id]
+                        .map_referenced_nodes(|referenced_id| renumbering[&referenced_id]),
+                );
+            }
+        }
+        let tail_edge = match &value_funclet.tail_edge {
+            ir::TailEdge::Return { return_values } => ir::TailEdge::Return {
+                return_values: return_values
+                    .iter()
+                    .map(|node_id| renumbering.get(node_id).copied())
+                    .collect::<Option<_>>()?,
+            },
+            _ => return None,
+        };
+        let mut funclets = vec![(
+            value_funclet_id,
+            ir::Funclet {
+                nodes: nodes.into_boxed_slice(),
+                tail_edge,
+                ..value_funclet.clone()
+            },
+        )];
+
+        // Everything implementing the value funclet refers to its nodes by number
+        for (schedule_id, schedule) in prog.funclets.iter() {
+            let ir::FuncletSpecBinding::ScheduleExplicit { value, .. } = &schedule.spec_binding
+            else {
+                continue;
+            };
+            if value.funclet_id_opt != Some(value_funclet_id) {
+                continue;
+            }
+            let skipped: &[ir::NodeId] = if schedule_id == funclet_id {
+                &opportunity.dispatches
+            } else {
+                &[]
+            };
+            funclets.push((
+                schedule_id,
+                renumber_schedule(schedule, &renumbering, skipped)?,
+            ));
+        }
+
+        Some(Self {
+            opportunity,
+            funclet_id,
+            value_funclet_id,
+            dimensions,
+            input_types,
+            output_types,
+            fused_call,
+            renumbering,
+            funclets,
+        })
+    }
+
+    fn apply(self, prog: &mut ir::Program, debug_info: &mut DebugInfo) {
+        let Self {
+            opportunity,
+            funclet_id,
+            value_funclet_id,
+            dimensions,
+            input_types,
+            output_types,
+            fused_call,
+            renumbering,
+            funclets,
+        } = self;
+        let name = opportunity.kernel.name.clone();
+        let external_function_id = ffi::ExternalFunctionId(
+            prog.native_interface
+                .external_functions
+                .add(ffi::ExternalFunction::GpuKernel(opportunity.kernel)),
+        );
+        let function_class_id = prog.function_classes.add(ir::FunctionClass {
+            name_opt: Some(name.clone()),
+            input_types: input_types.into_boxed_slice(),
+            output_types: output_types.into_boxed_slice(),
+            default_funclet_id: None,
+            external_function_ids: std::iter::once(external_function_id).collect(),
+        });
+        debug_info
+            .external_function_map
+            .insert(external_function_id.0, name.clone());
+        debug_info
+            .function_class_map
+            .insert(function_class_id, name);
+
+        for (id, mut funclet) in funclets {
+            let mut nodes = funclet.nodes.into_vec();
+            if id == value_funclet_id {
+                if let ir::Node::CallFunctionClass { function_id, .. } = &mut nodes[fused_call] {
+                    *function_id = function_class_id;
+                }
+            }
+            if id == funclet_id {
+                let first = opportunity.dispatches[0];
+                let ir::Node::EncodeDoExternal { encoder, .. } = nodes[first] else {
+                    unreachable!("fused dispatches are EncodeDoExternal nodes");
+                };
+                nodes[first] = ir::Node::EncodeDoExternal {
+                    encoder,
+                    operation: ir::Quotient::Node {
+                        node_id: fused_call,
+                    },
+                    external_function_id,
+                    inputs: dimensions
+                        .iter()
+                        .chain(opportunity.inputs.iter())
+                        .copied()
+                        .collect(),
+                    outputs: opportunity.outputs.iter().copied().collect(),
+                };
+                for &dispatch in opportunity.dispatches[1..].iter() {
+                    nodes[dispatch] = ir::Node::None;
+                }
+            }
+            funclet.nodes = nodes.into_boxed_slice();
+            prog.funclets[id] = funclet;
+        }
+
+        if let Some(funclet_map) = debug_info.funclet_map.get_mut(&value_funclet_id) {
+            funclet_map.node_map = funclet_map
+                .node_map
+                .drain()
+                .filter_map(|(quot, name)| Some((renumber(quot, &renumbering)?, name)))
+                .collect();
+        }
+    }
+}
+
+fn renumber(
+    quot: ir::Quotient,
+    renumbering: &HashMap<ir::NodeId, ir::NodeId>,
+) -> Option<ir::Quotient> {
+    match quot {
+        ir::Quotient::Node { node_id } => Some(ir::Quotient::Node {
+            node_id: *renumbering.get(&node_id)?,
+        }),
+        quot => Some(quot),
+    }
+}
+
+/// Renumbers the value quotients of a scheduling funclet, other than those of the `skipped` nodes
+fn renumber_schedule(
+    schedule: &ir::Funclet,
+    renumbering: &HashMap<ir::NodeId, ir::NodeId>,
+    skipped: &[ir::NodeId],
+) -> Option<ir::Funclet> {
+    let renumber_tag = |tag: &ir::Tag| {
+        Some(ir::Tag {
+            quot: renumber(tag.quot, renumbering)?,
+            flow: tag.flow,
+        })
+    };
+    let renumber_tags = |tags: &[ir::Tag]| tags.iter().map(renumber_tag).collect::<Option<_>>();
+    let mut spec_binding = schedule.spec_binding.clone();
+    if let ir::FuncletSpecBinding::ScheduleExplicit { value, .. } = &mut spec_binding {
+        value.input_tags = renumber_tags(&value.input_tags)?;
+        value.output_tags = renumber_tags(&value.output_tags)?;
+        value.implicit_in_tag = renumber_tag(&value.implicit_in_tag)?;
+        value.implicit_out_tag = renumber_tag(&value.implicit_out_tag)?;
+    }
+
+    let mut nodes = schedule.nodes.clone().into_vec();
+    for (node_id, node) in nodes.iter_mut().enumerate() {
+        if skipped.contains(&node_id) {
+            continue;
+        }
+        match node {
+            ir::Node::LocalDoBuiltin { operation, .. }
+            | ir::Node::LocalDoExternal { operation, .. }
+            | ir::Node::EncodeDoExternal { operation, .. } => {
+                *operation = renumber(*operation, renumbering)?;
+            }
+            _ => {}
+        }
+    }
+
+    let mut tail_edge = schedule.tail_edge.clone();
+    match &mut tail_edge {
+        ir::TailEdge::ScheduleCall {
+            value_operation, ..
+        }
+        | ir::TailEdge::ScheduleSelect {
+            value_operation, ..
+        }
+        | ir::TailEdge::ScheduleCallYield {
+            value_operation, ..
+        } => {
+            *value_operation = renumber(*value_operation, renumbering)?;
+        }
+        _ => {}
+    }
+
+    Some(ir::Funclet {
+        spec_binding,
+        nodes: nodes.into_boxed_slice(),
+        tail_edge,
+        ..schedule.clone()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frontend::{self, CompileData, Definition};
+    use crate::interpreter::{CpuFunctions, Interpreter, NodeResult, Ref, Value};
+    use std::path::{Path, PathBuf};
+
+    struct NoFunctions;
+
+    impl CpuFunctions for NoFunctions {}
+
+    fn test_file(file: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("caiman-test")
+            .join(file)
+    }
+
+    fn checked_definition(file: &str) -> Definition {
+        let path = test_file(file);
+        let data = CompileData {
+            path: path.parent().unwrap().to_string_lossy().to_string(),
+            filename: path.to_string_lossy().to_string(),
+            input_string: std::fs::read_to_string(&path).unwrap(),
         };
+        let program = frontend::parse_assembly(&data).unwrap();
+        let definition = frontend::lower_assembly(program, &data.filename).unwrap();
+        let definition = crate::explication::explicate(definition).unwrap();
+        frontend::check_definition(&definition).unwrap();
+        definition
+    }
+
+    fn fused_definition(file: &str) -> (Definition, usize) {
+        let mut definition = checked_definition(file);
+        let fused = fuse_program(&mut definition.program, &mut definition.debug_info).unwrap();
+        frontend::check_definition(&definition).unwrap();
+        (definition, fused)
+    }
+
+    fn run(program: &ir::Program, x: i32) -> Value {
+        let mut callbacks = NoFunctions;
+        let mut interpreter = Interpreter::new(program, &mut callbacks);
+        let input = Ref::new(ir::Place::Local, ffi::TypeId(0), Some(Value::I32(x)));
+        let results = interpreter
+            .run("main", vec![NodeResult::Ref(input)])
+            .unwrap();
+        results[0].value().unwrap()
+    }
+
+    // set CAIMAN_UPDATE_GOLDEN to rewrite the expected shader instead
+    #[test]
+    fn fused_kernel_matches_golden_wgsl() {
+        let (definition, fused) = fused_definition("optimizations/kernel_fusion_test.cair");
+        assert_eq!(fused, 1);
+        let kernels: Vec<_> = definition
+            .program
+            .native_interface
+            .external_functions
+            .iter()
+            .filter_map(|(_, function)| function.get_gpu_kernel())
+            .filter(|kernel| kernel.name.starts_with("fused_"))
+            .collect();
+        assert_eq!(kernels.len(), 1);
+        let wgsl = kernels[0].shader_module.emit_wgsl().unwrap();
+        let golden = test_file("optimizations/kernel_fusion_test.wgsl");
+        if std::env::var_os("CAIMAN_UPDATE_GOLDEN").is_some() {
+            std::fs::write(&golden, &wgsl).unwrap();
+        }
+        assert_eq!(wgsl, std::fs::read_to_string(&golden).unwrap());
+    }
+
+    #[test]
+    fn fused_program_matches_unfused() {
+        let unfused = checked_definition("optimizations/kernel_fusion_test.cair");
+        let (fused, _) = fused_definition("optimizations/kernel_fusion_test.cair");
+        for x in [-3, 0, 1, 20] {
+            assert_eq!(run(&fused.program, x), Value::I32((x + 1) * 2));
+            assert_eq!(run(&fused.program, x), run(&unfused.program, x));
+        }
+    }
+
+    #[test]
+    fn dispatches_in_separate_encodings_are_not_fused() {
+        let (_, fused) = fused_definition("gpu_timeline/gpu_call_sync_test.cair");
+        assert_eq!(fused, 0);
     }
 }
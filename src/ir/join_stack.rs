@@ -0,0 +1,511 @@
+// How many serialized joins a pipeline can have waiting on its join stack at once
+//
+// A serialized join is pushed when the node creating it runs and popped when it's jumped or
+//   returned to, just before its funclet runs. So while a funclet runs, everything it pushed
+//   is still waiting below whatever the funclets it calls push, and everything but the join a
+//   continuation funclet was popped for is still waiting below whatever that funclet pushes.
+//   The bound for a funclet is then its own joins plus the largest bound of the funclets it
+//   leads to, less the join it leads through.
+//
+// An inline join isn't pushed when a jump leads to it, but the backend serializes one that a call,
+//   select, or yield leads to unless its funclet does nothing but pass its inputs on.
+//
+// A cycle of funclets that pushes joins every time around can grow the stack without bound,
+//   which is reported unless the pipeline gives a recursion limit. Cycles that push nothing
+//   (or pop everything they push, like most loops) don't grow the stack.
+//
+// The size of a join depends on how the backend lays out its captures, so bounds count joins
+//   by kind rather than by bytes, and the backend adds up the bytes.
+
+use crate::ir;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+// a kind of serialized join, as the funclet it continues to and how many nodes it captures
+pub type JoinKind = (ir::FuncletId, usize);
+
+// how many joins of each kind are waiting at once
+pub type PendingJoins = BTreeMap<JoinKind, usize>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JoinStackBound {
+    // the stack never holds more than one of these at once, and which is largest in bytes
+    //   depends on the layout of the joins
+    Bounded(Vec<PendingJoins>),
+    // going around `cycle` pushes joins every time, and the pipeline gives no recursion limit
+    Unbounded { cycle: Vec<ir::FuncletId> },
+}
+
+impl JoinStackBound {
+    pub fn is_bounded(&self) -> bool {
+        match self {
+            JoinStackBound::Bounded(_) => true,
+            JoinStackBound::Unbounded { .. } => false,
+        }
+    }
+
+    // the most joins of any kind that can be waiting at once, or `None` if unbounded
+    pub fn max_join_count(&self) -> Option<usize> {
+        match self {
+            JoinStackBound::Bounded(alternatives) => Some(
+                alternatives
+                    .iter()
+                    .map(|pending| pending.values().sum())
+                    .max()
+                    .unwrap_or(0),
+            ),
+            JoinStackBound::Unbounded { .. } => None,
+        }
+    }
+}
+
+struct Edge {
+    target: ir::FuncletId,
+    // the joins still waiting when `target` starts, on top of whatever was there before the
+    //   funclet the edge leaves
+    waiting: PendingJoins,
+}
+
+struct FuncletJoins {
+    pushed: PendingJoins,
+    edges: Vec<Edge>,
+}
+
+fn add(pending: &mut PendingJoins, other: &PendingJoins, times: usize) {
+    if times == 0 {
+        return;
+    }
+    for (kind, count) in other.iter() {
+        *pending.entry(*kind).or_insert(0) += count * times;
+    }
+}
+
+fn remove_one(pending: &mut PendingJoins, kind: JoinKind) {
+    let count = pending.get_mut(&kind).unwrap();
+    *count -= 1;
+    if *count == 0 {
+        pending.remove(&kind);
+    }
+}
+
+fn is_covered_by(pending: &PendingJoins, other: &PendingJoins) -> bool {
+    pending.iter().all(|(kind, count)| {
+        other
+            .get(kind)
+            .map_or(false, |other_count| count <= other_count)
+    })
+}
+
+// drops every alternative that another one needs at least as much stack as
+fn prune(alternatives: Vec<PendingJoins>) -> Vec<PendingJoins> {
+    let mut kept = Vec::<PendingJoins>::new();
+    for (index, pending) in alternatives.iter().enumerate() {
+        let dominated = alternatives.iter().enumerate().any(|(other_index, other)| {
+            other_index != index
+                && is_covered_by(pending, other)
+                && (pending != other || other_index < index)
+        });
+        if !dominated {
+            kept.push(pending.clone());
+        }
+    }
+    kept
+}
+
+// whether a continuation to `funclet_id` with `captures_len` captures can be left out, since
+//   the funclet only takes phis and returns or jumps on with them, in order, to another such
+//   funclet
+pub fn is_trivial_join(
+    program: &ir::Program,
+    funclet_id: ir::FuncletId,
+    captures_len: usize,
+) -> bool {
+    if captures_len != 0 {
+        return false;
+    }
+    let funclet = &program.funclets[funclet_id];
+    let only_joins = funclet.nodes.iter().all(|node| {
+        matches!(
+            node,
+            ir::Node::Phi { .. } | ir::Node::DefaultJoin { .. } | ir::Node::InlineJoin { .. }
+        )
+    });
+    let passes_phis_on = |values: &[ir::NodeId]| {
+        values.len() == funclet.input_types.len()
+            && values.windows(2).all(|pair| pair[0] <= pair[1])
+            && values
+                .iter()
+                .all(|value| matches!(funclet.nodes[*value], ir::Node::Phi { .. }))
+    };
+    only_joins
+        && match &funclet.tail_edge {
+            ir::TailEdge::Return { return_values } => passes_phis_on(return_values),
+            ir::TailEdge::Jump { arguments, join } => {
+                passes_phis_on(arguments)
+                    && match &funclet.nodes[*join] {
+                        ir::Node::InlineJoin {
+                            funclet, captures, ..
+                        } => is_trivial_join(program, *funclet, captures.len()),
+                        _ => false,
+                    }
+            }
+            _ => false,
+        }
+}
+
+// whether the backend puts an inline join on the join stack, given the tail edge that leads
+//   to it
+fn serializes_inline_join(
+    program: &ir::Program,
+    tail_edge: &ir::TailEdge,
+    funclet_id: ir::FuncletId,
+    captures_len: usize,
+) -> bool {
+    match tail_edge {
+        ir::TailEdge::ScheduleCallYield { .. } => true,
+        ir::TailEdge::ScheduleCall { .. }
+        | ir::TailEdge::ScheduleSelect { .. }
+        | ir::TailEdge::DynamicAllocFromBuffer { .. } => {
+            !is_trivial_join(program, funclet_id, captures_len)
+        }
+        _ => false,
+    }
+}
+
+fn funclet_joins(program: &ir::Program, funclet: &ir::Funclet) -> FuncletJoins {
+    let mut pushed = PendingJoins::new();
+    for node in funclet.nodes.iter() {
+        match node {
+            ir::Node::SerializedJoin {
+                funclet, captures, ..
+            } => *pushed.entry((*funclet, captures.len())).or_insert(0) += 1,
+            ir::Node::InlineJoin {
+                funclet: join_funclet,
+                captures,
+                ..
+            } if serializes_inline_join(
+                program,
+                &funclet.tail_edge,
+                *join_funclet,
+                captures.len(),
+            ) =>
+            {
+                *pushed.entry((*join_funclet, captures.len())).or_insert(0) += 1
+            }
+            _ => (),
+        }
+    }
+
+    let mut edges = Vec::<Edge>::new();
+    for node in funclet.nodes.iter() {
+        match node {
+            ir::Node::SerializedJoin {
+                funclet: join_funclet,
+                captures,
+                ..
+            } => {
+                let mut waiting = pushed.clone();
+                remove_one(&mut waiting, (*join_funclet, captures.len()));
+                edges.push(Edge {
+                    target: *join_funclet,
+                    waiting,
+                });
+            }
+            ir::Node::InlineJoin {
+                funclet: join_funclet,
+                captures,
+                ..
+            } => {
+                let mut waiting = pushed.clone();
+                if serializes_inline_join(
+                    program,
+                    &funclet.tail_edge,
+                    *join_funclet,
+                    captures.len(),
+                ) {
+                    remove_one(&mut waiting, (*join_funclet, captures.len()));
+                }
+                edges.push(Edge {
+                    target: *join_funclet,
+                    waiting,
+                });
+            }
+            _ => (),
+        }
+    }
+
+    let callees: Vec<ir::FuncletId> = match &funclet.tail_edge {
+        ir::TailEdge::ScheduleCall {
+            callee_funclet_id, ..
+        } => vec![*callee_funclet_id],
+        ir::TailEdge::ScheduleSelect {
+            callee_funclet_ids, ..
+        } => callee_funclet_ids.to_vec(),
+        ir::TailEdge::DynamicAllocFromBuffer {
+            success_funclet_id,
+            failure_funclet_id,
+            ..
+        } => vec![*success_funclet_id, *failure_funclet_id],
+        _ => Vec::new(),
+    };
+    for callee in callees.iter() {
+        edges.push(Edge {
+            target: *callee,
+            waiting: pushed.clone(),
+        });
+    }
+
+    FuncletJoins { pushed, edges }
+}
+
+// Tarjan's algorithm, which finds the strongly connected components of the funclets reachable
+//   from the entry with every component after the ones it leads to
+struct Components<'a> {
+    joins: &'a HashMap<ir::FuncletId, FuncletJoins>,
+    index: HashMap<ir::FuncletId, usize>,
+    low_link: HashMap<ir::FuncletId, usize>,
+    stack: Vec<ir::FuncletId>,
+    on_stack: BTreeSet<ir::FuncletId>,
+    components: Vec<Vec<ir::FuncletId>>,
+}
+
+impl<'a> Components<'a> {
+    fn visit(&mut self, funclet_id: ir::FuncletId) {
+        let next_index = self.index.len();
+        self.index.insert(funclet_id, next_index);
+        self.low_link.insert(funclet_id, next_index);
+        self.stack.push(funclet_id);
+        self.on_stack.insert(funclet_id);
+
+        for edge in self.joins[&funclet_id].edges.iter() {
+            if !self.index.contains_key(&edge.target) {
+                self.visit(edge.target);
+                let low_link = self.low_link[&funclet_id].min(self.low_link[&edge.target]);
+                self.low_link.insert(funclet_id, low_link);
+            } else if self.on_stack.contains(&edge.target) {
+                let low_link = self.low_link[&funclet_id].min(self.index[&edge.target]);
+                self.low_link.insert(funclet_id, low_link);
+            }
+        }
+
+        if self.low_link[&funclet_id] == self.index[&funclet_id] {
+            let mut component = Vec::new();
+            loop {
+                let member = self.stack.pop().unwrap();
+                self.on_stack.remove(&member);
+                component.push(member);
+                if member == funclet_id {
+                    break;
+                }
+            }
+            component.sort();
+            self.components.push(component);
+        }
+    }
+}
+
+// a path from `from` back around to `from` through `to`, staying inside `component`
+fn find_cycle(
+    joins: &HashMap<ir::FuncletId, FuncletJoins>,
+    component: &BTreeSet<ir::FuncletId>,
+    from: ir::FuncletId,
+    to: ir::FuncletId,
+) -> Vec<ir::FuncletId> {
+    let mut previous = HashMap::<ir::FuncletId, ir::FuncletId>::new();
+    let mut queue = std::collections::VecDeque::from(vec![to]);
+    while let Some(funclet_id) = queue.pop_front() {
+        if funclet_id == from {
+            break;
+        }
+        for edge in joins[&funclet_id].edges.iter() {
+            if component.contains(&edge.target)
+                && edge.target != to
+                && !previous.contains_key(&edge.target)
+            {
+                previous.insert(edge.target, funclet_id);
+                queue.push_back(edge.target);
+            }
+        }
+    }
+    let mut cycle = vec![from];
+    let mut current = from;
+    while current != to {
+        current = previous[&current];
+        cycle.push(current);
+    }
+    // `cycle` runs from `from` back to `to`, so turn it around and start it at `from`
+    cycle.reverse();
+    cycle.pop();
+    cycle.insert(0, from);
+    cycle
+}
+
+pub fn pipeline_bound(program: &ir::Program, pipeline: &ir::Pipeline) -> JoinStackBound {
+    let mut joins = HashMap::<ir::FuncletId, FuncletJoins>::new();
+    let mut pending_funclet_ids = vec![pipeline.entry_funclet];
+    while let Some(funclet_id) = pending_funclet_ids.pop() {
+        if joins.contains_key(&funclet_id) {
+            continue;
+        }
+        let funclet_joins = funclet_joins(program, &program.funclets[funclet_id]);
+        pending_funclet_ids.extend(funclet_joins.edges.iter().map(|edge| edge.target));
+        joins.insert(funclet_id, funclet_joins);
+    }
+
+    let mut components = Components {
+        joins: &joins,
+        index: HashMap::new(),
+        low_link: HashMap::new(),
+        stack: Vec::new(),
+        on_stack: BTreeSet::new(),
+        components: Vec::new(),
+    };
+    components.visit(pipeline.entry_funclet);
+
+    let mut bounds = HashMap::<ir::FuncletId, JoinStackBound>::new();
+    for component in components.components.iter() {
+        let members = component.iter().copied().collect::<BTreeSet<_>>();
+        let mut bound = None;
+        let mut growing_edge = None;
+        let mut alternatives = Vec::<PendingJoins>::new();
+        for member in component.iter() {
+            alternatives.push(joins[member].pushed.clone());
+            for edge in joins[member].edges.iter() {
+                if members.contains(&edge.target) {
+                    if !edge.waiting.is_empty() && growing_edge.is_none() {
+                        growing_edge = Some((*member, edge.target));
+                    }
+                    continue;
+                }
+                match &bounds[&edge.target] {
+                    JoinStackBound::Bounded(target_alternatives) => {
+                        for target_pending in target_alternatives.iter() {
+                            let mut pending = edge.waiting.clone();
+                            add(&mut pending, target_pending, 1);
+                            alternatives.push(pending);
+                        }
+                    }
+                    unbounded => {
+                        bound.get_or_insert_with(|| unbounded.clone());
+                    }
+                }
+            }
+        }
+
+        let bound = match (bound, growing_edge, pipeline.recursion_limit_opt) {
+            (Some(unbounded), _, _) => unbounded,
+            (None, None, _) => JoinStackBound::Bounded(prune(alternatives)),
+            (None, Some((from, to)), None) => JoinStackBound::Unbounded {
+                cycle: find_cycle(&joins, &members, from, to),
+            },
+            // no cycle visits a funclet twice, so one time around pushes at most what every
+            //   funclet in the component pushes
+            (None, Some(_), Some(limit)) => {
+                let mut round = PendingJoins::new();
+                for member in component.iter() {
+                    add(&mut round, &joins[member].pushed, 1);
+                }
+                let mut limited = Vec::new();
+                for alternative in alternatives.into_iter() {
+                    let mut pending = alternative;
+                    add(&mut pending, &round, limit + 1);
+                    limited.push(pending);
+                }
+                JoinStackBound::Bounded(prune(limited))
+            }
+        };
+        for member in component.iter() {
+            bounds.insert(*member, bound.clone());
+        }
+    }
+
+    bounds.remove(&pipeline.entry_funclet).unwrap()
+}
+
+pub fn program_bounds(program: &ir::Program) -> Vec<(String, JoinStackBound)> {
+    program
+        .pipelines
+        .iter()
+        .map(|pipeline| (pipeline.name.clone(), pipeline_bound(program, pipeline)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::explication;
+    use crate::frontend::{self, CompileData};
+    use std::path::Path;
+
+    fn program(file: &str, edit: impl Fn(String) -> String) -> ir::Program {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("caiman-test")
+            .join(file);
+        let data = CompileData {
+            path: path.parent().unwrap().to_string_lossy().to_string(),
+            filename: path.to_string_lossy().to_string(),
+            input_string: edit(std::fs::read_to_string(&path).unwrap()),
+        };
+        let assembly = frontend::parse_assembly(&data).unwrap();
+        let definition = frontend::lower_assembly(assembly, &data.filename).unwrap();
+        explication::explicate(definition).unwrap().program
+    }
+
+    fn main_bound(program: &ir::Program) -> JoinStackBound {
+        let bounds = program_bounds(program);
+        assert_eq!(bounds.len(), 1);
+        assert_eq!(bounds[0].0, "main");
+        bounds[0].1.clone()
+    }
+
+    #[test]
+    fn bounds_pipelines_without_joins() {
+        let program = program("basics/copy_add_test.cair", |text| text);
+        assert_eq!(
+            main_bound(&program),
+            JoinStackBound::Bounded(vec![PendingJoins::new()])
+        );
+        assert_eq!(main_bound(&program).max_join_count(), Some(0));
+    }
+
+    #[test]
+    fn reports_recursion_that_pushes_joins() {
+        let program = program("control_flow/rec_sum_test.cair", |text| text);
+        let cycle = match main_bound(&program) {
+            JoinStackBound::Unbounded { cycle } => cycle,
+            bounded => panic!("Expected no bound, got {:?}", bounded),
+        };
+        // every funclet of the cycle leads to the next, and the last back to the first
+        for (index, funclet_id) in cycle.iter().enumerate() {
+            let next = cycle[(index + 1) % cycle.len()];
+            assert!(funclet_joins(&program, &program.funclets[*funclet_id])
+                .edges
+                .iter()
+                .any(|edge| edge.target == next));
+        }
+    }
+
+    #[test]
+    fn bounds_recursion_by_the_recursion_limit() {
+        let limited = |limit: usize| {
+            let program = program("control_flow/rec_sum_test.cair", |text| {
+                text.replace(
+                    "effect %eff;",
+                    &format!("effect %eff, recursion {};", limit),
+                )
+            });
+            main_bound(&program).max_join_count().unwrap()
+        };
+        // three joins are pushed each time around the recursion
+        assert_eq!(limited(4) - limited(3), 3);
+    }
+
+    #[test]
+    fn counts_inline_joins_that_calls_lead_to() {
+        // %rec_sum_head calls through a join that captures %arg, so it waits on the stack
+        //   while %foo_head runs, but %main_ret only returns its input and is left out
+        let program = program("gpu_timeline/trivial_timeline_capture_test.cair", |text| {
+            text
+        });
+        assert_eq!(main_bound(&program).max_join_count(), Some(1));
+    }
+}
@@ -179,3 +179,347 @@ pub fn validate_spatial_funclet(program: &ir::Program, funclet: &ir::Funclet) {
     // Merging is associative
     // Splitting is coassociative
 }
+
+// A way a GPU kernel's declared interface disagrees with the shader implementing it
+#[derive(Debug, Clone)]
+pub struct KernelInterfaceMismatch {
+    pub message: String,
+    // the shader global the mismatch is about, when there is one
+    pub global: Option<naga::Handle<naga::GlobalVariable>>,
+}
+
+// Cross-references the resource bindings and dimensionality of `kernel` with the globals and
+//   entry point of its shader, returning every mismatch found
+// A mismatch isn't caught by wgpu when it's only a matter of layout, so it would otherwise
+//   read and write garbage at runtime
+pub fn check_gpu_kernel_interface(
+    kernel: &ir::ffi::GpuKernel,
+    types: &crate::stable_vec::StableVec<ir::ffi::Type>,
+) -> Vec<KernelInterfaceMismatch> {
+    let module = kernel.shader_module.module();
+    let mut mismatches = Vec::new();
+    let mut mismatch = |message: String, global| {
+        mismatches.push(KernelInterfaceMismatch { message, global });
+    };
+
+    let entry_point = match module
+        .entry_points
+        .iter()
+        .find(|ep| ep.name == kernel.entry_point)
+    {
+        Some(entry_point) => entry_point,
+        None => {
+            mismatch(
+                format!("The shader has no entry point named {}", kernel.entry_point),
+                None,
+            );
+            return mismatches;
+        }
+    };
+    if entry_point.stage != naga::ShaderStage::Compute {
+        mismatch(
+            format!("Entry point {} isn't a compute shader", kernel.entry_point),
+            None,
+        );
+    }
+    // dimensions beyond the kernel's are always dispatched with a size of 1
+    if kernel.dimensionality > 3 {
+        mismatch(
+            format!(
+                "Kernels have at most 3 dimensions, but {} is declared with {}",
+                kernel.name, kernel.dimensionality
+            ),
+            None,
+        );
+    } else if entry_point.workgroup_size[kernel.dimensionality..]
+        .iter()
+        .any(|size| *size != 1)
+    {
+        mismatch(
+            format!(
+                "Entry point {} has workgroup size {:?}, but {} is declared with {} dimensions",
+                kernel.entry_point, entry_point.workgroup_size, kernel.name, kernel.dimensionality
+            ),
+            None,
+        );
+    }
+
+    let mut declared = HashSet::new();
+    for resource_binding in kernel.resource_bindings.iter() {
+        let (group, binding) = (resource_binding.group, resource_binding.binding);
+        declared.insert((group as u32, binding as u32));
+        let found = module.global_variables.iter().find(|(_, global)| {
+            global.binding
+                == Some(naga::ResourceBinding {
+                    group: group as u32,
+                    binding: binding as u32,
+                })
+        });
+        let (handle, global) = match found {
+            Some(found) => found,
+            None => {
+                mismatch(
+                    format!(
+                        "The shader declares nothing at group {}, binding {}",
+                        group, binding
+                    ),
+                    None,
+                );
+                continue;
+            }
+        };
+
+        let (is_buffer, is_writable) = match global.space {
+            naga::AddressSpace::Storage { access } => {
+                (true, access.contains(naga::StorageAccess::STORE))
+            }
+            naga::AddressSpace::Uniform => (true, false),
+            _ => (false, false),
+        };
+        if !is_buffer {
+            mismatch(
+                format!(
+                    "Group {}, binding {} isn't a storage or uniform buffer",
+                    group, binding
+                ),
+                Some(handle),
+            );
+            continue;
+        }
+        if resource_binding.output.is_some() && !is_writable {
+            mismatch(
+                format!(
+                    "Group {}, binding {} holds an output, but is read-only in the shader",
+                    group, binding
+                ),
+                Some(handle),
+            );
+        }
+
+        let ffi_type = match (resource_binding.input, resource_binding.output) {
+            (_, Some(output)) => kernel.output_types.get(output),
+            (Some(input), None) => kernel.input_types.get(input),
+            (None, None) => {
+                mismatch(
+                    format!(
+                        "Group {}, binding {} is bound to neither an input nor an output",
+                        group, binding
+                    ),
+                    Some(handle),
+                );
+                continue;
+            }
+        };
+        let ffi_type = match ffi_type {
+            Some(ffi_type) => *ffi_type,
+            None => {
+                mismatch(
+                    format!(
+                        "Group {}, binding {} is bound to an argument {} doesn't have",
+                        group, binding, kernel.name
+                    ),
+                    Some(handle),
+                );
+                continue;
+            }
+        };
+        if !shader_type_matches(module, global.ty, types, ffi_type) {
+            mismatch(
+                format!(
+                    "Group {}, binding {} has type {} in the shader, but {} is bound to it",
+                    group,
+                    binding,
+                    shader_type_name(module, global.ty),
+                    ffi_type_name(types, ffi_type)
+                ),
+                Some(handle),
+            );
+        }
+    }
+
+    // a binding the entry point uses but the kernel doesn't provide can't be bound at all
+    for handle in kernel
+        .shader_module
+        .entry_point_globals(&kernel.entry_point)
+    {
+        if let Some(resource_binding) = &module.global_variables[handle].binding {
+            if !declared.contains(&(resource_binding.group, resource_binding.binding)) {
+                mismatch(
+                    format!(
+                        "Entry point {} uses group {}, binding {}, but {} doesn't bind it",
+                        kernel.entry_point,
+                        resource_binding.group,
+                        resource_binding.binding,
+                        kernel.name
+                    ),
+                    Some(handle),
+                );
+            }
+        }
+    }
+    mismatches
+}
+
+fn shader_scalar_matches(kind: naga::ScalarKind, width: u8, ffi_type: &ir::ffi::Type) -> bool {
+    use ir::ffi::Type;
+    use naga::ScalarKind;
+    match (kind, width, ffi_type) {
+        (ScalarKind::Sint, 1, Type::I8)
+        | (ScalarKind::Sint, 2, Type::I16)
+        | (ScalarKind::Sint, 4, Type::I32)
+        | (ScalarKind::Sint, 8, Type::I64)
+        | (ScalarKind::Uint, 1, Type::U8)
+        | (ScalarKind::Uint, 2, Type::U16)
+        | (ScalarKind::Uint, 4, Type::U32)
+        | (ScalarKind::Uint, 8, Type::U64)
+        | (ScalarKind::Float, 4, Type::F32)
+        | (ScalarKind::Float, 8, Type::F64) => true,
+        _ => false,
+    }
+}
+
+// Whether a buffer of `ffi_type` can be bound where the shader expects `shader_type`
+// Structs with a single member match their member, since GLSL buffers are always blocks
+fn shader_type_matches(
+    module: &naga::Module,
+    shader_type: naga::Handle<naga::Type>,
+    types: &crate::stable_vec::StableVec<ir::ffi::Type>,
+    ffi_type_id: ir::ffi::TypeId,
+) -> bool {
+    use ir::ffi::Type;
+    use naga::{ArraySize, TypeInner};
+    let ffi_type = &types[ffi_type_id.0];
+    let matches =
+        |shader_type, ffi_type_id| shader_type_matches(module, shader_type, types, ffi_type_id);
+    let array_length = |size: &ArraySize| match size {
+        ArraySize::Constant(constant) => match &module.constants[*constant].inner {
+            naga::ConstantInner::Scalar {
+                value: naga::ScalarValue::Uint(n),
+                ..
+            } => Some(Some(*n as usize)),
+            naga::ConstantInner::Scalar {
+                value: naga::ScalarValue::Sint(n),
+                ..
+            } => Some(Some(*n as usize)),
+            _ => None,
+        },
+        ArraySize::Dynamic => Some(None),
+    };
+    match (&module.types[shader_type].inner, ffi_type) {
+        (TypeInner::Struct { members, .. }, Type::Struct { fields, .. }) => {
+            members.len() == fields.len()
+                && members.iter().zip(fields.iter()).all(|(member, field)| {
+                    member.offset as usize == field.byte_offset && matches(member.ty, field.type_id)
+                })
+        }
+        (TypeInner::Struct { members, .. }, _) if members.len() == 1 => {
+            matches(members[0].ty, ffi_type_id)
+        }
+        (TypeInner::Scalar { kind, width }, _) | (TypeInner::Atomic { kind, width }, _) => {
+            shader_scalar_matches(*kind, *width, ffi_type)
+        }
+        (
+            TypeInner::Vector { size, kind, width },
+            Type::Array {
+                element_type,
+                length,
+            },
+        ) => {
+            *size as usize == *length
+                && shader_scalar_matches(*kind, *width, &types[element_type.0])
+        }
+        (
+            TypeInner::Array { base, size, .. },
+            Type::Array {
+                element_type,
+                length,
+            },
+        ) => array_length(size) == Some(Some(*length)) && matches(*base, *element_type),
+        (TypeInner::Array { base, size, .. }, Type::ErasedLengthArray { element_type }) => {
+            array_length(size) == Some(None) && matches(*base, *element_type)
+        }
+        _ => false,
+    }
+}
+
+// a Rust-like name for an ffi type, for error messages
+fn ffi_type_name(
+    types: &crate::stable_vec::StableVec<ir::ffi::Type>,
+    type_id: ir::ffi::TypeId,
+) -> String {
+    use ir::ffi::Type;
+    match &types[type_id.0] {
+        Type::I8 => "i8".to_string(),
+        Type::I16 => "i16".to_string(),
+        Type::I32 => "i32".to_string(),
+        Type::I64 => "i64".to_string(),
+        Type::U8 => "u8".to_string(),
+        Type::U16 => "u16".to_string(),
+        Type::U32 => "u32".to_string(),
+        Type::U64 => "u64".to_string(),
+        Type::F32 => "f32".to_string(),
+        Type::F64 => "f64".to_string(),
+        Type::Array {
+            element_type,
+            length,
+        } => format!("[{}; {}]", ffi_type_name(types, *element_type), length),
+        Type::ErasedLengthArray { element_type } => {
+            format!("[{}]", ffi_type_name(types, *element_type))
+        }
+        Type::Struct { fields, .. } => {
+            let fields = fields
+                .iter()
+                .map(|field| ffi_type_name(types, field.type_id))
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!("struct {{ {} }}", fields)
+        }
+        other => format!("{:?}", other),
+    }
+}
+
+// a WGSL-like name for a shader type, for error messages
+fn shader_type_name(module: &naga::Module, shader_type: naga::Handle<naga::Type>) -> String {
+    use naga::{ArraySize, ScalarKind, TypeInner};
+    let scalar = |kind: &ScalarKind, width: &u8| match kind {
+        ScalarKind::Sint => format!("i{}", *width as u32 * 8),
+        ScalarKind::Uint => format!("u{}", *width as u32 * 8),
+        ScalarKind::Float => format!("f{}", *width as u32 * 8),
+        ScalarKind::Bool => "bool".to_string(),
+    };
+    let typ = &module.types[shader_type];
+    match &typ.inner {
+        TypeInner::Scalar { kind, width } => scalar(kind, width),
+        TypeInner::Atomic { kind, width } => format!("atomic<{}>", scalar(kind, width)),
+        TypeInner::Vector { size, kind, width } => {
+            format!("vec{}<{}>", *size as usize, scalar(kind, width))
+        }
+        TypeInner::Array { base, size, .. } => match size {
+            ArraySize::Constant(constant) => match &module.constants[*constant].inner {
+                naga::ConstantInner::Scalar { value, .. } => format!(
+                    "array<{}, {}>",
+                    shader_type_name(module, *base),
+                    match value {
+                        naga::ScalarValue::Uint(n) => n.to_string(),
+                        naga::ScalarValue::Sint(n) => n.to_string(),
+                        other => format!("{:?}", other),
+                    }
+                ),
+                _ => format!("array<{}, ?>", shader_type_name(module, *base)),
+            },
+            ArraySize::Dynamic => format!("array<{}>", shader_type_name(module, *base)),
+        },
+        TypeInner::Struct { members, .. } => {
+            let members = members
+                .iter()
+                .map(|member| shader_type_name(module, member.ty))
+                .collect::<Vec<_>>()
+                .join(", ");
+            match &typ.name {
+                Some(name) => format!("{} {{ {} }}", name, members),
+                None => format!("struct {{ {} }}", members),
+            }
+        }
+        other => format!("{:?}", other),
+    }
+}
@@ -7,49 +7,59 @@ extern crate core;
 #[macro_use]
 mod operations;
 pub mod assembly;
+pub mod backend;
+pub mod build;
 pub mod explication;
 mod id_generator;
+pub mod interpreter;
 pub mod ir;
 pub mod stable_vec;
 //mod ir_builders;
-pub mod frontend;
 pub mod debug_info;
+pub mod diagnostics;
+pub mod frontend;
 mod rust_wgpu_backend;
 mod scheduling_state;
 mod shadergen;
 mod type_system;
+pub mod version;
 
 // TODO (stephen): unified CLI
 pub fn explicate_and_execute(
     output: Option<String>,
     program: assembly::ast::Program,
+    filename: &str,
     explicate_only: bool,
-) {
-    let version = &program.version;
-    assert_eq!((version.major, version.minor, version.detailed), (0, 0, 2));
-
-    let exp_defininition = assembly::lowering_pass::lower(program);
-    let definition = explication::explicate(exp_defininition);
+    backend: &dyn backend::Backend,
+) -> Result<(), frontend::CompileError> {
+    let exp_defininition = frontend::lower_assembly(program, filename)?;
+    let definition = explication::explicate(exp_defininition)?;
     if explicate_only {
         println!("{:#?}", definition);
-        return;
-    }
-    match crate::type_system::check_program(&definition.program, &definition.debug_info) {
-        Ok(_) => (),
-        Err(error) => panic!("Type checking failed:\n{}", error),
+        return Ok(());
     }
-    let mut codegen = rust_wgpu_backend::codegen::CodeGen::new(&definition.program, &definition.debug_info);
-    codegen.set_print_codgen_debug_info(true);
-    let output_string = codegen.generate();
+    frontend::check_definition(&definition)?;
+    let output_string = backend.generate(&definition.program, &definition.debug_info, true)?;
     match output {
         None => println!("{}", output_string),
         Some(path_str) => {
             // Copied from caiman/src/main.rs (by Mia)
             // Copied from Mia (by Stephen)
             let path = std::path::Path::new(&path_str);
-            let prefix = path.parent().unwrap();
-            std::fs::create_dir_all(prefix).unwrap();
-            std::fs::write(path, output_string).unwrap();
+            let io_error = |why: std::io::Error| {
+                frontend::CompileError::new(
+                    frontend::CompileErrorKind::Io,
+                    diagnostics::Diagnostic::error(
+                        diagnostics::codes::IO,
+                        format!("Couldn't write {}: {}", path_str, why),
+                    ),
+                )
+            };
+            if let Some(prefix) = path.parent() {
+                std::fs::create_dir_all(prefix).map_err(io_error)?;
+            }
+            std::fs::write(path, output_string).map_err(io_error)?;
         }
     }
+    Ok(())
 }
@@ -1,9 +1,12 @@
+#[macro_use]
 extern crate clap;
 
 use clap::{App, Arg};
 
+use caiman::backend::BackendKind;
+use caiman::diagnostics::{codes, Diagnostic, MessageFormat};
 use caiman::frontend;
-use caiman::frontend::{CompileData, CompileMode, CompileOptions};
+use caiman::frontend::{CompileData, CompileMode, CompileOptions, Optimization};
 use std::path::{Path, PathBuf};
 use std::process::Command;
 
@@ -16,11 +19,24 @@ fn format(p: &Path) {
         .status();
 }
 
+/// Prints the diagnostic in the requested format and exits with a failure.
+fn fail(diagnostic: Diagnostic, format: MessageFormat) -> ! {
+    eprintln!("{}", diagnostic.render(format));
+    std::process::exit(1);
+}
+
 struct Arguments {
     input: PathBuf,
     output: Option<PathBuf>,
     explicate_only: bool,
+    explicate_to_assembly: bool,
+    upgrade: bool,
+    print_join_stack: bool,
+    explain_choices: bool,
     print_codegen_debug_info: bool,
+    backend: BackendKind,
+    optimizations: Vec<Optimization>,
+    message_format: MessageFormat,
 }
 impl Arguments {
     fn from_cmdline() -> Self {
@@ -49,12 +65,70 @@ impl Arguments {
                     .help("Only run schedule explication")
                     .takes_value(false),
             )
+            .arg(
+                Arg::with_name("explicate_format")
+                    .long("explicate-format")
+                    .value_name("ron|cair")
+                    .help("How to print the result of schedule explication")
+                    .possible_values(&["ron", "cair"])
+                    .default_value("ron")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("upgrade")
+                    .long("upgrade")
+                    .help("Rewrite a RON program written for an older version for this one")
+                    .conflicts_with("explicate_only")
+                    .takes_value(false),
+            )
+            .arg(
+                Arg::with_name("print_join_stack")
+                    .long("print-join-stack")
+                    .help("Print how many joins each pipeline can have waiting at once")
+                    .conflicts_with_all(&["explicate_only", "upgrade"])
+                    .takes_value(false),
+            )
+            .arg(
+                Arg::with_name("explain_choices")
+                    .long("explain-choices")
+                    .help("Print which implementation explication chose for each function call")
+                    .conflicts_with_all(&["explicate_only", "upgrade", "print_join_stack"])
+                    .takes_value(false),
+            )
             .arg(
                 Arg::with_name("print_codegen_debug_info")
                     .long("print_codegen_debug_info")
                     .help("Print Codegen Debug Info")
                     .takes_value(false),
             )
+            .arg(
+                Arg::with_name("backend")
+                    .long("backend")
+                    .value_name("wgpu|cpu")
+                    .help("Where the generated code runs work placed on the GPU")
+                    .possible_values(&["wgpu", "cpu"])
+                    .default_value("wgpu")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("opt")
+                    .long("opt")
+                    .value_name("fusion")
+                    .help("Optimize the program once it type checks")
+                    .possible_values(&["fusion"])
+                    .multiple(true)
+                    .number_of_values(1)
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("message_format")
+                    .long("message-format")
+                    .value_name("human|json")
+                    .help("How to print errors")
+                    .possible_values(&["human", "json"])
+                    .default_value("human")
+                    .takes_value(true),
+            )
             .get_matches();
         let input = matches
             .value_of("input")
@@ -62,56 +136,104 @@ impl Arguments {
             .into();
         let output = matches.value_of("output").map(PathBuf::from);
         let explicate_only = matches.is_present("explicate_only");
+        let explicate_to_assembly = matches.value_of("explicate_format") == Some("cair");
+        let upgrade = matches.is_present("upgrade");
+        let print_join_stack = matches.is_present("print_join_stack");
+        let explain_choices = matches.is_present("explain_choices");
         let print_codegen_debug_info = matches.is_present("print_codegen_debug_info");
+        // clap has already checked these against their possible values
+        let backend = value_t!(matches, "backend", BackendKind).unwrap_or_else(|e| e.exit());
+        let optimizations = if matches.is_present("opt") {
+            values_t!(matches, "opt", Optimization).unwrap_or_else(|e| e.exit())
+        } else {
+            Vec::new()
+        };
+        let message_format =
+            value_t!(matches, "message_format", MessageFormat).unwrap_or_else(|e| e.exit());
         Arguments {
             input,
             output,
             explicate_only,
+            explicate_to_assembly,
+            upgrade,
+            print_join_stack,
+            explain_choices,
             print_codegen_debug_info,
+            backend,
+            optimizations,
+            message_format,
         }
     }
 }
 
 fn main() {
     let args = Arguments::from_cmdline();
-    let compile_mode = match args
-        .input
-        .extension()
-        .and_then(std::ffi::OsStr::to_str)
-        .unwrap()
-    {
-        "cair" => CompileMode::Assembly,
-        "ron" => CompileMode::RON,
-        _ => panic!("Unsupported file extension for {:?}", args.input),
+    let compile_mode = match args.input.extension().and_then(std::ffi::OsStr::to_str) {
+        Some("cair") => CompileMode::Assembly,
+        Some("ron") => CompileMode::RON,
+        _ => fail(
+            Diagnostic::error(
+                codes::USAGE,
+                format!(
+                    "Unsupported file extension for {}, .cair or .ron expected",
+                    args.input.display()
+                ),
+            ),
+            args.message_format,
+        ),
     };
 
-    let input_string = std::fs::read_to_string(&args.input).expect("couldn't read input");
+    let input_string = match std::fs::read_to_string(&args.input) {
+        Ok(input_string) => input_string,
+        Err(why) => fail(
+            Diagnostic::error(
+                codes::IO,
+                format!("Couldn't access {}: {}", args.input.display(), why),
+            ),
+            args.message_format,
+        ),
+    };
     let compile_info = CompileData {
         path: match args.input.parent() {
             None => "".to_string(),
             Some(s) => s.to_str().unwrap().to_string(),
         },
+        filename: args.input.to_string_lossy().to_string(),
         input_string,
     };
     let options = CompileOptions {
         print_codegen_debug_info: args.print_codegen_debug_info,
         compile_mode,
+        backend: args.backend,
+        optimizations: args.optimizations,
     };
 
-    let result = if args.explicate_only {
+    let result = if args.upgrade {
+        frontend::upgrade_caiman(compile_info, options)
+    } else if args.print_join_stack {
+        frontend::print_join_stacks(compile_info, options)
+    } else if args.explain_choices {
+        frontend::explain_choices(compile_info, options)
+    } else if args.explicate_only && args.explicate_to_assembly {
+        frontend::explicate_caiman_assembly(compile_info, options)
+    } else if args.explicate_only {
         frontend::explicate_caiman(compile_info, options)
     } else {
         frontend::compile_caiman(compile_info, options)
     };
 
-    let output_string = result.expect("compiler error");
+    let output_string = match result {
+        Ok(output_string) => output_string,
+        Err(error) => fail(error.diagnostic, args.message_format),
+    };
     match args.output.as_ref() {
         Some(path) => {
             // https://stackoverflow.com/a/59046435/5031773
             let prefix = path.parent().unwrap();
             std::fs::create_dir_all(prefix).unwrap();
             std::fs::write(path, output_string).unwrap();
-            if !args.explicate_only {
+            let report = args.print_join_stack || args.explain_choices;
+            if !args.explicate_only && !args.upgrade && !report {
                 format(path);
             }
         }
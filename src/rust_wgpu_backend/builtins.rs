@@ -0,0 +1,919 @@
+// Built-in operator function classes
+//
+// The high-level language lowers every operator (`+`, `<`, `!`, ...) into a call of a pure
+// CPU function class whose name is mangled from the operator and its operand types, such as
+// `_lt_i64_i64` or `_neg_f64` (see `hlc::lower::binop_to_str` and `hlc::lower::uop_to_str`).
+// Rather than forcing every user to implement these in `CpuFunctions`, codegen recognizes
+// them here and emits their bodies directly into the generated module.
+//
+// Array literals and indexing are lowered the same way, as `_array_{array type}` and
+// `_index_{array type}_{index type}`. Their array types are mangled with lengths and nested
+// element types, so they are recognized by their native signatures instead of their names.
+
+use super::ffi;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Lt,
+    Leq,
+    Gt,
+    Geq,
+    Eq,
+    Neq,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    And,
+    Or,
+    Xor,
+    Shl,
+    Shr,
+    AShr,
+    Land,
+    Lor,
+    Neg,
+    Not,
+    LNot,
+}
+
+impl Operator {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "lt" => Self::Lt,
+            "leq" => Self::Leq,
+            "gt" => Self::Gt,
+            "geq" => Self::Geq,
+            "eq" => Self::Eq,
+            "neq" => Self::Neq,
+            "add" => Self::Add,
+            "sub" => Self::Sub,
+            "mul" => Self::Mul,
+            "div" => Self::Div,
+            "mod" => Self::Mod,
+            "and" => Self::And,
+            "or" => Self::Or,
+            "xor" => Self::Xor,
+            "shl" => Self::Shl,
+            "shr" => Self::Shr,
+            "ashr" => Self::AShr,
+            "land" => Self::Land,
+            "lor" => Self::Lor,
+            "neg" => Self::Neg,
+            "not" => Self::Not,
+            "lnot" => Self::LNot,
+            _ => return None,
+        })
+    }
+
+    fn arity(self) -> usize {
+        match self {
+            Self::Neg | Self::Not | Self::LNot => 1,
+            _ => 2,
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            Self::Lt => "<",
+            Self::Leq => "<=",
+            Self::Gt => ">",
+            Self::Geq => ">=",
+            Self::Eq => "==",
+            Self::Neq => "!=",
+            Self::Add => "+",
+            Self::Sub => "-",
+            Self::Mul => "*",
+            Self::Div => "/",
+            Self::Mod => "%",
+            Self::And => "&",
+            Self::Or => "|",
+            Self::Xor => "^",
+            Self::Shl => "<<",
+            Self::Shr | Self::AShr => ">>",
+            Self::Land => "&&",
+            Self::Lor => "||",
+            Self::Neg => "-",
+            Self::Not | Self::LNot => "!",
+        }
+    }
+
+    // The name of the integer method that wraps instead of overflowing
+    fn wrapping_name(self) -> &'static str {
+        match self {
+            Self::Add => "wrapping_add",
+            Self::Sub => "wrapping_sub",
+            Self::Mul => "wrapping_mul",
+            Self::Div => "wrapping_div",
+            Self::Mod => "wrapping_rem",
+            Self::Neg => "wrapping_neg",
+            Self::Shl => "wrapping_shl",
+            Self::Shr | Self::AShr => "wrapping_shr",
+            _ => unreachable!("{:?} cannot overflow", self),
+        }
+    }
+
+    fn is_comparison(self) -> bool {
+        matches!(
+            self,
+            Self::Lt | Self::Leq | Self::Gt | Self::Geq | Self::Eq | Self::Neq
+        )
+    }
+}
+
+// The high-level type an operand was mangled with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operand {
+    // `usize` is pointer sized, and a different type from the integer of the same width
+    Int {
+        signed: bool,
+        bits: usize,
+        pointer_sized: bool,
+    },
+    Float {
+        bits: usize,
+    },
+    // Booleans are passed across the FFI boundary as integers, where any nonzero value is true
+    Bool,
+}
+
+impl Operand {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "bool" => Self::Bool,
+            "i8" => Self::Int {
+                signed: true,
+                bits: 8,
+                pointer_sized: false,
+            },
+            "i16" => Self::Int {
+                signed: true,
+                bits: 16,
+                pointer_sized: false,
+            },
+            "i32" => Self::Int {
+                signed: true,
+                bits: 32,
+                pointer_sized: false,
+            },
+            "i64" => Self::Int {
+                signed: true,
+                bits: 64,
+                pointer_sized: false,
+            },
+            "u8" => Self::Int {
+                signed: false,
+                bits: 8,
+                pointer_sized: false,
+            },
+            "u16" => Self::Int {
+                signed: false,
+                bits: 16,
+                pointer_sized: false,
+            },
+            "u32" => Self::Int {
+                signed: false,
+                bits: 32,
+                pointer_sized: false,
+            },
+            "u64" => Self::Int {
+                signed: false,
+                bits: 64,
+                pointer_sized: false,
+            },
+            "usize" => Self::Int {
+                signed: false,
+                bits: usize::BITS as usize,
+                pointer_sized: true,
+            },
+            "f32" => Self::Float { bits: 32 },
+            "f64" => Self::Float { bits: 64 },
+            _ => return None,
+        })
+    }
+
+    // Whether `typ` is how a value of this operand is represented in the native interface
+    fn is_represented_by(self, typ: &ffi::Type) -> bool {
+        match (self, scalar_of(typ)) {
+            (Self::Bool, Some(Self::Int { .. })) => true,
+            (operand, Some(scalar)) => operand == scalar,
+            (_, None) => false,
+        }
+    }
+
+    fn rust_name(self) -> String {
+        match self {
+            Self::Int {
+                signed,
+                pointer_sized: true,
+                ..
+            } => if signed { "isize" } else { "usize" }.to_string(),
+            Self::Int {
+                signed: true, bits, ..
+            } => format!("i{}", bits),
+            Self::Int {
+                signed: false,
+                bits,
+                ..
+            } => format!("u{}", bits),
+            Self::Float { bits } => format!("f{}", bits),
+            Self::Bool => "bool".to_string(),
+        }
+    }
+}
+
+fn scalar_of(typ: &ffi::Type) -> Option<Operand> {
+    Some(match typ {
+        ffi::Type::I8 => Operand::Int {
+            signed: true,
+            bits: 8,
+            pointer_sized: false,
+        },
+        ffi::Type::I16 => Operand::Int {
+            signed: true,
+            bits: 16,
+            pointer_sized: false,
+        },
+        ffi::Type::I32 => Operand::Int {
+            signed: true,
+            bits: 32,
+            pointer_sized: false,
+        },
+        ffi::Type::I64 => Operand::Int {
+            signed: true,
+            bits: 64,
+            pointer_sized: false,
+        },
+        ffi::Type::U8 => Operand::Int {
+            signed: false,
+            bits: 8,
+            pointer_sized: false,
+        },
+        ffi::Type::U16 => Operand::Int {
+            signed: false,
+            bits: 16,
+            pointer_sized: false,
+        },
+        ffi::Type::U32 => Operand::Int {
+            signed: false,
+            bits: 32,
+            pointer_sized: false,
+        },
+        ffi::Type::U64 => Operand::Int {
+            signed: false,
+            bits: 64,
+            pointer_sized: false,
+        },
+        ffi::Type::USize => Operand::Int {
+            signed: false,
+            bits: usize::BITS as usize,
+            pointer_sized: true,
+        },
+        ffi::Type::F32 => Operand::Float { bits: 32 },
+        ffi::Type::F64 => Operand::Float { bits: 64 },
+        _ => return None,
+    })
+}
+
+/// A pure CPU operation that codegen knows how to implement itself
+#[derive(Debug, Clone)]
+pub enum BuiltinOperation {
+    /// An operator applied to scalars
+    Scalar(ScalarOperation),
+    /// Reads the element of an array or slice at an integer index
+    Index,
+    /// Builds an array out of its elements
+    Array,
+}
+
+impl BuiltinOperation {
+    /// Recognizes `operation` as a built-in operation if its name follows the operator name
+    /// mangling of the high-level language and its signature agrees with the mangled types.
+    /// Anything else is left to the user-supplied `CpuFunctions`.
+    pub fn recognize(
+        operation: &ffi::CpuPureOperation,
+        native_interface: &ffi::NativeInterface,
+    ) -> Option<Self> {
+        if operation.output_types.len() != 1 {
+            return None;
+        }
+        let types = &native_interface.types;
+        let output = operation.output_types[0];
+        if operation.name.starts_with("_index_") {
+            let [array, index] = match &*operation.input_types {
+                [array, index] => [*array, *index],
+                _ => return None,
+            };
+            return match (&types[array.0], scalar_of(&types[index.0])) {
+                (
+                    ffi::Type::Array { element_type, .. }
+                    | ffi::Type::ErasedLengthArray { element_type },
+                    Some(Operand::Int { .. }),
+                ) if *element_type == output => Some(Self::Index),
+                _ => None,
+            };
+        }
+        if operation.name.starts_with("_array_") {
+            return match &types[output.0] {
+                ffi::Type::Array {
+                    element_type,
+                    length,
+                } if *length == operation.input_types.len()
+                    && operation.input_types.iter().all(|t| t == element_type) =>
+                {
+                    Some(Self::Array)
+                }
+                _ => None,
+            };
+        }
+        ScalarOperation::recognize(operation, native_interface).map(Self::Scalar)
+    }
+
+    /// Builds a Rust expression evaluating to the one-element output tuple of the operation
+    /// applied to the given argument expressions
+    pub fn build_body(&self, arguments: &[String]) -> String {
+        match self {
+            Self::Scalar(scalar) => scalar.build_body(arguments),
+            Self::Index => format!("({}[{} as usize],)", arguments[0], arguments[1]),
+            Self::Array => format!("([{}],)", arguments.join(", ")),
+        }
+    }
+}
+
+/// An operator applied to scalars
+#[derive(Debug, Clone)]
+pub struct ScalarOperation {
+    operator: Operator,
+    operands: Vec<Operand>,
+    output: Operand,
+}
+
+impl ScalarOperation {
+    fn recognize(
+        operation: &ffi::CpuPureOperation,
+        native_interface: &ffi::NativeInterface,
+    ) -> Option<Self> {
+        let mut components = operation.name.strip_prefix('_')?.split('_');
+        let operator = Operator::from_name(components.next()?)?;
+        let operands = components
+            .map(Operand::from_name)
+            .collect::<Option<Vec<_>>>()?;
+        if operands.len() != operator.arity()
+            || operation.input_types.len() != operands.len()
+            || operation.output_types.len() != 1
+        {
+            return None;
+        }
+        for (operand, type_id) in operands.iter().zip(operation.input_types.iter()) {
+            if !operand.is_represented_by(&native_interface.types[type_id.0]) {
+                return None;
+            }
+        }
+        let output = scalar_of(&native_interface.types[operation.output_types[0].0])?;
+        let builtin = Self {
+            operator,
+            operands,
+            output,
+        };
+        builtin.result_operand().map(|_| builtin)
+    }
+
+    // The high-level type of the result, or `None` if the operator isn't defined on the operands
+    fn result_operand(&self) -> Option<Operand> {
+        use Operator::*;
+        let first = self.operands[0];
+        let same_operands = self.operands.iter().all(|operand| *operand == first);
+        let result = match (self.operator, first) {
+            (op, _) if op.is_comparison() && same_operands => Operand::Bool,
+            (Add | Sub | Mul | Div | Mod, Operand::Int { .. } | Operand::Float { .. })
+                if same_operands =>
+            {
+                first
+            }
+            (And | Or | Xor, Operand::Int { .. } | Operand::Bool) if same_operands => first,
+            (Land | Lor, Operand::Bool) if same_operands => Operand::Bool,
+            (Shl | Shr | AShr, Operand::Int { .. })
+                if matches!(self.operands[1], Operand::Int { .. }) =>
+            {
+                first
+            }
+            (Neg, Operand::Int { signed: true, .. } | Operand::Float { .. }) => first,
+            (Not, Operand::Int { .. } | Operand::Bool) => first,
+            (LNot, Operand::Bool) => Operand::Bool,
+            _ => return None,
+        };
+        match result {
+            Operand::Bool if matches!(self.output, Operand::Int { .. }) => Some(result),
+            _ if result == self.output => Some(result),
+            _ => None,
+        }
+    }
+
+    fn build_body(&self, arguments: &[String]) -> String {
+        use Operator::*;
+        assert_eq!(arguments.len(), self.operands.len());
+        // Booleans are computed as `bool` and only converted back at the end
+        let values: Vec<String> = arguments
+            .iter()
+            .zip(self.operands.iter())
+            .map(|(argument, operand)| match operand {
+                Operand::Bool => format!("({} != 0)", argument),
+                _ => argument.clone(),
+            })
+            .collect();
+        let symbol = self.operator.symbol();
+        let expression = match (self.operator, self.operands[0]) {
+            // Integer arithmetic wraps instead of panicking on overflow in debug builds, and
+            // shifts only use as many bits of the shift amount as the shifted type has
+            (Neg, operand @ Operand::Int { .. }) => format!(
+                "{}::{}({})",
+                operand.rust_name(),
+                self.operator.wrapping_name(),
+                values[0]
+            ),
+            (Neg | Not | LNot, _) => format!("{}{}", symbol, values[0]),
+            // Dividing by zero panics with the error the interpreter gives for it
+            (Div | Mod, operand @ Operand::Int { .. }) => format!(
+                "{{ if {1} == 0 {{ panic!(\"{3}\") }} {0}::{2}({4}, {1}) }}",
+                operand.rust_name(),
+                values[1],
+                self.operator.wrapping_name(),
+                self.division_by_zero(),
+                values[0]
+            ),
+            (Add | Sub | Mul, operand @ Operand::Int { .. }) => format!(
+                "{}::{}({}, {})",
+                operand.rust_name(),
+                self.operator.wrapping_name(),
+                values[0],
+                values[1]
+            ),
+            (Shl, operand @ Operand::Int { .. })
+            | (Shr, operand @ Operand::Int { signed: false, .. })
+            | (AShr, operand @ Operand::Int { signed: true, .. }) => format!(
+                "{}::{}({}, {} as u32)",
+                operand.rust_name(),
+                self.operator.wrapping_name(),
+                values[0],
+                values[1]
+            ),
+            (
+                Shr | AShr,
+                operand @ Operand::Int {
+                    signed,
+                    bits,
+                    pointer_sized,
+                },
+            ) => {
+                // Reinterpret with the opposite signedness to pick the other kind of shift
+                let from = operand.rust_name();
+                let to = Operand::Int {
+                    signed: !signed,
+                    bits,
+                    pointer_sized,
+                }
+                .rust_name();
+                format!(
+                    "{}::wrapping_shr({} as {}, {} as u32) as {}",
+                    to, values[0], to, values[1], from
+                )
+            }
+            _ => format!("{} {} {}", values[0], symbol, values[1]),
+        };
+        let result = self.result_operand().unwrap();
+        let expression = if result == Operand::Bool {
+            format!("({}) as {}", expression, self.output.rust_name())
+        } else {
+            expression
+        };
+        format!("({},)", expression)
+    }
+
+    // The error for dividing an integer by zero, which the generated code panics with
+    fn division_by_zero(&self) -> String {
+        format!("Division by zero in {:?}", self.operator)
+    }
+
+    /// Applies the operation to the given arguments, agreeing with the expression built by
+    /// `build_body`. Integer arithmetic wraps, dividing an integer by zero is an error, and the
+    /// result is converted to the output type.
+    pub fn evaluate(&self, arguments: &[ScalarValue]) -> Result<ScalarValue, String> {
+        use Operator::*;
+        if arguments.len() != self.operands.len() {
+            return Err(format!(
+                "{:?} takes {} arguments, not {}",
+                self.operator,
+                self.operands.len(),
+                arguments.len()
+            ));
+        }
+        let values = arguments
+            .iter()
+            .zip(self.operands.iter())
+            .map(|(argument, operand)| match (operand, argument) {
+                (Operand::Float { .. }, ScalarValue::Float(value)) => Some(Number::Float(*value)),
+                (Operand::Int { .. }, ScalarValue::Int(value)) => Some(Number::Int(*value)),
+                (Operand::Bool, ScalarValue::Int(value)) => Some(Number::Bool(*value != 0)),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| format!("Arguments {:?} do not match {:?}", arguments, self.operands))?;
+        let (signed, bits) = match self.operands[0] {
+            Operand::Int { signed, bits, .. } => (signed, bits),
+            _ => (false, 0),
+        };
+        // Truncating wraps at the operand width, so this agrees with wrapping at that width even
+        //   when a product of 64-bit operands overflows the 128 bits it is computed in
+        let wrap_first = |value: i128| wrap(value, signed, bits);
+        let result = match (self.operator, values[0], values.get(1).copied()) {
+            (Lt, a, Some(b)) => Number::Bool(a.compare(b) == Some(std::cmp::Ordering::Less)),
+            (Leq, a, Some(b)) => Number::Bool(matches!(
+                a.compare(b),
+                Some(std::cmp::Ordering::Less | std::cmp::Ordering::Equal)
+            )),
+            (Gt, a, Some(b)) => Number::Bool(a.compare(b) == Some(std::cmp::Ordering::Greater)),
+            (Geq, a, Some(b)) => Number::Bool(matches!(
+                a.compare(b),
+                Some(std::cmp::Ordering::Greater | std::cmp::Ordering::Equal)
+            )),
+            (Eq, a, Some(b)) => Number::Bool(a.compare(b) == Some(std::cmp::Ordering::Equal)),
+            (Neq, a, Some(b)) => Number::Bool(a.compare(b) != Some(std::cmp::Ordering::Equal)),
+            (Div | Mod, Number::Int(_), Some(Number::Int(0))) => {
+                return Err(self.division_by_zero())
+            }
+            (Add, Number::Int(a), Some(Number::Int(b))) => {
+                Number::Int(wrap_first(a.wrapping_add(b)))
+            }
+            (Sub, Number::Int(a), Some(Number::Int(b))) => {
+                Number::Int(wrap_first(a.wrapping_sub(b)))
+            }
+            (Mul, Number::Int(a), Some(Number::Int(b))) => {
+                Number::Int(wrap_first(a.wrapping_mul(b)))
+            }
+            (Div, Number::Int(a), Some(Number::Int(b))) => Number::Int(wrap_first(a / b)),
+            (Mod, Number::Int(a), Some(Number::Int(b))) => Number::Int(wrap_first(a % b)),
+            (Add, Number::Float(a), Some(Number::Float(b))) => Number::Float(a + b),
+            (Sub, Number::Float(a), Some(Number::Float(b))) => Number::Float(a - b),
+            (Mul, Number::Float(a), Some(Number::Float(b))) => Number::Float(a * b),
+            (Div, Number::Float(a), Some(Number::Float(b))) => Number::Float(a / b),
+            (Mod, Number::Float(a), Some(Number::Float(b))) => Number::Float(a % b),
+            (And, Number::Int(a), Some(Number::Int(b))) => Number::Int(wrap_first(a & b)),
+            (Or, Number::Int(a), Some(Number::Int(b))) => Number::Int(wrap_first(a | b)),
+            (Xor, Number::Int(a), Some(Number::Int(b))) => Number::Int(wrap_first(a ^ b)),
+            (And | Land, Number::Bool(a), Some(Number::Bool(b))) => Number::Bool(a & b),
+            (Or | Lor, Number::Bool(a), Some(Number::Bool(b))) => Number::Bool(a | b),
+            (Xor, Number::Bool(a), Some(Number::Bool(b))) => Number::Bool(a ^ b),
+            (Shl | Shr | AShr, Number::Int(a), Some(Number::Int(b))) => {
+                let shift = b.rem_euclid(bits as i128) as u32;
+                Number::Int(match self.operator {
+                    Shl => wrap_first(a << shift),
+                    Shr => wrap_first(wrap(a, false, bits) >> shift),
+                    _ => wrap_first(wrap(a, true, bits) >> shift),
+                })
+            }
+            (Neg, Number::Int(a), None) => Number::Int(wrap_first(-a)),
+            (Neg, Number::Float(a), None) => Number::Float(-a),
+            (Not, Number::Int(a), None) => Number::Int(wrap_first(!a)),
+            (Not | LNot, Number::Bool(a), None) => Number::Bool(!a),
+            _ => return Err(format!("{:?} is undefined on {:?}", self.operator, values)),
+        };
+        Ok(match (result, self.output) {
+            (Number::Bool(value), _) => ScalarValue::Int(value as i128),
+            (Number::Int(value), Operand::Int { signed, bits, .. }) => {
+                ScalarValue::Int(wrap(value, signed, bits))
+            }
+            (Number::Float(value), Operand::Float { bits: 32 }) => {
+                ScalarValue::Float(value as f32 as f64)
+            }
+            (Number::Float(value), _) => ScalarValue::Float(value),
+            (Number::Int(value), _) => ScalarValue::Int(value),
+        })
+    }
+}
+
+/// A scalar argument or result of a built-in operation. Booleans are integers, as they are
+/// across the FFI boundary.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScalarValue {
+    Int(i128),
+    Float(f64),
+}
+
+// An operand of a built-in operation while it is being evaluated
+#[derive(Debug, Clone, Copy)]
+enum Number {
+    Int(i128),
+    Float(f64),
+    Bool(bool),
+}
+
+impl Number {
+    fn compare(self, other: Self) -> Option<std::cmp::Ordering> {
+        match (self, other) {
+            (Self::Int(a), Self::Int(b)) => a.partial_cmp(&b),
+            (Self::Float(a), Self::Float(b)) => a.partial_cmp(&b),
+            (Self::Bool(a), Self::Bool(b)) => a.partial_cmp(&b),
+            _ => None,
+        }
+    }
+}
+
+// Truncates `value` to an integer of the given width and signedness
+fn wrap(value: i128, signed: bool, bits: usize) -> i128 {
+    let modulus = 1i128 << bits;
+    let value = value.rem_euclid(modulus);
+    if signed && value >= modulus / 2 {
+        value - modulus
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stable_vec::StableVec;
+
+    fn interface() -> (ffi::NativeInterface, ffi::TypeId, ffi::TypeId, ffi::TypeId) {
+        let mut types = StableVec::new();
+        let i32_type = ffi::TypeId(types.add(ffi::Type::I32));
+        let i64_type = ffi::TypeId(types.add(ffi::Type::I64));
+        let f64_type = ffi::TypeId(types.add(ffi::Type::F64));
+        let native_interface = ffi::NativeInterface {
+            types,
+            ..Default::default()
+        };
+        (native_interface, i32_type, i64_type, f64_type)
+    }
+
+    fn operation(name: &str, inputs: &[ffi::TypeId], output: ffi::TypeId) -> ffi::CpuPureOperation {
+        ffi::CpuPureOperation {
+            name: name.to_string(),
+            input_types: inputs.to_vec().into_boxed_slice(),
+            output_types: vec![output].into_boxed_slice(),
+        }
+    }
+
+    fn body(name: &str, inputs: &[ffi::TypeId], output: ffi::TypeId) -> Option<String> {
+        let (native_interface, ..) = interface();
+        let arguments: Vec<String> = (0..inputs.len()).map(|i| format!("a{}", i)).collect();
+        BuiltinOperation::recognize(&operation(name, inputs, output), &native_interface)
+            .map(|builtin| builtin.build_body(&arguments))
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let (_, i32_type, i64_type, f64_type) = interface();
+        assert_eq!(
+            body("_add_i64_i64", &[i64_type, i64_type], i64_type).unwrap(),
+            "(i64::wrapping_add(a0, a1),)"
+        );
+        assert_eq!(
+            body("_mod_f64_f64", &[f64_type, f64_type], f64_type).unwrap(),
+            "(a0 % a1,)"
+        );
+        assert_eq!(
+            body("_neg_i32", &[i32_type], i32_type).unwrap(),
+            "(i32::wrapping_neg(a0),)"
+        );
+    }
+
+    #[test]
+    fn test_booleans() {
+        let (_, i32_type, i64_type, _) = interface();
+        assert_eq!(
+            body("_lt_i64_i64", &[i64_type, i64_type], i32_type).unwrap(),
+            "((a0 < a1) as i32,)"
+        );
+        assert_eq!(
+            body("_lor_bool_bool", &[i32_type, i32_type], i32_type).unwrap(),
+            "(((a0 != 0) || (a1 != 0)) as i32,)"
+        );
+        assert_eq!(
+            body("_eq_bool_bool", &[i32_type, i32_type], i32_type).unwrap(),
+            "(((a0 != 0) == (a1 != 0)) as i32,)"
+        );
+        assert_eq!(
+            body("_lnot_bool", &[i32_type], i32_type).unwrap(),
+            "((!(a0 != 0)) as i32,)"
+        );
+    }
+
+    #[test]
+    fn test_shifts() {
+        let (_, _, i64_type, _) = interface();
+        assert_eq!(
+            body("_shr_i64_i64", &[i64_type, i64_type], i64_type).unwrap(),
+            "(u64::wrapping_shr(a0 as u64, a1 as u32) as i64,)"
+        );
+        assert_eq!(
+            body("_ashr_i64_i64", &[i64_type, i64_type], i64_type).unwrap(),
+            "(i64::wrapping_shr(a0, a1 as u32),)"
+        );
+    }
+
+    fn evaluate(
+        name: &str,
+        inputs: &[ffi::TypeId],
+        output: ffi::TypeId,
+        arguments: &[ScalarValue],
+    ) -> Result<ScalarValue, String> {
+        let (native_interface, ..) = interface();
+        match BuiltinOperation::recognize(&operation(name, inputs, output), &native_interface) {
+            Some(BuiltinOperation::Scalar(scalar)) => scalar.evaluate(arguments),
+            builtin => panic!("{} is not a scalar operation: {:?}", name, builtin),
+        }
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let (_, i32_type, i64_type, f64_type) = interface();
+        assert_eq!(
+            evaluate(
+                "_add_i32_i32",
+                &[i32_type, i32_type],
+                i32_type,
+                &[ScalarValue::Int(i32::MAX as i128), ScalarValue::Int(1)]
+            ),
+            Ok(ScalarValue::Int(i32::MIN as i128))
+        );
+        assert_eq!(
+            evaluate(
+                "_div_f64_f64",
+                &[f64_type, f64_type],
+                f64_type,
+                &[ScalarValue::Float(1.0), ScalarValue::Float(4.0)]
+            ),
+            Ok(ScalarValue::Float(0.25))
+        );
+        assert_eq!(
+            evaluate(
+                "_lt_i64_i64",
+                &[i64_type, i64_type],
+                i32_type,
+                &[ScalarValue::Int(-1), ScalarValue::Int(2)]
+            ),
+            Ok(ScalarValue::Int(1))
+        );
+        assert_eq!(
+            evaluate(
+                "_shr_i64_i64",
+                &[i64_type, i64_type],
+                i64_type,
+                &[ScalarValue::Int(-1), ScalarValue::Int(60)]
+            ),
+            Ok(ScalarValue::Int(15))
+        );
+        assert_eq!(
+            evaluate(
+                "_ashr_i64_i64",
+                &[i64_type, i64_type],
+                i64_type,
+                &[ScalarValue::Int(-16), ScalarValue::Int(2)]
+            ),
+            Ok(ScalarValue::Int(-4))
+        );
+        assert_eq!(
+            evaluate("_lnot_bool", &[i32_type], i32_type, &[ScalarValue::Int(5)]),
+            Ok(ScalarValue::Int(0))
+        );
+        assert!(evaluate(
+            "_mod_i32_i32",
+            &[i32_type, i32_type],
+            i32_type,
+            &[ScalarValue::Int(1), ScalarValue::Int(0)]
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_arrays() {
+        let mut types = StableVec::new();
+        let i64_type = ffi::TypeId(types.add(ffi::Type::I64));
+        let f64_type = ffi::TypeId(types.add(ffi::Type::F64));
+        let array_type = ffi::TypeId(types.add(ffi::Type::Array {
+            element_type: i64_type,
+            length: 2,
+        }));
+        let slice_type = ffi::TypeId(types.add(ffi::Type::ErasedLengthArray {
+            element_type: i64_type,
+        }));
+        let native_interface = ffi::NativeInterface {
+            types,
+            ..Default::default()
+        };
+        let recognize = |name, inputs: &[ffi::TypeId], output| {
+            BuiltinOperation::recognize(&operation(name, inputs, output), &native_interface)
+        };
+        let arguments = ["a0".to_string(), "a1".to_string()];
+        let array = recognize("_array__a2_i64", &[i64_type, i64_type], array_type).unwrap();
+        assert_eq!(array.build_body(&arguments), "([a0, a1],)");
+        let index = recognize("_index__a2_i64_i64", &[array_type, i64_type], i64_type).unwrap();
+        assert_eq!(index.build_body(&arguments), "(a0[a1 as usize],)");
+        let index = recognize("_index__a_i64_i64", &[slice_type, i64_type], i64_type).unwrap();
+        assert_eq!(index.build_body(&arguments), "(a0[a1 as usize],)");
+        // signatures that disagree with the array type
+        assert!(recognize("_array__a2_i64", &[i64_type], array_type).is_none());
+        assert!(recognize("_index__a2_i64_i64", &[array_type, i64_type], f64_type).is_none());
+    }
+
+    #[test]
+    fn test_usize() {
+        let mut types = StableVec::new();
+        let usize_type = ffi::TypeId(types.add(ffi::Type::USize));
+        let u64_type = ffi::TypeId(types.add(ffi::Type::U64));
+        let native_interface = ffi::NativeInterface {
+            types,
+            ..Default::default()
+        };
+        let recognize = |name, inputs: &[ffi::TypeId], output| {
+            BuiltinOperation::recognize(&operation(name, inputs, output), &native_interface)
+        };
+        let arguments = ["a0".to_string(), "a1".to_string()];
+        let sub = recognize("_sub_usize_usize", &[usize_type, usize_type], usize_type).unwrap();
+        assert_eq!(sub.build_body(&arguments), "(usize::wrapping_sub(a0, a1),)");
+        let ashr = recognize("_ashr_usize_usize", &[usize_type, usize_type], usize_type);
+        assert_eq!(
+            ashr.unwrap().build_body(&arguments),
+            "(isize::wrapping_shr(a0 as isize, a1 as u32) as usize,)"
+        );
+        let sub = match sub {
+            BuiltinOperation::Scalar(scalar) => scalar,
+            builtin => panic!("{:?} is not a scalar operation", builtin),
+        };
+        assert_eq!(
+            sub.evaluate(&[ScalarValue::Int(0), ScalarValue::Int(1)]),
+            Ok(ScalarValue::Int(usize::MAX as i128))
+        );
+        // usize is a different type from the integer of the same width
+        assert!(recognize("_sub_u64_u64", &[usize_type, usize_type], usize_type).is_none());
+        assert!(recognize("_sub_usize_usize", &[u64_type, u64_type], u64_type).is_none());
+    }
+
+    #[test]
+    fn test_division_by_zero() {
+        let (_, _, i64_type, _) = interface();
+        assert_eq!(
+            body("_div_i64_i64", &[i64_type, i64_type], i64_type).unwrap(),
+            "({ if a1 == 0 { panic!(\"Division by zero in Div\") } i64::wrapping_div(a0, a1) },)"
+        );
+        assert_eq!(
+            evaluate(
+                "_div_i64_i64",
+                &[i64_type, i64_type],
+                i64_type,
+                &[ScalarValue::Int(1), ScalarValue::Int(0)]
+            ),
+            Err("Division by zero in Div".to_string())
+        );
+        // the one division that overflows wraps
+        assert_eq!(
+            evaluate(
+                "_div_i64_i64",
+                &[i64_type, i64_type],
+                i64_type,
+                &[ScalarValue::Int(i64::MIN as i128), ScalarValue::Int(-1)]
+            ),
+            Ok(ScalarValue::Int(i64::MIN as i128))
+        );
+    }
+
+    #[test]
+    fn test_wide_products() {
+        let mut types = StableVec::new();
+        let u64_type = ffi::TypeId(types.add(ffi::Type::U64));
+        let native_interface = ffi::NativeInterface {
+            types,
+            ..Default::default()
+        };
+        let mul = BuiltinOperation::recognize(
+            &operation("_mul_u64_u64", &[u64_type, u64_type], u64_type),
+            &native_interface,
+        );
+        let mul = match mul {
+            Some(BuiltinOperation::Scalar(scalar)) => scalar,
+            builtin => panic!("{:?} is not a scalar operation", builtin),
+        };
+        let max = ScalarValue::Int(u64::MAX as i128);
+        assert_eq!(
+            mul.evaluate(&[max, max]),
+            Ok(ScalarValue::Int(u64::MAX.wrapping_mul(u64::MAX) as i128))
+        );
+    }
+
+    #[test]
+    fn test_not_builtin() {
+        let (_, i32_type, i64_type, f64_type) = interface();
+        // user-supplied externs
+        assert!(body("foo", &[i64_type], i64_type).is_none());
+        assert!(body("_foo_i64", &[i64_type], i64_type).is_none());
+        // signature disagrees with the mangled name
+        assert!(body("_add_i64_i64", &[i32_type, i32_type], i32_type).is_none());
+        assert!(body("_add_i64_i64", &[i64_type, i64_type], f64_type).is_none());
+        // operators undefined on the operand types
+        assert!(body("_and_f64_f64", &[f64_type, f64_type], f64_type).is_none());
+        assert!(body("_add_bool_bool", &[i32_type, i32_type], i32_type).is_none());
+        assert!(body("_add_i64", &[i64_type], i64_type).is_none());
+    }
+}
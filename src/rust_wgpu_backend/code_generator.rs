@@ -1,9 +1,12 @@
 use itertools::Itertools;
 
+use super::builtins::BuiltinOperation;
 use super::ffi;
 use crate::id_generator::IdGenerator;
 use crate::ir;
+use crate::ir::join_stack::JoinStackBound;
 use crate::rust_wgpu_backend::code_writer::CodeWriter;
+use crate::rust_wgpu_backend::layout::{LayoutError, LayoutRules};
 use crate::shadergen;
 use crate::shadergen::ShaderModule;
 use crate::stable_vec::StableVec;
@@ -39,6 +42,16 @@ pub struct ClosureId(usize);
 #[derive(Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Debug, Default, Hash)]
 pub struct DispatcherId(usize);
 
+// Where the generated code runs the work the program places on the GPU
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Target {
+    // Through wgpu, which requires the caller to provide a device and queue
+    #[default]
+    Wgpu,
+    // On the host, where GPU buffers are host memory and kernels call into `CpuFunctions`
+    Cpu,
+}
+
 #[derive(Debug, Default)]
 pub struct YieldPoint {
     pub name: String,
@@ -168,6 +181,9 @@ struct ActiveFuncletState {
     output_count: usize,
     output_type_ids: Box<[ffi::TypeId]>,
     next_funclet_input_types: Option<Box<[Box<[ffi::TypeId]>]>>,
+    // The arguments a call back to this funclet reassigns before going around again, if its
+    //   body is a loop
+    loop_argument_var_ids: Option<Box<[VarId]>>,
 }
 
 #[derive(Clone, PartialOrd, Ord, PartialEq, Eq, Debug, Default)]
@@ -227,6 +243,9 @@ pub struct CodeGenerator<'program> {
     active_dispatchers: HashMap<Box<[ffi::TypeId]>, Dispatcher>,
     gpu_fence_type: Option<ffi::TypeId>,
     gpu_encoder_type: Option<ffi::TypeId>,
+    target: Target,
+    // For the cpu target, the cpu operation a kernel calls when no cpu version is provided
+    host_fallbacks: BTreeMap<ffi::ExternalFunctionId, ffi::ExternalFunctionId>,
 }
 
 impl<'program> CodeGenerator<'program> {
@@ -261,6 +280,8 @@ impl<'program> CodeGenerator<'program> {
             active_dispatchers: HashMap::new(),
             gpu_fence_type: None,
             gpu_encoder_type: None,
+            target: Target::Wgpu,
+            host_fallbacks: BTreeMap::new(),
         };
 
         code_generator.gpu_fence_type = Some(code_generator.create_ffi_type(ffi::Type::GpuFence));
@@ -280,6 +301,20 @@ impl<'program> CodeGenerator<'program> {
         code_generator
     }
 
+    pub fn set_target(&mut self, target: Target) {
+        self.target = target;
+    }
+
+    /// Makes the cpu target run `kernel_id` by calling the cpu operation `operation_id`
+    /// unless the caller implements the kernel itself
+    pub fn add_host_fallback(
+        &mut self,
+        kernel_id: ffi::ExternalFunctionId,
+        operation_id: ffi::ExternalFunctionId,
+    ) {
+        self.host_fallbacks.insert(kernel_id, operation_id);
+    }
+
     /// Gets the type id of the encoder
     pub fn get_encoder_type(&self) -> ffi::TypeId {
         self.gpu_encoder_type.unwrap()
@@ -296,6 +331,14 @@ impl<'program> CodeGenerator<'program> {
         &self.native_interface
     }
 
+    // The state the generated code asks for, which only needs a GPU when running through wgpu
+    fn state_trait(&self) -> &'static str {
+        match self.target {
+            Target::Wgpu => "caiman_rt::GpuState",
+            Target::Cpu => "caiman_rt::State",
+        }
+    }
+
     fn get_var_name(&self, variable_id: VarId) -> String {
         format!("var_{}", variable_id.0)
     }
@@ -504,6 +547,11 @@ impl<'program> CodeGenerator<'program> {
         argument_vars: &[VarId],
         output_vars: &[VarId],
     ) {
+        if self.target == Target::Cpu {
+            self.generate_host_dispatch(kernel, dimension_vars, argument_vars, output_vars);
+            return;
+        }
+
         let mut rw_bindings = HashSet::new();
         /*for rb in kernel.resource_bindings.iter() {
             if let Some(input) = rb.input {
@@ -592,6 +640,40 @@ impl<'program> CodeGenerator<'program> {
         self.enqueue_command_buffer(command_buffer_id);
     }
 
+    fn generate_host_dispatch(
+        &mut self,
+        kernel: &ffi::GpuKernel,
+        dimension_vars: &[VarId; 3],
+        argument_vars: &[VarId],
+        output_vars: &[VarId],
+    ) {
+        assert_eq!(kernel.input_types.len(), argument_vars.len());
+        let call_result_var = self.variable_tracker.generate();
+        write!(
+            self.code_writer,
+            "let {} = instance.cpu_functions.{}(instance.state, [{}]",
+            self.get_var_name(call_result_var),
+            kernel.name,
+            dimension_vars
+                .iter()
+                .map(|var_id| format!("({}).try_into().unwrap()", self.access_val_str(*var_id)))
+                .join(", ")
+        );
+        for argument_var in argument_vars.iter() {
+            write!(self.code_writer, ", {}", self.access_val_str(*argument_var));
+        }
+        write!(self.code_writer, ");\n");
+        for (output_index, output_var) in output_vars.iter().enumerate() {
+            write!(
+                self.code_writer,
+                "*{} = {}.{};\n",
+                self.access_ref_str(*output_var, true),
+                self.get_var_name(call_result_var),
+                output_index
+            );
+        }
+    }
+
     pub fn flush_submission(&mut self) -> SubmissionId {
         let mut active_submission_encoding_state = None;
         std::mem::swap(
@@ -600,24 +682,26 @@ impl<'program> CodeGenerator<'program> {
         );
 
         let submission_id = self.submission_queue.next_submission_id;
+        self.submission_queue.next_submission_id.0 += 1;
+        if self.target == Target::Cpu {
+            return submission_id;
+        }
 
+        // submitted even without command buffers, since that's what flushes copies
+        //   written straight to the queue
+        write!(
+            self.code_writer,
+            "let submission_index_{} = instance.state.get_queue_mut().submit([",
+            submission_id.0
+        );
         if let Some(submission_encoding_state) = active_submission_encoding_state {
-            if submission_encoding_state.command_buffer_ids.len() > 0 {
-                write!(
-                    self.code_writer,
-                    "let submission_index_{} = instance.state.get_queue_mut().submit([",
-                    submission_id.0
-                );
-                for &command_buffer_id in submission_encoding_state.command_buffer_ids.iter() {
-                    self.code_writer
-                        .write(format!("command_buffer_{}, ", command_buffer_id.0));
-                }
-                self.code_writer.write("]);\n".to_string());
-                self.submission_queue.last_submission_id_opt = Some(submission_id);
+            for &command_buffer_id in submission_encoding_state.command_buffer_ids.iter() {
+                self.code_writer
+                    .write(format!("command_buffer_{}, ", command_buffer_id.0));
             }
         }
-
-        self.submission_queue.next_submission_id.0 += 1;
+        self.code_writer.write("]);\n".to_string());
+        self.submission_queue.last_submission_id_opt = Some(submission_id);
 
         submission_id
     }
@@ -636,6 +720,15 @@ impl<'program> CodeGenerator<'program> {
         let recv_var_id = self
             .variable_tracker
             .create_fence(Some(self.gpu_fence_type.unwrap()));
+        if self.target == Target::Cpu {
+            // Work on the host is done as soon as it is submitted
+            write!(
+                self.code_writer,
+                "let {} : caiman_rt::GpuFence = None;\n",
+                self.get_var_name(recv_var_id)
+            );
+            return recv_var_id;
+        }
         write!(
             self.code_writer,
             "let {} = Some(submission_index_{}.clone());\n",
@@ -647,6 +740,9 @@ impl<'program> CodeGenerator<'program> {
     }
 
     pub fn sync_gpu_fence(&mut self, recv_var_id: VarId) {
+        if self.target == Target::Cpu {
+            return;
+        }
         write!(self.code_writer, "instance.state.get_device_mut().poll(if let Some(id) = {} {{ wgpu::Maintain::WaitForSubmissionIndex(id) }} else {{ wgpu::Maintain::Wait }});\n", self.get_var_name(recv_var_id));
     }
 
@@ -673,10 +769,16 @@ impl<'program> CodeGenerator<'program> {
 
         self.active_pipeline_name = Some(String::from(pipeline_name));
         self.code_writer.begin_module(pipeline_name);
-        write!(
-            self.code_writer,
-            "use caiman_rt::{{LocalVars, GpuLocals, wgpu, bytemuck}};\n"
-        );
+        match self.target {
+            Target::Wgpu => write!(
+                self.code_writer,
+                "use caiman_rt::{{LocalVars, GpuLocals, wgpu, bytemuck}};\n"
+            ),
+            Target::Cpu => write!(
+                self.code_writer,
+                "use caiman_rt::{{LocalVars, bytemuck}};\n"
+            ),
+        };
         write!(self.code_writer, "use std::marker::PhantomData;\n");
 
         self.code_writer.begin_module("outputs");
@@ -696,6 +798,47 @@ impl<'program> CodeGenerator<'program> {
                         self.get_tuple_definition_string(tuple_fields.as_slice())
                     );
                 }
+                if let Some(kernel) = external_cpu_function.get_gpu_kernel() {
+                    if self.target == Target::Cpu {
+                        write!(
+                            self.code_writer,
+                            "pub type {} = {};\n",
+                            kernel.name,
+                            self.get_tuple_definition_string(&kernel.output_types)
+                        );
+                    }
+                }
+            }
+        }
+        self.code_writer.end_module();
+
+        self.code_writer.begin_module("builtins");
+        {
+            for (_, external_cpu_function) in self.native_interface.external_functions.iter() {
+                if let Some(cpu_operation) = external_cpu_function.get_cpu_pure_operation() {
+                    if let Some(builtin) =
+                        BuiltinOperation::recognize(cpu_operation, &self.native_interface)
+                    {
+                        let mut arguments = Vec::<String>::new();
+                        self.code_writer
+                            .write(format!("pub fn {}(", cpu_operation.name));
+                        for (input_index, input_type) in
+                            cpu_operation.input_types.iter().enumerate()
+                        {
+                            arguments.push(format!("input_{}", input_index));
+                            self.code_writer.write(format!(
+                                "input_{} : {}, ",
+                                input_index,
+                                self.get_type_name(*input_type)
+                            ));
+                        }
+                        self.code_writer.write(format!(
+                            ") -> super::outputs::{} {{ {} }}\n",
+                            cpu_operation.name,
+                            builtin.build_body(&arguments)
+                        ));
+                    }
+                }
             }
         }
         self.code_writer.end_module();
@@ -704,6 +847,9 @@ impl<'program> CodeGenerator<'program> {
             .write(format!("pub trait CpuFunctions\n{{\n"));
         for (_, external_cpu_function) in self.native_interface.external_functions.iter() {
             if let Some(cpu_operation) = external_cpu_function.get_cpu_pure_operation() {
+                if BuiltinOperation::recognize(cpu_operation, &self.native_interface).is_some() {
+                    continue;
+                }
                 self.code_writer.write(format!(
                     "\tfn {}(&self, state : &mut caiman_rt::State",
                     cpu_operation.name
@@ -717,14 +863,88 @@ impl<'program> CodeGenerator<'program> {
                     .write(format!(") -> outputs::{};\n", cpu_operation.name));
             }
         }
+        if self.target == Target::Cpu {
+            let kernel_ids = self
+                .native_interface
+                .external_functions
+                .iter()
+                .filter(|(_, external_function)| external_function.get_gpu_kernel().is_some())
+                .map(|(external_function_id, _)| ffi::ExternalFunctionId(external_function_id))
+                .collect::<Vec<_>>();
+            for kernel_id in kernel_ids {
+                self.write_host_kernel_declaration(kernel_id);
+            }
+        }
         self.code_writer.write(format!("}}\n"));
     }
 
+    // Kernels run on the cpu target as a method of `CpuFunctions` taking the workgroup counts
+    // and the contents of the input buffers, and returning the contents of the output buffers
+    fn write_host_kernel_declaration(&mut self, kernel_id: ffi::ExternalFunctionId) {
+        let kernel = self.native_interface.external_functions[kernel_id.0]
+            .get_gpu_kernel()
+            .unwrap();
+        self.code_writer.write(format!(
+            "\tfn {}(&self, state : &mut caiman_rt::State, workgroups : [u32; 3]",
+            kernel.name
+        ));
+        for (input_index, input_type) in kernel.input_types.iter().enumerate() {
+            self.code_writer.write(format!(
+                ", input_{} : {}",
+                input_index,
+                self.get_type_name(*input_type)
+            ));
+        }
+        self.code_writer
+            .write(format!(") -> outputs::{}", kernel.name));
+
+        let operation = match self.host_fallbacks.get(&kernel_id) {
+            Some(operation_id) => self.native_interface.external_functions[operation_id.0]
+                .get_cpu_pure_operation()
+                .unwrap(),
+            None => {
+                self.code_writer.write_str(";\n");
+                return;
+            }
+        };
+        // The operation implements the same function as the kernel, so it also takes the
+        // dimensions of the dispatch
+        let mut arguments = Vec::<String>::new();
+        for dimension_index in 0..kernel.dimensionality {
+            arguments.push(format!(
+                "workgroups[{}].try_into().unwrap()",
+                dimension_index
+            ));
+        }
+        for input_index in 0..kernel.input_types.len() {
+            arguments.push(format!("input_{}", input_index));
+        }
+        self.code_writer
+            .write_str("\n\t{\n\t\tuse std::convert::TryInto;\n\t\t");
+        if BuiltinOperation::recognize(operation, &self.native_interface).is_some() {
+            self.code_writer.write(format!(
+                "builtins::{}({})\n",
+                operation.name,
+                arguments.join(", ")
+            ));
+        } else {
+            self.code_writer.write(format!(
+                "self.{}(state, {})\n",
+                operation.name,
+                arguments.join(", ")
+            ));
+        }
+        self.code_writer.write_str("\t}\n");
+    }
+
+    /// Begins the function for a funclet. If `is_loop`, the body is a loop and a call from the
+    /// funclet back to itself goes around it again rather than growing the stack.
     pub fn begin_funclet(
         &mut self,
         funclet_id: ir::FuncletId,
         input_types: &[ffi::TypeId],
         output_types: &[ffi::TypeId],
+        is_loop: bool,
     ) -> Box<[VarId]> {
         // Temporarily need to do this until pipelines are constructed correctly
         self.reset_pipeline();
@@ -755,10 +975,11 @@ impl<'program> CodeGenerator<'program> {
                 .new_arg(variable_id, input_type.clone());
             argument_variable_ids.push(variable_id);
             let type_name = self.get_type_name_with_ref(*input_type, Some("callee"));
-            let is_mutable = match &self.native_interface.types[input_type.0] {
-                ffi::Type::GpuBufferAllocator => true,
-                _ => false,
-            };
+            let is_mutable = is_loop
+                || match &self.native_interface.types[input_type.0] {
+                    ffi::Type::GpuBufferAllocator => true,
+                    _ => false,
+                };
             inputs.push((variable_id, input_type));
             if is_mutable {
                 self.code_writer.write(format!(
@@ -781,6 +1002,9 @@ impl<'program> CodeGenerator<'program> {
         );
         self.code_writer
             .write("\n{\n\tuse std::convert::TryInto;\n".to_string());
+        if is_loop {
+            self.code_writer.write("loop {\n".to_string());
+        }
 
         self.active_funclet_state = Some(ActiveFuncletState {
             funclet_id,
@@ -790,6 +1014,8 @@ impl<'program> CodeGenerator<'program> {
             output_count: 0,
             output_type_ids: output_types.to_vec().into_boxed_slice(),
             next_funclet_input_types: None,
+            loop_argument_var_ids: is_loop
+                .then(|| argument_variable_ids.clone().into_boxed_slice()),
         });
 
         argument_variable_ids.into_boxed_slice()
@@ -799,8 +1025,10 @@ impl<'program> CodeGenerator<'program> {
         &mut self,
         funclet_id: ir::FuncletId,
         input_types: &[ffi::TypeId],
+        input_names: &[String],
         output_types: &[ffi::TypeId],
         yield_points_opt: Option<&[(ffi::ExternalFunctionId, YieldPoint)]>,
+        join_stack_bound: &JoinStackBound,
     ) {
         let pipeline_name = self.active_pipeline_name.as_ref().unwrap();
 
@@ -871,9 +1099,13 @@ impl<'program> CodeGenerator<'program> {
         write!(
             self.code_writer,
             "pub struct Instance<'state, 'cpu_functions, F : CpuFunctions>{{\
-            state : & 'state mut dyn caiman_rt::State, cpu_functions : & 'cpu_functions F, \
-            locals: LocalVars, glocals: GpuLocals"
+            state : & 'state mut dyn {}, cpu_functions : & 'cpu_functions F, \
+            locals: LocalVars",
+            self.state_trait()
         );
+        if self.target == Target::Wgpu {
+            write!(self.code_writer, ", glocals: GpuLocals");
+        }
 
         for (shader_module_key, shader_module) in self.shader_modules.iter() {
             write!(
@@ -893,17 +1125,17 @@ impl<'program> CodeGenerator<'program> {
 
         write!(
             self.code_writer,
-            "{}",
             "
 		impl<'state, 'cpu_functions, F : CpuFunctions> Instance<'state, 'cpu_functions, F>
-		{
-			pub fn new(state : & 'state mut dyn caiman_rt::State, cpu_functions : & 'cpu_functions F) -> Self
-			{
-				"
+		{{
+			pub fn new(state : & 'state mut dyn {}, cpu_functions : & 'cpu_functions F) -> Self
+			{{
+				",
+            self.state_trait()
         );
 
         for (shader_module_key, shader_module) in self.shader_modules.iter_mut() {
-            write!(self.code_writer, "let {} = state.get_device_mut().create_shader_module(wgpu::ShaderModuleDescriptor {{ label : None, source : wgpu::ShaderSource::Wgsl(std::borrow::Cow::from(\"{}\"))}});\n", shader_module_key.instance_field_name(), shader_module.emit_wgsl().as_str());
+            write!(self.code_writer, "let {} = state.get_device_mut().create_shader_module(wgpu::ShaderModuleDescriptor {{ label : None, source : wgpu::ShaderSource::Wgsl(std::borrow::Cow::from(\"{}\"))}});\n", shader_module_key.instance_field_name(), shader_module.emit_wgsl().expect("Shaders are validated before code generation").as_str());
         }
 
         for (gpu_function_invocation_id, gpu_function_invocation) in
@@ -931,9 +1163,12 @@ impl<'program> CodeGenerator<'program> {
         write!(
             self.code_writer,
             "{}",
-            "
-				Self{locals: LocalVars::new(), glocals: GpuLocals::new(state), state, cpu_functions"
+            "\n\t\t\t\tSelf{locals: LocalVars::new(), "
         );
+        if self.target == Target::Wgpu {
+            write!(self.code_writer, "glocals: GpuLocals::new(state), ");
+        }
+        write!(self.code_writer, "state, cpu_functions");
 
         for (shader_module_key, shader_module) in self.shader_modules.iter() {
             write!(
@@ -1141,25 +1376,266 @@ impl<'program> CodeGenerator<'program> {
             }
         }"#;
         write!(self.code_writer, "{s}");
+
+        self.emit_pipeline_wrapper(input_types, input_names, yield_points_opt, join_stack_bound);
+    }
+
+    /// The name of the variant of `Yielded` for a yield point, in camel case
+    fn yield_variant_name(yield_point: &YieldPoint) -> String {
+        yield_point
+            .name
+            .split('_')
+            .filter(|word| !word.is_empty())
+            .map(|word| {
+                let mut chars = word.chars();
+                chars
+                    .next()
+                    .unwrap()
+                    .to_uppercase()
+                    .chain(chars)
+                    .collect::<String>()
+            })
+            .collect()
+    }
+
+    /// Emits `JOIN_STACK_BYTES`, the most bytes the joins of the active pipeline can take up on
+    /// its join stack, or a comment naming the cycle that keeps it from having a bound.
+    fn emit_join_stack_bytes(&mut self, pipeline_name: &str, join_stack_bound: &JoinStackBound) {
+        let alternatives = match join_stack_bound {
+            JoinStackBound::Bounded(alternatives) => alternatives,
+            JoinStackBound::Unbounded { cycle } => {
+                let cycle = cycle
+                    .iter()
+                    .map(|funclet_id| format!("funclet {}", funclet_id))
+                    .collect::<Vec<_>>()
+                    .join(" -> ");
+                write!(
+                    self.code_writer,
+                    "// The join stack of `{}` has no bound, since going around {} -> back pushes joins every time\n",
+                    pipeline_name, cycle
+                );
+                return;
+            }
+        };
+        write!(
+            self.code_writer,
+            "/// The most bytes the joins `{}` has pending at once can take up\npub const JOIN_STACK_BYTES : usize = ",
+            pipeline_name
+        );
+        for _ in 1..alternatives.len() {
+            write!(self.code_writer, "caiman_rt::max_join_stack_bytes(");
+        }
+        for (index, pending) in alternatives.iter().enumerate() {
+            write!(self.code_writer, "0");
+            for ((funclet_id, capture_count), count) in pending.iter() {
+                write!(self.code_writer, " + {} * (std::mem::size_of::<ClosureHeader>() + std::mem::size_of::<Funclet{}Capturing{}CapturedTuple<'static>>())", count, funclet_id, capture_count);
+            }
+            if index > 0 {
+                write!(self.code_writer, ")");
+            }
+            if index + 1 < alternatives.len() {
+                write!(self.code_writer, ", ");
+            }
+        }
+        write!(self.code_writer, ";\n");
+    }
+
+    /// Emits `Pipeline`, which owns the join stack of the active pipeline and wraps its
+    /// entry points in methods taking named arguments. Pipelines that can yield return a
+    /// `Result` of their outputs or the `Yielded` point they stopped at, and only allow
+    /// resuming at the point they last yielded at.
+    fn emit_pipeline_wrapper(
+        &mut self,
+        input_types: &[ffi::TypeId],
+        input_names: &[String],
+        yield_points_opt: Option<&[(ffi::ExternalFunctionId, YieldPoint)]>,
+        join_stack_bound: &JoinStackBound,
+    ) {
+        let pipeline_name = self.active_pipeline_name.clone().unwrap();
+        self.emit_join_stack_bytes(&pipeline_name, join_stack_bound);
+
+        let yield_points = yield_points_opt.unwrap_or(&[]);
+        let output_type = match yield_points_opt {
+            None => "PipelineOutputTuple<'callee>".to_string(),
+            Some(_) => {
+                let yielded_tuples = yield_points
+                    .iter()
+                    .map(|(_, yield_point)| {
+                        self.get_tuple_definition_string(&yield_point.yielded_types)
+                    })
+                    .collect::<Vec<_>>();
+                let yielded_type = if yielded_tuples.iter().any(|t| t.contains("'callee")) {
+                    "Yielded<'callee>"
+                } else {
+                    "Yielded"
+                };
+                write!(
+                    self.code_writer,
+                    "/// Where `{}` yielded, and what it yielded there\npub enum {} {{ ",
+                    pipeline_name, yielded_type
+                );
+                for ((_, yield_point), yielded_tuple) in
+                    yield_points.iter().zip(yielded_tuples.iter())
+                {
+                    write!(
+                        self.code_writer,
+                        "{}({}), ",
+                        Self::yield_variant_name(yield_point),
+                        yielded_tuple
+                    );
+                }
+                write!(self.code_writer, "}}\n");
+                format!("Result<PipelineOutputTuple<'callee>, {}>", yielded_type)
+            }
+        };
+
+        write!(
+            self.code_writer,
+            "/// Runs `{}`, keeping its join stack between calls\n\
+            pub struct Pipeline<'state, 'cpu_functions, 'callee, F : CpuFunctions> {{ \
+            instance : Option<Instance<'state, 'cpu_functions, F>>, \
+            join_stack : caiman_rt::OwnedJoinStack, \
+            waiting_at : Option<&'static str>, \
+            phantom : std::marker::PhantomData<& 'callee ()> }}\n",
+            pipeline_name
+        );
+        write!(
+            self.code_writer,
+            "impl<'state, 'cpu_functions, 'callee, F : CpuFunctions> Pipeline<'state, 'cpu_functions, 'callee, F> {{\n"
+        );
+        // Without a bound, the caller has to pick how big the join stack is
+        if join_stack_bound.is_bounded() {
+            write!(
+                self.code_writer,
+                "pub fn new(state : & 'state mut dyn {}, cpu_functions : & 'cpu_functions F) -> Self \
+                {{ Self::with_join_stack_bytes(state, cpu_functions, JOIN_STACK_BYTES) }}\n",
+                self.state_trait()
+            );
+        }
+        write!(
+            self.code_writer,
+            "/// Creates the pipeline with a join stack of `join_stack_bytes`\n\
+            pub fn with_join_stack_bytes(state : & 'state mut dyn {}, cpu_functions : & 'cpu_functions F, join_stack_bytes : usize) -> Self \
+            {{ Self {{ instance : Some(Instance::new(state, cpu_functions)), join_stack : caiman_rt::OwnedJoinStack::new(join_stack_bytes), waiting_at : None, phantom : std::marker::PhantomData }} }}\n",
+            self.state_trait()
+        );
+
+        // Starting over drops whatever the last run left on the join stack
+        write!(self.code_writer, "pub fn start(&mut self");
+        for (input_name, input_type) in input_names.iter().zip(input_types.iter()) {
+            write!(
+                self.code_writer,
+                ", {} : {}",
+                input_name,
+                self.get_type_name(*input_type)
+            );
+        }
+        write!(
+            self.code_writer,
+            ") -> {} {{ self.join_stack.clear(); \
+            let instance = self.instance.take().expect(\"Pipeline {} panicked during an earlier call\"); \
+            let result = unsafe {{ self.join_stack.with_join_stack(|join_stack| instance.start(join_stack",
+            output_type, pipeline_name
+        );
+        for input_name in input_names.iter() {
+            write!(self.code_writer, ", {}", input_name);
+        }
+        write!(self.code_writer, ")) }}; self.finish(result) }}\n");
+
+        for (_, yield_point) in yield_points.iter() {
+            write!(
+                self.code_writer,
+                "pub fn resume_at_{}(&mut self",
+                yield_point.name
+            );
+            for (resuming_index, resuming_type) in yield_point.resuming_types.iter().enumerate() {
+                write!(
+                    self.code_writer,
+                    ", arg_{} : {}",
+                    resuming_index,
+                    self.get_type_name(*resuming_type)
+                );
+            }
+            // Resuming anywhere but where the pipeline last yielded is an error rather than a
+            // panic, since the join stack wouldn't hold what that yield point expects
+            write!(
+                self.code_writer,
+                ") -> Result<{}, caiman_rt::ResumeError> {{ if self.waiting_at != Some(\"{}\") {{ \
+                return Err(caiman_rt::ResumeError::NotWaitingAt {{ resumed_at : \"{}\", waiting_at : self.waiting_at }}); }} \
+                let instance = self.instance.take().expect(\"Pipeline {} panicked during an earlier call\"); \
+                let result = unsafe {{ self.join_stack.with_join_stack(|join_stack| instance.resume_at_{}(join_stack",
+                output_type, yield_point.name, yield_point.name, pipeline_name, yield_point.name
+            );
+            for resuming_index in 0..yield_point.resuming_types.len() {
+                write!(self.code_writer, ", arg_{}", resuming_index);
+            }
+            write!(self.code_writer, ")) }}; Ok(self.finish(result)) }}\n");
+        }
+
+        write!(
+            self.code_writer,
+            "fn finish(&mut self, result : FuncletResult<'state, 'cpu_functions, 'callee, F, PipelineOutputTuple<'callee>>) -> {} \
+            {{ let FuncletResult {{ instance, intermediates, .. }} = result; self.instance = Some(instance); \
+            match intermediates {{ ",
+            output_type
+        );
+        match yield_points_opt {
+            None => write!(
+                self.code_writer,
+                "FuncletResultIntermediates::Return(outputs) => outputs, "
+            ),
+            Some(_) => write!(
+                self.code_writer,
+                "FuncletResultIntermediates::Return(outputs) => {{ self.waiting_at = None; Ok(outputs) }} "
+            ),
+        };
+        for (yield_point_id, yield_point) in yield_points.iter() {
+            write!(
+                self.code_writer,
+                "FuncletResultIntermediates::Yield{} {{ yielded }} => {{ self.waiting_at = Some(\"{}\"); Err(Yielded::{}(yielded)) }} ",
+                yield_point_id.0,
+                yield_point.name,
+                Self::yield_variant_name(yield_point)
+            );
+        }
+        write!(self.code_writer, "}} }}\n}}\n");
     }
 
     pub fn emit_oneshot_pipeline_entry_point(
         &mut self,
         funclet_id: ir::FuncletId,
         input_types: &[ffi::TypeId],
+        input_names: &[String],
         output_types: &[ffi::TypeId],
+        join_stack_bound: &JoinStackBound,
     ) {
-        self.emit_pipeline_entry_point(funclet_id, input_types, output_types, None)
+        self.emit_pipeline_entry_point(
+            funclet_id,
+            input_types,
+            input_names,
+            output_types,
+            None,
+            join_stack_bound,
+        )
     }
 
     pub fn emit_yieldable_pipeline_entry_point(
         &mut self,
         funclet_id: ir::FuncletId,
         input_types: &[ffi::TypeId],
+        input_names: &[String],
         output_types: &[ffi::TypeId],
         yield_points: &[(ffi::ExternalFunctionId, YieldPoint)],
+        join_stack_bound: &JoinStackBound,
     ) {
-        self.emit_pipeline_entry_point(funclet_id, input_types, output_types, Some(yield_points))
+        self.emit_pipeline_entry_point(
+            funclet_id,
+            input_types,
+            input_names,
+            output_types,
+            Some(yield_points),
+            join_stack_bound,
+        )
     }
 
     pub fn build_indirect_stack_jump_to_popped_serialized_join<'a>(
@@ -1194,6 +1670,64 @@ impl<'program> CodeGenerator<'program> {
         write!(self.code_writer, ", instance);\n");
     }
 
+    /// Builds a tail call to the function of another funclet
+    /// # Arguments
+    /// * `funclet_id` - The funclet being called
+    /// * `argument_var_ids` - The variable ids of the arguments
+    /// * `argument_types` - The input types of the funclet being called
+    pub fn build_call_funclet(
+        &mut self,
+        funclet_id: ir::FuncletId,
+        argument_var_ids: &[VarId],
+        argument_types: &[ffi::TypeId],
+    ) {
+        let mut arguments = Vec::<String>::new();
+        let mut i = 0;
+        for var_type in argument_types.iter() {
+            if *var_type == self.get_encoder_type() {
+                // dummy argument for the encoder, no argument_var_id for this
+                arguments.push(String::from("()"));
+                continue;
+            }
+            let var_id = argument_var_ids[i];
+            i += 1;
+            if self.is_cpu_ref(*var_type) {
+                arguments.push(self.make_stack_ref(var_id));
+            } else if self.is_gpu_buf(*var_type) {
+                arguments.push(self.access_gpu_str(var_id));
+            } else {
+                arguments.push(self.access_val_str(var_id));
+            }
+        }
+        let active_funclet_state = self.active_funclet_state.as_ref().unwrap();
+        if let (true, Some(loop_argument_var_ids)) = (
+            active_funclet_state.funclet_id == funclet_id,
+            &active_funclet_state.loop_argument_var_ids,
+        ) {
+            // A call back to the funclet it's in is the loop going around again, with the
+            //   arguments assigned all at once since they may be each other's old values
+            write!(self.code_writer, "{{ (");
+            for var_id in loop_argument_var_ids.iter() {
+                write!(self.code_writer, "{}, ", self.get_var_name(*var_id));
+            }
+            write!(self.code_writer, ") = (");
+            for argument in arguments.iter() {
+                write!(self.code_writer, "{}, ", argument);
+            }
+            write!(self.code_writer, "); continue; }}\n");
+            return;
+        }
+        write!(
+            self.code_writer,
+            "return funclet{}_func(instance, join_stack",
+            funclet_id
+        );
+        for argument in arguments.iter() {
+            write!(self.code_writer, ", {}", argument);
+        }
+        write!(self.code_writer, ");\n");
+    }
+
     /// Builds a return statement for the current funclet
     /// # Arguments
     /// * `output_var_ids` - The variable ids of the variables being returned
@@ -1290,6 +1824,13 @@ impl<'program> CodeGenerator<'program> {
     }
 
     pub fn end_funclet(&mut self) {
+        if let Some(ActiveFuncletState {
+            loop_argument_var_ids: Some(_),
+            ..
+        }) = &self.active_funclet_state
+        {
+            self.code_writer.write("}\n".to_string());
+        }
         self.code_writer.write("}\n".to_string());
 
         self.active_funclet_result_type_ids = None;
@@ -1332,10 +1873,15 @@ impl<'program> CodeGenerator<'program> {
                 length,
             } => (),
             ffi::Type::Tuple { fields } => {
-                write!(self.type_code_writer, "pub type type_{} = (", type_id.0);
+                // Rust tuples have no defined layout, so a tuple is a repr(C) tuple struct
+                write!(
+                    self.type_code_writer,
+                    "#[repr(C)]\npub struct type_{}(",
+                    type_id.0
+                );
                 for (index, field_type_id) in fields.iter().enumerate() {
                     let type_name = self.get_type_name(*field_type_id);
-                    write!(self.type_code_writer, "{}, ", type_name);
+                    write!(self.type_code_writer, "pub {}, ", type_name);
                 }
                 self.type_code_writer.write_str(");\n");
             }
@@ -1344,6 +1890,13 @@ impl<'program> CodeGenerator<'program> {
                 byte_alignment,
                 byte_size,
             } => {
+                // The declared layout has been checked against repr(C), see `layout`
+                match byte_alignment {
+                    Some(alignment) => {
+                        write!(self.type_code_writer, "#[repr(C, align({}))]\n", alignment)
+                    }
+                    None => write!(self.type_code_writer, "#[repr(C)]\n"),
+                };
                 write!(self.type_code_writer, "pub struct type_{}", type_id.0);
                 self.type_code_writer.write_str("{\n");
                 for field in fields.iter() {
@@ -1358,6 +1911,16 @@ impl<'program> CodeGenerator<'program> {
             }
             ffi::Type::GpuBufferRef { element_type } => (),
             ffi::Type::GpuBufferSlice { element_type } => (),
+            ffi::Type::ErasedLengthArray { element_type } => {
+                // Values are copied into local slots and join stacks, which need a
+                // `Copy + 'static` type, so an unsized array is passed as a static slice
+                let type_name = self.get_type_name(type_id);
+                write!(
+                    self.type_code_writer,
+                    "pub type type_{} = {};\n",
+                    type_id.0, type_name
+                );
+            }
             ffi::Type::GpuBufferAllocator => (),
             ffi::Type::CpuBufferAllocator => (),
             ffi::Type::GpuFence => (),
@@ -1367,17 +1930,27 @@ impl<'program> CodeGenerator<'program> {
         }
     }
 
-    fn get_type_binding_info(&self, type_id: ffi::TypeId) -> ffi::TypeBindingInfo {
-        self.native_interface.calculate_type_binding_info(type_id)
+    // The layout of `type_id` in a buffer on `place`, the same one the type checker allocated
+    fn get_type_binding_info(
+        &self,
+        type_id: ffi::TypeId,
+        place: ir::Place,
+    ) -> Result<ffi::TypeBindingInfo, LayoutError> {
+        self.native_interface
+            .calculate_type_layout(type_id, LayoutRules::for_place(place))
     }
 
-    /// Returns true if the type is a CPU reference type
+    /// Returns true if the type is a CPU reference type. On the cpu target, this includes
+    /// GPU buffer types, since their contents live in host memory.
     fn is_cpu_ref(&self, type_id: ffi::TypeId) -> bool {
         match &self.native_interface.types[type_id.0] {
             ffi::Type::ConstRef { .. }
             | ffi::Type::MutRef { .. }
             | ffi::Type::ConstSlice { .. }
             | ffi::Type::MutSlice { .. } => true,
+            ffi::Type::GpuBufferRef { .. } | ffi::Type::GpuBufferSlice { .. } => {
+                self.target == Target::Cpu
+            }
             _ => false,
         }
     }
@@ -1393,6 +1966,9 @@ impl<'program> CodeGenerator<'program> {
     fn is_cpu_mut_ref(&self, type_id: ffi::TypeId) -> bool {
         match &self.native_interface.types[type_id.0] {
             ffi::Type::MutRef { .. } | ffi::Type::MutSlice { .. } => true,
+            ffi::Type::GpuBufferRef { .. } | ffi::Type::GpuBufferSlice { .. } => {
+                self.target == Target::Cpu
+            }
             _ => false,
         }
     }
@@ -1402,6 +1978,24 @@ impl<'program> CodeGenerator<'program> {
     }
 
     fn get_type_name_with_ref(&self, type_id: ffi::TypeId, lifetime: Option<&str>) -> String {
+        match (&self.native_interface.types[type_id.0], self.target) {
+            // Buffers on the cpu target are named like the local references they stand in for
+            (ffi::Type::GpuBufferRef { element_type }, Target::Cpu) => {
+                let element_type_name = self.get_type_name_with_ref(*element_type, lifetime);
+                return match lifetime {
+                    Some(_) => format!("StackRef<{}>", element_type_name),
+                    None => format!("&mut {}", element_type_name),
+                };
+            }
+            (ffi::Type::GpuBufferSlice { element_type }, Target::Cpu) => {
+                let element_type_name = self.get_type_name_with_ref(*element_type, lifetime);
+                return match lifetime {
+                    Some(_) => format!("StackRef<[{}]>", element_type_name),
+                    None => format!("&mut [{}]", element_type_name),
+                };
+            }
+            _ => (),
+        }
         match &self.native_interface.types[type_id.0] {
             ffi::Type::F32 => "f32".to_string(),
             ffi::Type::F64 => "f64".to_string(),
@@ -1471,6 +2065,11 @@ impl<'program> CodeGenerator<'program> {
                 self.get_type_name_with_ref(*element_type, lifetime),
                 length
             ),
+            // Spelled out since pipeline modules don't see the definitions of named types
+            ffi::Type::ErasedLengthArray { element_type } => format!(
+                "&'static [{}]",
+                self.get_type_name_with_ref(*element_type, lifetime)
+            ),
             ffi::Type::GpuBufferRef { element_type } => format!(
                 "caiman_rt::GpuBufferRef<{}>",
                 self.get_type_name_with_ref(*element_type, lifetime)
@@ -1569,6 +2168,26 @@ impl<'program> CodeGenerator<'program> {
         )
     }
 
+    // a float is written with its fractional part so Rust doesn't read it as an integer,
+    //   unless it's infinite or NaN and has no literal to write
+    pub fn build_constant_f32(&mut self, value: f32, type_id: ffi::TypeId) -> VarId {
+        let literal = if value.is_finite() {
+            format!("{:?}", value)
+        } else {
+            format!("f32::from_bits({})", value.to_bits())
+        };
+        self.build_const_int(literal, &self.get_stripped_type_name(type_id), type_id)
+    }
+
+    pub fn build_constant_f64(&mut self, value: f64, type_id: ffi::TypeId) -> VarId {
+        let literal = if value.is_finite() {
+            format!("{:?}", value)
+        } else {
+            format!("f64::from_bits({})", value.to_bits())
+        };
+        self.build_const_int(literal, &self.get_stripped_type_name(type_id), type_id)
+    }
+
     pub fn build_select_hack(
         &mut self,
         condition_var_id: VarId,
@@ -1659,12 +2278,21 @@ impl<'program> CodeGenerator<'program> {
                 argument_string += ", ";
             }
         }
-        self.code_writer.write(format!(
-            "let {} = instance.cpu_functions.{}(instance.state, {});\n",
-            self.get_var_name(call_result_var),
-            external_cpu_function.name,
-            argument_string
-        ));
+        if BuiltinOperation::recognize(external_cpu_function, &self.native_interface).is_some() {
+            self.code_writer.write(format!(
+                "let {} = builtins::{}({});\n",
+                self.get_var_name(call_result_var),
+                external_cpu_function.name,
+                argument_string
+            ));
+        } else {
+            self.code_writer.write(format!(
+                "let {} = instance.cpu_functions.{}(instance.state, {});\n",
+                self.get_var_name(call_result_var),
+                external_cpu_function.name,
+                argument_string
+            ));
+        }
         let mut output_variables = Vec::<VarId>::new();
         for (i, output_type) in external_cpu_function.output_types.iter().enumerate() {
             let var = self.variable_tracker.create_local_alloc(Some(*output_type));
@@ -1684,10 +2312,19 @@ impl<'program> CodeGenerator<'program> {
         &mut self,
         type_id: ffi::TypeId,
         buffer_flags: ir::BufferFlags,
-    ) -> VarId {
+    ) -> Result<VarId, LayoutError> {
+        // Fails for types that can't be stored on the GPU
+        self.get_type_binding_info(type_id, ir::Place::Gpu)?;
         let variable_id = self.variable_tracker.create_local_gpu(Some(type_id));
-        let type_binding_info = self.get_type_binding_info(type_id);
         let type_name = self.get_stripped_type_name(type_id);
+        if self.target == Target::Cpu {
+            write!(
+                self.code_writer,
+                "instance.locals.malloc::<{type_name}>({});\n",
+                variable_id.0
+            );
+            return Ok(variable_id);
+        }
         write!(
             self.code_writer,
             "instance.glocals.alloc_gpu::<{type_name}>(instance.state, {}, wgpu::BufferUsages::empty()",
@@ -1713,7 +2350,7 @@ impl<'program> CodeGenerator<'program> {
         }
         write!(self.code_writer, ");\n");
         //self.code_writer.write(format!("let mut {} = instance.state.get_device_mut().create_buffer(& wgpu::BufferDescriptor {{ label : None, size : {}, usage : wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::MAP_WRITE, mapped_at_creation : false}});\n", self.get_var_name(variable_id), type_binding_info.size));
-        variable_id
+        Ok(variable_id)
     }
 
     pub fn build_buffer_ref(&mut self, buffer_var_id: VarId, type_id: ffi::TypeId) -> VarId {
@@ -1725,13 +2362,11 @@ impl<'program> CodeGenerator<'program> {
         buffer_allocator_var_id: VarId,
         type_id: ffi::TypeId,
     ) -> VarId {
-        todo!();
-        let variable_id = self.variable_tracker.create_local_data(None);
-        let type_binding_info = self.get_type_binding_info(type_id);
+        let variable_id = self.variable_tracker.create_buffer(Some(type_id));
         let type_name = self.get_type_name(type_id);
         write!(
             self.code_writer,
-            "let {} = {}.suballocate_ref::<'callee, {}>().unwrap();\n",
+            "let {} = {}.suballocate_ref::<{}>().unwrap();\n",
             self.get_var_name(variable_id),
             self.get_var_name(buffer_allocator_var_id),
             type_name
@@ -1745,42 +2380,45 @@ impl<'program> CodeGenerator<'program> {
         type_id: ffi::TypeId,
         count_var_id: VarId,
     ) -> VarId {
-        todo!();
-        let variable_id = self.variable_tracker.create_local_data(None);
-        let type_binding_info = self.get_type_binding_info(type_id);
+        let variable_id = self.variable_tracker.create_buffer(Some(type_id));
         let type_name = self.get_type_name(type_id);
         write!(
             self.code_writer,
-            "let {} = {}.suballocate_slice::<'callee, {}>({}).unwrap();\n",
+            "let {} = {}.suballocate_slice::<{}>({} as usize).unwrap();\n",
             self.get_var_name(variable_id),
             self.get_var_name(buffer_allocator_var_id),
             type_name,
-            self.get_var_name(count_var_id)
+            self.access_val_str(count_var_id)
         );
         variable_id
     }
 
+    /// Builds a check of whether every allocation fits in the buffer, without allocating
+    /// any of them. Returns a variable which is nonzero when they all fit.
+    /// # Arguments
+    /// * `buffer_allocator_var_id` - The allocator of the buffer
+    /// * `type_id_and_count_var_id_pairs` - The element type of each allocation, and the
+    ///     variable holding its element count if it is a slice
     pub fn build_test_suballocate_many(
         &mut self,
         buffer_allocator_var_id: VarId,
         type_id_and_count_var_id_pairs: &[(ffi::TypeId, Option<VarId>)],
     ) -> VarId {
-        todo!();
         let mut layouts_string = String::from("");
         let mut element_counts_string = String::from("");
 
         for (type_id, count_var_id_opt) in type_id_and_count_var_id_pairs.iter() {
-            let type_binding_info = self.get_type_binding_info(*type_id);
+            let type_name = self.get_type_name(*type_id);
             write!(
                 layouts_string,
-                "caiman_rt::TypeLayout{{byte_size : {}, alignment : {}}}, ",
-                type_binding_info.size, type_binding_info.alignment
+                "caiman_rt::TypeLayout{{byte_size : std::mem::size_of::<{}>(), alignment : std::mem::align_of::<{}>()}}, ",
+                type_name, type_name
             );
             if let Some(count_var_id) = count_var_id_opt {
                 write!(
                     element_counts_string,
-                    "Some({}), ",
-                    self.get_var_name(*count_var_id)
+                    "Some({} as usize), ",
+                    self.access_val_str(*count_var_id)
                 );
             } else {
                 write!(element_counts_string, "None, ");
@@ -1790,11 +2428,12 @@ impl<'program> CodeGenerator<'program> {
         let success_var_id = self.variable_tracker.generate();
         write!(
             self.code_writer,
-            "let {} = {}.test_suballocate_many(&[{}], &[{}]);\n",
+            "let {} = ({}.test_suballocate_many(&[{}], &[{}]) == {}) as usize;\n",
             self.get_var_name(success_var_id),
             self.get_var_name(buffer_allocator_var_id),
             layouts_string,
-            element_counts_string
+            element_counts_string,
+            type_id_and_count_var_id_pairs.len()
         );
 
         success_var_id
@@ -1841,24 +2480,42 @@ impl<'program> CodeGenerator<'program> {
         &mut self,
         source_var: VarId,
         type_id: ffi::TypeId,
-    ) -> VarId {
+    ) -> Result<VarId, LayoutError> {
         //let type_id = self.variable_tracker.get_type_id(source_var);
 
+        if self.target == Target::Cpu {
+            let output_var_id = self.variable_tracker.create_local_data(Some(type_id));
+            write!(
+                self.code_writer,
+                "let {} = {};\n",
+                self.get_var_name(output_var_id),
+                self.access_val_str(source_var)
+            );
+            return Ok(output_var_id);
+        }
+
         let range_var_id = self.variable_tracker.generate();
         let output_temp_var_id = self.variable_tracker.generate();
         let slice_var_id = self.variable_tracker.generate();
         let future_var_id = self.variable_tracker.generate();
-        let type_binding_info = self.get_type_binding_info(type_id);
+        let gpu_ref_var_id = self.variable_tracker.generate();
+        // Fails for types that can't be stored on the GPU
+        self.get_type_binding_info(type_id, ir::Place::Gpu)?;
         let type_name = self.get_type_name(type_id);
 
         let output_var_id = self.variable_tracker.create_local_data(Some(type_id));
 
         self.code_writer
             .write(format!("let {} = {{\n", self.get_var_name(output_var_id)));
+        self.code_writer.write(format!(
+            "let {} = {};\n",
+            self.get_var_name(gpu_ref_var_id),
+            self.build_get_gpu_ref(source_var, None)
+        ));
         self.code_writer.write(format!(
             "let {} = {}.slice();\n",
             self.get_var_name(slice_var_id),
-            self.build_get_gpu_ref(source_var, None)
+            self.get_var_name(gpu_ref_var_id)
         ));
         self.code_writer.write(format!(
             "let ({0}_send, {0}_recv) = futures::channel::oneshot::channel::<()>();\n",
@@ -1878,12 +2535,20 @@ impl<'program> CodeGenerator<'program> {
             self.get_var_name(slice_var_id)
         ));
         self.code_writer.write(format!(
-            "*unsafe {{ std::mem::transmute::<* const u8, & {}>({}.as_ptr()) }}\n",
+            "let {} = *unsafe {{ std::mem::transmute::<* const u8, & {}>({}.as_ptr()) }};\n",
+            self.get_var_name(output_temp_var_id),
             type_name,
             self.get_var_name(range_var_id)
         ));
+        // The buffer stays mapped until it's unmapped, so other slots in it can be read later
+        self.code_writer.write(format!(
+            "drop({});\n{}.unmap();\n{}\n",
+            self.get_var_name(range_var_id),
+            self.get_var_name(gpu_ref_var_id),
+            self.get_var_name(output_temp_var_id)
+        ));
         self.code_writer.write(String::from("};\n"));
-        return output_var_id;
+        return Ok(output_var_id);
     }
 
     /// Returns a string representing variable `var` as a slice of little-endian bytes.
@@ -1898,10 +2563,16 @@ impl<'program> CodeGenerator<'program> {
             F32 | F64 | U8 | U16 | U32 | U64 | USize | I8 | I16 | I32 | I64 => {
                 return format!("&{}.to_le_bytes()", self.access_val_str(var),)
             }
+            // Copied out so the bytes don't borrow the instance while the queue is written
             Array {
                 element_type,
                 length,
-            } => return format!("bytemuck::cast_slice(&{})", self.access_val_str(var)),
+            } => {
+                return format!(
+                    "&bytemuck::cast_slice::<_, u8>(&{}).to_vec()",
+                    self.access_val_str(var)
+                )
+            }
             _ => panic!("type {:?} not yet supported", var_type),
         }
     }
@@ -1957,6 +2628,10 @@ impl<'program> CodeGenerator<'program> {
         source_var: VarId,
         type_id: ffi::TypeId,
     ) {
+        if self.target == Target::Cpu {
+            self.build_host_buffer_copy(destination_var, source_var);
+            return;
+        }
         let buffer_view_var_name = self.get_var_name(destination_var);
         write!(self.code_writer, "{{ \n");
         let source_bytes = self.local_as_le_bytes(source_var, type_id);
@@ -1978,22 +2653,40 @@ impl<'program> CodeGenerator<'program> {
         destination_var: VarId,
         source_var: VarId,
         type_id: ffi::TypeId,
-    ) {
+    ) -> Result<(), LayoutError> {
         //let type_id = self.variable_tracker.get_type_id(source_var);
         //assert_eq!(type_id, self.variable_tracker.get_type_id(destination_var));
-        let type_binding_info = self.get_type_binding_info(type_id);
-        let type_name = self.get_type_name(type_id);
+        if self.target == Target::Cpu {
+            self.build_host_buffer_copy(destination_var, source_var);
+            return Ok(());
+        }
+        let type_binding_info = self.get_type_binding_info(type_id, ir::Place::Gpu)?;
         self.begin_command_encoding();
         write!(
             self.code_writer,
-            "command_encoder.copy_buffer_to_buffer(& {}, 0, \
-                & {}, 0, {});\n",
-            self.build_get_gpu_ref(destination_var, Some(type_id)),
+            "{{\nlet source = {};\nlet destination = {};\n",
             self.build_get_gpu_ref(source_var, Some(type_id)),
+            self.build_get_gpu_ref(destination_var, Some(type_id))
+        );
+        write!(
+            self.code_writer,
+            "command_encoder.copy_buffer_to_buffer(unsafe {{ &*source.buffer }}, source.base_address, \
+                unsafe {{ &*destination.buffer }}, destination.base_address, {});\n}}\n",
             type_binding_info.size
         );
         let command_buffer_id = self.end_command_encoding();
         self.enqueue_command_buffer(command_buffer_id);
+        Ok(())
+    }
+
+    // GPU buffers are host memory on the cpu target, so copies into them are plain writes
+    fn build_host_buffer_copy(&mut self, destination_var: VarId, source_var: VarId) {
+        write!(
+            self.code_writer,
+            "*{} = {};\n",
+            self.access_ref_str(destination_var, true),
+            self.access_val_str(source_var)
+        );
     }
 
     /*fn build_create_buffer_with_data(&mut self, data_var: VarId, type_id: ffi::TypeId, buffer_flags : ir::BufferFlags) -> VarId {
@@ -1,7 +1,8 @@
 use crate::debug_info::DebugInfo;
 use crate::ir::{self, Funclet, Program, Type};
 use crate::rust_wgpu_backend::code_generator;
-use crate::rust_wgpu_backend::code_generator::{CodeGenerator, SubmissionId, VarId};
+use crate::rust_wgpu_backend::code_generator::{CodeGenerator, SubmissionId, Target, VarId};
+use crate::rust_wgpu_backend::layout::LayoutError;
 use crate::shadergen;
 use crate::stable_vec::StableVec;
 use crate::type_system;
@@ -150,6 +151,59 @@ impl JoinGraph {
     }
 }
 
+// The funclets that a schedule call can lead back to without yielding, such as the functions
+//   loops are desugared into. Inlining a call to one of these would never finish, so the call
+//   is compiled as a call to the funclet's function instead, and the function's body is a loop
+//   that a call back to itself goes around rather than recursing.
+fn find_recursive_funclets(program: &ir::Program) -> HashSet<ir::FuncletId> {
+    let successors = |funclet: &ir::Funclet| {
+        let mut successors = Vec::<ir::FuncletId>::new();
+        if !matches!(funclet.tail_edge, ir::TailEdge::ScheduleCallYield { .. }) {
+            for node in funclet.nodes.iter() {
+                match node {
+                    ir::Node::InlineJoin { funclet, .. }
+                    | ir::Node::SerializedJoin { funclet, .. } => successors.push(*funclet),
+                    _ => (),
+                }
+            }
+        }
+        match &funclet.tail_edge {
+            ir::TailEdge::ScheduleCall {
+                callee_funclet_id, ..
+            } => successors.push(*callee_funclet_id),
+            ir::TailEdge::ScheduleSelect {
+                callee_funclet_ids, ..
+            } => successors.extend(callee_funclet_ids.iter()),
+            ir::TailEdge::DynamicAllocFromBuffer {
+                success_funclet_id,
+                failure_funclet_id,
+                ..
+            } => successors.extend([*success_funclet_id, *failure_funclet_id]),
+            _ => (),
+        }
+        successors
+    };
+
+    let mut recursive_funclets = HashSet::<ir::FuncletId>::new();
+    for (callee_id, _) in program.funclets.iter() {
+        let mut visited = HashSet::<ir::FuncletId>::new();
+        let mut stack = vec![callee_id];
+        while let Some(funclet_id) = stack.pop() {
+            if !visited.insert(funclet_id) {
+                continue;
+            }
+            let funclet = &program.funclets[funclet_id];
+            if matches!(&funclet.tail_edge, ir::TailEdge::ScheduleCall { callee_funclet_id, .. } if *callee_funclet_id == callee_id)
+            {
+                recursive_funclets.insert(callee_id);
+                break;
+            }
+            stack.extend(successors(funclet));
+        }
+    }
+    recursive_funclets
+}
+
 #[derive(Debug)]
 enum SplitPoint {
     Call {
@@ -359,6 +413,78 @@ impl PipelineContext {
     }
 }
 
+// Identifiers a generated pipeline wrapper can't use for an argument, either because they're
+//   reserved by Rust or because the wrapper uses them itself
+const RESERVED_NAMES: &[&str] = &[
+    "as",
+    "async",
+    "await",
+    "break",
+    "const",
+    "continue",
+    "crate",
+    "dyn",
+    "else",
+    "enum",
+    "extern",
+    "false",
+    "fn",
+    "for",
+    "if",
+    "impl",
+    "in",
+    "let",
+    "loop",
+    "match",
+    "mod",
+    "move",
+    "mut",
+    "pub",
+    "ref",
+    "return",
+    "self",
+    "Self",
+    "static",
+    "struct",
+    "super",
+    "trait",
+    "true",
+    "type",
+    "unsafe",
+    "use",
+    "where",
+    "while",
+    "abstract",
+    "become",
+    "box",
+    "do",
+    "final",
+    "macro",
+    "override",
+    "priv",
+    "try",
+    "typeof",
+    "unsized",
+    "virtual",
+    "yield",
+    "_",
+    "instance",
+    "result",
+    "join_stack",
+];
+
+fn is_argument_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    let starts_identifier = chars
+        .next()
+        .map_or(false, |c| c.is_ascii_alphabetic() || c == '_');
+    starts_identifier
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !RESERVED_NAMES.contains(&name)
+        // so they can't collide with unnamed arguments
+        && !name.starts_with("arg_")
+}
+
 pub struct CodeGen<'program> {
     program: &'program ir::Program,
     debug_info: &'program DebugInfo,
@@ -367,6 +493,7 @@ pub struct CodeGen<'program> {
     generated_local_slot_ffi_type_map: HashMap<ir::TypeId, ir::ffi::TypeId>,
     default_usize_ffi_type_id: ir::ffi::TypeId,
     default_u64_ffi_type_id: ir::ffi::TypeId,
+    recursive_funclet_ids: HashSet<ir::FuncletId>,
 }
 
 impl<'program> CodeGen<'program> {
@@ -382,6 +509,7 @@ impl<'program> CodeGen<'program> {
             generated_local_slot_ffi_type_map: HashMap::new(),
             default_usize_ffi_type_id,
             default_u64_ffi_type_id,
+            recursive_funclet_ids: find_recursive_funclets(program),
         }
     }
 
@@ -389,6 +517,45 @@ impl<'program> CodeGen<'program> {
         self.print_codegen_debug_info = to;
     }
 
+    pub fn set_target(&mut self, target: Target) {
+        self.code_generator.set_target(target);
+        if target != Target::Cpu {
+            return;
+        }
+
+        // A cpu operation implementing the same function class as a kernel computes the same
+        // thing, so the host can run it in place of the kernel
+        let external_functions = &self.program.native_interface.external_functions;
+        for (_, function_class) in self.program.function_classes.iter() {
+            for kernel_id in function_class.external_function_ids.iter() {
+                let kernel = match external_functions[kernel_id.0].get_gpu_kernel() {
+                    Some(kernel) => kernel,
+                    None => continue,
+                };
+                let operation_id_opt =
+                    function_class
+                        .external_function_ids
+                        .iter()
+                        .find(
+                            |id| match external_functions[id.0].get_cpu_pure_operation() {
+                                Some(operation) => {
+                                    operation.input_types.len()
+                                        == kernel.dimensionality + kernel.input_types.len()
+                                        && operation.input_types[kernel.dimensionality..]
+                                            == kernel.input_types[..]
+                                        && operation.output_types == kernel.output_types
+                                }
+                                None => false,
+                            },
+                        );
+                if let Some(operation_id) = operation_id_opt {
+                    self.code_generator
+                        .add_host_fallback(*kernel_id, *operation_id);
+                }
+            }
+        }
+    }
+
     // The (rust) ffi type we need to refer to the data from a cpu function
     fn get_cpu_useable_type(&mut self, type_id: ir::TypeId) -> ir::ffi::TypeId {
         if let Some(ffi_type_id) = self.generated_local_slot_ffi_type_map.get(&type_id) {
@@ -608,6 +775,30 @@ impl<'program> CodeGen<'program> {
                     ir::Constant::I32(value) => self
                         .code_generator
                         .build_constant_int(*value, storage_type_id),
+                    ir::Constant::I8(value) => self
+                        .code_generator
+                        .build_constant_int(*value, storage_type_id),
+                    ir::Constant::I16(value) => self
+                        .code_generator
+                        .build_constant_int(*value, storage_type_id),
+                    ir::Constant::U8(value) => self
+                        .code_generator
+                        .build_constant_int(*value, storage_type_id),
+                    ir::Constant::U16(value) => self
+                        .code_generator
+                        .build_constant_int(*value, storage_type_id),
+                    ir::Constant::U32(value) => self
+                        .code_generator
+                        .build_constant_int(*value, storage_type_id),
+                    ir::Constant::USize(value) => self
+                        .code_generator
+                        .build_constant_int(*value, storage_type_id),
+                    ir::Constant::F32(value) => self
+                        .code_generator
+                        .build_constant_f32(*value, storage_type_id),
+                    ir::Constant::F64(value) => self
+                        .code_generator
+                        .build_constant_f64(*value, storage_type_id),
                 };
                 check_storage_type_implements_value_type(&self.program, storage_type_id, *type_id);
 
@@ -822,6 +1013,7 @@ impl<'program> CodeGen<'program> {
     /// * None for no unhandled split points, otherwise, a tuple of the
     ///     unhandled split point node results and a boolean indicating whether
     ///     the split point is a call
+    /// * An error if the funclet uses a type that has no layout where it's stored
     fn process_current_funclet(
         &mut self,
         current_funclet_id: ir::FuncletId,
@@ -832,14 +1024,14 @@ impl<'program> CodeGen<'program> {
         func_inline: bool,
         branch_inline: bool,
         pipeline_rets: &[ffi::TypeId],
-    ) -> Option<(Box<[NodeResult]>, bool)> {
+    ) -> Result<Option<(Box<[NodeResult]>, bool)>, LayoutError> {
         let split_point = self.compile_scheduling_funclet(
             current_funclet_id,
             &current_output_node_results,
             pipeline_context,
             default_join_point_id_opt,
             func_inline || branch_inline,
-        );
+        )?;
 
         //println!("Split point: {:?}", split_point);
         match split_point {
@@ -849,7 +1041,7 @@ impl<'program> CodeGen<'program> {
             } => {
                 // schedule call
                 *default_join_point_id_opt = continuation_join_point_id_opt;
-                return Some((return_node_results, true));
+                return Ok(Some((return_node_results, true)));
             }
             SplitPoint::Jump {
                 return_node_results,
@@ -864,7 +1056,7 @@ impl<'program> CodeGen<'program> {
                     func_inline,
                     branch_inline,
                 });
-                return None;
+                return Ok(None);
             }
             SplitPoint::Return {
                 return_node_results,
@@ -882,9 +1074,9 @@ impl<'program> CodeGen<'program> {
                         &argument_ffi_types,
                         pipeline_rets.iter().eq(argument_ffi_types.iter()),
                     );
-                    return None;
+                    return Ok(None);
                 } else {
-                    return Some((return_node_results, true));
+                    return Ok(Some((return_node_results, true)));
                 }
             }
             SplitPoint::Yield {
@@ -910,7 +1102,7 @@ impl<'program> CodeGen<'program> {
                 //panic!("Not yet implemented");
                 //current_funclet_id_opt = None;
                 *default_join_point_id_opt = None;
-                return None;
+                return Ok(None);
             }
             SplitPoint::Select {
                 return_node_results,
@@ -934,20 +1126,33 @@ impl<'program> CodeGen<'program> {
                     func_inline,
                     branch_inline: true,
                 });
-                return None;
+                return Ok(None);
             }
-            SplitPoint::DynAlloc { .. } => {
-                unreachable!();
-                // assert!(default_join_point_id_opt.is_none());
-                // traversal_state_stack.push(TraversalState::DynAllocIf {
-                //     buffer_node_result,
-                //     success_funclet_id,
-                //     failure_funclet_id,
-                //     argument_node_results,
-                //     dynamic_allocation_size_slot_ids,
-                //     continuation_join_point_id_opt,
-                // });
-                // return None;
+            SplitPoint::DynAlloc {
+                buffer_node_result,
+                success_funclet_id,
+                failure_funclet_id,
+                argument_node_results,
+                dynamic_allocation_size_slot_ids,
+                continuation_join_point_id_opt,
+            } => {
+                assert!(default_join_point_id_opt.is_none());
+                traversal_state_stack.push(InlineFuncletState {
+                    funclet_id: success_funclet_id,
+                    current_out_node_results: argument_node_results.clone(),
+                    join_point_id: continuation_join_point_id_opt,
+                    traversal_state: Some(TraversalState::DynAllocIf {
+                        buffer_node_result,
+                        success_funclet_id,
+                        failure_funclet_id,
+                        argument_node_results,
+                        dynamic_allocation_size_slot_ids,
+                        continuation_join_point_id_opt,
+                    }),
+                    func_inline,
+                    branch_inline: true,
+                });
+                return Ok(None);
             }
         }
     }
@@ -981,52 +1186,13 @@ impl<'program> CodeGen<'program> {
                 false_funclet_id,
                 continuation_join_point_id_opt,
             } => {
-                let true_funclet = &self.program.funclets[true_funclet_id];
-                let output_types = true_funclet
-                    .output_types
-                    .iter()
-                    .map(|type_id| self.get_cpu_useable_type(*type_id))
-                    .collect::<Box<[ir::ffi::TypeId]>>();
-                let output_var_ids = self
-                    .code_generator
-                    .begin_if_else(condition_var_id, &output_types);
-                let mut output_node_results = Vec::<NodeResult>::new();
-                for (output_index, output_type) in true_funclet.output_types.iter().enumerate() {
-                    // Joins capture by slot.  This is ok because joins can't escape the scope they were created in.  We'll reach None before leaving the scope.
-                    let node_result = match &self.program.types[*output_type] {
-                        ir::Type::NativeValue { storage_type, .. } => NodeResult::LocalValue {
-                            var_id: output_var_ids[output_index],
-                            storage_type: *storage_type,
-                        },
-                        ir::Type::Ref {
-                            storage_type,
-                            storage_place,
-                            ..
-                        } => NodeResult::Ref {
-                            var_id: output_var_ids[output_index],
-                            storage_place: *storage_place,
-                            storage_type: *storage_type,
-                        },
-                        ir::Type::Fence { queue_place } => {
-                            let fence_id = output_var_ids[output_index]; //self.code_generator.convert_var_to_gpu_fence(output_var_ids[output_index]);
-                            NodeResult::Fence {
-                                place: *queue_place,
-                                fence_id,
-                            }
-                        }
-                        ir::Type::Encoder { queue_place } => NodeResult::Encoder {
-                            place: *queue_place,
-                        },
-                        x => panic!("Incorrect type: {:?}", x),
-                    };
-                    output_node_results.push(node_result);
-                }
+                let output_node_results = self.begin_branches(condition_var_id, true_funclet_id);
                 funclet_stack.push(InlineFuncletState {
                     funclet_id: false_funclet_id,
                     current_out_node_results: branch_input_node_results.clone(),
                     join_point_id: continuation_join_point_id_opt,
                     traversal_state: Some(TraversalState::SelectElse {
-                        output_node_results: output_node_results.into_boxed_slice(),
+                        output_node_results,
                         branch_input_node_results,
                         false_funclet_id,
                         continuation_join_point_id_opt,
@@ -1065,17 +1231,192 @@ impl<'program> CodeGen<'program> {
                 // do not process funclet (return false)
                 return false;
             }
-            _ => unimplemented!(),
+            TraversalState::DynAllocIf {
+                buffer_node_result,
+                success_funclet_id,
+                failure_funclet_id,
+                argument_node_results,
+                dynamic_allocation_size_slot_ids,
+                continuation_join_point_id_opt,
+            } => {
+                let buffer_var_id = buffer_node_result.get_var_id().unwrap();
+                let success_funclet = &self.program.funclets[success_funclet_id];
+                // Each allocation's place, storage type, element type, and size
+                let mut allocations =
+                    Vec::<(ir::Place, ir::ffi::TypeId, ir::ffi::TypeId, Option<VarId>)>::new();
+                for (type_id, size_var_id_opt) in success_funclet.input_types
+                    [argument_node_results.len()..]
+                    .iter()
+                    .zip(dynamic_allocation_size_slot_ids.iter())
+                {
+                    if let ir::Type::Ref {
+                        storage_type,
+                        storage_place,
+                        ..
+                    } = &self.program.types[*type_id]
+                    {
+                        // Slices are allocated by their element type
+                        let element_type =
+                            match &self.program.native_interface.types[storage_type.0] {
+                                ir::ffi::Type::ErasedLengthArray { element_type } => *element_type,
+                                _ => *storage_type,
+                            };
+                        allocations.push((
+                            *storage_place,
+                            *storage_type,
+                            element_type,
+                            *size_var_id_opt,
+                        ));
+                    } else {
+                        panic!("Allocation is not a ref")
+                    }
+                }
+                let layouts = allocations
+                    .iter()
+                    .map(|(_, _, element_type, size_var_id_opt)| (*element_type, *size_var_id_opt))
+                    .collect::<Vec<_>>();
+
+                // Nothing is allocated unless everything fits, so failing leaves the buffer as it was
+                let condition_var_id = self
+                    .code_generator
+                    .build_test_suballocate_many(buffer_var_id, &layouts);
+                let output_node_results = self.begin_branches(condition_var_id, success_funclet_id);
+
+                let mut success_input_node_results = argument_node_results.to_vec();
+                for (storage_place, storage_type, element_type, size_var_id_opt) in allocations {
+                    let var_id = match size_var_id_opt {
+                        Some(size_var_id) => self.code_generator.build_buffer_suballocate_slice(
+                            buffer_var_id,
+                            element_type,
+                            size_var_id,
+                        ),
+                        None => self
+                            .code_generator
+                            .build_buffer_suballocate_ref(buffer_var_id, storage_type),
+                    };
+                    success_input_node_results.push(NodeResult::Ref {
+                        var_id,
+                        storage_place,
+                        storage_type,
+                    });
+                }
+
+                funclet_stack.push(InlineFuncletState {
+                    funclet_id: failure_funclet_id,
+                    current_out_node_results: argument_node_results.clone(),
+                    join_point_id: continuation_join_point_id_opt,
+                    traversal_state: Some(TraversalState::DynAllocElse {
+                        output_node_results,
+                        failure_funclet_id,
+                        argument_node_results,
+                        continuation_join_point_id_opt,
+                    }),
+                    func_inline,
+                    branch_inline: true,
+                });
+                // the success funclet takes the allocations too, so it's compiled from its own state
+                funclet_stack.push(InlineFuncletState {
+                    funclet_id: success_funclet_id,
+                    current_out_node_results: success_input_node_results.into_boxed_slice(),
+                    join_point_id: continuation_join_point_id_opt,
+                    traversal_state: None,
+                    func_inline,
+                    branch_inline: true,
+                });
+                return false;
+            }
+            TraversalState::DynAllocElse {
+                output_node_results,
+                failure_funclet_id,
+                argument_node_results,
+                continuation_join_point_id_opt,
+            } => {
+                self.code_generator
+                    .end_if_begin_else(&NodeResult::collect_vars(&current_out_node_results));
+                funclet_stack.push(InlineFuncletState {
+                    funclet_id: failure_funclet_id,
+                    current_out_node_results: argument_node_results,
+                    join_point_id: continuation_join_point_id_opt,
+                    traversal_state: Some(TraversalState::DynAllocEnd {
+                        output_node_results,
+                        continuation_join_point_id_opt,
+                    }),
+                    func_inline,
+                    branch_inline: true,
+                });
+            }
+            TraversalState::DynAllocEnd {
+                output_node_results,
+                continuation_join_point_id_opt,
+            } => {
+                self.code_generator
+                    .end_else(&NodeResult::collect_vars(current_out_node_results));
+                // do not process funclet (return false)
+                return false;
+            }
         }
         true
     }
 
+    /// Begins the branches of an if-else on the given condition, returning the
+    /// node results of the outputs that both branches assign to
+    /// # Arguments
+    /// * `condition_var_id` - The variable the branches are chosen by
+    /// * `funclet_id` - A funclet of one of the branches, which has the same outputs as the other
+    fn begin_branches(
+        &mut self,
+        condition_var_id: VarId,
+        funclet_id: ir::FuncletId,
+    ) -> Box<[NodeResult]> {
+        let funclet = &self.program.funclets[funclet_id];
+        let output_types = funclet
+            .output_types
+            .iter()
+            .map(|type_id| self.get_cpu_useable_type(*type_id))
+            .collect::<Box<[ir::ffi::TypeId]>>();
+        let output_var_ids = self
+            .code_generator
+            .begin_if_else(condition_var_id, &output_types);
+        let mut output_node_results = Vec::<NodeResult>::new();
+        for (output_index, output_type) in funclet.output_types.iter().enumerate() {
+            // Joins capture by slot.  This is ok because joins can't escape the scope they were created in.  We'll reach None before leaving the scope.
+            let node_result = match &self.program.types[*output_type] {
+                ir::Type::NativeValue { storage_type, .. } => NodeResult::LocalValue {
+                    var_id: output_var_ids[output_index],
+                    storage_type: *storage_type,
+                },
+                ir::Type::Ref {
+                    storage_type,
+                    storage_place,
+                    ..
+                } => NodeResult::Ref {
+                    var_id: output_var_ids[output_index],
+                    storage_place: *storage_place,
+                    storage_type: *storage_type,
+                },
+                ir::Type::Fence { queue_place } => {
+                    let fence_id = output_var_ids[output_index]; //self.code_generator.convert_var_to_gpu_fence(output_var_ids[output_index]);
+                    NodeResult::Fence {
+                        place: *queue_place,
+                        fence_id,
+                    }
+                }
+                ir::Type::Encoder { queue_place } => NodeResult::Encoder {
+                    place: *queue_place,
+                },
+                x => panic!("Incorrect type: {:?}", x),
+            };
+            output_node_results.push(node_result);
+        }
+        output_node_results.into_boxed_slice()
+    }
+
     fn compile_externally_visible_scheduling_funclet(
         &mut self,
         funclet_id: ir::FuncletId,
         pipeline_context: &mut PipelineContext,
         pipeline_rets: &[ffi::TypeId],
-    ) {
+    ) -> Result<(), LayoutError> {
         let funclet = &self.program.funclets[funclet_id];
         assert_eq!(funclet.kind, ir::FuncletKind::ScheduleExplicit);
         //let funclet_extra = & self.program.scheduling_funclet_extras[& funclet_id];
@@ -1089,9 +1430,12 @@ impl<'program> CodeGen<'program> {
             .iter()
             .map(|type_id| self.get_cpu_useable_type(*type_id))
             .collect::<Box<[ir::ffi::TypeId]>>();
-        let argument_variable_ids =
-            self.code_generator
-                .begin_funclet(funclet_id, &input_types, &output_types);
+        let argument_variable_ids = self.code_generator.begin_funclet(
+            funclet_id,
+            &input_types,
+            &output_types,
+            self.recursive_funclet_ids.contains(&funclet_id),
+        );
 
         let mut argument_node_results = Vec::<NodeResult>::new();
 
@@ -1206,7 +1550,7 @@ impl<'program> CodeGen<'program> {
                 func_inline,
                 branch_inline,
                 pipeline_rets,
-            ) {
+            )? {
                 current_out_node_results = cur_out;
                 force_process_join = force_process;
             }
@@ -1238,6 +1582,33 @@ impl<'program> CodeGen<'program> {
                                 may_return,
                             );
                         }
+                        JoinPoint::CallJoinPoint(simple_join_point)
+                            if self
+                                .recursive_funclet_ids
+                                .contains(&simple_join_point.scheduling_funclet_id) =>
+                        {
+                            // recursive schedule call, so continue in the callee's function
+                            //   with the rest of this one serialized onto the join stack
+                            let callee_funclet_id = simple_join_point.scheduling_funclet_id;
+                            self.serialize_continuation(
+                                pipeline_context,
+                                simple_join_point.continuation_join_point_id,
+                            );
+                            pipeline_context.pending_funclet_ids.push(callee_funclet_id);
+
+                            let argument_var_ids =
+                                NodeResult::collect_vars(&current_out_node_results);
+                            let argument_types: Vec<_> = self.program.funclets[callee_funclet_id]
+                                .input_types
+                                .iter()
+                                .map(|x| self.get_cpu_useable_type(*x))
+                                .collect();
+                            self.code_generator.build_call_funclet(
+                                callee_funclet_id,
+                                &argument_var_ids,
+                                &argument_types,
+                            );
+                        }
                         JoinPoint::CallJoinPoint(simple_join_point) => {
                             // schedule call
                             let mut input_node_results = Vec::<NodeResult>::new();
@@ -1279,6 +1650,7 @@ impl<'program> CodeGen<'program> {
         //assert!(current_funclet_id_opt.is_none());
 
         self.code_generator.end_funclet();
+        Ok(())
     }
 
     fn collect_local_inputs(
@@ -1312,55 +1684,37 @@ impl<'program> CodeGen<'program> {
     }
 
     /// Determines if a continuation of the given funclet can be optimized away.
-    /// A funclet is trivial if it has no captures and it
-    /// only contains phis, returns of all phi nodes, or jumps to other trivial funclets.
-    /// # Arguments
-    /// * `funclet_id` - The continuation target
-    /// * `captures_len` - The number of captures for the continuation
-    /// # Returns
-    /// * True if the continuation can be optimized away, otherwise false
+    /// See [`ir::join_stack::is_trivial_join`]
     fn is_trivial_funclet(&self, funclet_id: usize, captures_len: usize) -> bool {
-        if captures_len != 0 {
-            return false;
-        }
-        let f = &self.program.funclets[funclet_id];
-        for node in f.nodes.iter() {
-            if !matches!(
-                node,
-                ir::Node::Phi { .. } | ir::Node::DefaultJoin { .. } | ir::Node::InlineJoin { .. }
-            ) {
-                return false;
-            }
-        }
-        match &f.tail_edge {
-            ir::TailEdge::Return { return_values } => {
-                let mut v: Vec<_> = return_values.iter().cloned().collect();
-                v.sort();
-                return_values.len() == f.input_types.len()
-                    && return_values.iter().eq(v.iter())
-                    && return_values
-                        .iter()
-                        .all(|x| matches!(f.nodes[*x], ir::Node::Phi { .. }))
-            }
-            ir::TailEdge::Jump {
-                arguments: return_values,
-                join,
-            } => {
-                let mut v: Vec<_> = return_values.iter().cloned().collect();
-                v.sort();
-                return_values.len() == f.input_types.len()
-                    && return_values
-                        .iter()
-                        .all(|x| matches!(f.nodes[*x], ir::Node::Phi { .. }))
-                    && return_values.iter().eq(v.iter())
-                    && match &f.nodes[*join] {
-                        ir::Node::InlineJoin {
-                            funclet, captures, ..
-                        } => self.is_trivial_funclet(*funclet, captures.len()),
-                        _ => false,
-                    }
+        ir::join_stack::is_trivial_join(self.program, funclet_id, captures_len)
+    }
+
+    /// Pushes the joins a continuation still has to run onto the join stack, outermost first,
+    /// so that they run when the function being called returns.
+    /// Joins that only pass their inputs on are left out.
+    fn serialize_continuation(
+        &mut self,
+        pipeline_context: &mut PipelineContext,
+        join_point_id: JoinPointId,
+    ) {
+        match pipeline_context.join_graph.get_join(join_point_id) {
+            JoinPoint::RootJoinPoint(_) | JoinPoint::SerializedJoinPoint(_) => (),
+            JoinPoint::SimpleJoinPoint(simple_join_point) => {
+                let simple_join_point = simple_join_point.clone();
+                self.serialize_continuation(
+                    pipeline_context,
+                    simple_join_point.continuation_join_point_id,
+                );
+                let funclet_id = simple_join_point.scheduling_funclet_id;
+                if !self.is_trivial_funclet(funclet_id, simple_join_point.captures.len()) {
+                    self.build_push_serialized_join(funclet_id, &simple_join_point.captures);
+                    pipeline_context.pending_funclet_ids.push(funclet_id);
+                }
             }
-            _ => false,
+            join_point => panic!(
+                "Cannot serialize join point #{:?}: {:?}",
+                join_point_id, join_point
+            ),
         }
     }
 
@@ -1499,7 +1853,7 @@ impl<'program> CodeGen<'program> {
         pipeline_context: &mut PipelineContext,
         default_join_point_id_opt: &mut Option<JoinPointId>,
         inline: bool,
-    ) -> SplitPoint {
+    ) -> Result<SplitPoint, LayoutError> {
         let funclet = &self.program.funclets[funclet_id];
         assert_eq!(funclet.kind, ir::FuncletKind::ScheduleExplicit);
 
@@ -1583,7 +1937,7 @@ impl<'program> CodeGen<'program> {
                     ir::Place::Gpu => {
                         let buffer_var_id = self
                             .code_generator
-                            .build_alloc_temp_gpu(*storage_type, *buffer_flags);
+                            .build_alloc_temp_gpu(*storage_type, *buffer_flags)?;
                         let var_id = self
                             .code_generator
                             .build_buffer_ref(buffer_var_id, *storage_type);
@@ -1686,7 +2040,11 @@ impl<'program> CodeGen<'program> {
                     outputs,
                     encoder,
                 } => {
-                    let Some(NodeResult::Encoder{place}) = funclet_scoped_state.get_node_result(*encoder) else { panic!("No encoder"); };
+                    let Some(NodeResult::Encoder { place }) =
+                        funclet_scoped_state.get_node_result(*encoder)
+                    else {
+                        panic!("No encoder");
+                    };
 
                     assert_eq!(*place, ir::Place::Gpu);
                     let operation_funclet_id = funclet
@@ -1793,7 +2151,7 @@ impl<'program> CodeGen<'program> {
                         (ir::Place::Local, ir::Place::Local, ir::Place::Gpu) => {
                             let temp_var_id = self
                                 .code_generator
-                                .encode_clone_local_data_from_buffer(src_slot_id, dst_type);
+                                .encode_clone_local_data_from_buffer(src_slot_id, dst_type)?;
                             self.code_generator
                                 .build_write_local_ref(dst_slot_id, temp_var_id);
                         }
@@ -1880,7 +2238,11 @@ impl<'program> CodeGen<'program> {
                     output,
                     encoder,
                 } => {
-                    let Some(NodeResult::Encoder{place}) = funclet_scoped_state.get_node_result(*encoder) else { panic!("No encoder"); };
+                    let Some(NodeResult::Encoder { place }) =
+                        funclet_scoped_state.get_node_result(*encoder)
+                    else {
+                        panic!("No encoder");
+                    };
                     let src_slot_id = funclet_scoped_state.get_node_var_id(*input).unwrap();
                     let dst_slot_id = funclet_scoped_state.get_node_var_id(*output).unwrap();
 
@@ -1953,13 +2315,17 @@ impl<'program> CodeGen<'program> {
                                 dst_slot_id,
                                 src_slot_id,
                                 src_storage_type,
-                            );
+                            )?;
                         }
                         _ => panic!("Unimplemented"),
                     }
                 }
                 ir::Node::Submit { event, encoder } => {
-                    let Some(NodeResult::Encoder{place}) = funclet_scoped_state.get_node_result(*encoder) else { panic!("No encoder"); };
+                    let Some(NodeResult::Encoder { place }) =
+                        funclet_scoped_state.get_node_result(*encoder)
+                    else {
+                        panic!("No encoder");
+                    };
                     match place {
                         ir::Place::Gpu => {
                             self.code_generator.flush_submission();
@@ -2002,9 +2368,18 @@ impl<'program> CodeGen<'program> {
                     place,
                 } => {
                     for (i, size) in sizes.iter().enumerate() {
-                        let NodeResult::Buffer{static_layout_opt: Some(static_layout), ..} : &mut NodeResult = funclet_scoped_state.node_results.get_mut(&buffer_impl_node_id).unwrap() else { panic!("") };
+                        let NodeResult::Buffer {
+                            static_layout_opt: Some(static_layout),
+                            ..
+                        }: &mut NodeResult = funclet_scoped_state
+                            .node_results
+                            .get_mut(&buffer_impl_node_id)
+                            .unwrap()
+                        else {
+                            panic!("")
+                        };
                         let predecessor_layout =
-                            static_layout.split_static(&self.program.native_interface, sizes[i]);
+                            static_layout.split_static(&self.program.native_interface, sizes[i])?;
                         /*funclet_scoped_state.node_results.insert(
                             current_node_id,
                             NodeResult::Buffer {
@@ -2033,8 +2408,25 @@ impl<'program> CodeGen<'program> {
                 } => {
                     let buffer_node_id = impl_node_ids[impl_node_ids.len() - 1];
                     for i in (0..(impl_node_ids.len() - 1)).rev() {
-                        let NodeResult::Buffer{static_layout_opt: Some(predecessor_static_layout), ..} = funclet_scoped_state.move_node_result(impl_node_ids[i]).unwrap() else { panic!("") };
-                        let NodeResult::Buffer{static_layout_opt: Some(static_layout), ..} : &mut NodeResult = funclet_scoped_state.node_results.get_mut(&buffer_node_id).unwrap() else { panic!("") };
+                        let NodeResult::Buffer {
+                            static_layout_opt: Some(predecessor_static_layout),
+                            ..
+                        } = funclet_scoped_state
+                            .move_node_result(impl_node_ids[i])
+                            .unwrap()
+                        else {
+                            panic!("")
+                        };
+                        let NodeResult::Buffer {
+                            static_layout_opt: Some(static_layout),
+                            ..
+                        }: &mut NodeResult = funclet_scoped_state
+                            .node_results
+                            .get_mut(&buffer_node_id)
+                            .unwrap()
+                        else {
+                            panic!("")
+                        };
                         static_layout.merge_static_left(
                             &self.program.native_interface,
                             predecessor_static_layout,
@@ -2061,8 +2453,10 @@ impl<'program> CodeGen<'program> {
                         assert_eq!(*storage_place, *place);
                         if let Some(static_layout) = static_layout_opt {
                             //let NodeResult::Buffer{static_layout_opt: Some(static_layout), ..} : &mut NodeResult = funclet_scoped_state.node_results.get_mut(&buffer_node_id).unwrap() else { panic!("") };
+                            // the type system checked the allocation fits the buffer's layout
                             static_layout
-                                .alloc_static(&self.program.native_interface, *storage_type);
+                                .alloc_static(&self.program.native_interface, *place, *storage_type)
+                                .unwrap();
                         }
 
                         let allocation_var_id = self
@@ -2404,14 +2798,30 @@ impl<'program> CodeGen<'program> {
                     continuation_join_point_id_opt: Some(continuation_join_point_id),
                 }
             }
-            /*ir::TailEdge::DynamicAllocFromBuffer {
+            ir::TailEdge::DynamicAllocFromBuffer {
                 buffer: buffer_node_id,
+                arguments,
                 dynamic_allocation_size_slots,
                 success_funclet_id,
                 failure_funclet_id,
-                arguments,
                 continuation_join: continuation_join_node_id,
             } => {
+                if pending_inline_join.is_some() {
+                    let (funclet_id, captures, ..) = pending_inline_join.as_ref().unwrap();
+                    if self.is_trivial_funclet(**funclet_id, captures.len()) {
+                        self.do_inline_join(
+                            &mut funclet_scoped_state,
+                            pipeline_context,
+                            pending_inline_join,
+                        );
+                    } else {
+                        self.do_serialized_join(
+                            &mut funclet_scoped_state,
+                            pipeline_context,
+                            pending_inline_join,
+                        );
+                    }
+                }
                 let buffer_node_result = funclet_scoped_state
                     .get_node_result(*buffer_node_id)
                     .unwrap()
@@ -2420,53 +2830,26 @@ impl<'program> CodeGen<'program> {
                 let continuation_join_point_id = funclet_scoped_state
                     .move_node_join_point_id(*continuation_join_node_id)
                     .unwrap();
-                let continuation_join_point = placement_state
-                    .join_graph
-                    .get_join(continuation_join_point_id);
-
-                let true_funclet_id = success_funclet_id;
-                let false_funclet_id = failure_funclet_id;
-                let true_funclet = &self.program.funclets[*true_funclet_id];
-                let false_funclet = &self.program.funclets[*false_funclet_id];
-                //let true_funclet_extra = & self.program.scheduling_funclet_extras[& true_funclet_id];
-                //let false_funclet_extra = & self.program.scheduling_funclet_extras[& false_funclet_id];
-
-                assert_eq!(
-                    funclet_scoped_state.value_funclet_id,
-                    true_funclet
-                        .spec_binding
-                        .get_value_spec()
-                        .funclet_id_opt
-                        .unwrap()
-                );
-                assert_eq!(
-                    funclet_scoped_state.value_funclet_id,
-                    false_funclet
-                        .spec_binding
-                        .get_value_spec()
-                        .funclet_id_opt
-                        .unwrap()
-                );
 
+                let success_funclet = &self.program.funclets[*success_funclet_id];
+                let failure_funclet = &self.program.funclets[*failure_funclet_id];
                 assert_eq!(
                     arguments.len() + dynamic_allocation_size_slots.len(),
-                    true_funclet.input_types.len()
+                    success_funclet.input_types.len()
                 );
-                assert_eq!(arguments.len(), false_funclet.input_types.len());
+                assert_eq!(arguments.len(), failure_funclet.input_types.len());
 
                 // Do these first before they move
                 let mut dynamic_allocation_size_slot_ids = Vec::<Option<VarId>>::new();
-                for (allocation_index, allocation_size_node_id_opt) in
-                    dynamic_allocation_size_slots.iter().enumerate()
-                {
+                for allocation_size_node_id_opt in dynamic_allocation_size_slots.iter() {
                     if let Some(allocation_size_node_id) = allocation_size_node_id_opt {
                         let node_result = funclet_scoped_state
                             .get_node_result(*allocation_size_node_id)
                             .unwrap();
-                        if let NodeResult::Slot { slot_id, .. } = node_result {
-                            dynamic_allocation_size_slot_ids.push(Some(*slot_id));
+                        if let NodeResult::LocalValue { var_id, .. } = node_result {
+                            dynamic_allocation_size_slot_ids.push(Some(*var_id));
                         } else {
-                            panic!("Allocation size is not a slot")
+                            panic!("Allocation size is not a local value")
                         }
                     } else {
                         dynamic_allocation_size_slot_ids.push(None);
@@ -2474,7 +2857,7 @@ impl<'program> CodeGen<'program> {
                 }
 
                 let mut argument_node_results = Vec::<NodeResult>::new();
-                for (argument_index, argument_node_id) in arguments.iter().enumerate() {
+                for argument_node_id in arguments.iter() {
                     let node_result = funclet_scoped_state
                         .move_node_result(*argument_node_id)
                         .unwrap();
@@ -2491,14 +2874,33 @@ impl<'program> CodeGen<'program> {
                         .into_boxed_slice(),
                     continuation_join_point_id_opt: Some(continuation_join_point_id),
                 }
-                //panic!("Unimplemented")
-            }*/
+            }
             _ => panic!("Umimplemented"),
         };
-        split_point
+        Ok(split_point)
     }
 
-    fn generate_pipeline(&mut self, pipeline: &ir::Pipeline) {
+    // The names of the arguments of a pipeline's wrapper, taken from the inputs of its entry
+    //   funclet where they're usable as Rust identifiers
+    fn pipeline_input_names(&self, funclet_id: ir::FuncletId, input_count: usize) -> Vec<String> {
+        let node_map = self
+            .debug_info
+            .funclet_map
+            .get(&funclet_id)
+            .map(|f| &f.node_map);
+        let mut names = Vec::<String>::new();
+        for index in 0..input_count {
+            let name = node_map
+                .and_then(|node_map| node_map.get(&ir::Quotient::Input { index }))
+                .filter(|name| is_argument_name(name) && !names.contains(name))
+                .cloned()
+                .unwrap_or_else(|| format!("arg_{}", index));
+            names.push(name);
+        }
+        names
+    }
+
+    fn generate_pipeline(&mut self, pipeline: &ir::Pipeline) -> Result<(), LayoutError> {
         let entry_funclet_id: ir::FuncletId = pipeline.entry_funclet;
         let pipeline_name: &str = pipeline.name.as_str();
         let entry_funclet = &self.program.funclets[entry_funclet_id];
@@ -2522,12 +2924,14 @@ impl<'program> CodeGen<'program> {
                     funclet_id,
                     &mut pipeline_context,
                     &output_types,
-                );
+                )?;
 
                 assert!(visited_funclet_ids.insert(funclet_id));
             }
         }
 
+        let join_stack_bound = ir::join_stack::pipeline_bound(self.program, pipeline);
+
         // Get effectful operations and use as yield points for now
 
         if let Some(effect_id) = pipeline.effect_id_opt {
@@ -2576,11 +2980,14 @@ impl<'program> CodeGen<'program> {
                 .iter()
                 .map(|type_id| self.get_cpu_useable_type(*type_id))
                 .collect::<Box<[ir::ffi::TypeId]>>();
+            let input_names = self.pipeline_input_names(entry_funclet_id, input_types.len());
             self.code_generator.emit_yieldable_pipeline_entry_point(
                 entry_funclet_id,
                 &input_types,
+                &input_names,
                 &output_types,
                 ffi_yield_points.as_slice(),
+                &join_stack_bound,
             );
         } else {
             let input_types = entry_funclet
@@ -2593,21 +3000,26 @@ impl<'program> CodeGen<'program> {
                 .iter()
                 .map(|type_id| self.get_cpu_useable_type(*type_id))
                 .collect::<Box<[ir::ffi::TypeId]>>();
+            let input_names = self.pipeline_input_names(entry_funclet_id, input_types.len());
             self.code_generator.emit_oneshot_pipeline_entry_point(
                 entry_funclet_id,
                 &input_types,
+                &input_names,
                 &output_types,
+                &join_stack_bound,
             );
         }
 
         self.code_generator.end_pipeline();
+        Ok(())
     }
 
-    pub fn generate<'codegen>(&'codegen mut self) -> String {
+    /// Fails if a value is stored somewhere its type has no layout, such as a `u8` in a GPU buffer
+    pub fn generate<'codegen>(&'codegen mut self) -> Result<String, LayoutError> {
         for pipeline in self.program.pipelines.iter() {
-            self.generate_pipeline(pipeline);
+            self.generate_pipeline(pipeline)?;
         }
-        return self.code_generator.finish();
+        return Ok(self.code_generator.finish());
     }
 }
 
@@ -1,3 +1,4 @@
+use super::layout::{LayoutError, LayoutRules};
 use crate::shadergen::ShaderModule;
 use crate::stable_vec::StableVec;
 use serde_derive::{Deserialize, Serialize};
@@ -126,29 +127,9 @@ pub enum Type {
 
 impl Type {
     pub fn estimate_size(&self, types: &StableVec<Type>) -> usize {
-        match self {
-            Self::F32 => 4,
-            Self::F64 => 8,
-            Self::U8 => 1,
-            Self::U16 => 2,
-            Self::U32 => 4,
-            Self::U64 => 8,
-            Self::USize => std::mem::size_of::<usize>(),
-            Self::I8 => 1,
-            Self::I16 => 2,
-            Self::I32 => 4,
-            Self::I64 => 8,
-            Self::Array {
-                element_type,
-                length,
-            } => {
-                // assuming no padding, probably incorrect
-                // that's ok though, this is only an estimate
-                return length * types[element_type.0].estimate_size(types);
-            }
-            // TODO: finish this for other types
-            _ => usize::MAX,
-        }
+        // Types without a fixed host layout are treated as arbitrarily large
+        self.calculate_layout(types, LayoutRules::Host)
+            .map_or(usize::MAX, |layout| layout.size)
     }
 
     pub fn get_ref_pointee_type_id(&self) -> Option<TypeId> {
@@ -286,6 +267,7 @@ pub struct NativeInterface {
     pub effects: StableVec<Effect>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct TypeBindingInfo {
     pub size: usize,
     pub alignment: usize,
@@ -294,82 +276,23 @@ pub struct TypeBindingInfo {
 impl NativeInterface {
     // For the sake of an MVP, this is assuming that we're compiling for the machine we're running on
 
-    pub fn calculate_type_binding_info(&self, type_id: TypeId) -> TypeBindingInfo {
-        match &self.types[type_id.0] {
-            Type::F32 => TypeBindingInfo {
-                size: std::mem::size_of::<f32>(),
-                alignment: std::mem::align_of::<f32>(),
-            },
-            Type::F64 => TypeBindingInfo {
-                size: std::mem::size_of::<f64>(),
-                alignment: std::mem::align_of::<f64>(),
-            },
-            Type::U8 => TypeBindingInfo {
-                size: std::mem::size_of::<u8>(),
-                alignment: std::mem::align_of::<u8>(),
-            },
-            Type::U16 => TypeBindingInfo {
-                size: std::mem::size_of::<u16>(),
-                alignment: std::mem::align_of::<u16>(),
-            },
-            Type::U32 => TypeBindingInfo {
-                size: std::mem::size_of::<u32>(),
-                alignment: std::mem::align_of::<u32>(),
-            },
-            Type::U64 => TypeBindingInfo {
-                size: std::mem::size_of::<u64>(),
-                alignment: std::mem::align_of::<u64>(),
-            },
-            Type::USize => TypeBindingInfo {
-                size: std::mem::size_of::<usize>(),
-                alignment: std::mem::align_of::<usize>(),
-            },
-            Type::I8 => TypeBindingInfo {
-                size: std::mem::size_of::<i8>(),
-                alignment: std::mem::align_of::<i8>(),
-            },
-            Type::I16 => TypeBindingInfo {
-                size: std::mem::size_of::<i16>(),
-                alignment: std::mem::align_of::<i16>(),
-            },
-            Type::I32 => TypeBindingInfo {
-                size: std::mem::size_of::<i32>(),
-                alignment: std::mem::align_of::<i32>(),
-            },
-            Type::I64 => TypeBindingInfo {
-                size: std::mem::size_of::<i64>(),
-                alignment: std::mem::align_of::<i64>(),
-            },
-            Type::ConstRef { element_type } => panic!("Unimplemented"),
-            Type::MutRef { element_type } => panic!("Unimplemented"),
-            Type::ConstSlice { element_type } => panic!("Unimplemented"),
-            Type::MutSlice { element_type } => panic!("Unimplemented"),
-            Type::Array {
-                element_type,
-                length,
-            } => {
-                let inner = self.calculate_type_binding_info(*element_type);
-                TypeBindingInfo {
-                    size: inner.size * length,
-                    alignment: inner.alignment,
-                }
-            }
-            Type::Struct {
-                fields,
-                byte_alignment,
-                byte_size,
-            } => panic!("Unimplemented"),
-            _ => panic!("Unimplemented"),
-        }
+    /// The host layout of `type_id`. Fails if the type has no fixed host layout, which is
+    /// the case for erased length arrays and runtime handles such as buffer references.
+    pub fn calculate_type_binding_info(
+        &self,
+        type_id: TypeId,
+    ) -> Result<TypeBindingInfo, LayoutError> {
+        self.calculate_type_layout(type_id, LayoutRules::Host)
     }
 
-    pub fn calculate_type_alignment_bits(&self, type_id: TypeId) -> usize {
-        self.calculate_type_binding_info(type_id)
+    pub fn calculate_type_alignment_bits(&self, type_id: TypeId) -> Result<usize, LayoutError> {
+        Ok(self
+            .calculate_type_binding_info(type_id)?
             .alignment
-            .trailing_zeros() as usize
+            .trailing_zeros() as usize)
     }
 
-    pub fn calculate_type_byte_size(&self, type_id: TypeId) -> usize {
-        self.calculate_type_binding_info(type_id).size
+    pub fn calculate_type_byte_size(&self, type_id: TypeId) -> Result<usize, LayoutError> {
+        Ok(self.calculate_type_binding_info(type_id)?.size)
     }
 }
@@ -0,0 +1,631 @@
+// Memory layouts of native interface types
+//
+// Host layouts are `repr(C)` on the machine the compiler runs on, which is also how codegen
+// declares the structs it emits. GPU layouts follow the WGSL memory layout rules of the
+// `storage` and `uniform` address spaces, which are std430- and std140-like respectively.
+// The offsets, sizes and alignments declared on a struct are never trusted: they are checked
+// against the layout computed here, so a declaration that disagrees is an error rather than
+// silently corrupted data.
+
+use super::ffi::{NativeInterface, StructField, Type, TypeBindingInfo, TypeId};
+use crate::ir::Place;
+use crate::stable_vec::StableVec;
+use serde_derive::{Deserialize, Serialize};
+
+/// The rules used to lay out a type in memory
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LayoutRules {
+    /// `repr(C)` on the host
+    Host,
+    /// WGSL's `storage` address space (std430-like)
+    Storage,
+    /// WGSL's `uniform` address space (std140-like)
+    Uniform,
+}
+
+impl LayoutRules {
+    /// The rules for data in a buffer on `place`, which is how that place reads it
+    pub fn for_place(place: Place) -> Self {
+        match place {
+            Place::Local | Place::Cpu => Self::Host,
+            Place::Gpu => Self::Storage,
+        }
+    }
+}
+
+impl std::fmt::Display for LayoutRules {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Host => write!(f, "host"),
+            Self::Storage => write!(f, "storage"),
+            Self::Uniform => write!(f, "uniform"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LayoutError {
+    /// The type has no size known at compile time, such as an erased length array
+    Unsized { type_name: String },
+    /// The type can't be stored in memory laid out with the given rules, such as a reference on
+    /// the GPU or a runtime handle like a fence anywhere
+    Unsupported {
+        type_name: String,
+        rules: LayoutRules,
+    },
+    /// An array whose element stride isn't a multiple of 16 bytes, which `uniform` forbids
+    UniformStride { type_name: String, stride: usize },
+    /// A struct declares an offset, size or alignment that disagrees with its computed layout
+    Mismatch {
+        type_name: String,
+        what: String,
+        declared: usize,
+        computed: usize,
+        rules: LayoutRules,
+    },
+    /// A static allocation needs more bytes, alignment padding included, than its buffer has left
+    OutOfSpace { byte_size: usize, available: usize },
+}
+
+impl std::fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unsized { type_name } => {
+                write!(f, "{} has no size known at compile time", type_name)
+            }
+            Self::Unsupported { type_name, rules } => {
+                write!(f, "{} cannot be laid out in {} memory", type_name, rules)
+            }
+            Self::UniformStride { type_name, stride } => write!(
+                f,
+                "{} has an element stride of {} bytes, but uniform arrays need a multiple of 16",
+                type_name, stride
+            ),
+            Self::Mismatch {
+                type_name,
+                what,
+                declared,
+                computed,
+                rules,
+            } => write!(
+                f,
+                "{} declares a {} of {} bytes, but its {} layout has {}",
+                type_name, what, declared, rules, computed
+            ),
+            Self::OutOfSpace {
+                byte_size,
+                available,
+            } => write!(
+                f,
+                "{} bytes don't fit in the {} bytes left in the static buffer",
+                byte_size, available
+            ),
+        }
+    }
+}
+
+fn round_up(value: usize, alignment: usize) -> usize {
+    (value + alignment - 1) / alignment * alignment
+}
+
+fn type_name(typ: &Type) -> String {
+    format!("{:?}", typ)
+}
+
+// Computes layouts under one set of rules
+struct Layouter<'types> {
+    types: &'types StableVec<Type>,
+    rules: LayoutRules,
+}
+
+impl<'types> Layouter<'types> {
+    fn unsupported(&self, typ: &Type) -> LayoutError {
+        LayoutError::Unsupported {
+            type_name: type_name(typ),
+            rules: self.rules,
+        }
+    }
+
+    fn scalar<T>(&self) -> TypeBindingInfo {
+        TypeBindingInfo {
+            size: std::mem::size_of::<T>(),
+            alignment: std::mem::align_of::<T>(),
+        }
+    }
+
+    fn layout(&self, typ: &Type) -> Result<TypeBindingInfo, LayoutError> {
+        use LayoutRules::*;
+        let host = self.rules == Host;
+        Ok(match typ {
+            Type::F32 => self.scalar::<f32>(),
+            Type::I32 => self.scalar::<i32>(),
+            Type::U32 => self.scalar::<u32>(),
+            // WGSL only has 64-bit floats through the `f64` extension, where they are 8 bytes
+            Type::F64 if host => self.scalar::<f64>(),
+            Type::F64 => TypeBindingInfo {
+                size: 8,
+                alignment: 8,
+            },
+            Type::U8 if host => self.scalar::<u8>(),
+            Type::U16 if host => self.scalar::<u16>(),
+            Type::U64 if host => self.scalar::<u64>(),
+            Type::USize if host => self.scalar::<usize>(),
+            Type::I8 if host => self.scalar::<i8>(),
+            Type::I16 if host => self.scalar::<i16>(),
+            Type::I64 if host => self.scalar::<i64>(),
+            Type::Array {
+                element_type,
+                length,
+            } => {
+                let element = self.layout_id(*element_type)?;
+                let stride = round_up(element.size, element.alignment);
+                if self.rules == Uniform && stride % 16 != 0 {
+                    return Err(LayoutError::UniformStride {
+                        type_name: type_name(typ),
+                        stride,
+                    });
+                }
+                TypeBindingInfo {
+                    size: stride * length,
+                    alignment: element.alignment,
+                }
+            }
+            Type::ErasedLengthArray { .. } => {
+                return Err(LayoutError::Unsized {
+                    type_name: type_name(typ),
+                })
+            }
+            Type::Struct {
+                fields,
+                byte_alignment,
+                byte_size,
+            } => {
+                self.struct_layout(typ, fields, *byte_alignment, *byte_size)?
+                    .1
+            }
+            Type::Tuple { fields } => self.fields_layout(fields.iter().copied())?.1,
+            Type::ConstRef { .. } | Type::MutRef { .. } if host => self.scalar::<&u8>(),
+            Type::ConstSlice { .. } | Type::MutSlice { .. } if host => self.scalar::<&[u8]>(),
+            _ => return Err(self.unsupported(typ)),
+        })
+    }
+
+    fn layout_id(&self, type_id: TypeId) -> Result<TypeBindingInfo, LayoutError> {
+        self.layout(&self.types[type_id.0])
+    }
+
+    // The alignment a value of `type_id` needs when it is a member of a struct
+    fn member_alignment(&self, type_id: TypeId, layout: &TypeBindingInfo) -> usize {
+        match &self.types[type_id.0] {
+            Type::Struct { .. } | Type::Tuple { .. } | Type::Array { .. }
+                if self.rules == LayoutRules::Uniform =>
+            {
+                round_up(layout.alignment, 16)
+            }
+            _ => layout.alignment,
+        }
+    }
+
+    // Places the fields one after another, each at the next offset that satisfies its alignment
+    fn fields_layout(
+        &self,
+        fields: impl Iterator<Item = TypeId>,
+    ) -> Result<(Vec<usize>, TypeBindingInfo), LayoutError> {
+        let mut offsets = Vec::new();
+        let mut end = 0;
+        let mut alignment = 1;
+        let mut previous_is_struct = false;
+        for field in fields {
+            let layout = self.layout_id(field)?;
+            let field_alignment = self.member_alignment(field, &layout);
+            if previous_is_struct && self.rules == LayoutRules::Uniform {
+                // Uniform requires 16 bytes of padding after a struct member
+                end = round_up(end, 16);
+            }
+            let offset = round_up(end, field_alignment);
+            offsets.push(offset);
+            end = offset + layout.size;
+            alignment = alignment.max(field_alignment);
+            previous_is_struct = matches!(
+                self.types[field.0],
+                Type::Struct { .. } | Type::Tuple { .. }
+            );
+        }
+        Ok((
+            offsets,
+            TypeBindingInfo {
+                size: round_up(end, alignment),
+                alignment,
+            },
+        ))
+    }
+
+    fn struct_layout(
+        &self,
+        typ: &Type,
+        fields: &[StructField],
+        byte_alignment: Option<usize>,
+        byte_size: Option<usize>,
+    ) -> Result<(Vec<usize>, TypeBindingInfo), LayoutError> {
+        let mismatch = |what: String, declared: usize, computed: usize| LayoutError::Mismatch {
+            type_name: type_name(typ),
+            what,
+            declared,
+            computed,
+            rules: self.rules,
+        };
+        let (offsets, mut layout) = self.fields_layout(fields.iter().map(|f| f.type_id))?;
+        for (field, offset) in fields.iter().zip(offsets.iter()) {
+            if field.byte_offset != *offset {
+                return Err(mismatch(
+                    format!("offset for field {}", field.name),
+                    field.byte_offset,
+                    *offset,
+                ));
+            }
+            let size = self.layout_id(field.type_id)?.size;
+            if field.byte_size != size {
+                return Err(mismatch(
+                    format!("size for field {}", field.name),
+                    field.byte_size,
+                    size,
+                ));
+            }
+        }
+        if let Some(byte_alignment) = byte_alignment {
+            // A struct may ask to be more aligned than its fields, but never less
+            if !byte_alignment.is_power_of_two() || byte_alignment < layout.alignment {
+                return Err(mismatch(
+                    String::from("alignment"),
+                    byte_alignment,
+                    layout.alignment,
+                ));
+            }
+            layout.alignment = byte_alignment;
+            layout.size = round_up(layout.size, byte_alignment);
+        }
+        if let Some(byte_size) = byte_size {
+            if byte_size != layout.size {
+                return Err(mismatch(String::from("size"), byte_size, layout.size));
+            }
+        }
+        Ok((offsets, layout))
+    }
+}
+
+impl Type {
+    /// The layout of this type under `rules`, looking up any types it contains in `types`
+    pub fn calculate_layout(
+        &self,
+        types: &StableVec<Type>,
+        rules: LayoutRules,
+    ) -> Result<TypeBindingInfo, LayoutError> {
+        Layouter { types, rules }.layout(self)
+    }
+}
+
+impl NativeInterface {
+    /// The size and alignment of `type_id` under `rules`
+    pub fn calculate_type_layout(
+        &self,
+        type_id: TypeId,
+        rules: LayoutRules,
+    ) -> Result<TypeBindingInfo, LayoutError> {
+        self.types[type_id.0].calculate_layout(&self.types, rules)
+    }
+
+    /// The byte offset of each field of the struct or tuple `type_id` under `rules`
+    pub fn calculate_field_offsets(
+        &self,
+        type_id: TypeId,
+        rules: LayoutRules,
+    ) -> Result<Vec<usize>, LayoutError> {
+        let layouter = Layouter {
+            types: &self.types,
+            rules,
+        };
+        let typ = &self.types[type_id.0];
+        match typ {
+            Type::Struct {
+                fields,
+                byte_alignment,
+                byte_size,
+            } => Ok(layouter
+                .struct_layout(typ, fields, *byte_alignment, *byte_size)?
+                .0),
+            Type::Tuple { fields } => Ok(layouter.fields_layout(fields.iter().copied())?.0),
+            _ => Err(layouter.unsupported(typ)),
+        }
+    }
+
+    /// Checks that every struct declared in the interface agrees with its host layout, and with
+    /// its storage and uniform layouts wherever it can be laid out in those at all
+    pub fn validate_layouts(&self) -> Result<(), (TypeId, LayoutError)> {
+        for (index, typ) in self.types.iter() {
+            if let Type::Struct { .. } = typ {
+                let type_id = TypeId(index);
+                self.calculate_type_layout(type_id, LayoutRules::Host)
+                    .map_err(|error| (type_id, error))?;
+                for rules in [LayoutRules::Storage, LayoutRules::Uniform] {
+                    // Host-only fields keep a struct off the GPU, which is reported where it's
+                    //   put there, but a struct that fits must keep its declared layout
+                    if let Err(error @ LayoutError::Mismatch { .. }) =
+                        self.calculate_type_layout(type_id, rules)
+                    {
+                        return Err((type_id, error));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(name: &str, type_id: TypeId, byte_offset: usize, byte_size: usize) -> StructField {
+        StructField {
+            name: name.to_string(),
+            type_id,
+            byte_offset,
+            byte_size,
+        }
+    }
+
+    fn interface(types: Vec<Type>) -> NativeInterface {
+        let mut stable_types = StableVec::new();
+        for typ in types {
+            stable_types.add(typ);
+        }
+        NativeInterface {
+            types: stable_types,
+            ..Default::default()
+        }
+    }
+
+    fn layout(
+        native_interface: &NativeInterface,
+        index: usize,
+        rules: LayoutRules,
+    ) -> (usize, usize) {
+        let layout = native_interface
+            .calculate_type_layout(TypeId(index), rules)
+            .unwrap();
+        (layout.size, layout.alignment)
+    }
+
+    #[test]
+    fn test_host_struct() {
+        let native_interface = interface(vec![
+            Type::U8,
+            Type::F64,
+            Type::Struct {
+                fields: vec![
+                    field("a", TypeId(0), 0, 1),
+                    field("b", TypeId(1), 8, 8),
+                    field("c", TypeId(0), 16, 1),
+                ]
+                .into_boxed_slice(),
+                byte_alignment: None,
+                byte_size: Some(24),
+            },
+            Type::Tuple {
+                fields: vec![TypeId(0), TypeId(1)].into_boxed_slice(),
+            },
+            Type::Array {
+                element_type: TypeId(2),
+                length: 3,
+            },
+        ]);
+        assert_eq!(layout(&native_interface, 2, LayoutRules::Host), (24, 8));
+        assert_eq!(layout(&native_interface, 3, LayoutRules::Host), (16, 8));
+        assert_eq!(layout(&native_interface, 4, LayoutRules::Host), (72, 8));
+        assert_eq!(
+            native_interface.calculate_field_offsets(TypeId(3), LayoutRules::Host),
+            Ok(vec![0, 8])
+        );
+        assert!(native_interface.validate_layouts().is_ok());
+        // 8-bit integers don't exist in WGSL
+        assert!(native_interface
+            .calculate_type_layout(TypeId(2), LayoutRules::Storage)
+            .is_err());
+    }
+
+    #[test]
+    fn test_gpu_layouts() {
+        let native_interface = interface(vec![
+            Type::F32,
+            Type::Array {
+                element_type: TypeId(0),
+                length: 4,
+            },
+            Type::Struct {
+                fields: vec![field("x", TypeId(0), 0, 4)].into_boxed_slice(),
+                byte_alignment: None,
+                byte_size: None,
+            },
+            Type::Tuple {
+                fields: vec![TypeId(2), TypeId(0)].into_boxed_slice(),
+            },
+            Type::Array {
+                element_type: TypeId(2),
+                length: 2,
+            },
+        ]);
+        assert_eq!(layout(&native_interface, 1, LayoutRules::Storage), (16, 4));
+        assert_eq!(layout(&native_interface, 3, LayoutRules::Storage), (8, 4));
+        assert_eq!(layout(&native_interface, 3, LayoutRules::Uniform), (32, 16));
+        assert_eq!(
+            native_interface.calculate_field_offsets(TypeId(3), LayoutRules::Uniform),
+            Ok(vec![0, 16])
+        );
+        assert_eq!(
+            native_interface.calculate_type_layout(TypeId(1), LayoutRules::Uniform),
+            Err(LayoutError::UniformStride {
+                type_name: type_name(&native_interface.types[1]),
+                stride: 4
+            })
+        );
+        assert!(native_interface
+            .calculate_type_layout(TypeId(4), LayoutRules::Uniform)
+            .is_err());
+    }
+
+    #[test]
+    fn test_declared_layout_mismatch() {
+        let native_interface = interface(vec![
+            Type::U32,
+            Type::U64,
+            Type::Struct {
+                fields: vec![field("a", TypeId(0), 0, 4), field("b", TypeId(1), 4, 8)]
+                    .into_boxed_slice(),
+                byte_alignment: None,
+                byte_size: None,
+            },
+            Type::Struct {
+                fields: vec![field("a", TypeId(0), 0, 4)].into_boxed_slice(),
+                byte_alignment: Some(16),
+                byte_size: Some(16),
+            },
+            Type::Struct {
+                fields: vec![field("a", TypeId(1), 0, 8)].into_boxed_slice(),
+                byte_alignment: Some(4),
+                byte_size: None,
+            },
+        ]);
+        assert!(matches!(
+            native_interface.calculate_type_layout(TypeId(2), LayoutRules::Host),
+            Err(LayoutError::Mismatch {
+                declared: 4,
+                computed: 8,
+                ..
+            })
+        ));
+        assert_eq!(layout(&native_interface, 3, LayoutRules::Host), (16, 16));
+        assert!(matches!(
+            native_interface.calculate_type_layout(TypeId(4), LayoutRules::Host),
+            Err(LayoutError::Mismatch {
+                declared: 4,
+                computed: 8,
+                ..
+            })
+        ));
+        assert_eq!(
+            native_interface.validate_layouts().unwrap_err().0,
+            TypeId(2)
+        );
+    }
+
+    #[test]
+    fn test_gpu_layout_mismatch() {
+        let native_interface = interface(vec![
+            Type::F32,
+            Type::Struct {
+                fields: vec![field("x", TypeId(0), 0, 4)].into_boxed_slice(),
+                byte_alignment: None,
+                byte_size: None,
+            },
+            // fine on the host and in storage, but uniform puts `inner` 16 bytes in
+            Type::Struct {
+                fields: vec![field("a", TypeId(0), 0, 4), field("inner", TypeId(1), 4, 4)]
+                    .into_boxed_slice(),
+                byte_alignment: None,
+                byte_size: None,
+            },
+        ]);
+        assert_eq!(layout(&native_interface, 2, LayoutRules::Storage), (8, 4));
+        let (type_id, error) = native_interface.validate_layouts().unwrap_err();
+        assert_eq!(type_id, TypeId(2));
+        assert!(matches!(
+            error,
+            LayoutError::Mismatch {
+                declared: 4,
+                computed: 16,
+                rules: LayoutRules::Uniform,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_struct_in_static_buffer() {
+        let native_interface = interface(vec![
+            Type::I32,
+            Type::I64,
+            Type::Struct {
+                fields: vec![field("a", TypeId(0), 0, 4), field("b", TypeId(1), 8, 8)]
+                    .into_boxed_slice(),
+                byte_alignment: None,
+                byte_size: Some(16),
+            },
+        ]);
+        let mut buffer = crate::ir::StaticBufferLayout {
+            alignment_bits: 4,
+            byte_size: 64,
+        };
+        buffer
+            .alloc_static(&native_interface, crate::ir::Place::Local, TypeId(2))
+            .unwrap();
+        assert_eq!(buffer.byte_size, 48);
+        // the buffer is laid out for the GPU there, where 64-bit integers don't exist
+        assert!(matches!(
+            buffer.alloc_static(&native_interface, crate::ir::Place::Gpu, TypeId(2)),
+            Err(LayoutError::Unsupported {
+                rules: LayoutRules::Storage,
+                ..
+            })
+        ));
+        assert_eq!(buffer.byte_size, 48);
+        assert_eq!(
+            buffer.split_static(&native_interface, 64).unwrap_err(),
+            LayoutError::OutOfSpace {
+                byte_size: 64,
+                available: 48
+            }
+        );
+        buffer.split_static(&native_interface, 32).unwrap();
+        buffer
+            .alloc_static(&native_interface, crate::ir::Place::Local, TypeId(2))
+            .unwrap();
+        assert_eq!(
+            buffer.alloc_static(&native_interface, crate::ir::Place::Local, TypeId(2)),
+            Err(LayoutError::OutOfSpace {
+                byte_size: 16,
+                available: 0
+            })
+        );
+    }
+
+    #[test]
+    fn test_unsized_and_handles() {
+        let native_interface = interface(vec![
+            Type::I32,
+            Type::ErasedLengthArray {
+                element_type: TypeId(0),
+            },
+            Type::GpuBufferRef {
+                element_type: TypeId(0),
+            },
+            Type::MutSlice {
+                element_type: TypeId(0),
+            },
+        ]);
+        assert!(matches!(
+            native_interface.calculate_type_layout(TypeId(1), LayoutRules::Storage),
+            Err(LayoutError::Unsized { .. })
+        ));
+        assert!(matches!(
+            native_interface.calculate_type_layout(TypeId(2), LayoutRules::Host),
+            Err(LayoutError::Unsupported { .. })
+        ));
+        assert_eq!(
+            layout(&native_interface, 3, LayoutRules::Host),
+            (
+                2 * std::mem::size_of::<usize>(),
+                std::mem::align_of::<usize>()
+            )
+        );
+    }
+}
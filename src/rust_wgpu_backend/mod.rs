@@ -1,4 +1,6 @@
+pub mod builtins;
 pub mod code_generator;
 mod code_writer;
 pub mod codegen;
 pub mod ffi;
+pub mod layout;
@@ -56,11 +56,68 @@ pub struct FuseDescriptor<'a> {
 pub enum ShaderModuleContent {
     Wgsl(String),
     Glsl(String),
+    Spirv(Vec<u8>),
+}
+
+/// A single problem naga reported with a shader.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShaderDiagnostic {
+    pub message: String,
+    /// The 1-indexed `(line, column)` in the shader's source where the problem starts.
+    /// SPIR-V has no source lines, and validation errors in modules without a source
+    /// (deserialized or fused ones) can't be located either.
+    pub location: Option<(usize, usize)>,
+}
+
+/// Every problem naga reported while reading or validating a shader.
+#[derive(Debug, Clone)]
+pub struct ShaderError {
+    pub diagnostics: Vec<ShaderDiagnostic>,
+}
+
+impl ShaderError {
+    fn new(message: String, location: Option<(usize, usize)>) -> Self {
+        Self {
+            diagnostics: vec![ShaderDiagnostic { message, location }],
+        }
+    }
+}
+
+impl std::fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (index, diagnostic) in self.diagnostics.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            if let Some((line, column)) = diagnostic.location {
+                write!(f, "{}:{}: ", line, column)?;
+            }
+            write!(f, "{}", diagnostic.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for ShaderError {}
+
+fn source_location(span: naga::Span, source: &str) -> Option<(usize, usize)> {
+    if !span.is_defined() {
+        return None;
+    }
+    let location = span.location(source);
+    Some((
+        location.line_number as usize,
+        location.line_position as usize,
+    ))
 }
 
 #[derive(Debug, Clone)]
 pub struct ShaderModule {
     module: naga::Module,
+    // Where the module was read from and its text, if it was parsed from text, kept so
+    //   problems found after parsing can still be located in the shader
+    path: Option<String>,
+    source: Option<String>,
 }
 
 impl<'de> serde::Deserialize<'de> for ShaderModule {
@@ -72,6 +129,7 @@ impl<'de> serde::Deserialize<'de> for ShaderModule {
         let result = match contents {
             ShaderModuleContent::Glsl(glsl) => Self::from_glsl(&glsl),
             ShaderModuleContent::Wgsl(wgsl) => Self::from_wgsl(&wgsl),
+            ShaderModuleContent::Spirv(spirv) => Self::from_spirv(&spirv),
         };
         result.map_err(|e| <D::Error as serde::de::Error>::custom(e))
     }
@@ -79,46 +137,144 @@ impl<'de> serde::Deserialize<'de> for ShaderModule {
 
 impl serde::Serialize for ShaderModule {
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        let contents = ShaderModuleContent::Wgsl(self.emit_wgsl());
-        contents.serialize(serializer)
+        let wgsl = self
+            .emit_wgsl()
+            .map_err(|e| <S::Error as serde::ser::Error>::custom(e))?;
+        ShaderModuleContent::Wgsl(wgsl).serialize(serializer)
     }
 }
 
 impl ShaderModule {
-    pub fn from_wgsl(text: &str) -> Result<Self, Box<dyn Error>> {
-        let module = naga::front::wgsl::parse_str(text)?;
-        Ok(Self { module })
+    /// Reads the shader at `path`, picking the front-end from its extension:
+    /// `.wgsl` for WGSL, `.comp` or `.glsl` for GLSL compute shaders, and `.spv` for SPIR-V.
+    pub fn read(path: &std::path::Path) -> Result<Self, ShaderError> {
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        let read_error = |why: std::io::Error| {
+            ShaderError::new(format!("Couldn't read {}: {}", path.display(), why), None)
+        };
+        let shader_module = match extension {
+            "wgsl" => Self::from_wgsl(&std::fs::read_to_string(path).map_err(read_error)?),
+            "comp" | "glsl" => Self::from_glsl(&std::fs::read_to_string(path).map_err(read_error)?),
+            "spv" => Self::from_spirv(&std::fs::read(path).map_err(read_error)?),
+            _ => Err(ShaderError::new(
+                format!(
+                    "Unsupported extension for shader {}, .wgsl, .comp or .spv expected",
+                    path.display()
+                ),
+                None,
+            )),
+        }?;
+        Ok(Self {
+            path: Some(path.to_string_lossy().to_string()),
+            ..shader_module
+        })
+    }
+    pub fn from_wgsl(text: &str) -> Result<Self, ShaderError> {
+        // The WGSL front-end stops at the first error it finds
+        let module = naga::front::wgsl::parse_str(text).map_err(|why| {
+            let location = why
+                .location(text)
+                .map(|l| (l.line_number as usize, l.line_position as usize));
+            ShaderError::new(why.message().to_string(), location)
+        })?;
+        Self::validated(module, Some(text))
     }
-    pub fn from_glsl(text: &str) -> Result<Self, Box<dyn Error>> {
+    pub fn from_glsl(text: &str) -> Result<Self, ShaderError> {
         let mut parser = naga::front::glsl::Frontend::default();
         let options = naga::front::glsl::Options::from(naga::ShaderStage::Compute);
-        match parser.parse(&options, text) {
-            Ok(module) => Ok(Self { module }),
-            Err(errors) => {
-                // Just report the first error for now.
-                // Could do something better here in the future, but there's a lot of
-                // error handling work that needs to be done beforehand...
-                Err(Box::new(errors.into_iter().next().unwrap()))
-            }
-        }
+        let module = parser.parse(&options, text).map_err(|errors| ShaderError {
+            diagnostics: errors
+                .into_iter()
+                .map(|error| ShaderDiagnostic {
+                    message: error.kind.to_string(),
+                    location: source_location(error.meta, text),
+                })
+                .collect(),
+        })?;
+        Self::validated(module, Some(text))
+    }
+    pub fn from_spirv(bytes: &[u8]) -> Result<Self, ShaderError> {
+        let options = naga::front::spv::Options::default();
+        let module = naga::front::spv::parse_u8_slice(bytes, &options)
+            .map_err(|why| ShaderError::new(why.to_string(), None))?;
+        Self::validated(module, None)
     }
-    pub fn emit_wgsl(&self) -> String {
+    fn validated(module: naga::Module, source: Option<&str>) -> Result<Self, ShaderError> {
+        let shader_module = Self {
+            module,
+            path: None,
+            source: source.map(str::to_string),
+        };
+        shader_module.validate(source)?;
+        Ok(shader_module)
+    }
+    /// Validates the module, locating the error in `source` if the module was parsed from it.
+    fn validate(&self, source: Option<&str>) -> Result<naga::valid::ModuleInfo, ShaderError> {
         let mut validator = naga::valid::Validator::new(
             naga::valid::ValidationFlags::all(),
             naga::valid::Capabilities::empty(),
         );
-        let module_info = match validator.validate(&self.module) {
-            Err(why) => panic!("Error while validating WGSL: {}", why),
-            Ok(module_info) => module_info,
-        };
-        match naga::back::wgsl::write_string(
+        validator.validate(&self.module).map_err(|why| {
+            // The outermost error only names the invalid item, the reason is further down
+            let mut message = why.to_string();
+            let mut cause = why.source();
+            while let Some(inner) = cause {
+                message.push_str(&format!(": {}", inner));
+                cause = inner.source();
+            }
+            // The innermost span, on the offending expression, is the most precise
+            let location = source.and_then(|source| {
+                why.spans()
+                    .last()
+                    .and_then(|(span, _)| source_location(*span, source))
+            });
+            ShaderError::new(format!("Invalid shader: {}", message), location)
+        })
+    }
+    pub fn module(&self) -> &naga::Module {
+        &self.module
+    }
+    /// The file this module was read from, if it was read by [`ShaderModule::read`].
+    pub fn path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+    /// The 1-indexed `(line, column)` where `global` is declared, if the module has a source.
+    pub fn global_variable_location(
+        &self,
+        global: Handle<GlobalVariable>,
+    ) -> Option<(usize, usize)> {
+        let source = self.source.as_ref()?;
+        source_location(self.module.global_variables.get_span(global), source)
+    }
+    /// Which global variables the entry point named `entry_point` uses.
+    pub fn entry_point_globals(&self, entry_point: &str) -> HashSet<Handle<GlobalVariable>> {
+        let info = self
+            .validate(None)
+            .expect("Shaders are validated when they are read");
+        let index = self
+            .module
+            .entry_points
+            .iter()
+            .position(|ep| ep.name == entry_point);
+        let mut used = HashSet::new();
+        if let Some(index) = index {
+            let function_info = info.get_entry_point(index);
+            for (handle, _) in self.module.global_variables.iter() {
+                if !function_info[handle].is_empty() {
+                    used.insert(handle);
+                }
+            }
+        }
+        used
+    }
+    pub fn emit_wgsl(&self) -> Result<String, ShaderError> {
+        let module_info = self.validate(None)?;
+        naga::back::wgsl::write_string(
             &self.module,
             &module_info,
             naga::back::wgsl::WriterFlags::EXPLICIT_TYPES,
-        ) {
-            Err(why) => panic!("Error while emitting WGSL: {}", why),
-            Ok(text) => text,
-        }
+        )
+        .map_err(|why| ShaderError::new(format!("Error while emitting WGSL: {}", why), None))
     }
 
     /// Returns the `(x, y, z)` local size for the compute pipeline at the given entry point.
@@ -201,6 +357,8 @@ impl ShaderModule {
                 functions,
                 entry_points: vec![entry_point.expect("tried to merge zero kernels")],
             },
+            path: None,
+            source: None,
         };
     }
 }
@@ -559,18 +717,16 @@ impl<'a> Fuser<'a> {
     ) -> Statement {
         match old_stmt {
             Statement::Emit(range) => {
-                // HACK:  Naga doesn't want people seeing range endpoints for some reason. (???)
-                // So... bye bye type system
-                let worst_thing_ever = ron::to_string(range).expect("seriously");
-                let really_awful: std::ops::Range<u32> =
-                    ron::from_str(&worst_thing_ever).expect("this should be a crime");
-                let start_str = really_awful.start.to_string();
-                let start: Handle<Expression> = ron::from_str(&start_str).expect("sorry");
-                let end_str = really_awful.end.to_string();
-                let end: Handle<Expression> = ron::from_str(&end_str).expect("sorry sorry");
-                // The funniest thing is that THIS is the part that might break.
-                let new_range = Range::new_from_bounds(expr_map[&start], expr_map[&end]);
-                Statement::Emit(new_range)
+                // Ranges are inclusive of both bounds, and expressions are remapped in order
+                let mut handles = range.clone();
+                match (handles.next(), handles.last()) {
+                    (Some(first), last) => Statement::Emit(Range::new_from_bounds(
+                        expr_map[&first],
+                        expr_map[&last.unwrap_or(first)],
+                    )),
+                    // Nothing to emit, and naga has no way to build an empty range
+                    (None, _) => Statement::Block(Block::new()),
+                }
             }
             Statement::Block(block) => {
                 Statement::Block(Self::update_block(block, fn_map, expr_map))
@@ -601,7 +757,7 @@ impl<'a> Fuser<'a> {
                 break_if,
             } => Statement::Loop {
                 body: Self::update_block(body, fn_map, expr_map),
-                continuing: Self::update_block(body, fn_map, expr_map),
+                continuing: Self::update_block(continuing, fn_map, expr_map),
                 break_if: break_if.as_ref().map(|expr| expr_map[expr]),
             },
             Statement::Break => Statement::Break,
@@ -878,7 +1034,51 @@ mod tests {
     #[test]
     fn test_naga_sanity() {
         let mut shader_module = ShaderModule::from_wgsl(sample_text_1).unwrap();
-        let wgsl_text = shader_module.emit_wgsl();
+        let wgsl_text = shader_module.emit_wgsl().unwrap();
+    }
+
+    #[test]
+    fn test_spirv_input() {
+        let shader_module = ShaderModule::from_wgsl(sample_text_1).unwrap();
+        let info = shader_module.validate(None).unwrap();
+        let words = naga::back::spv::write_vec(
+            shader_module.module(),
+            &info,
+            &naga::back::spv::Options::default(),
+            None,
+        )
+        .unwrap();
+        let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+        let from_spirv = ShaderModule::from_spirv(&bytes).unwrap();
+        assert_eq!(from_spirv.local_size("main"), [1, 1, 1]);
+        assert!(ShaderModule::from_spirv(&bytes[..6]).is_err());
+    }
+
+    #[test]
+    fn test_glsl_reports_every_error() {
+        // mismatched matrix operands are reported without stopping the parse
+        let text = "#version 450\nlayout(local_size_x = 1) in;\nvoid main() {\n  mat2 a = mat2(1.0);\n  mat3 b = mat3(1.0);\n  a + b;\n  b - a;\n}\n";
+        let error = ShaderModule::from_glsl(text).unwrap_err();
+        let lines: Vec<_> = error
+            .diagnostics
+            .iter()
+            .map(|d| d.location.map(|(line, _)| line))
+            .collect();
+        assert_eq!(lines, vec![Some(6), Some(7)]);
+    }
+
+    #[test]
+    fn test_wgsl_errors_are_located() {
+        let error =
+            ShaderModule::from_wgsl("@compute @workgroup_size(1)\nfn main() {\n  let x = ;\n}\n")
+                .unwrap_err();
+        assert_eq!(error.diagnostics.len(), 1);
+        assert_eq!(error.diagnostics[0].location.map(|(line, _)| line), Some(3));
+
+        // well-formed, but returns the wrong type
+        let error = ShaderModule::from_wgsl("fn f() -> i32 {\n  return 1.0;\n}\n").unwrap_err();
+        assert!(error.diagnostics[0].message.starts_with("Invalid shader"));
+        assert_eq!(error.diagnostics[0].location.map(|(line, _)| line), Some(2));
     }
 
     #[test]
@@ -894,14 +1094,14 @@ mod tests {
             }],
             resources: &map,
         });
-        let wgsl_text = fused.emit_wgsl();
+        let wgsl_text = fused.emit_wgsl().unwrap();
         eprintln!("{wgsl_text}");
     }
 
     const fadd_wgpu: &str = "
     @group(0) @binding(0) var<storage, read> lhs : array<f32, 1024>;
     @group(0) @binding(1) var<storage, read> rhs : array<f32, 1024>;
-    @group(0) @binding(2) var<storage, write> output : array<f32, 1024>;
+    @group(0) @binding(2) var<storage, read_write> output : array<f32, 1024>;
 
     @compute @workgroup_size(256) 
     fn main(@builtin(local_invocation_index) i : u32)
@@ -936,21 +1136,21 @@ mod tests {
             resources: &map,
         });
 
-        let wgsl_text = fused.emit_wgsl();
+        let wgsl_text = fused.emit_wgsl().unwrap();
         eprintln!("{wgsl_text}");
     }
 
     const struct00: &str = "
     struct Struct00 { field_from_struct00 : f32 }
-    @group(0) @binding(0) var<storage, write> struct00_out : Struct00;
+    @group(0) @binding(0) var<storage, read_write> struct00_out : Struct00;
     @compute @workgroup_size(1) fn main() { 
-        struct00_out.field_from_struct00 = 7; 
+        struct00_out.field_from_struct00 = 7.0; 
     }";
 
     const struct01: &str = "
     struct Struct01 { field_from_struct01 : f32 }
     @group(0) @binding(0) var<storage, read> struct01_in : Struct01;
-    @group(0) @binding(1) var<storage, write> struct01_out : Struct01;
+    @group(0) @binding(1) var<storage, read_write> struct01_out : Struct01;
     @compute @workgroup_size(1) fn not_named_main() { 
         struct01_out.field_from_struct01 = struct01_in.field_from_struct01; 
     }";
@@ -979,7 +1179,7 @@ mod tests {
             resources: &map,
         });
 
-        let wgsl_text = fused.emit_wgsl();
+        let wgsl_text = fused.emit_wgsl().unwrap();
         eprintln!("{wgsl_text}");
     }
 
@@ -1007,7 +1207,7 @@ mod tests {
             resources: &map,
         });
 
-        let wgsl_text = fused.emit_wgsl();
+        let wgsl_text = fused.emit_wgsl().unwrap();
         eprintln!("{wgsl_text}");
     }
 }
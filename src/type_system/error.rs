@@ -2,11 +2,96 @@ use std::fmt::Debug;
 
 //use std::fmt::Display;
 use crate::debug_info::DebugInfo;
+use crate::diagnostics::{codes, Diagnostic};
+use crate::ir;
 
+// The node ids are scheduling nodes, while the quotients of tags refer to the spec funclet
+//   being checked.  The message is rendered when the error is found, since it needs names
+//   from the debug info, and ends with the context the error was found in.
 #[derive(Debug)]
 pub enum Error {
-    Unknown { message: String },
-    Generic { message: String },
+    Unknown {
+        message: String,
+    },
+    Generic {
+        message: String,
+    },
+    // The flow of a value doesn't allow it to be used this way, like passing a saved value
+    //   where a usable one is expected
+    FlowViolation {
+        node_id_opt: Option<usize>,
+        expected: ir::Tag,
+        actual: ir::Tag,
+        message: String,
+    },
+    // The flows agree, but the value stands for a different part of the spec
+    QuotientMismatch {
+        node_id_opt: Option<usize>,
+        expected: ir::Tag,
+        actual: ir::Tag,
+        message: String,
+    },
+    // A value is used after it was consumed or before anything was written to it
+    DeadValue {
+        node_id_opt: Option<usize>,
+        expected: ir::Tag,
+        actual: ir::Tag,
+        message: String,
+    },
+    // A value is used while the spec still needs it to be computed
+    UnsatisfiedNeed {
+        node_id_opt: Option<usize>,
+        expected: ir::Tag,
+        actual: ir::Tag,
+        message: String,
+    },
+    WrongPlace {
+        node_id: usize,
+        expected: ir::Place,
+        actual: ir::Place,
+        message: String,
+    },
+    // The buffer flags of a ref don't allow an operation, or two refs that must agree don't
+    BufferFlagMismatch {
+        node_id: usize,
+        expected: ir::BufferFlags,
+        actual: ir::BufferFlags,
+        message: String,
+    },
+    // A join, call or return passes a different number of values than its target takes
+    JoinArityMismatch {
+        node_id_opt: Option<usize>,
+        expected: usize,
+        actual: usize,
+        message: String,
+    },
+    TypeMismatch {
+        node_id_opt: Option<usize>,
+        message: String,
+    },
+    // A node is used after it was moved out of, or where no value was ever defined
+    UnavailableNode {
+        node_id: usize,
+        message: String,
+    },
+}
+
+impl Error {
+    // The scheduling node the error is about, if it names one
+    pub fn node_id_opt(&self) -> Option<usize> {
+        match self {
+            Self::Unknown { .. } | Self::Generic { .. } => None,
+            Self::FlowViolation { node_id_opt, .. }
+            | Self::QuotientMismatch { node_id_opt, .. }
+            | Self::DeadValue { node_id_opt, .. }
+            | Self::UnsatisfiedNeed { node_id_opt, .. }
+            | Self::JoinArityMismatch { node_id_opt, .. }
+            | Self::TypeMismatch { node_id_opt, .. } => *node_id_opt,
+            Self::WrongPlace { node_id, .. }
+            | Self::BufferFlagMismatch { node_id, .. }
+            | Self::UnavailableNode { node_id, .. } => Some(*node_id),
+        }
+    }
 }
 
 impl std::fmt::Display for Error {
@@ -14,12 +99,84 @@ impl std::fmt::Display for Error {
         match self {
             Self::Unknown { message } => write!(f, "Unknown error: {}", message),
             Self::Generic { message } => write!(f, "Generic error: {}", message),
+            Self::FlowViolation { message, .. } => write!(f, "Flow violation: {}", message),
+            Self::QuotientMismatch { message, .. } => write!(f, "Quotient mismatch: {}", message),
+            Self::DeadValue { message, .. } => write!(f, "Use of a dead value: {}", message),
+            Self::UnsatisfiedNeed { message, .. } => write!(f, "Unsatisfied need: {}", message),
+            Self::WrongPlace { message, .. } => write!(f, "Wrong place: {}", message),
+            Self::BufferFlagMismatch { message, .. } => {
+                write!(f, "Buffer flag mismatch: {}", message)
+            }
+            Self::JoinArityMismatch { message, .. } => {
+                write!(f, "Join arity mismatch: {}", message)
+            }
+            Self::TypeMismatch { message, .. } => write!(f, "Type mismatch: {}", message),
+            Self::UnavailableNode { message, .. } => write!(f, "Unavailable node: {}", message),
         }
     }
 }
 
 impl std::error::Error for Error {}
 
+// An error along with the funclet and node (None for the tail edge) where it was found
+#[derive(Debug)]
+pub struct LocatedError {
+    pub error: Error,
+    pub funclet_id: usize,
+    pub node_id: Option<usize>,
+}
+
+impl std::fmt::Display for LocatedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(f, "{}", self.error)
+    }
+}
+
+impl std::error::Error for LocatedError {}
+
+impl LocatedError {
+    pub fn to_diagnostic(&self, debug_info: &DebugInfo) -> Diagnostic {
+        // the first line is the error itself, the rest is the context it was found in
+        let rendered = self.error.to_string();
+        let mut lines = rendered.lines().filter(|l| !l.trim().is_empty());
+        let message = lines.next().unwrap_or_default().to_string();
+        let mut diagnostic = Diagnostic::error(codes::TYPE_CHECK, message);
+        let funclet_span = debug_info.funclet_span(&self.funclet_id);
+        match self.node_id {
+            Some(node_id) => {
+                diagnostic =
+                    diagnostic.with_primary(debug_info.node_span(&self.funclet_id, node_id));
+                if let Some(span) = funclet_span {
+                    if diagnostic.primary.as_ref() != Some(&span) {
+                        diagnostic = diagnostic.with_label(
+                            span,
+                            format!("in funclet {}", debug_info.funclet(&self.funclet_id)),
+                        );
+                    }
+                }
+            }
+            None => diagnostic = diagnostic.with_primary(funclet_span),
+        }
+        if let Some(offending_node_id) = self.error.node_id_opt() {
+            if self.node_id != Some(offending_node_id) {
+                if let Some(span) = debug_info.node_span(&self.funclet_id, offending_node_id) {
+                    diagnostic = diagnostic.with_label(
+                        span,
+                        format!(
+                            "node {} is defined here",
+                            debug_info.node(&self.funclet_id, offending_node_id)
+                        ),
+                    );
+                }
+            }
+        }
+        for line in lines {
+            diagnostic = diagnostic.with_note(line.trim().to_string());
+        }
+        diagnostic
+    }
+}
+
 /*impl Error {
     pub fn append_message(mut self, new_message: String) -> Self {
         match self {
@@ -33,6 +190,26 @@ impl std::error::Error for Error {}
     }
 }*/
 
+// What a checked tag belongs to, for error messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TagSubject {
+    Node(usize),
+    Implicit,
+    // An input of a join, by position
+    Input(usize),
+    // A value flowing out of a callee, join or choice into its continuation, by position
+    Output(usize),
+}
+
+impl TagSubject {
+    pub fn node_id_opt(self) -> Option<usize> {
+        match self {
+            Self::Node(node_id) => Some(node_id),
+            _ => None,
+        }
+    }
+}
+
 pub struct ErrorContext<'scope> {
     parent_opt: Option<&'scope Self>,
     contextualize_cb_opt:
@@ -54,7 +231,7 @@ impl<'scope> ErrorContext<'scope> {
             parent_opt,
             contextualize_cb_opt,
             debug_info,
-            current_funclet_id
+            current_funclet_id,
         }
     }
 
@@ -64,6 +241,136 @@ impl<'scope> ErrorContext<'scope> {
         }
     }
 
+    // Explains why a value tagged with `actual` in the spec funclet `spec_funclet_id` can't
+    //   be used where `expected` is required.  The kind of error depends on how they differ.
+    pub fn tag_mismatch(
+        &self,
+        spec_funclet_id: usize,
+        subject: TagSubject,
+        expected: ir::Tag,
+        actual: ir::Tag,
+    ) -> Error {
+        let node_id_opt = subject.node_id_opt();
+        let subject = match subject {
+            TagSubject::Node(node_id) => format!("Node {}", self.debug_node(node_id)),
+            TagSubject::Implicit => String::from("The implicit tag"),
+            TagSubject::Input(index) => format!("Input {}", index),
+            TagSubject::Output(index) => format!("Output {}", index),
+        };
+        let expected_quot = self.debug_info.quot(&spec_funclet_id, &expected.quot);
+        let actual_quot = self.debug_info.quot(&spec_funclet_id, &actual.quot);
+        if actual.flow == ir::Flow::Dead && expected.flow != ir::Flow::Dead {
+            Error::DeadValue {
+                node_id_opt,
+                expected,
+                actual,
+                message: format!(
+                    "{} holds no value for {}, but is used as {} with flow {:?}\n{}",
+                    subject, actual_quot, expected_quot, expected.flow, self
+                ),
+            }
+        } else if actual.flow == ir::Flow::Need && expected.flow != ir::Flow::Need {
+            Error::UnsatisfiedNeed {
+                node_id_opt,
+                expected,
+                actual,
+                message: format!(
+                    "{} still needs {} to be computed, but is used as {} with flow {:?}\n{}",
+                    subject, actual_quot, expected_quot, expected.flow, self
+                ),
+            }
+        } else if actual.flow != expected.flow {
+            Error::FlowViolation {
+                node_id_opt,
+                expected,
+                actual,
+                message: format!(
+                    "{} has flow {:?} for {}, but flow {:?} is required for {}\n{}",
+                    subject, actual.flow, actual_quot, expected.flow, expected_quot, self
+                ),
+            }
+        } else {
+            Error::QuotientMismatch {
+                node_id_opt,
+                expected,
+                actual,
+                message: format!(
+                    "{} holds {}, but {} is expected\n{}",
+                    subject, actual_quot, expected_quot, self
+                ),
+            }
+        }
+    }
+
+    pub fn wrong_place(&self, node_id: usize, expected: ir::Place, actual: ir::Place) -> Error {
+        Error::WrongPlace {
+            node_id,
+            expected,
+            actual,
+            message: format!(
+                "Node {} is in {} memory, but must be in {} memory\n{}",
+                self.debug_node(node_id),
+                actual,
+                expected,
+                self
+            ),
+        }
+    }
+
+    // `expected` holds the flags that are required, not every flag the node must have
+    pub fn buffer_flag_mismatch(
+        &self,
+        node_id: usize,
+        expected: ir::BufferFlags,
+        actual: ir::BufferFlags,
+        m: &dyn std::fmt::Display,
+    ) -> Error {
+        Error::BufferFlagMismatch {
+            node_id,
+            expected,
+            actual,
+            message: format!(
+                "{} (node {} has {:?})\n{}",
+                m,
+                self.debug_node(node_id),
+                actual,
+                self
+            ),
+        }
+    }
+
+    pub fn join_arity_mismatch(
+        &self,
+        node_id_opt: Option<usize>,
+        expected: usize,
+        actual: usize,
+        m: &dyn std::fmt::Display,
+    ) -> Error {
+        Error::JoinArityMismatch {
+            node_id_opt,
+            expected,
+            actual,
+            message: format!(
+                "{} passes {} values, but {} are expected\n{}",
+                m, actual, expected, self
+            ),
+        }
+    }
+
+    pub fn type_mismatch(&self, node_id_opt: Option<usize>, m: &dyn std::fmt::Display) -> Error {
+        Error::TypeMismatch {
+            node_id_opt,
+            message: format!("{}\n{}", m, self),
+        }
+    }
+
+    pub fn unavailable_node(&self, node_id: usize, m: &dyn std::fmt::Display) -> Error {
+        Error::UnavailableNode {
+            node_id,
+            message: format!("{}\n{}", m, self),
+        }
+    }
+
     pub fn debug_info(&self) -> &DebugInfo {
         &self.debug_info
     }
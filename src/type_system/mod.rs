@@ -6,58 +6,305 @@ pub mod error;
 pub fn check_program(
     program: &super::ir::Program,
     debug_info: &crate::debug_info::DebugInfo,
-) -> Result<(), error::Error> {
-    for (funclet_id, funclet) in program.funclets.iter() {
-        let funclet_error_contextualizer = |writer: &mut dyn std::fmt::Write| {
-            write!(writer, "In funclet {}", debug_info.funclet(&funclet_id))
-        };
-        let funclet_error_context = error::ErrorContext::new(
-            None,
-            Some(&funclet_error_contextualizer),
-            debug_info,
-            funclet_id,
-        );
+) -> Result<(), error::LocatedError> {
+    for (funclet_id, _) in program.funclets.iter() {
+        check_funclet(program, funclet_id, debug_info)?;
+    }
+    return Ok(());
+}
 
-        if funclet.kind != super::ir::FuncletKind::ScheduleExplicit {
-            continue;
-        }
-
-        let mut funclet_checker = scheduling::FuncletChecker::new(&program, funclet_id, funclet, debug_info);
-
-        for (current_node_id, node) in funclet.nodes.iter().enumerate() {
-            let node_error_contextualizer = |writer: &mut dyn std::fmt::Write| {
-                write!(
-                    writer,
-                    "While type checking node {}: {}",
-                    debug_info.node(&funclet_id, current_node_id),
-                    debug_info.node_ir(funclet_id, node)
-                )
-            };
-            let node_error_context = error::ErrorContext::new(
-                Some(&funclet_error_context),
-                Some(&node_error_contextualizer),
-                debug_info,
-                funclet_id,
-            );
+// checks a single funclet against the rest of the program
+// only schedules have anything to check
+pub fn check_funclet(
+    program: &super::ir::Program,
+    funclet_id: usize,
+    debug_info: &crate::debug_info::DebugInfo,
+) -> Result<(), error::LocatedError> {
+    let funclet = &program.funclets[funclet_id];
+    let funclet_error_contextualizer = |writer: &mut dyn std::fmt::Write| {
+        write!(writer, "In funclet {}", debug_info.funclet(&funclet_id))
+    };
+    let funclet_error_context = error::ErrorContext::new(
+        None,
+        Some(&funclet_error_contextualizer),
+        debug_info,
+        funclet_id,
+    );
+
+    if funclet.kind != super::ir::FuncletKind::ScheduleExplicit {
+        return Ok(());
+    }
 
-            funclet_checker.check_next_node(&node_error_context, current_node_id)?;
-        }
+    let mut funclet_checker =
+        scheduling::FuncletChecker::new(&program, funclet_id, funclet, debug_info);
 
-        let tail_error_contextualizer = |writer: &mut dyn std::fmt::Write| {
+    for (current_node_id, node) in funclet.nodes.iter().enumerate() {
+        let node_error_contextualizer = |writer: &mut dyn std::fmt::Write| {
             write!(
                 writer,
-                "While type checking funclet {} with tail edge: {:?}",
-                debug_info.funclet(&funclet_id),
-                funclet.tail_edge
+                "While type checking node {}: {}",
+                debug_info.node(&funclet_id, current_node_id),
+                debug_info.node_ir(funclet_id, node)
             )
         };
-        let tail_error_context = error::ErrorContext::new(
+        let node_error_context = error::ErrorContext::new(
             Some(&funclet_error_context),
-            Some(&tail_error_contextualizer),
+            Some(&node_error_contextualizer),
             debug_info,
             funclet_id,
         );
-        funclet_checker.check_tail_edge(&tail_error_context)?;
+
+        funclet_checker
+            .check_next_node(&node_error_context, current_node_id)
+            .map_err(|error| error::LocatedError {
+                error,
+                funclet_id,
+                node_id: Some(current_node_id),
+            })?;
     }
+
+    let tail_error_contextualizer = |writer: &mut dyn std::fmt::Write| {
+        write!(
+            writer,
+            "While type checking funclet {} with tail edge: {:?}",
+            debug_info.funclet(&funclet_id),
+            funclet.tail_edge
+        )
+    };
+    let tail_error_context = error::ErrorContext::new(
+        Some(&funclet_error_context),
+        Some(&tail_error_contextualizer),
+        debug_info,
+        funclet_id,
+    );
+    funclet_checker
+        .check_tail_edge(&tail_error_context)
+        .map_err(|error| error::LocatedError {
+            error,
+            funclet_id,
+            node_id: None,
+        })?;
     return Ok(());
 }
+
+#[cfg(test)]
+mod tests {
+    use super::error::Error;
+    use crate::assembly::{lowering_pass, parser};
+    use crate::frontend::Definition;
+    use crate::ir;
+
+    const PROGRAM: &str = "version 0.0.2
+
+ffi i64;
+event %event0;
+buffer_space %buffspace;
+native_value %i64 : i64;
+
+function @main() -> %i64;
+
+value[impl default @main] %value() -> %i64 {
+    %x = constant %i64 4;
+    %y = constant %i64 5;
+    return %x;
+}
+
+timeline %time(%e : %event0) -> %event0 {
+    return %e;
+}
+
+spatial %space(%bs : %buffspace) -> %buffspace {
+    return %bs;
+}
+
+schedule[value $val = %value, timeline $time = %time, spatial $space = %space]
+%foo<$time-usable, $time-usable>() ->
+[%out : $val.%x-usable $time-usable $space-usable %i64] {
+    %x_ref = alloc-temporary local [] i64;
+    local-do-builtin $val.%x() -> %x_ref;
+    %result = read-ref i64 %x_ref;
+    return %result;
+}
+
+pipeline \"main\" = %foo;
+";
+
+    // Two selects share a condition, so one schedule-select decides both
+    const SELECT_PROGRAM: &str = "version 0.0.2
+
+ffi i64;
+event %event0;
+buffer_space %buffspace;
+native_value %i64 : i64;
+
+function @main() -> %i64;
+
+value[impl default @main] %foo() -> [%out: %i64] {
+    %x = constant %i64 0;
+    %y = constant %i64 1;
+    %z = constant %i64 2;
+    %r = select %x %y %z;
+    %s = select %x %z %y;
+    return %r;
+}
+
+timeline %time(%e : %event0) -> %event0 {
+    return %e;
+}
+
+spatial %space(%bs : %buffspace) -> %buffspace {
+    return %bs;
+}
+
+schedule[value $val = %foo, timeline $time = %time, spatial $space = %space]
+%foo_main<$time-usable, $time-usable>() ->
+[%out : $val.%out-usable $space-usable $time-usable %i64] {
+    %x_loc = alloc-temporary local [] i64;
+    local-do-builtin $val.%x() -> %x_loc;
+    %x_val = read-ref i64 %x_loc;
+    %djoin = default-join;
+    %join = inline-join %foo_ret [] %djoin;
+    schedule-select %x_val [%foo_left, %foo_right]
+        [$val.%r, $time, $space]
+        () %join;
+}
+
+schedule[value $val = %foo, timeline $time = %time, spatial $space = %space]
+%foo_left<$time-usable, $time-usable>() ->
+[%r_out : $val.%y-usable $space-usable $time-usable %i64,
+%s_out : $val.%z-usable $space-usable $time-usable %i64] {
+    %y_loc = alloc-temporary local [] i64;
+    local-do-builtin $val.%y() -> %y_loc;
+    %y_val = read-ref i64 %y_loc;
+    %z_loc = alloc-temporary local [] i64;
+    local-do-builtin $val.%z() -> %z_loc;
+    %z_val = read-ref i64 %z_loc;
+    return [%y_val, %z_val];
+}
+
+schedule[value $val = %foo, timeline $time = %time, spatial $space = %space]
+%foo_right<$time-usable, $time-usable>() ->
+[%r_out : $val.%z-usable $space-usable $time-usable %i64,
+%s_out : $val.%y-usable $space-usable $time-usable %i64] {
+    %y_loc = alloc-temporary local [] i64;
+    local-do-builtin $val.%y() -> %y_loc;
+    %y_val = read-ref i64 %y_loc;
+    %z_loc = alloc-temporary local [] i64;
+    local-do-builtin $val.%z() -> %z_loc;
+    %z_val = read-ref i64 %z_loc;
+    return [%z_val, %y_val];
+}
+
+schedule[value $val = %foo, timeline $time = %time, spatial $space = %space]
+%foo_ret<$time-usable, $time-usable>
+    (%r_val : $val.%r-usable $space-usable $time-usable %i64,
+    %s_val : $val.%s-usable $space-usable $time-usable %i64) ->
+[%out : $val.%out-usable $space-usable $time-usable %i64] {
+    return %r_val;
+}
+
+pipeline \"main\" = %foo_main;
+";
+
+    fn definition(text: &str) -> Definition {
+        let program = parser::parse("", text).unwrap();
+        crate::explication::explicate(lowering_pass::lower(program, "").unwrap()).unwrap()
+    }
+
+    fn check(definition: &Definition) -> Result<(), Error> {
+        super::check_program(&definition.program, &definition.debug_info).map_err(|e| e.error)
+    }
+
+    fn schedule(definition: &mut Definition) -> &mut ir::Funclet {
+        definition
+            .program
+            .funclets
+            .iter_mut()
+            .map(|(_, funclet)| funclet)
+            .find(|funclet| funclet.kind == ir::FuncletKind::ScheduleExplicit)
+            .unwrap()
+    }
+
+    #[test]
+    fn accepts_well_typed_schedule() {
+        check(&definition(PROGRAM)).unwrap();
+    }
+
+    #[test]
+    fn reports_dead_value() {
+        // drop the write, so the read sees a temporary that was never filled
+        let mut definition = definition(PROGRAM);
+        let funclet = schedule(&mut definition);
+        let mut nodes = funclet.nodes.to_vec();
+        let write = nodes
+            .iter()
+            .position(|node| matches!(node, ir::Node::LocalDoBuiltin { .. }))
+            .unwrap();
+        nodes[write] = ir::Node::None;
+        funclet.nodes = nodes.into_boxed_slice();
+        let error = check(&definition).unwrap_err();
+        assert!(matches!(error, Error::DeadValue { .. }), "{}", error);
+    }
+
+    #[test]
+    fn reports_external_arity_mismatch() {
+        // the schedule passes one input to an external function taking two
+        let text = include_str!("../../caiman-test/malformed/external_arity.cair");
+        let definition = definition(text);
+        let error = super::check_program(&definition.program, &definition.debug_info).unwrap_err();
+        assert!(
+            matches!(
+                error.error,
+                Error::JoinArityMismatch {
+                    expected: 2,
+                    actual: 1,
+                    ..
+                }
+            ),
+            "{}",
+            error
+        );
+        assert_eq!(error.node_id, Some(2));
+    }
+
+    #[test]
+    fn reports_wrong_place() {
+        let text = PROGRAM.replace("alloc-temporary local", "alloc-temporary gpu");
+        let error = check(&definition(&text)).unwrap_err();
+        assert!(matches!(error, Error::WrongPlace { .. }), "{}", error);
+    }
+
+    #[test]
+    fn reports_quotient_mismatch() {
+        // claim the result is %y while the schedule computes %x
+        let mut definition = definition(PROGRAM);
+        let funclet = schedule(&mut definition);
+        let ir::FuncletSpecBinding::ScheduleExplicit { value, .. } = &mut funclet.spec_binding
+        else {
+            panic!("Not a schedule");
+        };
+        let ir::Quotient::Node { node_id } = &mut value.output_tags[0].quot else {
+            panic!("Not a node quotient");
+        };
+        *node_id += 1;
+        let error = check(&definition).unwrap_err();
+        assert!(matches!(error, Error::QuotientMismatch { .. }), "{}", error);
+    }
+
+    #[test]
+    fn accepts_select_with_several_results() {
+        check(&definition(SELECT_PROGRAM)).unwrap();
+    }
+
+    #[test]
+    fn reports_select_case_mismatch() {
+        // the false case of %s is %y, so the right branch can't return %z for it
+        let text = SELECT_PROGRAM.replace(
+            "%s_out : $val.%y-usable $space-usable $time-usable %i64] {",
+            "%s_out : $val.%z-usable $space-usable $time-usable %i64] {",
+        );
+        let text = text.replacen("return [%z_val, %y_val];", "return [%z_val, %z_val];", 1);
+        let error = check(&definition(&text)).unwrap_err();
+        assert!(matches!(error, Error::QuotientMismatch { .. }), "{}", error);
+    }
+}
@@ -77,7 +77,14 @@ impl Buffer {
         static_layout.alignment_bits =
             (total_byte_size + starting_alignment_offset).trailing_zeros() as usize;*/
 
-        static_layout.alloc_static(native_interface, storage_type);
+        static_layout
+            .alloc_static(native_interface, self.storage_place, storage_type)
+            .map_err(|error| {
+                error_context.type_mismatch(
+                    None,
+                    &format!("Cannot allocate in a static buffer: {}", error),
+                )
+            })?;
 
         return Ok(());
     }
@@ -100,7 +107,12 @@ impl Buffer {
         static_layout.alignment_bits =
             (size + starting_alignment_offset).trailing_zeros() as usize;*/
 
-        let predecessor_static_layout = static_layout.split_static(native_interface, size);
+        let predecessor_static_layout = static_layout
+            .split_static(native_interface, size)
+            .map_err(|error| {
+                error_context
+                    .type_mismatch(None, &format!("Cannot split a static buffer: {}", error))
+            })?;
 
         return Ok(predecessor_static_layout);
     }
@@ -150,9 +162,11 @@ impl NodeType {
 fn check_slot_type(
     program: &ir::Program,
     type_id: ir::TypeId,
+    node_id: ir::NodeId,
     node_type: &NodeType,
     error_context: &ErrorContext,
-) {
+) -> Result<(), Error> {
+    let type_name = error_context.debug_info().typ(&type_id);
     match &program.types[type_id] {
         ir::Type::NativeValue {
             storage_type: storage_type_1,
@@ -161,14 +175,27 @@ fn check_slot_type(
                 storage_type: storage_type_2,
             }) = node_type
             {
-                assert_eq!(*storage_type_1, *storage_type_2, "\n{}", error_context);
+                if *storage_type_1 != *storage_type_2 {
+                    return Err(error_context.type_mismatch(
+                        Some(node_id),
+                        &format!(
+                            "Node {} holds a {}, but type {} holds a {}",
+                            error_context.debug_node(node_id),
+                            error_context.debug_info().ffi_typ(&storage_type_2.0),
+                            type_name,
+                            error_context.debug_info().ffi_typ(&storage_type_1.0)
+                        ),
+                    ));
+                }
             } else {
-                panic!(
-                    "type id {} is a native value type, but node {:?} is not a local variable\n{}",
-                    error_context.debug_info().typ(&type_id),
-                    node_type,
-                    error_context
-                );
+                return Err(error_context.type_mismatch(
+                    Some(node_id),
+                    &format!(
+                        "type id {} is a native value type, but node {} is not a local variable",
+                        type_name,
+                        error_context.debug_node(node_id)
+                    ),
+                ));
             }
         }
         ir::Type::Ref {
@@ -184,31 +211,57 @@ fn check_slot_type(
                 buffer_flags,
             }) = node_type
             {
-                assert_eq!(*queue_place_2, *queue_place, "\n{}", error_context);
+                if *queue_place_2 != *queue_place {
+                    return Err(error_context.wrong_place(node_id, *queue_place_2, *queue_place));
+                }
                 //assert_eq!(*queue_stage_2, *queue_stage);
-                assert_eq!(*storage_type, *storage_type_2, "\n{}", error_context);
-                assert_eq!(*buffer_flags, *buffer_flags_2, "\n{}", error_context);
+                if *storage_type != *storage_type_2 {
+                    return Err(error_context.type_mismatch(
+                        Some(node_id),
+                        &format!(
+                            "Node {} refers to a {}, but type {} refers to a {}",
+                            error_context.debug_node(node_id),
+                            error_context.debug_info().ffi_typ(&storage_type.0),
+                            type_name,
+                            error_context.debug_info().ffi_typ(&storage_type_2.0)
+                        ),
+                    ));
+                }
+                if *buffer_flags != *buffer_flags_2 {
+                    return Err(error_context.buffer_flag_mismatch(
+                        node_id,
+                        *buffer_flags_2,
+                        *buffer_flags,
+                        &format!("The flags of type {} are {:?}", type_name, buffer_flags_2),
+                    ));
+                }
             } else {
-                panic!(
-                    "type id {} is a ref type, but node type {:?} is not a ref\n{}",
-                    error_context.debug_info().typ(&type_id),
-                    node_type,
-                    error_context
-                );
+                return Err(error_context.type_mismatch(
+                    Some(node_id),
+                    &format!(
+                        "type id {} is a ref type, but node {} is not a ref",
+                        type_name,
+                        error_context.debug_node(node_id)
+                    ),
+                ));
             }
         }
         ir::Type::Fence {
             queue_place: queue_place_2,
         } => {
             if let NodeType::Fence(Fence { queue_place }) = node_type {
-                assert_eq!(*queue_place_2, *queue_place, "\n{}", error_context);
+                if *queue_place_2 != *queue_place {
+                    return Err(error_context.wrong_place(node_id, *queue_place_2, *queue_place));
+                }
             } else {
-                panic!(
-                    "type id {} is a fence type, but node type {:?} is not a fence\n{}",
-                    error_context.debug_info().typ(&type_id),
-                    node_type,
-                    error_context
-                );
+                return Err(error_context.type_mismatch(
+                    Some(node_id),
+                    &format!(
+                        "type id {} is a fence type, but node {} is not a fence",
+                        type_name,
+                        error_context.debug_node(node_id)
+                    ),
+                ));
             }
         }
         ir::Type::Encoder {
@@ -216,18 +269,23 @@ fn check_slot_type(
         } => {
             // TODO: Is this check correct???
             if let NodeType::Encoder(Encoder { queue_place }) = node_type {
-                assert_eq!(*queue_place_2, *queue_place, "\n{}", error_context);
+                if *queue_place_2 != *queue_place {
+                    return Err(error_context.wrong_place(node_id, *queue_place_2, *queue_place));
+                }
             } else {
-                panic!(
-                    "type id {} is an encoder type, but node type {:?} is not an encoder\n{}",
-                    error_context.debug_info().typ(&type_id),
-                    node_type,
-                    error_context
-                );
+                return Err(error_context.type_mismatch(
+                    Some(node_id),
+                    &format!(
+                        "type id {} is an encoder type, but node {} is not an encoder",
+                        type_name,
+                        error_context.debug_node(node_id)
+                    ),
+                ));
             }
         }
         _ => panic!("Unimplemented\n{}", error_context),
     }
+    Ok(())
 }
 
 fn check_slot_storage_type(
@@ -244,17 +302,9 @@ fn advance_forward_value_copy<'program>(
     input_impl_node_id: ir::NodeId,
     output_impl_node_id: ir::NodeId,
 ) -> Result<(), Error> {
-    let scalar = &value_spec_checker.scalar_nodes[&input_impl_node_id];
-    assert!(scalar.flow.is_readable(), "\n{}", error_context);
+    let scalar = value_spec_checker.check_node_is_readable(error_context, input_impl_node_id)?;
     //value_spec_checker.check_node_tag(output_impl_node_id, ir::Tag{quot: scalar.quot, flow: ir::Flow::None})?;
-    assert!(
-        value_spec_checker.can_drop_node(output_impl_node_id),
-        "node {}\n{}",
-        error_context
-            .debug_info()
-            .node(&value_spec_checker.funclet_id, output_impl_node_id),
-        error_context
-    );
+    value_spec_checker.check_can_drop_node(error_context, output_impl_node_id)?;
     value_spec_checker.update_scalar_node(output_impl_node_id, scalar.quot, ir::Flow::Usable);
     return Ok(());
 }
@@ -276,14 +326,7 @@ fn advance_forward_value_do<'program>(
             // Outputs
             assert_eq!(output_impl_node_ids.len(), 1, "\n{}", error_context);
             //value_spec_checker.check_node_tag(output_impl_node_ids[0], ir::Tag{quot: ir::Quotient::Node{node_id: spec_node_id}, flow: ir::Flow::None})?;
-            assert!(
-                value_spec_checker.can_drop_node(output_impl_node_ids[0]),
-                "node {}\n{}",
-                error_context
-                    .debug_info()
-                    .node(&value_spec_checker.funclet_id, output_impl_node_ids[0]),
-                error_context
-            );
+            value_spec_checker.check_can_drop_node(error_context, output_impl_node_ids[0])?;
             value_spec_checker.update_scalar_node(
                 output_impl_node_ids[0],
                 ir::Quotient::Node {
@@ -302,34 +345,16 @@ fn advance_forward_value_do<'program>(
             for (input_index, input_spec_node_id) in
                 [*condition, *true_case, *false_case].iter().enumerate()
             {
-                let scalar = &value_spec_checker.scalar_nodes[&input_impl_node_ids[input_index]];
-                assert_eq!(
-                    scalar.quot,
-                    ir::Quotient::Node {
-                        node_id: *input_spec_node_id
-                    },
-                    "\n\n{} =/= {}\n\n{}",
-                    error_context
-                        .debug_info()
-                        .quot(&value_spec_checker.funclet_id, &scalar.quot),
-                    error_context
-                        .debug_info()
-                        .node(&value_spec_checker.funclet_id, *input_spec_node_id),
-                    error_context
-                );
-                assert!(scalar.flow.is_readable(), "\n{}", error_context);
+                value_spec_checker.check_node_is_readable_as(
+                    error_context,
+                    input_impl_node_ids[input_index],
+                    *input_spec_node_id,
+                )?;
             }
             // Outputs
             assert_eq!(output_impl_node_ids.len(), 1, "\n{}", error_context);
             //value_spec_checker.check_node_tag(output_impl_node_ids[0], ir::Tag{quot: ir::Quotient::Node{node_id: spec_node_id}, flow: ir::Flow::None})?;
-            assert!(
-                value_spec_checker.can_drop_node(output_impl_node_ids[0]),
-                "node {}\n{}",
-                error_context
-                    .debug_info()
-                    .node(&value_spec_checker.funclet_id, output_impl_node_ids[0]),
-                error_context
-            );
+            value_spec_checker.check_can_drop_node(error_context, output_impl_node_ids[0])?;
             value_spec_checker.update_scalar_node(
                 output_impl_node_ids[0],
                 ir::Quotient::Node {
@@ -350,35 +375,16 @@ fn advance_forward_value_do<'program>(
                 error_context
             );
             for (input_index, input_spec_node_id) in arguments.iter().enumerate() {
-                let scalar = &value_spec_checker.scalar_nodes[&input_impl_node_ids[input_index]];
-
-                assert_eq!(
-                    scalar.quot,
-                    ir::Quotient::Node {
-                        node_id: *input_spec_node_id
-                    },
-                    "\n\n{} =/= {}\n\n{}",
-                    error_context
-                        .debug_info()
-                        .quot(&value_spec_checker.funclet_id, &scalar.quot),
-                    error_context
-                        .debug_info()
-                        .node(&value_spec_checker.funclet_id, *input_spec_node_id),
-                    error_context
-                );
-                assert!(scalar.flow.is_readable(), "\n{}", error_context);
+                value_spec_checker.check_node_is_readable_as(
+                    error_context,
+                    input_impl_node_ids[input_index],
+                    *input_spec_node_id,
+                )?;
             }
             // Outputs
             for (output_index, output_impl_node_id) in output_impl_node_ids.iter().enumerate() {
                 // To do: Check that spec node is really an extractresult
-                assert!(
-                    value_spec_checker.can_drop_node(*output_impl_node_id),
-                    "node {}\n{}",
-                    error_context
-                        .debug_info()
-                        .node(&value_spec_checker.funclet_id, *output_impl_node_id),
-                    error_context
-                );
+                value_spec_checker.check_can_drop_node(error_context, *output_impl_node_id)?;
                 //value_spec_checker.check_node_tag(*output_impl_node_id, ir::Tag{quot: ir::Quotient::Node{node_id: spec_node_id}, flow: ir::Flow::Need})?;
                 value_spec_checker.update_scalar_node(
                     *output_impl_node_id,
@@ -516,7 +522,7 @@ fn advance_forward_timeline<'program>(
             );
 
             timeline_spec_checker.transition_state_forwards(*local_past, spec_node_id)?;
-            timeline_spec_checker.transition_state_forwards(*remote_local_past, spec_node_id);
+            timeline_spec_checker.transition_state_forwards(*remote_local_past, spec_node_id)?;
         }
         _ => panic!("Unsupported node: {:?}\n{}", encoded_node, error_context),
     }
@@ -798,6 +804,27 @@ impl<'program> FuncletChecker<'program> {
             .drop_node(node_id);
     }
 
+    // Explains why a node can't be dropped, through the first spec that doesn't allow it
+    fn undroppable_node_error(&self, error_context: &ErrorContext, node_id: ir::NodeId) -> Error {
+        for spec_checker in [
+            &self.value_spec_checker_opt,
+            &self.timeline_spec_checker_opt,
+            &self.spatial_spec_checker_opt,
+        ] {
+            if let Err(error) = spec_checker
+                .as_ref()
+                .unwrap()
+                .check_can_drop_node(error_context, node_id)
+            {
+                return error;
+            }
+        }
+        error_context.generic_error(&format!(
+            "Node {} can't be dropped",
+            error_context.debug_node(node_id)
+        ))
+    }
+
     /*fn contextualize_error(&self, error : Error) -> Error {
         match error {
             Error::Unknown{message} => Error::Unknown{message},
@@ -824,42 +851,33 @@ impl<'program> FuncletChecker<'program> {
         }
 
         let Some(spec_funclet_id) = funclet_spec.funclet_id_opt else {
-            return Err(Error::Generic {
-                message: String::from("Expected spec funclet id"),
-            });
+            return Err(error_context.generic_error(&"Expected spec funclet id"));
         };
         let spec_funclet = &self.program.funclets[spec_funclet_id];
         let ir::Quotient::Node {
             node_id: call_node_id,
         } = operation
         else {
-            return Err(Error::Generic {
-                message: String::from("Expected operation to be a Node"),
-            });
+            return Err(error_context.generic_error(&"Expected operation to be a Node"));
         };
         let ir::Node::CallFunctionClass {
             function_id,
             arguments,
         } = &spec_funclet.nodes[call_node_id]
         else {
-            return Err(Error::Generic {
-                message: String::from("Expected call node"),
-            });
+            return Err(error_context.generic_error(&"Expected call node"));
         };
         if !self.program.function_classes[*function_id]
             .external_function_ids
             .contains(&external_function_id)
         {
-            return Err(Error::Generic {
-                message: format!(
-                    "External function {} does not implement function class {}\n{}",
-                    error_context
-                        .debug_info()
-                        .external_function(&external_function_id.0),
-                    error_context.debug_info().function_class(&function_id),
-                    error_context
-                ),
-            });
+            return Err(error_context.generic_error(&format!(
+                "External function {} does not implement function class {}",
+                error_context
+                    .debug_info()
+                    .external_function(&external_function_id.0),
+                error_context.debug_info().function_class(&function_id),
+            )));
         }
 
         let spec_checker = spec_checker_opt.unwrap();
@@ -875,12 +893,14 @@ impl<'program> FuncletChecker<'program> {
             "\n{}",
             error_context
         );
-        assert_eq!(
-            yielded_impl_node_ids.len() + 1,
-            arguments.len(),
-            "\n{}",
-            error_context
-        );
+        if yielded_impl_node_ids.len() + 1 != arguments.len() {
+            return Err(error_context.join_arity_mismatch(
+                None,
+                arguments.len() - 1,
+                yielded_impl_node_ids.len(),
+                &"The yield",
+            ));
+        }
 
         assert!(arguments.len() > 0, "\n{}", error_context);
 
@@ -910,12 +930,19 @@ impl<'program> FuncletChecker<'program> {
 
         // Check continuation against outputs
         let continuation_join_point = &self.node_join_points[&continuation_impl_node_id];
-        assert_eq!(
-            continuation_join_point.input_types.len(),
-            effectful_operation.output_types.len(),
-            "\n{}",
-            error_context
-        );
+        if continuation_join_point.input_types.len() != effectful_operation.output_types.len() {
+            return Err(error_context.join_arity_mismatch(
+                Some(continuation_impl_node_id),
+                continuation_join_point.input_types.len(),
+                effectful_operation.output_types.len(),
+                &format!(
+                    "The external function {}",
+                    error_context
+                        .debug_info()
+                        .external_function(&external_function_id.0)
+                ),
+            ));
+        }
         let mut continuation_input_tags = Vec::<ir::Tag>::new();
         for index in 0..continuation_join_point.input_types.len() {
             // 0th output is the implicit
@@ -925,9 +952,7 @@ impl<'program> FuncletChecker<'program> {
                 index: argument_index,
             } = &spec_funclet.nodes[extract_node_id]
             else {
-                return Err(Error::Generic {
-                    message: String::from("Expected extract result"),
-                });
+                return Err(error_context.generic_error(&"Expected extract result"));
             };
             assert_eq!(
                 call_node_id,
@@ -967,7 +992,7 @@ impl<'program> FuncletChecker<'program> {
                 },
                 flow: ir::Flow::Usable,
             },
-        );
+        )?;
 
         return Ok(());
     }
@@ -1024,18 +1049,14 @@ impl<'program> FuncletChecker<'program> {
                 node: dropped_node_id,
             } => {
                 if let Some(node_type) = self.node_types.remove(dropped_node_id) {
-                    assert!(
-                        self.can_drop_node(*dropped_node_id),
-                        "node {}\n{}",
-                        error_context.debug_node(*dropped_node_id),
-                        error_context
-                    );
+                    if !self.can_drop_node(*dropped_node_id) {
+                        return Err(self.undroppable_node_error(error_context, *dropped_node_id));
+                    }
                 } else {
-                    panic!(
-                        "No node at {}\n{}",
-                        error_context.debug_node(*dropped_node_id),
-                        error_context
-                    )
+                    return Err(error_context.unavailable_node(
+                        *dropped_node_id,
+                        &format!("No node at {}", error_context.debug_node(*dropped_node_id)),
+                    ));
                 }
                 self.drop_node(*dropped_node_id);
             }
@@ -1117,12 +1138,20 @@ impl<'program> FuncletChecker<'program> {
             } => {
                 let external_function =
                     &self.program.native_interface.external_functions[external_function_id.0];
-                assert_eq!(
-                    external_function.get_input_types().map(|x| x.len()),
-                    Some(inputs.len()),
-                    "\n{}",
-                    error_context
-                );
+                let input_count = external_function.get_input_types().map_or(0, |x| x.len());
+                if input_count != inputs.len() {
+                    return Err(error_context.join_arity_mismatch(
+                        Some(current_node_id),
+                        input_count,
+                        inputs.len(),
+                        &format!(
+                            "The call to external function {}",
+                            error_context
+                                .debug_info()
+                                .external_function(&external_function_id.0)
+                        ),
+                    ));
+                }
 
                 advance_forward_value_do(
                     self.value_spec_checker_opt.as_mut().unwrap(),
@@ -1333,15 +1362,32 @@ impl<'program> FuncletChecker<'program> {
                             );
 
                             let NodeType::Slot(Slot {
-                                queue_place: ir::Place::Gpu,
+                                queue_place,
                                 buffer_flags,
                                 ..
                             }) = self.node_types.get(input_impl_node_id).as_ref().unwrap()
                             else {
-                                panic!("Non-dimensionality arguments to encode_do of a GPU kernel must be GPU refs (offending argument to encode_do: {} which is node {})\n{}", error_context.debug_node(input_index), error_context.debug_node(*input_impl_node_id), error_context)
+                                return Err(error_context.type_mismatch(Some(*input_impl_node_id), &format!("Non-dimensionality arguments to encode_do of a GPU kernel must be GPU refs (offending argument to encode_do: {} which is node {})", input_index, error_context.debug_node(*input_impl_node_id))));
                             };
+                            if *queue_place != ir::Place::Gpu {
+                                return Err(error_context.wrong_place(
+                                    *input_impl_node_id,
+                                    ir::Place::Gpu,
+                                    *queue_place,
+                                ));
+                            }
 
-                            assert!(buffer_flags.storage || buffer_flags.uniform, "Argument {} to encode_do is marked as neither a storage nor uniform buffer (maps to node {})\n{}", error_context.debug_node(input_index), error_context.debug_node(*input_impl_node_id), error_context);
+                            if !(buffer_flags.storage || buffer_flags.uniform) {
+                                return Err(error_context.buffer_flag_mismatch(
+                                    *input_impl_node_id,
+                                    ir::BufferFlags {
+                                        storage: true,
+                                        ..Default::default()
+                                    },
+                                    *buffer_flags,
+                                    &format!("Argument {} to encode_do is marked as neither a storage nor uniform buffer", input_index),
+                                ));
+                            }
 
                             //value_spec_checker.check_node_is_readable_at(*input_impl_node_id, encoder_value_tag)?;
                             timeline_spec_checker.check_node_is_readable_at(
@@ -1427,13 +1473,14 @@ impl<'program> FuncletChecker<'program> {
                     ..
                 }) = &self.node_types[source]
                 else {
-                    panic!(
-                        "Must be a slot {}\n{}",
-                        error_context.debug_node(*source),
-                        error_context
-                    )
+                    return Err(error_context.type_mismatch(
+                        Some(*source),
+                        &format!("Must be a slot {}", error_context.debug_node(*source)),
+                    ));
                 };
-                assert_eq!(*queue_place, ir::Place::Local, "\n{}", error_context);
+                if *queue_place != ir::Place::Local {
+                    return Err(error_context.wrong_place(*source, ir::Place::Local, *queue_place));
+                }
 
                 // Input
                 self.timeline_spec_checker_opt
@@ -1474,11 +1521,10 @@ impl<'program> FuncletChecker<'program> {
                 source,
             } => {
                 let NodeType::LocalVar(LocalVar { .. }) = &self.node_types[source] else {
-                    panic!(
-                        "Must be a local var {}\n{}",
-                        error_context.debug_node(*source),
-                        error_context
-                    )
+                    return Err(error_context.type_mismatch(
+                        Some(*source),
+                        &format!("Must be a local var {}", error_context.debug_node(*source)),
+                    ));
                 };
                 let NodeType::Slot(Slot {
                     queue_place,
@@ -1486,13 +1532,18 @@ impl<'program> FuncletChecker<'program> {
                     ..
                 }) = &self.node_types[destination]
                 else {
-                    panic!(
-                        "Must be a slot {}\n{}",
-                        error_context.debug_node(*destination),
-                        error_context
-                    )
+                    return Err(error_context.type_mismatch(
+                        Some(*destination),
+                        &format!("Must be a slot {}", error_context.debug_node(*destination)),
+                    ));
                 };
-                assert_eq!(*queue_place, ir::Place::Local, "\n{}", error_context);
+                if *queue_place != ir::Place::Local {
+                    return Err(error_context.wrong_place(
+                        *destination,
+                        ir::Place::Local,
+                        *queue_place,
+                    ));
+                }
 
                 // Input
                 self.timeline_spec_checker_opt
@@ -1543,18 +1594,28 @@ impl<'program> FuncletChecker<'program> {
                         error_context
                     );
                 };
-                assert!(
-                    input_buffer_flags.map_read,
-                    "Source of local_copy (node {}) must be marked with map_read\n{}",
-                    error_context.debug_node(*input),
-                    error_context
-                );
-                assert!(
-                    output_buffer_flags.map_write,
-                    "Destination of local_copy (node {}) must be marked with map_write\n{}",
-                    error_context.debug_node(*output),
-                    error_context
-                );
+                if !input_buffer_flags.map_read {
+                    return Err(error_context.buffer_flag_mismatch(
+                        *input,
+                        ir::BufferFlags {
+                            map_read: true,
+                            ..Default::default()
+                        },
+                        *input_buffer_flags,
+                        &"The source of local_copy must be marked with map_read",
+                    ));
+                }
+                if !output_buffer_flags.map_write {
+                    return Err(error_context.buffer_flag_mismatch(
+                        *output,
+                        ir::BufferFlags {
+                            map_write: true,
+                            ..Default::default()
+                        },
+                        *output_buffer_flags,
+                        &"The destination of local_copy must be marked with map_write",
+                    ));
+                }
 
                 advance_forward_value_copy(
                     self.value_spec_checker_opt.as_mut().unwrap(),
@@ -1610,18 +1671,28 @@ impl<'program> FuncletChecker<'program> {
                         error_context
                     );
                 };
-                assert!(
-                    input_buffer_flags.copy_src,
-                    "Source of encode_copy (node {}) must be marked with copy_src\n{}",
-                    error_context.debug_node(*input),
-                    error_context
-                );
-                assert!(
-                    output_buffer_flags.copy_dst,
-                    "Destination of encode_copy (node {}) must be marked with copy_dst\n{}",
-                    error_context.debug_node(*output),
-                    error_context
-                );
+                if !input_buffer_flags.copy_src {
+                    return Err(error_context.buffer_flag_mismatch(
+                        *input,
+                        ir::BufferFlags {
+                            copy_src: true,
+                            ..Default::default()
+                        },
+                        *input_buffer_flags,
+                        &"The source of encode_copy must be marked with copy_src",
+                    ));
+                }
+                if !output_buffer_flags.copy_dst {
+                    return Err(error_context.buffer_flag_mismatch(
+                        *output,
+                        ir::BufferFlags {
+                            copy_dst: true,
+                            ..Default::default()
+                        },
+                        *output_buffer_flags,
+                        &"The destination of encode_copy must be marked with copy_dst",
+                    ));
+                }
 
                 advance_forward_value_copy(
                     self.value_spec_checker_opt.as_mut().unwrap(),
@@ -1749,10 +1820,15 @@ impl<'program> FuncletChecker<'program> {
                 {
                     *queue_place
                 } else {
-                    panic!("Not a fence");
+                    return Err(error_context.type_mismatch(
+                        Some(*fence),
+                        &format!("Node {} is not a fence", error_context.debug_node(*fence)),
+                    ));
                 };
 
-                assert_eq!(fenced_place, ir::Place::Gpu, "\n{}", error_context);
+                if fenced_place != ir::Place::Gpu {
+                    return Err(error_context.wrong_place(*fence, ir::Place::Gpu, fenced_place));
+                }
             }
             ir::Node::StaticSplit {
                 spatial_operation:
@@ -1764,9 +1840,8 @@ impl<'program> FuncletChecker<'program> {
                 place,
             } => {
                 // Temporary restriction
-                match *place {
-                    ir::Place::Local => panic!("Unimplemented"),
-                    _ => {}
+                if *place == ir::Place::Local {
+                    return Err(error_context.wrong_place(current_node_id, ir::Place::Gpu, *place));
                 }
 
                 let spatial_spec_checker = self.spatial_spec_checker_opt.as_mut().unwrap();
@@ -1790,13 +1865,19 @@ impl<'program> FuncletChecker<'program> {
                             },
                             flow: ir::Flow::Usable,
                         },
-                    );
+                    )?;
 
                 let Some(NodeType::Buffer(buffer)) = self.node_types.get_mut(buffer_impl_node_id)
                 else {
                     panic!("{}", error_context);
                 };
-                assert_eq!(buffer.storage_place, *place, "\n{}", error_context);
+                if buffer.storage_place != *place {
+                    return Err(error_context.wrong_place(
+                        *buffer_impl_node_id,
+                        *place,
+                        buffer.storage_place,
+                    ));
+                }
 
                 assert_eq!(sizes.len(), *space_count, "\n{}", error_context);
 
@@ -1812,9 +1893,11 @@ impl<'program> FuncletChecker<'program> {
                         panic!("{}", error_context);
                     };
                     let buffer_flags = buffer.buffer_flags;
-                    let new_static_layout = buffer
-                        .split_static(&self.program.native_interface, error_context, *size)
-                        .unwrap();
+                    let new_static_layout = buffer.split_static(
+                        &self.program.native_interface,
+                        error_context,
+                        *size,
+                    )?;
 
                     self.value_spec_checker_opt
                         .as_mut()
@@ -1892,9 +1975,8 @@ impl<'program> FuncletChecker<'program> {
                 place,
             } => {
                 // Temporary restriction
-                match *place {
-                    ir::Place::Local => panic!("Unimplemented"),
-                    _ => {}
+                if *place == ir::Place::Local {
+                    return Err(error_context.wrong_place(current_node_id, ir::Place::Gpu, *place));
                 }
 
                 assert!(impl_node_ids.len() > 0, "\n{}", error_context);
@@ -1921,7 +2003,7 @@ impl<'program> FuncletChecker<'program> {
                             },
                             flow: ir::Flow::Usable,
                         },
-                    );
+                    )?;
 
                 // Deallocate in reverse order
                 for i in (0..(impl_node_ids.len() - 1)).rev() {
@@ -1937,28 +2019,37 @@ impl<'program> FuncletChecker<'program> {
                         panic!("{}", error_context)
                     };
 
-                    assert_eq!(storage_place, *place, "\n{}", error_context);
+                    if storage_place != *place {
+                        return Err(error_context.wrong_place(impl_node_id, *place, storage_place));
+                    }
 
                     let Some(NodeType::Buffer(buffer)) =
                         self.node_types.get_mut(&buffer_impl_node_id)
                     else {
                         panic!("{}", error_context);
                     };
-                    assert_eq!(buffer_flags, buffer.buffer_flags, "\n{}", error_context);
+                    if buffer_flags != buffer.buffer_flags {
+                        return Err(error_context.buffer_flag_mismatch(
+                            impl_node_id,
+                            buffer.buffer_flags,
+                            buffer_flags,
+                            &format!(
+                                "Merged buffers must have the same flags as {} ({:?})",
+                                error_context.debug_node(buffer_impl_node_id),
+                                buffer.buffer_flags
+                            ),
+                        ));
+                    }
                     buffer.merge_static_left(
                         &self.program.native_interface,
                         error_context,
                         predecessor_static_layout,
                     )?;
 
-                    assert!(
-                        self.value_spec_checker_opt
-                            .as_mut()
-                            .unwrap()
-                            .can_drop_node(impl_node_id),
-                        "\n{}",
-                        error_context
-                    );
+                    self.value_spec_checker_opt
+                        .as_ref()
+                        .unwrap()
+                        .check_can_drop_node(error_context, impl_node_id)?;
                     self.timeline_spec_checker_opt
                         .as_mut()
                         .unwrap()
@@ -1969,7 +2060,7 @@ impl<'program> FuncletChecker<'program> {
                                 quot: ir::Quotient::None,
                                 flow: ir::Flow::Usable,
                             },
-                        );
+                        )?;
                     self.spatial_spec_checker_opt
                         .as_mut()
                         .unwrap()
@@ -1982,7 +2073,7 @@ impl<'program> FuncletChecker<'program> {
                                 },
                                 flow: ir::Flow::Usable,
                             },
-                        );
+                        )?;
                 }
 
                 {
@@ -2021,9 +2112,8 @@ impl<'program> FuncletChecker<'program> {
                 place,
             } => {
                 // Temporary restriction
-                match *place {
-                    ir::Place::Local => panic!("Unimplemented"),
-                    _ => {}
+                if *place == ir::Place::Local {
+                    return Err(error_context.wrong_place(current_node_id, ir::Place::Gpu, *place));
                 }
 
                 let spatial_spec_checker = self.spatial_spec_checker_opt.as_mut().unwrap();
@@ -2102,9 +2192,8 @@ impl<'program> FuncletChecker<'program> {
                 place,
             } => {
                 // Temporary restriction
-                match *place {
-                    ir::Place::Local => panic!("Unimplemented"),
-                    _ => {}
+                if *place == ir::Place::Local {
+                    return Err(error_context.wrong_place(current_node_id, ir::Place::Gpu, *place));
                 }
 
                 assert!(impl_node_ids.len() > 0);
@@ -2180,9 +2269,8 @@ impl<'program> FuncletChecker<'program> {
                 storage_type,
             } => {
                 // Temporary restriction
-                match *place {
-                    ir::Place::Local => panic!("Unimplemented\n{}", error_context),
-                    _ => {}
+                if *place == ir::Place::Local {
+                    return Err(error_context.wrong_place(current_node_id, ir::Place::Gpu, *place));
                 }
 
                 let buffer_spatial_tag =
@@ -2190,7 +2278,13 @@ impl<'program> FuncletChecker<'program> {
                 assert_ne!(buffer_spatial_tag.flow, ir::Flow::Saved); // A continuation must own the space
 
                 if let Some(NodeType::Buffer(buffer)) = self.node_types.get_mut(buffer_node_id) {
-                    assert_eq!(buffer.storage_place, *place, "\n{}", error_context);
+                    if buffer.storage_place != *place {
+                        return Err(error_context.wrong_place(
+                            *buffer_node_id,
+                            *place,
+                            buffer.storage_place,
+                        ));
+                    }
                     let buffer_flags = buffer.buffer_flags;
                     buffer.alloc_static(
                         &self.program.native_interface,
@@ -2202,10 +2296,11 @@ impl<'program> FuncletChecker<'program> {
                         .as_mut()
                         .unwrap()
                         .update_scalar_node(current_node_id, ir::Quotient::None, ir::Flow::Usable);
+                    // Can be used in encoding, like a temporary
                     self.timeline_spec_checker_opt
                         .as_mut()
                         .unwrap()
-                        .update_scalar_node(current_node_id, ir::Quotient::None, ir::Flow::Usable);
+                        .update_node_current_with_implicit(current_node_id);
                     self.spatial_spec_checker_opt
                         .as_mut()
                         .unwrap()
@@ -2308,26 +2403,25 @@ impl<'program> FuncletChecker<'program> {
         let join_funclet_timeline_spec =
             &self.get_funclet_timeline_spec(join_funclet, error_context);
         let join_funclet_spatial_spec = &self.get_funclet_spatial_spec(join_funclet, error_context);
-        let continuation_join_point = &self.node_join_points[&continuation_join_node_id];
-
-        if let Some(NodeType::JoinPoint) = self.node_types.remove(&continuation_join_node_id) {
-            // Nothing, for now...
-        } else {
-            panic!(
-                "Node {} is not a join point\n{}",
-                error_context.debug_node(continuation_join_node_id),
-                error_context
-            )
+        self.take_join_point(error_context, continuation_join_node_id)?;
+
+        if captures.len() > join_funclet.input_types.len() {
+            return Err(error_context.join_arity_mismatch(
+                Some(continuation_join_node_id),
+                join_funclet.input_types.len(),
+                captures.len(),
+                &"The captures of the join",
+            ));
         }
-
         for (capture_index, capture_node_id) in captures.iter().enumerate() {
-            let node_type = self.node_types.remove(capture_node_id).unwrap();
+            let node_type = self.take_node_type(error_context, *capture_node_id)?;
             check_slot_type(
                 &self.program,
                 join_funclet.input_types[capture_index],
+                *capture_node_id,
                 &node_type,
                 error_context,
-            );
+            )?;
         }
 
         let mut remaining_input_types = Vec::<ir::TypeId>::new();
@@ -2335,20 +2429,15 @@ impl<'program> FuncletChecker<'program> {
             remaining_input_types.push(join_funclet.input_types[input_index]);
         }
 
+        let continuation_join_point = &self.node_join_points[&continuation_join_node_id];
         let continuation_join_input_types = &continuation_join_point.input_types;
 
-        for (join_output_index, join_output_type) in join_funclet.output_types.iter().enumerate() {
-            assert_eq!(
-                *join_output_type,
-                continuation_join_input_types[join_output_index],
-                "\n\n{} =/= {}\n\n{}",
-                error_context.debug_info().typ(&join_output_type),
-                error_context
-                    .debug_info()
-                    .typ(&continuation_join_input_types[join_output_index]),
-                error_context,
-            );
-        }
+        self.check_continuation_types(
+            error_context,
+            continuation_join_node_id,
+            &join_funclet.output_types,
+            continuation_join_input_types,
+        )?;
 
         let join_point = JoinPoint {
             join_kind,
@@ -2383,6 +2472,75 @@ impl<'program> FuncletChecker<'program> {
         return Ok(());
     }
 
+    // Moves out of a node that must have been defined and not yet moved out of
+    fn take_node_type(
+        &mut self,
+        error_context: &ErrorContext,
+        node_id: ir::NodeId,
+    ) -> Result<NodeType, Error> {
+        let Some(node_type) = self.node_types.remove(&node_id) else {
+            return Err(error_context.unavailable_node(
+                node_id,
+                &format!(
+                    "Using nonexistent node {}. Was it already used?",
+                    error_context.debug_node(node_id)
+                ),
+            ));
+        };
+        Ok(node_type)
+    }
+
+    fn take_join_point(
+        &mut self,
+        error_context: &ErrorContext,
+        node_id: ir::NodeId,
+    ) -> Result<(), Error> {
+        match self.take_node_type(error_context, node_id)? {
+            NodeType::JoinPoint => Ok(()),
+            _ => Err(error_context.type_mismatch(
+                Some(node_id),
+                &format!(
+                    "Node {} is not a join point",
+                    error_context.debug_node(node_id)
+                ),
+            )),
+        }
+    }
+
+    // Checks that the outputs of a callee, join or choice are what its continuation takes
+    fn check_continuation_types(
+        &self,
+        error_context: &ErrorContext,
+        continuation_node_id: ir::NodeId,
+        output_types: &[ir::TypeId],
+        continuation_input_types: &[ir::TypeId],
+    ) -> Result<(), Error> {
+        if output_types.len() != continuation_input_types.len() {
+            return Err(error_context.join_arity_mismatch(
+                Some(continuation_node_id),
+                continuation_input_types.len(),
+                output_types.len(),
+                &"The callee",
+            ));
+        }
+        for (index, output_type) in output_types.iter().enumerate() {
+            if *output_type != continuation_input_types[index] {
+                return Err(error_context.type_mismatch(
+                    Some(continuation_node_id),
+                    &format!(
+                        "Output {} is a {}, but the continuation takes a {}",
+                        index,
+                        error_context.debug_info().typ(output_type),
+                        error_context
+                            .debug_info()
+                            .typ(&continuation_input_types[index])
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+
     pub fn check_tail_edge(&mut self, error_context: &ErrorContext) -> Result<(), Error> {
         assert_eq!(
             self.current_node_id,
@@ -2392,21 +2550,23 @@ impl<'program> FuncletChecker<'program> {
         );
         match &self.scheduling_funclet.tail_edge {
             ir::TailEdge::Return { return_values } => {
+                if return_values.len() != self.scheduling_funclet.output_types.len() {
+                    return Err(error_context.join_arity_mismatch(
+                        None,
+                        self.scheduling_funclet.output_types.len(),
+                        return_values.len(),
+                        &"The return",
+                    ));
+                }
                 for (return_index, return_node_id) in return_values.iter().enumerate() {
-                    let Some(node_type) = self.node_types.remove(return_node_id) else {
-                        return Err(error_context.generic_error(&format!(
-                            "Returning nonexistent node {}. Was it already used?\n{}",
-                            error_context.debug_node(*return_node_id),
-                            error_context
-                        )));
-                        //return Err(Error::Generic{message: format!("Returning nonexistent node #{}. Was it already used?", return_node_id)});
-                    };
+                    let node_type = self.take_node_type(error_context, *return_node_id)?;
                     check_slot_type(
                         &self.program,
                         self.scheduling_funclet.output_types[return_index],
+                        *return_node_id,
                         &node_type,
                         error_context,
-                    );
+                    )?;
                 }
 
                 self.value_spec_checker_opt
@@ -2423,26 +2583,27 @@ impl<'program> FuncletChecker<'program> {
                     .check_return(error_context, return_values)?;
             }
             ir::TailEdge::Jump { join, arguments } => {
-                if let Some(NodeType::JoinPoint) = self.node_types.remove(join) {
-                    // Nothing, for now...
-                } else {
-                    panic!(
-                        "Node {} is not a join point\n{}",
-                        error_context.debug_node(*join),
-                        error_context
-                    )
+                self.take_join_point(error_context, *join)?;
+
+                let input_types = self.node_join_points[join].input_types.clone();
+                if arguments.len() != input_types.len() {
+                    return Err(error_context.join_arity_mismatch(
+                        Some(*join),
+                        input_types.len(),
+                        arguments.len(),
+                        &format!("The jump to {}", error_context.debug_node(*join)),
+                    ));
                 }
 
-                let join_point = &self.node_join_points[join];
-
                 for (argument_index, argument_node_id) in arguments.iter().enumerate() {
-                    let node_type = self.node_types.remove(argument_node_id).unwrap();
+                    let node_type = self.take_node_type(error_context, *argument_node_id)?;
                     check_slot_type(
                         &self.program,
-                        join_point.input_types[argument_index],
+                        input_types[argument_index],
+                        *argument_node_id,
                         &node_type,
                         error_context,
-                    );
+                    )?;
                 }
 
                 self.value_spec_checker_opt.as_mut().unwrap().check_jump(
@@ -2469,17 +2630,7 @@ impl<'program> FuncletChecker<'program> {
                 continuation_join: continuation_join_node_id,
             } => {
                 let callee_scheduling_funclet_id = *callee_scheduling_funclet_id_ref;
-                let continuation_join_point = &self.node_join_points[continuation_join_node_id];
-
-                if let Some(NodeType::JoinPoint) = self.node_types.remove(continuation_join_node_id)
-                {
-                    // Nothing, for now...
-                } else {
-                    panic!(
-                        "Node {} is not a join point",
-                        error_context.debug_node(*continuation_join_node_id)
-                    )
-                }
+                self.take_join_point(error_context, *continuation_join_node_id)?;
 
                 let callee_funclet = &self.program.funclets[callee_scheduling_funclet_id];
                 assert_eq!(
@@ -2502,11 +2653,11 @@ impl<'program> FuncletChecker<'program> {
                                 spec_checker.check_vertical_call(*continuation_join_node_id, callee_arguments, value_spec, arguments, *value_operation_node_id)
                             }
                             else {
-                                panic!("Not a call")
+                                Err(error_context.type_mismatch(None, &"The value operation of a call must be a call"))
                             }
                         }
                         ir::Quotient::None => spec_checker.check_interior_call(*continuation_join_node_id, callee_arguments, value_spec),
-                        _ => panic!(""),
+                        _ => Err(error_context.type_mismatch(None, &"The value operation of a call must be a node or none")),
                     };
                     e.map_err(|e| self.contextualize_error(e))?;
                 }*/
@@ -2544,31 +2695,33 @@ impl<'program> FuncletChecker<'program> {
                 )?;
 
                 // Step 1: Check current -> callee edge
+                if callee_arguments.len() != callee_funclet.input_types.len() {
+                    return Err(error_context.join_arity_mismatch(
+                        None,
+                        callee_funclet.input_types.len(),
+                        callee_arguments.len(),
+                        &"The call",
+                    ));
+                }
                 for (argument_index, argument_node_id) in callee_arguments.iter().enumerate() {
-                    let node_type = self.node_types.remove(argument_node_id).unwrap();
+                    let node_type = self.take_node_type(error_context, *argument_node_id)?;
                     check_slot_type(
                         &self.program,
                         callee_funclet.input_types[argument_index],
+                        *argument_node_id,
                         &node_type,
                         error_context,
-                    );
+                    )?;
                 }
 
                 // Step 2: Check callee -> continuation edge
-                for (callee_output_index, callee_output_type) in
-                    callee_funclet.output_types.iter().enumerate()
-                {
-                    assert_eq!(
-                        *callee_output_type,
-                        continuation_join_point.input_types[callee_output_index],
-                        "\n\n{} =/= {}\n\n{}",
-                        error_context.debug_info().typ(callee_output_type),
-                        error_context
-                            .debug_info()
-                            .typ(&continuation_join_point.input_types[callee_output_index]),
-                        error_context
-                    );
-                }
+                let continuation_join_point = &self.node_join_points[continuation_join_node_id];
+                self.check_continuation_types(
+                    error_context,
+                    *continuation_join_node_id,
+                    &callee_funclet.output_types,
+                    &continuation_join_point.input_types,
+                )?;
             }
             ir::TailEdge::ScheduleSelect {
                 value_operation:
@@ -2582,17 +2735,7 @@ impl<'program> FuncletChecker<'program> {
                 callee_arguments,
                 continuation_join: continuation_join_node_id,
             } => {
-                let continuation_join_point = &self.node_join_points[continuation_join_node_id];
-
-                if let Some(NodeType::JoinPoint) = self.node_types.remove(continuation_join_node_id)
-                {
-                    // Nothing, for now...
-                } else {
-                    panic!(
-                        "Node {} is not a join point",
-                        error_context.debug_node(*continuation_join_node_id)
-                    )
-                }
+                self.take_join_point(error_context, *continuation_join_node_id)?;
 
                 assert_eq!(callee_funclet_ids.len(), 2, "\n{}", error_context);
                 let true_funclet_id = callee_funclet_ids[0];
@@ -2643,18 +2786,16 @@ impl<'program> FuncletChecker<'program> {
                     error_context
                 );
 
-                assert_eq!(
-                    callee_arguments.len(),
-                    true_funclet.input_types.len(),
-                    "\n{}",
-                    error_context
-                );
-                assert_eq!(
-                    callee_arguments.len(),
-                    false_funclet.input_types.len(),
-                    "\n{}",
-                    error_context
-                );
+                for funclet in [true_funclet, false_funclet] {
+                    if callee_arguments.len() != funclet.input_types.len() {
+                        return Err(error_context.join_arity_mismatch(
+                            None,
+                            funclet.input_types.len(),
+                            callee_arguments.len(),
+                            &"The select",
+                        ));
+                    }
+                }
 
                 if let ir::Node::Select {
                     condition,
@@ -2677,15 +2818,30 @@ impl<'program> FuncletChecker<'program> {
                                 },
                                 flow: ir::Flow::Usable,
                             },
-                        );
+                        )?;
+                    // Every select on the same condition is decided by this choice, so
+                    // a select with multiple results casts each case to its own node
+                    let mut true_remaps = vec![(*true_case, *value_operation_node_id)];
+                    let mut false_remaps = vec![(*false_case, *value_operation_node_id)];
+                    for (node_id, node) in current_value_funclet.nodes.iter().enumerate() {
+                        if let ir::Node::Select {
+                            condition: other_condition,
+                            true_case: other_true_case,
+                            false_case: other_false_case,
+                        } = node
+                        {
+                            if other_condition == condition && node_id != *value_operation_node_id {
+                                true_remaps.push((*other_true_case, node_id));
+                                false_remaps.push((*other_false_case, node_id));
+                            }
+                        }
+                    }
                     self.value_spec_checker_opt.as_mut().unwrap().check_choice(
                         error_context,
                         *continuation_join_node_id,
                         callee_arguments,
-                        &[
-                            &[(*true_case, *value_operation_node_id)],
-                            &[(*false_case, *value_operation_node_id)],
-                        ],
+                        &[&[], &[]],
+                        &[&true_remaps, &false_remaps],
                         &[true_funclet_value_spec, false_funclet_value_spec],
                     )?;
                 } else {
@@ -2705,6 +2861,7 @@ impl<'program> FuncletChecker<'program> {
                         *continuation_join_node_id,
                         callee_arguments,
                         &[&[], &[]],
+                        &[&[], &[]],
                         &[true_funclet_timeline_spec, false_funclet_timeline_spec],
                     )?;
                 self.spatial_spec_checker_opt
@@ -2715,62 +2872,31 @@ impl<'program> FuncletChecker<'program> {
                         *continuation_join_node_id,
                         callee_arguments,
                         &[&[], &[]],
+                        &[&[], &[]],
                         &[true_funclet_spatial_spec, false_funclet_spatial_spec],
                     )?;
 
                 for (argument_index, argument_node_id) in callee_arguments.iter().enumerate() {
-                    let node_type = self.node_types.remove(argument_node_id).unwrap();
-                    check_slot_type(
-                        &self.program,
-                        true_funclet.input_types[argument_index],
-                        &node_type,
-                        error_context,
-                    );
-                    check_slot_type(
-                        &self.program,
-                        false_funclet.input_types[argument_index],
-                        &node_type,
-                        error_context,
-                    );
+                    let node_type = self.take_node_type(error_context, *argument_node_id)?;
+                    for funclet in [true_funclet, false_funclet] {
+                        check_slot_type(
+                            &self.program,
+                            funclet.input_types[argument_index],
+                            *argument_node_id,
+                            &node_type,
+                            error_context,
+                        )?;
+                    }
                 }
 
-                assert_eq!(
-                    true_funclet.output_types.len(),
-                    continuation_join_point.input_types.len(),
-                    "\n{}",
-                    error_context
-                );
-                assert_eq!(
-                    false_funclet.output_types.len(),
-                    continuation_join_point.input_types.len(),
-                    "\n{}",
-                    error_context
-                );
-                for (output_index, _) in true_funclet.output_types.iter().enumerate() {
-                    assert_eq!(
-                        true_funclet.output_types[output_index],
-                        continuation_join_point.input_types[output_index],
-                        "\n\n{} =/= {}\n\n{}",
-                        error_context
-                            .debug_info()
-                            .typ(&true_funclet.output_types[output_index]),
-                        error_context
-                            .debug_info()
-                            .typ(&continuation_join_point.input_types[output_index]),
-                        error_context
-                    );
-                    assert_eq!(
-                        false_funclet.output_types[output_index],
-                        continuation_join_point.input_types[output_index],
-                        "\n\n{} =/= {}\n\n{}",
-                        error_context
-                            .debug_info()
-                            .typ(&false_funclet.output_types[output_index]),
-                        error_context
-                            .debug_info()
-                            .typ(&continuation_join_point.input_types[output_index]),
-                        error_context
-                    );
+                let continuation_join_point = &self.node_join_points[continuation_join_node_id];
+                for funclet in [true_funclet, false_funclet] {
+                    self.check_continuation_types(
+                        error_context,
+                        *continuation_join_node_id,
+                        &funclet.output_types,
+                        &continuation_join_point.input_types,
+                    )?;
                 }
             }
             ir::TailEdge::ScheduleCallYield {
@@ -2834,14 +2960,16 @@ impl<'program> FuncletChecker<'program> {
                 };
 
                 // Step 1: Check current -> callee edge
-                assert_eq!(
-                    effectful_operation.input_types.len(),
-                    yielded_impl_node_ids.len(),
-                    "\n{}",
-                    error_context
-                );
+                if effectful_operation.input_types.len() != yielded_impl_node_ids.len() {
+                    return Err(error_context.join_arity_mismatch(
+                        None,
+                        effectful_operation.input_types.len(),
+                        yielded_impl_node_ids.len(),
+                        &"The yield",
+                    ));
+                }
                 for (argument_index, argument_node_id) in yielded_impl_node_ids.iter().enumerate() {
-                    let node_type = self.node_types.remove(argument_node_id).unwrap();
+                    let node_type = self.take_node_type(error_context, *argument_node_id)?;
                     check_slot_storage_type(
                         &self.program,
                         effectful_operation.input_types[argument_index],
@@ -2850,25 +2978,20 @@ impl<'program> FuncletChecker<'program> {
                 }
 
                 // Check continuation against outputs
-                let continuation_join_point = &self.node_join_points[continuation_impl_node_id];
-                assert_eq!(
-                    continuation_join_point.input_types.len(),
-                    effectful_operation.output_types.len(),
-                    "\n{}",
-                    error_context
-                );
-
-                if let Some(NodeType::JoinPoint) = self.node_types.remove(continuation_impl_node_id)
-                {
-                    // Nothing, for now...
-                } else {
-                    panic!(
-                        "Node {} is not a join point\n{}",
-                        error_context.debug_node(*continuation_impl_node_id),
-                        error_context
-                    )
+                let continuation_input_count = self.node_join_points[continuation_impl_node_id]
+                    .input_types
+                    .len();
+                if continuation_input_count != effectful_operation.output_types.len() {
+                    return Err(error_context.join_arity_mismatch(
+                        Some(*continuation_impl_node_id),
+                        continuation_input_count,
+                        effectful_operation.output_types.len(),
+                        &"The yield",
+                    ));
                 }
 
+                self.take_join_point(error_context, *continuation_impl_node_id)?;
+
                 // Step 2: Check callee -> continuation edge
                 for (callee_output_index, callee_output_type) in
                     effectful_operation.output_types.iter().enumerate()
@@ -2879,17 +3002,240 @@ impl<'program> FuncletChecker<'program> {
                     );*/
                 }
             }
+            ir::TailEdge::DynamicAllocFromBuffer {
+                buffer: buffer_node_id,
+                arguments,
+                dynamic_allocation_size_slots,
+                success_funclet_id,
+                failure_funclet_id,
+                continuation_join: continuation_join_node_id,
+            } => {
+                // The buffer is only allocated from, so it isn't used up
+                // Allocations are checked against what's left in the buffer when the program runs,
+                //   so its static layout doesn't need to fit them
+                let (storage_place, buffer_flags) = match self.node_types.get(buffer_node_id) {
+                    Some(NodeType::Buffer(buffer)) => (buffer.storage_place, buffer.buffer_flags),
+                    _ => {
+                        return Err(error_context.type_mismatch(
+                            Some(*buffer_node_id),
+                            &format!(
+                                "Node {} is not a buffer",
+                                error_context.debug_node(*buffer_node_id)
+                            ),
+                        ))
+                    }
+                };
+                // Temporary restriction
+                if storage_place != ir::Place::Gpu {
+                    return Err(error_context.wrong_place(
+                        *buffer_node_id,
+                        ir::Place::Gpu,
+                        storage_place,
+                    ));
+                }
+
+                let buffer_spatial_tag =
+                    self.spatial_spec_checker_opt.as_mut().unwrap().scalar_nodes[buffer_node_id];
+                assert_ne!(buffer_spatial_tag.flow, ir::Flow::Saved); // A continuation must own the space
+
+                for size_slot_node_id in dynamic_allocation_size_slots.iter().flatten() {
+                    match self.node_types.get(size_slot_node_id) {
+                        Some(NodeType::LocalVar(_)) => (),
+                        _ => {
+                            return Err(error_context.type_mismatch(
+                                Some(*size_slot_node_id),
+                                &format!(
+                                    "Allocation size {} is not a local variable",
+                                    error_context.debug_node(*size_slot_node_id)
+                                ),
+                            ))
+                        }
+                    }
+                    self.value_spec_checker_opt
+                        .as_ref()
+                        .unwrap()
+                        .check_node_is_readable(error_context, *size_slot_node_id)?;
+                }
+
+                let success_funclet = &self.program.funclets[*success_funclet_id];
+                let failure_funclet = &self.program.funclets[*failure_funclet_id];
+                let allocation_count = dynamic_allocation_size_slots.len();
+                if success_funclet.input_types.len() != arguments.len() + allocation_count {
+                    return Err(error_context.join_arity_mismatch(
+                        None,
+                        success_funclet.input_types.len(),
+                        arguments.len() + allocation_count,
+                        &"The successful allocation",
+                    ));
+                }
+                if failure_funclet.input_types.len() != arguments.len() {
+                    return Err(error_context.join_arity_mismatch(
+                        None,
+                        failure_funclet.input_types.len(),
+                        arguments.len(),
+                        &"The failed allocation",
+                    ));
+                }
+
+                // Slots allocate an array of elements and the rest allocate a single element
+                for (allocation_index, (size_slot_opt, type_id)) in dynamic_allocation_size_slots
+                    .iter()
+                    .zip(success_funclet.input_types[arguments.len()..].iter())
+                    .enumerate()
+                {
+                    let type_name = error_context.debug_info().typ(type_id);
+                    let ir::Type::Ref {
+                        storage_type,
+                        storage_place: allocation_place,
+                        buffer_flags: allocation_flags,
+                    } = &self.program.types[*type_id]
+                    else {
+                        return Err(error_context.type_mismatch(
+                            None,
+                            &format!(
+                                "Allocation {} is a {}, which is not a ref type",
+                                allocation_index, type_name
+                            ),
+                        ));
+                    };
+                    if *allocation_place != storage_place {
+                        return Err(error_context.wrong_place(
+                            *buffer_node_id,
+                            *allocation_place,
+                            storage_place,
+                        ));
+                    }
+                    if *allocation_flags != buffer_flags {
+                        return Err(error_context.buffer_flag_mismatch(
+                            *buffer_node_id,
+                            *allocation_flags,
+                            buffer_flags,
+                            &format!(
+                                "Allocation {} is a {} with flags {:?}",
+                                allocation_index, type_name, allocation_flags
+                            ),
+                        ));
+                    }
+                    let is_array = matches!(
+                        &self.program.native_interface.types[storage_type.0],
+                        ir::ffi::Type::ErasedLengthArray { .. }
+                    );
+                    if is_array != size_slot_opt.is_some() {
+                        return Err(error_context.type_mismatch(
+                            size_slot_opt.clone(),
+                            &format!(
+                                "Allocation {} is a {}, but {}",
+                                allocation_index,
+                                type_name,
+                                if is_array {
+                                    "has no size"
+                                } else {
+                                    "is given a size"
+                                }
+                            ),
+                        ));
+                    }
+                }
+
+                self.take_join_point(error_context, *continuation_join_node_id)?;
+
+                let success_funclet_value_spec =
+                    self.get_funclet_value_spec(success_funclet, error_context);
+                let success_funclet_timeline_spec =
+                    self.get_funclet_timeline_spec(success_funclet, error_context);
+                let success_funclet_spatial_spec =
+                    self.get_funclet_spatial_spec(success_funclet, error_context);
+                let failure_funclet_value_spec =
+                    self.get_funclet_value_spec(failure_funclet, error_context);
+                let failure_funclet_timeline_spec =
+                    self.get_funclet_timeline_spec(failure_funclet, error_context);
+                let failure_funclet_spatial_spec =
+                    self.get_funclet_spatial_spec(failure_funclet, error_context);
+                for value_spec in [success_funclet_value_spec, failure_funclet_value_spec] {
+                    assert_eq!(
+                        self.value_funclet_id,
+                        value_spec.funclet_id_opt.unwrap(),
+                        "\n\n{} =/= {}\n\n{}",
+                        error_context.debug_info().funclet(&self.value_funclet_id),
+                        error_context
+                            .debug_info()
+                            .funclet(&value_spec.funclet_id_opt.unwrap()),
+                        error_context
+                    );
+                }
+
+                // The allocations are tagged like static allocations from the same buffer
+                let value_allocation_tags = vec![
+                    ir::Tag {
+                        quot: ir::Quotient::None,
+                        flow: ir::Flow::Usable,
+                    };
+                    allocation_count
+                ];
+                self.value_spec_checker_opt.as_mut().unwrap().check_choice(
+                    error_context,
+                    *continuation_join_node_id,
+                    arguments,
+                    &[&value_allocation_tags, &[]],
+                    &[&[], &[]],
+                    &[success_funclet_value_spec, failure_funclet_value_spec],
+                )?;
+                let timeline_spec_checker = self.timeline_spec_checker_opt.as_mut().unwrap();
+                let timeline_allocation_tags =
+                    vec![timeline_spec_checker.current_implicit_tag; allocation_count];
+                timeline_spec_checker.check_choice(
+                    error_context,
+                    *continuation_join_node_id,
+                    arguments,
+                    &[&timeline_allocation_tags, &[]],
+                    &[&[], &[]],
+                    &[success_funclet_timeline_spec, failure_funclet_timeline_spec],
+                )?;
+                let spatial_allocation_tags = vec![
+                    ir::Tag {
+                        quot: buffer_spatial_tag.quot,
+                        flow: ir::Flow::Saved,
+                    };
+                    allocation_count
+                ];
+                self.spatial_spec_checker_opt
+                    .as_mut()
+                    .unwrap()
+                    .check_choice(
+                        error_context,
+                        *continuation_join_node_id,
+                        arguments,
+                        &[&spatial_allocation_tags, &[]],
+                        &[&[], &[]],
+                        &[success_funclet_spatial_spec, failure_funclet_spatial_spec],
+                    )?;
+
+                for (argument_index, argument_node_id) in arguments.iter().enumerate() {
+                    let node_type = self.take_node_type(error_context, *argument_node_id)?;
+                    for funclet in [success_funclet, failure_funclet] {
+                        check_slot_type(
+                            &self.program,
+                            funclet.input_types[argument_index],
+                            *argument_node_id,
+                            &node_type,
+                            error_context,
+                        )?;
+                    }
+                }
+
+                let continuation_join_point = &self.node_join_points[continuation_join_node_id];
+                for funclet in [success_funclet, failure_funclet] {
+                    self.check_continuation_types(
+                        error_context,
+                        *continuation_join_node_id,
+                        &funclet.output_types,
+                        &continuation_join_point.input_types,
+                    )?;
+                }
+            }
             ir::TailEdge::DebugHole { inputs } => {
                 for input in inputs.iter() {
-                    if let Some(_) = self.node_types.remove(input) {
-                        // Nothing, for now...
-                    } else {
-                        panic!(
-                            "Node {} does not exist\n{}",
-                            error_context.debug_node(*input),
-                            error_context
-                        )
-                    }
+                    self.take_node_type(error_context, *input)?;
                 }
             }
             _ => panic!("Unimplemented\n{}", error_context),
@@ -2897,12 +3243,9 @@ impl<'program> FuncletChecker<'program> {
 
         // Enforce use of all nodes
         for (node_id, node_type) in self.node_types.iter() {
-            assert!(
-                self.can_drop_node(*node_id) || self.is_neutral_node(*node_id),
-                "node {}\n{}",
-                error_context.debug_node(*node_id),
-                error_context
-            );
+            if !(self.can_drop_node(*node_id) || self.is_neutral_node(*node_id)) {
+                return Err(self.undroppable_node_error(error_context, *node_id));
+            }
             //self.drop_node(*dropped_node_id)
         }
 
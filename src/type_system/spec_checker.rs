@@ -1,30 +1,10 @@
 use itertools::Itertools;
 
-use super::error::{Error, ErrorContext};
+use super::error::{Error, ErrorContext, TagSubject};
 use crate::debug_info::DebugInfo;
 use crate::ir;
 use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::default::Default;
-#[macro_use]
-use crate::type_system::error::error_ifn_eq;
-
-/*macro_rules! error_ifn_eq {
-    ($ctx:expr, $left:expr, $right:expr $(,)?) => {
-        match (&$left, &$right) {
-            (left_val, right_val) => {
-                if !(*left_val == *right_val) {
-                    Result::<(), Error>::Err(assert_failed($ctx, &*left_val, &*right_val))
-                } else {
-                    Result::<(), Error>::Ok(())
-                }
-            }
-        }
-    };
-}*/
-
-fn assert_failed<T: std::fmt::Debug>(error_context: &ErrorContext, a: T, b: T) -> Error {
-    error_context.generic_error(&format!("{:?} != {:?}", a, b))
-}
 
 // Language independent plumbing for type checking
 
@@ -56,7 +36,7 @@ pub struct FuncletSpecChecker<'program> {
     //current_node_id: ir::NodeId,
     pub current_implicit_tag: ir::Tag,
     pub language_string: &'static str,
-    debug_info: &'program DebugInfo
+    debug_info: &'program DebugInfo,
 }
 
 //{tags : &[ir::Tag], flows : &[ir::Flow], implicit_tag : ir::Tag, stage : bool},
@@ -143,7 +123,7 @@ impl<'program> FuncletSpecChecker<'program> {
             //current_node_id: 0,
             current_implicit_tag: funclet_spec.implicit_in_tag,
             language_string,
-            debug_info
+            debug_info,
         };
         state.initialize();
         state
@@ -180,7 +160,7 @@ impl<'program> FuncletSpecChecker<'program> {
 
     fn contextualize_error(&self, writer: &mut dyn std::fmt::Write) -> Result<(), std::fmt::Error> {
         write!(writer, "Checking {} spec funclet\nSpec {}\nSpec Funclet {}\nScalar Nodes {:?}\nJoin Nodes {:?}\nImplicit Tag {}\n",
-        self.language_string, 
+        self.language_string,
         self.debug_info.spec(self.funclet_spec),
         self.debug_info.funclet(&self.funclet_id),
         self.scalar_nodes.iter().map(|n| self.debug_info.tag(&self.funclet_id, &n.1)).collect_vec(),
@@ -207,9 +187,9 @@ impl<'program> FuncletSpecChecker<'program> {
     ) -> Result<(), Error> {
         let continuation_join = self.join_nodes.remove(&continuation_node_id).unwrap();
 
-        check_tag_compatibility_interior(
+        self.check_tag_compatibility_interior(
             error_context,
-            self.spec_funclet,
+            TagSubject::Implicit,
             funclet_spec.implicit_out_tag,
             continuation_join.implicit_tag,
         )?;
@@ -219,20 +199,22 @@ impl<'program> FuncletSpecChecker<'program> {
 
             match scalar.flow {
                 ir::Flow::Usable => (), // Can borrow
-                _ => panic!(
-                    "Capturing {:?} is unsupported\n{}",
-                    scalar.flow, error_context
-                ),
+                _ => {
+                    return Err(self.tag_mismatch(
+                        error_context,
+                        TagSubject::Node(*capture_node_id),
+                        ir::Tag {
+                            quot: scalar.quot,
+                            flow: ir::Flow::Usable,
+                        },
+                        *scalar,
+                    ))
+                }
             }
-            assert_eq!(
-                funclet_spec.input_tags[capture_index].flow, scalar.flow,
-                "\n{}",
-                error_context
-            );
 
-            check_tag_compatibility_interior(
+            self.check_tag_compatibility_interior(
                 error_context,
-                self.spec_funclet,
+                TagSubject::Node(*capture_node_id),
                 *scalar,
                 funclet_spec.input_tags[capture_index],
             )?;
@@ -247,19 +229,21 @@ impl<'program> FuncletSpecChecker<'program> {
             remaining_input_tags.push(funclet_spec.input_tags[index]);
         }
 
-        assert_eq!(
-            funclet_spec.output_tags.len(),
-            continuation_join.input_tags.len(),
-            "\n{}",
-            error_context
-        );
+        if funclet_spec.output_tags.len() != continuation_join.input_tags.len() {
+            return Err(error_context.join_arity_mismatch(
+                Some(continuation_node_id),
+                continuation_join.input_tags.len(),
+                funclet_spec.output_tags.len(),
+                &"The joined funclet",
+            ));
+        }
         for index in 0..funclet_spec.output_tags.len() {
             //assert_eq!(funclet_spec.output_tags[index].flow, ir::Flow::Have, "\n{}", error_context);
             //assert_eq!(continuation_join.input_tags[index].flow, ir::Flow::Have, "\n{}", error_context);
 
-            check_tag_compatibility_interior(
+            self.check_tag_compatibility_interior(
                 error_context,
-                self.spec_funclet,
+                TagSubject::Output(index),
                 funclet_spec.output_tags[index],
                 continuation_join.input_tags[index],
             )?;
@@ -300,32 +284,39 @@ impl<'program> FuncletSpecChecker<'program> {
             old_error_context.funclet_id(),
         );
 
-        check_tag_compatibility_interior(
+        self.check_tag_compatibility_interior(
             error_context,
-            self.spec_funclet,
+            TagSubject::Implicit,
             self.current_implicit_tag,
             continuation_join.implicit_tag,
         )?;
 
-        assert_eq!(
-            argument_node_ids.len(),
-            continuation_join.input_tags.len(),
-            "\n{}",
-            error_context
-        );
+        if argument_node_ids.len() != continuation_join.input_tags.len() {
+            return Err(error_context.join_arity_mismatch(
+                Some(continuation_node_id),
+                continuation_join.input_tags.len(),
+                argument_node_ids.len(),
+                &format!(
+                    "The jump to {}",
+                    error_context.debug_node(continuation_node_id)
+                ),
+            ));
+        }
         for index in 0..argument_node_ids.len() {
             let Some(scalar) = self.scalar_nodes.get(&argument_node_ids[index]) else {
-                panic!(
-                    "Jump input {}, impl node {} has no tag for spec\n{}",
-                    error_context.debug_node(index),
-                    error_context.debug_node(argument_node_ids[index]),
-                    error_context
-                )
+                return Err(error_context.unavailable_node(
+                    argument_node_ids[index],
+                    &format!(
+                        "Jump input {}, impl node {} has no tag for spec",
+                        index,
+                        error_context.debug_node(argument_node_ids[index])
+                    ),
+                ));
             };
 
-            check_tag_compatibility_interior(
+            self.check_tag_compatibility_interior(
                 error_context,
-                self.spec_funclet,
+                TagSubject::Node(argument_node_ids[index]),
                 *scalar,
                 continuation_join.input_tags[index],
             )?;
@@ -348,26 +339,28 @@ impl<'program> FuncletSpecChecker<'program> {
             old_error_context.funclet_id(),
         );
 
-        check_tag_compatibility_interior(
+        self.check_tag_compatibility_interior(
             &return_error_context,
-            self.spec_funclet,
+            TagSubject::Implicit,
             self.current_implicit_tag,
             self.funclet_spec.implicit_out_tag,
         )?;
 
-        assert_eq!(
-            return_value_node_ids.len(),
-            self.funclet_spec.output_tags.len(),
-            "\n{}",
-            old_error_context
-        );
+        if return_value_node_ids.len() != self.funclet_spec.output_tags.len() {
+            return Err(old_error_context.join_arity_mismatch(
+                None,
+                self.funclet_spec.output_tags.len(),
+                return_value_node_ids.len(),
+                &"The return",
+            ));
+        }
         for index in 0..return_value_node_ids.len() {
-            let scalar = &self.scalar_nodes[&return_value_node_ids[index]];
+            let scalar = self.scalar_tag(&return_error_context, return_value_node_ids[index])?;
 
-            check_tag_compatibility_interior(
+            self.check_tag_compatibility_interior(
                 &return_error_context,
-                self.spec_funclet,
-                *scalar,
+                TagSubject::Node(return_value_node_ids[index]),
+                scalar,
                 self.funclet_spec.output_tags[index],
             )?;
         }
@@ -382,48 +375,52 @@ impl<'program> FuncletSpecChecker<'program> {
         argument_node_ids: &[ir::NodeId],
         callee_funclet_spec: &ir::FuncletSpec,
     ) -> Result<(), Error> {
-        check_tag_compatibility_interior(
+        self.check_tag_compatibility_interior(
             error_context,
-            self.spec_funclet,
+            TagSubject::Implicit,
             self.current_implicit_tag,
             callee_funclet_spec.implicit_in_tag,
         )?;
 
         let continuation_join = self.join_nodes.remove(&continuation_node_id).unwrap();
-        check_tag_compatibility_interior(
+        self.check_tag_compatibility_interior(
             error_context,
-            self.spec_funclet,
+            TagSubject::Implicit,
             callee_funclet_spec.implicit_out_tag,
             continuation_join.implicit_tag,
         )?;
 
-        assert_eq!(
-            argument_node_ids.len(),
-            callee_funclet_spec.input_tags.len(),
-            "\n{}",
-            error_context
-        );
+        if argument_node_ids.len() != callee_funclet_spec.input_tags.len() {
+            return Err(error_context.join_arity_mismatch(
+                None,
+                callee_funclet_spec.input_tags.len(),
+                argument_node_ids.len(),
+                &"The call",
+            ));
+        }
         for index in 0..argument_node_ids.len() {
-            let scalar = &self.scalar_nodes[&argument_node_ids[index]];
+            let scalar = self.scalar_tag(error_context, argument_node_ids[index])?;
 
-            check_tag_compatibility_interior(
+            self.check_tag_compatibility_interior(
                 error_context,
-                self.spec_funclet,
-                *scalar,
+                TagSubject::Node(argument_node_ids[index]),
+                scalar,
                 callee_funclet_spec.input_tags[index],
             )?;
         }
 
-        assert_eq!(
-            continuation_join.input_tags.len(),
-            callee_funclet_spec.output_tags.len(),
-            "\n{}",
-            error_context
-        );
+        if continuation_join.input_tags.len() != callee_funclet_spec.output_tags.len() {
+            return Err(error_context.join_arity_mismatch(
+                Some(continuation_node_id),
+                continuation_join.input_tags.len(),
+                callee_funclet_spec.output_tags.len(),
+                &"The callee",
+            ));
+        }
         for index in 0..callee_funclet_spec.output_tags.len() {
-            check_tag_compatibility_interior(
+            self.check_tag_compatibility_interior(
                 error_context,
-                self.spec_funclet,
+                TagSubject::Output(index),
                 callee_funclet_spec.output_tags[index],
                 continuation_join.input_tags[index],
             )?;
@@ -441,49 +438,55 @@ impl<'program> FuncletSpecChecker<'program> {
         output_spec_node_ids: &[ir::NodeId],
         call_spec_node_id: ir::NodeId,
     ) -> Result<(), Error> {
-        check_tag_compatibility_enter(
+        self.check_tag_compatibility_enter(
             error_context,
+            TagSubject::Implicit,
             output_spec_node_ids,
             self.current_implicit_tag,
             callee_funclet_spec.implicit_in_tag,
         )?;
 
         let continuation_join = self.join_nodes.remove(&continuation_node_id).unwrap();
-        check_tag_compatibility_exit(
+        self.check_tag_compatibility_exit(
             error_context,
-            self.spec_funclet,
+            TagSubject::Implicit,
             call_spec_node_id,
             callee_funclet_spec.implicit_out_tag,
             continuation_join.implicit_tag,
         )?;
 
-        assert_eq!(
-            input_impl_node_ids.len(),
-            callee_funclet_spec.input_tags.len(),
-            "\n{}",
-            error_context
-        );
+        if input_impl_node_ids.len() != callee_funclet_spec.input_tags.len() {
+            return Err(error_context.join_arity_mismatch(
+                None,
+                callee_funclet_spec.input_tags.len(),
+                input_impl_node_ids.len(),
+                &"The call",
+            ));
+        }
         for index in 0..input_impl_node_ids.len() {
-            let scalar = &self.scalar_nodes[&input_impl_node_ids[index]];
+            let scalar = self.scalar_tag(error_context, input_impl_node_ids[index])?;
 
-            check_tag_compatibility_enter(
+            self.check_tag_compatibility_enter(
                 error_context,
+                TagSubject::Node(input_impl_node_ids[index]),
                 output_spec_node_ids,
-                *scalar,
+                scalar,
                 callee_funclet_spec.input_tags[index],
             )?;
         }
 
-        assert_eq!(
-            continuation_join.input_tags.len(),
-            callee_funclet_spec.output_tags.len(),
-            "\n{}",
-            error_context
-        );
+        if continuation_join.input_tags.len() != callee_funclet_spec.output_tags.len() {
+            return Err(error_context.join_arity_mismatch(
+                Some(continuation_node_id),
+                continuation_join.input_tags.len(),
+                callee_funclet_spec.output_tags.len(),
+                &"The callee",
+            ));
+        }
         for index in 0..callee_funclet_spec.output_tags.len() {
-            check_tag_compatibility_exit(
+            self.check_tag_compatibility_exit(
                 error_context,
-                self.spec_funclet,
+                TagSubject::Output(index),
                 call_spec_node_id,
                 callee_funclet_spec.output_tags[index],
                 continuation_join.input_tags[index],
@@ -561,11 +564,14 @@ impl<'program> FuncletSpecChecker<'program> {
         }
     }
 
+    // Each case of the choice takes the input nodes followed by inputs with the tags in
+    //   choice_extra_input_tags, like the allocations of a dynamic allocation
     pub fn check_choice(
         &mut self,
         old_error_context: &ErrorContext,
         continuation_impl_node_id: ir::NodeId,
         input_impl_node_ids: &[ir::NodeId],
+        choice_extra_input_tags: &[&[ir::Tag]],
         choice_remaps: &[&[(ir::NodeId, ir::NodeId)]],
         choice_specs: &[&ir::FuncletSpec],
     ) -> Result<(), Error> {
@@ -596,53 +602,74 @@ impl<'program> FuncletSpecChecker<'program> {
             "\n{}",
             error_context
         );
+        assert_eq!(
+            choice_extra_input_tags.len(),
+            choice_specs.len(),
+            "\n{}",
+            error_context
+        );
         for choice_index in 0..choice_specs.len() {
             let choice_spec = &choice_specs[choice_index];
             let choice_remap = choice_remaps[choice_index];
+            let extra_input_tags = choice_extra_input_tags[choice_index];
             //
-            check_tag_compatibility_interior(
+            self.check_tag_compatibility_interior(
                 error_context,
-                self.spec_funclet,
+                TagSubject::Implicit,
                 self.current_implicit_tag,
                 choice_spec.implicit_in_tag,
             )?;
 
-            assert_eq!(
-                input_impl_node_ids.len(),
-                choice_spec.input_tags.len(),
-                "\n{}",
-                error_context
-            );
+            let input_count = input_impl_node_ids.len() + extra_input_tags.len();
+            if input_count != choice_spec.input_tags.len() {
+                return Err(error_context.join_arity_mismatch(
+                    None,
+                    choice_spec.input_tags.len(),
+                    input_count,
+                    &format!("Case {} of the choice", choice_index),
+                ));
+            }
             for index in 0..input_impl_node_ids.len() {
-                let scalar = &self.scalar_nodes[&input_impl_node_ids[index]];
+                let scalar = self.scalar_tag(error_context, input_impl_node_ids[index])?;
 
-                check_tag_compatibility_interior(
+                self.check_tag_compatibility_interior(
                     error_context,
-                    self.spec_funclet,
-                    *scalar,
+                    TagSubject::Node(input_impl_node_ids[index]),
+                    scalar,
+                    choice_spec.input_tags[index],
+                )?;
+            }
+            for (extra_index, extra_input_tag) in extra_input_tags.iter().enumerate() {
+                let index = input_impl_node_ids.len() + extra_index;
+                self.check_tag_compatibility_interior(
+                    error_context,
+                    TagSubject::Input(index),
+                    *extra_input_tag,
                     choice_spec.input_tags[index],
                 )?;
             }
 
             // Continuation gets the unified result
-            check_tag_compatibility_interior_cast(
+            self.check_tag_compatibility_interior_cast(
                 error_context,
-                self.spec_funclet,
+                TagSubject::Implicit,
                 choice_spec.implicit_out_tag,
                 continuation_join.implicit_tag,
                 choice_remap,
             )?;
 
-            assert_eq!(
-                continuation_join.input_tags.len(),
-                choice_spec.output_tags.len(),
-                "\n{}",
-                error_context
-            );
+            if continuation_join.input_tags.len() != choice_spec.output_tags.len() {
+                return Err(error_context.join_arity_mismatch(
+                    Some(continuation_impl_node_id),
+                    continuation_join.input_tags.len(),
+                    choice_spec.output_tags.len(),
+                    &format!("Case {} of the choice", choice_index),
+                ));
+            }
             for index in 0..choice_spec.output_tags.len() {
-                check_tag_compatibility_interior_cast(
+                self.check_tag_compatibility_interior_cast(
                     error_context,
-                    self.spec_funclet,
+                    TagSubject::Output(index),
                     choice_spec.output_tags[index],
                     continuation_join.input_tags[index],
                     choice_remap,
@@ -653,6 +680,23 @@ impl<'program> FuncletSpecChecker<'program> {
         return Ok(());
     }
 
+    fn scalar_tag(
+        &self,
+        error_context: &ErrorContext,
+        node_id: ir::NodeId,
+    ) -> Result<ir::Tag, Error> {
+        match self.scalar_nodes.get(&node_id) {
+            Some(scalar) => Ok(*scalar),
+            None => Err(error_context.unavailable_node(
+                node_id,
+                &format!(
+                    "Impl node {} has no tag for this spec",
+                    error_context.debug_node(node_id)
+                ),
+            )),
+        }
+    }
+
     // To do: Join
     pub fn can_drop_node(&self, impl_node_id: ir::NodeId) -> bool {
         if let Some(scalar) = &self.scalar_nodes.get(&impl_node_id) {
@@ -687,9 +731,9 @@ impl<'program> FuncletSpecChecker<'program> {
         );
 
         //assert_eq!(tag, self.current_implicit_tag);
-        check_tag_compatibility_interior(
+        self.check_tag_compatibility_interior(
             error_context,
-            self.spec_funclet,
+            TagSubject::Implicit,
             self.current_implicit_tag,
             tag,
         )?; //.map_err(|e| e.append_message(format!("While checking that the implicit tag {:?} is compatible with {:?}", self.current_implicit_tag, tag)))?;
@@ -711,15 +755,14 @@ impl<'program> FuncletSpecChecker<'program> {
             old_error_context.funclet_id(),
         );
 
-        let Some(scalar) = self.scalar_nodes.get(&node_id) else {
-            panic!(
-                "Impl node {} has no tag for this spec\n{}",
-                error_context.debug_node(node_id),
-                error_context
-            )
-        };
+        let scalar = self.scalar_tag(error_context, node_id)?;
         //assert_eq!(*scalar, tag);
-        check_tag_compatibility_interior(error_context, self.spec_funclet, *scalar, tag)?;
+        self.check_tag_compatibility_interior(
+            error_context,
+            TagSubject::Node(node_id),
+            scalar,
+            tag,
+        )?;
         /* .map_err(
             |e| {
                 e.append_message(format!(
@@ -748,6 +791,77 @@ impl<'program> FuncletSpecChecker<'program> {
         self.check_node_tag(error_context, node_id, self.current_implicit_tag)
     }
 
+    // A node can be read where `reader_tag` is current if its flow allows reading at all
+    fn check_readable(
+        &self,
+        error_context: &ErrorContext,
+        node_id: ir::NodeId,
+        scalar: ir::Tag,
+        reader_tag: ir::Tag,
+    ) -> Result<(), Error> {
+        if scalar.flow.is_readable() {
+            return Ok(());
+        }
+        let expected = ir::Tag {
+            quot: reader_tag.quot,
+            flow: ir::Flow::Usable,
+        };
+        Err(self.tag_mismatch(error_context, TagSubject::Node(node_id), expected, scalar))
+    }
+
+    // Checks that a node holds a value that can be read, and returns its tag
+    pub fn check_node_is_readable(
+        &self,
+        error_context: &ErrorContext,
+        node_id: ir::NodeId,
+    ) -> Result<ir::Tag, Error> {
+        let scalar = self.scalar_tag(error_context, node_id)?;
+        self.check_readable(error_context, node_id, scalar, scalar)?;
+        Ok(scalar)
+    }
+
+    // Checks that a node is readable and stands for `spec_node_id`, and returns its tag
+    pub fn check_node_is_readable_as(
+        &self,
+        error_context: &ErrorContext,
+        node_id: ir::NodeId,
+        spec_node_id: ir::NodeId,
+    ) -> Result<ir::Tag, Error> {
+        let scalar = self.check_node_is_readable(error_context, node_id)?;
+        let expected = ir::Tag {
+            quot: ir::Quotient::Node {
+                node_id: spec_node_id,
+            },
+            flow: scalar.flow,
+        };
+        if scalar.quot != expected.quot {
+            return Err(self.tag_mismatch(
+                error_context,
+                TagSubject::Node(node_id),
+                expected,
+                scalar,
+            ));
+        }
+        Ok(scalar)
+    }
+
+    // Checks that whatever a node holds can be discarded, to drop or overwrite it
+    pub fn check_can_drop_node(
+        &self,
+        error_context: &ErrorContext,
+        node_id: ir::NodeId,
+    ) -> Result<(), Error> {
+        if self.can_drop_node(node_id) {
+            return Ok(());
+        }
+        let scalar = self.scalar_nodes[&node_id];
+        let expected = ir::Tag {
+            quot: scalar.quot,
+            flow: ir::Flow::Usable,
+        };
+        Err(self.tag_mismatch(error_context, TagSubject::Node(node_id), expected, scalar))
+    }
+
     pub fn check_node_is_readable_at_implicit(
         &self,
         old_error_context: &ErrorContext,
@@ -761,14 +875,24 @@ impl<'program> FuncletSpecChecker<'program> {
             old_error_context.funclet_id(),
         );
 
-        let scalar = self.scalar_nodes.get(&node_id).unwrap();
-        assert!(scalar.flow.is_readable(), "{}", error_context);
+        let scalar = self.scalar_tag(error_context, node_id)?;
+        self.check_readable(error_context, node_id, scalar, self.current_implicit_tag)?;
+        if scalar.flow == ir::Flow::Saved {
+            // Saved nodes are storage borrowed from some space,
+            //   and reading them doesn't touch that space
+            return Ok(());
+        }
         let tag = ir::Tag {
             quot: self.current_implicit_tag.quot,
             flow: scalar.flow,
         };
         //assert_eq!(*scalar, tag);
-        check_tag_compatibility_interior(error_context, self.spec_funclet, *scalar, tag)?;
+        self.check_tag_compatibility_interior(
+            error_context,
+            TagSubject::Node(node_id),
+            scalar,
+            tag,
+        )?;
         /*.map_err(
             |e| {
                 e.append_message(format!(
@@ -795,15 +919,23 @@ impl<'program> FuncletSpecChecker<'program> {
             old_error_context.funclet_id(),
         );
 
-        let scalar = self.scalar_nodes.get(&node_id).unwrap();
-        assert!(scalar.flow.is_readable(), "{}", error_context);
+        let scalar = self.scalar_tag(error_context, node_id)?;
+        self.check_readable(error_context, node_id, scalar, reader_tag)?;
         assert_eq!(reader_tag.flow, ir::Flow::Usable, "\n{}", error_context);
+        if scalar.flow == ir::Flow::Saved {
+            return Ok(());
+        }
         let tag = ir::Tag {
             quot: reader_tag.quot,
             flow: scalar.flow,
         };
         //assert_eq!(*scalar, tag);
-        check_tag_compatibility_interior(error_context, self.spec_funclet, *scalar, tag)?;
+        self.check_tag_compatibility_interior(
+            error_context,
+            TagSubject::Node(node_id),
+            scalar,
+            tag,
+        )?;
         /*.map_err(
             |e| {
                 e.append_message(format!(
@@ -840,11 +972,19 @@ impl<'program> FuncletSpecChecker<'program> {
         );
 
         let join = self.join_nodes.get(&node_id).unwrap();
+        if input_tags.len() != join.input_tags.len() {
+            return Err(error_context.join_arity_mismatch(
+                Some(node_id),
+                join.input_tags.len(),
+                input_tags.len(),
+                &"The yield",
+            ));
+        }
         //assert_eq!(*scalar, tag);
         for index in 0..join.input_tags.len() {
-            check_tag_compatibility_interior(
+            self.check_tag_compatibility_interior(
                 error_context,
-                self.spec_funclet,
+                TagSubject::Input(index),
                 input_tags[index],
                 join.input_tags[index],
             )?;
@@ -856,9 +996,9 @@ impl<'program> FuncletSpecChecker<'program> {
             })?;*/
         }
 
-        check_tag_compatibility_interior(
+        self.check_tag_compatibility_interior(
             error_context,
-            self.spec_funclet,
+            TagSubject::Implicit,
             implicit_in_tag,
             join.implicit_tag,
         )?;
@@ -1034,239 +1174,193 @@ fn concretize_input_to_internal_tag(tag: ir::Tag) -> ir::Tag {
     }
 }
 
-fn check_tag_compatibility_enter(
-    error_context: &ErrorContext,
-    input_spec_node_ids: &[ir::NodeId],
-    caller_tag: ir::Tag,
-    callee_tag: ir::Tag,
-) -> Result<(), Error> {
-    assert_eq!(caller_tag.flow, callee_tag.flow, "\n{}", error_context);
-    match (caller_tag.quot, callee_tag.quot) {
-        (ir::Quotient::None, ir::Quotient::None) => (),
-        (ir::Quotient::Node { node_id }, ir::Quotient::Input { index }) => {
-            assert_eq!(
-                input_spec_node_ids[index],
-                node_id,
-                "{} -> {}\n{}",
-                error_context.debug_node(input_spec_node_ids[index]),
-                error_context.debug_node(node_id),
-                error_context
-            );
+impl<'program> FuncletSpecChecker<'program> {
+    fn tag_mismatch(
+        &self,
+        error_context: &ErrorContext,
+        subject: TagSubject,
+        expected: ir::Tag,
+        actual: ir::Tag,
+    ) -> Error {
+        error_context.tag_mismatch(self.funclet_id, subject, expected, actual)
+    }
+
+    fn check_tag_compatibility_enter(
+        &self,
+        error_context: &ErrorContext,
+        subject: TagSubject,
+        input_spec_node_ids: &[ir::NodeId],
+        caller_tag: ir::Tag,
+        callee_tag: ir::Tag,
+    ) -> Result<(), Error> {
+        if caller_tag.flow != callee_tag.flow {
+            return Err(self.tag_mismatch(error_context, subject, callee_tag, caller_tag));
         }
-        _ => {
-            panic!(
-                "Ill-formed: {:?} to {:?} via enter\n{}",
-                error_context.debug_tag(&caller_tag),
-                error_context.debug_tag(&callee_tag),
-                error_context
-            )
-            /*return Err(Error::Generic {
-                message: format!("Ill-formed: {:?} to {:?} via enter", caller_tag, callee_tag),
-            })*/
+        match (caller_tag.quot, callee_tag.quot) {
+            (ir::Quotient::None, ir::Quotient::None) => (),
+            (ir::Quotient::Node { node_id }, ir::Quotient::Input { index }) => {
+                if input_spec_node_ids[index] != node_id {
+                    let expected = ir::Tag {
+                        quot: ir::Quotient::Node {
+                            node_id: input_spec_node_ids[index],
+                        },
+                        flow: callee_tag.flow,
+                    };
+                    return Err(self.tag_mismatch(error_context, subject, expected, caller_tag));
+                }
+            }
+            _ => return Err(self.tag_mismatch(error_context, subject, callee_tag, caller_tag)),
         }
+        return Ok(());
     }
-    return Ok(());
-}
 
-// Check value tag in callee (source) scope transfering to caller (destination) scope
-fn check_tag_compatibility_exit(
-    error_context: &ErrorContext,
-    caller_spec_funclet: &ir::Funclet,
-    caller_spec_node_id: ir::NodeId,
-    source_tag: ir::Tag,
-    destination_tag: ir::Tag,
-) -> Result<(), Error> {
-    assert_eq!(source_tag.flow, ir::Flow::Usable, "\n{}", error_context);
-    assert_eq!(
-        destination_tag.flow,
-        ir::Flow::Usable,
-        "\n{}",
-        error_context
-    );
-    match (source_tag.quot, destination_tag.quot) {
-        (ir::Quotient::None, ir::Quotient::None) => (),
-        (
-            ir::Quotient::Output {
-                index: output_index,
-            },
-            ir::Quotient::Node { node_id },
-        ) => {
-            let node = &caller_spec_funclet.nodes[node_id];
-            if let ir::Node::ExtractResult {
-                node_id: call_node_id,
-                index,
-            } = node
-            {
-                assert_eq!(
-                    *index,
-                    output_index,
-                    "{} -> {}\n{}",
-                    error_context.debug_node(*index),
-                    error_context.debug_node(output_index),
-                    error_context
-                );
-                assert_eq!(
-                    *call_node_id,
-                    caller_spec_node_id,
-                    "{:?} -> {:?}\n{}",
-                    error_context.debug_node(*call_node_id),
-                    error_context.debug_node(caller_spec_node_id),
-                    error_context
-                );
-            } else {
-                panic!(
-                    "Target operation is not a result extraction: node {:?}, {:?}\n{}",
-                    error_context.debug_node(node_id),
-                    node,
-                    error_context
-                );
+    // Check value tag in callee (source) scope transfering to caller (destination) scope
+    fn check_tag_compatibility_exit(
+        &self,
+        error_context: &ErrorContext,
+        subject: TagSubject,
+        caller_spec_node_id: ir::NodeId,
+        source_tag: ir::Tag,
+        destination_tag: ir::Tag,
+    ) -> Result<(), Error> {
+        for tag in [source_tag, destination_tag] {
+            if tag.flow != ir::Flow::Usable {
+                let expected = ir::Tag {
+                    quot: tag.quot,
+                    flow: ir::Flow::Usable,
+                };
+                return Err(self.tag_mismatch(error_context, subject, expected, tag));
             }
         }
-        _ => panic!(
-            "Ill-formed: {:?} to {:?}\n{}",
-            error_context.debug_tag(&source_tag),
-            error_context.debug_tag(&destination_tag),
-            error_context
-        ),
-    };
+        let compatible = match (source_tag.quot, destination_tag.quot) {
+            (ir::Quotient::None, ir::Quotient::None) => true,
+            (
+                ir::Quotient::Output {
+                    index: output_index,
+                },
+                ir::Quotient::Node { node_id },
+            ) => match &self.spec_funclet.nodes[node_id] {
+                ir::Node::ExtractResult {
+                    node_id: call_node_id,
+                    index,
+                } => *index == output_index && *call_node_id == caller_spec_node_id,
+                _ => false,
+            },
+            _ => false,
+        };
+        if !compatible {
+            return Err(self.tag_mismatch(error_context, subject, destination_tag, source_tag));
+        }
 
-    return Ok(());
-}
+        return Ok(());
+    }
 
-fn check_tag_compatibility_interior_cast(
-    error_context: &ErrorContext,
-    current_spec_funclet: &ir::Funclet,
-    source_tag: ir::Tag,
-    destination_tag: ir::Tag,
-    casts: &[(ir::NodeId, ir::NodeId)],
-) -> Result<(), Error> {
-    match (source_tag, destination_tag) {
-        (
-            ir::Tag {
-                quot:
-                    ir::Quotient::Node {
-                        node_id: src_node_id,
-                    },
-                flow: src_flow,
-            },
-            ir::Tag {
-                quot:
-                    ir::Quotient::Node {
-                        node_id: dst_node_id,
-                    },
-                flow: dst_flow,
-            },
-        ) => {
-            if src_flow == dst_flow && casts.contains(&(src_node_id, dst_node_id)) {
-                return Ok(());
+    fn check_tag_compatibility_interior_cast(
+        &self,
+        error_context: &ErrorContext,
+        subject: TagSubject,
+        source_tag: ir::Tag,
+        destination_tag: ir::Tag,
+        casts: &[(ir::NodeId, ir::NodeId)],
+    ) -> Result<(), Error> {
+        match (source_tag, destination_tag) {
+            (
+                ir::Tag {
+                    quot:
+                        ir::Quotient::Node {
+                            node_id: src_node_id,
+                        },
+                    flow: src_flow,
+                },
+                ir::Tag {
+                    quot:
+                        ir::Quotient::Node {
+                            node_id: dst_node_id,
+                        },
+                    flow: dst_flow,
+                },
+            ) => {
+                if src_flow == dst_flow && casts.contains(&(src_node_id, dst_node_id)) {
+                    return Ok(());
+                }
             }
+            _ => (),
         }
-        _ => (),
+
+        self.check_tag_compatibility_interior(error_context, subject, source_tag, destination_tag)?;
+
+        return Ok(());
     }
 
-    check_tag_compatibility_interior(
-        error_context,
-        current_spec_funclet,
-        source_tag,
-        destination_tag,
-    )?;
+    fn returns_node(
+        &self,
+        error_context: &ErrorContext,
+        index: usize,
+        node_id: ir::NodeId,
+    ) -> Result<bool, Error> {
+        match &self.spec_funclet.tail_edge {
+            ir::TailEdge::Return { return_values } => Ok(return_values[index] == node_id),
+            _ => Err(error_context.generic_error(&"Not a unit")),
+        }
+    }
 
-    return Ok(());
-}
+    // Check value tag transition in same scope
+    fn check_tag_compatibility_interior(
+        &self,
+        error_context: &ErrorContext,
+        subject: TagSubject,
+        source_tag: ir::Tag,
+        destination_tag: ir::Tag,
+    ) -> Result<(), Error> {
+        if source_tag.flow != destination_tag.flow {
+            return Err(self.tag_mismatch(error_context, subject, destination_tag, source_tag));
+        }
+        let flow = source_tag.flow;
 
-// Check value tag transition in same scope
-fn check_tag_compatibility_interior(
-    error_context: &ErrorContext,
-    current_value_funclet: &ir::Funclet,
-    source_tag: ir::Tag,
-    destination_tag: ir::Tag,
-) -> Result<(), Error> {
-    assert_eq!(source_tag.flow, destination_tag.flow, "\n{}", error_context);
-    let flow = source_tag.flow;
-
-    match (source_tag.quot, destination_tag.quot) {
-        (ir::Quotient::None, ir::Quotient::None) => (),
-        (_, ir::Quotient::None) if flow.is_droppable() => (),
-        (ir::Quotient::None, _) if flow.is_duplicable() => (),
-        // Input and the first few nodes are equivalent
-        (
-            ir::Quotient::Input { index },
-            ir::Quotient::Node {
-                node_id: remote_node_id,
-            },
-        ) if flow == ir::Flow::Usable => {
-            if let ir::Node::Phi { index: phi_index } = &current_value_funclet.nodes[remote_node_id]
-            {
-                assert_eq!(*phi_index, index, "\n{}", error_context);
-            } else {
-                panic!(
-                    "While checking interior compatibility of {:?} to {:?}: {:?} is not a phi\n{}",
-                    error_context.debug_tag(&source_tag),
-                    error_context.debug_tag(&destination_tag),
-                    current_value_funclet.nodes[remote_node_id],
-                    error_context
-                );
+        let is_phi = |node_id: ir::NodeId, index: usize| match &self.spec_funclet.nodes[node_id] {
+            ir::Node::Phi { index: phi_index } => *phi_index == index,
+            _ => false,
+        };
+        let compatible = match (source_tag.quot, destination_tag.quot) {
+            (ir::Quotient::None, ir::Quotient::None) => true,
+            (_, ir::Quotient::None) if flow.is_droppable() => true,
+            (ir::Quotient::None, _) if flow.is_duplicable() => true,
+            // Input and the first few nodes are equivalent
+            (
+                ir::Quotient::Input { index },
+                ir::Quotient::Node {
+                    node_id: remote_node_id,
+                },
+            ) if flow == ir::Flow::Usable => is_phi(remote_node_id, index),
+            (
+                ir::Quotient::Node {
+                    node_id: remote_node_id,
+                },
+                ir::Quotient::Input { index },
+            ) if flow == ir::Flow::Need => is_phi(remote_node_id, index),
+            (ir::Quotient::Node { node_id }, ir::Quotient::Node { node_id: node_id_2 }) => {
+                node_id == node_id_2
             }
-        }
-        (
-            ir::Quotient::Node {
-                node_id: remote_node_id,
-            },
-            ir::Quotient::Input { index },
-        ) if flow == ir::Flow::Need => {
-            if let ir::Node::Phi { index: phi_index } = &current_value_funclet.nodes[remote_node_id]
+            // An output is not necessarily equivalent to a node (there are some monad things going on)
+            (ir::Quotient::Node { node_id }, ir::Quotient::Output { index })
+                if flow == ir::Flow::Usable =>
             {
-                assert_eq!(*phi_index, index);
-            } else {
-                panic!(
-                    "While checking interior compatibility of {} to {}: {:?} is not a phi\n{}",
-                    error_context.debug_tag(&source_tag),
-                    error_context.debug_tag(&destination_tag),
-                    current_value_funclet.nodes[remote_node_id],
-                    error_context
-                );
+                self.returns_node(error_context, index, node_id)?
             }
-        }
-        (ir::Quotient::Node { node_id }, ir::Quotient::Node { node_id: node_id_2 }) => {
-            assert_eq!(node_id, node_id_2, "\n{}", error_context);
-            /*if node_id != node_id_2 {
-
-                //return Err(Error::Generic{message: format!("Tag of node #{} is not compatibile with tag of node #{}\n{}", node_id, node_id_2, error_context)});
-            }*/
-        }
-        // An output is not necessarily equivalent to a node (there are some monad things going on)
-        (ir::Quotient::Node { node_id }, ir::Quotient::Output { index })
-            if flow == ir::Flow::Usable =>
-        {
-            match &current_value_funclet.tail_edge {
-                ir::TailEdge::Return { return_values } => {
-                    //error_ifn_eq!(error_context, return_values[index], node_id)?;
-                    assert_eq!(return_values[index], node_id, "\n{}", error_context);
-                }
-                _ => panic!("Not a unit\n{}", error_context),
+            (ir::Quotient::Output { index }, ir::Quotient::Node { node_id })
+                if flow == ir::Flow::Need =>
+            {
+                self.returns_node(error_context, index, node_id)?
             }
-        }
-        (ir::Quotient::Output { index }, ir::Quotient::Node { node_id })
-            if flow == ir::Flow::Need =>
-        {
-            match &current_value_funclet.tail_edge {
-                ir::TailEdge::Return { return_values } => {
-                    assert_eq!(return_values[index], node_id, "\n{}", error_context)
-                }
-                _ => panic!("Not a unit\n{}", error_context),
+            (ir::Quotient::Output { index }, ir::Quotient::Output { index: index_2 }) => {
+                index == index_2
             }
+            _ => false,
+        };
+        if !compatible {
+            return Err(self.tag_mismatch(error_context, subject, destination_tag, source_tag));
         }
-        (ir::Quotient::Output { index }, ir::Quotient::Output { index: index_2 }) => {
-            //assert_eq!(funclet_id, funclet_id_2);
-            assert_eq!(index, index_2, "\n{}", error_context);
-        }
-        _ => panic!(
-            "Ill-formed: {:?} to {:?}\n{}",
-            error_context.debug_tag(&source_tag),
-            error_context.debug_tag(&destination_tag),
-            error_context
-        ),
-    }
 
-    Ok(())
+        Ok(())
+    }
 }
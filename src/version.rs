@@ -0,0 +1,209 @@
+// The versions of the IR a program can be written against
+//
+// A RON `frontend::Definition` written against an older version is brought up to date by running
+//   the migrations registered below in order. `caimanc --upgrade` writes the result back out, so
+//   archived programs only need to be migrated once. Assembly is accepted at any version we can
+//   migrate from, since changes to its grammar only ever add syntax.
+//
+// To change the RON schema (renaming an `ir::Node` variant, say), bump `CURRENT` and add a
+//   migration from the old version that rewrites programs into the new schema.
+
+use serde_derive::Deserialize;
+
+pub type Version = (u32, u32, u32);
+
+pub const CURRENT: Version = (0, 0, 2);
+
+pub struct Migration {
+    pub from: Version,
+    pub to: Version,
+    // rewrites the RON text of a definition written for `from` into one for `to`
+    pub upgrade: fn(&str) -> Result<String, String>,
+}
+
+pub const MIGRATIONS: &[Migration] = &[];
+
+pub fn to_string(version: Version) -> String {
+    format!("{}.{}.{}", version.0, version.1, version.2)
+}
+
+// the oldest version that `migrations` can bring up to `CURRENT`
+pub fn oldest_supported(migrations: &[Migration]) -> Version {
+    let mut oldest = CURRENT;
+    // bounded, in case the registry has a cycle
+    for _ in migrations {
+        match migrations.iter().find(|m| m.to == oldest) {
+            Some(migration) => oldest = migration.from,
+            None => break,
+        }
+    }
+    oldest
+}
+
+pub fn is_supported(version: Version, migrations: &[Migration]) -> bool {
+    upgrade_path(version, migrations).is_some()
+}
+
+// what to tell users about the versions `migrations` lets us read
+pub fn supported_versions(migrations: &[Migration]) -> String {
+    let oldest = oldest_supported(migrations);
+    if oldest == CURRENT {
+        to_string(CURRENT)
+    } else {
+        format!("{} through {}", to_string(oldest), to_string(CURRENT))
+    }
+}
+
+fn upgrade_path(version: Version, migrations: &[Migration]) -> Option<Vec<&Migration>> {
+    let mut path = Vec::new();
+    let mut current = version;
+    while current != CURRENT {
+        let migration = migrations.iter().find(|m| m.from == current)?;
+        // as above, a cycle would never reach `CURRENT`
+        if path.len() >= migrations.len() {
+            return None;
+        }
+        path.push(migration);
+        current = migration.to;
+    }
+    Some(path)
+}
+
+pub fn unsupported(version: Version, supported: &str) -> String {
+    format!(
+        "Unsupported version {}, this compiler supports {}",
+        to_string(version),
+        supported
+    )
+}
+
+#[derive(Deserialize)]
+struct Versioned {
+    version: Version,
+}
+
+// reads just the version of a RON definition, so we know how to read the rest of it
+pub fn ron_version(text: &str) -> Result<Version, ron::Error> {
+    ron::from_str::<Versioned>(text).map(|versioned| versioned.version)
+}
+
+// rewrites the RON text of a definition written for `version` into one for `CURRENT`
+// the version recorded in the text itself is left for the caller to update
+pub fn migrate(text: &str, version: Version, migrations: &[Migration]) -> Result<String, String> {
+    let path = match upgrade_path(version, migrations) {
+        Some(path) => path,
+        None => return Err(unsupported(version, &supported_versions(migrations))),
+    };
+    let mut text = text.to_string();
+    for migration in path {
+        text = (migration.upgrade)(&text).map_err(|why| {
+            format!(
+                "Failed to upgrade from {} to {}: {}",
+                to_string(migration.from),
+                to_string(migration.to),
+                why
+            )
+        })?;
+    }
+    Ok(text)
+}
+
+// renames every use of the identifier `old` outside of string literals, which is how most
+//   migrations rename a type, field, or variant
+pub fn rename_identifier(text: &str, old: &str, new: &str) -> String {
+    let is_identifier = |c: char| c.is_alphanumeric() || c == '_';
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.char_indices().peekable();
+    while let Some((index, c)) = chars.next() {
+        if c == '"' {
+            result.push(c);
+            while let Some((_, c)) = chars.next() {
+                result.push(c);
+                match c {
+                    '\\' => {
+                        if let Some((_, escaped)) = chars.next() {
+                            result.push(escaped);
+                        }
+                    }
+                    '"' => break,
+                    _ => {}
+                }
+            }
+        } else if is_identifier(c) {
+            let mut end = index + c.len_utf8();
+            while let Some(&(next, c)) = chars.peek() {
+                if !is_identifier(c) {
+                    break;
+                }
+                end = next + c.len_utf8();
+                chars.next();
+            }
+            let identifier = &text[index..end];
+            result.push_str(if identifier == old { new } else { identifier });
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rename_node(text: &str) -> Result<String, String> {
+        Ok(rename_identifier(text, "OldNode", "Node"))
+    }
+
+    fn fail(_: &str) -> Result<String, String> {
+        Err("no way forward".to_string())
+    }
+
+    const MIGRATIONS: &[Migration] = &[
+        Migration {
+            from: (0, 0, 0),
+            to: (0, 0, 1),
+            upgrade: fail,
+        },
+        Migration {
+            from: (0, 0, 1),
+            to: CURRENT,
+            upgrade: rename_node,
+        },
+    ];
+
+    #[test]
+    fn renames_identifiers_outside_strings() {
+        let text = r#"[OldNode(x : 1), OldNodes, "OldNode \" OldNode", OldNode]"#;
+        assert_eq!(
+            rename_identifier(text, "OldNode", "Node"),
+            r#"[Node(x : 1), OldNodes, "OldNode \" OldNode", Node]"#
+        );
+    }
+
+    #[test]
+    fn reports_supported_versions() {
+        assert_eq!(oldest_supported(MIGRATIONS), (0, 0, 0));
+        assert_eq!(supported_versions(MIGRATIONS), "0.0.0 through 0.0.2");
+        assert_eq!(supported_versions(&[]), "0.0.2");
+        assert!(is_supported((0, 0, 1), MIGRATIONS));
+        assert!(!is_supported((0, 0, 3), MIGRATIONS));
+    }
+
+    #[test]
+    fn migrates_along_the_registry() {
+        let text = "(version : (0, 0, 1), nodes : [OldNode])";
+        assert_eq!(ron_version(text).unwrap(), (0, 0, 1));
+        assert_eq!(
+            migrate(text, (0, 0, 1), MIGRATIONS).unwrap(),
+            "(version : (0, 0, 1), nodes : [Node])"
+        );
+        assert_eq!(migrate(text, CURRENT, MIGRATIONS).unwrap(), text);
+        assert!(migrate(text, (0, 0, 0), MIGRATIONS)
+            .unwrap_err()
+            .contains("no way forward"));
+        assert!(migrate(text, (1, 0, 0), MIGRATIONS)
+            .unwrap_err()
+            .starts_with("Unsupported version 1.0.0"));
+    }
+}